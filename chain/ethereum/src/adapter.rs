@@ -4,7 +4,7 @@ use futures::Future;
 use mockall::automock;
 use mockall::predicate::*;
 use std::cmp;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
 use std::marker::Unpin;
 use thiserror::Error;
@@ -12,9 +12,10 @@ use tiny_keccak::keccak256;
 use web3::types::{Address, Block, Log, H256};
 
 use graph::{
-    blockchain as bc,
-    components::metrics::{CounterVec, GaugeVec, HistogramVec},
+    blockchain::{self as bc, DataSource as _},
+    components::metrics::{CounterVec, Gauge, GaugeVec, HistogramVec},
     petgraph::{self, graphmap::GraphMap},
+    util::scan_counter::ScanCounter,
 };
 use graph::{components::ethereum::EthereumNetworkIdentifier, prelude::*};
 
@@ -62,32 +63,60 @@ enum LogFilterNode {
     Event(EventSignature),
 }
 
+/// The values that topics 1 to 3 of a log must have. Logs match if each
+/// of their topics is one of the values for its position; `None` matches
+/// any value
+pub type TopicValues = [Option<Vec<H256>>; 3];
+
+pub(crate) fn matches_topic_values<'a>(
+    values: impl IntoIterator<Item = &'a Option<Vec<H256>>>,
+    log: &Log,
+) -> bool {
+    values
+        .into_iter()
+        .enumerate()
+        .all(|(i, values)| match values {
+            None => true,
+            Some(values) => log
+                .topics
+                .get(i + 1)
+                .map_or(false, |topic| values.contains(topic)),
+        })
+}
+
 /// Corresponds to an `eth_getLogs` call.
 #[derive(Clone)]
 pub struct EthGetLogsFilter {
     pub contracts: Vec<Address>,
     pub event_signatures: Vec<EventSignature>,
+    pub topics: TopicValues,
 }
 
 impl fmt::Display for EthGetLogsFilter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if self.contracts.len() == 1 {
+        if self.event_signatures.is_empty() {
+            write!(f, "all events of {} contracts", self.contracts.len())?;
+        } else if self.contracts.len() == 1 {
             write!(
                 f,
                 "contract {:?}, {} events",
                 self.contracts[0],
                 self.event_signatures.len()
-            )
+            )?;
         } else if self.event_signatures.len() == 1 {
             write!(
                 f,
                 "event {:?}, {} contracts",
                 self.event_signatures[0],
                 self.contracts.len()
-            )
+            )?;
         } else {
-            write!(f, "unreachable")
+            write!(f, "unreachable")?;
         }
+        if self.topics.iter().any(Option::is_some) {
+            write!(f, ", filtered by topics")?;
+        }
+        Ok(())
     }
 }
 
@@ -137,11 +166,33 @@ pub(crate) struct EthereumLogFilter {
 
     // Event sigs with no associated address, matching on all addresses.
     wildcard_events: HashSet<EventSignature>,
+
+    /// Events whose handlers ask for the receipt of the transaction, by
+    /// contract, or `None` for data sources without an address
+    receipt_events: HashSet<(Option<Address>, EventSignature)>,
+
+    /// Events whose handlers only want logs with certain values for the
+    /// indexed parameters of the event, by contract, or `None` for data
+    /// sources without an address. These do not appear in the graph or the
+    /// wildcard events, so that we only ask for the logs they want
+    topic_events: HashSet<(Option<Address>, EventSignature, TopicValues)>,
+
+    /// Contracts with anonymous events. Their logs do not start with the
+    /// signature of the event, so we want all logs of these contracts
+    anonymous_contracts: HashSet<Address>,
+
+    /// Contracts with anonymous events whose handlers ask for the receipt
+    /// of the transaction
+    anonymous_receipts: HashSet<Address>,
 }
 
 impl EthereumLogFilter {
     /// Check if this filter matches the specified `Log`.
     pub fn matches(&self, log: &Log) -> bool {
+        if self.anonymous_contracts.contains(&log.address) {
+            return true;
+        }
+
         // First topic should be event sig
         match log.topics.first() {
             None => false,
@@ -158,6 +209,11 @@ impl EthereumLogFilter {
                         (s == contract && t == event) || (t == contract && s == event)
                     })
                     || self.wildcard_events.contains(sig)
+                    || self.topic_events.iter().any(|(contract, event, values)| {
+                        contract.map_or(true, |contract| contract == log.address)
+                            && event == sig
+                            && matches_topic_values(values.iter(), log)
+                    })
             }
         }
     }
@@ -165,7 +221,30 @@ impl EthereumLogFilter {
     pub fn from_data_sources<'a>(iter: impl IntoIterator<Item = &'a DataSource>) -> Self {
         let mut this = EthereumLogFilter::default();
         for ds in iter {
-            for event_sig in ds.mapping.event_handlers.iter().map(|e| e.topic0()) {
+            for handler in &ds.mapping.event_handlers {
+                if handler.anonymous {
+                    // Validation makes sure that data sources with
+                    // anonymous events have an address
+                    if let Some(contract) = ds.source.address {
+                        this.anonymous_contracts.insert(contract);
+                        if handler.receipt {
+                            this.anonymous_receipts.insert(contract);
+                        }
+                    }
+                    continue;
+                }
+                let event_sig = handler.topic0();
+                if handler.receipt {
+                    this.receipt_events.insert((ds.source.address, event_sig));
+                }
+                if handler.has_topic_filter() {
+                    this.topic_events.insert((
+                        ds.source.address,
+                        event_sig,
+                        handler.topic_values(),
+                    ));
+                    continue;
+                }
                 match ds.source.address {
                     Some(contract) => {
                         this.contracts_and_events_graph.add_edge(
@@ -189,11 +268,38 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            receipt_events,
+            topic_events,
+            anonymous_contracts,
+            anonymous_receipts,
         } = other;
         for (s, t, ()) in contracts_and_events_graph.all_edges() {
             self.contracts_and_events_graph.add_edge(s, t, ());
         }
         self.wildcard_events.extend(wildcard_events);
+        self.receipt_events.extend(receipt_events);
+        self.topic_events.extend(topic_events);
+        self.anonymous_contracts.extend(anonymous_contracts);
+        self.anonymous_receipts.extend(anonymous_receipts);
+    }
+
+    /// Whether a handler for `log` asks for the receipt of its transaction
+    pub fn requires_receipt(&self, log: &Log) -> bool {
+        if self.anonymous_receipts.contains(&log.address) {
+            return true;
+        }
+        match log.topics.first() {
+            None => false,
+            Some(sig) => {
+                self.receipt_events.contains(&(Some(log.address), *sig))
+                    || self.receipt_events.contains(&(None, *sig))
+            }
+        }
+    }
+
+    /// Whether any handler asks for receipts
+    pub fn requires_receipts(&self) -> bool {
+        !self.receipt_events.is_empty() || !self.anonymous_receipts.is_empty()
     }
 
     /// An empty filter is one that never matches.
@@ -202,8 +308,15 @@ impl EthereumLogFilter {
         let EthereumLogFilter {
             contracts_and_events_graph,
             wildcard_events,
+            receipt_events: _,
+            topic_events,
+            anonymous_contracts,
+            anonymous_receipts: _,
         } = self;
-        contracts_and_events_graph.edge_count() == 0 && wildcard_events.is_empty()
+        contracts_and_events_graph.edge_count() == 0
+            && wildcard_events.is_empty()
+            && topic_events.is_empty()
+            && anonymous_contracts.is_empty()
     }
 
     /// Filters for `eth_getLogs` calls. The filters will not return false positives. This attempts
@@ -212,11 +325,56 @@ impl EthereumLogFilter {
     pub fn eth_get_logs_filters(self) -> impl Iterator<Item = EthGetLogsFilter> {
         let mut filters = Vec::new();
 
-        // First add the wildcard event filters.
-        for wildcard_event in self.wildcard_events {
+        // Events with topic filters get a request of their own, unless
+        // another handler wants all logs for the event anyway
+        for (contract, event, topics) in self.topic_events {
+            let covered = self.wildcard_events.contains(&event)
+                || contract.map_or(false, |contract| {
+                    self.anonymous_contracts.contains(&contract)
+                        || self.contracts_and_events_graph.contains_edge(
+                            LogFilterNode::Contract(contract),
+                            LogFilterNode::Event(event),
+                        )
+                });
+            if !covered {
+                filters.push(EthGetLogsFilter {
+                    contracts: contract.into_iter().collect(),
+                    event_signatures: vec![event],
+                    topics,
+                })
+            }
+        }
+
+        // All wildcard events go into one request that only filters on
+        // topic0. Logs for them from contracts that data sources with an
+        // address are interested in come back with that request, so those
+        // edges don't need requests of their own
+        let mut g = self.contracts_and_events_graph;
+        if !self.wildcard_events.is_empty() {
+            for event in &self.wildcard_events {
+                g.remove_node(LogFilterNode::Event(*event));
+            }
+            let mut event_signatures: Vec<_> = self.wildcard_events.into_iter().collect();
+            event_signatures.sort();
             filters.push(EthGetLogsFilter {
                 contracts: vec![],
-                event_signatures: vec![wildcard_event],
+                event_signatures,
+                topics: TopicValues::default(),
+            })
+        }
+
+        // Contracts with anonymous events get one request for all of their
+        // logs, which also covers the other events we want from them
+        if !self.anonymous_contracts.is_empty() {
+            for contract in &self.anonymous_contracts {
+                g.remove_node(LogFilterNode::Contract(*contract));
+            }
+            let mut contracts: Vec<_> = self.anonymous_contracts.into_iter().collect();
+            contracts.sort();
+            filters.push(EthGetLogsFilter {
+                contracts,
+                event_signatures: vec![],
+                topics: TopicValues::default(),
             })
         }
 
@@ -233,7 +391,6 @@ impl EthereumLogFilter {
         // single node. For example if a subgraph has two data sources, each with the same two
         // events, we could cover that with a single filter and no false positives. However that
         // might cause the filter to become too broad, so at the moment it seems excessive.
-        let mut g = self.contracts_and_events_graph;
         while g.edge_count() > 0 {
             // If there are edges, there are vertexes.
             let max_vertex = g.nodes().max_by_key(|&n| g.neighbors(n).count()).unwrap();
@@ -241,10 +398,12 @@ impl EthereumLogFilter {
                 LogFilterNode::Contract(address) => EthGetLogsFilter {
                     contracts: vec![address],
                     event_signatures: vec![],
+                    topics: TopicValues::default(),
                 },
                 LogFilterNode::Event(event_sig) => EthGetLogsFilter {
                     contracts: vec![],
                     event_signatures: vec![event_sig],
+                    topics: TopicValues::default(),
                 },
             };
             for neighbor in g.neighbors(max_vertex) {
@@ -396,6 +555,12 @@ impl From<EthereumBlockFilter> for EthereumCallFilter {
 pub(crate) struct EthereumBlockFilter {
     pub contract_addresses: HashSet<(BlockNumber, Address)>,
     pub trigger_every_block: bool,
+    /// The start blocks of data sources that have a block handler with a
+    /// `once` filter
+    pub start_blocks: HashSet<BlockNumber>,
+    /// The blocks from which data sources with a block handler with a
+    /// `polling` filter count, and their intervals
+    pub polling_intervals: HashSet<(BlockNumber, BlockNumber)>,
 }
 
 impl EthereumBlockFilter {
@@ -420,8 +585,19 @@ impl EthereumBlockFilter {
                     .into_iter()
                     .any(|block_handler| block_handler.filter.is_none());
 
+                let once_handler_block = data_source.once_handler_block();
+
+                let polling_intervals = data_source
+                    .polling_interval()
+                    .filter(|every| *every >= 1)
+                    .map(|every| (data_source.polling_start(), every))
+                    .into_iter()
+                    .collect();
+
                 filter_opt.extend(Self {
                     trigger_every_block: has_block_handler_without_filter,
+                    polling_intervals,
+                    start_blocks: once_handler_block.into_iter().collect(),
                     contract_addresses: if has_block_handler_with_call_filter {
                         vec![(
                             data_source.source.start_block,
@@ -439,6 +615,8 @@ impl EthereumBlockFilter {
 
     pub fn extend(&mut self, other: EthereumBlockFilter) {
         self.trigger_every_block = self.trigger_every_block || other.trigger_every_block;
+        self.start_blocks.extend(other.start_blocks);
+        self.polling_intervals.extend(other.polling_intervals);
         self.contract_addresses = self.contract_addresses.iter().cloned().fold(
             HashSet::new(),
             |mut addresses, (start_block, address)| {
@@ -463,12 +641,59 @@ impl EthereumBlockFilter {
     fn requires_traces(&self) -> bool {
         !self.contract_addresses.is_empty()
     }
+
+    /// Whether some data source with a `polling` block handler polls at
+    /// `block`
+    pub fn polls_at(&self, block: BlockNumber) -> bool {
+        self.polling_intervals
+            .iter()
+            .any(|(start, every)| block >= *start && (block - start) % every == 0)
+    }
+
+    /// The blocks in `[from, to]` at which some data source with a
+    /// `polling` block handler polls, in ascending order. This only
+    /// depends on the intervals and not on the number of blocks in the
+    /// range, so that ranges without polls are skipped cheaply
+    pub fn polling_blocks(&self, from: BlockNumber, to: BlockNumber) -> Vec<BlockNumber> {
+        let mut blocks = BTreeSet::new();
+        for (start, every) in &self.polling_intervals {
+            let first = if from <= *start {
+                Some(*start)
+            } else {
+                // The first block after `start` that is a multiple of
+                // `every` away from it and not before `from`
+                match (from - start) % every {
+                    0 => Some(from),
+                    offset => from.checked_add(every - offset),
+                }
+            };
+            let mut block = match first {
+                Some(first) => first,
+                None => continue,
+            };
+            while block <= to {
+                blocks.insert(block);
+                block = match block.checked_add(*every) {
+                    Some(next) => next,
+                    None => break,
+                };
+            }
+        }
+        blocks.into_iter().collect()
+    }
 }
 
 #[derive(Clone)]
 pub struct ProviderEthRpcMetrics {
     request_duration: Box<HistogramVec>,
     errors: Box<CounterVec>,
+    logs_response_size: Box<HistogramVec>,
+    largest_logs_response: Box<Gauge>,
+    provider_active: Box<GaugeVec>,
+    failovers: Box<CounterVec>,
+    request_limit_waits: Box<CounterVec>,
+    request_limit_wait_time: Box<CounterVec>,
+    receipt_requests: Box<CounterVec>,
 }
 
 impl ProviderEthRpcMetrics {
@@ -488,9 +713,73 @@ impl ProviderEthRpcMetrics {
                 vec![String::from("method")],
             )
             .unwrap();
+        let logs_response_size = registry
+            .new_histogram_vec(
+                "eth_rpc_logs_response_size",
+                "Measures the size in bytes of eth_getLogs responses",
+                vec![String::from("method")],
+                vec![
+                    1_000_000.0,
+                    10_000_000.0,
+                    50_000_000.0,
+                    100_000_000.0,
+                    250_000_000.0,
+                    500_000_000.0,
+                ],
+            )
+            .unwrap();
+        let largest_logs_response = registry
+            .new_gauge(
+                "eth_rpc_largest_logs_response_size",
+                "The size in bytes of the largest eth_getLogs response seen",
+                HashMap::new(),
+            )
+            .unwrap();
+        let provider_active = registry
+            .new_gauge_vec(
+                "eth_provider_active",
+                "Whether a provider is used for requests, i.e., whether it is not failing",
+                vec![String::from("provider")],
+            )
+            .unwrap();
+        let failovers = registry
+            .new_counter_vec(
+                "eth_provider_failovers",
+                "Counts how often a provider was taken out of rotation because it was failing",
+                vec![String::from("provider")],
+            )
+            .unwrap();
+        let request_limit_waits = registry
+            .new_counter_vec(
+                "eth_provider_request_limit_waits",
+                "Counts requests that had to wait because of the request limit for a provider",
+                vec![String::from("provider")],
+            )
+            .unwrap();
+        let request_limit_wait_time = registry
+            .new_counter_vec(
+                "eth_provider_request_limit_wait_secs",
+                "Measures the time requests waited because of the request limit for a provider",
+                vec![String::from("provider")],
+            )
+            .unwrap();
+        let receipt_requests = registry
+            .new_counter_vec(
+                "eth_rpc_receipt_requests",
+                "Counts the blocks for which receipts were fetched by the method used to fetch them",
+                vec![String::from("provider"), String::from("method")],
+            )
+            .unwrap();
         Self {
             request_duration,
             errors,
+            logs_response_size,
+            largest_logs_response,
+            provider_active,
+            failovers,
+            request_limit_waits,
+            request_limit_wait_time,
+            receipt_requests,
         }
     }
 
@@ -503,16 +792,54 @@ impl ProviderEthRpcMetrics {
     pub fn add_error(&self, method: &str) {
         self.errors.with_label_values(vec![method].as_slice()).inc();
     }
+
+    pub fn set_provider_active(&self, provider: &str, active: bool) {
+        self.provider_active
+            .with_label_values(&[provider])
+            .set(if active { 1.0 } else { 0.0 });
+    }
+
+    pub fn add_failover(&self, provider: &str) {
+        self.failovers.with_label_values(&[provider]).inc();
+    }
+
+    pub fn add_request_limit_wait(&self, provider: &str, requests: usize, secs: f64) {
+        self.request_limit_waits
+            .with_label_values(&[provider])
+            .inc_by(requests as f64);
+        self.request_limit_wait_time
+            .with_label_values(&[provider])
+            .inc_by(secs);
+    }
+
+    pub fn add_receipt_request(&self, provider: &str, method: &str) {
+        self.receipt_requests
+            .with_label_values(&[provider, method])
+            .inc();
+    }
+
+    pub fn observe_logs_response_size(&self, size: usize) {
+        let size = size as f64;
+        self.logs_response_size
+            .with_label_values(&["eth_getLogs"])
+            .observe(size);
+        if size > self.largest_logs_response.get() {
+            self.largest_logs_response.set(size);
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct SubgraphEthRpcMetrics {
     request_duration: Box<GaugeVec>,
     errors: Box<CounterVec>,
+    /// How much data we fetched for the subgraph
+    pub scan: Arc<ScanCounter>,
 }
 
 impl SubgraphEthRpcMetrics {
-    pub fn new(registry: Arc<dyn MetricsRegistry>, subgraph_hash: &str) -> Self {
+    pub fn new(registry: Arc<dyn MetricsRegistry>, deployment: &DeploymentHash) -> Self {
+        let subgraph_hash = deployment.as_str();
         let request_duration = registry
             .new_deployment_gauge_vec(
                 "deployment_eth_rpc_request_duration",
@@ -532,6 +859,7 @@ impl SubgraphEthRpcMetrics {
         Self {
             request_duration,
             errors,
+            scan: ScanCounter::for_deployment(deployment),
         }
     }
 
@@ -649,9 +977,9 @@ pub trait EthereumAdapter: Send + Sync + 'static {
 
 #[cfg(test)]
 mod tests {
-    use super::EthereumCallFilter;
+    use super::{EthereumCallFilter, EthereumLogFilter, LogFilterNode, TopicValues};
 
-    use graph::prelude::web3::types::Address;
+    use graph::prelude::web3::types::{Address, Log, H256};
 
     use std::collections::{HashMap, HashSet};
     use std::iter::FromIterator;
@@ -700,4 +1028,104 @@ mod tests {
             Some(&(1, HashSet::from_iter(vec![[1u8; 4]])))
         );
     }
+
+    #[test]
+    fn log_filter_receipts() {
+        let log = |address: u64, sig: u64| Log {
+            address: Address::from_low_u64_be(address),
+            topics: vec![H256::from_low_u64_be(sig)],
+            ..Log::default()
+        };
+
+        let mut filter = EthereumLogFilter::default();
+        assert!(!filter.requires_receipts());
+        filter
+            .receipt_events
+            .insert((Some(Address::from_low_u64_be(1)), H256::from_low_u64_be(7)));
+        let mut wildcard = EthereumLogFilter::default();
+        wildcard
+            .receipt_events
+            .insert((None, H256::from_low_u64_be(8)));
+        filter.extend(wildcard);
+
+        assert!(filter.requires_receipts());
+        assert!(filter.requires_receipt(&log(1, 7)));
+        assert!(!filter.requires_receipt(&log(2, 7)));
+        assert!(filter.requires_receipt(&log(2, 8)));
+        assert!(!filter.requires_receipt(&Log::default()));
+    }
+
+    #[test]
+    fn log_filter_wildcards() {
+        let contract = |n: u64| LogFilterNode::Contract(Address::from_low_u64_be(n));
+        let event = |n: u64| LogFilterNode::Event(H256::from_low_u64_be(n));
+
+        let mut filter = EthereumLogFilter::default();
+        filter.wildcard_events.insert(H256::from_low_u64_be(7));
+        filter.wildcard_events.insert(H256::from_low_u64_be(8));
+        filter
+            .contracts_and_events_graph
+            .add_edge(contract(1), event(7), ());
+        filter
+            .contracts_and_events_graph
+            .add_edge(contract(2), event(7), ());
+        filter
+            .contracts_and_events_graph
+            .add_edge(contract(2), event(9), ());
+
+        // All wildcard events are requested at once, and only the event
+        // that no wildcard covers needs a request with a contract
+        let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+        assert_eq!(2, filters.len());
+        assert!(filters[0].contracts.is_empty());
+        assert_eq!(
+            vec![H256::from_low_u64_be(7), H256::from_low_u64_be(8)],
+            filters[0].event_signatures
+        );
+        assert_eq!(vec![Address::from_low_u64_be(2)], filters[1].contracts);
+        assert_eq!(vec![H256::from_low_u64_be(9)], filters[1].event_signatures);
+    }
+
+    #[test]
+    fn log_filter_topics() {
+        let topic = H256::from_low_u64_be;
+        let log = |address: u64, topics: Vec<H256>| Log {
+            address: Address::from_low_u64_be(address),
+            topics,
+            ..Log::default()
+        };
+
+        let values: TopicValues = [None, Some(vec![topic(5), topic(6)]), None];
+        let mut filter = EthereumLogFilter::default();
+        filter
+            .topic_events
+            .insert((Some(Address::from_low_u64_be(1)), topic(7), values.clone()));
+
+        // Only logs with one of the values for the second indexed parameter
+        // match
+        assert!(filter.matches(&log(1, vec![topic(7), topic(0), topic(5)])));
+        assert!(filter.matches(&log(1, vec![topic(7), topic(0), topic(6)])));
+        assert!(!filter.matches(&log(1, vec![topic(7), topic(0), topic(8)])));
+        assert!(!filter.matches(&log(1, vec![topic(7), topic(0)])));
+        assert!(!filter.matches(&log(2, vec![topic(7), topic(0), topic(5)])));
+
+        // The topics are part of the request
+        let filters: Vec<_> = filter.clone().eth_get_logs_filters().collect();
+        assert_eq!(1, filters.len());
+        assert_eq!(vec![Address::from_low_u64_be(1)], filters[0].contracts);
+        assert_eq!(values, filters[0].topics);
+
+        // When another handler wants all logs for the event, we request
+        // them all and filter them when matching handlers
+        let mut all = EthereumLogFilter::default();
+        all.contracts_and_events_graph.add_edge(
+            LogFilterNode::Contract(Address::from_low_u64_be(1)),
+            LogFilterNode::Event(topic(7)),
+            (),
+        );
+        filter.extend(all);
+        let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+        assert_eq!(1, filters.len());
+        assert_eq!(TopicValues::default(), filters[0].topics);
+    }
 }
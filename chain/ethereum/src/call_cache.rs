@@ -0,0 +1,167 @@
+//! Count how often `ethereum.call`s are answered from the call cache in the
+//! chain store, and allow turning the cache off when we suspect that it
+//! serves stale data. Cached results are keyed by the hash of the block
+//! that the call was made against, so that results for blocks that were
+//! reorged out are never used for the blocks that replaced them
+use std::sync::Arc;
+
+use graph::prelude::{
+    ethabi, lazy_static, BlockPtr, CounterVec, Error, EthereumCallCache, MetricsRegistry,
+};
+
+lazy_static! {
+    /// Set to `true` to always make `eth_call`s against the provider
+    static ref CALL_CACHE_DISABLED: bool = std::env::var("GRAPH_ETHEREUM_DISABLE_CALL_CACHE")
+        .ok()
+        .map(|s| s.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+}
+
+/// The call cache for one network
+pub struct MeteredCallCache {
+    network: String,
+    cache: Arc<dyn EthereumCallCache>,
+    lookups: CounterVec,
+    disabled: bool,
+}
+
+impl MeteredCallCache {
+    pub fn new(
+        network: &str,
+        cache: Arc<dyn EthereumCallCache>,
+        registry: Arc<dyn MetricsRegistry>,
+    ) -> Self {
+        let lookups = registry
+            .global_counter_vec(
+                "eth_call_cache_lookups",
+                "Counts lookups in the cache for eth_call results by whether the result was cached",
+                &["network", "result"],
+            )
+            .expect("failed to create `eth_call_cache_lookups` counter");
+        MeteredCallCache {
+            network: network.to_string(),
+            cache,
+            lookups,
+            disabled: *CALL_CACHE_DISABLED,
+        }
+    }
+}
+
+impl EthereumCallCache for MeteredCallCache {
+    fn get_call(
+        &self,
+        contract_address: ethabi::Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if self.disabled {
+            return Ok(None);
+        }
+        let result = self.cache.get_call(contract_address, encoded_call, block)?;
+        let outcome = if result.is_some() { "hit" } else { "miss" };
+        self.lookups
+            .with_label_values(&[self.network.as_str(), outcome])
+            .inc();
+        Ok(result)
+    }
+
+    fn set_call(
+        &self,
+        contract_address: ethabi::Address,
+        encoded_call: &[u8],
+        block: BlockPtr,
+        return_value: &[u8],
+    ) -> Result<(), Error> {
+        if self.disabled {
+            return Ok(());
+        }
+        self.cache
+            .set_call(contract_address, encoded_call, block, return_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use graph::prelude::web3::types::H256;
+    use graph::prelude::{o, Logger};
+    use graph::prometheus::Registry;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryCache(Mutex<HashMap<(ethabi::Address, Vec<u8>, BlockPtr), Vec<u8>>>);
+
+    impl EthereumCallCache for MemoryCache {
+        fn get_call(
+            &self,
+            contract_address: ethabi::Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+        ) -> Result<Option<Vec<u8>>, Error> {
+            let key = (contract_address, encoded_call.to_vec(), block);
+            Ok(self.0.lock().unwrap().get(&key).cloned())
+        }
+
+        fn set_call(
+            &self,
+            contract_address: ethabi::Address,
+            encoded_call: &[u8],
+            block: BlockPtr,
+            return_value: &[u8],
+        ) -> Result<(), Error> {
+            let key = (contract_address, encoded_call.to_vec(), block);
+            self.0.lock().unwrap().insert(key, return_value.to_vec());
+            Ok(())
+        }
+    }
+
+    fn registry() -> Arc<dyn MetricsRegistry> {
+        Arc::new(graph_core::MetricsRegistry::new(
+            Logger::root(graph::slog::Discard, o!()),
+            Arc::new(Registry::new()),
+        ))
+    }
+
+    fn lookups(cache: &MeteredCallCache, outcome: &str) -> f64 {
+        cache.lookups.with_label_values(&["mainnet", outcome]).get()
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let cache = MeteredCallCache::new("mainnet", Arc::new(MemoryCache::default()), registry());
+        let address = ethabi::Address::from_low_u64_be(1);
+        let block = BlockPtr::from((H256::from_low_u64_be(1), 1i32));
+
+        assert_eq!(None, cache.get_call(address, &[1], block.clone()).unwrap());
+        cache.set_call(address, &[1], block.clone(), &[7]).unwrap();
+        assert_eq!(
+            Some(vec![7]),
+            cache.get_call(address, &[1], block.clone()).unwrap()
+        );
+        assert_eq!(Some(vec![7]), cache.get_call(address, &[1], block).unwrap());
+
+        assert_eq!(2.0, lookups(&cache, "hit"));
+        assert_eq!(1.0, lookups(&cache, "miss"));
+    }
+
+    #[test]
+    fn disabled_cache_is_bypassed() {
+        let inner = Arc::new(MemoryCache::default());
+        let mut cache = MeteredCallCache::new("mainnet", inner.clone(), registry());
+        cache.disabled = true;
+        let address = ethabi::Address::from_low_u64_be(1);
+        let block = BlockPtr::from((H256::from_low_u64_be(1), 1i32));
+
+        // Results are neither stored nor looked up
+        cache.set_call(address, &[1], block.clone(), &[7]).unwrap();
+        assert!(inner.0.lock().unwrap().is_empty());
+        inner.set_call(address, &[1], block.clone(), &[7]).unwrap();
+        assert_eq!(None, cache.get_call(address, &[1], block).unwrap());
+
+        assert_eq!(0.0, lookups(&cache, "hit"));
+        assert_eq!(0.0, lookups(&cache, "miss"));
+    }
+}
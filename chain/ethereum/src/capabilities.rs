@@ -70,6 +70,32 @@ impl fmt::Display for NodeCapabilities {
 
 impl_slog_value!(NodeCapabilities, "{}");
 
+impl NodeCapabilities {
+    /// The capabilities in `self` that none of `available` has, for the
+    /// provider that comes closest to having all of them. Returns `None`
+    /// if one of `available` has all of `self`
+    pub fn missing_from<'a>(
+        &self,
+        available: impl IntoIterator<Item = &'a NodeCapabilities>,
+    ) -> Option<NodeCapabilities> {
+        let missing = |available: &NodeCapabilities| NodeCapabilities {
+            archive: self.archive && !available.archive,
+            traces: self.traces && !available.traces,
+        };
+        let count = |caps: &NodeCapabilities| caps.archive as u8 + caps.traces as u8;
+        let missing = available
+            .into_iter()
+            .map(missing)
+            .min_by_key(count)
+            .unwrap_or(*self);
+        if count(&missing) == 0 {
+            None
+        } else {
+            Some(missing)
+        }
+    }
+}
+
 impl graph::blockchain::NodeCapabilities<crate::Chain> for NodeCapabilities {
     fn from_data_sources(data_sources: &[DataSource]) -> Self {
         NodeCapabilities {
@@ -1,9 +1,9 @@
-use anyhow::{Context, Error};
+use anyhow::{anyhow, Context, Error};
 use graph::blockchain::BlockchainKind;
 use graph::data::subgraph::UnifiedMappingApiVersion;
 use graph::firehose::endpoints::FirehoseNetworkEndpoints;
 use graph::prelude::{
-    EthereumCallCache, LightEthereumBlock, LightEthereumBlockExt, StopwatchMetrics,
+    EthereumCallCache, EthereumUncle, LightEthereumBlock, LightEthereumBlockExt, StopwatchMetrics,
 };
 use graph::{
     blockchain::{
@@ -23,7 +23,7 @@ use graph::{
     prelude::{
         async_trait, error, lazy_static, o, web3::types::H256, BlockNumber, ChainStore,
         EthereumBlockWithCalls, Future01CompatExt, Logger, LoggerFactory, MetricsRegistry, NodeId,
-        SubgraphStore,
+        SubgraphStore, BLOCK_NUMBER_MAX,
     },
 };
 use prost::Message;
@@ -36,12 +36,15 @@ use crate::data_source::UnresolvedDataSourceTemplate;
 use crate::RuntimeAdapter;
 use crate::{
     adapter::EthereumAdapter as _,
+    call_cache::MeteredCallCache,
+    capabilities::NodeCapabilities,
     codec,
     data_source::{DataSource, UnresolvedDataSource},
     ethereum_adapter::{
-        blocks_with_triggers, get_calls, parse_block_triggers, parse_call_triggers,
-        parse_log_triggers,
+        blocks_with_triggers, get_calls, load_uncles, parse_block_triggers, parse_call_triggers,
+        parse_log_triggers, uncles_needed,
     },
+    log_range::LogRanges,
     SubgraphEthRpcMetrics, TriggerFilter,
 };
 use crate::{network::EthereumNetworkAdapters, EthereumAdapter};
@@ -102,6 +105,11 @@ impl Chain {
         reorg_threshold: BlockNumber,
         is_ingestible: bool,
     ) -> Self {
+        let call_cache = Arc::new(MeteredCallCache::new(
+            &name,
+            call_cache,
+            registry.cheap_clone(),
+        ));
         Chain {
             logger_factory,
             name,
@@ -247,17 +255,21 @@ impl Blockchain for Chain {
         unified_api_version: UnifiedMappingApiVersion,
         stopwatch_metrics: StopwatchMetrics,
     ) -> Result<Arc<Self::TriggersAdapter>, Error> {
-        let eth_adapter = self.eth_adapters.cheapest_with(capabilities)?.clone();
+        // Make sure there is an adapter with the required capabilities
+        self.eth_adapters.cheapest_with(capabilities)?;
         let logger = self
             .logger_factory
             .subgraph_logger(&loc)
             .new(o!("component" => "BlockStream"));
         let ethrpc_metrics = Arc::new(SubgraphEthRpcMetrics::new(self.registry.clone(), &loc.hash));
+        let log_ranges = Arc::new(LogRanges::new(self.registry.clone(), &loc.hash));
 
         let adapter = TriggersAdapter {
             logger,
             ethrpc_metrics,
-            eth_adapter,
+            log_ranges,
+            eth_adapters: self.eth_adapters.cheap_clone(),
+            capabilities: *capabilities,
             stopwatch_metrics,
             chain_store: self.chain_store.cheap_clone(),
             unified_api_version,
@@ -310,21 +322,17 @@ impl Blockchain for Chain {
     }
 
     fn ingestor_adapter(&self) -> Arc<Self::IngestorAdapter> {
-        let eth_adapter = self.eth_adapters.cheapest().unwrap().clone();
-        let logger = self
-            .logger_factory
-            .component_logger(
-                "BlockIngestor",
-                Some(ComponentLoggerConfig {
-                    elastic: Some(ElasticComponentLoggerConfig {
-                        index: String::from("block-ingestor-logs"),
-                    }),
+        let logger = self.logger_factory.component_logger(
+            "BlockIngestor",
+            Some(ComponentLoggerConfig {
+                elastic: Some(ElasticComponentLoggerConfig {
+                    index: String::from("block-ingestor-logs"),
                 }),
-            )
-            .new(o!("provider" => eth_adapter.provider().to_string()));
+            }),
+        );
 
         let adapter = IngestorAdapter {
-            eth_adapter,
+            eth_adapters: self.eth_adapters.cheap_clone(),
             logger,
             ancestor_count: self.ancestor_count,
             chain_store: self.chain_store.clone(),
@@ -332,6 +340,23 @@ impl Blockchain for Chain {
         Arc::new(adapter)
     }
 
+    fn check_capabilities(&self, capabilities: &NodeCapabilities) -> Result<(), Error> {
+        match self.eth_adapters.missing_capabilities(capabilities) {
+            None => Ok(()),
+            Some(missing) if missing.traces => Err(anyhow!(
+                "no provider for network `{}` supports {}; call handlers and block handlers \
+                 with a `call` filter need a provider that supports `trace_filter`",
+                self.name,
+                missing
+            )),
+            Some(missing) => Err(anyhow!(
+                "no provider for network `{}` supports {}",
+                self.name,
+                missing
+            )),
+        }
+    }
+
     fn chain_store(&self) -> Arc<dyn ChainStore> {
         self.chain_store.clone()
     }
@@ -365,8 +390,10 @@ impl Blockchain for Chain {
 /// always fetch the full block data.
 #[derive(Clone, Debug)]
 pub enum BlockFinality {
-    /// If a block is final, we only need the header and the triggers.
-    Final(Arc<LightEthereumBlock>),
+    /// If a block is final, we only need the header and the triggers, and
+    /// the headers of its uncles if the mappings can see them; see
+    /// `uncles_needed`
+    Final(Arc<LightEthereumBlock>, Option<Arc<Vec<EthereumUncle>>>),
 
     // If a block may still be reorged, we need to work with more local data.
     NonFinal(EthereumBlockWithCalls),
@@ -375,16 +402,28 @@ pub enum BlockFinality {
 impl BlockFinality {
     pub(crate) fn light_block(&self) -> Arc<LightEthereumBlock> {
         match self {
-            BlockFinality::Final(block) => block.cheap_clone(),
+            BlockFinality::Final(block, _) => block.cheap_clone(),
             BlockFinality::NonFinal(block) => block.ethereum_block.block.cheap_clone(),
         }
     }
+
+    /// The headers of all uncles of the block, or `None` if we do not
+    /// have all of them
+    pub(crate) fn uncles(&self) -> Option<Arc<Vec<EthereumUncle>>> {
+        match self {
+            BlockFinality::Final(_, uncles) => uncles.clone(),
+            BlockFinality::NonFinal(block) => block
+                .ethereum_block
+                .complete_uncles()
+                .map(|uncles| Arc::new(uncles.to_vec())),
+        }
+    }
 }
 
 impl<'a> From<&'a BlockFinality> for BlockPtr {
     fn from(block: &'a BlockFinality) -> BlockPtr {
         match block {
-            BlockFinality::Final(b) => BlockPtr::from(&**b),
+            BlockFinality::Final(b, _) => BlockPtr::from(&**b),
             BlockFinality::NonFinal(b) => BlockPtr::from(&b.ethereum_block),
         }
     }
@@ -393,17 +432,21 @@ impl<'a> From<&'a BlockFinality> for BlockPtr {
 impl Block for BlockFinality {
     fn ptr(&self) -> BlockPtr {
         match self {
-            BlockFinality::Final(block) => block.block_ptr(),
+            BlockFinality::Final(block, _) => block.block_ptr(),
             BlockFinality::NonFinal(block) => block.ethereum_block.block.block_ptr(),
         }
     }
 
     fn parent_ptr(&self) -> Option<BlockPtr> {
         match self {
-            BlockFinality::Final(block) => block.parent_ptr(),
+            BlockFinality::Final(block, _) => block.parent_ptr(),
             BlockFinality::NonFinal(block) => block.ethereum_block.block.parent_ptr(),
         }
     }
+
+    fn timestamp(&self) -> Option<u64> {
+        Some(self.light_block().timestamp.low_u64())
+    }
 }
 
 pub struct DummyDataSourceTemplate;
@@ -411,12 +454,23 @@ pub struct DummyDataSourceTemplate;
 pub struct TriggersAdapter {
     logger: Logger,
     ethrpc_metrics: Arc<SubgraphEthRpcMetrics>,
+    log_ranges: Arc<LogRanges>,
     stopwatch_metrics: StopwatchMetrics,
     chain_store: Arc<dyn ChainStore>,
-    eth_adapter: Arc<EthereumAdapter>,
+    eth_adapters: Arc<EthereumNetworkAdapters>,
+    capabilities: NodeCapabilities,
     unified_api_version: UnifiedMappingApiVersion,
 }
 
+impl TriggersAdapter {
+    /// The adapter for the next request. We choose one for every request
+    /// so that we switch to another provider when the one we used
+    /// starts failing
+    fn eth_adapter(&self) -> Result<Arc<EthereumAdapter>, Error> {
+        self.eth_adapters.cheapest_with(&self.capabilities)
+    }
+}
+
 #[async_trait]
 impl TriggersAdapterTrait<Chain> for TriggersAdapter {
     async fn scan_triggers(
@@ -425,18 +479,21 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         to: BlockNumber,
         filter: &TriggerFilter,
     ) -> Result<Vec<BlockWithTriggers<Chain>>, Error> {
-        blocks_with_triggers(
-            self.eth_adapter.clone(),
+        let blocks = blocks_with_triggers(
+            self.eth_adapter()?,
             self.logger.clone(),
             self.chain_store.clone(),
             self.ethrpc_metrics.clone(),
+            self.log_ranges.clone(),
             self.stopwatch_metrics.clone(),
             from,
             to,
             filter,
             self.unified_api_version.clone(),
         )
-        .await
+        .await?;
+        self.ethrpc_metrics.scan.add_blocks((to - from + 1) as u64);
+        Ok(blocks)
     }
 
     async fn triggers_in_block(
@@ -446,7 +503,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         filter: &TriggerFilter,
     ) -> Result<BlockWithTriggers<Chain>, Error> {
         let block = get_calls(
-            self.eth_adapter.as_ref(),
+            self.eth_adapter()?.as_ref(),
             logger.clone(),
             self.ethrpc_metrics.clone(),
             filter.requires_traces(),
@@ -454,14 +511,15 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         )
         .await?;
 
-        match &block {
-            BlockFinality::Final(_) => {
+        let block_with_triggers = match block {
+            BlockFinality::Final(..) => {
                 let block_number = block.number() as BlockNumber;
                 let blocks = blocks_with_triggers(
-                    self.eth_adapter.clone(),
+                    self.eth_adapter()?,
                     logger.clone(),
                     self.chain_store.clone(),
                     self.ethrpc_metrics.clone(),
+                    self.log_ranges.clone(),
                     self.stopwatch_metrics.clone(),
                     block_number,
                     block_number,
@@ -470,23 +528,38 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
                 )
                 .await?;
                 assert!(blocks.len() == 1);
-                Ok(blocks.into_iter().next().unwrap())
+                blocks.into_iter().next().unwrap()
             }
-            BlockFinality::NonFinal(full_block) => {
+            BlockFinality::NonFinal(mut full_block) => {
                 let mut triggers = Vec::new();
                 triggers.append(&mut parse_log_triggers(
                     &filter.log,
                     &full_block.ethereum_block,
-                ));
+                )?);
                 triggers.append(&mut parse_call_triggers(&filter.call, &full_block)?);
                 triggers.append(&mut parse_block_triggers(filter.block.clone(), &full_block));
-                Ok(BlockWithTriggers::new(block, triggers))
+
+                // Blocks that were stored before we kept uncles lack them
+                if uncles_needed(&self.unified_api_version, &triggers)
+                    && full_block.ethereum_block.complete_uncles().is_none()
+                {
+                    full_block.ethereum_block.uncles = load_uncles(
+                        self.eth_adapter()?.as_ref(),
+                        logger,
+                        self.chain_store.as_ref(),
+                        &full_block.ethereum_block.block,
+                    )
+                    .await?;
+                }
+                BlockWithTriggers::new(BlockFinality::NonFinal(full_block), triggers)
             }
-        }
+        };
+        self.ethrpc_metrics.scan.add_blocks(1);
+        Ok(block_with_triggers)
     }
 
     async fn is_on_main_chain(&self, ptr: BlockPtr) -> Result<bool, Error> {
-        self.eth_adapter
+        self.eth_adapter()?
             .is_on_main_chain(&self.logger, ptr.clone())
             .await
     }
@@ -512,7 +585,7 @@ impl TriggersAdapterTrait<Chain> for TriggersAdapter {
         use graph::prelude::LightEthereumBlockExt;
 
         let blocks = self
-            .eth_adapter
+            .eth_adapter()?
             .load_blocks(
                 self.logger.cheap_clone(),
                 self.chain_store.cheap_clone(),
@@ -559,6 +632,14 @@ impl FirehoseMapperTrait<Chain> for FirehoseMapper {
         // Check about adding basic information about the block in the bstream::BlockResponseV2 or maybe
         // define a slimmed down stuct that would decode only a few fields and ignore all the rest.
         let block = codec::Block::decode(any_block.value.as_ref())?;
+        if block.number >= BLOCK_NUMBER_MAX as u64 {
+            return Err(anyhow::format_err!(
+                "Firehose sent block {} but block numbers must be less than {}",
+                block.number,
+                BLOCK_NUMBER_MAX
+            )
+            .into());
+        }
 
         match step {
             bstream::ForkStep::StepNew => {
@@ -603,7 +684,7 @@ impl FirehoseMapper {
     ) -> Result<BlockWithTriggers<Chain>, FirehoseError> {
         let mut triggers = Vec::new();
 
-        triggers.append(&mut parse_log_triggers(&filter.log, &block.ethereum_block));
+        triggers.append(&mut parse_log_triggers(&filter.log, &block.ethereum_block)?);
         triggers.append(&mut parse_call_triggers(&filter.call, &block)?);
         triggers.append(&mut parse_block_triggers(filter.block.clone(), &block));
 
@@ -617,10 +698,20 @@ impl FirehoseMapper {
 pub struct IngestorAdapter {
     logger: Logger,
     ancestor_count: i32,
-    eth_adapter: Arc<EthereumAdapter>,
+    eth_adapters: Arc<EthereumNetworkAdapters>,
     chain_store: Arc<dyn ChainStore>,
 }
 
+impl IngestorAdapter {
+    /// The adapter for the next request, so that we switch to another
+    /// provider when the one we used starts failing
+    fn eth_adapter(&self) -> Result<Arc<EthereumAdapter>, IngestorError> {
+        self.eth_adapters
+            .cheapest()
+            .ok_or_else(|| IngestorError::Unknown(anyhow!("no adapter to ingest blocks with")))
+    }
+}
+
 #[async_trait]
 impl IngestorAdapterTrait<Chain> for IngestorAdapter {
     fn logger(&self) -> &Logger {
@@ -632,7 +723,7 @@ impl IngestorAdapterTrait<Chain> for IngestorAdapter {
     }
 
     async fn latest_block(&self) -> Result<BlockPtr, IngestorError> {
-        self.eth_adapter
+        self.eth_adapter()?
             .latest_block_header(&self.logger)
             .compat()
             .await
@@ -646,19 +737,36 @@ impl IngestorAdapterTrait<Chain> for IngestorAdapter {
         // TODO: H256::from_slice can panic
         let block_hash = H256::from_slice(block_hash.as_slice());
 
-        // Get the fully populated block
-        let block = self
-            .eth_adapter
+        // Get the fully populated block. Use the same provider for both
+        // requests since the block might not be on another provider yet
+        let eth_adapter = self.eth_adapter()?;
+        let block = eth_adapter
             .block_by_hash(&self.logger, block_hash)
             .compat()
             .await?
             .ok_or_else(|| IngestorError::BlockUnavailable(block_hash))?;
-        let block = self
-            .eth_adapter
+        let block = eth_adapter
             .load_full_block(&self.logger, block)
             .compat()
             .await?;
 
+        // The headers of the uncles are not fetched here: most subgraphs
+        // never look at them, and a provider that does not have an uncle
+        // must not stall head ingestion. `load_uncles` gets them when a
+        // block handler needs them and stores them with the block
+
+        // A different block at the same height was reverted by a reorg;
+        // the uncles stored with it no longer belong to the chain
+        let reverted: Vec<_> = self
+            .chain_store
+            .block_hashes_by_block_number(block.block.number())?
+            .into_iter()
+            .filter(|hash| *hash != block_hash)
+            .collect();
+        if !reverted.is_empty() {
+            self.chain_store.remove_uncles(&reverted)?;
+        }
+
         // Store it in the database and try to advance the chain head pointer
         self.chain_store.upsert_block(block).await?;
 
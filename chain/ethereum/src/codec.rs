@@ -3,7 +3,7 @@ mod pbcodec;
 
 use graph::prelude::{
     web3::types::TransactionReceipt as w3TransactionReceipt, EthereumBlock, EthereumBlockWithCalls,
-    EthereumCall, LightEthereumBlock,
+    EthereumCall, EthereumTransaction, EthereumUncle, LightEthereumBlock,
 };
 use std::sync::Arc;
 use web3::types::{Bytes, H160, H2048, H256, H64, U256, U64};
@@ -28,6 +28,32 @@ impl<'a> CallAt<'a> {
     pub fn new(call: &'a Call, block: &'a Block, trace: &'a TransactionTrace) -> Self {
         Self { call, block, trace }
     }
+
+    /// The trace address of the call, computed from the positions of the
+    /// call and its ancestors among their siblings
+    fn trace_address(&self) -> Vec<usize> {
+        let calls = &self.trace.calls;
+        let mut address = vec![];
+        let mut call = self.call;
+        while call.depth > 0 {
+            let position = calls
+                .iter()
+                .filter(|sibling| {
+                    sibling.parent_index == call.parent_index && sibling.index < call.index
+                })
+                .count();
+            address.push(position);
+            match calls
+                .iter()
+                .find(|parent| parent.index == call.parent_index)
+            {
+                Some(parent) => call = parent,
+                None => break,
+            }
+        }
+        address.reverse();
+        address
+    }
 }
 
 impl<'a> Into<EthereumCall> for CallAt<'a> {
@@ -47,6 +73,7 @@ impl<'a> Into<EthereumCall> for CallAt<'a> {
             block_number: self.block.number as i32,
             transaction_hash: Some(H256::from_slice(&self.trace.hash)),
             transaction_index: self.trace.index as u64,
+            trace_address: self.trace_address(),
         }
     }
 }
@@ -141,9 +168,10 @@ impl<'a> TransactionTraceAt<'a> {
     }
 }
 
-impl<'a> Into<web3::types::Transaction> for TransactionTraceAt<'a> {
-    fn into(self) -> web3::types::Transaction {
-        web3::types::Transaction {
+/// Firehose does not give us the type or the fee fields of transactions
+impl<'a> Into<EthereumTransaction> for TransactionTraceAt<'a> {
+    fn into(self) -> EthereumTransaction {
+        EthereumTransaction::from(web3::types::Transaction {
             hash: H256::from_slice(&self.trace.hash),
             nonce: U256::from(self.trace.nonce),
             block_hash: Some(H256::from_slice(&self.block.hash)),
@@ -163,7 +191,7 @@ impl<'a> Into<web3::types::Transaction> for TransactionTraceAt<'a> {
                 .map_or_else(|| U256::from(0), |x| x.into()),
             gas: U256::from(self.trace.gas_used),
             input: Bytes::from(self.trace.input.clone()),
-        }
+        })
     }
 }
 
@@ -271,8 +299,38 @@ impl Into<EthereumBlockWithCalls> for &Block {
                         })
                     })
                     .collect(),
+                uncles: self.uncles.iter().map(Into::into).collect(),
             },
             calls: Some(vec![]),
         }
     }
 }
+
+impl Into<EthereumUncle> for &BlockHeader {
+    fn into(self) -> EthereumUncle {
+        EthereumUncle {
+            hash: Some(H256::from_slice(&self.hash)),
+            number: Some(U64::from(self.number)),
+            author: H160::from_slice(&self.coinbase),
+            parent_hash: H256::from_slice(&self.parent_hash),
+            uncles_hash: H256::from_slice(&self.uncle_hash),
+            state_root: H256::from_slice(&self.state_root),
+            transactions_root: H256::from_slice(&self.transactions_root),
+            receipts_root: H256::from_slice(&self.receipt_root),
+            gas_used: U256::from(self.gas_used),
+            gas_limit: U256::from(self.gas_limit),
+            timestamp: self
+                .timestamp
+                .as_ref()
+                .map_or_else(U256::default, |v| U256::from(v.seconds)),
+            difficulty: self
+                .difficulty
+                .as_ref()
+                .map_or_else(U256::default, |v| v.into()),
+            extra_data: Bytes::from(self.extra_data.clone()),
+            mix_hash: Some(H256::from_slice(&self.mix_hash)),
+            nonce: Some(H64::from_low_u64_be(self.nonce)),
+            ..Default::default()
+        }
+    }
+}
@@ -5,25 +5,28 @@ use graph::components::store::StoredDynamicDataSource;
 use graph::prelude::futures03::future::try_join;
 use graph::prelude::futures03::stream::FuturesOrdered;
 use graph::prelude::{Entity, Link, SubgraphManifestValidationError};
-use graph::slog::trace;
-use std::collections::BTreeMap;
+use graph::runtime::DeterministicHostError;
+use graph::slog::{trace, warn};
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::{convert::TryFrom, sync::Arc};
 use tiny_keccak::{keccak256, Keccak};
 use web3::types::{Log, Transaction, H256};
 
 use graph::{
-    blockchain::{self, Blockchain},
+    blockchain::{self, Blockchain, DataSource as _},
     prelude::{
         async_trait, info, serde_json, BlockNumber, CheapClone, DataSourceTemplateInfo,
-        Deserialize, EthereumCall, LightEthereumBlock, LightEthereumBlockExt, LinkResolver, Logger,
-        TryStreamExt,
+        Deserialize, EthereumCall, EthereumTransaction, EthereumUncle, LightEthereumBlock,
+        LightEthereumBlockExt, LinkResolver, Logger, TryStreamExt,
     },
 };
 
-use graph::data::subgraph::{calls_host_fn, DataSourceContext, Source};
+use graph::data::subgraph::{calls_host_fn, DataSourceContext, Source, API_VERSION_0_0_6};
 
+use crate::adapter::{matches_topic_values, TopicValues};
 use crate::chain::Chain;
+use crate::log_size::{self, LogFit, MAX_LOG_DYNAMIC_DATA_SIZE};
 use crate::trigger::{EthereumBlockTriggerType, EthereumTrigger, MappingTrigger};
 
 /// Runtime representation of a data source.
@@ -38,6 +41,14 @@ pub struct DataSource {
     pub mapping: Mapping,
     pub context: Arc<Option<DataSourceContext>>,
     pub creation_block: Option<BlockNumber>,
+    /// The first block the deployment of this data source processes since
+    /// it was started. That is later than the start block of the data
+    /// source if the deployment was grafted onto a block after it
+    pub first_block: BlockNumber,
+    /// The block in which the block handler with a `once` filter ran, if
+    /// it already ran. This is persisted with the deployment and set when
+    /// the deployment starts
+    pub once_handlers_ran: Option<BlockNumber>,
     pub contract_abi: Arc<MappingABI>,
 }
 
@@ -50,14 +61,27 @@ impl blockchain::DataSource<Chain> for DataSource {
         self.source.start_block
     }
 
+    fn end_block(&self) -> Option<BlockNumber> {
+        self.source.end_block
+    }
+
+    fn handler_kinds(&self) -> BTreeSet<&'static str> {
+        self.mapping.handler_kinds()
+    }
+
     fn match_and_decode(
         &self,
         trigger: &<Chain as Blockchain>::TriggerData,
         block: Arc<<Chain as Blockchain>::Block>,
         logger: &Logger,
     ) -> Result<Option<<Chain as Blockchain>::MappingTrigger>, Error> {
+        // Only block handlers get to see the uncles
+        let uncles = match trigger {
+            EthereumTrigger::Block(..) => block.uncles(),
+            _ => None,
+        };
         let block = block.light_block();
-        self.match_and_decode(trigger, block, logger)
+        self.match_and_decode(trigger, block, uncles, logger)
     }
 
     fn name(&self) -> &str {
@@ -80,6 +104,22 @@ impl blockchain::DataSource<Chain> for DataSource {
         self.creation_block
     }
 
+    fn set_first_block(&mut self, first_block: BlockNumber) {
+        self.first_block = first_block;
+    }
+
+    fn once_handler_block(&self) -> Option<BlockNumber> {
+        if self.mapping.has_block_handler_with_once_filter() {
+            Some(self.once_handlers_ran.unwrap_or_else(|| self.once_block()))
+        } else {
+            None
+        }
+    }
+
+    fn set_once_handlers_ran(&mut self, block: BlockNumber) {
+        self.once_handlers_ran = Some(block);
+    }
+
     fn is_duplicate_of(&self, other: &Self) -> bool {
         let DataSource {
             kind,
@@ -92,6 +132,8 @@ impl blockchain::DataSource<Chain> for DataSource {
             // The creation block is ignored for detection duplicate data sources.
             // Contract ABI equality is implicit in `source` and `mapping.abis` equality.
             creation_block: _,
+            first_block: _,
+            once_handlers_ran: _,
             contract_abi: _,
         } = self;
 
@@ -149,6 +191,8 @@ impl blockchain::DataSource<Chain> for DataSource {
             mapping: template.mapping.clone(),
             context: Arc::new(context),
             creation_block,
+            first_block: 0,
+            once_handlers_ran: None,
             contract_abi,
         })
     }
@@ -168,22 +212,148 @@ impl blockchain::DataSource<Chain> for DataSource {
         let has_too_many_block_handlers = {
             let mut non_filtered_block_handler_count = 0;
             let mut call_filtered_block_handler_count = 0;
+            let mut once_filtered_block_handler_count = 0;
+            let mut polling_filtered_block_handler_count = 0;
             self.mapping
                 .block_handlers
                 .iter()
-                .for_each(|block_handler| {
-                    if block_handler.filter.is_none() {
-                        non_filtered_block_handler_count += 1
-                    } else {
-                        call_filtered_block_handler_count += 1
+                .for_each(|block_handler| match block_handler.filter {
+                    None => non_filtered_block_handler_count += 1,
+                    Some(BlockHandlerFilter::Call) => call_filtered_block_handler_count += 1,
+                    Some(BlockHandlerFilter::Once) => once_filtered_block_handler_count += 1,
+                    Some(BlockHandlerFilter::Polling { .. }) => {
+                        polling_filtered_block_handler_count += 1
                     }
                 });
-            non_filtered_block_handler_count > 1 || call_filtered_block_handler_count > 1
+            non_filtered_block_handler_count > 1
+                || call_filtered_block_handler_count > 1
+                || once_filtered_block_handler_count > 1
+                || polling_filtered_block_handler_count > 1
         };
         if has_too_many_block_handlers {
             errors.push(SubgraphManifestValidationError::DataSourceBlockHandlerLimitExceeded);
         }
 
+        // Validate that handlers that run once are not also used for other
+        // block handlers, since they would then run more than once
+        for once_handler in self
+            .mapping
+            .block_handlers
+            .iter()
+            .filter(|handler| handler.filter == Some(BlockHandlerFilter::Once))
+        {
+            let conflict = self.mapping.block_handlers.iter().any(|handler| {
+                handler.handler == once_handler.handler
+                    && handler.filter != Some(BlockHandlerFilter::Once)
+            });
+            if conflict {
+                errors.push(
+                    SubgraphManifestValidationError::DataSourceBlockHandlerOnceFilterConflict(
+                        once_handler.handler.clone(),
+                    ),
+                );
+            }
+        }
+
+        for handler in &self.mapping.block_handlers {
+            if let Some(BlockHandlerFilter::Polling { every }) = handler.filter {
+                if every < 1 {
+                    errors.push(
+                        SubgraphManifestValidationError::DataSourceBlockHandlerPollingInterval(
+                            handler.handler.clone(),
+                            every,
+                        ),
+                    );
+                }
+            }
+        }
+
+        // Validate that each event handler refers to exactly one event in
+        // the ABI of the contract
+        for handler in &self.mapping.event_handlers {
+            match self.contract_event_with_signature(&handler.event) {
+                Ok(event) => {
+                    // Handlers with an explicit `topic0` pick their logs by
+                    // that topic, which is how anonymous events were
+                    // handled before handlers could be marked anonymous
+                    if handler.topic0.is_none() && handler.anonymous != event.anonymous {
+                        errors.push(
+                            SubgraphManifestValidationError::DataSourceEventHandlerAnonymousMismatch(
+                                handler.handler.clone(),
+                                handler.event.clone(),
+                                event.anonymous,
+                            ),
+                        );
+                    }
+
+                    // Only indexed parameters become topics
+                    let indexed = event.inputs.iter().filter(|input| input.indexed).count();
+                    let filters = [&handler.topic1, &handler.topic2, &handler.topic3];
+                    for (i, values) in filters.iter().enumerate() {
+                        let values = match values {
+                            Some(values) => values,
+                            None => continue,
+                        };
+                        if i >= indexed {
+                            errors.push(
+                                SubgraphManifestValidationError::DataSourceEventHandlerTopicNotIndexed(
+                                    handler.handler.clone(),
+                                    i + 1,
+                                    handler.event.clone(),
+                                    indexed,
+                                ),
+                            );
+                        } else if values.is_empty() {
+                            errors.push(
+                                SubgraphManifestValidationError::DataSourceEventHandlerTopicEmpty(
+                                    handler.handler.clone(),
+                                    i + 1,
+                                ),
+                            );
+                        }
+                    }
+                }
+                Err(e) => errors.push(e),
+            }
+            if handler.anonymous {
+                // Without a signature topic, the only thing that narrows
+                // down the logs we request is the address of the contract
+                if self.source.address.is_none() {
+                    errors.push(
+                        SubgraphManifestValidationError::DataSourceEventHandlerAnonymousWithoutAddress(
+                            handler.handler.clone(),
+                        ),
+                    );
+                }
+                if handler.has_topic_filter() {
+                    errors.push(
+                        SubgraphManifestValidationError::DataSourceEventHandlerAnonymousTopicFilter(
+                            handler.handler.clone(),
+                        ),
+                    );
+                }
+            }
+            if handler.receipt && self.mapping.api_version < API_VERSION_0_0_6 {
+                errors.push(
+                    SubgraphManifestValidationError::DataSourceEventHandlerReceiptApiVersion(
+                        handler.handler.clone(),
+                    ),
+                );
+            }
+        }
+
+        if let Some(end_block) = self.source.end_block {
+            if end_block < self.source.start_block {
+                errors.push(
+                    SubgraphManifestValidationError::DataSourceEndBlockBeforeStartBlock(
+                        self.name.clone(),
+                        self.source.start_block,
+                        end_block,
+                    ),
+                );
+            }
+        }
+
         errors
     }
 
@@ -219,19 +389,29 @@ impl DataSource {
             mapping,
             context: Arc::new(context),
             creation_block,
+            first_block: 0,
+            once_handlers_ran: None,
             contract_abi,
         })
     }
 
     fn handlers_for_log(&self, log: &Log) -> Result<Vec<MappingEventHandler>, Error> {
-        // Get signature from the log
-        let topic0 = log.topics.get(0).context("Ethereum event has no topics")?;
+        // Get signature from the log. Logs without topics can only be for
+        // anonymous events
+        let topic0 = log.topics.get(0);
+        ensure!(
+            topic0.is_some() || self.mapping.has_anonymous_event_handlers(),
+            "Ethereum event has no topics"
+        );
 
         let handlers = self
             .mapping
             .event_handlers
             .iter()
-            .filter(|handler| *topic0 == handler.topic0())
+            // Anonymous events can only be told apart by decoding the log
+            .filter(|handler| handler.anonymous || topic0 == Some(&handler.topic0()))
+            // Providers may return more logs than we asked for
+            .filter(|handler| handler.matches_topics(log))
             .cloned()
             .collect::<Vec<_>>();
 
@@ -277,110 +457,120 @@ impl DataSource {
                 .iter()
                 .find(move |handler| handler.filter == Some(BlockHandlerFilter::Call))
                 .cloned(),
+            EthereumBlockTriggerType::Once => self
+                .mapping
+                .block_handlers
+                .iter()
+                .find(move |handler| handler.filter == Some(BlockHandlerFilter::Once))
+                .cloned(),
+            EthereumBlockTriggerType::Polling => self
+                .mapping
+                .block_handlers
+                .iter()
+                .find(move |handler| {
+                    matches!(handler.filter, Some(BlockHandlerFilter::Polling { .. }))
+                })
+                .cloned(),
         }
     }
 
-    /// Returns the contract event with the given signature, if it exists. A an event from the ABI
-    /// will be matched if:
-    /// 1. An event signature is equal to `signature`.
-    /// 2. There are no equal matches, but there is exactly one event that equals `signature` if all
-    ///    `indexed` modifiers are removed from the parameters.
-    fn contract_event_with_signature(&self, signature: &str) -> Option<&Event> {
-        // Returns an `Event(uint256,address)` signature for an event, without `indexed` hints.
-        fn ambiguous_event_signature(event: &Event) -> String {
-            format!(
-                "{}({})",
-                event.name,
-                event
-                    .inputs
-                    .iter()
-                    .map(|input| format!("{}", event_param_type_signature(&input.kind)))
-                    .collect::<Vec<_>>()
-                    .join(",")
-            )
-        }
+    /// The interval of the block handler with a `polling` filter, if
+    /// there is one
+    pub(crate) fn polling_interval(&self) -> Option<BlockNumber> {
+        self.mapping
+            .block_handlers
+            .iter()
+            .find_map(|handler| match handler.filter {
+                Some(BlockHandlerFilter::Polling { every }) => Some(every),
+                _ => None,
+            })
+    }
 
-        // Returns an `Event(indexed uint256,address)` type signature for an event.
-        fn event_signature(event: &Event) -> String {
-            format!(
-                "{}({})",
-                event.name,
-                event
-                    .inputs
-                    .iter()
-                    .map(|input| format!(
-                        "{}{}",
-                        if input.indexed { "indexed " } else { "" },
-                        event_param_type_signature(&input.kind)
-                    ))
-                    .collect::<Vec<_>>()
-                    .join(",")
-            )
-        }
+    /// The block from which the intervals of a `polling` block handler
+    /// count: the block in which a data source was created from a
+    /// template, and the start block of the data source otherwise. This
+    /// does not depend on where indexing resumed or on grafting, so that
+    /// a handler runs at the same blocks in all deployments of a subgraph
+    pub(crate) fn polling_start(&self) -> BlockNumber {
+        self.creation_block.unwrap_or(self.source.start_block)
+    }
 
-        // Returns the signature of an event parameter type (e.g. `uint256`).
-        fn event_param_type_signature(kind: &ParamType) -> String {
-            use ParamType::*;
-
-            match kind {
-                Address => "address".into(),
-                Bytes => "bytes".into(),
-                Int(size) => format!("int{}", size),
-                Uint(size) => format!("uint{}", size),
-                Bool => "bool".into(),
-                String => "string".into(),
-                Array(inner) => format!("{}[]", event_param_type_signature(&*inner)),
-                FixedBytes(size) => format!("bytes{}", size),
-                FixedArray(inner, size) => {
-                    format!("{}[{}]", event_param_type_signature(&*inner), size)
-                }
-                Tuple(components) => format!(
-                    "({})",
-                    components
-                        .iter()
-                        .map(|component| event_param_type_signature(&component))
-                        .collect::<Vec<_>>()
-                        .join(",")
-                ),
+    /// Whether the block handler with a `polling` filter runs at `block`
+    pub(crate) fn polls_at(&self, block: BlockNumber) -> bool {
+        match self.polling_interval() {
+            Some(every) if every >= 1 => {
+                let start = self.polling_start();
+                block >= start && (block - start) % every == 0
             }
+            _ => false,
         }
+    }
 
-        self.contract_abi
-            .contract
+    /// The block at which block handlers with a `once` filter run: the
+    /// block in which a data source was created from a template, and the
+    /// start block of the data source otherwise. Data sources in the
+    /// manifest never run their `once` handler before the first block the
+    /// deployment processes, so that grafted deployments and data sources
+    /// without an explicit `startBlock` run it in that block. Once the
+    /// handler ran, it only runs again in the block in which it ran, see
+    /// `once_handlers_ran`
+    pub(crate) fn once_block(&self) -> BlockNumber {
+        match self.creation_block {
+            Some(creation_block) => creation_block,
+            None => self.source.start_block.max(self.first_block),
+        }
+    }
+
+    /// Returns the contract event with the given signature. An event from
+    /// the ABI matches if
+    /// 1. its signature, including `indexed` modifiers, is equal to `signature`, or
+    /// 2. no event matches that way, and there is exactly one event whose
+    ///    signature is equal to `signature` once all `indexed` modifiers are
+    ///    removed. This also tells overloaded events, i.e., events with the
+    ///    same name but different parameter types, apart.
+    ///
+    /// It is an error if no event or more than one event matches
+    fn contract_event_with_signature(
+        &self,
+        signature: &str,
+    ) -> Result<&Event, SubgraphManifestValidationError> {
+        let contract = &self.contract_abi.contract;
+        if let Some(event) = contract
             .events()
             .find(|event| event_signature(event) == signature)
-            .or_else(|| {
-                // Fallback for subgraphs that don't use `indexed` in event signatures yet:
-                //
-                // If there is only one event variant with this name and if its signature
-                // without `indexed` matches the event signature from the manifest, we
-                // can safely assume that the event is a match, we don't need to force
-                // the subgraph to add `indexed`.
+        {
+            return Ok(event);
+        }
 
+        // Fallback for subgraphs that don't use `indexed` in event signatures
+        let matching_events = contract
+            .events()
+            .filter(|event| event_signature_without_indexed(event) == signature)
+            .collect::<Vec<_>>();
+        match matching_events.as_slice() {
+            [event] => Ok(event),
+            [] => {
                 // Extract the event name; if there is no '(' in the signature,
                 // `event_name` will be empty and not match any events, so that's ok
                 let parens = signature.find('(').unwrap_or(0);
                 let event_name = &signature[0..parens];
-
-                let matching_events = self
-                    .contract_abi
-                    .contract
+                let near_misses = contract
                     .events()
                     .filter(|event| event.name == event_name)
-                    .collect::<Vec<_>>();
-
-                // Only match the event signature without `indexed` if there is
-                // only a single event variant
-                if matching_events.len() == 1
-                    && ambiguous_event_signature(matching_events[0]) == signature
-                {
-                    Some(matching_events[0])
-                } else {
-                    // More than one event variant or the signature
-                    // still doesn't match, even if we ignore `indexed` hints
-                    None
-                }
-            })
+                    .map(event_signature)
+                    .collect();
+                Err(SubgraphManifestValidationError::DataSourceEventNotFound(
+                    self.name.clone(),
+                    signature.to_string(),
+                    near_misses,
+                ))
+            }
+            events => Err(SubgraphManifestValidationError::DataSourceEventAmbiguous(
+                self.name.clone(),
+                signature.to_string(),
+                events.iter().map(|event| event_signature(event)).collect(),
+            )),
+        }
     }
 
     fn contract_function_with_signature(&self, target_signature: &str) -> Option<&Function> {
@@ -419,10 +609,12 @@ impl DataSource {
         let trigger_address = match trigger {
             EthereumTrigger::Block(_, EthereumBlockTriggerType::WithCallTo(address)) => address,
             EthereumTrigger::Call(call) => &call.to,
-            EthereumTrigger::Log(log) => &log.address,
+            EthereumTrigger::Log(log, _) => &log.address,
 
             // Unfiltered block triggers match any data source address.
             EthereumTrigger::Block(_, EthereumBlockTriggerType::Every) => return true,
+            EthereumTrigger::Block(_, EthereumBlockTriggerType::Once) => return true,
+            EthereumTrigger::Block(_, EthereumBlockTriggerType::Polling) => return true,
         };
 
         ds_address == *trigger_address
@@ -434,6 +626,7 @@ impl DataSource {
         &self,
         trigger: &EthereumTrigger,
         block: Arc<LightEthereumBlock>,
+        uncles: Option<Arc<Vec<EthereumUncle>>>,
         logger: &Logger,
     ) -> Result<Option<MappingTrigger>, Error> {
         if !self.matches_trigger_address(&trigger) {
@@ -444,15 +637,39 @@ impl DataSource {
             return Ok(None);
         }
 
+        if let Some(end_block) = self.source.end_block {
+            if block.number() > end_block {
+                return Ok(None);
+            }
+        }
+
         match trigger {
-            EthereumTrigger::Block(_, trigger_type) => {
+            EthereumTrigger::Block(ptr, trigger_type) => {
+                // Several data sources may start in the same block, but
+                // only the ones that start at this block run their `once`
+                // handler
+                if *trigger_type == EthereumBlockTriggerType::Once
+                    && self.once_handler_block() != Some(ptr.number)
+                {
+                    return Ok(None);
+                }
+                // Likewise, data sources with different intervals poll at
+                // different blocks
+                if *trigger_type == EthereumBlockTriggerType::Polling && !self.polls_at(ptr.number)
+                {
+                    return Ok(None);
+                }
                 let handler = match self.handler_for_block(trigger_type) {
                     Some(handler) => handler,
                     None => return Ok(None),
                 };
-                Ok(Some(MappingTrigger::Block { block, handler }))
+                Ok(Some(MappingTrigger::Block {
+                    block,
+                    uncles,
+                    handler,
+                }))
             }
-            EthereumTrigger::Log(log) => {
+            EthereumTrigger::Log(log, receipt) => {
                 let potential_handlers = self.handlers_for_log(log)?;
 
                 // Map event handlers to (event handler, event ABI) pairs; fail if there are
@@ -461,17 +678,8 @@ impl DataSource {
                     .into_iter()
                     .map(|event_handler| {
                         // Identify the event ABI in the contract
-                        let event_abi = self
-                            .contract_event_with_signature(event_handler.event.as_str())
-                            .with_context(|| {
-                                anyhow!(
-                                    "Event with the signature \"{}\" not found in \
-                                            contract \"{}\" of data source \"{}\"",
-                                    event_handler.event,
-                                    self.contract_abi.name,
-                                    self.name,
-                                )
-                            })?;
+                        let event_abi =
+                            self.contract_event_with_signature(event_handler.event.as_str())?;
                         Ok((event_handler, event_abi))
                     })
                     .collect::<Result<Vec<_>, anyhow::Error>>()?;
@@ -481,31 +689,80 @@ impl DataSource {
                 // but have indexed vs. non-indexed params that are encoded differently).
                 //
                 // Map (handler, event ABI) pairs to (handler, decoded params) pairs.
-                let mut matching_handlers = valid_handlers
-                    .into_iter()
-                    .filter_map(|(event_handler, event_abi)| {
-                        event_abi
-                            .parse_log(RawLog {
-                                topics: log.topics.clone(),
-                                data: log.data.clone().0,
-                            })
-                            .map(|log| log.params)
-                            .map_err(|e| {
-                                trace!(
-                                    logger,
-                                    "Skipping handler because the event parameters do not \
-                                    match the event signature. This is typically the case \
-                                    when parameters are indexed in the event but not in the \
-                                    signature or the other way around";
-                                    "handler" => &event_handler.handler,
-                                    "event" => &event_handler.event,
-                                    "error" => format!("{}", e),
-                                );
-                            })
-                            .ok()
-                            .map(|params| (event_handler, params))
-                    })
-                    .collect::<Vec<_>>();
+                let mut matching_handlers = Vec::new();
+                for (event_handler, event_abi) in valid_handlers {
+                    // Check the size of the log before decoding it so that
+                    // we do not copy or decode huge logs that can't match
+                    match log_size::check(event_abi, log, *MAX_LOG_DYNAMIC_DATA_SIZE) {
+                        LogFit::Fits => {}
+                        LogFit::Mismatch(reason) => {
+                            trace!(
+                                logger,
+                                "Skipping handler because the log does not match the \
+                                 layout of the event";
+                                "handler" => &event_handler.handler,
+                                "event" => &event_handler.event,
+                                "reason" => reason,
+                            );
+                            continue;
+                        }
+                        LogFit::Oversized { size, limit } => {
+                            match self.mapping.oversized_log_policy() {
+                                // Mappings before apiVersion 0.0.6 that do not
+                                // set a policy decode all logs like they always did
+                                None => {}
+                                Some(OversizedLogPolicy::Skip) => {
+                                    warn!(
+                                        logger,
+                                        "Skipping handler because the log data is larger than \
+                                         the event allows";
+                                        "handler" => &event_handler.handler,
+                                        "event" => &event_handler.event,
+                                        "size" => size,
+                                        "limit" => limit,
+                                        "tx" => format!("{:?}", log.transaction_hash),
+                                    );
+                                    continue;
+                                }
+                                Some(OversizedLogPolicy::Fail) => {
+                                    return Err(DeterministicHostError(anyhow!(
+                                        "Log for event `{}` in transaction {:?} has {} bytes of \
+                                         data, but handler `{}` accepts at most {} bytes",
+                                        event_handler.event,
+                                        log.transaction_hash,
+                                        size,
+                                        event_handler.handler,
+                                        limit
+                                    ))
+                                    .into());
+                                }
+                            }
+                        }
+                    }
+
+                    let params = event_abi
+                        .parse_log(RawLog {
+                            topics: log.topics.clone(),
+                            data: log.data.clone().0,
+                        })
+                        .map(|log| log.params)
+                        .map_err(|e| {
+                            trace!(
+                                logger,
+                                "Skipping handler because the event parameters do not \
+                                match the event signature. This is typically the case \
+                                when parameters are indexed in the event but not in the \
+                                signature or the other way around";
+                                "handler" => &event_handler.handler,
+                                "event" => &event_handler.event,
+                                "error" => format!("{}", e),
+                            );
+                        })
+                        .ok();
+                    if let Some(params) = params {
+                        matching_handlers.push((event_handler, params));
+                    }
+                }
 
                 if matching_handlers.is_empty() {
                     return Ok(None);
@@ -532,21 +789,36 @@ impl DataSource {
                         .context("Found no transaction for event")?
                 } else {
                     // Infer some fields from the log and fill the rest with zeros.
-                    Transaction {
+                    EthereumTransaction::from(Transaction {
                         hash: log.transaction_hash.unwrap(),
                         block_hash: block.hash,
                         block_number: block.number,
                         transaction_index: log.transaction_index,
                         ..Transaction::default()
-                    }
+                    })
+                };
+
+                // The block stream fetches receipts for all logs that a
+                // handler with `receipt: true` might handle
+                let receipt = if event_handler.receipt {
+                    Some(receipt.cheap_clone().ok_or_else(|| {
+                        anyhow!(
+                            "Found no receipt for transaction {:?} for handler `{}`",
+                            log.transaction_hash,
+                            event_handler.handler
+                        )
+                    })?)
+                } else {
+                    None
                 };
 
                 Ok(Some(MappingTrigger::Log {
                     block,
                     transaction: Arc::new(transaction),
-                    log: log.cheap_clone(),
+                    log: log.log().cheap_clone(),
                     params,
                     handler: event_handler,
+                    receipt,
                 }))
             }
             EthereumTrigger::Call(call) => {
@@ -724,10 +996,13 @@ impl TryFrom<DataSourceTemplateInfo<Chain>> for DataSource {
                 address: Some(address),
                 abi: template.source.abi,
                 start_block: 0,
+                end_block: None,
             },
             mapping: template.mapping,
             context: Arc::new(context),
             creation_block: Some(creation_block),
+            first_block: 0,
+            once_handlers_ran: None,
             contract_abi,
         })
     }
@@ -784,6 +1059,10 @@ impl blockchain::DataSourceTemplate<Chain> for DataSourceTemplate {
     fn runtime(&self) -> &[u8] {
         self.mapping.runtime.as_ref()
     }
+
+    fn handler_kinds(&self) -> BTreeSet<&'static str> {
+        self.mapping.handler_kinds()
+    }
 }
 
 #[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Deserialize)]
@@ -800,6 +1079,8 @@ pub struct UnresolvedMapping {
     pub call_handlers: Vec<MappingCallHandler>,
     #[serde(default)]
     pub event_handlers: Vec<MappingEventHandler>,
+    #[serde(default)]
+    pub oversized_logs: Option<OversizedLogPolicy>,
     pub file: Link,
 }
 
@@ -813,11 +1094,37 @@ pub struct Mapping {
     pub block_handlers: Vec<MappingBlockHandler>,
     pub call_handlers: Vec<MappingCallHandler>,
     pub event_handlers: Vec<MappingEventHandler>,
+    pub oversized_logs: Option<OversizedLogPolicy>,
     pub runtime: Arc<Vec<u8>>,
     pub link: Link,
 }
 
+/// What to do with logs whose data is larger than the event of a handler
+/// allows, according to `MAX_LOG_DYNAMIC_DATA_SIZE`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizedLogPolicy {
+    /// Log a warning and do not run the handler
+    Skip,
+    /// Fail the subgraph with a deterministic error
+    Fail,
+}
+
 impl Mapping {
+    /// The policy for oversized logs that the manifest sets, or `Skip` for
+    /// mappings with apiVersion 0.0.6 or later. Older mappings that do not
+    /// set a policy get `None` and decode oversized logs as before, since
+    /// skipping them would change the PoI of existing subgraphs
+    pub fn oversized_log_policy(&self) -> Option<OversizedLogPolicy> {
+        self.oversized_logs.or_else(|| {
+            if self.api_version >= API_VERSION_0_0_6 {
+                Some(OversizedLogPolicy::Skip)
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn requires_archive(&self) -> anyhow::Result<bool> {
         calls_host_fn(&self.runtime, "ethereum.call")
     }
@@ -832,6 +1139,38 @@ impl Mapping {
             .any(|handler| matches!(handler.filter, Some(BlockHandlerFilter::Call)))
     }
 
+    pub fn has_block_handler_with_once_filter(&self) -> bool {
+        self.block_handlers
+            .iter()
+            .any(|handler| matches!(handler.filter, Some(BlockHandlerFilter::Once)))
+    }
+
+    pub fn has_anonymous_event_handlers(&self) -> bool {
+        self.event_handlers.iter().any(|handler| handler.anonymous)
+    }
+
+    /// The kinds of handlers in this mapping: `event`, `call`, and `block`,
+    /// `block/call`, `block/once` or `block/polling` depending on the
+    /// filter of block handlers
+    pub fn handler_kinds(&self) -> BTreeSet<&'static str> {
+        let mut kinds = BTreeSet::new();
+        if !self.event_handlers.is_empty() {
+            kinds.insert("event");
+        }
+        if !self.call_handlers.is_empty() {
+            kinds.insert("call");
+        }
+        for handler in &self.block_handlers {
+            kinds.insert(match handler.filter {
+                None => "block",
+                Some(BlockHandlerFilter::Call) => "block/call",
+                Some(BlockHandlerFilter::Once) => "block/once",
+                Some(BlockHandlerFilter::Polling { .. }) => "block/polling",
+            });
+        }
+        kinds
+    }
+
     pub fn find_abi(&self, abi_name: &str) -> Result<Arc<MappingABI>, Error> {
         Ok(self
             .abis
@@ -857,6 +1196,7 @@ impl UnresolvedMapping {
             block_handlers,
             call_handlers,
             event_handlers,
+            oversized_logs,
             file: link,
         } = self;
 
@@ -890,6 +1230,7 @@ impl UnresolvedMapping {
             block_handlers: block_handlers.clone(),
             call_handlers: call_handlers.clone(),
             event_handlers: event_handlers.clone(),
+            oversized_logs,
             runtime,
             link,
         })
@@ -942,6 +1283,12 @@ pub enum BlockHandlerFilter {
     // Call filter will trigger on all blocks where the data source contract
     // address has been called
     Call,
+    // Once filter will trigger only on the block where the data source
+    // starts
+    Once,
+    // Polling filter will trigger on every `every`-th block, counting from
+    // the block where the data source starts
+    Polling { every: BlockNumber },
 }
 
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Deserialize)]
@@ -955,6 +1302,21 @@ pub struct MappingEventHandler {
     pub event: String,
     pub topic0: Option<H256>,
     pub handler: String,
+    /// Whether the handler gets the receipt of the transaction that
+    /// emitted the event
+    #[serde(default)]
+    pub receipt: bool,
+    /// The values of the first three indexed parameters of the event that
+    /// the handler wants; logs with other values are neither requested
+    /// from the provider nor passed to the handler
+    pub topic1: Option<Vec<H256>>,
+    pub topic2: Option<Vec<H256>>,
+    pub topic3: Option<Vec<H256>>,
+    /// Whether the event is declared `anonymous`. Logs of anonymous
+    /// events do not have the signature of the event as their first
+    /// topic, and we try to decode every log of the contract with them
+    #[serde(default)]
+    pub anonymous: bool,
 }
 
 impl MappingEventHandler {
@@ -962,6 +1324,85 @@ impl MappingEventHandler {
         self.topic0
             .unwrap_or_else(|| string_to_h256(&self.event.replace("indexed ", "")))
     }
+
+    /// Whether the handler only wants logs with certain values for some
+    /// indexed parameters
+    pub fn has_topic_filter(&self) -> bool {
+        self.topic1.is_some() || self.topic2.is_some() || self.topic3.is_some()
+    }
+
+    pub(crate) fn topic_values(&self) -> TopicValues {
+        [
+            self.topic1.clone(),
+            self.topic2.clone(),
+            self.topic3.clone(),
+        ]
+    }
+
+    /// Whether the topics of `log` have the values the handler wants
+    pub fn matches_topics(&self, log: &Log) -> bool {
+        matches_topic_values(
+            [&self.topic1, &self.topic2, &self.topic3].iter().copied(),
+            log,
+        )
+    }
+}
+
+/// Returns an `Event(indexed uint256,address)` type signature for an event.
+fn event_signature(event: &Event) -> String {
+    format!(
+        "{}({})",
+        event.name,
+        event
+            .inputs
+            .iter()
+            .map(|input| format!(
+                "{}{}",
+                if input.indexed { "indexed " } else { "" },
+                event_param_type_signature(&input.kind)
+            ))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+/// Returns an `Event(uint256,address)` signature for an event, without `indexed` hints.
+fn event_signature_without_indexed(event: &Event) -> String {
+    format!(
+        "{}({})",
+        event.name,
+        event
+            .inputs
+            .iter()
+            .map(|input| event_param_type_signature(&input.kind))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+/// Returns the signature of an event parameter type (e.g. `uint256`).
+fn event_param_type_signature(kind: &ParamType) -> String {
+    use ParamType::*;
+
+    match kind {
+        Address => "address".into(),
+        Bytes => "bytes".into(),
+        Int(size) => format!("int{}", size),
+        Uint(size) => format!("uint{}", size),
+        Bool => "bool".into(),
+        String => "string".into(),
+        Array(inner) => format!("{}[]", event_param_type_signature(&*inner)),
+        FixedBytes(size) => format!("bytes{}", size),
+        FixedArray(inner, size) => format!("{}[{}]", event_param_type_signature(&*inner), size),
+        Tuple(components) => format!(
+            "({})",
+            components
+                .iter()
+                .map(|component| event_param_type_signature(&component))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    }
 }
 
 /// Hashes a string to a H256 hash.
@@ -3,7 +3,7 @@ use ethabi::Token;
 use futures::future;
 use futures::prelude::*;
 use graph::components::transaction_receipt::LightTransactionReceipt;
-use graph::data::subgraph::UnifiedMappingApiVersion;
+use graph::data::subgraph::{UnifiedMappingApiVersion, API_VERSION_0_0_6};
 use graph::prelude::StopwatchMetrics;
 use graph::{
     blockchain::{block_stream::BlockWithTriggers, BlockPtr, IngestorError},
@@ -11,7 +11,7 @@ use graph::{
         anyhow::{self, anyhow, bail},
         async_trait, debug, error, ethabi,
         futures03::{self, compat::Future01CompatExt, FutureExt, StreamExt, TryStreamExt},
-        hex, info, retry, stream, tiny_keccak, trace, warn,
+        hex, info, retry, serde_json, stream, tiny_keccak, trace, warn,
         web3::{
             self,
             types::{
@@ -20,32 +20,38 @@ use graph::{
             },
         },
         BlockNumber, ChainStore, CheapClone, DynTryFuture, Error, EthereumCallCache, Logger,
-        TimeoutError, TryFutureExt,
+        TimeoutError, TryFutureExt, BLOCK_NUMBER_MAX,
     },
 };
 use graph::{
     components::ethereum::*,
     prelude::web3::types::{Trace, TraceFilter, TraceFilterBuilder, H160},
+    util::scan_counter::json_size,
 };
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::iter::FromIterator;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use web3::api::Web3;
 use web3::transports::batch::Batch;
+use web3::Transport as _;
 
 use crate::chain::BlockFinality;
+use crate::failover::ProviderHealth;
 use crate::{
     adapter::{
         EthGetLogsFilter, EthereumAdapter as EthereumAdapterTrait, EthereumBlockFilter,
         EthereumCallFilter, EthereumContractCall, EthereumContractCallError, EthereumLogFilter,
         ProviderEthRpcMetrics, SubgraphEthRpcMetrics,
     },
+    log_range::{is_range_too_large, LogRange, LogRanges},
+    peer_block_cache::PeerBlockCache,
     transport::Transport,
-    trigger::{EthereumBlockTriggerType, EthereumTrigger},
+    trigger::{BlockLog, EthereumBlockTriggerType, EthereumTrigger},
     TriggerFilter,
 };
 
@@ -57,6 +63,34 @@ pub struct EthereumAdapter {
     web3: Arc<Web3<Transport>>,
     metrics: Arc<ProviderEthRpcMetrics>,
     supports_eip_1898: bool,
+    peer_block_cache: Option<PeerBlockCache>,
+    health: Arc<ProviderHealth>,
+    /// The method that the provider answered the last time we probed it
+    /// for receipts of whole blocks. Shared by all clones of this adapter
+    block_receipts: Arc<Mutex<Option<BlockReceiptsMethod>>>,
+    /// Whether the provider answered the last `trace_filter` probe. Shared
+    /// by all clones of this adapter
+    supports_traces: Arc<AtomicBool>,
+}
+
+/// The methods that providers offer to get the receipts of all transactions
+/// in a block with one request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockReceiptsMethod {
+    /// `eth_getBlockReceipts`, supported by Erigon and recent versions of
+    /// most other clients
+    Eth,
+    /// `parity_getBlockReceipts`, supported by OpenEthereum and Nethermind
+    Parity,
+}
+
+impl BlockReceiptsMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockReceiptsMethod::Eth => "eth_getBlockReceipts",
+            BlockReceiptsMethod::Parity => "parity_getBlockReceipts",
+        }
+    }
 }
 
 lazy_static! {
@@ -69,10 +103,22 @@ lazy_static! {
     /// contract address, only event signature, and are therefore expensive.
     ///
     /// According to Ethereum node operators, size 500 is reasonable here.
+    /// Data sources without an address, which match events from any
+    /// contract, make such requests
     static ref MAX_EVENT_ONLY_RANGE: BlockNumber = std::env::var("GRAPH_ETHEREUM_MAX_EVENT_ONLY_RANGE")
-        .unwrap_or("500".into())
-        .parse::<BlockNumber>()
-        .expect("invalid number of parallel Ethereum block ranges to scan");
+        .ok()
+        .map(|s| {
+            s.parse::<BlockNumber>()
+                .ok()
+                .filter(|range| *range > 0)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "GRAPH_ETHEREUM_MAX_EVENT_ONLY_RANGE must be a positive number, but is `{}`",
+                        s
+                    )
+                })
+        })
+        .unwrap_or(500);
 
     static ref BLOCK_BATCH_SIZE: usize = std::env::var("ETHEREUM_BLOCK_BATCH_SIZE")
             .unwrap_or("10".into())
@@ -144,6 +190,10 @@ impl CheapClone for EthereumAdapter {
             web3: self.web3.cheap_clone(),
             metrics: self.metrics.cheap_clone(),
             supports_eip_1898: self.supports_eip_1898,
+            peer_block_cache: self.peer_block_cache.clone(),
+            health: self.health.cheap_clone(),
+            block_receipts: self.block_receipts.cheap_clone(),
+            supports_traces: self.supports_traces.cheap_clone(),
         }
     }
 }
@@ -156,6 +206,7 @@ impl EthereumAdapter {
         transport: Transport,
         provider_metrics: Arc<ProviderEthRpcMetrics>,
         supports_eip_1898: bool,
+        peer_block_cache: Option<PeerBlockCache>,
     ) -> Self {
         // Unwrap: The transport was constructed with this url, so it is valid and has a host.
         let hostname = graph::url::Url::parse(url)
@@ -164,7 +215,11 @@ impl EthereumAdapter {
             .unwrap()
             .to_string();
 
-        let web3 = Arc::new(Web3::new(transport));
+        let health = Arc::new(ProviderHealth::new(
+            provider.clone(),
+            provider_metrics.cheap_clone(),
+        ));
+        let web3 = Arc::new(Web3::new(transport.with_health(health.cheap_clone())));
 
         // Use the client version to check if it is ganache. For compatibility with unit tests, be
         // are lenient with errors, defaulting to false.
@@ -176,6 +231,16 @@ impl EthereumAdapter {
             .map(|s| s.contains("TestRPC"))
             .unwrap_or(false);
 
+        let block_receipts = probe_block_receipts(&web3).await;
+        match block_receipts {
+            Some(method) => info!(logger, "Fetching receipts for whole blocks";
+                                  "provider" => &provider, "method" => method.name()),
+            None => info!(logger, "Fetching receipts one transaction at a time";
+                          "provider" => &provider),
+        }
+
+        let supports_traces = Arc::new(AtomicBool::new(probe_traces(&web3).await));
+
         EthereumAdapter {
             logger,
             provider,
@@ -183,9 +248,48 @@ impl EthereumAdapter {
             web3,
             metrics: provider_metrics,
             supports_eip_1898: supports_eip_1898 && !is_ganache,
+            peer_block_cache,
+            health,
+            block_receipts: Arc::new(Mutex::new(block_receipts)),
+            supports_traces,
         }
     }
 
+    /// Whether requests to this provider failed recently so that other
+    /// providers for the same network should be used if possible
+    pub fn is_failing(&self) -> bool {
+        self.health.is_failing()
+    }
+
+    /// The method we use to get all receipts of a block at once, or `None`
+    /// if the provider did not support any the last time we probed it
+    pub fn block_receipts_method(&self) -> Option<BlockReceiptsMethod> {
+        *self.block_receipts.lock().unwrap()
+    }
+
+    /// Probe the provider for a method to get the receipts of whole blocks
+    /// again and return the method it supports
+    pub async fn reprobe_block_receipts(&self) -> Option<BlockReceiptsMethod> {
+        let block_receipts = probe_block_receipts(&self.web3).await;
+        *self.block_receipts.lock().unwrap() = block_receipts;
+        block_receipts
+    }
+
+    /// Whether the provider answered a `trace_filter` request the last
+    /// time we probed it
+    pub fn supports_traces(&self) -> bool {
+        self.supports_traces.load(Ordering::SeqCst)
+    }
+
+    /// Probe the provider for `trace_filter` support again and return
+    /// whether it has it
+    pub async fn reprobe_traces(&self) -> bool {
+        let supports_traces = probe_traces(&self.web3).await;
+        self.supports_traces
+            .store(supports_traces, Ordering::SeqCst);
+        supports_traces
+    }
+
     async fn traces(
         self,
         logger: Logger,
@@ -247,6 +351,13 @@ impl EthereumAdapter {
                         let elapsed = start.elapsed().as_secs_f64();
                         provider_metrics.observe_request(elapsed, "trace_filter");
                         subgraph_metrics.observe_request(elapsed, "trace_filter");
+                        if let Ok(traces) = &result {
+                            // The size of the response is about what the
+                            // traces take up in JSON
+                            subgraph_metrics
+                                .scan
+                                .add_traces(traces.len() as u64, json_size(traces));
+                        }
                         if result.is_err() {
                             provider_metrics.add_error("trace_filter");
                             subgraph_metrics.add_error("trace_filter");
@@ -273,6 +384,7 @@ impl EthereumAdapter {
                 })
             })
             .await
+            .and_then(|traces| canonical_traces(traces, from, to))
     }
 
     async fn logs_with_sigs(
@@ -282,16 +394,15 @@ impl EthereumAdapter {
         from: BlockNumber,
         to: BlockNumber,
         filter: Arc<EthGetLogsFilter>,
-        too_many_logs_fingerprints: &'static [&'static str],
     ) -> Result<Vec<Log>, TimeoutError<web3::error::Error>> {
         let eth_adapter = self.clone();
 
         retry("eth_getLogs RPC call", &logger)
             .when(move |res: &Result<_, web3::error::Error>| match res {
                 Ok(_) => false,
-                Err(e) => !too_many_logs_fingerprints
-                    .iter()
-                    .any(|f| e.to_string().contains(f)),
+                // Retrying the same range is pointless; the caller will
+                // ask for fewer blocks instead
+                Err(e) => !is_range_too_large(&e.to_string()),
             })
             .limit(*REQUEST_RETRIES)
             .timeout_secs(*JSON_RPC_TIMEOUT)
@@ -299,31 +410,63 @@ impl EthereumAdapter {
                 let start = Instant::now();
                 let subgraph_metrics = subgraph_metrics.clone();
                 let provider_metrics = eth_adapter.metrics.clone();
+                let eth_adapter = eth_adapter.cheap_clone();
 
                 // Create a log filter
                 let log_filter: Filter = FilterBuilder::default()
                     .from_block(from.into())
                     .to_block(to.into())
                     .address(filter.contracts.clone())
-                    .topics(Some(filter.event_signatures.clone()), None, None, None)
+                    .topics(
+                        // Requests for the anonymous events of contracts
+                        // do not filter on the first topic
+                        Some(filter.event_signatures.clone())
+                            .filter(|signatures| !signatures.is_empty()),
+                        filter.topics[0].clone(),
+                        filter.topics[1].clone(),
+                        filter.topics[2].clone(),
+                    )
                     .build();
 
-                // Request logs from client
-                eth_adapter
-                    .web3
-                    .eth()
-                    .logs(log_filter)
-                    .then(move |result| {
-                        let elapsed = start.elapsed().as_secs_f64();
-                        provider_metrics.observe_request(elapsed, "eth_getLogs");
-                        subgraph_metrics.observe_request(elapsed, "eth_getLogs");
-                        if result.is_err() {
+                // Request logs from client, streaming the response if the
+                // transport allows it
+                async move {
+                    let result = match eth_adapter.web3.transport().logs_client() {
+                        Some(client) => {
+                            client.logs(log_filter, from == to, &provider_metrics).await
+                        }
+                        None => {
+                            // We do not see the response; its size is about
+                            // what the logs take up in JSON
+                            eth_adapter
+                                .web3
+                                .eth()
+                                .logs(log_filter)
+                                .compat()
+                                .await
+                                .map(|logs| {
+                                    let size = json_size(&logs) as usize;
+                                    (logs, size)
+                                })
+                        }
+                    };
+                    let elapsed = start.elapsed().as_secs_f64();
+                    provider_metrics.observe_request(elapsed, "eth_getLogs");
+                    subgraph_metrics.observe_request(elapsed, "eth_getLogs");
+                    match result {
+                        Ok((logs, size)) => {
+                            subgraph_metrics
+                                .scan
+                                .add_logs(logs.len() as u64, size as u64);
+                            Ok(logs)
+                        }
+                        Err(e) => {
                             provider_metrics.add_error("eth_getLogs");
                             subgraph_metrics.add_error("eth_getLogs");
+                            Err(e)
                         }
-                        result
-                    })
-                    .compat()
+                    }
+                }
             })
             .await
     }
@@ -384,15 +527,8 @@ impl EthereumAdapter {
         from: BlockNumber,
         to: BlockNumber,
         filter: EthGetLogsFilter,
+        log_range: Arc<LogRange>,
     ) -> DynTryFuture<'static, Vec<Log>, Error> {
-        // Codes returned by Ethereum node providers if an eth_getLogs request is too heavy.
-        // The first one is for Infura when it hits the log limit, the rest for Alchemy timeouts.
-        const TOO_MANY_LOGS_FINGERPRINTS: &[&str] = &[
-            "ServerError(-32005)",
-            "503 Service Unavailable",
-            "ServerError(-32000)",
-        ];
-
         if from > to {
             panic!(
                 "cannot produce a log stream on a backwards block range (from={}, to={})",
@@ -404,27 +540,28 @@ impl EthereumAdapter {
         let eth = self.cheap_clone();
         let filter = Arc::new(filter);
 
-        let step = match filter.contracts.is_empty() {
-            // `to - from + 1`  blocks will be scanned.
-            false => to - from,
-            true => (to - from).min(*MAX_EVENT_ONLY_RANGE - 1),
+        let max_size = match filter.contracts.is_empty() {
+            false => BLOCK_NUMBER_MAX,
+            true => *MAX_EVENT_ONLY_RANGE,
         };
 
         // Typically this will loop only once and fetch the entire range in one request. But if the
         // node returns an error that signifies the request is to heavy to process, the range will
-        // be broken down to smaller steps.
-        futures03::stream::try_unfold((from, step), move |(start, step)| {
+        // be broken down to smaller steps, which `log_range` remembers for later requests.
+        futures03::stream::try_unfold(from, move |start| {
             let logger = logger.cheap_clone();
             let filter = filter.cheap_clone();
             let eth = eth.cheap_clone();
             let subgraph_metrics = subgraph_metrics.cheap_clone();
+            let log_range = log_range.cheap_clone();
 
             async move {
                 if start > to {
                     return Ok(None);
                 }
 
-                let end = (start + step).min(to);
+                let end = log_range.end(start, to, max_size);
+                let size = end - start + 1;
                 debug!(
                     logger,
                     "Requesting logs for blocks [{}, {}], {}", start, end, filter
@@ -436,7 +573,6 @@ impl EthereumAdapter {
                         start,
                         end,
                         filter.cheap_clone(),
-                        TOO_MANY_LOGS_FINGERPRINTS,
                     )
                     .await;
 
@@ -444,25 +580,26 @@ impl EthereumAdapter {
                     Err(e) => {
                         let string_err = e.to_string();
 
-                        // If the step is already 0, the request is too heavy even for a single
-                        // block. We hope this never happens, but if it does, make sure to error.
-                        if TOO_MANY_LOGS_FINGERPRINTS
-                            .iter()
-                            .any(|f| string_err.contains(f))
-                            && step > 0
-                        {
-                            // The range size for a request is `step + 1`. So it's ok if the step
-                            // goes down to 0, in that case we'll request one block at a time.
-                            let new_step = step / 10;
+                        // If we already asked for a single block, the request is too heavy
+                        // even for that. We hope this never happens, but if it does, make
+                        // sure to error. Our own limit on the size of responses does not
+                        // apply to single blocks and never gets us here.
+                        if is_range_too_large(&string_err) && log_range.shrink(size) {
                             debug!(logger, "Reducing block range size to scan for events";
-                                               "new_size" => new_step + 1);
-                            Ok(Some((vec![], (start, new_step))))
+                                           "size" => size,
+                                           "new_size" => log_range.limit(),
+                                           "error" => &string_err);
+                            Ok(Some((vec![], start)))
                         } else {
                             warn!(logger, "Unexpected RPC error"; "error" => &string_err);
                             Err(anyhow!("{}", string_err))
                         }
                     }
-                    Ok(logs) => Ok(Some((logs, (end + 1, step)))),
+                    Ok(logs) => {
+                        check_log_block_numbers(&logs, start, end)?;
+                        log_range.grow(size);
+                        Ok(Some((logs, end + 1)))
+                    }
                 }
             }
         })
@@ -622,17 +759,18 @@ impl EthereumAdapter {
 
         stream::iter_ok::<_, Error>(ids.into_iter().map(move |hash| {
             let web3 = web3.clone();
-            retry(format!("load block {}", hash), &logger)
+            let logger = logger.new(o!("block_hash" => format!("{:x}", hash)));
+            retry("load block", &logger)
                 .limit(*REQUEST_RETRIES)
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
-                    web3.eth()
-                        .block_with_txs(BlockId::Hash(hash))
-                        .from_err::<Error>()
+                    block_with_txs(&web3, BlockId::Hash(hash))
                         .and_then(move |block| {
-                            block.ok_or_else(|| {
+                            let block = block.ok_or_else(|| {
                                 anyhow::anyhow!("Ethereum node did not find block {:?}", hash)
-                            })
+                            })?;
+                            check_block_number(&block)?;
+                            Ok(block)
                         })
                         .compat()
                 })
@@ -655,7 +793,8 @@ impl EthereumAdapter {
 
         stream::iter_ok::<_, Error>(block_nums.into_iter().map(move |block_num| {
             let web3 = web3.clone();
-            retry(format!("load block ptr {}", block_num), &logger)
+            let logger = logger.new(o!("block_number" => block_num));
+            retry("load block ptr", &logger)
                 .no_limit()
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
@@ -663,9 +802,11 @@ impl EthereumAdapter {
                         .block(BlockId::Number(Web3BlockNumber::Number(block_num.into())))
                         .from_err::<Error>()
                         .and_then(move |block| {
-                            block.ok_or_else(|| {
+                            let block = block.ok_or_else(|| {
                                 anyhow!("Ethereum node did not find block {:?}", block_num)
-                            })
+                            })?;
+                            check_block_number(&block)?;
+                            Ok(block)
                         })
                         .compat()
                 })
@@ -708,22 +849,32 @@ impl EthereumAdapter {
         from: BlockNumber,
         to: BlockNumber,
         log_filter: EthereumLogFilter,
+        log_ranges: Arc<LogRanges>,
     ) -> DynTryFuture<'static, Vec<Log>, Error> {
         let eth: Self = self.cheap_clone();
         let logger = logger.clone();
 
         futures03::stream::iter(log_filter.eth_get_logs_filters().map(move |filter| {
+            let log_range = log_ranges.for_filter(&filter);
             eth.cheap_clone().log_stream(
                 logger.cheap_clone(),
                 subgraph_metrics.cheap_clone(),
                 from,
                 to,
                 filter,
+                log_range,
             )
         }))
         // Real limits on the number of parallel requests are imposed within the adapter.
         .buffered(1000)
         .try_concat()
+        .map_ok(|mut logs| {
+            // Requests for the same event with different topic filters
+            // can return the same log more than once
+            logs.sort_by_key(|log| (log.block_number, log.transaction_index, log.log_index));
+            logs.dedup_by_key(|log| (log.block_hash, log.log_index));
+            logs
+        })
         .boxed()
     }
 
@@ -797,23 +948,17 @@ impl EthereumAdapter {
             ));
         }
 
-        // Since we can only pull traces by block number and we have
-        // all the traces for the block, we need to ensure that the
-        // block hash for the traces is equal to the desired block hash.
-        // Assume all traces are for the same block.
-        if traces.iter().nth(0).unwrap().block_hash != block_hash {
-            return Err(anyhow!(
-                "Trace stream returned traces for an unexpected block: \
-                         number = `{}`, hash = `{}`",
-                block_number,
-                block_hash,
-            ));
-        }
+        calls_from_traces(traces, block_number, block_hash)
+    }
 
-        Ok(traces
-            .iter()
-            .filter_map(EthereumCall::try_from_trace)
-            .collect())
+    /// Reorg safety: all `numbers` must be final blocks.
+    pub(crate) fn block_numbers_to_ptrs(
+        &self,
+        logger: Logger,
+        numbers: Vec<BlockNumber>,
+    ) -> Box<dyn Future<Item = Vec<BlockPtr>, Error = Error> + Send> {
+        debug!(&logger, "Requesting hashes for blocks {:?}", numbers);
+        Box::new(self.load_block_ptrs_rpc(logger, numbers).collect())
     }
 
     /// Reorg safety: `to` must be a final block.
@@ -844,6 +989,70 @@ impl EthereumAdapter {
         )
         .map_err(Error::msg)
     }
+
+    /// The number of the latest block the provider knows about. This makes
+    /// exactly one `eth_blockNumber` call and does not retry, since it is
+    /// only used to check how far behind other providers this one is
+    pub async fn latest_block_number(&self) -> Result<BlockNumber, Error> {
+        let number = self
+            .web3
+            .eth()
+            .block_number()
+            .compat()
+            .await
+            .map_err(|e| anyhow!("could not get latest block number: {}", e))?;
+        BlockNumber::try_from(number.as_u64()).map_err(Error::msg)
+    }
+
+    /// The latest block the provider knows about, asked for once without
+    /// retrying
+    pub async fn latest_block_ptr(&self) -> Result<BlockPtr, Error> {
+        let block = self
+            .web3
+            .eth()
+            .block(Web3BlockNumber::Latest.into())
+            .compat()
+            .await
+            .map_err(|e| anyhow!("could not get latest block: {}", e))?
+            .ok_or_else(|| anyhow!("no latest block returned from Ethereum"))?;
+        check_block_number(&block)?;
+        Ok(BlockPtr::from(&block))
+    }
+
+    /// The hash the provider has for block `number`, asked for once
+    /// without retrying
+    pub async fn block_hash_at(&self, number: BlockNumber) -> Result<Option<H256>, Error> {
+        let block = self
+            .web3
+            .eth()
+            .block(BlockId::Number(number.into()))
+            .compat()
+            .await
+            .map_err(|e| anyhow!("could not get block #{}: {}", number, e))?;
+        Ok(block.and_then(|block| block.hash))
+    }
+
+    /// The name and version of the client software the provider runs
+    pub async fn client_version(&self) -> Result<String, Error> {
+        self.web3
+            .web3()
+            .client_version()
+            .compat()
+            .await
+            .map_err(|e| anyhow!("could not get client version: {}", e))
+    }
+
+    /// Whether the provider is still syncing with the network
+    pub async fn is_syncing(&self) -> Result<bool, Error> {
+        let state = self
+            .web3
+            .eth()
+            .syncing()
+            .compat()
+            .await
+            .map_err(|e| anyhow!("could not get sync state: {}", e))?;
+        Ok(matches!(state, web3::types::SyncState::Syncing(_)))
+    }
 }
 
 #[async_trait]
@@ -920,11 +1129,12 @@ impl EthereumAdapterTrait for EthereumAdapter {
                     web3.eth()
                         .block(Web3BlockNumber::Latest.into())
                         .map_err(|e| anyhow!("could not get latest block from Ethereum: {}", e))
-                        .from_err()
+                        .from_err::<IngestorError>()
                         .and_then(|block_opt| {
-                            block_opt.ok_or_else(|| {
-                                anyhow!("no latest block returned from Ethereum").into()
-                            })
+                            let block = block_opt
+                                .ok_or_else(|| anyhow!("no latest block returned from Ethereum"))?;
+                            check_block_number(&block)?;
+                            Ok(block)
                         })
                         .compat()
                 })
@@ -949,14 +1159,14 @@ impl EthereumAdapterTrait for EthereumAdapter {
                 .no_limit()
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
-                    web3.eth()
-                        .block_with_txs(Web3BlockNumber::Latest.into())
+                    block_with_txs(&web3, Web3BlockNumber::Latest.into())
                         .map_err(|e| anyhow!("could not get latest block from Ethereum: {}", e))
-                        .from_err()
+                        .from_err::<IngestorError>()
                         .and_then(|block_opt| {
-                            block_opt.ok_or_else(|| {
-                                anyhow!("no latest block returned from Ethereum").into()
-                            })
+                            let block = block_opt
+                                .ok_or_else(|| anyhow!("no latest block returned from Ethereum"))?;
+                            check_block_number(&block)?;
+                            Ok(block)
                         })
                         .compat()
                 })
@@ -1001,9 +1211,13 @@ impl EthereumAdapterTrait for EthereumAdapter {
                 .limit(*REQUEST_RETRIES)
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
-                    web3.eth()
-                        .block_with_txs(BlockId::Hash(block_hash))
-                        .from_err()
+                    block_with_txs(&web3, BlockId::Hash(block_hash))
+                        .and_then(|block| {
+                            if let Some(block) = &block {
+                                check_block_number(block)?;
+                            }
+                            Ok(block)
+                        })
                         .compat()
                 })
                 .map_err(move |e| {
@@ -1029,9 +1243,13 @@ impl EthereumAdapterTrait for EthereumAdapter {
                 .no_limit()
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
-                    web3.eth()
-                        .block_with_txs(BlockId::Number(block_number.into()))
-                        .from_err()
+                    block_with_txs(&web3, BlockId::Number(block_number.into()))
+                        .and_then(|block| {
+                            if let Some(block) = &block {
+                                check_block_number(block)?;
+                            }
+                            Ok(block)
+                        })
                         .compat()
                 })
                 .map_err(move |e| {
@@ -1062,93 +1280,59 @@ impl EthereumAdapterTrait for EthereumAdapter {
             return Box::new(future::ok(EthereumBlock {
                 block: Arc::new(block),
                 transaction_receipts: Vec::new(),
+                uncles: Vec::new(),
             }));
         }
         let web3 = self.web3.clone();
+        let block_receipts = self.block_receipts_method();
+        let provider = self.provider.clone();
+        let metrics = self.metrics.cheap_clone();
 
         // Retry, but eventually give up.
         // A receipt might be missing because the block was uncled, and the
         // transaction never made it back into the main chain.
         Box::new(
-            retry("batch eth_getTransactionReceipt RPC call", &logger)
+            retry("block receipts RPC call", &logger)
                 .limit(16)
                 .no_logging()
                 .timeout_secs(*JSON_RPC_TIMEOUT)
                 .run(move || {
+                    let logger = logger.clone();
+                    let web3 = web3.cheap_clone();
                     let block = block.clone();
-                    let batching_web3 = Web3::new(Batch::new(web3.transport().clone()));
-
-                    let receipt_futures = block
-                        .transactions
-                        .iter()
-                        .map(|tx| {
-                            let logger = logger.clone();
-                            let tx_hash = tx.hash;
-
-                            batching_web3
-                                .eth()
-                                .transaction_receipt(tx_hash)
-                                .from_err()
-                                .map_err(IngestorError::Unknown)
-                                .and_then(move |receipt_opt| {
-                                    receipt_opt.ok_or_else(move || {
-                                        // No receipt was returned.
-                                        //
-                                        // This can be because the Ethereum node no longer
-                                        // considers this block to be part of the main chain,
-                                        // and so the transaction is no longer in the main
-                                        // chain.  Nothing we can do from here except give up
-                                        // trying to ingest this block.
-                                        //
-                                        // This could also be because the receipt is simply not
-                                        // available yet. For that case, we should retry until
-                                        // it becomes available.
-                                        IngestorError::ReceiptUnavailable(block_hash, tx_hash)
-                                    })
-                                })
-                                .and_then(move |receipt| {
-                                    // Check if the receipt has a block hash and is for the right
-                                    // block. Parity nodes seem to return receipts with no block
-                                    // hash when a transaction is no longer in the main chain, so
-                                    // treat that case the same as a receipt being absent entirely.
-                                    if receipt.block_hash != Some(block_hash) {
-                                        info!(
-                                            logger, "receipt block mismatch";
-                                            "receipt_block_hash" =>
-                                            receipt.block_hash.unwrap_or_default().to_string(),
-                                            "block_hash" =>
-                                                block_hash.to_string(),
-                                            "tx_hash" => tx_hash.to_string(),
-                                        );
-
-                                        // If the receipt came from a different block, then the
-                                        // Ethereum node no longer considers this block to be
-                                        // in the main chain.  Nothing we can do from here
-                                        // except give up trying to ingest this block.
-                                        // There is no way to get the transaction receipt from
-                                        // this block.
-                                        Err(IngestorError::BlockUnavailable(block_hash))
-                                    } else {
-                                        Ok(receipt)
-                                    }
-                                })
-                        })
-                        .collect::<Vec<_>>();
-
-                    batching_web3
-                        .transport()
-                        .submit_batch()
-                        .from_err()
-                        .map_err(IngestorError::Unknown)
-                        .and_then(move |_| {
-                            stream::futures_ordered(receipt_futures).collect().map(
-                                move |transaction_receipts| EthereumBlock {
+                    let provider = provider.clone();
+                    let metrics = metrics.cheap_clone();
+                    async move {
+                        if let Some(method) = block_receipts {
+                            metrics.add_receipt_request(&provider, method.name());
+                            if let Some(transaction_receipts) =
+                                fetch_block_receipts(&web3, method, &block).await?
+                            {
+                                return Ok(EthereumBlock {
                                     block: Arc::new(block),
                                     transaction_receipts,
-                                },
-                            )
-                        })
+                                    uncles: Vec::new(),
+                                });
+                            }
+                            debug!(logger, "No receipts for whole block, fetching them per transaction";
+                                   "block_hash" => block_hash.to_string(),
+                                   "method" => method.name());
+                        }
+                        metrics.add_receipt_request(&provider, "eth_getTransactionReceipt");
+                        let transaction_receipts = fetch_transaction_receipts(
+                            &logger,
+                            &web3,
+                            block_hash,
+                            block.transactions.iter().map(|tx| tx.hash).collect(),
+                        )
                         .compat()
+                        .await?;
+                        Ok::<_, IngestorError>(EthereumBlock {
+                            block: Arc::new(block),
+                            transaction_receipts,
+                            uncles: Vec::new(),
+                        })
+                    }
                 })
                 .map_err(move |e| {
                     e.into_inner().unwrap_or_else(move || {
@@ -1366,6 +1550,7 @@ impl EthereumAdapterTrait for EthereumAdapter {
             .blocks(block_hashes.iter().cloned().collect())
             .map_err(|e| error!(&logger, "Error accessing block cache {}", e))
             .unwrap_or_default();
+        blocks.retain(|block| block.has_transaction_types());
 
         let missing_blocks = Vec::from_iter(
             block_hashes
@@ -1373,20 +1558,39 @@ impl EthereumAdapterTrait for EthereumAdapter {
                 .filter(|hash| !blocks.iter().any(|b| b.hash == Some(*hash))),
         );
 
-        // Return a stream that lazily loads batches of blocks.
-        debug!(logger, "Requesting {} block(s)", missing_blocks.len());
+        // Ask peers for the blocks we don't have before going to the
+        // Ethereum node; peers answer quickly or not at all
+        let peer_block_cache = self.peer_block_cache.clone();
+        let adapter = self.clone();
+        let peer_logger = logger.clone();
         Box::new(
-            self.load_blocks_rpc(logger.clone(), missing_blocks.into_iter().collect())
-                .collect()
-                .map(move |new_blocks| {
-                    if let Err(e) = chain_store.upsert_light_blocks(new_blocks.clone()) {
-                        error!(logger, "Error writing to block cache {}", e);
-                    }
-                    blocks.extend(new_blocks);
-                    blocks.sort_by_key(|block| block.number);
-                    stream::iter_ok(blocks)
+            async move {
+                Ok::<_, Error>(match peer_block_cache {
+                    Some(cache) => cache.blocks(&peer_logger, missing_blocks).await,
+                    None => (vec![], missing_blocks),
                 })
-                .flatten_stream(),
+            }
+            .boxed()
+            .compat()
+            .and_then(move |(peer_blocks, missing_blocks)| {
+                // Return a stream that lazily loads batches of blocks.
+                debug!(logger, "Requesting {} block(s)", missing_blocks.len();
+                       "from_peers" => peer_blocks.len());
+                adapter
+                    .load_blocks_rpc(logger.clone(), missing_blocks)
+                    .collect()
+                    .map(move |rpc_blocks| {
+                        let new_blocks: Vec<_> =
+                            peer_blocks.into_iter().chain(rpc_blocks).collect();
+                        if let Err(e) = chain_store.upsert_light_blocks(new_blocks.clone()) {
+                            error!(logger, "Error writing to block cache {}", e);
+                        }
+                        blocks.extend(new_blocks);
+                        blocks.sort_by_key(|block| block.number);
+                        stream::iter_ok(blocks)
+                    })
+            })
+            .flatten_stream(),
         )
     }
 }
@@ -1409,6 +1613,7 @@ pub(crate) async fn blocks_with_triggers(
     logger: Logger,
     chain_store: Arc<dyn ChainStore>,
     subgraph_metrics: Arc<SubgraphEthRpcMetrics>,
+    log_ranges: Arc<LogRanges>,
     stopwatch_metrics: StopwatchMetrics,
     from: BlockNumber,
     to: BlockNumber,
@@ -1434,11 +1639,14 @@ pub(crate) async fn blocks_with_triggers(
                 from,
                 to,
                 filter.log.clone(),
+                log_ranges,
             )
-            .map_ok(|logs: Vec<Log>| {
-                logs.into_iter()
-                    .map(Arc::new)
-                    .map(EthereumTrigger::Log)
+            .map(|logs: Result<Vec<Log>, Error>| {
+                // Logs that the provider returned without their position in
+                // the chain fail the scan, which will be retried
+                logs?
+                    .into_iter()
+                    .map(|log| Ok(EthereumTrigger::Log(BlockLog::new(Arc::new(log))?, None)))
                     .collect()
             })
             .compat(),
@@ -1480,6 +1688,41 @@ pub(crate) async fn blocks_with_triggers(
         ));
     }
 
+    // Blocks at which data sources start that have a handler that should
+    // only run once
+    let start_blocks: Vec<_> = filter
+        .block
+        .start_blocks
+        .iter()
+        .cloned()
+        .filter(|number| from <= *number && *number <= to)
+        .collect();
+    if !start_blocks.is_empty() {
+        trigger_futs.push(Box::new(
+            adapter
+                .block_numbers_to_ptrs(logger.clone(), start_blocks)
+                .map(move |ptrs| {
+                    ptrs.into_iter()
+                        .map(|ptr| EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Once))
+                        .collect()
+                }),
+        ))
+    }
+
+    // Blocks at which data sources with a `polling` block handler poll
+    let polling_blocks = filter.block.polling_blocks(from, to);
+    if !polling_blocks.is_empty() {
+        trigger_futs.push(Box::new(
+            adapter
+                .block_numbers_to_ptrs(logger.clone(), polling_blocks)
+                .map(move |ptrs| {
+                    ptrs.into_iter()
+                        .map(|ptr| EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Polling))
+                        .collect()
+                }),
+        ))
+    }
+
     let logger1 = logger.cheap_clone();
     let logger2 = logger.cheap_clone();
     let eth_clone = eth.cheap_clone();
@@ -1522,7 +1765,7 @@ pub(crate) async fn blocks_with_triggers(
         .and_then(
             move |block| match triggers_by_block.remove(&(block.number() as BlockNumber)) {
                 Some(triggers) => Ok(BlockWithTriggers::new(
-                    BlockFinality::Final(Arc::new(block)),
+                    BlockFinality::Final(Arc::new(block), None),
                     triggers,
                 )),
                 None => Err(anyhow!(
@@ -1537,7 +1780,7 @@ pub(crate) async fn blocks_with_triggers(
 
     // Filter out call triggers that come from unsuccessful transactions
 
-    let mut blocks = if unified_api_version
+    let blocks = if unified_api_version
         .equal_or_greater_than(&graph::data::subgraph::API_VERSION_0_0_5)
     {
         let section =
@@ -1552,6 +1795,23 @@ pub(crate) async fn blocks_with_triggers(
         blocks
     };
 
+    let blocks = if filter.log.requires_receipts() {
+        let section = stopwatch_metrics.start_section("fetch_receipts_for_log_triggers");
+        let futures = blocks
+            .into_iter()
+            .map(|block| add_receipts_to_log_triggers(block, &eth, &filter.log, &logger));
+        let blocks = futures03::future::try_join_all(futures).await?;
+        section.end();
+        blocks
+    } else {
+        blocks
+    };
+
+    let futures = blocks.into_iter().map(|block| {
+        add_uncles_to_final_block(block, &eth, &chain_store, &logger, &unified_api_version)
+    });
+    let mut blocks = futures03::future::try_join_all(futures).await?;
+
     blocks.sort_by_key(|block| block.ptr().number);
 
     // Sanity check that the returned blocks are in the correct range.
@@ -1587,7 +1847,7 @@ pub(crate) async fn get_calls(
     // (`calls.is_some()`), do nothing; if we haven't checked for calls, do
     // that now
     match block {
-        BlockFinality::Final(_)
+        BlockFinality::Final(..)
         | BlockFinality::NonFinal(EthereumBlockWithCalls {
             ethereum_block: _,
             calls: Some(_),
@@ -1603,8 +1863,7 @@ pub(crate) async fn get_calls(
                     .calls_in_block(
                         &logger,
                         subgraph_metrics.clone(),
-                        BlockNumber::try_from(ethereum_block.block.number.unwrap().as_u64())
-                            .unwrap(),
+                        ethereum_block.block.number(),
                         ethereum_block.block.hash.unwrap(),
                     )
                     .await?
@@ -1617,10 +1876,123 @@ pub(crate) async fn get_calls(
     }
 }
 
+/// Check that the number of `block` is below `BLOCK_NUMBER_MAX`. Blocks
+/// with higher numbers do not fit into a `BlockNumber`, and the store uses
+/// `BLOCK_NUMBER_MAX` itself to mark entity versions that are current, so
+/// that a block with that number can not be indexed either. It is better
+/// to fail when the block is received than to panic when its number is used
+/// later on
+pub(crate) fn check_block_number<T>(block: &Block<T>) -> Result<(), Error> {
+    match block.number {
+        Some(number) if number.as_u64() >= BLOCK_NUMBER_MAX as u64 => Err(anyhow!(
+            "Block {} has number {}, but block numbers must be less than {}",
+            block
+                .hash
+                .map_or(String::from("-"), |hash| format!("{:x}", hash)),
+            number,
+            BLOCK_NUMBER_MAX
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Check that all `logs` have a block number in `[from, to]`. Since we
+/// only asked for logs in that range, anything else is an error on the
+/// part of the provider
+pub(crate) fn check_log_block_numbers(
+    logs: &[Log],
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<(), Error> {
+    for log in logs {
+        match log.block_number {
+            Some(number) if number.as_u64() >= from as u64 && number.as_u64() <= to as u64 => {}
+            number => {
+                return Err(anyhow!(
+                    "eth_getLogs returned a log for block {:?} when blocks [{}, {}] were requested",
+                    number.map(|number| number.as_u64()),
+                    from,
+                    to
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that all `traces` are for blocks in `[from, to]` and that traces
+/// for the same block number agree on the block hash, and put them in
+/// canonical order, i.e., by block number, transaction index and trace
+/// address. Not all providers return traces in the same order, and the
+/// order of the traces determines the order of call triggers
+pub(crate) fn canonical_traces(
+    mut traces: Vec<Trace>,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> Result<Vec<Trace>, Error> {
+    let mut hashes: HashMap<u64, H256> = HashMap::new();
+    for trace in &traces {
+        let number = trace.block_number;
+        if number < from as u64 || number > to as u64 {
+            return Err(anyhow!(
+                "Trace stream returned a trace for block {} when blocks [{}, {}] were requested",
+                number,
+                from,
+                to
+            ));
+        }
+        let hash = hashes.entry(number).or_insert(trace.block_hash);
+        if *hash != trace.block_hash {
+            return Err(anyhow!(
+                "Trace stream returned traces for different blocks with number {}: \
+                 hashes `{}` and `{}`",
+                number,
+                hash,
+                trace.block_hash
+            ));
+        }
+    }
+    traces.sort_by(|a, b| {
+        (a.block_number, a.transaction_position, &a.trace_address).cmp(&(
+            b.block_number,
+            b.transaction_position,
+            &b.trace_address,
+        ))
+    });
+    Ok(traces)
+}
+
+/// Turn the `traces` for the block with `block_number` and `block_hash`
+/// into calls in canonical order. Since we can only pull traces by block
+/// number, every trace must be checked against the desired block hash
+pub(crate) fn calls_from_traces(
+    traces: Vec<Trace>,
+    block_number: BlockNumber,
+    block_hash: H256,
+) -> Result<Vec<EthereumCall>, Error> {
+    let traces = canonical_traces(traces, block_number, block_number)?;
+    if let Some(trace) = traces.iter().find(|trace| trace.block_hash != block_hash) {
+        return Err(anyhow!(
+            "Trace stream returned traces for an unexpected block: \
+             number = `{}`, hash = `{}`, expected hash = `{}`",
+            block_number,
+            trace.block_hash,
+            block_hash,
+        ));
+    }
+
+    Ok(traces
+        .iter()
+        .filter_map(EthereumCall::try_from_trace)
+        .collect())
+}
+
+/// Fails if a log of the block lacks its position in the chain, which only
+/// happens when the provider gave us a broken block
 pub(crate) fn parse_log_triggers(
     log_filter: &EthereumLogFilter,
     block: &EthereumBlock,
-) -> Vec<EthereumTrigger> {
+) -> anyhow::Result<Vec<EthereumTrigger>> {
     block
         .transaction_receipts
         .iter()
@@ -1629,7 +2001,17 @@ pub(crate) fn parse_log_triggers(
                 .logs
                 .iter()
                 .filter(move |log| log_filter.matches(log))
-                .map(move |log| EthereumTrigger::Log(Arc::new(log.clone())))
+                .map(move |log| {
+                    let receipt = if log_filter.requires_receipt(log) {
+                        Some(Arc::new(receipt.clone()))
+                    } else {
+                        None
+                    };
+                    Ok(EthereumTrigger::Log(
+                        BlockLog::new(Arc::new(log.clone()))?,
+                        receipt,
+                    ))
+                })
         })
         .collect()
 }
@@ -1661,6 +2043,8 @@ pub(crate) fn parse_block_triggers(
 ) -> Vec<EthereumTrigger> {
     let block_ptr = BlockPtr::from(&block.ethereum_block);
     let trigger_every_block = block_filter.trigger_every_block;
+    let trigger_start = block_filter.start_blocks.contains(&block_ptr.number);
+    let trigger_polling = block_filter.polls_at(block_ptr.number);
     let call_filter = EthereumCallFilter::from(block_filter);
     let block_ptr2 = block_ptr.cheap_clone();
     let mut triggers = match &block.calls {
@@ -1676,6 +2060,18 @@ pub(crate) fn parse_block_triggers(
             .collect::<Vec<EthereumTrigger>>(),
         None => vec![],
     };
+    if trigger_start {
+        triggers.push(EthereumTrigger::Block(
+            block_ptr.cheap_clone(),
+            EthereumBlockTriggerType::Once,
+        ));
+    }
+    if trigger_polling {
+        triggers.push(EthereumTrigger::Block(
+            block_ptr.cheap_clone(),
+            EthereumBlockTriggerType::Polling,
+        ));
+    }
     if trigger_every_block {
         triggers.push(EthereumTrigger::Block(
             block_ptr,
@@ -1685,6 +2081,187 @@ pub(crate) fn parse_block_triggers(
     triggers
 }
 
+/// Find out whether the provider can return the receipts of all
+/// transactions in a block with one request. Providers that do not know a
+/// method answer with an error
+async fn probe_block_receipts(web3: &Web3<Transport>) -> Option<BlockReceiptsMethod> {
+    for method in &[BlockReceiptsMethod::Eth, BlockReceiptsMethod::Parity] {
+        let result = web3
+            .transport()
+            .execute(method.name(), vec![serde_json::json!("latest")])
+            .compat()
+            .await;
+        if result.is_ok() {
+            return Some(*method);
+        }
+    }
+    None
+}
+
+/// Find out whether the provider supports `trace_filter`, which call
+/// handlers need. The request only asks for traces of calls to the zero
+/// address in the latest block to keep the response small
+async fn probe_traces(web3: &Web3<Transport>) -> bool {
+    let filter = TraceFilterBuilder::default()
+        .from_block(Web3BlockNumber::Latest)
+        .to_block(Web3BlockNumber::Latest)
+        .to_address(vec![H160::zero()])
+        .build();
+    web3.trace().filter(filter).compat().await.is_ok()
+}
+
+/// Fetch the block `id` with its transactions. We can not use
+/// `web3.eth().block_with_txs` for that since the transactions it returns
+/// lack the fields that EIP-2718 and EIP-1559 added
+fn block_with_txs(
+    web3: &Web3<Transport>,
+    id: BlockId,
+) -> impl Future<Item = Option<LightEthereumBlock>, Error = Error> {
+    let (method, id) = match id {
+        BlockId::Hash(hash) => ("eth_getBlockByHash", serde_json::to_value(hash)),
+        BlockId::Number(number) => ("eth_getBlockByNumber", serde_json::to_value(number)),
+    };
+    web3.transport()
+        .execute(method, vec![id.unwrap(), serde_json::Value::Bool(true)])
+        .from_err::<Error>()
+        .and_then(move |value| {
+            serde_json::from_value(value)
+                .map_err(|e| anyhow!("invalid response to {}: {}", method, e))
+        })
+}
+
+/// Fetch the receipts for all transactions in `block` with `method`.
+/// Returns `None` if the provider did not return usable receipts
+async fn fetch_block_receipts(
+    web3: &Web3<Transport>,
+    method: BlockReceiptsMethod,
+    block: &LightEthereumBlock,
+) -> Result<Option<Vec<TransactionReceipt>>, IngestorError> {
+    let number = block.number.expect("block is missing block number");
+    let value = web3
+        .transport()
+        .execute(
+            method.name(),
+            vec![serde_json::to_value(Web3BlockNumber::Number(number)).unwrap()],
+        )
+        .compat()
+        .await
+        .map_err(|e| IngestorError::Unknown(e.into()))?;
+    let receipts = serde_json::from_value(value).map_err(|e| {
+        IngestorError::Unknown(anyhow!("invalid response to {}: {}", method.name(), e))
+    })?;
+    check_block_receipts(block, receipts)
+}
+
+/// Check that `receipts` are the receipts for the transactions in `block`.
+/// Some providers that support getting receipts for whole blocks return
+/// `null`, or receipts without a block hash, for blocks they consider
+/// pending; for those, we return `None` so that the receipts get fetched
+/// one transaction at a time
+pub(crate) fn check_block_receipts(
+    block: &LightEthereumBlock,
+    receipts: Option<Vec<TransactionReceipt>>,
+) -> Result<Option<Vec<TransactionReceipt>>, IngestorError> {
+    let block_hash = block.hash.expect("block is missing block hash");
+    let receipts = match receipts {
+        Some(receipts) => receipts,
+        None => return Ok(None),
+    };
+    if receipts.iter().any(|receipt| receipt.block_hash.is_none()) {
+        return Ok(None);
+    }
+    // We ask for the receipts by block number. Receipts from a different
+    // block mean that the node no longer considers this block to be in the
+    // main chain
+    if receipts
+        .iter()
+        .any(|receipt| receipt.block_hash != Some(block_hash))
+    {
+        return Err(IngestorError::BlockUnavailable(block_hash));
+    }
+    let complete = receipts.len() == block.transactions.len()
+        && receipts
+            .iter()
+            .zip(block.transactions.iter())
+            .all(|(receipt, tx)| receipt.transaction_hash == tx.hash);
+    Ok(if complete { Some(receipts) } else { None })
+}
+
+/// Fetch the receipts for the transactions `tx_hashes` in the block
+/// `block_hash` with a batch of `eth_getTransactionReceipt` requests. Fails
+/// if a receipt is not for that block, so that receipts never come from a
+/// block that replaced it in a reorg. `tx_hashes` must not be empty
+fn fetch_transaction_receipts(
+    logger: &Logger,
+    web3: &Web3<Transport>,
+    block_hash: H256,
+    tx_hashes: Vec<H256>,
+) -> impl Future<Item = Vec<TransactionReceipt>, Error = IngestorError> + Send {
+    let batching_web3 = Web3::new(Batch::new(web3.transport().clone()));
+
+    let receipt_futures = tx_hashes
+        .into_iter()
+        .map(|tx_hash| {
+            let logger = logger.clone();
+
+            batching_web3
+                .eth()
+                .transaction_receipt(tx_hash)
+                .from_err()
+                .map_err(IngestorError::Unknown)
+                .and_then(move |receipt_opt| {
+                    receipt_opt.ok_or_else(move || {
+                        // No receipt was returned.
+                        //
+                        // This can be because the Ethereum node no longer
+                        // considers this block to be part of the main chain,
+                        // and so the transaction is no longer in the main
+                        // chain.  Nothing we can do from here except give up
+                        // trying to ingest this block.
+                        //
+                        // This could also be because the receipt is simply not
+                        // available yet. For that case, we should retry until
+                        // it becomes available.
+                        IngestorError::ReceiptUnavailable(block_hash, tx_hash)
+                    })
+                })
+                .and_then(move |receipt| {
+                    // Check if the receipt has a block hash and is for the right
+                    // block. Parity nodes seem to return receipts with no block
+                    // hash when a transaction is no longer in the main chain, so
+                    // treat that case the same as a receipt being absent entirely.
+                    if receipt.block_hash != Some(block_hash) {
+                        info!(
+                            logger, "receipt block mismatch";
+                            "receipt_block_hash" =>
+                            receipt.block_hash.unwrap_or_default().to_string(),
+                            "block_hash" =>
+                                block_hash.to_string(),
+                            "tx_hash" => tx_hash.to_string(),
+                        );
+
+                        // If the receipt came from a different block, then the
+                        // Ethereum node no longer considers this block to be
+                        // in the main chain.  Nothing we can do from here
+                        // except give up trying to ingest this block.
+                        // There is no way to get the transaction receipt from
+                        // this block.
+                        Err(IngestorError::BlockUnavailable(block_hash))
+                    } else {
+                        Ok(receipt)
+                    }
+                })
+        })
+        .collect::<Vec<_>>();
+
+    batching_web3
+        .transport()
+        .submit_batch()
+        .from_err()
+        .map_err(IngestorError::Unknown)
+        .and_then(move |_| stream::futures_ordered(receipt_futures).collect())
+}
+
 async fn fetch_receipt_from_ethereum_client(
     eth: &EthereumAdapter,
     transaction_hash: &H256,
@@ -1702,6 +2279,141 @@ async fn fetch_receipt_from_ethereum_client(
     }
 }
 
+/// Attach the receipts of their transactions to the log triggers in `block`
+/// whose handlers ask for them
+async fn add_receipts_to_log_triggers(
+    mut block: BlockWithTriggers<crate::Chain>,
+    eth: &EthereumAdapter,
+    log_filter: &EthereumLogFilter,
+    logger: &Logger,
+) -> anyhow::Result<BlockWithTriggers<crate::Chain>> {
+    let block_hash = block.ptr().hash_as_h256();
+    let needs_receipt = |log: &Log| {
+        // Logs without a transaction of their own, like Celo's epoch
+        // rewards, have the block hash as their transaction hash
+        log_filter.requires_receipt(log) && log.transaction_hash != Some(block_hash)
+    };
+
+    let tx_hashes: BTreeSet<H256> = block
+        .trigger_data
+        .iter()
+        .filter_map(|trigger| match trigger {
+            EthereumTrigger::Log(log, None) if needs_receipt(log.log().as_ref()) => {
+                log.transaction_hash
+            }
+            _ => None,
+        })
+        .collect();
+    if tx_hashes.is_empty() {
+        return Ok(block);
+    }
+
+    eth.metrics
+        .add_receipt_request(&eth.provider, "eth_getTransactionReceipt");
+    let receipts: HashMap<H256, Arc<TransactionReceipt>> = fetch_transaction_receipts(
+        logger,
+        &eth.web3,
+        block_hash,
+        tx_hashes.into_iter().collect(),
+    )
+    .compat()
+    .await?
+    .into_iter()
+    .map(|receipt| (receipt.transaction_hash, Arc::new(receipt)))
+    .collect();
+
+    for trigger in block.trigger_data.iter_mut() {
+        if let EthereumTrigger::Log(log, receipt @ None) = trigger {
+            if needs_receipt(log.log().as_ref()) {
+                *receipt = log
+                    .transaction_hash
+                    .and_then(|hash| receipts.get(&hash).cloned());
+            }
+        }
+    }
+    Ok(block)
+}
+
+/// Whether the mappings see the uncles of a block with `triggers`. Only
+/// block handlers of mappings with apiVersion 0.0.6 and later get them
+pub(crate) fn uncles_needed(
+    unified_api_version: &UnifiedMappingApiVersion,
+    triggers: &[EthereumTrigger],
+) -> bool {
+    unified_api_version.equal_or_greater_than(&API_VERSION_0_0_6)
+        && triggers
+            .iter()
+            .any(|trigger| matches!(trigger, EthereumTrigger::Block(..)))
+}
+
+/// The headers of the uncles of `block`. The block ingestor stores them
+/// with the block, and we only ask the Ethereum node for them if the
+/// chain store does not have all of them. What we get from the Ethereum
+/// node is stored with the block so that we only fetch it once
+pub(crate) async fn load_uncles(
+    eth: &EthereumAdapter,
+    logger: &Logger,
+    chain_store: &dyn ChainStore,
+    block: &LightEthereumBlock,
+) -> anyhow::Result<Vec<EthereumUncle>> {
+    if block.uncles.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let hash = block.hash.expect("blocks have a hash");
+    match chain_store.uncles(hash) {
+        Ok(Some(uncles)) if uncles_complete(block, &uncles) => return Ok(uncles),
+        Ok(_) => {}
+        Err(e) => warn!(logger, "Failed to read uncles from the block cache";
+                        "hash" => format!("{:x}", hash),
+                        "error" => e.to_string()),
+    }
+
+    let uncles = eth
+        .uncles(logger, block)
+        .compat()
+        .await?
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| {
+            anyhow!(
+                "the Ethereum node does not have all uncles of block {:x}",
+                hash
+            )
+        })?;
+    if !uncles_complete(block, &uncles) {
+        bail!(
+            "the Ethereum node returned the wrong uncles for block {:x}",
+            hash
+        );
+    }
+    if let Err(e) = chain_store.set_uncles(hash, &uncles) {
+        warn!(logger, "Failed to store uncles in the block cache";
+              "hash" => format!("{:x}", hash),
+              "error" => e.to_string());
+    }
+    Ok(uncles)
+}
+
+/// Attach the headers of its uncles to a final `block` if its block
+/// triggers need them
+async fn add_uncles_to_final_block(
+    mut block: BlockWithTriggers<crate::Chain>,
+    eth: &EthereumAdapter,
+    chain_store: &Arc<dyn ChainStore>,
+    logger: &Logger,
+    unified_api_version: &UnifiedMappingApiVersion,
+) -> anyhow::Result<BlockWithTriggers<crate::Chain>> {
+    if !uncles_needed(unified_api_version, &block.trigger_data) {
+        return Ok(block);
+    }
+    if let BlockFinality::Final(light_block, uncles @ None) = &mut block.block {
+        let loaded = load_uncles(eth, logger, chain_store.as_ref(), &**light_block).await?;
+        *uncles = Some(Arc::new(loaded));
+    }
+    Ok(block)
+}
+
 async fn filter_call_triggers_from_unsuccessful_transactions(
     mut block: BlockWithTriggers<crate::Chain>,
     eth: &EthereumAdapter,
@@ -1731,10 +2443,11 @@ async fn filter_call_triggers_from_unsuccessful_transactions(
     // And obtain all Transaction values for the calls in this block.
     let transactions: Vec<&Transaction> = {
         match &block.block {
-            BlockFinality::Final(ref block) => block
+            BlockFinality::Final(ref block, _) => block
                 .transactions
                 .iter()
                 .filter(|transaction| transaction_hashes.contains(&transaction.hash))
+                .map(|transaction| &transaction.transaction)
                 .collect(),
             BlockFinality::NonFinal(_block_with_calls) => {
                 unreachable!(
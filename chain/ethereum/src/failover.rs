@@ -0,0 +1,153 @@
+//! Take providers out of rotation while they fail. When a request to a
+//! provider fails because the provider can not be reached or because it
+//! rate limits us, we avoid the provider for `GRAPH_ETHEREUM_FAILOVER_COOLDOWN`
+//! seconds if there are other providers for the same network. The first
+//! request that succeeds after that puts the provider back into rotation.
+//! Providers whose head lags behind are handled by the `HeadLagMonitor`
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use graph::prelude::{lazy_static, web3};
+use jsonrpc_core::ErrorCode;
+
+use crate::ProviderEthRpcMetrics;
+
+lazy_static! {
+    /// How long we avoid a provider after a request to it failed
+    static ref FAILOVER_COOLDOWN: Duration = {
+        let secs = std::env::var("GRAPH_ETHEREUM_FAILOVER_COOLDOWN")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_FAILOVER_COOLDOWN must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(60);
+        Duration::from_secs(secs)
+    };
+}
+
+/// JSON-RPC error codes that providers use to tell us that we sent too
+/// many requests
+const RATE_LIMIT_CODES: &[i64] = &[429, -32005];
+
+/// Whether `error` means that we should stop using the provider for a
+/// while rather than that there was something wrong with the request
+pub(crate) fn is_provider_failure(error: &web3::Error) -> bool {
+    match error {
+        web3::Error::Transport(_) => true,
+        web3::Error::Rpc(error) => {
+            let message = error.message.to_lowercase();
+            matches!(error.code, ErrorCode::ServerError(code) if RATE_LIMIT_CODES.contains(&code))
+                || message.contains("rate limit")
+                || message.contains("too many requests")
+        }
+        _ => false,
+    }
+}
+
+/// Whether a provider is failing, shared by all clones of its transport
+/// and adapter
+pub struct ProviderHealth {
+    provider: String,
+    metrics: Arc<ProviderEthRpcMetrics>,
+    /// Set while the provider is failing to when we may use it again
+    failing_until: Mutex<Option<Instant>>,
+}
+
+impl std::fmt::Debug for ProviderHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProviderHealth")
+            .field("provider", &self.provider)
+            .field("failing_until", &self.failing_until)
+            .finish()
+    }
+}
+
+impl ProviderHealth {
+    pub fn new(provider: String, metrics: Arc<ProviderEthRpcMetrics>) -> Self {
+        metrics.set_provider_active(&provider, true);
+        ProviderHealth {
+            provider,
+            metrics,
+            failing_until: Mutex::new(None),
+        }
+    }
+
+    /// Whether the provider failed recently and should be avoided
+    pub fn is_failing(&self) -> bool {
+        match *self.failing_until.lock().unwrap() {
+            Some(until) => until > Instant::now(),
+            None => false,
+        }
+    }
+
+    /// Take the provider out of rotation if `result` says that it is
+    /// failing, and put it back if it answered after it had failed
+    pub(crate) fn check<T>(&self, result: &Result<T, web3::Error>) {
+        match result {
+            Err(e) if is_provider_failure(e) => self.fail(),
+            Err(_) => {}
+            Ok(_) => self.recover(),
+        }
+    }
+
+    /// Like `check`, but for the results of a batch request, where each
+    /// request can be rate limited on its own
+    pub(crate) fn check_batch<T>(&self, result: &Result<Vec<Result<T, web3::Error>>, web3::Error>) {
+        match result {
+            Ok(results) => match results.iter().find(|result| result.is_err()) {
+                Some(failed) => self.check(failed),
+                None => self.recover(),
+            },
+            Err(_) => self.check(result),
+        }
+    }
+
+    fn fail(&self) {
+        let mut failing_until = self.failing_until.lock().unwrap();
+        if failing_until.is_none() {
+            self.metrics.add_failover(&self.provider);
+            self.metrics.set_provider_active(&self.provider, false);
+        }
+        *failing_until = Some(Instant::now() + *FAILOVER_COOLDOWN);
+    }
+
+    fn recover(&self) {
+        let mut failing_until = self.failing_until.lock().unwrap();
+        if let Some(until) = *failing_until {
+            if until <= Instant::now() {
+                *failing_until = None;
+                self.metrics.set_provider_active(&self.provider, true);
+            }
+        }
+    }
+}
+
+#[test]
+fn provider_failures() {
+    use jsonrpc_core::Error as RpcError;
+
+    let rpc = |code: i64, message: &str| {
+        web3::Error::Rpc(RpcError {
+            code: ErrorCode::ServerError(code),
+            message: message.to_string(),
+            data: None,
+        })
+    };
+
+    assert!(is_provider_failure(&web3::Error::Transport(
+        "Unexpected response status code: 429 Too Many Requests".to_string()
+    )));
+    assert!(is_provider_failure(&rpc(429, "slow down")));
+    assert!(is_provider_failure(&rpc(-32005, "limit exceeded")));
+    assert!(is_provider_failure(&rpc(-32000, "Rate limit reached")));
+    assert!(!is_provider_failure(&rpc(-32000, "execution reverted")));
+    assert!(!is_provider_failure(&web3::Error::Decoder(
+        "invalid response".to_string()
+    )));
+}
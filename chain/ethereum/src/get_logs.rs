@@ -0,0 +1,421 @@
+//! Fetch `eth_getLogs` over HTTP without holding the whole response in
+//! memory. For very busy filters, the response for a block range can be
+//! hundreds of megabytes; the web3 HTTP transport buffers all of it and
+//! then turns it into a `serde_json::Value` before we ever see a log. Here,
+//! we decode the entries of the `result` array one at a time as the body
+//! arrives, and give up on the request as soon as the body is larger than
+//! `GRAPH_ETHEREUM_MAX_LOGS_RESPONSE_SIZE` so that the caller can split the
+//! block range instead. A single block can not be split further, and its
+//! logs are read regardless of their size; otherwise a node with a lower
+//! limit than others would never get past that block
+use graph::prelude::{
+    futures03::StreamExt,
+    lazy_static,
+    reqwest::{
+        self,
+        header::{HeaderMap, HeaderName, HeaderValue},
+    },
+    serde_json::{self, json},
+    web3::{
+        self,
+        types::{Filter, Log},
+    },
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::adapter::ProviderEthRpcMetrics;
+use crate::request_limit::RequestLimiter;
+use crate::rpc_debug::RpcDebug;
+
+lazy_static! {
+    /// The most bytes we accept in the body of an `eth_getLogs` response
+    pub(crate) static ref MAX_LOGS_RESPONSE_SIZE: usize =
+        std::env::var("GRAPH_ETHEREUM_MAX_LOGS_RESPONSE_SIZE")
+            .ok()
+            .map(|s| {
+                s.parse::<usize>().unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_MAX_LOGS_RESPONSE_SIZE must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(100 * 1024 * 1024);
+}
+
+/// Part of the error message when a response is larger than
+/// `MAX_LOGS_RESPONSE_SIZE`; callers look for it to decide that they should
+/// request a smaller block range
+pub(crate) const RESPONSE_TOO_LARGE: &str = "eth_getLogs response too large";
+
+/// A JSON-RPC client over HTTP that only knows how to make `eth_getLogs`
+/// requests
+#[derive(Clone, Debug)]
+pub struct LogsClient {
+    client: reqwest::Client,
+    url: Arc<String>,
+    headers: HeaderMap,
+    next_id: Arc<AtomicUsize>,
+    pub(crate) debug: Option<Arc<RpcDebug>>,
+    pub(crate) limiter: Option<Arc<RequestLimiter>>,
+}
+
+impl LogsClient {
+    /// Create a client for `url`. The `headers` are what the web3 transport
+    /// for the same provider sends and use the older `http` crate that
+    /// rust-web3 depends on
+    pub fn new(url: &str, headers: &::http::HeaderMap) -> Self {
+        let headers = headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_str().as_bytes()).ok()?;
+                let value = HeaderValue::from_bytes(value.as_bytes()).ok()?;
+                Some((name, value))
+            })
+            .collect();
+
+        LogsClient {
+            client: reqwest::Client::new(),
+            url: Arc::new(url.to_string()),
+            headers,
+            next_id: Arc::new(AtomicUsize::new(1)),
+            debug: None,
+            limiter: None,
+        }
+    }
+
+    /// Request the logs matching `filter` and return them together with
+    /// the size of the response. Fails with an error that contains
+    /// `RESPONSE_TOO_LARGE` once the response is bigger than
+    /// `MAX_LOGS_RESPONSE_SIZE`, unless `single_block` is set
+    pub async fn logs(
+        &self,
+        filter: Filter,
+        single_block: bool,
+        metrics: &ProviderEthRpcMetrics,
+    ) -> Result<(Vec<Log>, usize), web3::Error> {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(1).await;
+        }
+
+        let debug = match &self.debug {
+            Some(debug) => debug,
+            None => return self.request_logs(filter, single_block, metrics).await,
+        };
+
+        let tracked = debug.request(
+            "eth_getLogs".to_string(),
+            serde_json::to_string(&[&filter]).unwrap_or_default(),
+        );
+        let result = self.request_logs(filter, single_block, metrics).await;
+        debug.finish(
+            tracked,
+            result
+                .as_ref()
+                .map(|(logs, size)| move || format!("{} logs in {} bytes", logs.len(), size)),
+        );
+        result
+    }
+
+    /// Make the request for `filter` and return the logs together with
+    /// the size of the response
+    async fn request_logs(
+        &self,
+        filter: Filter,
+        single_block: bool,
+        metrics: &ProviderEthRpcMetrics,
+    ) -> Result<(Vec<Log>, usize), web3::Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": "eth_getLogs",
+            "params": [filter],
+            "id": id,
+        });
+
+        let response = self
+            .client
+            .post(self.url.as_str())
+            .headers(self.headers.clone())
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| web3::Error::Transport(e.to_string()))?;
+
+        // Same message as the web3 HTTP transport so that the checks for
+        // overloaded providers work for both
+        if !response.status().is_success() {
+            return Err(web3::Error::Transport(format!(
+                "Unexpected response status code: {}",
+                response.status()
+            )));
+        }
+
+        let limit = match single_block {
+            true => usize::MAX,
+            false => *MAX_LOGS_RESPONSE_SIZE,
+        };
+        let mut scanner = ResponseScanner::new(limit);
+        let mut body = response.bytes_stream();
+        let res = async {
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(|e| web3::Error::Transport(e.to_string()))?;
+                scanner.feed(&chunk)?;
+            }
+            Ok::<_, web3::Error>(())
+        }
+        .await;
+        let size = scanner.received;
+        metrics.observe_logs_response_size(size);
+        res?;
+        scanner.finish().map(|logs| (logs, size))
+    }
+}
+
+/// Incrementally picks the entries of the `result` array out of a JSON-RPC
+/// response. Only the entry that is currently being read is kept as bytes;
+/// all entries that are complete are decoded into logs right away
+struct ResponseScanner {
+    limit: usize,
+    received: usize,
+    /// How many objects and arrays are open at the current position
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Whether the next string in the top-level object is a member name
+    expect_key: bool,
+    reading_key: bool,
+    /// The name of the top-level member whose value we are in
+    key: Vec<u8>,
+    /// Whether we are inside the `result` array
+    in_result: bool,
+    saw_result: bool,
+    /// The depth at which the value we are collecting in `buf` started
+    capture: Option<usize>,
+    buf: Vec<u8>,
+    error: Option<Vec<u8>>,
+    logs: Vec<Log>,
+}
+
+impl ResponseScanner {
+    fn new(limit: usize) -> Self {
+        ResponseScanner {
+            limit,
+            received: 0,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            expect_key: true,
+            reading_key: false,
+            key: Vec::new(),
+            in_result: false,
+            saw_result: false,
+            capture: None,
+            buf: Vec::new(),
+            error: None,
+            logs: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) -> Result<(), web3::Error> {
+        self.received += chunk.len();
+        if self.received > self.limit {
+            return Err(web3::Error::Transport(format!(
+                "{}: more than {} bytes",
+                RESPONSE_TOO_LARGE, self.limit
+            )));
+        }
+
+        for &b in chunk {
+            if self.in_string {
+                if self.capture.is_some() {
+                    self.buf.push(b);
+                }
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                    self.reading_key = false;
+                    continue;
+                }
+                if self.reading_key {
+                    self.key.push(b);
+                }
+                continue;
+            }
+
+            if b.is_ascii_whitespace() {
+                if self.capture.is_some() {
+                    self.buf.push(b);
+                }
+                continue;
+            }
+
+            // Start collecting the value of a top-level member, or an
+            // entry of the `result` array
+            if self.capture.is_none() && !matches!(b, b',' | b':' | b'}' | b']') {
+                if self.depth == 1 && !self.expect_key {
+                    if self.key == b"result" && b == b'[' {
+                        self.in_result = true;
+                        self.saw_result = true;
+                        self.depth += 1;
+                        continue;
+                    }
+                    self.capture = Some(1);
+                } else if self.depth == 2 && self.in_result {
+                    self.capture = Some(2);
+                }
+            }
+            if self.capture.is_some() {
+                self.buf.push(b);
+            }
+
+            match b {
+                b'"' => {
+                    self.in_string = true;
+                    if self.depth == 1 && self.expect_key {
+                        self.reading_key = true;
+                        self.key.clear();
+                    }
+                }
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    if self.depth == 0 {
+                        return Err(invalid("unbalanced brackets"));
+                    }
+                    // The end of the enclosing object or array also ends a
+                    // scalar value we are collecting
+                    if self.capture == Some(self.depth) {
+                        self.buf.pop();
+                        self.finish_value()?;
+                    }
+                    self.depth -= 1;
+                    if self.in_result && self.depth == 1 {
+                        self.in_result = false;
+                    }
+                    if self.capture == Some(self.depth) {
+                        self.finish_value()?;
+                    }
+                }
+                b':' if self.depth == 1 => self.expect_key = false,
+                b',' => {
+                    if self.capture == Some(self.depth) {
+                        self.buf.pop();
+                        self.finish_value()?;
+                    }
+                    if self.depth == 1 {
+                        self.expect_key = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn finish_value(&mut self) -> Result<(), web3::Error> {
+        let depth = self.capture.take();
+        let value = std::mem::take(&mut self.buf);
+        match depth {
+            Some(2) => {
+                let log = serde_json::from_slice(&value)
+                    .map_err(|e| invalid(&format!("could not decode log: {}", e)))?;
+                self.logs.push(log);
+            }
+            _ if self.key == b"error" => self.error = Some(value),
+            _ if self.key == b"result" => self.saw_result = true,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<Log>, web3::Error> {
+        if self.depth != 0 || self.in_string {
+            return Err(invalid("response ended early"));
+        }
+        if let Some(error) = self.error {
+            let error: jsonrpc_core::Error = serde_json::from_slice(&error)
+                .map_err(|e| invalid(&format!("could not decode error: {}", e)))?;
+            return Err(web3::Error::Rpc(error));
+        }
+        if !self.saw_result {
+            return Err(invalid("response has neither a result nor an error"));
+        }
+        Ok(self.logs)
+    }
+}
+
+fn invalid(msg: &str) -> web3::Error {
+    web3::Error::InvalidResponse(format!("eth_getLogs: {}", msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResponseScanner, RESPONSE_TOO_LARGE};
+
+    const LOG: &str = r#"{
+        "address": "0x0000000000000000000000000000000000000001",
+        "topics": ["0x0000000000000000000000000000000000000000000000000000000000000002"],
+        "data": "0x5b5d7b7d",
+        "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+        "blockNumber": "0x4",
+        "transactionHash": "0x0000000000000000000000000000000000000000000000000000000000000005",
+        "transactionIndex": "0x0",
+        "logIndex": "0x1",
+        "transactionLogIndex": null,
+        "logType": null,
+        "removed": false
+    }"#;
+
+    /// Feed `response` to a scanner in chunks of `chunk` bytes
+    fn scan(response: &str, chunk: usize, limit: usize) -> Result<usize, String> {
+        let mut scanner = ResponseScanner::new(limit);
+        for part in response.as_bytes().chunks(chunk) {
+            scanner.feed(part).map_err(|e| e.to_string())?;
+        }
+        scanner
+            .finish()
+            .map(|logs| logs.len())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn decodes_logs_across_chunks() {
+        let response = format!(
+            r#"{{"jsonrpc":"2.0","id":7,"result":[{}, {}, {}]}}"#,
+            LOG, LOG, LOG
+        );
+        for chunk in &[1, 3, 64, response.len()] {
+            assert_eq!(Ok(3), scan(&response, *chunk, usize::MAX));
+        }
+
+        let response = format!(r#"{{ "result" : [ {} ], "id": 7, "jsonrpc": "2.0" }}"#, LOG);
+        assert_eq!(Ok(1), scan(&response, 5, usize::MAX));
+
+        let response = r#"{"jsonrpc":"2.0","id":"x\"]}","result":[]}"#;
+        assert_eq!(Ok(0), scan(response, 2, usize::MAX));
+    }
+
+    #[test]
+    fn errors() {
+        let response = r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"query returned more than 10000 results"}}"#;
+        let err = scan(response, 4, usize::MAX).unwrap_err();
+        assert!(err.contains("-32005"), "{}", err);
+
+        let response = format!(r#"{{"jsonrpc":"2.0","id":1,"result":[{}"#, LOG);
+        assert!(scan(&response, 4, usize::MAX).is_err());
+
+        assert!(scan(r#"{"jsonrpc":"2.0","id":1}"#, 4, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn too_large() {
+        let response = format!(r#"{{"jsonrpc":"2.0","id":7,"result":[{}, {}]}}"#, LOG, LOG);
+        let err = scan(&response, 16, response.len() - 1).unwrap_err();
+        assert!(err.contains(RESPONSE_TOO_LARGE), "{}", err);
+        assert_eq!(Ok(2), scan(&response, 16, response.len()));
+    }
+}
@@ -0,0 +1,186 @@
+//! Watch how far the head of each provider lags behind the heads of the
+//! other providers for the same network. A provider that still answers
+//! requests but serves a stale head makes everything that uses it slow
+//! without ever failing, so failover never kicks in. We poll the latest
+//! block number of every provider with one `eth_blockNumber` call per
+//! interval, export the lag as the `head_lag_blocks` gauge, and mark
+//! providers that lag by more than `GRAPH_ETHEREUM_MAX_HEAD_LAG` blocks so
+//! that adapter selection avoids them until they catch up
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use graph::prelude::{
+    debug, futures03, info, lazy_static, o, tokio, warn, BlockNumber, Error, GaugeVec, Logger,
+    MetricsRegistry,
+};
+
+use crate::network::{EthereumNetworkAdapters, EthereumNetworks};
+use crate::EthereumAdapterTrait as _;
+
+lazy_static! {
+    /// How often we poll the head of every provider, in seconds
+    static ref HEAD_LAG_POLL_INTERVAL: Duration = {
+        let secs = std::env::var("GRAPH_ETHEREUM_HEAD_LAG_POLL_INTERVAL")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_HEAD_LAG_POLL_INTERVAL must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(30);
+        Duration::from_secs(secs)
+    };
+
+    /// How many blocks the head of a provider can be behind the highest
+    /// head of all providers for the same network before we stop using
+    /// the provider if there are others
+    static ref MAX_HEAD_LAG: BlockNumber = std::env::var("GRAPH_ETHEREUM_MAX_HEAD_LAG")
+        .ok()
+        .map(|s| {
+            BlockNumber::from_str(&s).unwrap_or_else(|_| {
+                panic!(
+                    "GRAPH_ETHEREUM_MAX_HEAD_LAG must be a number, but is `{}`",
+                    s
+                )
+            })
+        })
+        .unwrap_or(10);
+}
+
+/// For each of `heads`, how many blocks it is behind the highest of
+/// `heads`. Providers whose head we could not get have no lag
+pub(crate) fn head_lags(heads: &[Option<BlockNumber>]) -> Vec<Option<BlockNumber>> {
+    let max = heads.iter().flatten().max().copied();
+    heads
+        .iter()
+        .map(|head| max.zip(*head).map(|(max, head)| max - head))
+        .collect()
+}
+
+/// Polls the heads of all providers and keeps track of which ones lag
+pub struct HeadLagMonitor {
+    logger: Logger,
+    networks: Vec<(String, EthereumNetworkAdapters)>,
+    head_lag: Box<GaugeVec>,
+}
+
+impl HeadLagMonitor {
+    pub fn new(
+        logger: &Logger,
+        registry: Arc<impl MetricsRegistry>,
+        networks: &EthereumNetworks,
+    ) -> Result<Self, Error> {
+        let head_lag = registry.new_gauge_vec(
+            "head_lag_blocks",
+            "How many blocks the head of a provider is behind the highest head of all providers for the network",
+            vec![String::from("network"), String::from("provider")],
+        )?;
+        Ok(HeadLagMonitor {
+            logger: logger.new(o!("component" => "HeadLagMonitor")),
+            networks: networks
+                .networks
+                .iter()
+                .map(|(name, adapters)| (name.clone(), adapters.clone()))
+                .collect(),
+            head_lag,
+        })
+    }
+
+    /// Poll the heads of all providers every
+    /// `GRAPH_ETHEREUM_HEAD_LAG_POLL_INTERVAL` seconds. Networks with only
+    /// one provider have nothing to compare against and are not polled
+    pub fn start(self) {
+        graph::spawn_named("head-lag-monitor", async move {
+            let mut interval = tokio::time::interval(*HEAD_LAG_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                for (network, adapters) in &self.networks {
+                    if adapters.adapters.len() > 1 {
+                        self.check_network(network, adapters).await;
+                    }
+                }
+            }
+        });
+    }
+
+    async fn check_network(&self, network: &str, adapters: &EthereumNetworkAdapters) {
+        let heads = futures03::future::join_all(adapters.adapters.iter().map(|adapter| {
+            let logger = &self.logger;
+            async move {
+                let provider = adapter.adapter().provider();
+                match tokio::time::timeout(
+                    *HEAD_LAG_POLL_INTERVAL,
+                    adapter.adapter().latest_block_number(),
+                )
+                .await
+                {
+                    Ok(Ok(head)) => Some(head),
+                    Ok(Err(e)) => {
+                        debug!(logger, "Failed to get head of provider";
+                               "network" => network,
+                               "provider" => provider,
+                               "error" => e.to_string());
+                        None
+                    }
+                    Err(_) => {
+                        debug!(logger, "Provider took too long to return its head";
+                               "network" => network,
+                               "provider" => provider);
+                        None
+                    }
+                }
+            }
+        }))
+        .await;
+
+        let lags = head_lags(&heads);
+        for ((adapter, head), lag) in adapters.adapters.iter().zip(heads).zip(lags) {
+            // If we could not get the head of a provider, we leave
+            // everything as it was after the last successful poll
+            let (head, lag) = match head.zip(lag) {
+                Some(head_and_lag) => head_and_lag,
+                None => continue,
+            };
+            let provider = adapter.adapter().provider();
+            self.head_lag
+                .with_label_values(&[network, provider])
+                .set(lag as f64);
+
+            let lagging = lag > *MAX_HEAD_LAG;
+            if lagging && !adapter.is_lagging() {
+                warn!(self.logger, "Provider is lagging behind, using other providers until it catches up";
+                      "network" => network,
+                      "provider" => provider,
+                      "head" => head,
+                      "lag" => lag,
+                      "max_lag" => *MAX_HEAD_LAG);
+            } else if !lagging && adapter.is_lagging() {
+                info!(self.logger, "Provider caught up";
+                      "network" => network,
+                      "provider" => provider,
+                      "head" => head,
+                      "lag" => lag);
+            }
+            adapter.set_lagging(lagging);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::head_lags;
+
+    #[test]
+    fn lags() {
+        assert_eq!(
+            vec![Some(0), Some(32), None, Some(1)],
+            head_lags(&[Some(100), Some(68), None, Some(99)])
+        );
+        assert_eq!(vec![None, None], head_lags(&[None, None]));
+        assert!(head_lags(&[]).is_empty());
+    }
+}
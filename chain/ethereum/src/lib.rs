@@ -1,19 +1,43 @@
 mod adapter;
+mod call_cache;
 mod capabilities;
 pub mod codec;
 mod data_source;
 mod ethereum_adapter;
+mod failover;
+mod get_logs;
+mod head_lag;
+mod log_range;
+mod log_size;
+#[cfg(test)]
+mod mock_provider;
 pub mod network_indexer;
+mod peer_block_cache;
+mod provider_status;
+mod receipts_probe;
+mod request_limit;
+mod rpc_debug;
 pub mod runtime;
+mod trace_probe;
 mod transport;
 
 pub use self::capabilities::NodeCapabilities;
-pub use self::ethereum_adapter::EthereumAdapter;
+pub use self::ethereum_adapter::{BlockReceiptsMethod, EthereumAdapter};
+pub use self::head_lag::HeadLagMonitor;
+pub use self::peer_block_cache::{BlockCachePeers, PeerBlockCache};
+pub use self::provider_status::{NetworkStatus, ProviderStatus, ProviderStatuses};
+pub use self::receipts_probe::ReceiptsProbe;
+pub use self::request_limit::RequestLimiter;
+pub use self::rpc_debug::{failed_requests, FailedRequest};
 pub use self::runtime::RuntimeAdapter;
+pub use self::trace_probe::TraceProbe;
 pub use self::transport::{EventLoopHandle, Transport};
 
 // ETHDEP: These concrete types should probably not be exposed.
-pub use data_source::{DataSource, DataSourceTemplate, Mapping, MappingABI, TemplateSource};
+pub use data_source::{
+    DataSource, DataSourceTemplate, Mapping, MappingABI, MappingBlockHandler, MappingEventHandler,
+    OversizedLogPolicy, TemplateSource,
+};
 pub use trigger::MappingTrigger;
 
 pub mod chain;
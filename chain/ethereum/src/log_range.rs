@@ -0,0 +1,275 @@
+//! Size the block ranges of `eth_getLogs` requests for a subgraph. Providers
+//! reject requests whose response would be too large, each with their own
+//! error. When that happens, we halve the number of blocks we ask for and
+//! try again; every request that succeeds with the full number of blocks
+//! lets the range grow by a quarter, so that a subgraph that passed through
+//! a busy stretch of the chain gets back to large ranges eventually.
+//! Requests that only filter on event signatures are much heavier than the
+//! ones that name contracts and have a range of their own, so that they do
+//! not hold back the requests for contracts
+use std::sync::{Arc, Mutex};
+
+use graph::prelude::{BlockNumber, Gauge, MetricsRegistry};
+
+use crate::adapter::EthGetLogsFilter;
+use crate::get_logs::RESPONSE_TOO_LARGE;
+
+/// Parts of the errors that providers return when a request for logs is
+/// too heavy for them. Infura uses `-32005` when a request hits its log
+/// limit; Alchemy times out with `-32000` or a 503, or complains about the
+/// size of the response. The last one is ours, for responses bigger than
+/// we are willing to hold
+const TOO_LARGE_FINGERPRINTS: &[&str] = &[
+    "ServerError(-32005)",
+    "503 Service Unavailable",
+    "ServerError(-32000)",
+    "query returned more than",
+    "response size exceeded",
+    "response too large",
+    RESPONSE_TOO_LARGE,
+];
+
+/// Whether `error` means that we should ask for fewer blocks
+pub(crate) fn is_range_too_large(error: &str) -> bool {
+    let error = error.to_lowercase();
+    TOO_LARGE_FINGERPRINTS
+        .iter()
+        .any(|fingerprint| error.contains(&fingerprint.to_lowercase()))
+}
+
+/// The number of blocks to request logs for at once, shared by all
+/// requests of one kind for one subgraph
+pub struct LogRange {
+    /// The most blocks we ask for in one request; `None` as long as no
+    /// provider has rejected a range
+    limit: Mutex<Option<BlockNumber>>,
+    /// The number of blocks in the last request
+    size: Box<Gauge>,
+}
+
+impl LogRange {
+    fn new(
+        registry: &Arc<dyn MetricsRegistry>,
+        subgraph_hash: &str,
+        name: &str,
+        help: &str,
+    ) -> Self {
+        let size = registry
+            .new_deployment_gauge(name, help, subgraph_hash)
+            .unwrap();
+        LogRange {
+            limit: Mutex::new(None),
+            size,
+        }
+    }
+
+    /// The last block of the request that starts at `start`. Requests never
+    /// go past `to` and never cover more than `max` blocks
+    pub(crate) fn end(&self, start: BlockNumber, to: BlockNumber, max: BlockNumber) -> BlockNumber {
+        let max = match *self.limit.lock().unwrap() {
+            Some(limit) => limit.min(max),
+            None => max,
+        };
+        let end = to.min(start.saturating_add(max - 1));
+        self.size.set((end - start + 1) as f64);
+        end
+    }
+
+    /// A provider rejected a request for `blocks` blocks as too large.
+    /// Returns `false` if we can not make the range any smaller
+    pub(crate) fn shrink(&self, blocks: BlockNumber) -> bool {
+        if blocks <= 1 {
+            return false;
+        }
+        let mut limit = self.limit.lock().unwrap();
+        let smaller = blocks / 2;
+        *limit = Some(limit.map_or(smaller, |limit| limit.min(smaller)));
+        true
+    }
+
+    /// A request for `blocks` blocks succeeded
+    pub(crate) fn grow(&self, blocks: BlockNumber) {
+        let mut limit = self.limit.lock().unwrap();
+        if let Some(current) = *limit {
+            // Only requests that were held back by the limit tell us that
+            // the provider can handle that many blocks
+            if blocks >= current {
+                *limit = Some(current.saturating_add((current / 4).max(1)));
+            }
+        }
+    }
+
+    /// The current limit, if there is one
+    pub(crate) fn limit(&self) -> Option<BlockNumber> {
+        *self.limit.lock().unwrap()
+    }
+}
+
+/// The ranges for all requests of one subgraph
+pub struct LogRanges {
+    contracts: Arc<LogRange>,
+    event_only: Arc<LogRange>,
+}
+
+impl LogRanges {
+    pub fn new(registry: Arc<dyn MetricsRegistry>, subgraph_hash: &str) -> Self {
+        LogRanges {
+            contracts: Arc::new(LogRange::new(
+                &registry,
+                subgraph_hash,
+                "deployment_eth_get_logs_range_size",
+                "The number of blocks in the last eth_getLogs request for a subgraph deployment",
+            )),
+            event_only: Arc::new(LogRange::new(
+                &registry,
+                subgraph_hash,
+                "deployment_eth_get_logs_event_only_range_size",
+                "The number of blocks in the last eth_getLogs request without contract \
+                 addresses for a subgraph deployment",
+            )),
+        }
+    }
+
+    /// The range for requests with `filter`
+    pub(crate) fn for_filter(&self, filter: &EthGetLogsFilter) -> Arc<LogRange> {
+        match filter.contracts.is_empty() {
+            true => self.event_only.clone(),
+            false => self.contracts.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_range_too_large, LogRange};
+    use graph::prelude::{web3, BlockNumber, Gauge};
+    use jsonrpc_core::{Error as RpcError, ErrorCode};
+    use std::sync::Mutex;
+
+    fn rpc(code: i64, message: &str) -> String {
+        web3::Error::Rpc(RpcError {
+            code: ErrorCode::ServerError(code),
+            message: message.to_string(),
+            data: None,
+        })
+        .to_string()
+    }
+
+    fn log_range() -> LogRange {
+        LogRange {
+            limit: Mutex::new(None),
+            size: Box::new(Gauge::new("range_size", "range size").unwrap()),
+        }
+    }
+
+    /// Scan `[from, to]` against a provider that rejects requests for more
+    /// than `capacity` blocks and return the sizes of all requests
+    fn scan(
+        range: &LogRange,
+        from: BlockNumber,
+        to: BlockNumber,
+        capacity: BlockNumber,
+    ) -> Vec<(BlockNumber, bool)> {
+        let mut requests = Vec::new();
+        let mut start = from;
+        while start <= to {
+            let end = range.end(start, to, BlockNumber::MAX);
+            let blocks = end - start + 1;
+            if blocks > capacity {
+                let error = rpc(-32005, "query returned more than 10000 results");
+                assert!(is_range_too_large(&error));
+                assert!(range.shrink(blocks));
+                requests.push((blocks, false));
+            } else {
+                range.grow(blocks);
+                requests.push((blocks, true));
+                start = end + 1;
+            }
+        }
+        requests
+    }
+
+    #[test]
+    fn recognizes_provider_errors() {
+        assert!(is_range_too_large(&rpc(
+            -32005,
+            "query returned more than 10000 results"
+        )));
+        assert!(is_range_too_large(&rpc(
+            -32602,
+            "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"
+        )));
+        assert!(is_range_too_large(&rpc(-32000, "request timed out")));
+        assert!(is_range_too_large(
+            &web3::Error::Transport(
+                "Unexpected response status code: 503 Service Unavailable".to_string()
+            )
+            .to_string()
+        ));
+        assert!(is_range_too_large(
+            &web3::Error::Transport(
+                "eth_getLogs response too large: more than 10 bytes".to_string()
+            )
+            .to_string()
+        ));
+        assert!(!is_range_too_large(&rpc(-32602, "invalid argument 0")));
+        assert!(!is_range_too_large(
+            &web3::Error::Transport("connection refused".to_string()).to_string()
+        ));
+    }
+
+    #[test]
+    fn halves_and_grows_back() {
+        let range = log_range();
+
+        // Without errors, the whole range is requested at once
+        assert_eq!(vec![(1000, true)], scan(&range, 0, 999, 1000));
+        assert_eq!(None, range.limit());
+
+        // The first request is too large and gets halved until it fits,
+        // after which the range grows until the provider rejects it again
+        let requests = scan(&range, 0, 999, 300);
+        assert_eq!(
+            vec![
+                (1000, false),
+                (500, false),
+                (250, true),
+                (312, false),
+                (156, true)
+            ],
+            requests[0..5]
+        );
+        assert!(requests.iter().all(|(blocks, ok)| !ok || *blocks <= 300));
+        let scanned: BlockNumber = requests
+            .iter()
+            .filter(|(_, ok)| *ok)
+            .map(|(blocks, _)| blocks)
+            .sum();
+        assert_eq!(1000, scanned);
+
+        // Once the provider can handle more, the range grows back
+        let requests = scan(&range, 0, 99_999, BlockNumber::MAX);
+        assert!(requests.iter().all(|(_, ok)| *ok));
+        // The last request only covers what is left of the range
+        let requests = &requests[..requests.len() - 1];
+        assert!(requests.windows(2).all(|w| w[0].0 <= w[1].0));
+        assert!(range.limit().unwrap() > 10_000);
+    }
+
+    #[test]
+    fn single_blocks_can_not_shrink() {
+        let range = log_range();
+        assert!(range.shrink(2));
+        assert_eq!(Some(1), range.limit());
+        assert!(!range.shrink(1));
+        assert_eq!(Some(1), range.limit());
+
+        // Requests that are smaller than the limit do not grow it
+        let range = log_range();
+        assert!(range.shrink(100));
+        range.grow(10);
+        assert_eq!(Some(50), range.limit());
+        range.grow(50);
+        assert_eq!(Some(62), range.limit());
+    }
+}
@@ -0,0 +1,98 @@
+//! Check whether the topics and `data` of a log can plausibly be decoded
+//! with an event ABI before handing the log to ethabi. Some contracts, for
+//! example rollups that post transaction batches, emit logs with megabytes
+//! of data. Decoding those with an ABI that does not match them makes
+//! ethabi allocate buffers based on whatever lengths it finds in the data,
+//! which can use up all the memory of a node. Since ethabi needs an owned
+//! copy of the log data for every handler it decodes with, we also want to
+//! avoid that copy for logs that can't be decoded anyway
+use graph::prelude::{
+    ethabi::{Event, ParamType},
+    lazy_static,
+    web3::types::Log,
+};
+
+lazy_static! {
+    /// The most bytes that the dynamic parameters of an event, like `bytes`,
+    /// `string` or arrays, can take up in the data of a log. Whether a log
+    /// is skipped or fails the subgraph depends on this limit, so all
+    /// indexers need to use the same value to compute the same PoIs
+    pub(crate) static ref MAX_LOG_DYNAMIC_DATA_SIZE: usize =
+        std::env::var("GRAPH_ETHEREUM_MAX_LOG_DYNAMIC_DATA_SIZE")
+            .ok()
+            .map(|s| {
+                s.parse::<usize>().unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_MAX_LOG_DYNAMIC_DATA_SIZE must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(1024 * 1024);
+}
+
+/// How a log compares to the layout of an event
+#[derive(Debug, PartialEq)]
+pub(crate) enum LogFit {
+    /// The log might decode with the event
+    Fits,
+    /// The log can not possibly decode with the event
+    Mismatch(String),
+    /// The data of the log is `size` bytes, but the event can use at most
+    /// `limit` bytes
+    Oversized { size: usize, limit: usize },
+}
+
+/// The number of bytes a value of type `kind` takes up in ABI encoding,
+/// or `None` if the type is dynamic
+fn encoded_size(kind: &ParamType) -> Option<usize> {
+    use ParamType::*;
+
+    match kind {
+        Bytes | String | Array(_) => None,
+        FixedArray(inner, len) => encoded_size(inner).map(|size| size * len),
+        Tuple(components) => components.iter().map(encoded_size).sum(),
+        Address | Int(_) | Uint(_) | Bool | FixedBytes(_) => Some(32),
+    }
+}
+
+/// Compare the topics and data of `log` to the layout of `event` without
+/// copying anything from the log. The dynamic parameters of the event may
+/// use at most `max_dynamic` bytes
+pub(crate) fn check(event: &Event, log: &Log, max_dynamic: usize) -> LogFit {
+    let topics = log.topics.len();
+    let data = log.data.0.len();
+    let indexed = event.inputs.iter().filter(|input| input.indexed).count();
+    let expected_topics = if event.anonymous {
+        indexed
+    } else {
+        indexed + 1
+    };
+    if topics != expected_topics {
+        return LogFit::Mismatch(format!(
+            "the event has {} topics, but the log has {}",
+            expected_topics, topics
+        ));
+    }
+
+    // Dynamic parameters take up one word in the head of the encoding
+    // which points to their actual data
+    let head: usize = event
+        .inputs
+        .iter()
+        .filter(|input| !input.indexed)
+        .map(|input| encoded_size(&input.kind).unwrap_or(32))
+        .sum();
+    if data < head {
+        return LogFit::Mismatch(format!(
+            "the event needs at least {} bytes of data, but the log has {}",
+            head, data
+        ));
+    }
+
+    let limit = head.saturating_add(max_dynamic);
+    if data > limit {
+        return LogFit::Oversized { size: data, limit };
+    }
+    LogFit::Fits
+}
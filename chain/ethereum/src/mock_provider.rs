@@ -0,0 +1,138 @@
+//! Ethereum providers for tests that run on a local port. They answer
+//! the JSON-RPC methods they are told to support with an empty list and
+//! all other methods with a `Method not found` error
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use graph::prelude::serde_json::{self, json, Value};
+use graph::prelude::{o, Logger};
+use graph::prometheus::Registry;
+
+use crate::{EthereumAdapter, ProviderEthRpcMetrics, Transport};
+
+pub(crate) struct MockProvider {
+    /// The HTTP status of every response; 429 for a provider that rate
+    /// limits us
+    status: u16,
+    /// The methods the provider supports
+    supported: Mutex<HashSet<String>>,
+}
+
+impl MockProvider {
+    pub fn new(status: u16) -> Arc<Self> {
+        Arc::new(MockProvider {
+            status,
+            supported: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn support(&self, method: &str) {
+        self.supported.lock().unwrap().insert(method.to_string());
+    }
+
+    fn respond(&self, request: &Value) -> Value {
+        let supported = request["method"]
+            .as_str()
+            .map(|method| self.supported.lock().unwrap().contains(method))
+            .unwrap_or(false);
+        if supported {
+            json!({ "jsonrpc": "2.0", "id": request["id"], "result": [] })
+        } else {
+            json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": -32601, "message": "Method not found" }
+            })
+        }
+    }
+
+    /// Answer one HTTP request
+    fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let (reason, body) = match self.status {
+            200 => {
+                let response = match serde_json::from_slice(&body).unwrap() {
+                    Value::Array(requests) => Value::Array(
+                        requests
+                            .iter()
+                            .map(|request| self.respond(request))
+                            .collect(),
+                    ),
+                    request => self.respond(&request),
+                };
+                ("OK", response.to_string())
+            }
+            _ => ("Too Many Requests", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            body.len(),
+            body
+        )
+    }
+
+    /// Serve requests on a local port and return the URL to send them to
+    fn serve(self: &Arc<Self>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let provider = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let provider = provider.clone();
+                    std::thread::spawn(move || provider.answer(stream));
+                }
+            }
+        });
+        url
+    }
+
+    /// Serve requests and create an adapter named `name` for them. Needs
+    /// to be called on a tokio runtime
+    pub async fn adapter(self: &Arc<Self>, name: &str) -> Arc<EthereumAdapter> {
+        let url = self.serve();
+        let (event_loop, transport) = Transport::new_rpc(&url, Default::default());
+        // The transport stops working when the event loop is dropped
+        std::mem::forget(event_loop);
+        let registry = Arc::new(graph_core::MetricsRegistry::new(
+            Logger::root(graph::slog::Discard, o!()),
+            Arc::new(Registry::new()),
+        ));
+        Arc::new(
+            EthereumAdapter::new(
+                Logger::root(graph::slog::Discard, o!()),
+                name.to_string(),
+                &url,
+                transport,
+                Arc::new(ProviderEthRpcMetrics::new(registry)),
+                true,
+                None,
+            )
+            .await,
+        )
+    }
+}
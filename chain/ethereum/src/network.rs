@@ -2,6 +2,7 @@ use anyhow::{anyhow, Context};
 use graph::cheap_clone::CheapClone;
 use graph::prelude::rand::{self, seq::IteratorRandom};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 pub use graph::impl_slog_value;
@@ -15,6 +16,33 @@ use crate::EthereumAdapter;
 pub struct EthereumNetworkAdapter {
     pub capabilities: NodeCapabilities,
     adapter: Arc<EthereumAdapter>,
+    /// Whether the head of this provider is too far behind the heads of
+    /// the other providers for the same network. Set by the
+    /// `HeadLagMonitor` and shared by all clones of this adapter
+    lagging: Arc<AtomicBool>,
+}
+
+impl EthereumNetworkAdapter {
+    pub fn adapter(&self) -> &Arc<EthereumAdapter> {
+        &self.adapter
+    }
+
+    pub fn is_lagging(&self) -> bool {
+        self.lagging.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_lagging(&self, lagging: bool) {
+        self.lagging.store(lagging, Ordering::SeqCst)
+    }
+
+    /// The capabilities of the provider as configured, but without
+    /// `traces` while the provider does not answer `trace_filter` requests
+    pub fn available_capabilities(&self) -> NodeCapabilities {
+        NodeCapabilities {
+            traces: self.capabilities.traces && self.adapter.supports_traces(),
+            ..self.capabilities
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -30,13 +58,21 @@ impl EthereumNetworkAdapters {
         let cheapest_sufficient_capability = self
             .adapters
             .iter()
-            .find(|adapter| &adapter.capabilities >= required_capabilities)
-            .map(|adapter| &adapter.capabilities);
+            .map(|adapter| adapter.available_capabilities())
+            .find(|capabilities| capabilities >= required_capabilities);
 
-        // Select randomly from the cheapest adapters that have sufficent capabilities.
-        self.adapters
+        // Select randomly from the cheapest adapters that have sufficent
+        // capabilities, avoiding adapters that are failing or whose head
+        // lags behind the other providers as long as there are others
+        let cheapest: Vec<_> = self
+            .adapters
             .iter()
-            .filter(|adapter| Some(&adapter.capabilities) == cheapest_sufficient_capability)
+            .filter(|adapter| {
+                Some(adapter.available_capabilities()) == cheapest_sufficient_capability
+            })
+            .collect();
+        Self::most_healthy(&cheapest)
+            .into_iter()
             .choose(&mut rand::thread_rng())
             .map(|adapter| adapter.adapter.cheap_clone())
             .with_context(|| {
@@ -47,15 +83,55 @@ impl EthereumNetworkAdapters {
             })
     }
 
+    /// The capabilities from `required` that no adapter has, or `None` if
+    /// there is an adapter with all of them
+    pub fn missing_capabilities(&self, required: &NodeCapabilities) -> Option<NodeCapabilities> {
+        let available: Vec<_> = self
+            .adapters
+            .iter()
+            .map(|adapter| adapter.available_capabilities())
+            .collect();
+        required.missing_from(&available)
+    }
+
     pub fn cheapest(&self) -> Option<Arc<EthereumAdapter>> {
         // EthereumAdapters are sorted by their NodeCapabilities when the EthereumNetworks
         // struct is instantiated so they do not need to be sorted here
-        self.adapters
-            .iter()
+        let adapters: Vec<_> = self.adapters.iter().collect();
+        Self::most_healthy(&adapters)
+            .into_iter()
             .next()
             .map(|ethereum_network_adapter| ethereum_network_adapter.adapter.clone())
     }
 
+    /// The adapters from `adapters` that are in the best shape: if there
+    /// are any that are neither failing nor lagging, those, then the ones
+    /// that are not failing, then the ones that are not lagging, and
+    /// finally all of them. Failing adapters are avoided more than lagging
+    /// ones since requests to them will most likely fail
+    fn most_healthy<'a>(
+        adapters: &[&'a EthereumNetworkAdapter],
+    ) -> Vec<&'a EthereumNetworkAdapter> {
+        let preferences: [&dyn Fn(&EthereumNetworkAdapter) -> bool; 3] = [
+            &|adapter: &EthereumNetworkAdapter| {
+                !adapter.adapter.is_failing() && !adapter.is_lagging()
+            },
+            &|adapter: &EthereumNetworkAdapter| !adapter.adapter.is_failing(),
+            &|adapter: &EthereumNetworkAdapter| !adapter.is_lagging(),
+        ];
+        preferences
+            .iter()
+            .map(|preferred| {
+                adapters
+                    .iter()
+                    .copied()
+                    .filter(|adapter| preferred(adapter))
+                    .collect::<Vec<_>>()
+            })
+            .find(|preferred| !preferred.is_empty())
+            .unwrap_or_else(|| adapters.to_vec())
+    }
+
     pub fn remove(&mut self, provider: &str) {
         self.adapters
             .retain(|adapter| adapter.adapter.provider() != provider);
@@ -87,6 +163,7 @@ impl EthereumNetworks {
         network_adapters.adapters.push(EthereumNetworkAdapter {
             capabilities,
             adapter: adapter.clone(),
+            lagging: Arc::new(AtomicBool::new(false)),
         });
     }
 
@@ -140,7 +217,84 @@ impl EthereumNetworks {
 
 #[cfg(test)]
 mod tests {
-    use super::NodeCapabilities;
+    use std::sync::Arc;
+
+    use graph::prelude::tokio;
+
+    use super::{EthereumNetworks, NodeCapabilities};
+    use crate::adapter::EthereumAdapter as _;
+    use crate::mock_provider::MockProvider;
+
+    #[test]
+    fn healthy_providers_are_preferred() {
+        const FULL: NodeCapabilities = NodeCapabilities {
+            archive: false,
+            traces: false,
+        };
+        const ARCHIVE: NodeCapabilities = NodeCapabilities {
+            archive: true,
+            traces: false,
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            // Creating the adapter probes the provider, which takes the
+            // rate limited one out of rotation
+            let failing = MockProvider::new(429).adapter("failing").await;
+            assert!(failing.is_failing());
+            let lagging = MockProvider::new(200).adapter("lagging").await;
+            let healthy = MockProvider::new(200).adapter("healthy").await;
+            let archive = MockProvider::new(200).adapter("archive").await;
+            assert!(!healthy.is_failing());
+
+            let mut networks = EthereumNetworks::new();
+            networks.insert("mainnet".to_string(), FULL, failing);
+            networks.insert("mainnet".to_string(), FULL, lagging);
+            networks.insert("mainnet".to_string(), FULL, healthy);
+            networks.insert("mainnet".to_string(), ARCHIVE, archive);
+            networks.sort();
+            let mut adapters = networks.networks.remove("mainnet").unwrap();
+            adapters
+                .adapters
+                .iter()
+                .find(|adapter| adapter.adapter.provider() == "lagging")
+                .unwrap()
+                .set_lagging(true);
+
+            // The choice is random among equally good providers
+            let selected = |adapters: &super::EthereumNetworkAdapters,
+                            required: &NodeCapabilities| {
+                (0..20)
+                    .map(|_| {
+                        adapters
+                            .cheapest_with(required)
+                            .unwrap()
+                            .provider()
+                            .to_string()
+                    })
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+            };
+
+            // Providers with fewer capabilities are used first, and of
+            // those the ones that are neither failing nor lagging
+            assert_eq!(vec!["healthy"], selected(&adapters, &FULL));
+            assert_eq!(
+                "healthy",
+                adapters.cheapest().unwrap().provider().to_string()
+            );
+            assert_eq!(vec!["archive"], selected(&adapters, &ARCHIVE));
+
+            // Lagging providers are better than failing ones
+            adapters.remove("healthy");
+            assert_eq!(vec!["lagging"], selected(&adapters, &FULL));
+
+            // Failing providers are still used if there is nothing else
+            adapters.remove("lagging");
+            assert_eq!(vec!["failing"], selected(&adapters, &FULL));
+        });
+    }
 
     #[test]
     fn ethereum_capabilities_comparison() {
@@ -196,4 +350,25 @@ mod tests {
         assert_eq!(true, &full_traces >= &full);
         assert_eq!(true, &full_traces >= &full_traces);
     }
+
+    #[test]
+    fn ethereum_missing_capabilities() {
+        let caps = |archive, traces| NodeCapabilities { archive, traces };
+        let full = caps(false, false);
+        let archive = caps(true, false);
+        let traces = caps(false, true);
+        let archive_traces = caps(true, true);
+
+        assert_eq!(None, full.missing_from(&[full]));
+        assert_eq!(None, full.missing_from(&[]));
+        assert_eq!(Some(traces), traces.missing_from(&[full, archive]));
+        assert_eq!(None, traces.missing_from(&[full, archive_traces]));
+        assert_eq!(Some(archive_traces), archive_traces.missing_from(&[]));
+        // Providers with some of the capabilities are not enough; we name
+        // what the closest provider lacks
+        assert_eq!(
+            Some(traces),
+            archive_traces.missing_from(&[full, archive, traces])
+        );
+    }
 }
@@ -31,6 +31,7 @@ fn create_subgraph(
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        max_triggers_per_block: None,
         chain: PhantomData,
     };
 
@@ -0,0 +1,295 @@
+//! Fetch blocks from the block caches of sibling graph-node instances
+//! before asking the Ethereum node for them. Peers serve their cached
+//! blocks from the index node server under
+//! `/block-cache/<network>/<hash>`. Peers are only trusted as far as the
+//! shared token goes; we still check that the header of the block they
+//! send us hashes to the hash we asked for, and fall back to the Ethereum
+//! node for anything we can't get from a peer quickly. Blocks from chains
+//! whose headers do not follow the Ethereum layout, e.g., because they
+//! use seal fields, never check out and always come from the Ethereum node
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures03::future::select_ok;
+use futures03::stream::{self, StreamExt};
+use graph::prelude::{
+    debug, futures03, lazy_static, reqwest, tiny_keccak, tokio,
+    web3::types::{H256, U256},
+    CounterVec, Error, LightEthereumBlock, LightEthereumBlockExt, Logger, MetricsRegistry,
+};
+use graph::url::Url;
+
+lazy_static! {
+    /// How long we wait for peers to answer a request for a block, in
+    /// milliseconds. Peers that are slower than that are treated as if
+    /// they did not have the block
+    static ref PEER_TIMEOUT: Duration = {
+        let timeout = std::env::var("GRAPH_BLOCK_CACHE_PEER_TIMEOUT")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_BLOCK_CACHE_PEER_TIMEOUT must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(300);
+        Duration::from_millis(timeout)
+    };
+}
+
+/// How many blocks we ask peers for at the same time. Each request goes
+/// to all peers
+const MAX_CONCURRENT_BLOCKS: usize = 10;
+
+/// The peers whose block caches we use, shared by all networks
+pub struct BlockCachePeers {
+    client: reqwest::Client,
+    peers: Vec<Url>,
+    token: String,
+    requests: Box<CounterVec>,
+}
+
+impl BlockCachePeers {
+    /// Create a client for the block caches of the index node servers at
+    /// `peers`, authenticating with `token`
+    pub fn new(
+        peers: &[String],
+        token: String,
+        registry: Arc<impl MetricsRegistry>,
+    ) -> Result<Self, Error> {
+        let peers = peers
+            .iter()
+            .map(|peer| Url::parse(peer).map_err(|e| anyhow::anyhow!("{}: {}", peer, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let client = reqwest::Client::builder().timeout(*PEER_TIMEOUT).build()?;
+        let requests = registry.new_counter_vec(
+            "eth_block_cache_peer_requests",
+            "Counts requests for blocks to the block caches of peers by whether a peer had the block",
+            vec![String::from("network"), String::from("result")],
+        )?;
+        Ok(BlockCachePeers {
+            client,
+            peers,
+            token,
+            requests,
+        })
+    }
+
+    /// The block cache of the peers for `network`
+    pub fn for_network(self: &Arc<Self>, network: &str) -> PeerBlockCache {
+        PeerBlockCache {
+            network: network.to_string(),
+            peers: self.clone(),
+        }
+    }
+
+    async fn fetch(
+        &self,
+        peer: &Url,
+        network: &str,
+        hash: H256,
+    ) -> Result<LightEthereumBlock, Error> {
+        let url = peer.join(&format!("block-cache/{}/{:x}", network, hash))?;
+        let block: LightEthereumBlock = self
+            .client
+            .get(url)
+            .bearer_auth(&self.token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        verify(&block, hash)?;
+        Ok(block)
+    }
+}
+
+fn rlp_append_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        rlp_append_length(out, 0x80, bytes.len());
+        out.extend_from_slice(bytes);
+    }
+}
+
+fn rlp_append_uint(out: &mut Vec<u8>, value: U256) {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    rlp_append_bytes(out, &bytes[start..]);
+}
+
+/// Append the prefix for an item of `len` bytes; `offset` is `0x80` for
+/// strings and `0xc0` for lists
+fn rlp_append_length(out: &mut Vec<u8>, offset: u8, len: usize) {
+    if len <= 55 {
+        out.push(offset + len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        out.push(offset + 55 + (bytes.len() - start) as u8);
+        out.extend_from_slice(&bytes[start..]);
+    }
+}
+
+/// Compute the hash of the header of `block`, i.e., the Keccak hash of
+/// its RLP encoding. Return `None` if `block` lacks some of the fields
+/// of an Ethereum header
+pub(crate) fn header_hash(block: &LightEthereumBlock) -> Option<H256> {
+    let number = block.number?;
+    let logs_bloom = block.logs_bloom?;
+    let mix_hash = block.mix_hash?;
+    let nonce = block.nonce?;
+
+    let mut fields = Vec::with_capacity(600);
+    rlp_append_bytes(&mut fields, block.parent_hash.as_bytes());
+    rlp_append_bytes(&mut fields, block.uncles_hash.as_bytes());
+    rlp_append_bytes(&mut fields, block.author.as_bytes());
+    rlp_append_bytes(&mut fields, block.state_root.as_bytes());
+    rlp_append_bytes(&mut fields, block.transactions_root.as_bytes());
+    rlp_append_bytes(&mut fields, block.receipts_root.as_bytes());
+    rlp_append_bytes(&mut fields, logs_bloom.as_bytes());
+    rlp_append_uint(&mut fields, block.difficulty);
+    rlp_append_uint(&mut fields, U256::from(number.as_u64()));
+    rlp_append_uint(&mut fields, block.gas_limit);
+    rlp_append_uint(&mut fields, block.gas_used);
+    rlp_append_uint(&mut fields, block.timestamp);
+    rlp_append_bytes(&mut fields, &block.extra_data.0);
+    rlp_append_bytes(&mut fields, mix_hash.as_bytes());
+    rlp_append_bytes(&mut fields, nonce.as_bytes());
+    // Since London (EIP-1559)
+    if let Some(base_fee_per_gas) = block.base_fee_per_gas {
+        rlp_append_uint(&mut fields, base_fee_per_gas);
+    }
+
+    let mut header = Vec::with_capacity(fields.len() + 3);
+    rlp_append_length(&mut header, 0xc0, fields.len());
+    header.extend_from_slice(&fields);
+    Some(H256::from(tiny_keccak::keccak256(&header)))
+}
+
+/// Check that `block` is the block with hash `hash` and that we can use
+/// it. We do not trust the hash that the peer sends and recompute it from
+/// the header. Peers that run an older version can have blocks in their
+/// cache that lack the types of transactions, which we fetch again from
+/// the Ethereum node like those in our own cache
+pub(crate) fn verify(block: &LightEthereumBlock, hash: H256) -> Result<(), Error> {
+    if block.hash != Some(hash) {
+        return Err(anyhow::anyhow!(
+            "peer sent block {:?} when we asked for {:x}",
+            block.hash,
+            hash
+        ));
+    }
+    if !block.has_transaction_types() {
+        return Err(anyhow::anyhow!(
+            "peer sent block {:x} without transaction types",
+            hash
+        ));
+    }
+    if block.number.is_none() {
+        return Err(anyhow::anyhow!(
+            "peer sent block {:x} without a number",
+            hash
+        ));
+    }
+    match header_hash(block) {
+        Some(header_hash) if header_hash == hash => (),
+        Some(header_hash) => {
+            return Err(anyhow::anyhow!(
+                "the header of block {:x} sent by peer hashes to {:x}",
+                hash,
+                header_hash
+            ))
+        }
+        None => {
+            return Err(anyhow::anyhow!(
+                "peer sent block {:x} with an incomplete header",
+                hash
+            ))
+        }
+    }
+    crate::ethereum_adapter::check_block_number(block)?;
+    if let Some(tx) = block
+        .transactions
+        .iter()
+        .find(|tx| tx.block_hash != Some(hash))
+    {
+        return Err(anyhow::anyhow!(
+            "transaction {:x} in block {:x} sent by peer belongs to block {:?}",
+            tx.hash,
+            hash,
+            tx.block_hash
+        ));
+    }
+    Ok(())
+}
+
+/// The block caches of our peers for one network
+#[derive(Clone)]
+pub struct PeerBlockCache {
+    network: String,
+    peers: Arc<BlockCachePeers>,
+}
+
+impl PeerBlockCache {
+    /// Ask all peers for the block with hash `hash` and use the first
+    /// answer that checks out. Return `None` if no peer has the block or
+    /// none answered within `GRAPH_BLOCK_CACHE_PEER_TIMEOUT`
+    async fn block(&self, logger: &Logger, hash: H256) -> Option<LightEthereumBlock> {
+        let requests = self
+            .peers
+            .peers
+            .iter()
+            .map(|peer| Box::pin(self.peers.fetch(peer, &self.network, hash)));
+        let block = match tokio::time::timeout(*PEER_TIMEOUT, select_ok(requests)).await {
+            Ok(Ok((block, _))) => Some(block),
+            Ok(Err(e)) => {
+                debug!(logger, "No peer had block in its cache";
+                       "hash" => format!("{:x}", hash),
+                       "error" => e.to_string());
+                None
+            }
+            Err(_) => None,
+        };
+        let result = if block.is_some() { "hit" } else { "miss" };
+        self.peers
+            .requests
+            .with_label_values(&[self.network.as_str(), result])
+            .inc();
+        block
+    }
+
+    /// Get as many of the blocks with hashes `hashes` from peers as
+    /// possible. Return the blocks we got, and the hashes of the blocks
+    /// that we still need to get from somewhere else
+    pub(crate) async fn blocks(
+        &self,
+        logger: &Logger,
+        hashes: Vec<H256>,
+    ) -> (Vec<LightEthereumBlock>, Vec<H256>) {
+        if self.peers.peers.is_empty() {
+            return (vec![], hashes);
+        }
+
+        let blocks: Vec<_> = stream::iter(hashes)
+            .map(|hash| async move { (hash, self.block(logger, hash).await) })
+            .buffer_unordered(MAX_CONCURRENT_BLOCKS)
+            .collect()
+            .await;
+
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for (hash, block) in blocks {
+            match block {
+                Some(block) => found.push(block),
+                None => missing.push(hash),
+            }
+        }
+        (found, missing)
+    }
+}
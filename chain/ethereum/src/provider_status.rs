@@ -0,0 +1,302 @@
+//! Compare what the providers for a network report about the chain. For
+//! every provider we ask for its latest block, its client version and
+//! whether it is still syncing, and then for the hash of the highest block
+//! that all providers and this node have. Providers whose hash for that
+//! block differs from the one most providers report are on a fork. Since
+//! every check sends requests to all providers, results are cached for
+//! `CACHE_TTL` so that callers can not use this to flood the providers
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use graph::blockchain::BlockPtr;
+use graph::components::store::ChainStore;
+use graph::data::graphql::{object, IntoValue};
+use graph::data::store::scalar::Timestamp;
+use graph::prelude::{chrono::Utc, futures03, q, tokio, web3::types::H256, BlockNumber, Error};
+
+use crate::head_lag::head_lags;
+use crate::network::{EthereumNetworkAdapters, EthereumNetworks};
+use crate::EthereumAdapter;
+use crate::EthereumAdapterTrait as _;
+
+/// How long we reuse the statuses of the providers of a network
+const CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// How long we wait for a provider to answer one request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What one provider reported
+#[derive(Clone, Debug)]
+pub struct ProviderStatus {
+    pub provider: String,
+    pub head: Option<BlockPtr>,
+    /// How many blocks `head` is behind the highest head of all providers
+    pub blocks_behind: Option<BlockNumber>,
+    pub client_version: Option<String>,
+    pub syncing: Option<bool>,
+    /// The hash the provider has for the common block
+    pub common_hash: Option<H256>,
+    /// Whether `common_hash` differs from what most providers report
+    pub forked: bool,
+    pub errors: Vec<String>,
+}
+
+impl IntoValue for ProviderStatus {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "ProviderStatus",
+            provider: self.provider,
+            head: self.head.map(block_value),
+            blocksBehind: self.blocks_behind.map(|behind| behind.to_string()),
+            clientVersion: self.client_version,
+            syncing: self.syncing,
+            commonHash: self.common_hash.map(|hash| format!("{:#x}", hash)),
+            forked: self.forked,
+            errors: self.errors,
+        }
+    }
+}
+
+/// What all providers for a network reported, and how that compares to
+/// the chain this node ingested
+#[derive(Clone, Debug)]
+pub struct NetworkStatus {
+    pub network: String,
+    pub ingested_head: Option<BlockPtr>,
+    /// The highest block that all providers that answered and this node
+    /// have
+    pub common_block: Option<BlockNumber>,
+    /// Whether the hash this node has for the common block differs from
+    /// what most providers report
+    pub ingested_forked: bool,
+    pub providers: Vec<ProviderStatus>,
+    pub checked_at: Timestamp,
+}
+
+impl IntoValue for NetworkStatus {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "NetworkProviderStatuses",
+            network: self.network,
+            ingestedHead: self.ingested_head.map(block_value),
+            commonBlock: self.common_block.map(|number| number.to_string()),
+            ingestedForked: self.ingested_forked,
+            providers: self.providers.into_iter().map(IntoValue::into_value).collect::<Vec<_>>(),
+            checkedAt: self.checked_at.to_string(),
+        }
+    }
+}
+
+fn block_value(ptr: BlockPtr) -> q::Value {
+    object! {
+        __typename: "Block",
+        hash: format!("{:#x}", ptr.hash_as_h256()),
+        number: ptr.number.to_string(),
+    }
+}
+
+/// The hash that most of `hashes` agree on. Ties go to the hash that
+/// appears first
+fn majority_hash(hashes: &[Option<H256>]) -> Option<H256> {
+    let mut counts: Vec<(H256, usize)> = Vec::new();
+    for hash in hashes.iter().flatten() {
+        match counts.iter_mut().find(|(other, _)| other == hash) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((*hash, 1)),
+        }
+    }
+    counts
+        .into_iter()
+        .fold(
+            None,
+            |best: Option<(H256, usize)>, (hash, count)| match best {
+                Some((_, best_count)) if best_count >= count => best,
+                _ => Some((hash, count)),
+            },
+        )
+        .map(|(hash, _)| hash)
+}
+
+async fn ask<T>(request: impl Future<Output = Result<T, Error>>) -> Result<T, String> {
+    match tokio::time::timeout(REQUEST_TIMEOUT, request).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no response within {}s", REQUEST_TIMEOUT.as_secs())),
+    }
+}
+
+/// Checks the providers of all networks on demand
+pub struct ProviderStatuses {
+    networks: HashMap<String, (EthereumNetworkAdapters, Arc<dyn ChainStore>)>,
+    cache: tokio::sync::Mutex<HashMap<String, (Instant, NetworkStatus)>>,
+}
+
+impl ProviderStatuses {
+    /// Check the providers in `networks`, comparing them to the chain in
+    /// `chain_stores`. Networks without a chain store are ignored
+    pub fn new(
+        networks: &EthereumNetworks,
+        chain_stores: HashMap<String, Arc<dyn ChainStore>>,
+    ) -> Self {
+        let networks = networks
+            .networks
+            .iter()
+            .filter_map(|(name, adapters)| {
+                chain_stores
+                    .get(name)
+                    .map(|store| (name.clone(), (adapters.clone(), store.clone())))
+            })
+            .collect();
+        ProviderStatuses {
+            networks,
+            cache: tokio::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The status of the providers for `network`, or `None` if this node
+    /// has no providers for it. Concurrent callers wait for the same
+    /// check instead of each starting their own
+    pub async fn status(&self, network: &str) -> Option<NetworkStatus> {
+        let (adapters, chain_store) = self.networks.get(network)?;
+
+        let mut cache = self.cache.lock().await;
+        if let Some((checked, status)) = cache.get(network) {
+            if checked.elapsed() < CACHE_TTL {
+                return Some(status.clone());
+            }
+        }
+
+        let status = check_network(network, adapters, chain_store.as_ref()).await;
+        cache.insert(network.to_string(), (Instant::now(), status.clone()));
+        Some(status)
+    }
+}
+
+async fn check_provider(adapter: &EthereumAdapter) -> ProviderStatus {
+    let (head, client_version, syncing) = futures03::join!(
+        ask(adapter.latest_block_ptr()),
+        ask(adapter.client_version()),
+        ask(adapter.is_syncing())
+    );
+    let errors = [
+        head.as_ref().err(),
+        client_version.as_ref().err(),
+        syncing.as_ref().err(),
+    ]
+    .iter()
+    .flatten()
+    .map(|e| e.to_string())
+    .collect();
+    ProviderStatus {
+        provider: adapter.provider().to_string(),
+        head: head.ok(),
+        blocks_behind: None,
+        client_version: client_version.ok(),
+        syncing: syncing.ok(),
+        common_hash: None,
+        forked: false,
+        errors,
+    }
+}
+
+async fn check_network(
+    network: &str,
+    adapters: &EthereumNetworkAdapters,
+    chain_store: &dyn ChainStore,
+) -> NetworkStatus {
+    let mut providers = futures03::future::join_all(
+        adapters
+            .adapters
+            .iter()
+            .map(|adapter| check_provider(adapter.adapter())),
+    )
+    .await;
+
+    let heads: Vec<_> = providers
+        .iter()
+        .map(|provider| provider.head.as_ref().map(|head| head.number))
+        .collect();
+    for (provider, behind) in providers.iter_mut().zip(head_lags(&heads)) {
+        provider.blocks_behind = behind;
+    }
+
+    // Failing to read our own head should not hide what the providers say
+    let ingested_head = chain_store.chain_head_ptr().ok().flatten();
+    let common_block = heads
+        .iter()
+        .flatten()
+        .copied()
+        .chain(ingested_head.as_ref().map(|head| head.number))
+        .min();
+
+    if let Some(common_block) = common_block {
+        let hashes = futures03::future::join_all(adapters.adapters.iter().zip(&providers).map(
+            |(adapter, provider)| async move {
+                match &provider.head {
+                    Some(head) if head.number == common_block => Ok(Some(head.hash_as_h256())),
+                    Some(_) => ask(adapter.adapter().block_hash_at(common_block)).await,
+                    None => Ok(None),
+                }
+            },
+        ))
+        .await;
+        for (provider, hash) in providers.iter_mut().zip(hashes) {
+            match hash {
+                Ok(hash) => provider.common_hash = hash,
+                Err(e) => provider.errors.push(e),
+            }
+        }
+    }
+
+    let hashes: Vec<_> = providers
+        .iter()
+        .map(|provider| provider.common_hash)
+        .collect();
+    let majority = majority_hash(&hashes);
+    for provider in providers.iter_mut() {
+        provider.forked = provider.common_hash.is_some() && provider.common_hash != majority;
+    }
+
+    // The block cache can hold several blocks with the same number; we
+    // only know which one we consider canonical if there is just one
+    let ingested_hash = common_block.and_then(|number| match &ingested_head {
+        Some(head) if head.number == number => Some(head.hash_as_h256()),
+        _ => chain_store
+            .block_hashes_by_block_number(number)
+            .ok()
+            .filter(|hashes| hashes.len() == 1)
+            .map(|hashes| hashes[0]),
+    });
+    let ingested_forked =
+        ingested_hash.is_some() && majority.is_some() && ingested_hash != majority;
+
+    NetworkStatus {
+        network: network.to_string(),
+        ingested_head,
+        common_block,
+        ingested_forked,
+        providers,
+        checked_at: Timestamp::from(Utc::now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::majority_hash;
+    use graph::prelude::web3::types::H256;
+
+    #[test]
+    fn majority() {
+        let a = Some(H256::from_low_u64_be(1));
+        let b = Some(H256::from_low_u64_be(2));
+
+        assert_eq!(a, majority_hash(&[a, b, a]));
+        assert_eq!(b, majority_hash(&[a, None, b, b]));
+        // Ties go to the first hash
+        assert_eq!(a, majority_hash(&[a, b]));
+        assert_eq!(None, majority_hash(&[None, None]));
+        assert_eq!(None, majority_hash(&[]));
+    }
+}
@@ -0,0 +1,143 @@
+//! We fetch the receipts of a whole block with one request when the
+//! provider supports `eth_getBlockReceipts` or `parity_getBlockReceipts`.
+//! Which of them a provider supports can change while the node runs, for
+//! example because the first probe failed with a transient error or
+//! because the provider was upgraded, so we probe all providers again
+//! every `GRAPH_ETHEREUM_RECEIPTS_PROBE_INTERVAL` seconds
+use std::str::FromStr;
+use std::time::Duration;
+
+use graph::prelude::{info, lazy_static, o, tokio, Logger};
+
+use crate::network::{EthereumNetworkAdapter, EthereumNetworks};
+use crate::EthereumAdapterTrait as _;
+
+lazy_static! {
+    /// How often we probe providers for block receipts support, in seconds
+    static ref RECEIPTS_PROBE_INTERVAL: Duration = {
+        let secs = std::env::var("GRAPH_ETHEREUM_RECEIPTS_PROBE_INTERVAL")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_RECEIPTS_PROBE_INTERVAL must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(300);
+        Duration::from_secs(secs)
+    };
+}
+
+/// Probes all providers for the methods they support to get the receipts
+/// of whole blocks
+pub struct ReceiptsProbe {
+    logger: Logger,
+    adapters: Vec<(String, EthereumNetworkAdapter)>,
+}
+
+impl ReceiptsProbe {
+    pub fn new(logger: &Logger, networks: &EthereumNetworks) -> Self {
+        ReceiptsProbe {
+            logger: logger.new(o!("component" => "ReceiptsProbe")),
+            adapters: networks
+                .networks
+                .iter()
+                .flat_map(|(name, adapters)| {
+                    adapters
+                        .adapters
+                        .iter()
+                        .map(move |adapter| (name.clone(), adapter.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Probe the providers every `GRAPH_ETHEREUM_RECEIPTS_PROBE_INTERVAL`
+    /// seconds. They were already probed when they were created, so the
+    /// first probe happens after one interval
+    pub fn start(self) {
+        if self.adapters.is_empty() {
+            return;
+        }
+        graph::spawn_named("receipts-probe", async move {
+            let mut interval = tokio::time::interval(*RECEIPTS_PROBE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                for (network, adapter) in &self.adapters {
+                    self.probe(network, adapter).await;
+                }
+            }
+        });
+    }
+
+    async fn probe(&self, network: &str, adapter: &EthereumNetworkAdapter) {
+        let adapter = adapter.adapter();
+        let before = adapter.block_receipts_method();
+        let after = adapter.reprobe_block_receipts().await;
+        if before == after {
+            return;
+        }
+        match after {
+            Some(method) => info!(self.logger, "Fetching receipts for whole blocks";
+                                  "network" => network,
+                                  "provider" => adapter.provider(),
+                                  "method" => method.name()),
+            None => info!(self.logger, "Fetching receipts one transaction at a time";
+                          "network" => network,
+                          "provider" => adapter.provider()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use graph::prelude::{o, tokio, CheapClone, Logger};
+
+    use super::ReceiptsProbe;
+    use crate::mock_provider::MockProvider;
+    use crate::{BlockReceiptsMethod, EthereumNetworks, NodeCapabilities};
+
+    #[test]
+    fn probe_notices_new_receipts_support() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let provider = MockProvider::new(200);
+            let adapter = provider.adapter("upgraded").await;
+            let clone = adapter.cheap_clone();
+            assert_eq!(None, adapter.block_receipts_method());
+
+            let mut networks = EthereumNetworks::new();
+            networks.insert(
+                "mainnet".to_string(),
+                NodeCapabilities {
+                    archive: false,
+                    traces: false,
+                },
+                adapter.clone(),
+            );
+            let probe = ReceiptsProbe::new(&Logger::root(graph::slog::Discard, o!()), &networks);
+
+            // Nothing changes while the provider does not support the method
+            for (network, adapter) in &probe.adapters {
+                probe.probe(network, adapter).await;
+            }
+            assert_eq!(None, adapter.block_receipts_method());
+
+            provider.support("eth_getBlockReceipts");
+            for (network, adapter) in &probe.adapters {
+                probe.probe(network, adapter).await;
+            }
+            assert_eq!(
+                Some(BlockReceiptsMethod::Eth),
+                adapter.block_receipts_method()
+            );
+            assert_eq!(
+                Some(BlockReceiptsMethod::Eth),
+                clone.block_receipts_method()
+            );
+        });
+    }
+}
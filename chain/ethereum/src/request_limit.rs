@@ -0,0 +1,130 @@
+//! Stay within the number of requests per second that a provider allows.
+//! The limit for a provider can be set in its configuration, and
+//! `GRAPH_ETHEREUM_REQUEST_LIMIT` sets it for all providers that do not
+//! have their own. Requests that would exceed the limit wait until the
+//! provider has capacity for them again
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::prelude::{lazy_static, tokio, Arc};
+
+use crate::ProviderEthRpcMetrics;
+
+lazy_static! {
+    /// The requests per second we send to providers that do not have their
+    /// own limit. There is no limit if this is not set
+    static ref DEFAULT_REQUEST_LIMIT: Option<u32> = std::env::var("GRAPH_ETHEREUM_REQUEST_LIMIT")
+        .ok()
+        .map(|s| {
+            u32::from_str(&s)
+                .ok()
+                .filter(|limit| *limit > 0)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "GRAPH_ETHEREUM_REQUEST_LIMIT must be a positive number, but is `{}`",
+                        s
+                    )
+                })
+        });
+}
+
+/// A token bucket that holds up to one second's worth of requests
+struct Bucket {
+    /// Requests per second
+    rate: f64,
+    /// The requests we can still make right away. Negative when requests
+    /// are waiting for capacity
+    tokens: f64,
+    /// When `tokens` was last updated
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        Bucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    /// Take `n` tokens from the bucket and return how long the requests
+    /// for them have to wait
+    fn take(&mut self, n: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        self.tokens -= n as f64;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Limits the requests per second for one provider, shared by all clones
+/// of its transport
+pub struct RequestLimiter {
+    provider: String,
+    metrics: Arc<ProviderEthRpcMetrics>,
+    bucket: Mutex<Bucket>,
+}
+
+impl std::fmt::Debug for RequestLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RequestLimiter")
+            .field("provider", &self.provider)
+            .field("rate", &self.bucket.lock().unwrap().rate)
+            .finish()
+    }
+}
+
+impl RequestLimiter {
+    /// A limiter for `provider` that allows `limit` requests per second,
+    /// or `GRAPH_ETHEREUM_REQUEST_LIMIT` if `limit` is not set. Returns
+    /// `None` if there is no limit at all
+    pub fn new(
+        provider: &str,
+        limit: Option<u32>,
+        metrics: Arc<ProviderEthRpcMetrics>,
+    ) -> Option<Self> {
+        let limit = limit.or(*DEFAULT_REQUEST_LIMIT)?;
+        Some(RequestLimiter {
+            provider: provider.to_string(),
+            metrics,
+            bucket: Mutex::new(Bucket::new(limit, Instant::now())),
+        })
+    }
+
+    /// Wait until we may send `n` more requests to the provider
+    pub(crate) async fn acquire(&self, n: usize) {
+        let wait = self.bucket.lock().unwrap().take(n, Instant::now());
+        if wait > Duration::from_secs(0) {
+            self.metrics
+                .add_request_limit_wait(&self.provider, n, wait.as_secs_f64());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[test]
+fn bucket_limits_rate() {
+    let start = Instant::now();
+    let mut bucket = Bucket::new(10, start);
+    let mut take = |n, now| (bucket.take(n, now).as_secs_f64() * 1000.0).round() as u64;
+
+    // A full bucket allows a burst of up to one second's worth of requests
+    for _ in 0..10 {
+        assert_eq!(0, take(1, start));
+    }
+    // Every request after that waits for its turn
+    assert_eq!(100, take(1, start));
+    assert_eq!(300, take(2, start));
+
+    // Capacity that was not used does not accumulate beyond one second
+    let later = start + Duration::from_secs(60);
+    assert_eq!(0, take(10, later));
+    assert_eq!(100, take(1, later));
+}
@@ -0,0 +1,214 @@
+//! A debugging aid for misbehaving providers. When `GRAPH_ETH_RPC_DEBUG`
+//! is set to a sample rate between 0 and 1, that fraction of JSON-RPC
+//! requests is logged at debug level together with the response, and the
+//! last `GRAPH_ETH_RPC_DEBUG_FAILURES` requests that failed are kept in
+//! memory for each endpoint so they can be looked at after the fact. When
+//! the variable is not set, transports do not carry any debugging state
+//! and requests go straight to the provider
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use graph::data::graphql::{object, IntoValue};
+use graph::data::store::scalar::Timestamp;
+use graph::prelude::{chrono::Utc, debug, lazy_static, o, q, rand, serde_json, web3, Logger};
+use graph::util::security::SafeDisplay;
+use jsonrpc_core::types::Call;
+use serde_json::Value;
+
+lazy_static! {
+    /// The fraction of requests to log, or `None` if debugging is off
+    static ref SAMPLE_RATE: Option<f64> = std::env::var("GRAPH_ETH_RPC_DEBUG")
+        .ok()
+        .map(|s| {
+            s.parse::<f64>()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .unwrap_or_else(|| {
+                    panic!(
+                        "GRAPH_ETH_RPC_DEBUG must be a number between 0 and 1, but is `{}`",
+                        s
+                    )
+                })
+        });
+
+    /// How many failed requests to remember for each endpoint
+    static ref MAX_FAILURES: usize = std::env::var("GRAPH_ETH_RPC_DEBUG_FAILURES")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!(
+                    "GRAPH_ETH_RPC_DEBUG_FAILURES must be a number, but is `{}`",
+                    s
+                )
+            })
+        })
+        .unwrap_or(20);
+
+    /// The most recent failures for each endpoint, oldest first
+    static ref FAILURES: Mutex<HashMap<String, VecDeque<FailedRequest>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// How much of a request or response body we log or remember
+const MAX_BODY_LEN: usize = 2048;
+
+/// A JSON-RPC request that failed
+#[derive(Clone, Debug)]
+pub struct FailedRequest {
+    pub endpoint: String,
+    pub method: String,
+    /// The parameters of the request, truncated
+    pub params: String,
+    pub error: String,
+    pub duration_ms: u64,
+    pub failed_at: Timestamp,
+}
+
+impl IntoValue for FailedRequest {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "RpcFailure",
+            endpoint: self.endpoint,
+            method: self.method,
+            params: self.params,
+            error: self.error,
+            durationMs: self.duration_ms,
+            failedAt: self.failed_at.to_string(),
+        }
+    }
+}
+
+/// The failed requests that were remembered, most recent first. If
+/// `endpoint` is given, only failures for endpoints that contain it are
+/// returned
+pub fn failed_requests(endpoint: Option<&str>) -> Vec<FailedRequest> {
+    let failures = FAILURES.lock().unwrap();
+    let mut requests: Vec<_> = failures
+        .iter()
+        .filter(|(name, _)| endpoint.map_or(true, |endpoint| name.contains(endpoint)))
+        .flat_map(|(_, requests)| requests.iter().cloned())
+        .collect();
+    requests.sort_by(|a, b| b.failed_at.cmp(&a.failed_at));
+    requests
+}
+
+/// Debugging state for the transport to one endpoint
+#[derive(Debug)]
+pub(crate) struct RpcDebug {
+    logger: Logger,
+    /// The URL of the endpoint with secrets removed
+    endpoint: String,
+    sample_rate: f64,
+}
+
+impl RpcDebug {
+    /// Return debugging state for the endpoint at `url`, or `None` if
+    /// debugging is turned off
+    pub fn new(logger: &Logger, url: &str) -> Option<Arc<Self>> {
+        let sample_rate = (*SAMPLE_RATE)?;
+        let endpoint = SafeDisplay(url).to_string();
+        Some(Arc::new(RpcDebug {
+            logger: logger.new(o!("endpoint" => endpoint.clone())),
+            endpoint,
+            sample_rate,
+        }))
+    }
+
+    /// Start tracking one request to the endpoint
+    pub fn request(&self, method: String, params: String) -> Request {
+        let sampled = rand::random::<f64>() < self.sample_rate;
+        let params = truncate(params);
+        if sampled {
+            debug!(self.logger, "JSON-RPC request";
+                   "method" => &method,
+                   "params" => &params);
+        }
+        Request {
+            method,
+            params,
+            sampled,
+            start: Instant::now(),
+        }
+    }
+
+    /// Record the outcome of `request`. The `response` is only rendered
+    /// for requests that are logged
+    pub fn finish(&self, request: Request, result: Result<impl FnOnce() -> String, &web3::Error>) {
+        let duration_ms = request.start.elapsed().as_millis() as u64;
+        match result {
+            Ok(response) => {
+                if request.sampled {
+                    let response = response();
+                    debug!(self.logger, "JSON-RPC response";
+                           "method" => &request.method,
+                           "response_size" => response.len(),
+                           "response" => truncate(response),
+                           "duration_ms" => duration_ms);
+                }
+            }
+            Err(e) => {
+                let error = e.to_string();
+                if request.sampled {
+                    debug!(self.logger, "JSON-RPC request failed";
+                           "method" => &request.method,
+                           "error" => &error,
+                           "duration_ms" => duration_ms);
+                }
+                let failure = FailedRequest {
+                    endpoint: self.endpoint.clone(),
+                    method: request.method,
+                    params: request.params,
+                    error,
+                    duration_ms,
+                    failed_at: Timestamp::from(Utc::now()),
+                };
+                let mut failures = FAILURES.lock().unwrap();
+                let failures = failures.entry(self.endpoint.clone()).or_default();
+                failures.push_back(failure);
+                while failures.len() > *MAX_FAILURES {
+                    failures.pop_front();
+                }
+            }
+        }
+    }
+}
+
+/// A request that is being tracked
+pub(crate) struct Request {
+    method: String,
+    params: String,
+    sampled: bool,
+    start: Instant,
+}
+
+/// The method and parameters of a JSON-RPC call
+pub(crate) fn describe_call(call: &Call) -> (String, String) {
+    match call {
+        Call::MethodCall(call) => (
+            call.method.clone(),
+            serde_json::to_string(&call.params).unwrap_or_default(),
+        ),
+        Call::Notification(call) => (
+            call.method.clone(),
+            serde_json::to_string(&call.params).unwrap_or_default(),
+        ),
+        Call::Invalid { .. } => ("invalid".to_string(), String::new()),
+    }
+}
+
+pub(crate) fn describe_value(value: &Value) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn truncate(mut s: String) -> String {
+    if s.len() > MAX_BODY_LEN {
+        let mut end = MAX_BODY_LEN;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push_str("...");
+    }
+    s
+}
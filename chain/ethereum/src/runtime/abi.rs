@@ -1,4 +1,4 @@
-use graph::prelude::BigInt;
+use graph::prelude::{BigInt, EthereumUncle};
 use graph::runtime::{asc_get, asc_new, AscPtr, DeterministicHostError, FromAscObj, ToAscObj};
 use graph::runtime::{AscHeap, AscIndexId, AscType, IndexForAscTypeId};
 use graph_runtime_derive::AscType;
@@ -7,6 +7,7 @@ use graph_runtime_wasm::asc_abi::class::{
 };
 use semver::Version;
 use std::mem::size_of;
+use web3::types::{Log, TransactionReceipt, H256, U256};
 
 use crate::trigger::{
     EthereumBlockData, EthereumCallData, EthereumEventData, EthereumTransactionData,
@@ -121,6 +122,68 @@ impl AscIndexId for AscEthereumBlock {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumBlock;
 }
 
+/// Adds the uncles of the block, which are null unless the block is
+/// passed to a block handler
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumBlock_0_0_6 {
+    pub hash: AscPtr<AscH256>,
+    pub parent_hash: AscPtr<AscH256>,
+    pub uncles_hash: AscPtr<AscH256>,
+    pub author: AscPtr<AscH160>,
+    pub state_root: AscPtr<AscH256>,
+    pub transactions_root: AscPtr<AscH256>,
+    pub receipts_root: AscPtr<AscH256>,
+    pub number: AscPtr<AscBigInt>,
+    pub gas_used: AscPtr<AscBigInt>,
+    pub gas_limit: AscPtr<AscBigInt>,
+    pub timestamp: AscPtr<AscBigInt>,
+    pub difficulty: AscPtr<AscBigInt>,
+    pub total_difficulty: AscPtr<AscBigInt>,
+    pub size: AscPtr<AscBigInt>,
+    pub uncles: AscPtr<AscEthereumUncleArray>,
+}
+
+impl AscIndexId for AscEthereumBlock_0_0_6 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumBlock;
+}
+
+/// The header of an uncle, with what it takes to compute uncle rewards
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumUncle {
+    pub hash: AscPtr<AscH256>,
+    pub parent_hash: AscPtr<AscH256>,
+    pub number: AscPtr<AscBigInt>,
+    pub author: AscPtr<AscH160>,
+    pub timestamp: AscPtr<AscBigInt>,
+    pub gas_used: AscPtr<AscBigInt>,
+    pub gas_limit: AscPtr<AscBigInt>,
+    pub difficulty: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscEthereumUncle {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumUncle;
+}
+
+pub(crate) struct AscEthereumUncleArray(Array<AscPtr<AscEthereumUncle>>);
+
+impl AscType for AscEthereumUncleArray {
+    fn to_asc_bytes(&self) -> Result<Vec<u8>, DeterministicHostError> {
+        self.0.to_asc_bytes()
+    }
+    fn from_asc_bytes(
+        asc_obj: &[u8],
+        api_version: &Version,
+    ) -> Result<Self, DeterministicHostError> {
+        Ok(Self(Array::from_asc_bytes(asc_obj, api_version)?))
+    }
+}
+
+impl AscIndexId for AscEthereumUncleArray {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayEthereumUncle;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscEthereumTransaction_0_0_1 {
@@ -154,6 +217,28 @@ impl AscIndexId for AscEthereumTransaction_0_0_2 {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransaction;
 }
 
+/// Adds the fee fields of EIP-1559 transactions, which are null for
+/// transactions that do not have them
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumTransaction_0_0_6 {
+    pub hash: AscPtr<AscH256>,
+    pub index: AscPtr<AscBigInt>,
+    pub from: AscPtr<AscH160>,
+    pub to: AscPtr<AscH160>,
+    pub value: AscPtr<AscBigInt>,
+    pub gas_limit: AscPtr<AscBigInt>,
+    pub gas_price: AscPtr<AscBigInt>,
+    pub input: AscPtr<Uint8Array>,
+    pub max_fee_per_gas: AscPtr<AscBigInt>,
+    pub max_priority_fee_per_gas: AscPtr<AscBigInt>,
+    pub effective_gas_price: AscPtr<AscBigInt>,
+}
+
+impl AscIndexId for AscEthereumTransaction_0_0_6 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransaction;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscEthereumEvent<T>
@@ -177,6 +262,100 @@ impl AscIndexId for AscEthereumEvent<AscEthereumTransaction_0_0_2> {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumEvent;
 }
 
+/// Adds the receipt of the transaction, which is null unless the event
+/// handler asks for it with `receipt: true`
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumEvent_0_0_6 {
+    pub address: AscPtr<AscAddress>,
+    pub log_index: AscPtr<AscBigInt>,
+    pub transaction_log_index: AscPtr<AscBigInt>,
+    pub log_type: AscPtr<AscString>,
+    pub block: AscPtr<AscEthereumBlock_0_0_6>,
+    pub transaction: AscPtr<AscEthereumTransaction_0_0_6>,
+    pub params: AscPtr<AscLogParamArray>,
+    pub receipt: AscPtr<AscEthereumTransactionReceipt>,
+}
+
+impl AscIndexId for AscEthereumEvent_0_0_6 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumEvent;
+}
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumTransactionReceipt {
+    pub transaction_hash: AscPtr<AscH256>,
+    pub transaction_index: AscPtr<AscBigInt>,
+    pub block_hash: AscPtr<AscH256>,
+    pub block_number: AscPtr<AscBigInt>,
+    pub cumulative_gas_used: AscPtr<AscBigInt>,
+    pub gas_used: AscPtr<AscBigInt>,
+    pub contract_address: AscPtr<AscAddress>,
+    pub logs: AscPtr<AscEthereumLogArray>,
+    pub status: AscPtr<AscBigInt>,
+    pub root: AscPtr<AscH256>,
+    pub logs_bloom: AscPtr<Uint8Array>,
+}
+
+impl AscIndexId for AscEthereumTransactionReceipt {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumTransactionReceipt;
+}
+
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumLog {
+    pub address: AscPtr<AscAddress>,
+    pub topics: AscPtr<AscH256Array>,
+    pub data: AscPtr<Uint8Array>,
+    pub block_hash: AscPtr<AscH256>,
+    pub block_number: AscPtr<AscBigInt>,
+    pub transaction_hash: AscPtr<AscH256>,
+    pub transaction_index: AscPtr<AscBigInt>,
+    pub log_index: AscPtr<AscBigInt>,
+    pub transaction_log_index: AscPtr<AscBigInt>,
+    pub log_type: AscPtr<AscString>,
+}
+
+impl AscIndexId for AscEthereumLog {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumLog;
+}
+
+pub struct AscEthereumLogArray(Array<AscPtr<AscEthereumLog>>);
+
+impl AscType for AscEthereumLogArray {
+    fn to_asc_bytes(&self) -> Result<Vec<u8>, DeterministicHostError> {
+        self.0.to_asc_bytes()
+    }
+    fn from_asc_bytes(
+        asc_obj: &[u8],
+        api_version: &Version,
+    ) -> Result<Self, DeterministicHostError> {
+        Ok(Self(Array::from_asc_bytes(asc_obj, api_version)?))
+    }
+}
+
+impl AscIndexId for AscEthereumLogArray {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayEthereumLog;
+}
+
+pub struct AscH256Array(Array<AscPtr<AscH256>>);
+
+impl AscType for AscH256Array {
+    fn to_asc_bytes(&self) -> Result<Vec<u8>, DeterministicHostError> {
+        self.0.to_asc_bytes()
+    }
+    fn from_asc_bytes(
+        asc_obj: &[u8],
+        api_version: &Version,
+    ) -> Result<Self, DeterministicHostError> {
+        Ok(Self(Array::from_asc_bytes(asc_obj, api_version)?))
+    }
+}
+
+impl AscIndexId for AscH256Array {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::ArrayH256;
+}
+
 #[repr(C)]
 #[derive(AscType)]
 pub(crate) struct AscLogParam {
@@ -217,6 +396,21 @@ impl AscIndexId for AscEthereumCall_0_0_3 {
     const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumCall;
 }
 
+#[repr(C)]
+#[derive(AscType)]
+pub(crate) struct AscEthereumCall_0_0_6 {
+    pub to: AscPtr<AscAddress>,
+    pub from: AscPtr<AscAddress>,
+    pub block: AscPtr<AscEthereumBlock_0_0_6>,
+    pub transaction: AscPtr<AscEthereumTransaction_0_0_6>,
+    pub inputs: AscPtr<AscLogParamArray>,
+    pub outputs: AscPtr<AscLogParamArray>,
+}
+
+impl AscIndexId for AscEthereumCall_0_0_6 {
+    const INDEX_ASC_TYPE_ID: IndexForAscTypeId = IndexForAscTypeId::EthereumCall;
+}
+
 impl ToAscObj<AscEthereumBlock> for EthereumBlockData {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
@@ -244,6 +438,88 @@ impl ToAscObj<AscEthereumBlock> for EthereumBlockData {
     }
 }
 
+impl ToAscObj<AscEthereumBlock_0_0_6> for EthereumBlockData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumBlock_0_0_6, DeterministicHostError> {
+        let AscEthereumBlock {
+            hash,
+            parent_hash,
+            uncles_hash,
+            author,
+            state_root,
+            transactions_root,
+            receipts_root,
+            number,
+            gas_used,
+            gas_limit,
+            timestamp,
+            difficulty,
+            total_difficulty,
+            size,
+        } = ToAscObj::<AscEthereumBlock>::to_asc_obj(self, heap)?;
+        Ok(AscEthereumBlock_0_0_6 {
+            hash,
+            parent_hash,
+            uncles_hash,
+            author,
+            state_root,
+            transactions_root,
+            receipts_root,
+            number,
+            gas_used,
+            gas_limit,
+            timestamp,
+            difficulty,
+            total_difficulty,
+            size,
+            uncles: self
+                .uncles
+                .as_ref()
+                .map(|uncles| asc_new(heap, uncles.as_ref()))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumUncle> for EthereumUncle {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumUncle, DeterministicHostError> {
+        let hash = self
+            .hash
+            .ok_or_else(|| DeterministicHostError(anyhow::anyhow!("uncle without a hash")))?;
+        let number = self
+            .number
+            .ok_or_else(|| DeterministicHostError(anyhow::anyhow!("uncle without a number")))?;
+        Ok(AscEthereumUncle {
+            hash: asc_new(heap, &hash)?,
+            parent_hash: asc_new(heap, &self.parent_hash)?,
+            number: asc_new(heap, &BigInt::from(number))?,
+            author: asc_new(heap, &self.author)?,
+            timestamp: asc_new(heap, &BigInt::from_unsigned_u256(&self.timestamp))?,
+            gas_used: asc_new(heap, &BigInt::from_unsigned_u256(&self.gas_used))?,
+            gas_limit: asc_new(heap, &BigInt::from_unsigned_u256(&self.gas_limit))?,
+            difficulty: asc_new(heap, &BigInt::from_unsigned_u256(&self.difficulty))?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumUncleArray> for Vec<EthereumUncle> {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumUncleArray, DeterministicHostError> {
+        let content = self
+            .iter()
+            .map(|uncle| asc_new(heap, uncle))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(AscEthereumUncleArray(Array::new(&*content, heap)?))
+    }
+}
+
 impl ToAscObj<AscEthereumTransaction_0_0_1> for EthereumTransactionData {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
@@ -285,6 +561,37 @@ impl ToAscObj<AscEthereumTransaction_0_0_2> for EthereumTransactionData {
     }
 }
 
+impl ToAscObj<AscEthereumTransaction_0_0_6> for EthereumTransactionData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumTransaction_0_0_6, DeterministicHostError> {
+        let mut fee = |fee: Option<U256>| {
+            fee.map(|fee| asc_new(heap, &BigInt::from_unsigned_u256(&fee)))
+                .unwrap_or(Ok(AscPtr::null()))
+        };
+        let max_fee_per_gas = fee(self.max_fee_per_gas)?;
+        let max_priority_fee_per_gas = fee(self.max_priority_fee_per_gas)?;
+        let effective_gas_price = fee(self.effective_gas_price)?;
+        Ok(AscEthereumTransaction_0_0_6 {
+            hash: asc_new(heap, &self.hash)?,
+            index: asc_new(heap, &BigInt::from(self.index))?,
+            from: asc_new(heap, &self.from)?,
+            to: self
+                .to
+                .map(|to| asc_new(heap, &to))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            value: asc_new(heap, &BigInt::from_unsigned_u256(&self.value))?,
+            gas_limit: asc_new(heap, &BigInt::from_unsigned_u256(&self.gas_limit))?,
+            gas_price: asc_new(heap, &BigInt::from_unsigned_u256(&self.gas_price))?,
+            input: asc_new(heap, &*self.input.0)?,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            effective_gas_price,
+        })
+    }
+}
+
 impl<T: AscType + AscIndexId> ToAscObj<AscEthereumEvent<T>> for EthereumEventData
 where
     EthereumTransactionData: ToAscObj<T>,
@@ -312,6 +619,146 @@ where
     }
 }
 
+impl ToAscObj<AscEthereumEvent_0_0_6> for EthereumEventData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumEvent_0_0_6, DeterministicHostError> {
+        Ok(AscEthereumEvent_0_0_6 {
+            address: asc_new(heap, &self.address)?,
+            log_index: asc_new(heap, &BigInt::from_unsigned_u256(&self.log_index))?,
+            transaction_log_index: asc_new(
+                heap,
+                &BigInt::from_unsigned_u256(&self.transaction_log_index),
+            )?,
+            log_type: self
+                .log_type
+                .clone()
+                .map(|log_type| asc_new(heap, &log_type))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            block: asc_new(heap, &self.block)?,
+            transaction: asc_new(heap, &self.transaction)?,
+            params: asc_new(heap, &self.params)?,
+            receipt: self
+                .receipt
+                .as_ref()
+                .map(|receipt| asc_new(heap, receipt.as_ref()))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumTransactionReceipt> for TransactionReceipt {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumTransactionReceipt, DeterministicHostError> {
+        Ok(AscEthereumTransactionReceipt {
+            transaction_hash: asc_new(heap, &self.transaction_hash)?,
+            transaction_index: asc_new(heap, &BigInt::from(self.transaction_index))?,
+            block_hash: self
+                .block_hash
+                .map(|hash| asc_new(heap, &hash))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            block_number: self
+                .block_number
+                .map(|number| asc_new(heap, &BigInt::from(number)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            cumulative_gas_used: asc_new(
+                heap,
+                &BigInt::from_unsigned_u256(&self.cumulative_gas_used),
+            )?,
+            gas_used: self
+                .gas_used
+                .map(|gas| asc_new(heap, &BigInt::from_unsigned_u256(&gas)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            contract_address: self
+                .contract_address
+                .map(|address| asc_new(heap, &address))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            logs: asc_new(heap, &self.logs)?,
+            status: self
+                .status
+                .map(|status| asc_new(heap, &BigInt::from(status)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            root: self
+                .root
+                .map(|root| asc_new(heap, &root))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            logs_bloom: asc_new(heap, self.logs_bloom.as_bytes())?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumLog> for Log {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumLog, DeterministicHostError> {
+        let mut number = |number: Option<U256>| {
+            number
+                .map(|number| asc_new(heap, &BigInt::from_unsigned_u256(&number)))
+                .unwrap_or(Ok(AscPtr::null()))
+        };
+        let log_index = number(self.log_index)?;
+        let transaction_log_index = number(self.transaction_log_index)?;
+        Ok(AscEthereumLog {
+            address: asc_new(heap, &self.address)?,
+            topics: asc_new(heap, &self.topics)?,
+            data: asc_new(heap, &*self.data.0)?,
+            block_hash: self
+                .block_hash
+                .map(|hash| asc_new(heap, &hash))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            block_number: self
+                .block_number
+                .map(|number| asc_new(heap, &BigInt::from(number)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            transaction_hash: self
+                .transaction_hash
+                .map(|hash| asc_new(heap, &hash))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            transaction_index: self
+                .transaction_index
+                .map(|index| asc_new(heap, &BigInt::from(index)))
+                .unwrap_or(Ok(AscPtr::null()))?,
+            log_index,
+            transaction_log_index,
+            log_type: self
+                .log_type
+                .as_ref()
+                .map(|log_type| asc_new(heap, log_type))
+                .unwrap_or(Ok(AscPtr::null()))?,
+        })
+    }
+}
+
+impl ToAscObj<AscEthereumLogArray> for Vec<Log> {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumLogArray, DeterministicHostError> {
+        let content = self
+            .iter()
+            .map(|log| asc_new(heap, log))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(AscEthereumLogArray(Array::new(&*content, heap)?))
+    }
+}
+
+impl ToAscObj<AscH256Array> for Vec<H256> {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscH256Array, DeterministicHostError> {
+        let content = self
+            .iter()
+            .map(|hash| asc_new(heap, hash))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(AscH256Array(Array::new(&*content, heap)?))
+    }
+}
+
 impl ToAscObj<AscEthereumCall> for EthereumCallData {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
@@ -343,6 +790,22 @@ impl ToAscObj<AscEthereumCall_0_0_3> for EthereumCallData {
     }
 }
 
+impl ToAscObj<AscEthereumCall_0_0_6> for EthereumCallData {
+    fn to_asc_obj<H: AscHeap + ?Sized>(
+        &self,
+        heap: &mut H,
+    ) -> Result<AscEthereumCall_0_0_6, DeterministicHostError> {
+        Ok(AscEthereumCall_0_0_6 {
+            to: asc_new(heap, &self.to)?,
+            from: asc_new(heap, &self.from)?,
+            block: asc_new(heap, &self.block)?,
+            transaction: asc_new(heap, &self.transaction)?,
+            inputs: asc_new(heap, &self.inputs)?,
+            outputs: asc_new(heap, &self.outputs)?,
+        })
+    }
+}
+
 impl ToAscObj<AscLogParam> for ethabi::LogParam {
     fn to_asc_obj<H: AscHeap + ?Sized>(
         &self,
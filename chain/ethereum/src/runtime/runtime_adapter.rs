@@ -26,21 +26,37 @@ pub struct RuntimeAdapter {
     pub(crate) call_cache: Arc<dyn EthereumCallCache>,
 }
 
+impl RuntimeAdapter {
+    /// An adapter for mappings that do not need to reach an Ethereum node;
+    /// their `ethereum.call`s fail since there is no node to send them to
+    pub fn without_eth_adapters(call_cache: Arc<dyn EthereumCallCache>) -> Self {
+        RuntimeAdapter {
+            eth_adapters: Arc::new(EthereumNetworkAdapters { adapters: vec![] }),
+            call_cache,
+        }
+    }
+}
+
 impl blockchain::RuntimeAdapter<Chain> for RuntimeAdapter {
     fn host_fns(&self, ds: &DataSource) -> Result<Vec<HostFn>, Error> {
         let abis = ds.mapping.abis.clone();
         let call_cache = self.call_cache.cheap_clone();
-        let eth_adapter = self
-            .eth_adapters
-            .cheapest_with(&NodeCapabilities {
-                archive: ds.mapping.requires_archive()?,
-                traces: false,
-            })?
-            .cheap_clone();
+        let capabilities = NodeCapabilities {
+            archive: ds.mapping.requires_archive()?,
+            traces: false,
+        };
+        // Make sure there is an adapter with the required capabilities
+        self.eth_adapters.cheapest_with(&capabilities)?;
+        let eth_adapters = self.eth_adapters.cheap_clone();
 
         let ethereum_call = HostFn {
             name: "ethereum.call",
             func: Arc::new(move |ctx, wasm_ptr| {
+                // Choose the adapter for every call so that we switch to
+                // another provider when the one we used starts failing
+                let eth_adapter = eth_adapters
+                    .cheapest_with(&capabilities)
+                    .map_err(HostExportError::Unknown)?;
                 ethereum_call(&eth_adapter, call_cache.cheap_clone(), ctx, wasm_ptr, &abis)
                     .map(|ptr| ptr.wasm_ptr())
             }),
@@ -66,6 +82,7 @@ fn ethereum_call(
     } else {
         asc_get::<_, AscUnresolvedContractCall, _>(ctx.heap, wasm_ptr.into())?
     };
+    ctx.scan.add_eth_call();
 
     let result = eth_call(
         eth_adapter,
@@ -1,14 +1,36 @@
 use std::sync::Arc;
 
+use itertools::Itertools;
+
 use graph::{
-    blockchain::{block_stream::BlockWithTriggers, BlockPtr},
-    prelude::EthereumCall,
+    blockchain::{
+        block_stream::BlockWithTriggers, Block as _, BlockPtr, DataSource as DataSourceTrait,
+    },
+    data::subgraph::Source,
+    prelude::{
+        ethabi::{self, Contract, Token},
+        o, semver, serde_json, tiny_keccak, EthereumBlock, EthereumBlockWithCalls, EthereumCall,
+        EthereumTransaction, LightEthereumBlock, LightEthereumBlockExt, Link, Logger,
+        SubgraphManifestValidationError,
+    },
 };
 use web3::types::*;
 
 use crate::{
+    adapter::{EthereumBlockFilter, EthereumLogFilter},
     chain::BlockFinality,
-    trigger::{EthereumBlockTriggerType, EthereumTrigger},
+    data_source::{
+        BlockHandlerFilter, DataSource, Mapping, MappingABI, MappingBlockHandler,
+        MappingEventHandler, OversizedLogPolicy,
+    },
+    ethereum_adapter::{
+        calls_from_traces, canonical_traces, check_block_number, check_block_receipts,
+        check_log_block_numbers, parse_block_triggers,
+    },
+    trigger::{
+        BlockLog, EthereumBlockTriggerType, EthereumTransactionData, EthereumTrigger,
+        MappingTrigger,
+    },
 };
 
 #[test]
@@ -40,8 +62,8 @@ fn test_trigger_ordering() {
     call4.transaction_index = 2;
     let call4 = EthereumTrigger::Call(Arc::new(call4));
 
-    fn create_log(tx_index: u64, log_index: u64) -> Arc<Log> {
-        Arc::new(Log {
+    fn create_log(tx_index: u64, log_index: u64) -> BlockLog {
+        BlockLog::new(Arc::new(Log {
             address: H160::default(),
             topics: vec![],
             data: Bytes::default(),
@@ -53,20 +75,21 @@ fn test_trigger_ordering() {
             transaction_log_index: Some(log_index.into()),
             log_type: Some("".into()),
             removed: Some(false),
-        })
+        }))
+        .unwrap()
     }
 
     // Event with transaction_index 1 and log_index 0;
     // should be the first element after sorting
-    let log1 = EthereumTrigger::Log(create_log(1, 0));
+    let log1 = EthereumTrigger::Log(create_log(1, 0), None);
 
     // Event with transaction_index 1 and log_index 1;
     // should be the second element after sorting
-    let log2 = EthereumTrigger::Log(create_log(1, 1));
+    let log2 = EthereumTrigger::Log(create_log(1, 1), None);
 
     // Event with transaction_index 2 and log_index 5;
     // should come after call1 and before call2 after sorting
-    let log3 = EthereumTrigger::Log(create_log(2, 5));
+    let log3 = EthereumTrigger::Log(create_log(2, 5), None);
 
     let triggers = vec![
         // Call triggers; these should be in the order 1, 2, 4, 3 after sorting
@@ -75,7 +98,6 @@ fn test_trigger_ordering() {
         call2.clone(),
         call4.clone(),
         // Block triggers; these should appear at the end after sorting
-        // but with their order unchanged
         block2.clone(),
         block1.clone(),
         // Event triggers
@@ -85,11 +107,1317 @@ fn test_trigger_ordering() {
     ];
 
     // Test that `BlockWithTriggers` sorts the triggers.
-    let block_with_triggers =
-        BlockWithTriggers::<crate::Chain>::new(BlockFinality::Final(Default::default()), triggers);
+    let block_with_triggers = BlockWithTriggers::<crate::Chain>::new(
+        BlockFinality::Final(Default::default(), None),
+        triggers,
+    );
 
     assert_eq!(
         block_with_triggers.trigger_data,
         vec![log1, log2, call1, log3, call2, call4, call3, block2, block1]
     );
 }
+
+#[test]
+fn test_block_trigger_ordering() {
+    let ptr = BlockPtr::from((H256::random(), 1u64));
+    let block = |kind| EthereumTrigger::Block(ptr.clone(), kind);
+    let (low, high) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+    let sorted = vec![
+        block(EthereumBlockTriggerType::Once),
+        block(EthereumBlockTriggerType::Every),
+        block(EthereumBlockTriggerType::WithCallTo(low)),
+        block(EthereumBlockTriggerType::WithCallTo(high)),
+        block(EthereumBlockTriggerType::Polling),
+    ];
+
+    // Block triggers for the same block are found concurrently; however
+    // they arrive, they end up in the same order
+    for triggers in sorted.iter().cloned().permutations(sorted.len()) {
+        let block_with_triggers = BlockWithTriggers::<crate::Chain>::new(
+            BlockFinality::Final(Default::default(), None),
+            triggers,
+        );
+        assert_eq!(sorted, block_with_triggers.trigger_data);
+    }
+}
+
+#[test]
+fn test_trigger_ordering_within_transactions() {
+    fn call(tx_index: u64, trace_address: Vec<usize>) -> EthereumTrigger {
+        let mut call = EthereumCall::default();
+        call.transaction_index = tx_index;
+        call.trace_address = trace_address;
+        EthereumTrigger::Call(Arc::new(call))
+    }
+
+    fn log(tx_index: u64, log_index: u64) -> EthereumTrigger {
+        EthereumTrigger::Log(
+            BlockLog::new(Arc::new(Log {
+                address: H160::default(),
+                topics: vec![],
+                data: Bytes::default(),
+                block_hash: Some(H256::zero()),
+                block_number: Some(U64::zero()),
+                transaction_hash: Some(H256::zero()),
+                transaction_index: Some(tx_index.into()),
+                log_index: Some(log_index.into()),
+                transaction_log_index: Some(log_index.into()),
+                log_type: Some("".into()),
+                removed: Some(false),
+            }))
+            .unwrap(),
+            None,
+        )
+    }
+
+    // Two transactions with several matched calls at different depths,
+    // and logs that were emitted in between those calls
+    let expected = vec![
+        log(0, 0),
+        log(0, 1),
+        log(0, 2),
+        call(0, vec![]),
+        call(0, vec![0]),
+        call(0, vec![0, 0]),
+        call(0, vec![0, 1]),
+        call(0, vec![1]),
+        log(1, 3),
+        log(1, 4),
+        call(1, vec![0, 2]),
+        call(1, vec![0, 10]),
+        call(1, vec![1, 0, 0]),
+        call(1, vec![2]),
+        log(2, 5),
+        EthereumTrigger::Block(
+            BlockPtr::from((H256::zero(), 0u64)),
+            EthereumBlockTriggerType::Every,
+        ),
+    ];
+
+    // The order in which triggers are found must not matter
+    let mut inputs = vec![expected.clone()];
+    inputs.push(expected.iter().rev().cloned().collect());
+    for n in 1..expected.len() {
+        let mut rotated = expected.clone();
+        rotated.rotate_left(n);
+        inputs.push(rotated.clone());
+        rotated.reverse();
+        inputs.push(rotated);
+    }
+    for triggers in inputs {
+        let block_with_triggers = BlockWithTriggers::<crate::Chain>::new(
+            BlockFinality::Final(Default::default(), None),
+            triggers,
+        );
+        assert_eq!(block_with_triggers.trigger_data, expected);
+    }
+
+    // The ordering is antisymmetric and transitive
+    for a in &expected {
+        for b in &expected {
+            assert_eq!(a.cmp(b), b.cmp(a).reverse());
+            for c in &expected {
+                if a <= b && b <= c {
+                    assert!(a <= c);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_degenerate_logs() {
+    fn log(number: Option<u64>, hash: Option<H256>, tx_index: Option<u64>) -> Log {
+        Log {
+            block_hash: hash,
+            block_number: number.map(U64::from),
+            transaction_hash: Some(H256::zero()),
+            transaction_index: tx_index.map(U64::from),
+            log_index: tx_index.map(U256::from),
+            ..Log::default()
+        }
+    }
+    let hash = Some(H256::from_low_u64_be(7));
+
+    // Pending or malformed logs never become triggers
+    for degenerate in vec![
+        log(None, hash, Some(0)),
+        log(Some(7), None, Some(0)),
+        log(Some(7), hash, None),
+        log(Some(3_000_000_000), hash, Some(0)),
+        Log::default(),
+    ] {
+        assert!(BlockLog::new(Arc::new(degenerate)).is_err());
+    }
+
+    // Logs with unusual, but present, positions are ordered like any other
+    let trigger = |tx_index: u64| {
+        EthereumTrigger::Log(
+            BlockLog::new(Arc::new(log(Some(7), hash, Some(tx_index)))).unwrap(),
+            None,
+        )
+    };
+    let mut call = EthereumCall::default();
+    call.transaction_index = 1;
+    let call = EthereumTrigger::Call(Arc::new(call));
+    let block_with_triggers = BlockWithTriggers::<crate::Chain>::new(
+        BlockFinality::Final(Default::default(), None),
+        vec![trigger(u64::MAX), call.clone(), trigger(0)],
+    );
+    assert_eq!(
+        block_with_triggers.trigger_data,
+        vec![trigger(0), call, trigger(u64::MAX)]
+    );
+    assert_eq!(7, block_with_triggers.trigger_data[0].block_number());
+    assert_eq!(
+        hash.unwrap(),
+        block_with_triggers.trigger_data[0].block_hash()
+    );
+}
+
+/// A block with number `number`, a random hash, and no calls
+fn block_with_calls(number: u64) -> EthereumBlockWithCalls {
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(H256::random());
+    block.number = Some(number.into());
+    EthereumBlockWithCalls {
+        ethereum_block: EthereumBlock {
+            block: Arc::new(block),
+            transaction_receipts: vec![],
+            uncles: vec![],
+        },
+        calls: Some(vec![]),
+    }
+}
+
+#[test]
+fn test_once_block_trigger() {
+    let mut filter = EthereumBlockFilter::default();
+    filter.start_blocks.insert(7);
+
+    // The trigger only appears in the start block
+    let block = block_with_calls(7);
+    let ptr = BlockPtr::from(&block.ethereum_block);
+    assert_eq!(
+        parse_block_triggers(filter.clone(), &block),
+        vec![EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Once)]
+    );
+    assert!(parse_block_triggers(filter.clone(), &block_with_calls(8)).is_empty());
+
+    // It is independent of triggering every block
+    filter.trigger_every_block = true;
+    let triggers: Vec<_> = parse_block_triggers(filter, &block)
+        .into_iter()
+        .map(|trigger| match trigger {
+            EthereumTrigger::Block(_, kind) => kind,
+            _ => unreachable!("only block triggers are produced"),
+        })
+        .collect();
+    assert_eq!(
+        triggers,
+        vec![
+            EthereumBlockTriggerType::Once,
+            EthereumBlockTriggerType::Every
+        ]
+    );
+}
+
+#[test]
+fn test_polling_block_trigger() {
+    let mut filter = EthereumBlockFilter::default();
+    // One data source starts at block 10 and polls every 7200 blocks,
+    // another one starts at block 5 and polls every 3 blocks
+    filter.polling_intervals.insert((10, 7200));
+    filter.polling_intervals.insert((5, 3));
+
+    assert_eq!(vec![5, 8, 10, 11, 14], filter.polling_blocks(0, 15));
+    assert_eq!(vec![7208, 7210, 7211], filter.polling_blocks(7206, 7212));
+    assert!(filter.polling_blocks(7212, 7213).is_empty());
+    // Ranges far from any poll are skipped without looking at each block
+    let mut daily = EthereumBlockFilter::default();
+    daily.polling_intervals.insert((10, 7200));
+    assert_eq!(
+        vec![10 + 7200 * 1000],
+        daily.polling_blocks(10 + 7200 * 1000 - 5000, 10 + 7200 * 1000 + 5000)
+    );
+
+    let block = block_with_calls(7210);
+    let ptr = BlockPtr::from(&block.ethereum_block);
+    assert_eq!(
+        parse_block_triggers(filter.clone(), &block),
+        vec![EthereumTrigger::Block(
+            ptr,
+            EthereumBlockTriggerType::Polling
+        )]
+    );
+    assert!(parse_block_triggers(filter.clone(), &block_with_calls(7209)).is_empty());
+    assert!(parse_block_triggers(filter, &block_with_calls(4)).is_empty());
+}
+
+#[test]
+fn test_polling_every_block() {
+    let mut polling = EthereumBlockFilter::default();
+    polling.polling_intervals.insert((5, 1));
+    let mut every = EthereumBlockFilter::default();
+    every.trigger_every_block = true;
+
+    // With an interval of 1, a polling handler runs on every block from
+    // the start block on, just like a handler without a filter
+    assert_eq!((5..=20).collect::<Vec<_>>(), polling.polling_blocks(0, 20));
+    for number in 5..20 {
+        let block = block_with_calls(number);
+        let ptr = BlockPtr::from(&block.ethereum_block);
+        assert_eq!(
+            parse_block_triggers(polling.clone(), &block),
+            vec![EthereumTrigger::Block(
+                ptr.clone(),
+                EthereumBlockTriggerType::Polling
+            )]
+        );
+        assert_eq!(
+            parse_block_triggers(every.clone(), &block),
+            vec![EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Every)]
+        );
+    }
+    assert!(parse_block_triggers(polling.clone(), &block_with_calls(4)).is_empty());
+
+    // Polling triggers come after the calls and logs of their block
+    let block = block_with_calls(7);
+    let ptr = BlockPtr::from(&block.ethereum_block);
+    let call = EthereumTrigger::Call(Arc::new(EthereumCall::default()));
+    let mut triggers = parse_block_triggers(polling, &block);
+    triggers.push(call.clone());
+    triggers.sort();
+    assert_eq!(
+        vec![
+            call,
+            EthereumTrigger::Block(ptr, EthereumBlockTriggerType::Polling)
+        ],
+        triggers
+    );
+}
+
+#[test]
+fn test_polling_across_reorgs() {
+    let mut filter = EthereumBlockFilter::default();
+    filter.polling_intervals.insert((10, 100));
+
+    // The stream processed blocks up to 115, including the poll at 110,
+    // and a reorg replaces blocks 105 to 115. The blocks that replace them
+    // poll at the same numbers, no matter where scanning resumes
+    assert_eq!(vec![110], filter.polling_blocks(100, 115));
+    assert_eq!(vec![110], filter.polling_blocks(105, 115));
+    assert_eq!(vec![110, 210], filter.polling_blocks(105, 215));
+
+    let orphaned = block_with_calls(110);
+    let replacement = block_with_calls(110);
+    assert_ne!(
+        BlockPtr::from(&orphaned.ethereum_block),
+        BlockPtr::from(&replacement.ethereum_block)
+    );
+    for block in &[orphaned, replacement] {
+        assert_eq!(
+            parse_block_triggers(filter.clone(), block),
+            vec![EthereumTrigger::Block(
+                BlockPtr::from(&block.ethereum_block),
+                EthereumBlockTriggerType::Polling
+            )]
+        );
+    }
+
+    // A reorg that only goes back to just after the poll does not poll
+    // again until the next interval
+    assert!(filter.polling_blocks(111, 209).is_empty());
+    assert!(parse_block_triggers(filter, &block_with_calls(109)).is_empty());
+}
+
+#[test]
+fn test_check_block_receipts() {
+    let mut block = LightEthereumBlock::default();
+    let block_hash = H256::random();
+    block.hash = Some(block_hash);
+    block.number = Some(7.into());
+    block.transactions = (0..3)
+        .map(|_| {
+            EthereumTransaction::from(Transaction {
+                hash: H256::random(),
+                ..Default::default()
+            })
+        })
+        .collect();
+    let receipts = |block_hash: Option<H256>| -> Vec<TransactionReceipt> {
+        block
+            .transactions
+            .iter()
+            .map(|tx| TransactionReceipt {
+                transaction_hash: tx.hash,
+                block_hash,
+                ..Default::default()
+            })
+            .collect()
+    };
+
+    let good = receipts(Some(block_hash));
+    assert_eq!(
+        Some(good.clone()),
+        check_block_receipts(&block, Some(good.clone())).unwrap()
+    );
+
+    // Providers that return nothing, or receipts for a pending block, make
+    // us fetch the receipts per transaction
+    assert_eq!(None, check_block_receipts(&block, None).unwrap());
+    assert_eq!(
+        None,
+        check_block_receipts(&block, Some(receipts(None))).unwrap()
+    );
+    // So do incomplete receipts
+    assert_eq!(
+        None,
+        check_block_receipts(&block, Some(good[1..].to_vec())).unwrap()
+    );
+    let mut reordered = good.clone();
+    reordered.swap(0, 1);
+    assert_eq!(None, check_block_receipts(&block, Some(reordered)).unwrap());
+
+    // Receipts from another block mean that the block was reorged out
+    assert!(check_block_receipts(&block, Some(receipts(Some(H256::random())))).is_err());
+}
+
+/// The events of the sequencer inbox of Arbitrum before Nitro, which puts
+/// whole batches of L2 transactions into the data of its logs
+const SEQUENCER_INBOX_ABI: &str = include_str!("../tests/sequencer-inbox.json");
+
+/// A `SequencerBatchDelivered` log from the sequencer inbox in block 7 with
+/// `batch_size` bytes of transactions, encoded the way the contract
+/// encodes it
+fn sequencer_batch_log(batch_size: usize) -> Log {
+    let lengths = vec![Token::Uint(batch_size.into())];
+    let data = ethabi::encode(&[
+        Token::Uint(5_000_123u64.into()),
+        Token::FixedBytes(H256::from_low_u64_be(0xacc).as_bytes().to_vec()),
+        Token::Bytes(vec![0xf8; batch_size]),
+        Token::Array(lengths),
+        Token::Array(vec![Token::Uint(1u64.into()); 5]),
+        Token::Uint(31_337u64.into()),
+        Token::Address(Address::from_low_u64_be(0x5e9)),
+    ]);
+    let event = Contract::load(SEQUENCER_INBOX_ABI.as_bytes())
+        .unwrap()
+        .event("SequencerBatchDelivered")
+        .unwrap()
+        .signature();
+    Log {
+        address: Address::from_low_u64_be(0x1a),
+        topics: vec![
+            event,
+            H256::from_low_u64_be(5_000_000),
+            H256::from_low_u64_be(0xbcc),
+        ],
+        data: Bytes(data),
+        block_hash: Some(H256::from_low_u64_be(4)),
+        block_number: Some(7.into()),
+        transaction_hash: Some(H256::from_low_u64_be(3)),
+        transaction_index: Some(0.into()),
+        log_index: Some(0.into()),
+        ..Log::default()
+    }
+}
+
+#[test]
+fn test_log_size_check() {
+    use crate::log_size::{check, LogFit};
+
+    const MAX: usize = 1024 * 1024;
+
+    let contract = Contract::load(SEQUENCER_INBOX_ABI.as_bytes()).unwrap();
+    let batch = contract.event("SequencerBatchDelivered").unwrap();
+    let from_origin = contract.event("SequencerBatchDeliveredFromOrigin").unwrap();
+
+    // The head of the data has one word each for `newMessageCount`,
+    // `afterAcc`, `seqBatchIndex` and `sequencer`, and one word for the
+    // offset of each of `transactions`, `lengths` and `sectionsMetadata`
+    let head = 7 * 32;
+
+    let small = sequencer_batch_log(1024);
+    assert_eq!(LogFit::Fits, check(batch, &small, MAX));
+
+    let large = sequencer_batch_log(4 * 1024 * 1024);
+    assert_eq!(
+        LogFit::Oversized {
+            size: large.data.0.len(),
+            limit: head + MAX
+        },
+        check(batch, &large, MAX)
+    );
+
+    // The same batch checked against an event whose layout only has static
+    // parameters, like a handler for an event with the same signature but
+    // a different ABI would do
+    assert_eq!(
+        LogFit::Oversized {
+            size: large.data.0.len(),
+            limit: 3 * 32
+        },
+        check(from_origin, &large, MAX)
+    );
+
+    let mut missing_topic = small.clone();
+    missing_topic.topics.pop();
+    assert!(matches!(
+        check(batch, &missing_topic, MAX),
+        LogFit::Mismatch(_)
+    ));
+
+    let mut truncated = small;
+    truncated.data.0.truncate(head - 1);
+    assert!(matches!(check(batch, &truncated, MAX), LogFit::Mismatch(_)));
+}
+
+#[test]
+fn test_oversized_log_policy() {
+    const BATCH_EVENT: &str = "SequencerBatchDelivered(indexed uint256,indexed bytes32,uint256,bytes32,bytes,uint256[],uint256[],uint256,address)";
+
+    let logger = Logger::root(graph::slog::Discard, o!());
+
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(H256::from_low_u64_be(4));
+    block.number = Some(7.into());
+    block.transactions = vec![Transaction {
+        hash: H256::from_low_u64_be(3),
+        ..Transaction::default()
+    }
+    .into()];
+    let block = Arc::new(BlockFinality::Final(Arc::new(block), None));
+
+    // A data source that handles the batches of the sequencer inbox
+    let data_source = |api_version: semver::Version, policy: Option<OversizedLogPolicy>| {
+        let abi = Arc::new(MappingABI {
+            name: "SequencerInbox".to_string(),
+            contract: Contract::load(SEQUENCER_INBOX_ABI.as_bytes()).unwrap(),
+        });
+        let mut ds = transfer_data_source(None);
+        ds.source.abi = "SequencerInbox".to_string();
+        ds.contract_abi = abi.clone();
+        ds.mapping.abis = vec![abi];
+        ds.mapping.api_version = api_version;
+        ds.mapping.oversized_logs = policy;
+        ds.mapping.event_handlers[0].event = BATCH_EVENT.to_string();
+        ds.mapping.event_handlers[0].handler = "handleBatch".to_string();
+        ds
+    };
+    let run = |ds: &DataSource, batch_size: usize| {
+        let log = sequencer_batch_log(batch_size);
+        let trigger = EthereumTrigger::Log(BlockLog::new(Arc::new(log)).unwrap(), None);
+        DataSourceTrait::match_and_decode(ds, &trigger, block.clone(), &logger)
+    };
+
+    let v5 = semver::Version::new(0, 0, 5);
+    let v6 = semver::Version::new(0, 0, 6);
+    // Larger than the default `GRAPH_ETHEREUM_MAX_LOG_DYNAMIC_DATA_SIZE`
+    let oversized = 2 * 1024 * 1024;
+
+    // Logs that fit are decoded regardless of the policy
+    for policy in &[
+        None,
+        Some(OversizedLogPolicy::Skip),
+        Some(OversizedLogPolicy::Fail),
+    ] {
+        for version in &[v5.clone(), v6.clone()] {
+            let ds = data_source(version.clone(), *policy);
+            assert!(DataSourceTrait::validate(&ds).is_empty());
+            assert!(run(&ds, 1024).unwrap().is_some());
+        }
+    }
+
+    // Without a policy, mappings before 0.0.6 still decode oversized logs
+    // and later mappings skip them
+    assert!(run(&data_source(v5.clone(), None), oversized)
+        .unwrap()
+        .is_some());
+    assert!(run(&data_source(v6.clone(), None), oversized)
+        .unwrap()
+        .is_none());
+
+    // An explicit policy applies to all mappings
+    for version in &[v5, v6] {
+        let skip = data_source(version.clone(), Some(OversizedLogPolicy::Skip));
+        assert!(run(&skip, oversized).unwrap().is_none());
+        let fail = data_source(version.clone(), Some(OversizedLogPolicy::Fail));
+        let err = run(&fail, oversized).unwrap_err();
+        assert!(err.to_string().contains("handleBatch"), "{}", err);
+    }
+}
+
+#[test]
+fn test_block_uncles() {
+    use graph::components::ethereum::uncles_complete;
+    use graph::prelude::{serde_json, EthereumUncle};
+
+    // A Byzantium block, where the block reward is 3 ETH, that includes
+    // uncles at distance 1 and 2
+    let reward = U256::from(3_000_000_000_000_000_000u64);
+    let uncle = |number: u64| {
+        let mut uncle = EthereumUncle::default();
+        uncle.hash = Some(H256::from_low_u64_be(number));
+        uncle.number = Some(number.into());
+        uncle.author = Address::from_low_u64_be(number);
+        uncle
+    };
+    let uncles = vec![uncle(4_999_999), uncle(4_999_998)];
+    let mut light_block = LightEthereumBlock::default();
+    light_block.hash = Some(H256::from_low_u64_be(5_000_000));
+    light_block.number = Some(5_000_000.into());
+    light_block.uncles = uncles.iter().map(|uncle| uncle.hash.unwrap()).collect();
+    let block = EthereumBlock {
+        block: Arc::new(light_block),
+        transaction_receipts: vec![],
+        uncles: uncles.clone(),
+    };
+
+    assert!(uncles_complete(&block.block, &uncles));
+    assert_eq!(Some(uncles.as_slice()), block.complete_uncles());
+    assert!(!uncles_complete(&block.block, &uncles[..1]));
+    let reversed: Vec<_> = uncles.iter().rev().cloned().collect();
+    assert!(!uncles_complete(&block.block, &reversed));
+
+    // Uncles survive the trip through the chain store, and blocks that
+    // were stored before we kept uncles have none
+    let json = serde_json::to_value(&block).unwrap();
+    let stored: EthereumBlock = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!(block, stored);
+    let mut old_json = json;
+    old_json.as_object_mut().unwrap().remove("uncles");
+    let old: EthereumBlock = serde_json::from_value(old_json).unwrap();
+    assert!(old.uncles.is_empty());
+    assert_eq!(None, old.complete_uncles());
+
+    // What a mapping computes from `block.uncles`
+    let number = block.block.number.unwrap().as_u64();
+    let uncle_rewards: Vec<U256> = stored
+        .uncles
+        .iter()
+        .map(|uncle| reward * (uncle.number.unwrap().as_u64() + 8 - number) / 8)
+        .collect();
+    let nephew_reward = reward / 32 * stored.uncles.len();
+    assert_eq!(
+        vec![
+            U256::from(2_625_000_000_000_000_000u64),
+            U256::from(2_250_000_000_000_000_000u64)
+        ],
+        uncle_rewards
+    );
+    assert_eq!(U256::from(187_500_000_000_000_000u64), nephew_reward);
+}
+
+#[test]
+fn test_peer_block_verification() {
+    use crate::peer_block_cache::verify;
+    use std::str::FromStr;
+
+    fn h256(s: &str) -> H256 {
+        H256::from_str(s).unwrap()
+    }
+
+    // Block 1 on mainnet
+    let hash = h256("88e96d4537bea4d9c05d12549907b32561d3bf31f45aae734cdc119f13406cb6");
+    let empty_root = h256("56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421");
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(hash);
+    block.parent_hash = h256("d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3");
+    block.uncles_hash = h256("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347");
+    block.author = Address::from_str("05a56e2d52c817161883f50c441c3228cfe54d9f").unwrap();
+    block.state_root = h256("d67e4d450343046425ae4271474353857ab860dbc0a1dde64b41b5cd3a532bf3");
+    block.transactions_root = empty_root;
+    block.receipts_root = empty_root;
+    block.logs_bloom = Some(H2048::zero());
+    block.difficulty = U256::from(0x3ff800000u64);
+    block.number = Some(1.into());
+    block.gas_limit = U256::from(5000);
+    block.gas_used = U256::zero();
+    block.timestamp = U256::from(0x55ba4224);
+    block.extra_data =
+        Bytes(hex::decode("476574682f76312e302e302f6c696e75782f676f312e342e32").unwrap());
+    block.mix_hash = Some(h256(
+        "969b900de27b6ac6a67742365dd65f55a0526c41fd18e1b16f1a1215c2e66f59",
+    ));
+    block.nonce = Some(H64::from_str("539bd4979fef1ec4").unwrap());
+    assert!(verify(&block, hash).is_ok());
+
+    // The block is not the one we asked for
+    assert!(verify(&block, H256::from_low_u64_be(8)).is_err());
+
+    // The block has no number
+    let mut numberless = block.clone();
+    numberless.number = None;
+    assert!(verify(&numberless, hash).is_err());
+
+    // The peer sent the right hash, but changed the header
+    let mut tampered = block.clone();
+    tampered.timestamp = U256::from(0x55ba4225);
+    assert!(verify(&tampered, hash).is_err());
+
+    // The header lacks fields we need to compute its hash
+    let mut sealed = block.clone();
+    sealed.mix_hash = None;
+    assert!(verify(&sealed, hash).is_err());
+
+    // One of the transactions belongs to a different block
+    let mut tx = Transaction::default();
+    tx.block_hash = Some(H256::from_low_u64_be(8));
+    let mut mixed = block.clone();
+    mixed.transactions = vec![tx.into()];
+    assert!(verify(&mixed, hash).is_err());
+
+    // A block after London whose transactions were cached without their
+    // type by an older version
+    let mut tx = Transaction::default();
+    tx.block_hash = Some(hash);
+    let mut untyped = block.clone();
+    untyped.base_fee_per_gas = Some(U256::from(7));
+    untyped.transactions = vec![tx.into()];
+    let err = verify(&untyped, hash).unwrap_err();
+    assert!(err.to_string().contains("transaction types"), "{}", err);
+}
+
+#[test]
+fn test_trace_ordering() {
+    fn trace(block: u64, tx_index: Option<usize>, trace_address: Vec<usize>) -> Trace {
+        Trace {
+            action: Action::Call(Call {
+                from: Address::from_low_u64_be(1),
+                to: Address::from_low_u64_be(2),
+                value: U256::zero(),
+                gas: U256::from(21000),
+                input: Bytes(vec![0xde, 0xad, 0xbe, 0xef]),
+                call_type: CallType::Call,
+            }),
+            result: Some(Res::Call(CallResult {
+                gas_used: U256::from(21000),
+                output: Bytes::default(),
+            })),
+            trace_address,
+            subtraces: 0,
+            transaction_position: tx_index,
+            transaction_hash: tx_index.map(|index| H256::from_low_u64_be(index as u64)),
+            block_number: block,
+            block_hash: H256::from_low_u64_be(block),
+            action_type: ActionType::Call,
+            error: None,
+        }
+    }
+
+    fn triggers(traces: Vec<Trace>) -> Vec<EthereumTrigger> {
+        calls_from_traces(traces, 7, H256::from_low_u64_be(7))
+            .unwrap()
+            .into_iter()
+            .map(|call| EthereumTrigger::Call(Arc::new(call)))
+            .collect()
+    }
+
+    let canonical = vec![
+        trace(7, None, vec![]),
+        trace(7, Some(0), vec![]),
+        trace(7, Some(0), vec![0]),
+        trace(7, Some(0), vec![0, 0]),
+        trace(7, Some(0), vec![0, 1]),
+        trace(7, Some(0), vec![1]),
+        trace(7, Some(1), vec![0, 2]),
+        trace(7, Some(1), vec![0, 10]),
+        trace(7, Some(1), vec![1, 0, 0]),
+        trace(7, Some(3), vec![]),
+    ];
+    let expected = triggers(canonical.clone());
+    assert_eq!(9, expected.len());
+
+    // The order in which the provider returns traces must not matter
+    let mut inputs = vec![canonical.iter().rev().cloned().collect::<Vec<_>>()];
+    for n in 1..canonical.len() {
+        let mut rotated = canonical.clone();
+        rotated.rotate_left(n);
+        inputs.push(rotated.clone());
+        rotated.reverse();
+        inputs.push(rotated);
+    }
+    // Interleave the two halves
+    let (left, right) = canonical.split_at(canonical.len() / 2);
+    inputs.push(right.iter().interleave(left.iter()).cloned().collect());
+    for traces in inputs {
+        assert_eq!(canonical, canonical_traces(traces.clone(), 7, 7).unwrap());
+        assert_eq!(expected, triggers(traces));
+    }
+
+    // Traces for a range of blocks are ordered by block first
+    let range = vec![trace(9, Some(0), vec![]), trace(8, Some(4), vec![1])];
+    assert_eq!(
+        vec![trace(8, Some(4), vec![1]), trace(9, Some(0), vec![])],
+        canonical_traces(range, 8, 9).unwrap()
+    );
+
+    // Traces for a block other than the one we asked for are rejected
+    assert!(calls_from_traces(canonical.clone(), 7, H256::from_low_u64_be(8)).is_err());
+    assert!(calls_from_traces(canonical.clone(), 8, H256::from_low_u64_be(7)).is_err());
+
+    // Responses that mix traces from different blocks are rejected
+    let mut mixed = canonical.clone();
+    mixed[4].block_hash = H256::from_low_u64_be(8);
+    assert!(canonical_traces(mixed.clone(), 7, 7).is_err());
+    assert!(calls_from_traces(mixed, 7, H256::from_low_u64_be(7)).is_err());
+    let mut mixed = canonical;
+    mixed.push(trace(8, Some(0), vec![]));
+    assert!(canonical_traces(mixed, 7, 7).is_err());
+}
+
+#[test]
+fn test_block_number_range() {
+    let mut block = LightEthereumBlock::default();
+    block.number = Some(7.into());
+    assert!(check_block_number(&block).is_ok());
+
+    // Pending blocks have no number
+    block.number = None;
+    assert!(check_block_number(&block).is_ok());
+
+    block.number = Some((i32::MAX as u64 - 1).into());
+    assert!(check_block_number(&block).is_ok());
+    // The store uses `BLOCK_NUMBER_MAX` to mark current entity versions
+    block.number = Some((i32::MAX as u64).into());
+    assert!(check_block_number(&block).is_err());
+    block.number = Some(3_000_000_000u64.into());
+    assert!(check_block_number(&block).is_err());
+
+    let log = |number: Option<u64>| Log {
+        block_number: number.map(U64::from),
+        ..Log::default()
+    };
+    assert!(check_log_block_numbers(&[log(Some(7)), log(Some(9))], 7, 9).is_ok());
+    assert!(check_log_block_numbers(&[log(Some(7)), log(Some(10))], 7, 9).is_err());
+    assert!(check_log_block_numbers(&[log(Some(3_000_000_000))], 7, 9).is_err());
+    assert!(check_log_block_numbers(&[log(None)], 7, 9).is_err());
+}
+
+const TRANSFER_ABI: &str = r#"[{
+    "anonymous": false,
+    "inputs": [
+        { "indexed": true, "name": "from", "type": "address" },
+        { "indexed": true, "name": "to", "type": "address" },
+        { "indexed": false, "name": "value", "type": "uint256" }
+    ],
+    "name": "Transfer",
+    "type": "event"
+}]"#;
+
+/// A data source that handles `Transfer` events of the contract at
+/// `address`, or of any contract if `address` is `None`
+fn transfer_data_source(address: Option<Address>) -> DataSource {
+    let abi = Arc::new(MappingABI {
+        name: "Token".to_string(),
+        contract: Contract::load(TRANSFER_ABI.as_bytes()).unwrap(),
+    });
+    DataSource {
+        kind: "ethereum/contract".to_string(),
+        network: Some("mainnet".to_string()),
+        name: "Token".to_string(),
+        source: Source {
+            address,
+            abi: "Token".to_string(),
+            start_block: 0,
+            end_block: None,
+        },
+        mapping: Mapping {
+            kind: "ethereum/events".to_string(),
+            api_version: semver::Version::new(0, 0, 5),
+            language: "wasm/assemblyscript".to_string(),
+            entities: vec![],
+            abis: vec![abi.clone()],
+            block_handlers: vec![],
+            call_handlers: vec![],
+            event_handlers: vec![MappingEventHandler {
+                event: "Transfer(indexed address,indexed address,uint256)".to_string(),
+                topic0: None,
+                handler: "handleTransfer".to_string(),
+                receipt: false,
+                topic1: None,
+                topic2: None,
+                topic3: None,
+                anonymous: false,
+            }],
+            oversized_logs: None,
+            runtime: Arc::new(vec![]),
+            link: Link {
+                link: "link".to_string(),
+            },
+        },
+        context: Arc::new(None),
+        creation_block: None,
+        first_block: 0,
+        once_handlers_ran: None,
+        contract_abi: abi,
+    }
+}
+
+#[test]
+fn test_wildcard_data_source() {
+    let logger = Logger::root(graph::slog::Discard, o!());
+    let token = Address::from_low_u64_be(1);
+    let other = Address::from_low_u64_be(2);
+    let tx_hash = H256::from_low_u64_be(3);
+
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(H256::from_low_u64_be(4));
+    block.number = Some(7.into());
+    block.transactions = vec![Transaction {
+        hash: tx_hash,
+        ..Transaction::default()
+    }
+    .into()];
+    let block = Arc::new(BlockFinality::Final(Arc::new(block), None));
+
+    let transfer = |address: Address| {
+        let log = Log {
+            address,
+            topics: vec![
+                H256::from(tiny_keccak::keccak256(
+                    "Transfer(address,address,uint256)".as_bytes(),
+                )),
+                H256::from(Address::from_low_u64_be(10)),
+                H256::from(Address::from_low_u64_be(11)),
+            ],
+            data: Bytes(ethabi::encode(&[Token::Uint(100u64.into())])),
+            block_hash: Some(H256::from_low_u64_be(4)),
+            block_number: Some(7.into()),
+            transaction_hash: Some(tx_hash),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            ..Log::default()
+        };
+        EthereumTrigger::Log(BlockLog::new(Arc::new(log)).unwrap(), None)
+    };
+    let matches = |ds: &DataSource, address: Address| {
+        DataSourceTrait::match_and_decode(ds, &transfer(address), block.clone(), &logger)
+            .unwrap()
+            .is_some()
+    };
+
+    // A data source with an address only handles events of its contract
+    let normal = transfer_data_source(Some(token));
+    assert!(DataSourceTrait::validate(&normal).is_empty());
+    assert!(matches(&normal, token));
+    assert!(!matches(&normal, other));
+
+    // A wildcard data source handles events of all contracts
+    let wildcard = transfer_data_source(None);
+    assert!(DataSourceTrait::validate(&wildcard).is_empty());
+    assert!(matches(&wildcard, token));
+    assert!(matches(&wildcard, other));
+
+    // The logs for a wildcard data source are only filtered by event
+    let filter = EthereumLogFilter::from_data_sources(vec![&wildcard]);
+    let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(1, filters.len());
+    assert!(filters[0].contracts.is_empty());
+    let filter = EthereumLogFilter::from_data_sources(vec![&normal]);
+    let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(vec![token], filters[0].contracts);
+
+    // The request for the wildcard data source also returns the logs that
+    // the data source with an address wants
+    let filter = EthereumLogFilter::from_data_sources(vec![&normal, &wildcard]);
+    let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(1, filters.len());
+    assert!(filters[0].contracts.is_empty());
+
+    // Only data sources with an address can have block handlers
+    let mut with_block_handler = transfer_data_source(None);
+    with_block_handler
+        .mapping
+        .block_handlers
+        .push(MappingBlockHandler {
+            handler: "handleBlock".to_string(),
+            filter: None,
+        });
+    assert!(matches!(
+        DataSourceTrait::validate(&with_block_handler).as_slice(),
+        [SubgraphManifestValidationError::SourceAddressRequired]
+    ));
+}
+
+/// Whether `ds` runs its `once` block handler in block `number`
+fn runs_once_at(ds: &DataSource, number: u64) -> bool {
+    let logger = Logger::root(graph::slog::Discard, o!());
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(H256::from_low_u64_be(number));
+    block.number = Some(number.into());
+    let block = Arc::new(BlockFinality::Final(Arc::new(block), None));
+    let trigger = EthereumTrigger::Block(block.ptr(), EthereumBlockTriggerType::Once);
+    DataSourceTrait::match_and_decode(ds, &trigger, block, &logger)
+        .unwrap()
+        .is_some()
+}
+
+#[test]
+fn test_once_block_of_grafted_deployment() {
+    // A data source without a `startBlock`
+    let mut ds = transfer_data_source(Some(Address::from_low_u64_be(1)));
+    ds.mapping.block_handlers.push(MappingBlockHandler {
+        handler: "handleInit".to_string(),
+        filter: Some(BlockHandlerFilter::Once),
+    });
+
+    // A deployment that is not grafted processes block 0 first
+    let filter = EthereumBlockFilter::from_data_sources(vec![&ds]);
+    assert_eq!(vec![0], filter.start_blocks.into_iter().collect::<Vec<_>>());
+    assert!(runs_once_at(&ds, 0));
+
+    // A deployment grafted onto block 100 never sees block 0, and runs the
+    // handler in the first block it processes instead
+    ds.set_first_block(101);
+    let filter = EthereumBlockFilter::from_data_sources(vec![&ds]);
+    assert_eq!(
+        vec![101],
+        filter.start_blocks.into_iter().collect::<Vec<_>>()
+    );
+    assert!(!runs_once_at(&ds, 0));
+    assert!(runs_once_at(&ds, 101));
+    assert!(!runs_once_at(&ds, 102));
+
+    // A `startBlock` after the graft block is still respected
+    ds.source.start_block = 150;
+    let filter = EthereumBlockFilter::from_data_sources(vec![&ds]);
+    assert_eq!(
+        vec![150],
+        filter.start_blocks.into_iter().collect::<Vec<_>>()
+    );
+    assert!(!runs_once_at(&ds, 101));
+    assert!(runs_once_at(&ds, 150));
+}
+
+#[test]
+fn test_once_block_after_restart() {
+    let init_data_source = || {
+        let mut ds = transfer_data_source(Some(Address::from_low_u64_be(1)));
+        ds.source.start_block = 10;
+        ds.mapping.block_handlers.push(MappingBlockHandler {
+            handler: "handleInit".to_string(),
+            filter: Some(BlockHandlerFilter::Once),
+        });
+        ds
+    };
+
+    // The handler ran in block 10, and the deployment restarts after
+    // processing block 20
+    let mut ds = init_data_source();
+    ds.set_first_block(21);
+    ds.set_once_handlers_ran(10);
+    assert_eq!(Some(10), ds.once_handler_block());
+    let filter = EthereumBlockFilter::from_data_sources(vec![&ds]);
+    assert_eq!(
+        vec![10],
+        filter.start_blocks.into_iter().collect::<Vec<_>>()
+    );
+    assert!(!runs_once_at(&ds, 21));
+    // If block 10 gets reverted and processed again, the handler runs again
+    assert!(runs_once_at(&ds, 10));
+
+    // Without a record that the handler ran, for example because the data
+    // source was added to the deployment when its mappings were reloaded,
+    // it runs in the first block after the restart
+    let mut ds = init_data_source();
+    ds.set_first_block(21);
+    assert_eq!(Some(21), ds.once_handler_block());
+    assert!(!runs_once_at(&ds, 10));
+    assert!(runs_once_at(&ds, 21));
+
+    // Data sources without a `once` handler have no once block
+    let ds = transfer_data_source(Some(Address::from_low_u64_be(1)));
+    assert_eq!(None, ds.once_handler_block());
+}
+
+#[test]
+fn test_anonymous_events() {
+    let logger = Logger::root(graph::slog::Discard, o!());
+    let token = Address::from_low_u64_be(1);
+    let tx_hash = H256::from_low_u64_be(3);
+
+    let mut block = LightEthereumBlock::default();
+    block.hash = Some(H256::from_low_u64_be(4));
+    block.number = Some(7.into());
+    block.transactions = vec![Transaction {
+        hash: tx_hash,
+        ..Transaction::default()
+    }
+    .into()];
+    let block = Arc::new(BlockFinality::Final(Arc::new(block), None));
+
+    let anonymous_data_source = |address: Option<Address>| {
+        let mut ds = transfer_data_source(address);
+        let abi = Arc::new(MappingABI {
+            name: "Token".to_string(),
+            contract: Contract::load(
+                TRANSFER_ABI
+                    .replace(r#""anonymous": false"#, r#""anonymous": true"#)
+                    .as_bytes(),
+            )
+            .unwrap(),
+        });
+        ds.mapping.abis = vec![abi.clone()];
+        ds.contract_abi = abi;
+        ds.mapping.event_handlers[0].anonymous = true;
+        ds
+    };
+    let log = |topics: Vec<H256>, data: Vec<u8>| {
+        let log = Log {
+            address: token,
+            topics,
+            data: Bytes(data),
+            block_hash: Some(H256::from_low_u64_be(4)),
+            block_number: Some(7.into()),
+            transaction_hash: Some(tx_hash),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            ..Log::default()
+        };
+        EthereumTrigger::Log(BlockLog::new(Arc::new(log)).unwrap(), None)
+    };
+    let value = ethabi::encode(&[Token::Uint(100u64.into())]);
+    let from = H256::from(Address::from_low_u64_be(10));
+    let to = H256::from(Address::from_low_u64_be(11));
+
+    let ds = anonymous_data_source(Some(token));
+    assert!(DataSourceTrait::validate(&ds).is_empty());
+
+    // The log of an anonymous event has no signature topic, and the
+    // handler still shows the signature from the manifest
+    let trigger = DataSourceTrait::match_and_decode(
+        &ds,
+        &log(vec![from, to], value.clone()),
+        block.clone(),
+        &logger,
+    )
+    .unwrap();
+    match trigger {
+        Some(MappingTrigger::Log {
+            handler, params, ..
+        }) => {
+            assert_eq!(
+                "Transfer(indexed address,indexed address,uint256)",
+                handler.event
+            );
+            assert_eq!(3, params.len());
+        }
+        _ => panic!("the anonymous event must be decoded"),
+    }
+
+    // Logs of the contract that do not decode with the event are skipped
+    // instead of failing the block
+    for (topics, data) in vec![
+        (vec![], vec![]),
+        (vec![from], value.clone()),
+        (vec![H256::zero(), from, to], value.clone()),
+    ] {
+        assert!(
+            DataSourceTrait::match_and_decode(&ds, &log(topics, data), block.clone(), &logger)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    // We ask for all logs of contracts with anonymous events
+    let filter = EthereumLogFilter::from_data_sources(vec![&ds]);
+    assert!(filter.matches(&Log {
+        address: token,
+        ..Log::default()
+    }));
+    let filters: Vec<_> = filter.eth_get_logs_filters().collect();
+    assert_eq!(1, filters.len());
+    assert_eq!(vec![token], filters[0].contracts);
+    assert!(filters[0].event_signatures.is_empty());
+
+    // That request also covers the other events of the contract
+    let filter =
+        EthereumLogFilter::from_data_sources(vec![&ds, &transfer_data_source(Some(token))]);
+    assert_eq!(1, filter.eth_get_logs_filters().count());
+
+    // Anonymous events need an address, and handlers must agree with the
+    // ABI about whether the event is anonymous
+    assert!(matches!(
+        DataSourceTrait::validate(&anonymous_data_source(None)).as_slice(),
+        [SubgraphManifestValidationError::DataSourceEventHandlerAnonymousWithoutAddress(_)]
+    ));
+    let mut unmarked = anonymous_data_source(Some(token));
+    unmarked.mapping.event_handlers[0].anonymous = false;
+    assert!(matches!(
+        DataSourceTrait::validate(&unmarked).as_slice(),
+        [SubgraphManifestValidationError::DataSourceEventHandlerAnonymousMismatch(_, _, true)]
+    ));
+    // Manifests that match an anonymous event through an explicit
+    // `topic0` keep working
+    let mut unmarked = anonymous_data_source(Some(token));
+    unmarked.mapping.event_handlers[0].anonymous = false;
+    unmarked.mapping.event_handlers[0].topic0 = Some(from);
+    assert!(DataSourceTrait::validate(&unmarked).is_empty());
+    let mut marked = transfer_data_source(Some(token));
+    marked.mapping.event_handlers[0].anonymous = true;
+    assert!(matches!(
+        DataSourceTrait::validate(&marked).as_slice(),
+        [SubgraphManifestValidationError::DataSourceEventHandlerAnonymousMismatch(_, _, false)]
+    ));
+}
+
+#[test]
+fn test_eip1559_transaction() {
+    // A type 2 transaction as `eth_getBlockByHash` returns it
+    let json = serde_json::json!({
+        "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000007",
+        "blockNumber": "0xc5043f",
+        "from": "0x0000000000000000000000000000000000000001",
+        "gas": "0x5208",
+        "gasPrice": "0x2540be400",
+        "maxFeePerGas": "0x2e90edd000",
+        "maxPriorityFeePerGas": "0x3b9aca00",
+        "hash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+        "input": "0x",
+        "nonce": "0x1",
+        "to": "0x0000000000000000000000000000000000000002",
+        "transactionIndex": "0x0",
+        "value": "0x0",
+        "type": "0x2",
+        "accessList": [],
+        "chainId": "0x1",
+        "v": "0x0",
+        "r": "0x1",
+        "s": "0x1"
+    });
+    let tx: EthereumTransaction = serde_json::from_value(json).unwrap();
+    assert_eq!(Some(U64::from(2)), tx.transaction_type);
+    assert_eq!(Some(U256::from(200_000_000_000u64)), tx.max_fee_per_gas);
+    assert_eq!(
+        Some(U256::from(1_000_000_000u64)),
+        tx.max_priority_fee_per_gas
+    );
+    assert_eq!(U256::from(10_000_000_000u64), tx.gas_price);
+
+    // The fee fields survive the trip through the block cache
+    let stored: EthereumTransaction =
+        serde_json::from_value(serde_json::to_value(&tx).unwrap()).unwrap();
+    assert_eq!(tx, stored);
+
+    // Below the cap, the transaction pays the base fee plus its tip
+    let data = EthereumTransactionData::new(&tx, Some(U256::from(9_000_000_000u64)));
+    assert_eq!(tx.max_fee_per_gas, data.max_fee_per_gas);
+    assert_eq!(tx.max_priority_fee_per_gas, data.max_priority_fee_per_gas);
+    assert_eq!(
+        Some(U256::from(10_000_000_000u64)),
+        data.effective_gas_price
+    );
+
+    // Above the cap, it pays the max fee
+    let data = EthereumTransactionData::new(&tx, Some(U256::from(250_000_000_000u64)));
+    assert_eq!(
+        Some(U256::from(200_000_000_000u64)),
+        data.effective_gas_price
+    );
+
+    // Without the base fee, we can not tell what it paid
+    let data = EthereumTransactionData::new(&tx, None);
+    assert_eq!(None, data.effective_gas_price);
+
+    // Legacy transactions pay their gas price and have no fee fields
+    let legacy = EthereumTransaction::from(Transaction {
+        transaction_index: Some(0.into()),
+        gas_price: U256::from(7),
+        ..Transaction::default()
+    });
+    let data = EthereumTransactionData::new(&legacy, Some(U256::from(5)));
+    assert_eq!(None, data.max_fee_per_gas);
+    assert_eq!(None, data.max_priority_fee_per_gas);
+    assert_eq!(Some(U256::from(7)), data.effective_gas_price);
+
+    // Blocks cached before we kept transaction types get fetched again
+    let mut block = LightEthereumBlock::default();
+    block.transactions = vec![legacy, tx];
+    assert!(block.has_transaction_types());
+    block.base_fee_per_gas = Some(U256::from(5));
+    assert!(!block.has_transaction_types());
+    block.transactions[0].transaction_type = Some(U64::from(0));
+    assert!(block.has_transaction_types());
+}
+
+#[test]
+fn test_eip1559_gas_price_across_providers() {
+    let base_fee = U256::from(9_000_000_000u64);
+    let effective_gas_price = U256::from(10_000_000_000u64);
+    let tx = |gas_price: Option<&str>| {
+        let mut json = serde_json::json!({
+            "blockHash": "0x0000000000000000000000000000000000000000000000000000000000000007",
+            "blockNumber": "0xc5043f",
+            "from": "0x0000000000000000000000000000000000000001",
+            "gas": "0x5208",
+            "maxFeePerGas": "0x2e90edd000",
+            "maxPriorityFeePerGas": "0x3b9aca00",
+            "hash": "0x0000000000000000000000000000000000000000000000000000000000000003",
+            "input": "0x",
+            "nonce": "0x1",
+            "to": "0x0000000000000000000000000000000000000002",
+            "transactionIndex": "0x0",
+            "value": "0x0",
+            "type": "0x2"
+        });
+        if let Some(gas_price) = gas_price {
+            json["gasPrice"] = gas_price.into();
+        }
+        let tx: EthereumTransaction = serde_json::from_value(json).unwrap();
+        EthereumTransactionData::new(&tx, Some(base_fee))
+    };
+
+    // Geth reports the effective gas price
+    let geth = tx(Some("0x2540be400"));
+    // Some versions of Erigon report the max fee
+    let erigon = tx(Some("0x2e90edd000"));
+    // Some load balancers in front of Infura drop it
+    let infura = tx(None);
+
+    for data in &[geth, erigon, infura] {
+        assert_eq!(effective_gas_price, data.gas_price);
+        assert_eq!(Some(effective_gas_price), data.effective_gas_price);
+    }
+
+    // Legacy transactions keep the gas price they were sent with
+    let legacy = EthereumTransaction::from(Transaction {
+        transaction_index: Some(0.into()),
+        gas_price: U256::from(7),
+        ..Transaction::default()
+    });
+    let data = EthereumTransactionData::new(&legacy, Some(base_fee));
+    assert_eq!(U256::from(7), data.gas_price);
+}
+
+#[test]
+fn test_data_source_key() {
+    use graph::prelude::{DataSourceContext, DataSourceKey};
+
+    fn with_context(context: Option<DataSourceContext>) -> DataSourceKey {
+        let mut ds = transfer_data_source(Some(Address::from_low_u64_be(1)));
+        ds.context = Arc::new(context);
+        DataSourceKey::of::<crate::Chain>(&ds)
+    }
+
+    fn context(entries: Vec<(&str, &str)>) -> DataSourceContext {
+        DataSourceContext::from(
+            entries
+                .into_iter()
+                .map(|(name, value)| (name, graph::prelude::Value::from(value)))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    let one = context(vec![("a", "1"), ("b", "2")]);
+    // The same context with attributes in a different order
+    let same = context(vec![("b", "2"), ("a", "1")]);
+    let other = context(vec![("a", "1"), ("b", "3")]);
+
+    assert_eq!(with_context(Some(one.clone())), with_context(Some(same)));
+    assert_ne!(with_context(Some(one.clone())), with_context(Some(other)));
+    assert_ne!(with_context(Some(one)), with_context(None));
+    assert_eq!(with_context(None), with_context(None));
+}
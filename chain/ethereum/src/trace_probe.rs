@@ -0,0 +1,94 @@
+//! Providers that are configured with `traces` are only used for
+//! subgraphs that need traces while they answer `trace_filter` requests.
+//! That can change while the node runs, for example because the first
+//! probe failed with a transient error or because a provider was
+//! restarted with different settings, so we probe those providers again
+//! every `GRAPH_ETHEREUM_TRACE_PROBE_INTERVAL` seconds
+use std::str::FromStr;
+use std::time::Duration;
+
+use graph::prelude::{info, lazy_static, o, tokio, warn, Logger};
+
+use crate::network::{EthereumNetworkAdapter, EthereumNetworks};
+use crate::EthereumAdapterTrait as _;
+
+lazy_static! {
+    /// How often we probe providers for trace support, in seconds
+    static ref TRACE_PROBE_INTERVAL: Duration = {
+        let secs = std::env::var("GRAPH_ETHEREUM_TRACE_PROBE_INTERVAL")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_ETHEREUM_TRACE_PROBE_INTERVAL must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(300);
+        Duration::from_secs(secs)
+    };
+}
+
+/// Probes the providers that are configured with `traces` for trace
+/// support
+pub struct TraceProbe {
+    logger: Logger,
+    adapters: Vec<(String, EthereumNetworkAdapter)>,
+}
+
+impl TraceProbe {
+    pub fn new(logger: &Logger, networks: &EthereumNetworks) -> Self {
+        TraceProbe {
+            logger: logger.new(o!("component" => "TraceProbe")),
+            adapters: networks
+                .networks
+                .iter()
+                .flat_map(|(name, adapters)| {
+                    adapters
+                        .adapters
+                        .iter()
+                        .filter(|adapter| adapter.capabilities.traces)
+                        .map(move |adapter| (name.clone(), adapter.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Probe the providers every `GRAPH_ETHEREUM_TRACE_PROBE_INTERVAL`
+    /// seconds. They were already probed when they were created, so the
+    /// first probe happens after one interval
+    pub fn start(self) {
+        if self.adapters.is_empty() {
+            return;
+        }
+        graph::spawn_named("trace-probe", async move {
+            let mut interval = tokio::time::interval(*TRACE_PROBE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                for (network, adapter) in &self.adapters {
+                    self.probe(network, adapter).await;
+                }
+            }
+        });
+    }
+
+    async fn probe(&self, network: &str, adapter: &EthereumNetworkAdapter) {
+        let adapter = adapter.adapter();
+        let supported = adapter.supports_traces();
+        match (supported, adapter.reprobe_traces().await) {
+            (false, true) => {
+                info!(self.logger, "Provider supports `trace_filter` now, using it for subgraphs with call handlers";
+                                   "network" => network,
+                                   "provider" => adapter.provider())
+            }
+            (true, false) => {
+                warn!(self.logger, "Provider does not support `trace_filter` anymore, not using it for subgraphs with call handlers";
+                                   "network" => network,
+                                   "provider" => adapter.provider())
+            }
+            _ => {}
+        }
+    }
+}
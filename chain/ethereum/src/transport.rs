@@ -1,33 +1,67 @@
 use jsonrpc_core::types::Call;
 use serde_json::Value;
 use std::env;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 pub use web3::transports::EventLoopHandle;
 use web3::transports::{http, ipc, ws};
 use web3::RequestId;
 
 use graph::prelude::*;
+use graph::util::futures::{record_retry, Backoff};
+
+use crate::failover::ProviderHealth;
+use crate::get_logs::LogsClient;
+use crate::request_limit::RequestLimiter;
+use crate::rpc_debug::{self, RpcDebug};
 
 /// Abstraction over the different web3 transports.
 #[derive(Clone, Debug)]
-pub enum Transport {
-    RPC(http::Http),
+pub struct Transport {
+    kind: Kind,
+    /// Only set when `GRAPH_ETH_RPC_DEBUG` is set
+    debug: Option<Arc<RpcDebug>>,
+    /// Set once the transport belongs to an adapter
+    health: Option<Arc<ProviderHealth>>,
+    /// Only set if the provider has a request limit
+    limiter: Option<Arc<RequestLimiter>>,
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    /// HTTP also gets a separate client for `eth_getLogs` that does not
+    /// buffer whole responses
+    RPC(http::Http, LogsClient),
     IPC(ipc::Ipc),
-    WS(ws::WebSocket),
+    WS(ReconnectingWs),
 }
 
 impl Transport {
+    fn new(kind: Kind) -> Self {
+        Transport {
+            kind,
+            debug: None,
+            health: None,
+            limiter: None,
+        }
+    }
+
     /// Creates an IPC transport.
     pub fn new_ipc(ipc: &str) -> (EventLoopHandle, Self) {
         ipc::Ipc::new(ipc)
-            .map(|(event_loop, transport)| (event_loop, Transport::IPC(transport)))
+            .map(|(event_loop, transport)| (event_loop, Transport::new(Kind::IPC(transport))))
             .expect("Failed to connect to Ethereum IPC")
     }
 
-    /// Creates a WebSocket transport.
+    /// Creates a WebSocket transport. The connection is reestablished
+    /// when it fails
     pub fn new_ws(ws: &str) -> (EventLoopHandle, Self) {
         ws::WebSocket::new(ws)
-            .map(|(event_loop, transport)| (event_loop, Transport::WS(transport)))
+            .map(|(event_loop, transport)| {
+                let transport = ReconnectingWs::new(ws, transport);
+                (event_loop, Transport::new(Kind::WS(transport)))
+            })
             .expect("Failed to connect to Ethereum WS")
     }
 
@@ -40,28 +74,84 @@ impl Transport {
             .map(|s| s.to_str().unwrap().parse().unwrap())
             .unwrap_or(64);
 
+        let logs = LogsClient::new(rpc, &headers);
         http::Http::with_max_parallel_and_headers(rpc, max_parallel_http, headers)
-            .map(|(event_loop, transport)| (event_loop, Transport::RPC(transport)))
+            .map(|(event_loop, transport)| (event_loop, Transport::new(Kind::RPC(transport, logs))))
             .expect("Failed to connect to Ethereum RPC")
     }
+
+    /// Log and remember requests to the endpoint at `url` as configured
+    /// with `GRAPH_ETH_RPC_DEBUG`. Does nothing if that is not set
+    pub fn debug_requests(mut self, logger: &Logger, url: &str) -> Self {
+        self.debug = RpcDebug::new(logger, url);
+        if let Kind::RPC(_, logs) = &mut self.kind {
+            logs.debug = self.debug.clone();
+        }
+        self
+    }
+
+    /// Make every request wait for `limiter`, including the requests
+    /// for `eth_getLogs`. Does nothing if `limiter` is `None`
+    pub fn limit_requests(mut self, limiter: Option<RequestLimiter>) -> Self {
+        self.limiter = limiter.map(Arc::new);
+        if let Kind::RPC(_, logs) = &mut self.kind {
+            logs.limiter = self.limiter.clone();
+        }
+        self
+    }
+
+    /// Report the outcome of every request to `health`
+    pub(crate) fn with_health(mut self, health: Arc<ProviderHealth>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// The client to use for `eth_getLogs`, if this transport has one
+    pub(crate) fn logs_client(&self) -> Option<&LogsClient> {
+        match &self.kind {
+            Kind::RPC(_, logs) => Some(logs),
+            Kind::IPC(_) | Kind::WS(_) => None,
+        }
+    }
 }
 
 impl web3::Transport for Transport {
     type Out = Box<dyn Future<Item = Value, Error = web3::error::Error> + Send>;
 
     fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
-        match self {
-            Transport::RPC(http) => http.prepare(method, params),
-            Transport::IPC(ipc) => ipc.prepare(method, params),
-            Transport::WS(ws) => ws.prepare(method, params),
+        match &self.kind {
+            Kind::RPC(http, _) => http.prepare(method, params),
+            Kind::IPC(ipc) => ipc.prepare(method, params),
+            Kind::WS(ws) => ws.current().prepare(method, params),
         }
     }
 
     fn send(&self, id: RequestId, request: Call) -> Self::Out {
-        match self {
-            Transport::RPC(http) => Box::new(http.send(id, request)),
-            Transport::IPC(ipc) => Box::new(ipc.send(id, request)),
-            Transport::WS(ws) => Box::new(ws.send(id, request)),
+        let out = match &self.limiter {
+            None => self.send_debug(id, request),
+            Some(limiter) => {
+                let limiter = limiter.cheap_clone();
+                let this = self.clone();
+                Box::new(
+                    async move {
+                        limiter.acquire(1).await;
+                        Ok::<_, web3::Error>(())
+                    }
+                    .boxed()
+                    .compat()
+                    .and_then(move |()| this.send_debug(id, request)),
+                )
+            }
+        };
+        match &self.health {
+            None => out,
+            Some(health) => {
+                let health = health.cheap_clone();
+                Box::new(out.then(move |result| {
+                    health.check(&result);
+                    result
+                }))
+            }
         }
     }
 }
@@ -73,13 +163,335 @@ impl web3::BatchTransport for Transport {
     >;
 
     fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let out = match &self.limiter {
+            None => self.send_batch_debug(requests),
+            Some(limiter) => {
+                let limiter = limiter.cheap_clone();
+                let this = self.clone();
+                let requests: Vec<_> = requests.into_iter().collect();
+                Box::new(
+                    async move {
+                        limiter.acquire(requests.len()).await;
+                        Ok::<_, web3::Error>(requests)
+                    }
+                    .boxed()
+                    .compat()
+                    .and_then(move |requests| this.send_batch_debug(requests)),
+                )
+            }
+        };
+        match &self.health {
+            None => out,
+            Some(health) => {
+                let health = health.cheap_clone();
+                Box::new(out.then(move |result| {
+                    health.check_batch(&result);
+                    result
+                }))
+            }
+        }
+    }
+}
+
+impl Transport {
+    fn send_debug(&self, id: RequestId, request: Call) -> <Self as web3::Transport>::Out {
+        let debug = match &self.debug {
+            Some(debug) => debug.cheap_clone(),
+            None => return self.kind.send(id, request),
+        };
+
+        let (method, params) = rpc_debug::describe_call(&request);
+        let tracked = debug.request(method, params);
+        Box::new(self.kind.send(id, request).then(move |result| {
+            debug.finish(
+                tracked,
+                result
+                    .as_ref()
+                    .map(|value| move || rpc_debug::describe_value(value)),
+            );
+            result
+        }))
+    }
+
+    fn send_batch_debug<T>(&self, requests: T) -> <Self as web3::BatchTransport>::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let debug = match &self.debug {
+            Some(debug) => debug.cheap_clone(),
+            None => return self.kind.send_batch(requests),
+        };
+
+        let requests: Vec<_> = requests.into_iter().collect();
+        let (methods, params): (Vec<_>, Vec<_>) = requests
+            .iter()
+            .map(|(_, call)| rpc_debug::describe_call(call))
+            .unzip();
+        let tracked = debug.request(
+            format!("batch({})", methods.join(",")),
+            format!("[{}]", params.join(",")),
+        );
+        Box::new(self.kind.send_batch(requests).then(move |result| {
+            debug.finish(
+                tracked,
+                result.as_ref().map(|values| {
+                    move || {
+                        let values: Vec<_> = values
+                            .iter()
+                            .map(|value| match value {
+                                Ok(value) => rpc_debug::describe_value(value),
+                                Err(e) => format!("{:?}", e.to_string()),
+                            })
+                            .collect();
+                        format!("[{}]", values.join(","))
+                    }
+                }),
+            );
+            result
+        }))
+    }
+}
+
+impl Kind {
+    fn send(&self, id: RequestId, request: Call) -> <Transport as web3::Transport>::Out {
+        match self {
+            Kind::RPC(http, _) => Box::new(http.send(id, request)),
+            Kind::IPC(ipc) => Box::new(ipc.send(id, request)),
+            Kind::WS(ws) => {
+                let ws = ws.clone();
+                Box::new(ws.current().send(id, request).then(move |result| {
+                    ws.check(&result);
+                    result
+                }))
+            }
+        }
+    }
+
+    fn send_batch<T>(&self, requests: T) -> <Transport as web3::BatchTransport>::Batch
     where
         T: IntoIterator<Item = (RequestId, Call)>,
     {
         match self {
-            Transport::RPC(http) => Box::new(http.send_batch(requests)),
-            Transport::IPC(ipc) => Box::new(ipc.send_batch(requests)),
-            Transport::WS(ws) => Box::new(ws.send_batch(requests)),
+            Kind::RPC(http, _) => Box::new(http.send_batch(requests)),
+            Kind::IPC(ipc) => Box::new(ipc.send_batch(requests)),
+            Kind::WS(ws) => {
+                let ws = ws.clone();
+                Box::new(ws.current().send_batch(requests).then(move |result| {
+                    ws.check(&result);
+                    result
+                }))
+            }
         }
     }
 }
+
+/// The shortest time we wait before trying to reconnect a WebSocket
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest time we wait before trying to reconnect a WebSocket
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// A WebSocket connection that is reestablished after it failed. Requests
+/// that were under way when the connection failed fail, too; the retries
+/// around our JSON-RPC calls take care of them. Reconnecting happens when
+/// the next request is sent, backing off exponentially with jitter while
+/// the provider stays unreachable
+type ReconnectingWs = Reconnecting<ws::WebSocket, EventLoopHandle>;
+
+/// A connection of type `C` whose event loop is an `H`. Connections are
+/// made with `connect`
+struct Reconnecting<C, H> {
+    url: Arc<String>,
+    connect: Arc<dyn Fn(&str) -> Result<(H, C), web3::Error> + Send + Sync>,
+    state: Arc<Mutex<ConnectionState<C, H>>>,
+}
+
+struct ConnectionState<C, H> {
+    conn: C,
+    /// The event loop of the connection if we reconnected. The event loop
+    /// of the first connection belongs to whoever created the transport
+    event_loop: Option<H>,
+    /// Set when the connection failed
+    broken: bool,
+    /// How long to wait after failed attempts to reconnect
+    backoff: Backoff,
+    /// When we may try to reconnect next
+    next_attempt: Instant,
+}
+
+impl ReconnectingWs {
+    fn new(url: &str, ws: ws::WebSocket) -> Self {
+        Reconnecting::with_connect(url, ws, Arc::new(|url: &str| ws::WebSocket::new(url)))
+    }
+}
+
+impl<C: Clone, H> Reconnecting<C, H> {
+    fn with_connect(
+        url: &str,
+        conn: C,
+        connect: Arc<dyn Fn(&str) -> Result<(H, C), web3::Error> + Send + Sync>,
+    ) -> Self {
+        Reconnecting {
+            url: Arc::new(url.to_string()),
+            connect,
+            state: Arc::new(Mutex::new(ConnectionState {
+                conn,
+                event_loop: None,
+                broken: false,
+                backoff: Backoff::new(MIN_RECONNECT_DELAY, MAX_RECONNECT_DELAY),
+                next_attempt: Instant::now(),
+            })),
+        }
+    }
+
+    /// The connection to send the next request over, reconnecting first
+    /// if the connection failed and it is time to try again. Connecting
+    /// can take a while; we do not hold the lock while we do it, so that
+    /// other requests can go ahead, and fail, over the broken connection
+    fn current(&self) -> C {
+        let attempt = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            if state.broken && state.next_attempt <= now {
+                // Back off now so that nobody else tries to connect while
+                // we do
+                state.next_attempt = now + state.backoff.next_delay();
+                true
+            } else {
+                false
+            }
+        };
+
+        if attempt {
+            record_retry("ws reconnect");
+            if let Ok((event_loop, conn)) = (self.connect)(&self.url) {
+                let previous = {
+                    let mut state = self.state.lock().unwrap();
+                    state.conn = conn;
+                    state.broken = false;
+                    state.backoff.reset();
+                    state.event_loop.replace(event_loop)
+                };
+                // Dropping the event loop of the previous connection stops
+                // its thread
+                drop(previous);
+            }
+        }
+        self.state.lock().unwrap().conn.clone()
+    }
+
+    /// Remember that the connection failed if `result` says so
+    fn check<T>(&self, result: &Result<T, web3::Error>) {
+        if let Err(web3::Error::Transport(_)) = result {
+            let mut state = self.state.lock().unwrap();
+            if !state.broken {
+                state.broken = true;
+                state.next_attempt = Instant::now();
+            }
+        }
+    }
+}
+
+impl<C, H> Clone for Reconnecting<C, H> {
+    fn clone(&self) -> Self {
+        Reconnecting {
+            url: self.url.clone(),
+            connect: self.connect.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<C, H> std::fmt::Debug for Reconnecting<C, H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Reconnecting")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+
+    /// Stands in for the event loop of a connection and counts how many
+    /// event loops were dropped
+    struct EventLoop(Arc<AtomicUsize>);
+
+    impl Drop for EventLoop {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn reconnect() {
+        let broken: Result<(), web3::Error> =
+            Err(web3::Error::Transport("connection reset".to_string()));
+        let dropped = Arc::new(AtomicUsize::new(0));
+
+        // Connections are numbered; connecting waits until the test tells
+        // it whether to succeed
+        let (attempts_tx, attempts) = mpsc::channel::<()>();
+        let (outcomes, outcomes_rx) = mpsc::channel::<bool>();
+        let attempts_tx = Mutex::new(attempts_tx);
+        let outcomes_rx = Mutex::new(outcomes_rx);
+        let connections = AtomicUsize::new(0);
+        let connect = {
+            let dropped = dropped.clone();
+            move |_: &str| {
+                attempts_tx.lock().unwrap().send(()).unwrap();
+                if outcomes_rx.lock().unwrap().recv().unwrap() {
+                    let conn = connections.fetch_add(1, Ordering::SeqCst) + 1;
+                    Ok((EventLoop(dropped.clone()), conn))
+                } else {
+                    Err(web3::Error::Transport("connection refused".to_string()))
+                }
+            }
+        };
+        let ws = Reconnecting::with_connect("ws://localhost", 0, Arc::new(connect));
+
+        // A healthy connection is used as is
+        assert_eq!(0, ws.current());
+        assert!(attempts.try_recv().is_err());
+
+        // A broken connection gets replaced, without holding up requests
+        // while we connect
+        ws.check(&broken);
+        let connecting = {
+            let ws = ws.clone();
+            thread::spawn(move || ws.current())
+        };
+        attempts.recv().unwrap();
+        assert_eq!(0, ws.current());
+        outcomes.send(true).unwrap();
+        assert_eq!(1, connecting.join().unwrap());
+        assert_eq!(1, ws.current());
+        assert_eq!(0, dropped.load(Ordering::SeqCst));
+
+        // Reconnecting again drops the event loop of the previous
+        // connection
+        ws.check(&broken);
+        outcomes.send(true).unwrap();
+        assert_eq!(2, ws.current());
+        attempts.recv().unwrap();
+        assert_eq!(1, dropped.load(Ordering::SeqCst));
+
+        // When connecting fails, we keep the broken connection and wait
+        // before trying again
+        ws.check(&broken);
+        outcomes.send(false).unwrap();
+        assert_eq!(2, ws.current());
+        attempts.recv().unwrap();
+        assert_eq!(2, ws.current());
+        assert!(attempts.try_recv().is_err());
+        assert_eq!(1, dropped.load(Ordering::SeqCst));
+    }
+}
@@ -1,9 +1,13 @@
+use anyhow::anyhow;
 use ethabi::LogParam;
 use graph::blockchain;
 use graph::blockchain::TriggerData;
+use graph::data::subgraph::API_VERSION_0_0_6;
 use graph::prelude::BlockNumber;
 use graph::prelude::BlockPtr;
-use graph::prelude::{CheapClone, EthereumCall};
+use graph::prelude::{
+    CheapClone, EthereumCall, EthereumTransaction, EthereumUncle, LightEthereumBlock,
+};
 use graph::runtime::asc_new;
 use graph::runtime::AscHeap;
 use graph::runtime::AscPtr;
@@ -18,31 +22,34 @@ use web3::types::H160;
 use web3::types::U128;
 use web3::types::U256;
 use web3::types::U64;
-use web3::types::{Address, Block, Log, Transaction, H256};
+use web3::types::{Address, Block, Log, TransactionReceipt, H256};
 
 use crate::data_source::MappingBlockHandler;
 use crate::data_source::MappingCallHandler;
 use crate::data_source::MappingEventHandler;
+use crate::runtime::abi::AscEthereumBlock;
+use crate::runtime::abi::AscEthereumBlock_0_0_6;
 use crate::runtime::abi::AscEthereumCall;
 use crate::runtime::abi::AscEthereumCall_0_0_3;
+use crate::runtime::abi::AscEthereumCall_0_0_6;
 use crate::runtime::abi::AscEthereumEvent;
+use crate::runtime::abi::AscEthereumEvent_0_0_6;
 use crate::runtime::abi::AscEthereumTransaction_0_0_1;
 use crate::runtime::abi::AscEthereumTransaction_0_0_2;
 
-// ETHDEP: This should be defined in only one place.
-type LightEthereumBlock = Block<Transaction>;
-
 pub enum MappingTrigger {
     Log {
         block: Arc<LightEthereumBlock>,
-        transaction: Arc<Transaction>,
+        transaction: Arc<EthereumTransaction>,
         log: Arc<Log>,
         params: Vec<LogParam>,
         handler: MappingEventHandler,
+        /// The receipt of the transaction, if the handler asked for it
+        receipt: Option<Arc<TransactionReceipt>>,
     },
     Call {
         block: Arc<LightEthereumBlock>,
-        transaction: Arc<Transaction>,
+        transaction: Arc<EthereumTransaction>,
         call: Arc<EthereumCall>,
         inputs: Vec<LogParam>,
         outputs: Vec<LogParam>,
@@ -50,6 +57,8 @@ pub enum MappingTrigger {
     },
     Block {
         block: Arc<LightEthereumBlock>,
+        /// The uncles of the block, if the handler's mapping can see them
+        uncles: Option<Arc<Vec<EthereumUncle>>>,
         handler: MappingBlockHandler,
     },
 }
@@ -60,13 +69,14 @@ impl std::fmt::Debug for MappingTrigger {
         #[derive(Debug)]
         enum MappingTriggerWithoutBlock {
             Log {
-                transaction: Arc<Transaction>,
+                transaction: Arc<EthereumTransaction>,
                 log: Arc<Log>,
                 params: Vec<LogParam>,
                 handler: MappingEventHandler,
+                receipt: Option<Arc<TransactionReceipt>>,
             },
             Call {
-                transaction: Arc<Transaction>,
+                transaction: Arc<EthereumTransaction>,
                 call: Arc<EthereumCall>,
                 inputs: Vec<LogParam>,
                 outputs: Vec<LogParam>,
@@ -84,11 +94,13 @@ impl std::fmt::Debug for MappingTrigger {
                 log,
                 params,
                 handler,
+                receipt,
             } => MappingTriggerWithoutBlock::Log {
                 transaction: transaction.cheap_clone(),
                 log: log.cheap_clone(),
                 params: params.clone(),
                 handler: handler.clone(),
+                receipt: receipt.cheap_clone(),
             },
             MappingTrigger::Call {
                 block: _,
@@ -104,7 +116,7 @@ impl std::fmt::Debug for MappingTrigger {
                 outputs: outputs.clone(),
                 handler: handler.clone(),
             },
-            MappingTrigger::Block { block: _, handler } => MappingTriggerWithoutBlock::Block {
+            MappingTrigger::Block { handler, .. } => MappingTriggerWithoutBlock::Block {
                 handler: handler.clone(),
             },
         };
@@ -136,6 +148,10 @@ impl blockchain::MappingTrigger for MappingTrigger {
         }
     }
 
+    fn is_block_handler(&self) -> bool {
+        matches!(self, MappingTrigger::Block { .. })
+    }
+
     fn to_asc_ptr<H: AscHeap>(self, heap: &mut H) -> Result<AscPtr<()>, DeterministicHostError> {
         Ok(match self {
             MappingTrigger::Log {
@@ -144,35 +160,26 @@ impl blockchain::MappingTrigger for MappingTrigger {
                 log,
                 params,
                 handler: _,
+                receipt,
             } => {
-                if heap.api_version() >= Version::new(0, 0, 2) {
-                    asc_new::<AscEthereumEvent<AscEthereumTransaction_0_0_2>, _, _>(
-                        heap,
-                        &EthereumEventData {
-                            block: EthereumBlockData::from(block.as_ref()),
-                            transaction: EthereumTransactionData::from(transaction.deref()),
-                            address: log.address,
-                            log_index: log.log_index.unwrap_or(U256::zero()),
-                            transaction_log_index: log.log_index.unwrap_or(U256::zero()),
-                            log_type: log.log_type.clone(),
-                            params,
-                        },
-                    )?
-                    .erase()
+                let event = EthereumEventData {
+                    block: EthereumBlockData::from(block.as_ref()),
+                    transaction: EthereumTransactionData::new(&transaction, block.base_fee_per_gas),
+                    address: log.address,
+                    log_index: log.log_index.unwrap_or(U256::zero()),
+                    transaction_log_index: log.log_index.unwrap_or(U256::zero()),
+                    log_type: log.log_type.clone(),
+                    params,
+                    receipt,
+                };
+                if heap.api_version() >= API_VERSION_0_0_6 {
+                    asc_new::<AscEthereumEvent_0_0_6, _, _>(heap, &event)?.erase()
+                } else if heap.api_version() >= Version::new(0, 0, 2) {
+                    asc_new::<AscEthereumEvent<AscEthereumTransaction_0_0_2>, _, _>(heap, &event)?
+                        .erase()
                 } else {
-                    asc_new::<AscEthereumEvent<AscEthereumTransaction_0_0_1>, _, _>(
-                        heap,
-                        &EthereumEventData {
-                            block: EthereumBlockData::from(block.as_ref()),
-                            transaction: EthereumTransactionData::from(transaction.deref()),
-                            address: log.address,
-                            log_index: log.log_index.unwrap_or(U256::zero()),
-                            transaction_log_index: log.log_index.unwrap_or(U256::zero()),
-                            log_type: log.log_type.clone(),
-                            params,
-                        },
-                    )?
-                    .erase()
+                    asc_new::<AscEthereumEvent<AscEthereumTransaction_0_0_1>, _, _>(heap, &event)?
+                        .erase()
                 }
             }
             MappingTrigger::Call {
@@ -187,19 +194,32 @@ impl blockchain::MappingTrigger for MappingTrigger {
                     to: call.to,
                     from: call.from,
                     block: EthereumBlockData::from(block.as_ref()),
-                    transaction: EthereumTransactionData::from(transaction.deref()),
+                    transaction: EthereumTransactionData::new(&transaction, block.base_fee_per_gas),
                     inputs,
                     outputs,
                 };
-                if heap.api_version() >= Version::new(0, 0, 3) {
+                if heap.api_version() >= API_VERSION_0_0_6 {
+                    asc_new::<AscEthereumCall_0_0_6, _, _>(heap, &call)?.erase()
+                } else if heap.api_version() >= Version::new(0, 0, 3) {
                     asc_new::<AscEthereumCall_0_0_3, _, _>(heap, &call)?.erase()
                 } else {
                     asc_new::<AscEthereumCall, _, _>(heap, &call)?.erase()
                 }
             }
-            MappingTrigger::Block { block, handler: _ } => {
-                let block = EthereumBlockData::from(block.as_ref());
-                asc_new(heap, &block)?.erase()
+            MappingTrigger::Block {
+                block,
+                uncles,
+                handler: _,
+            } => {
+                let block = EthereumBlockData {
+                    uncles,
+                    ..EthereumBlockData::from(block.as_ref())
+                };
+                if heap.api_version() >= API_VERSION_0_0_6 {
+                    asc_new::<AscEthereumBlock_0_0_6, _, _>(heap, &block)?.erase()
+                } else {
+                    asc_new::<AscEthereumBlock, _, _>(heap, &block)?.erase()
+                }
             }
         })
     }
@@ -209,7 +229,70 @@ impl blockchain::MappingTrigger for MappingTrigger {
 pub enum EthereumTrigger {
     Block(BlockPtr, EthereumBlockTriggerType),
     Call(Arc<EthereumCall>),
-    Log(Arc<Log>),
+    /// A log, and the receipt of its transaction if an event handler
+    /// for the log asked for it
+    Log(BlockLog, Option<Arc<TransactionReceipt>>),
+}
+
+/// A log together with its position in the chain. Providers sometimes
+/// return pending or malformed logs that lack a block number, block hash
+/// or transaction index, for example, when a load balancer mixes nodes
+/// that are at different blocks. `BlockLog::new` rejects such logs so that
+/// they never become triggers
+#[derive(Clone, Debug)]
+pub struct BlockLog {
+    log: Arc<Log>,
+    block_number: BlockNumber,
+    block_hash: H256,
+    transaction_index: u64,
+}
+
+impl BlockLog {
+    pub fn new(log: Arc<Log>) -> Result<Self, anyhow::Error> {
+        let block_number = log
+            .block_number
+            .ok_or_else(|| anyhow!("log without a block number: {:?}", log))?
+            .as_u64();
+        let block_number = BlockNumber::try_from(block_number)
+            .map_err(|_| anyhow!("block number of log out of range: {}", block_number))?;
+        let block_hash = log
+            .block_hash
+            .ok_or_else(|| anyhow!("log without a block hash: {:?}", log))?;
+        let transaction_index = log
+            .transaction_index
+            .ok_or_else(|| anyhow!("log without a transaction index: {:?}", log))?
+            .as_u64();
+        Ok(BlockLog {
+            log,
+            block_number,
+            block_hash,
+            transaction_index,
+        })
+    }
+
+    pub fn log(&self) -> &Arc<Log> {
+        &self.log
+    }
+
+    pub fn block_number(&self) -> BlockNumber {
+        self.block_number
+    }
+
+    pub fn block_hash(&self) -> H256 {
+        self.block_hash
+    }
+
+    pub fn transaction_index(&self) -> u64 {
+        self.transaction_index
+    }
+}
+
+impl Deref for BlockLog {
+    type Target = Log;
+
+    fn deref(&self) -> &Log {
+        &self.log
+    }
 }
 
 impl PartialEq for EthereumTrigger {
@@ -221,7 +304,7 @@ impl PartialEq for EthereumTrigger {
 
             (Self::Call(a), Self::Call(b)) => a == b,
 
-            (Self::Log(a), Self::Log(b)) => {
+            (Self::Log(a, _), Self::Log(b, _)) => {
                 a.transaction_hash == b.transaction_hash && a.log_index == b.log_index
             }
 
@@ -236,6 +319,24 @@ impl Eq for EthereumTrigger {}
 pub enum EthereumBlockTriggerType {
     Every,
     WithCallTo(Address),
+    /// The block at which data sources with a `once` block handler run
+    /// that handler
+    Once,
+    /// A block at which at least one data source with a `polling` block
+    /// handler polls
+    Polling,
+}
+
+impl EthereumBlockTriggerType {
+    /// The key by which block triggers for the same block are ordered
+    fn sort_key(&self) -> (u8, Option<&Address>) {
+        match self {
+            EthereumBlockTriggerType::Once => (0, None),
+            EthereumBlockTriggerType::Every => (1, None),
+            EthereumBlockTriggerType::WithCallTo(address) => (2, Some(address)),
+            EthereumBlockTriggerType::Polling => (3, None),
+        }
+    }
 }
 
 impl EthereumTrigger {
@@ -243,7 +344,7 @@ impl EthereumTrigger {
         match self {
             EthereumTrigger::Block(block_ptr, _) => block_ptr.number,
             EthereumTrigger::Call(call) => call.block_number,
-            EthereumTrigger::Log(log) => i32::try_from(log.block_number.unwrap().as_u64()).unwrap(),
+            EthereumTrigger::Log(log, _) => log.block_number(),
         }
     }
 
@@ -251,47 +352,58 @@ impl EthereumTrigger {
         match self {
             EthereumTrigger::Block(block_ptr, _) => block_ptr.hash_as_h256(),
             EthereumTrigger::Call(call) => call.block_hash,
-            EthereumTrigger::Log(log) => log.block_hash.unwrap(),
+            EthereumTrigger::Log(log, _) => log.block_hash(),
         }
     }
 }
 
+/// Triggers are ordered by their position in the block:
+///
+/// 1. Log and call triggers come in the order of their transactions
+/// 2. Within a transaction, all log triggers come before all call triggers
+/// 3. Logs are ordered by their log index
+/// 4. Calls are ordered by their trace address, so that a call comes after
+///    the calls that were made before it, and after the call that made it
+/// 5. Block triggers come last, ordered by their kind: the start block of
+///    `once` handlers, every block, calls to an address (ordered by the
+///    address), and finally polling
+///
+/// Since the ordering determines the order in which handlers run, it must be
+/// total and must not depend on the order in which triggers were found, as
+/// that would make the proof of indexing depend on it. Triggers that compare
+/// equal are the same trigger. Within one trigger, handlers run in the order
+/// of their data sources in the manifest
 impl Ord for EthereumTrigger {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
-            // Keep the order when comparing two block triggers
-            (Self::Block(..), Self::Block(..)) => Ordering::Equal,
+            (Self::Block(a_ptr, a_kind), Self::Block(b_ptr, b_kind)) => a_ptr
+                .number
+                .cmp(&b_ptr.number)
+                .then_with(|| a_kind.sort_key().cmp(&b_kind.sort_key())),
 
             // Block triggers always come last
             (Self::Block(..), _) => Ordering::Greater,
             (_, Self::Block(..)) => Ordering::Less,
 
-            // Calls are ordered by their tx indexes
-            (Self::Call(a), Self::Call(b)) => a.transaction_index.cmp(&b.transaction_index),
+            (Self::Call(a), Self::Call(b)) => a
+                .transaction_index
+                .cmp(&b.transaction_index)
+                .then_with(|| a.trace_address.cmp(&b.trace_address)),
 
-            // Events are ordered by their log index
-            (Self::Log(a), Self::Log(b)) => a.log_index.cmp(&b.log_index),
+            (Self::Log(a, _), Self::Log(b, _)) => a
+                .transaction_index()
+                .cmp(&b.transaction_index())
+                .then_with(|| a.log_index.cmp(&b.log_index)),
 
-            // Calls vs. events are logged by their tx index;
-            // if they are from the same transaction, events come first
-            (Self::Call(a), Self::Log(b))
-                if a.transaction_index == b.transaction_index.unwrap().as_u64() =>
-            {
-                Ordering::Greater
-            }
-            (Self::Log(a), Self::Call(b))
-                if a.transaction_index.unwrap().as_u64() == b.transaction_index =>
-            {
-                Ordering::Less
-            }
-            (Self::Call(a), Self::Log(b)) => a
-                .transaction_index
-                .cmp(&b.transaction_index.unwrap().as_u64()),
-            (Self::Log(a), Self::Call(b)) => a
+            // If they are from the same transaction, logs come first
+            (Self::Call(a), Self::Log(b, _)) => a
                 .transaction_index
-                .unwrap()
-                .as_u64()
-                .cmp(&b.transaction_index),
+                .cmp(&b.transaction_index())
+                .then(Ordering::Greater),
+            (Self::Log(a, _), Self::Call(b)) => a
+                .transaction_index()
+                .cmp(&b.transaction_index)
+                .then(Ordering::Less),
         }
     }
 }
@@ -305,7 +417,7 @@ impl PartialOrd for EthereumTrigger {
 impl TriggerData for EthereumTrigger {
     fn error_context(&self) -> std::string::String {
         let transaction_id = match self {
-            EthereumTrigger::Log(log) => log.transaction_hash,
+            EthereumTrigger::Log(log, _) => log.transaction_hash,
             EthereumTrigger::Call(call) => call.transaction_hash,
             EthereumTrigger::Block(..) => None,
         };
@@ -339,6 +451,8 @@ pub struct EthereumBlockData {
     pub difficulty: U256,
     pub total_difficulty: U256,
     pub size: Option<U256>,
+    /// Only passed to block handlers from apiVersion 0.0.6 on
+    pub uncles: Option<Arc<Vec<EthereumUncle>>>,
 }
 
 impl<'a, T> From<&'a Block<T>> for EthereumBlockData {
@@ -358,6 +472,7 @@ impl<'a, T> From<&'a Block<T>> for EthereumBlockData {
             difficulty: block.difficulty,
             total_difficulty: block.total_difficulty.unwrap_or_default(),
             size: block.size,
+            uncles: None,
         }
     }
 }
@@ -373,10 +488,25 @@ pub struct EthereumTransactionData {
     pub gas_limit: U256,
     pub gas_price: U256,
     pub input: Bytes,
+    /// The fee fields of EIP-1559 transactions. Mappings only see them
+    /// from apiVersion 0.0.6 on, and they are `None` for transactions that
+    /// do not have them
+    pub max_fee_per_gas: Option<U256>,
+    pub max_priority_fee_per_gas: Option<U256>,
+    /// What the transaction actually paid per unit of gas; `None` for
+    /// EIP-1559 transactions in blocks whose base fee we do not know
+    pub effective_gas_price: Option<U256>,
 }
 
-impl From<&'_ Transaction> for EthereumTransactionData {
-    fn from(tx: &Transaction) -> EthereumTransactionData {
+impl EthereumTransactionData {
+    /// The data for `tx` in a block with base fee `base_fee_per_gas`.
+    /// Providers differ in what they report as the `gasPrice` of EIP-1559
+    /// transactions: some report the effective gas price, some the max
+    /// fee, and some nothing at all. So that mappings of all apiVersions
+    /// see the same value no matter which provider served the block, the
+    /// `gas_price` of such transactions is their effective gas price
+    pub fn new(tx: &EthereumTransaction, base_fee_per_gas: Option<U256>) -> Self {
+        let effective_gas_price = tx.effective_gas_price(base_fee_per_gas);
         EthereumTransactionData {
             hash: tx.hash,
             index: tx.transaction_index.unwrap().as_u64().into(),
@@ -384,8 +514,11 @@ impl From<&'_ Transaction> for EthereumTransactionData {
             to: tx.to,
             value: tx.value,
             gas_limit: tx.gas,
-            gas_price: tx.gas_price,
+            gas_price: effective_gas_price.unwrap_or(tx.gas_price),
             input: tx.input.clone(),
+            max_fee_per_gas: tx.max_fee_per_gas,
+            max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
+            effective_gas_price,
         }
     }
 }
@@ -400,6 +533,8 @@ pub struct EthereumEventData {
     pub block: EthereumBlockData,
     pub transaction: EthereumTransactionData,
     pub params: Vec<LogParam>,
+    /// Only passed to mappings from apiVersion 0.0.6 on
+    pub receipt: Option<Arc<TransactionReceipt>>,
 }
 
 impl Clone for EthereumEventData {
@@ -419,6 +554,7 @@ impl Clone for EthereumEventData {
                     value: log_param.value.clone(),
                 })
                 .collect(),
+            receipt: self.receipt.cheap_clone(),
         }
     }
 }
@@ -8,12 +8,12 @@ use graph::prelude::{
     SubgraphManifestValidationError, UnvalidatedSubgraphManifest,
 };
 use graph::{
-    blockchain::NodeCapabilities as _,
+    blockchain::{DataSource as _, NodeCapabilities as _},
     components::{
         link_resolver::{JsonValueStream, LinkResolver as LinkResolverTrait},
         store::EntityType,
     },
-    data::subgraph::SubgraphFeature,
+    data::subgraph::{DeploymentProfile, HeavyBlockPolicy, SubgraphFeature},
 };
 
 use graph_chain_ethereum::{Chain, NodeCapabilities};
@@ -23,6 +23,7 @@ const GQL_SCHEMA: &str = "type Thing @entity { id: ID! }";
 const GQL_SCHEMA_FULLTEXT: &str = include_str!("full-text.graphql");
 const MAPPING_WITH_IPFS_FUNC_WASM: &[u8] = include_bytes!("ipfs-on-ethereum-contracts.wasm");
 const ABI: &str = "[{\"type\":\"function\", \"inputs\": [{\"name\": \"i\",\"type\": \"uint256\"}],\"name\":\"get\",\"outputs\": [{\"type\": \"address\",\"name\": \"o\"}]}]";
+const OVERLOADED_EVENTS_ABI: &str = include_str!("overloaded-events.json");
 
 #[derive(Default)]
 struct TextResolver {
@@ -48,6 +49,10 @@ impl LinkResolverTrait for TextResolver {
         self
     }
 
+    fn without_cache(self) -> Self {
+        self
+    }
+
     async fn cat(&self, _logger: &Logger, link: &Link) -> Result<Vec<u8>, anyhow::Error> {
         self.texts
             .get(&link.link)
@@ -65,12 +70,19 @@ impl LinkResolverTrait for TextResolver {
 }
 
 async fn resolve_manifest(text: &str) -> SubgraphManifest<graph_chain_ethereum::Chain> {
+    resolve_manifest_with_abi(text, ABI).await
+}
+
+async fn resolve_manifest_with_abi(
+    text: &str,
+    abi: &str,
+) -> SubgraphManifest<graph_chain_ethereum::Chain> {
     let mut resolver = TextResolver::default();
     let id = DeploymentHash::new("Qmmanifest").unwrap();
 
     resolver.add(id.as_str(), &text);
     resolver.add("/ipfs/Qmschema", &GQL_SCHEMA);
-    resolver.add("/ipfs/Qmabi", &ABI);
+    resolver.add("/ipfs/Qmabi", &abi);
     resolver.add("/ipfs/Qmmapping", &MAPPING_WITH_IPFS_FUNC_WASM);
 
     let raw = serde_yaml::from_str(text).unwrap();
@@ -238,6 +250,140 @@ specVersion: 0.0.2
     assert_eq!(true, required_capabilities.traces);
 }
 
+#[tokio::test]
+async fn factory_profile() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Factory
+    network: mainnet
+    source:
+      address: '0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f'
+      abi: Factory
+      startBlock: 10000835
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - Pair
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Factory
+          file:
+            /: /ipfs/Qmabi
+      eventHandlers:
+        - event: PairCreated(indexed address,indexed address,address,uint256)
+          handler: handleNewPair
+templates:
+  - kind: ethereum/contract
+    name: Pair
+    network: mainnet
+    source:
+      abi: Pair
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - Pair
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Pair
+          file:
+            /: /ipfs/Qmabi
+      eventHandlers:
+        - event: Sync(uint112,uint112)
+          handler: handleSync
+      blockHandlers:
+        - handler: handleBlock
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest(YAML).await;
+    let profile = DeploymentProfile::from_manifest(&manifest);
+
+    assert_eq!(1, profile.static_data_sources);
+    assert_eq!(1, profile.templates);
+    assert!(profile.has_templates());
+    assert_eq!(
+        vec!["block", "event"],
+        profile
+            .handler_kinds
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+    );
+    assert!(!profile.handler_kinds.contains("call"));
+    assert!(profile.features.is_empty());
+    assert_eq!(Some(10000835), profile.earliest_start_block);
+}
+
+#[tokio::test]
+async fn overloaded_event_handlers() {
+    const YAML: &str = "
+dataSources:
+  - kind: ethereum/contract
+    name: Token
+    network: mainnet
+    source:
+      address: '0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f'
+      abi: Token
+    mapping:
+      kind: ethereum/events
+      apiVersion: 0.0.4
+      language: wasm/assemblyscript
+      entities:
+        - Transfer
+      file:
+        /: /ipfs/Qmmapping
+      abis:
+        - name: Token
+          file:
+            /: /ipfs/Qmabi
+      eventHandlers:
+        - event: Transfer(indexed address,indexed address,uint256)
+          handler: handleTransfer
+        - event: Transfer(address,address,uint256,bytes)
+          handler: handleTransferWithData
+        - event: Transfer(address,address,uint256)
+          handler: handleAmbiguousTransfer
+        - event: Transfer(address,uint256)
+          handler: handleMissingTransfer
+schema:
+  file:
+    /: /ipfs/Qmschema
+specVersion: 0.0.2
+";
+
+    let manifest = resolve_manifest_with_abi(YAML, OVERLOADED_EVENTS_ABI).await;
+    let errors = manifest.data_sources[0]
+        .validate()
+        .into_iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            "data source `Token` handles event `Transfer(address,address,uint256)`, which \
+             matches several events in its ABI: Transfer(indexed address,indexed address,uint256), \
+             Transfer(address,address,uint256). Mark parameters as `indexed` in the signature \
+             to select one of them",
+            "data source `Token` handles event `Transfer(address,uint256)`, but no event in its \
+             ABI has that signature. Events with the same name: \
+             Transfer(indexed address,indexed address,uint256), \
+             Transfer(address,address,uint256), \
+             Transfer(indexed address,indexed address,uint256,bytes)"
+        ],
+        errors
+    );
+}
+
 #[test]
 fn undeclared_grafting_feature_causes_feature_validation_error() {
     const YAML: &str = "
@@ -610,3 +756,50 @@ schema:
         assert!(manifest.features.contains(&SubgraphFeature::NonFatalErrors))
     });
 }
+
+#[test]
+fn heavy_block_policies_require_a_limit() {
+    const YAML: &str = "
+specVersion: 0.0.4
+features:
+  - skipHeavyBlocks
+  - failHeavyBlocks
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+";
+    test_store::run_test_sequentially(|store| async move {
+        let store = store.subgraph_store();
+        let unvalidated = resolve_unvalidated(YAML).await;
+        let errors: Vec<_> = unvalidated
+            .validate(store.clone(), true)
+            .expect_err("Validation must fail")
+            .into_iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    SubgraphManifestValidationError::ConflictingHeavyBlockPolicies
+                        | SubgraphManifestValidationError::HeavyBlockPolicyWithoutLimit(_)
+                )
+            })
+            .collect();
+        assert_eq!(3, errors.len());
+
+        let manifest = resolve_manifest(
+            "
+specVersion: 0.0.4
+features:
+  - skipHeavyBlocks
+maxTriggersPerBlock: 500
+dataSources: []
+schema:
+  file:
+    /: /ipfs/Qmschema
+",
+        )
+        .await;
+        assert_eq!(500, manifest.max_triggers_per_block());
+        assert_eq!(HeavyBlockPolicy::Skip, manifest.heavy_block_policy());
+    });
+}
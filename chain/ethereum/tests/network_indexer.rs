@@ -138,6 +138,7 @@ fn create_chain(n: u64, parent: Option<&BlockWithOmmers>) -> Vec<BlockWithOmmers
             block: EthereumBlock {
                 block: Arc::new(block),
                 transaction_receipts: vec![],
+                uncles: vec![],
             },
             ommers: vec![],
         });
@@ -0,0 +1,378 @@
+//! A pool of IPFS clients that spreads requests over all configured
+//! endpoints, preferring the ones that have recently been fast and
+//! reliable, and that can hedge slow requests by sending them to a second
+//! endpoint
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Either};
+use futures::stream::{FuturesUnordered, StreamExt};
+use graph::ipfs_client::IpfsClient;
+use graph::prelude::rand::{thread_rng, Rng};
+use graph::prelude::{
+    reqwest, tokio, CheapClone, CounterVec, GaugeVec, HistogramVec, MetricsRegistry,
+};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// Send a request to another endpoint when the first one has not
+    /// answered after this many milliseconds, and use whichever answer
+    /// arrives first. Setting this to `0` turns hedging off
+    static ref IPFS_HEDGE_DELAY: Option<Duration> =
+        hedge_delay(env::var("GRAPH_IPFS_HEDGE_DELAY").ok().as_deref());
+}
+
+/// The hedge delay, in milliseconds, when `GRAPH_IPFS_HEDGE_DELAY` is not set
+const DEFAULT_HEDGE_DELAY_MS: u64 = 2000;
+
+fn hedge_delay(var: Option<&str>) -> Option<Duration> {
+    let millis = match var {
+        Some(s) => u64::from_str(s).unwrap_or_else(|_| {
+            panic!(
+                "GRAPH_IPFS_HEDGE_DELAY must be a number of milliseconds, but is `{}`",
+                s
+            )
+        }),
+        None => DEFAULT_HEDGE_DELAY_MS,
+    };
+    match millis {
+        0 => None,
+        millis => Some(Duration::from_millis(millis)),
+    }
+}
+
+/// The weight of the most recent request in an endpoint's moving averages
+const SMOOTHING: f64 = 0.2;
+/// The latency, in seconds, that endpoints start out with
+const INITIAL_LATENCY: f64 = 0.5;
+/// Latencies below this are treated as this value to keep weights finite
+const MIN_LATENCY: f64 = 0.001;
+/// How much an endpoint that only produces errors is penalized compared
+/// to one that never does
+const ERROR_PENALTY: f64 = 20.0;
+
+/// Moving averages of the latency and error rate of one endpoint
+#[derive(Clone, Copy, Debug)]
+struct Health {
+    latency: f64,
+    error_rate: f64,
+}
+
+impl Health {
+    fn new() -> Self {
+        Health {
+            latency: INITIAL_LATENCY,
+            error_rate: 0.0,
+        }
+    }
+
+    fn success(&mut self, latency: f64) {
+        self.latency += SMOOTHING * (latency.max(MIN_LATENCY) - self.latency);
+        self.error_rate -= SMOOTHING * self.error_rate;
+    }
+
+    fn failure(&mut self, latency: f64) {
+        // A quick failure must not make the endpoint look fast
+        self.latency += SMOOTHING * (latency.max(self.latency) - self.latency);
+        self.error_rate += SMOOTHING * (1.0 - self.error_rate);
+    }
+
+    /// The score of the endpoint; lower is better
+    fn score(&self) -> f64 {
+        self.latency * (1.0 + ERROR_PENALTY * self.error_rate)
+    }
+}
+
+/// Pick an index with a probability proportional to its weight. `r` must
+/// be in `[0, 1)`
+fn choose(weights: &[f64], r: f64) -> usize {
+    let total: f64 = weights.iter().sum();
+    let mut target = r * total;
+    for (i, weight) in weights.iter().enumerate() {
+        if target < *weight {
+            return i;
+        }
+        target -= weight;
+    }
+    weights.len() - 1
+}
+
+struct PoolMetrics {
+    requests: Box<CounterVec>,
+    duration: Box<HistogramVec>,
+    score: Box<GaugeVec>,
+}
+
+impl PoolMetrics {
+    fn new(registry: Arc<impl MetricsRegistry>) -> Self {
+        let requests = registry
+            .new_counter_vec(
+                "ipfs_requests",
+                "Counts IPFS requests per endpoint, operation and outcome; the outcome \
+                 `cancelled` counts hedged requests that lost the race",
+                vec![
+                    String::from("endpoint"),
+                    String::from("op"),
+                    String::from("outcome"),
+                ],
+            )
+            .expect("failed to create `ipfs_requests` counter");
+        let duration = registry
+            .new_histogram_vec(
+                "ipfs_request_duration",
+                "Measures the duration of IPFS requests per endpoint and operation",
+                vec![String::from("endpoint"), String::from("op")],
+                vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0],
+            )
+            .expect("failed to create `ipfs_request_duration` histogram");
+        let score = registry
+            .new_gauge_vec(
+                "ipfs_endpoint_score",
+                "The health score of each IPFS endpoint; lower is better, and endpoints \
+                 are chosen in inverse proportion to their score",
+                vec![String::from("endpoint")],
+            )
+            .expect("failed to create `ipfs_endpoint_score` gauge");
+        PoolMetrics {
+            requests,
+            duration,
+            score,
+        }
+    }
+}
+
+/// The name under which an endpoint is reported in metrics. We only use
+/// host and port so that credentials in the URL do not end up in metrics
+fn endpoint_name(client: &IpfsClient) -> String {
+    let base = client.base();
+    match (base.host(), base.port_u16()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        (None, _) => String::from("unknown"),
+    }
+}
+
+pub struct IpfsClientPool {
+    clients: Vec<Arc<IpfsClient>>,
+    names: Vec<String>,
+    health: Mutex<Vec<Health>>,
+    hedge_delay: Option<Duration>,
+    metrics: Option<PoolMetrics>,
+}
+
+impl IpfsClientPool {
+    pub fn new(clients: Vec<IpfsClient>) -> Self {
+        let clients: Vec<_> = clients.into_iter().map(Arc::new).collect();
+        let names = clients.iter().map(|client| endpoint_name(client)).collect();
+        let health = Mutex::new(vec![Health::new(); clients.len()]);
+        IpfsClientPool {
+            clients,
+            names,
+            health,
+            hedge_delay: *IPFS_HEDGE_DELAY,
+            metrics: None,
+        }
+    }
+
+    /// Report requests and the health of endpoints to `registry`
+    pub fn with_metrics(mut self, registry: Arc<impl MetricsRegistry>) -> Self {
+        self.metrics = Some(PoolMetrics::new(registry));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+
+    /// Order all endpoints randomly, with healthier endpoints more likely
+    /// to come first
+    fn ordering(&self) -> Vec<usize> {
+        let mut weights: Vec<f64> = self
+            .health
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|health| 1.0 / health.score())
+            .collect();
+        let mut rng = thread_rng();
+        let mut remaining: Vec<usize> = (0..self.clients.len()).collect();
+        let mut order = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let i = choose(&weights, rng.gen::<f64>());
+            order.push(remaining.remove(i));
+            weights.remove(i);
+        }
+        order
+    }
+
+    fn record(&self, op: &str, index: usize, elapsed: Duration, success: bool) {
+        let elapsed = elapsed.as_secs_f64();
+        let score = {
+            let mut health = self.health.lock().unwrap();
+            if success {
+                health[index].success(elapsed);
+            } else {
+                health[index].failure(elapsed);
+            }
+            health[index].score()
+        };
+        if let Some(metrics) = &self.metrics {
+            let name = self.names[index].as_str();
+            let outcome = if success { "success" } else { "error" };
+            metrics
+                .requests
+                .with_label_values(&[name, op, outcome])
+                .inc();
+            metrics
+                .duration
+                .with_label_values(&[name, op])
+                .observe(elapsed);
+            metrics.score.with_label_values(&[name]).set(score);
+        }
+    }
+
+    fn record_cancelled(&self, op: &str, index: usize) {
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .requests
+                .with_label_values(&[self.names[index].as_str(), op, "cancelled"])
+                .inc();
+        }
+    }
+
+    async fn attempt<T, F, Fut>(
+        &self,
+        call: &F,
+        index: usize,
+    ) -> (usize, Duration, Result<T, reqwest::Error>)
+    where
+        F: Fn(Arc<IpfsClient>) -> Fut,
+        Fut: Future<Output = Result<T, reqwest::Error>>,
+    {
+        let start = Instant::now();
+        let res = call(self.clients[index].cheap_clone()).await;
+        (index, start.elapsed(), res)
+    }
+
+    /// Run `call` against the endpoints of this pool, starting with one
+    /// that is chosen based on the health of all endpoints. If that fails,
+    /// the next endpoint is tried until one succeeds or all have failed.
+    /// With hedging, another endpoint is also tried whenever no answer has
+    /// arrived within the hedge delay. Requests that are still in flight
+    /// once one has succeeded are dropped, which aborts their connections.
+    ///
+    /// Return the result and the client that produced it. The pool must
+    /// not be empty
+    pub async fn run<T, F, Fut>(
+        &self,
+        op: &'static str,
+        call: F,
+    ) -> Result<(T, Arc<IpfsClient>), reqwest::Error>
+    where
+        F: Fn(Arc<IpfsClient>) -> Fut + Send + Sync,
+        Fut: Future<Output = Result<T, reqwest::Error>> + Send,
+        T: Send,
+    {
+        assert!(!self.is_empty(), "IPFS client pool has no clients");
+
+        let mut order = self.ordering().into_iter();
+        let mut in_flight = FuturesUnordered::new();
+        let mut pending = vec![];
+        let first = order.next().unwrap();
+        in_flight.push(self.attempt(&call, first));
+        pending.push(first);
+
+        loop {
+            let hedge: Pin<Box<dyn Future<Output = ()> + Send>> =
+                match (self.hedge_delay, order.len() > 0) {
+                    (Some(delay), true) => Box::pin(tokio::time::sleep(delay)),
+                    _ => Box::pin(future::pending()),
+                };
+
+            let attempt = match future::select(in_flight.next(), hedge).await {
+                Either::Left((attempt, _)) => {
+                    Some(attempt.expect("there is always at least one IPFS request in flight"))
+                }
+                Either::Right(_) => None,
+            };
+
+            match attempt {
+                Some((index, elapsed, Ok(value))) => {
+                    pending.retain(|i| *i != index);
+                    self.record(op, index, elapsed, true);
+                    for loser in pending {
+                        self.record_cancelled(op, loser);
+                    }
+                    return Ok((value, self.clients[index].cheap_clone()));
+                }
+                Some((index, elapsed, Err(e))) => {
+                    pending.retain(|i| *i != index);
+                    self.record(op, index, elapsed, false);
+                    match order.next() {
+                        Some(next) => {
+                            in_flight.push(self.attempt(&call, next));
+                            pending.push(next);
+                        }
+                        None if in_flight.is_empty() => return Err(e),
+                        None => { /* wait for the requests in flight */ }
+                    }
+                }
+                None => {
+                    // No answer within the hedge delay; ask another endpoint
+                    if let Some(next) = order.next() {
+                        in_flight.push(self.attempt(&call, next));
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choose_is_proportional_to_weight() {
+        let weights = [1.0, 3.0];
+        assert_eq!(0, choose(&weights, 0.0));
+        assert_eq!(0, choose(&weights, 0.24));
+        assert_eq!(1, choose(&weights, 0.25));
+        assert_eq!(1, choose(&weights, 0.99));
+    }
+
+    #[test]
+    fn hedging_is_on_by_default() {
+        assert_eq!(
+            Some(Duration::from_millis(DEFAULT_HEDGE_DELAY_MS)),
+            hedge_delay(None)
+        );
+        assert_eq!(Some(Duration::from_millis(500)), hedge_delay(Some("500")));
+        assert_eq!(None, hedge_delay(Some("0")));
+    }
+
+    #[test]
+    fn failures_hurt_the_score() {
+        let mut fast = Health::new();
+        let mut failing = Health::new();
+        for _ in 0..10 {
+            fast.success(0.1);
+            // Failing quickly must not look better than succeeding
+            failing.failure(0.01);
+        }
+        assert!(fast.score() < Health::new().score());
+        assert!(failing.score() > 10.0 * fast.score());
+
+        // Endpoints recover once they succeed again
+        let before = failing.score();
+        for _ in 0..10 {
+            failing.success(0.1);
+        }
+        assert!(failing.score() < before);
+    }
+}
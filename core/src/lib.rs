@@ -1,7 +1,10 @@
+mod ipfs_pool;
 mod link_resolver;
 mod metrics;
 mod subgraph;
+mod webhook;
 
 pub use crate::link_resolver::LinkResolver;
 pub use crate::metrics::MetricsRegistry;
 pub use crate::subgraph::{SubgraphAssignmentProvider, SubgraphInstanceManager, SubgraphRegistrar};
+pub use crate::webhook::{DeploymentEvent, Webhook, WebhookNotifier};
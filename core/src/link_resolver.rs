@@ -7,7 +7,6 @@ use anyhow::anyhow;
 use async_trait::async_trait;
 use bytes::BytesMut;
 use futures01::{stream::poll_fn, try_ready};
-use futures03::stream::FuturesUnordered;
 use graph::util::futures::RetryConfigNoTimeout;
 use lazy_static::lazy_static;
 use lru_time_cache::LruCache;
@@ -18,6 +17,8 @@ use graph::{
     prelude::{LinkResolver as LinkResolverTrait, *},
 };
 
+use crate::ipfs_pool::IpfsClientPool;
+
 /// Environment variable for limiting the `ipfs.map` file size limit.
 const MAX_IPFS_MAP_FILE_SIZE_VAR: &'static str = "GRAPH_MAX_IPFS_MAP_FILE_SIZE";
 
@@ -73,58 +74,6 @@ fn retry_policy<I: Send + Sync>(
     .no_timeout() // The timeout should be set in the internal future.
 }
 
-/// The IPFS APIs don't have a quick "do you have the file" function. Instead, we
-/// just rely on whether an API times out. That makes sense for IPFS, but not for
-/// our application. We want to be able to quickly select from a potential list
-/// of clients where hopefully one already has the file, and just get the file
-/// from that.
-///
-/// The strategy here then is to use the object_stat API as a proxy for "do you
-/// have the file". Whichever client has or gets the file first wins. This API is
-/// a good choice, because it doesn't involve us actually starting to download
-/// the file from each client, which would be wasteful of bandwidth and memory in
-/// the case multiple clients respond in a timely manner. In addition, we may
-/// make good use of the stat returned.
-async fn select_fastest_client_with_stat(
-    clients: Arc<Vec<Arc<IpfsClient>>>,
-    logger: Logger,
-    path: String,
-    timeout: Duration,
-    do_retry: bool,
-) -> Result<(ObjectStatResponse, Arc<IpfsClient>), Error> {
-    let mut err: Option<Error> = None;
-
-    let mut stats: FuturesUnordered<_> = clients
-        .iter()
-        .enumerate()
-        .map(|(i, c)| {
-            let c = c.cheap_clone();
-            let path = path.clone();
-            retry_policy(do_retry, "object.stat", &logger).run(move || {
-                let path = path.clone();
-                let c = c.cheap_clone();
-                async move { c.object_stat(path, timeout).map_ok(move |s| (s, i)).await }
-            })
-        })
-        .collect();
-
-    while let Some(result) = stats.next().await {
-        match result {
-            Ok((stat, index)) => {
-                return Ok((stat, clients[index].cheap_clone()));
-            }
-            Err(e) => err = Some(e.into()),
-        }
-    }
-
-    Err(err.unwrap_or_else(|| {
-        anyhow!(
-            "No IPFS clients were supplied to handle the call to object.stat. File: {}",
-            path
-        )
-    }))
-}
-
 // Returns an error if the stat is bigger than `max_file_bytes`
 fn restrict_file_size(
     path: &str,
@@ -146,19 +95,21 @@ fn restrict_file_size(
 
 #[derive(Clone)]
 pub struct LinkResolver {
-    clients: Arc<Vec<Arc<IpfsClient>>>,
+    pool: Arc<IpfsClientPool>,
     cache: Arc<Mutex<LruCache<String, Vec<u8>>>>,
     timeout: Duration,
     retry: bool,
+    use_cache: bool,
 }
 
 impl CheapClone for LinkResolver {
     fn cheap_clone(&self) -> Self {
         LinkResolver {
-            clients: self.clients.cheap_clone(),
+            pool: self.pool.cheap_clone(),
             cache: self.cache.cheap_clone(),
             timeout: self.timeout,
             retry: self.retry,
+            use_cache: self.use_cache,
         }
     }
 }
@@ -171,14 +122,60 @@ impl From<IpfsClient> for LinkResolver {
 
 impl From<Vec<IpfsClient>> for LinkResolver {
     fn from(clients: Vec<IpfsClient>) -> Self {
+        Self::from_pool(IpfsClientPool::new(clients))
+    }
+}
+
+impl LinkResolver {
+    /// Create a resolver that reports the requests it makes to each IPFS
+    /// endpoint to `registry`
+    pub fn new(clients: Vec<IpfsClient>, registry: Arc<impl MetricsRegistry>) -> Self {
+        Self::from_pool(IpfsClientPool::new(clients).with_metrics(registry))
+    }
+
+    fn from_pool(pool: IpfsClientPool) -> Self {
         Self {
-            clients: Arc::new(clients.into_iter().map(Arc::new).collect()),
+            pool: Arc::new(pool),
             cache: Arc::new(Mutex::new(LruCache::with_capacity(
                 *MAX_IPFS_CACHE_SIZE as usize,
             ))),
             timeout: *IPFS_TIMEOUT,
             retry: false,
+            use_cache: true,
+        }
+    }
+
+    /// Stat the file at `path` to find out how large it is before we
+    /// download it. The stat goes through the pool like all other IPFS
+    /// requests, which picks the endpoint based on how healthy the IPFS
+    /// endpoints have recently been, fails over to other endpoints, and
+    /// hedges slow requests
+    async fn stat(&self, logger: &Logger, path: &str) -> Result<ObjectStatResponse, Error> {
+        if self.pool.is_empty() {
+            return Err(anyhow!(
+                "No IPFS clients were supplied to handle the call to object.stat. File: {}",
+                path
+            ));
         }
+
+        let pool = self.pool.cheap_clone();
+        let path = path.to_string();
+        let timeout = self.timeout;
+        retry_policy(self.retry, "object.stat", logger)
+            .run(move || {
+                let pool = pool.cheap_clone();
+                let path = path.clone();
+                async move {
+                    pool.run("object.stat", |client| {
+                        let path = path.clone();
+                        async move { client.object_stat(path, timeout).await }
+                    })
+                    .await
+                }
+            })
+            .await
+            .map(|(stat, _)| stat)
+            .map_err(Error::from)
     }
 }
 
@@ -194,25 +191,25 @@ impl LinkResolverTrait for LinkResolver {
         self
     }
 
+    fn without_cache(mut self) -> Self {
+        self.use_cache = false;
+        self
+    }
+
     /// Supports links of the form `/ipfs/ipfs_hash` or just `ipfs_hash`.
     async fn cat(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
         // Discard the `/ipfs/` prefix (if present) to get the hash.
         let path = link.link.trim_start_matches("/ipfs/").to_owned();
 
-        if let Some(data) = self.cache.lock().unwrap().get(&path) {
-            trace!(logger, "IPFS cache hit"; "hash" => &path);
-            return Ok(data.clone());
+        if self.use_cache {
+            if let Some(data) = self.cache.lock().unwrap().get(&path) {
+                trace!(logger, "IPFS cache hit"; "hash" => &path);
+                return Ok(data.clone());
+            }
+            trace!(logger, "IPFS cache miss"; "hash" => &path);
         }
-        trace!(logger, "IPFS cache miss"; "hash" => &path);
 
-        let (stat, client) = select_fastest_client_with_stat(
-            self.clients.cheap_clone(),
-            logger.cheap_clone(),
-            path.clone(),
-            self.timeout,
-            self.retry,
-        )
-        .await?;
+        let stat = self.stat(logger, &path).await?;
 
         // FIXME: Having an env variable here is a problem for consensus.
         // Index Nodes should not disagree on whether the file should be read.
@@ -226,17 +223,25 @@ impl LinkResolverTrait for LinkResolver {
         let data = retry_policy(self.retry, "ipfs.cat", &logger)
             .run(move || {
                 let path = path.clone();
-                let client = client.clone();
                 let this = this.clone();
                 let logger = logger.clone();
                 async move {
-                    let data = client.cat_all(path.clone(), timeout).await?.to_vec();
+                    let (data, _) = this
+                        .pool
+                        .run("cat", |client| {
+                            let path = path.clone();
+                            async move { client.cat_all(path, timeout).await }
+                        })
+                        .await?;
+                    let data = data.to_vec();
 
                     // Only cache files if they are not too large
                     if data.len() <= *MAX_IPFS_CACHE_FILE_SIZE as usize {
-                        let mut cache = this.cache.lock().unwrap();
-                        if !cache.contains_key(&path) {
-                            cache.insert(path.to_owned(), data.clone());
+                        if this.use_cache {
+                            let mut cache = this.cache.lock().unwrap();
+                            if !cache.contains_key(&path) {
+                                cache.insert(path.to_owned(), data.clone());
+                            }
                         }
                     } else {
                         debug!(logger, "File too large for cache";
@@ -256,20 +261,20 @@ impl LinkResolverTrait for LinkResolver {
         // Discard the `/ipfs/` prefix (if present) to get the hash.
         let path = link.link.trim_start_matches("/ipfs/");
 
-        let (stat, client) = select_fastest_client_with_stat(
-            self.clients.cheap_clone(),
-            logger.cheap_clone(),
-            path.to_string(),
-            self.timeout,
-            self.retry,
-        )
-        .await?;
+        let stat = self.stat(logger, path).await?;
 
         let max_file_size =
             read_u64_from_env(MAX_IPFS_MAP_FILE_SIZE_VAR).or(Some(DEFAULT_MAX_IPFS_MAP_FILE_SIZE));
         restrict_file_size(path, &stat, &max_file_size)?;
 
-        let mut stream = client.cat(path.to_string()).await?.fuse().boxed().compat();
+        let (stream, _) = self
+            .pool
+            .run("cat", |client| {
+                let path = path.to_string();
+                async move { client.cat(path).await }
+            })
+            .await?;
+        let mut stream = stream.fuse().boxed().compat();
 
         let mut buf = BytesMut::with_capacity(1024);
 
@@ -360,6 +365,29 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn uncached_resolver_fetches_cached_files() {
+        let resolver = super::LinkResolver::from(vec![]);
+        let logger = Logger::root(slog::Discard, o!());
+        let link = Link {
+            link: "/ipfs/QmCached".to_string(),
+        };
+        resolver
+            .cache
+            .lock()
+            .unwrap()
+            .insert("QmCached".to_string(), b"cached".to_vec());
+
+        let data = LinkResolver::cat(&resolver, &logger, &link).await.unwrap();
+        assert_eq!(b"cached".to_vec(), data);
+
+        // Without IPFS clients, fetching the file fails
+        let err = LinkResolver::cat(&resolver.without_cache(), &logger, &link)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("No IPFS clients"));
+    }
+
     async fn json_round_trip(text: &'static str) -> Result<Vec<Value>, Error> {
         let client = IpfsClient::localhost();
         let resolver = super::LinkResolver::from(client.clone());
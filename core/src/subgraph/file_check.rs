@@ -0,0 +1,252 @@
+//! Check that the files of a deployment can still be resolved. Deployments
+//! only need their files when they are started, so files that disappear
+//! from IPFS go unnoticed until a restart fails
+use graph::data::subgraph::Link;
+use graph::prelude::{
+    serde_yaml, DeploymentFilesReport, DeploymentHash, LinkResolver, Logger, Schema,
+    UnresolvableFile,
+};
+
+/// All links to files in the manifest `raw`, i.e., all values of `/` keys,
+/// in the order in which they first appear
+fn manifest_links(raw: &serde_yaml::Value) -> Vec<String> {
+    fn collect(value: &serde_yaml::Value, links: &mut Vec<String>) {
+        match value {
+            serde_yaml::Value::Mapping(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value.as_str()) {
+                        (Some("/"), Some(link)) => {
+                            if !links.iter().any(|l| l == link) {
+                                links.push(link.to_string())
+                            }
+                        }
+                        _ => collect(value, links),
+                    }
+                }
+            }
+            serde_yaml::Value::Sequence(values) => {
+                for value in values {
+                    collect(value, links)
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut links = Vec::new();
+    collect(raw, &mut links);
+    links
+}
+
+/// The link to the schema in the manifest `raw`
+fn schema_link(raw: &serde_yaml::Value) -> Option<&str> {
+    raw.get("schema")?.get("file")?.get("/")?.as_str()
+}
+
+/// Resolve the manifest of `deployment` and every file it links to with
+/// `resolver`, and compare the schema with `stored_schema`. Templates are
+/// part of the manifest, so this also covers the files of dynamic data
+/// sources
+pub(crate) async fn check_deployment_files(
+    logger: &Logger,
+    resolver: &impl LinkResolver,
+    deployment: &DeploymentHash,
+    stored_schema: Option<&Schema>,
+) -> DeploymentFilesReport {
+    let mut report = DeploymentFilesReport {
+        deployment: deployment.to_string(),
+        unresolvable: vec![],
+        mismatched: vec![],
+    };
+
+    let manifest_link = deployment.to_ipfs_link();
+    let raw = match resolver
+        .cat(logger, &manifest_link)
+        .await
+        .and_then(|bytes| {
+            serde_yaml::from_slice::<serde_yaml::Value>(&bytes).map_err(anyhow::Error::from)
+        }) {
+        Ok(raw) => raw,
+        Err(e) => {
+            report.unresolvable.push(UnresolvableFile {
+                link: manifest_link.link,
+                error: e.to_string(),
+            });
+            return report;
+        }
+    };
+
+    let schema_link = schema_link(&raw);
+    for link in manifest_links(&raw) {
+        let bytes = match resolver.cat(logger, &Link::from(link.clone())).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                report.unresolvable.push(UnresolvableFile {
+                    link,
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if schema_link == Some(link.as_str()) {
+            if let Some(stored) = stored_schema {
+                let same = String::from_utf8(bytes)
+                    .ok()
+                    .and_then(|sdl| Schema::parse(&sdl, deployment.clone()).ok())
+                    .map(|schema| schema.document.to_string() == stored.document.to_string())
+                    .unwrap_or(false);
+                if !same {
+                    report.mismatched.push(link);
+                }
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::prelude::{anyhow, async_trait, futures03, o, Error, JsonValueStream};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    const SCHEMA: &str = "type Thing @entity { id: ID! }";
+
+    fn manifest() -> String {
+        r#"
+specVersion: 0.0.2
+schema:
+  file:
+    /: /ipfs/QmSchema
+dataSources:
+  - kind: ethereum/contract
+    name: Token
+    source:
+      abi: Token
+    mapping:
+      abis:
+        - name: Token
+          file:
+            /: /ipfs/QmAbi
+      file:
+        /: /ipfs/QmMapping
+templates:
+  - kind: ethereum/contract
+    name: Pair
+    mapping:
+      abis:
+        - name: Token
+          file:
+            /: /ipfs/QmAbi
+      file:
+        /: /ipfs/QmTemplateMapping
+"#
+        .to_string()
+    }
+
+    /// A resolver that knows a fixed set of files
+    #[derive(Clone)]
+    struct FakeResolver(HashMap<String, String>);
+
+    #[async_trait]
+    impl LinkResolver for FakeResolver {
+        fn with_timeout(self, _timeout: Duration) -> Self {
+            self
+        }
+
+        fn with_retries(self) -> Self {
+            self
+        }
+
+        fn without_cache(self) -> Self {
+            self
+        }
+
+        async fn cat(&self, _logger: &Logger, link: &Link) -> Result<Vec<u8>, Error> {
+            self.0
+                .get(&link.link)
+                .map(|contents| contents.as_bytes().to_vec())
+                .ok_or_else(|| anyhow!("file {} not found", link.link))
+        }
+
+        async fn json_stream(
+            &self,
+            _logger: &Logger,
+            _link: &Link,
+        ) -> Result<JsonValueStream, Error> {
+            Ok(Box::pin(futures03::stream::empty()))
+        }
+    }
+
+    fn resolver(missing: &[&str]) -> FakeResolver {
+        let mut files = HashMap::new();
+        files.insert("/ipfs/QmDeployment".to_string(), manifest());
+        files.insert("/ipfs/QmSchema".to_string(), SCHEMA.to_string());
+        files.insert("/ipfs/QmAbi".to_string(), "[]".to_string());
+        files.insert("/ipfs/QmMapping".to_string(), "wasm".to_string());
+        files.insert("/ipfs/QmTemplateMapping".to_string(), "wasm".to_string());
+        for link in missing {
+            files.remove(*link);
+        }
+        FakeResolver(files)
+    }
+
+    #[test]
+    fn links() {
+        let raw = serde_yaml::from_str(&manifest()).unwrap();
+        assert_eq!(
+            vec![
+                "/ipfs/QmSchema",
+                "/ipfs/QmAbi",
+                "/ipfs/QmMapping",
+                "/ipfs/QmTemplateMapping"
+            ],
+            manifest_links(&raw)
+        );
+        assert_eq!(Some("/ipfs/QmSchema"), schema_link(&raw));
+    }
+
+    #[tokio::test]
+    async fn report() {
+        let logger = Logger::root(graph::slog::Discard, o!());
+        let id = DeploymentHash::new("QmDeployment").unwrap();
+        let stored = Schema::parse(SCHEMA, id.clone()).unwrap();
+        let other = Schema::parse("type Other @entity { id: ID! }", id.clone()).unwrap();
+
+        let check = |resolver: FakeResolver, stored: Option<Schema>| {
+            let logger = logger.clone();
+            let id = id.clone();
+            async move { check_deployment_files(&logger, &resolver, &id, stored.as_ref()).await }
+        };
+
+        let report = check(resolver(&[]), Some(stored.clone())).await;
+        assert!(report.is_ok());
+        assert_eq!("QmDeployment", report.deployment);
+
+        // Missing files are reported once, even if they are used by
+        // several data sources
+        let report = check(
+            resolver(&["/ipfs/QmAbi", "/ipfs/QmTemplateMapping"]),
+            Some(stored.clone()),
+        )
+        .await;
+        let unresolvable: Vec<_> = report
+            .unresolvable
+            .iter()
+            .map(|file| file.link.as_str())
+            .collect();
+        assert_eq!(vec!["/ipfs/QmAbi", "/ipfs/QmTemplateMapping"], unresolvable);
+        assert!(report.mismatched.is_empty());
+
+        // If the manifest is gone, nothing else can be checked
+        let report = check(resolver(&["/ipfs/QmDeployment"]), Some(stored)).await;
+        assert_eq!(1, report.unresolvable.len());
+        assert_eq!("/ipfs/QmDeployment", report.unresolvable[0].link);
+
+        // The schema differs from the stored one
+        let report = check(resolver(&[]), Some(other)).await;
+        assert!(report.unresolvable.is_empty());
+        assert_eq!(vec!["/ipfs/QmSchema".to_string()], report.mismatched);
+    }
+}
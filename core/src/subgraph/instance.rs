@@ -1,4 +1,3 @@
-use futures01::sync::mpsc::Sender;
 use lazy_static::lazy_static;
 
 use std::collections::HashMap;
@@ -30,8 +29,9 @@ pub struct SubgraphInstance<C: Blockchain, T: RuntimeHostBuilder<C>> {
     /// stream events are processed by the mappings in this same order.
     hosts: Vec<Arc<T::Host>>,
 
-    /// Maps the hash of a module to a channel to the thread in which the module is instantiated.
-    module_cache: HashMap<[u8; 32], Sender<T::Req>>,
+    /// The data sources created from templates so far, used to make
+    /// creating the same data source again a no-op.
+    created_data_sources: CreatedDataSources,
 }
 
 impl<T, C: Blockchain> SubgraphInstance<C, T>
@@ -53,13 +53,18 @@ where
             subgraph_id,
             network,
             hosts: Vec::new(),
-            module_cache: HashMap::new(),
+            created_data_sources: Arc::new(HashMap::new()),
         };
 
         // Create a new runtime host for each data source in the subgraph manifest;
         // we use the same order here as in the subgraph manifest to make the
         // event processing behavior predictable
         for ds in manifest.data_sources {
+            if let Some(creation_block) = ds.creation_block() {
+                Arc::make_mut(&mut this.created_data_sources)
+                    .entry(DataSourceKey::of::<C>(&ds))
+                    .or_insert(creation_block);
+            }
             let host = this.new_host(
                 logger.cheap_clone(),
                 ds,
@@ -73,28 +78,18 @@ where
     }
 
     fn new_host(
-        &mut self,
+        &self,
         logger: Logger,
         data_source: C::DataSource,
         templates: Arc<Vec<C::DataSourceTemplate>>,
         host_metrics: Arc<HostMetrics>,
     ) -> Result<T::Host, Error> {
-        let mapping_request_sender = {
-            let module_bytes = data_source.runtime();
-            let module_hash = tiny_keccak::keccak256(module_bytes);
-            if let Some(sender) = self.module_cache.get(&module_hash) {
-                sender.clone()
-            } else {
-                let sender = T::spawn_mapping(
-                    module_bytes.to_owned(),
-                    logger,
-                    self.subgraph_id.clone(),
-                    host_metrics.clone(),
-                )?;
-                self.module_cache.insert(module_hash, sender.clone());
-                sender
-            }
-        };
+        let mapping_request_sender = self.host_builder.spawn_mapping(
+            data_source.runtime(),
+            logger,
+            self.subgraph_id.clone(),
+            host_metrics.clone(),
+        )?;
         self.host_builder.build(
             self.network.clone(),
             self.subgraph_id.clone(),
@@ -180,17 +175,38 @@ where
                 <= data_source.creation_block()
         );
 
+        let key = DataSourceKey::of::<C>(&data_source);
+        let creation_block = data_source.creation_block();
         let host =
             Arc::new(self.new_host(logger.clone(), data_source, templates, metrics.clone())?);
 
         Ok(if self.hosts.contains(&host) {
             None
         } else {
+            if let Some(creation_block) = creation_block {
+                Arc::make_mut(&mut self.created_data_sources)
+                    .entry(key)
+                    .or_insert(creation_block);
+            }
             self.hosts.push(host.clone());
             Some(host)
         })
     }
 
+    /// The data sources whose handlers that only run once run in `block`
+    pub(crate) fn once_handlers_in(&self, block: BlockNumber) -> Vec<DataSourceKey> {
+        self.hosts
+            .iter()
+            .filter(|host| host.once_handler_block() == Some(block))
+            .map(|host| host.data_source_key())
+            .collect()
+    }
+
+    /// The data sources created from templates so far
+    pub(crate) fn created_data_sources(&self) -> CreatedDataSources {
+        self.created_data_sources.cheap_clone()
+    }
+
     pub(crate) fn revert_data_sources(&mut self, reverted_block: BlockNumber) {
         // `hosts` is ordered by the creation block.
         // See also 8f1bca33-d3b7-4035-affc-fd6161a12448.
@@ -202,5 +218,7 @@ where
         {
             self.hosts.pop();
         }
+        Arc::make_mut(&mut self.created_data_sources)
+            .retain(|_, creation_block| *creation_block < reverted_block);
     }
 }
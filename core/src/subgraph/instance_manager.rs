@@ -1,32 +1,45 @@
+use super::latency::BlockLatency;
 use super::loader::load_dynamic_data_sources;
+use super::trigger_queue::{while_running, TriggerQueue};
 use super::SubgraphInstance;
 use atomic_refcell::AtomicRefCell;
 use fail::fail_point;
-use graph::blockchain::{BlockchainKind, DataSource};
+use graph::blockchain::{
+    BlockchainKind, DataSource, DataSourceTemplate as _, IngestorAdapter as _,
+};
 use graph::data::store::scalar::Bytes;
-use graph::data::subgraph::{UnifiedMappingApiVersion, MAX_SPEC_VERSION};
+use graph::data::subgraph::{
+    status::RevertReport, HeavyBlockPolicy, UnifiedMappingApiVersion, MAX_SPEC_VERSION,
+};
 use graph::prelude::TryStreamExt;
 use graph::prelude::{SubgraphInstanceManager as SubgraphInstanceManagerTrait, *};
 use graph::util::lfu_cache::LfuCache;
+use graph::util::scan_counter::{self, ScanCounter};
+use graph::util::shutdown;
 use graph::{blockchain::block_stream::BlockStreamMetrics, components::store::WritableStore};
 use graph::{blockchain::block_stream::BlockWithTriggers, data::subgraph::SubgraphFeature};
 use graph::{
     blockchain::NodeCapabilities,
     blockchain::TriggersAdapter,
-    data::subgraph::schema::{SubgraphError, POI_OBJECT},
+    data::subgraph::schema::{block_info, SubgraphError, BLOCK_INFO_OBJECT, POI_OBJECT},
 };
 use graph::{
     blockchain::{block_stream::BlockStreamEvent, Blockchain, TriggerFilter as _},
-    components::subgraph::{MappingError, ProofOfIndexing, SharedProofOfIndexing},
+    components::subgraph::{
+        MappingError, ProofOfIndexing, ProofOfIndexingEvent, SharedProofOfIndexing,
+        OPERATOR_INTERVENTION_REGION,
+    },
+    runtime::{DeterministicHostError, MAX_TRIGGER_BYTES},
 };
 use graph::{
     blockchain::{Block, BlockchainMap},
     components::store::{DeploymentId, DeploymentLocator, ModificationsAndCache},
 };
 use lazy_static::lazy_static;
+use std::any::Any;
 use std::collections::{BTreeSet, HashMap};
-use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::task;
 
 lazy_static! {
@@ -44,17 +57,33 @@ lazy_static! {
         std::env::var("GRAPH_DISABLE_FAIL_FAST").is_ok();
 }
 
+/// How often a running deployment looks for blocks that an operator told
+/// it to skip since it last looked. Operators may tell it that through any
+/// node, so we can not wait for a notification
+const SKIPPED_BLOCKS_RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
 type SharedInstanceKeepAliveMap = Arc<RwLock<HashMap<DeploymentId, CancelGuard>>>;
 
 struct IndexingInputs<C: Blockchain> {
     deployment: DeploymentLocator,
     features: BTreeSet<SubgraphFeature>,
     start_blocks: Vec<BlockNumber>,
+    /// The block after which the deployment has nothing left to do
+    end_block: Option<BlockNumber>,
     store: Arc<dyn WritableStore>,
     triggers_adapter: Arc<C::TriggersAdapter>,
     chain: Arc<C>,
     templates: Arc<Vec<C::DataSourceTemplate>>,
     unified_api_version: UnifiedMappingApiVersion,
+    heavy_block_limit: HeavyBlockLimit,
+}
+
+/// Blocks with more than `max_triggers` triggers are too heavy and get
+/// handled according to `policy`
+#[derive(Copy, Clone, Debug)]
+struct HeavyBlockLimit {
+    max_triggers: usize,
+    policy: HeavyBlockPolicy,
 }
 
 struct IndexingState<T: RuntimeHostBuilder<C>, C: Blockchain> {
@@ -63,6 +92,9 @@ struct IndexingState<T: RuntimeHostBuilder<C>, C: Blockchain> {
     instances: SharedInstanceKeepAliveMap,
     filter: C::TriggerFilter,
     entity_lfu_cache: LfuCache<EntityKey, Option<Entity>>,
+    /// The reasons for skipping blocks that an operator told us to skip,
+    /// by block hash
+    skipped_blocks: HashMap<String, String>,
 }
 
 struct IndexingContext<T: RuntimeHostBuilder<C>, C: Blockchain> {
@@ -89,10 +121,24 @@ pub struct SubgraphInstanceManager<S, M, L> {
     manager_metrics: SubgraphInstanceManagerMetrics,
     instances: SharedInstanceKeepAliveMap,
     link_resolver: Arc<L>,
+    /// What we prepared for the deployments this node is the standby for.
+    /// The values are `WarmDeployment`s for the chain of the deployment
+    warm: Mutex<HashMap<DeploymentId, Box<dyn Any + Send>>>,
+}
+
+/// A deployment that is ready to be started: its files are resolved, and
+/// the mappings of its data sources and templates are compiled and running
+struct WarmDeployment<C: Blockchain> {
+    /// The manifest the deployment was prepared from
+    source: serde_yaml::Mapping,
+    manifest: SubgraphManifest<C>,
+    host_metrics: Arc<HostMetrics>,
+    host_builder: graph_runtime_wasm::RuntimeHostBuilder<C>,
 }
 
 struct SubgraphInstanceManagerMetrics {
     pub subgraph_count: Box<Gauge>,
+    pub writer_lease_conflicts: Box<Counter>,
 }
 
 impl SubgraphInstanceManagerMetrics {
@@ -104,7 +150,17 @@ impl SubgraphInstanceManagerMetrics {
                 HashMap::new(),
             )
             .expect("failed to create `deployment_count` gauge");
-        Self { subgraph_count }
+        let writer_lease_conflicts = registry
+            .new_counter(
+                "deployment_writer_lease_conflicts",
+                "Counts deployments that could not be started because another node \
+                 holds their writer lease",
+            )
+            .expect("failed to create `deployment_writer_lease_conflicts` counter");
+        Self {
+            subgraph_count,
+            writer_lease_conflicts,
+        }
     }
 }
 
@@ -112,8 +168,18 @@ struct SubgraphInstanceMetrics {
     pub block_trigger_count: Box<Histogram>,
     pub block_processing_duration: Box<Histogram>,
     pub block_ops_transaction_duration: Box<Histogram>,
+    pub block_latency: Box<Histogram>,
+    pub block_lag: Box<Gauge>,
+    pub trigger_queue_items: Box<Gauge>,
+    pub trigger_queue_bytes: Box<Gauge>,
+    pub revert_duration: Box<Histogram>,
+    pub revert_entity_count: Box<Histogram>,
+    pub revert_blocks: Box<Histogram>,
+    pub heavy_blocks: Box<Counter>,
 
     trigger_processing_duration: Box<Histogram>,
+    heaviest_block_trigger_count: Box<Gauge>,
+    heaviest_block_number: Box<Gauge>,
 }
 
 impl SubgraphInstanceMetrics {
@@ -150,12 +216,113 @@ impl SubgraphInstanceMetrics {
                 vec![0.01, 0.05, 0.1, 0.3, 0.7, 2.0],
             )
             .expect("failed to create `deployment_transact_block_operations_duration_{}");
+        let block_latency = registry
+            .new_deployment_histogram(
+                "deployment_block_latency",
+                "Measures the time in seconds from a block's timestamp until its changes \
+                 are committed and queryable, for blocks close to the chain head",
+                subgraph_hash,
+                vec![
+                    1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0,
+                ],
+            )
+            .expect("failed to create `deployment_block_latency` histogram");
+        let block_lag = registry
+            .new_deployment_gauge(
+                "deployment_block_lag",
+                "Time in seconds between the timestamp of the last processed block \
+                 and the moment its changes were committed",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_block_lag` gauge");
+        let trigger_queue_items = registry
+            .new_deployment_gauge(
+                "deployment_trigger_queue_items",
+                "The number of blocks and reverts waiting to be processed",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_trigger_queue_items` gauge");
+        let trigger_queue_bytes = registry
+            .new_deployment_gauge(
+                "deployment_trigger_queue_bytes",
+                "The estimated size in bytes of the blocks waiting to be processed",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_trigger_queue_bytes` gauge");
+        let revert_duration = registry
+            .new_deployment_histogram(
+                "deployment_revert_duration",
+                "Measures the time in seconds it takes to revert all blocks of a reorg \
+                 for a subgraph deployment",
+                subgraph_hash,
+                vec![0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 120.0],
+            )
+            .expect("failed to create `deployment_revert_duration` histogram");
+        let revert_entity_count = registry
+            .new_deployment_histogram(
+                "deployment_revert_entity_count",
+                "Measures the number of entity versions that reverting all blocks of a \
+                 reorg restored or removed for a subgraph deployment",
+                subgraph_hash,
+                vec![1.0, 10.0, 100.0, 1000.0, 10000.0, 100000.0],
+            )
+            .expect("failed to create `deployment_revert_entity_count` histogram");
+        let revert_blocks = registry
+            .new_deployment_histogram(
+                "deployment_revert_blocks",
+                "Measures the number of blocks that a reorg reverted for a subgraph deployment",
+                subgraph_hash,
+                vec![1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 1000.0],
+            )
+            .expect("failed to create `deployment_revert_blocks` histogram");
+        let heavy_blocks = registry
+            .new_deployment_counter(
+                "deployment_heavy_blocks",
+                "Counts the blocks that had more triggers than a subgraph deployment allows",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_heavy_blocks` counter");
+        let heaviest_block_trigger_count = registry
+            .new_deployment_gauge(
+                "deployment_heaviest_block_trigger_count",
+                "The most triggers that a block had for a subgraph deployment since it started",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_heaviest_block_trigger_count` gauge");
+        let heaviest_block_number = registry
+            .new_deployment_gauge(
+                "deployment_heaviest_block_number",
+                "The number of the block with the most triggers for a subgraph deployment \
+                 since it started",
+                subgraph_hash,
+            )
+            .expect("failed to create `deployment_heaviest_block_number` gauge");
 
         Self {
             block_trigger_count,
             block_processing_duration,
             trigger_processing_duration,
             block_ops_transaction_duration,
+            block_latency,
+            block_lag,
+            trigger_queue_items,
+            trigger_queue_bytes,
+            revert_duration,
+            revert_entity_count,
+            revert_blocks,
+            heavy_blocks,
+            heaviest_block_trigger_count,
+            heaviest_block_number,
+        }
+    }
+
+    /// Remember the block `block` with `trigger_count` triggers if it is
+    /// the heaviest so far
+    pub fn observe_block_trigger_count(&self, block: BlockNumber, trigger_count: usize) {
+        self.block_trigger_count.observe(trigger_count as f64);
+        if trigger_count as f64 > self.heaviest_block_trigger_count.get() {
+            self.heaviest_block_trigger_count.set(trigger_count as f64);
+            self.heaviest_block_number.set(block as f64);
         }
     }
 
@@ -168,6 +335,16 @@ impl SubgraphInstanceMetrics {
         registry.unregister(self.block_trigger_count.clone());
         registry.unregister(self.trigger_processing_duration.clone());
         registry.unregister(self.block_ops_transaction_duration.clone());
+        registry.unregister(self.block_latency.clone());
+        registry.unregister(self.block_lag.clone());
+        registry.unregister(self.trigger_queue_items.clone());
+        registry.unregister(self.trigger_queue_bytes.clone());
+        registry.unregister(self.revert_duration.clone());
+        registry.unregister(self.revert_entity_count.clone());
+        registry.unregister(self.revert_blocks.clone());
+        registry.unregister(self.heavy_blocks.clone());
+        registry.unregister(self.heaviest_block_trigger_count.clone());
+        registry.unregister(self.heaviest_block_number.clone());
     }
 }
 
@@ -184,32 +361,52 @@ where
         manifest: serde_yaml::Mapping,
     ) {
         let logger = self.logger_factory.subgraph_logger(&loc);
-        let err_logger = logger.clone();
-        let instance_manager = self.cheap_clone();
-
-        let subgraph_start_future = async move {
-            match BlockchainKind::from_manifest(&manifest)? {
-                BlockchainKind::Ethereum => {
-                    instance_manager
-                        .start_subgraph_inner::<graph_chain_ethereum::Chain>(logger, loc, manifest)
-                        .await
-                }
-            }
-        };
+
         // Perform the actual work of starting the subgraph in a separate
         // task. If the subgraph is a graft or a copy, starting it will
         // perform the actual work of grafting/copying, which can take
         // hours. Running it in the background makes sure the instance
         // manager does not hang because of that work.
-        graph::spawn(async move {
-            match subgraph_start_future.await {
-                Ok(()) => self.manager_metrics.subgraph_count.inc(),
-                Err(err) => error!(
-                    err_logger,
-                    "Failed to start subgraph";
-                    "error" => format!("{}", err),
-                    "code" => LogCode::SubgraphStartFailure
-                ),
+        graph::spawn_essential("start-subgraph", move || {
+            let instance_manager = self.cheap_clone();
+            let logger = logger.clone();
+            let loc = loc.clone();
+            let manifest = manifest.clone();
+            let this = self.cheap_clone();
+            let err_logger = logger.clone();
+
+            let subgraph_start_future = async move {
+                match BlockchainKind::from_manifest(&manifest)? {
+                    BlockchainKind::Ethereum => {
+                        instance_manager
+                            .start_subgraph_inner::<graph_chain_ethereum::Chain>(
+                                logger, loc, manifest,
+                            )
+                            .await
+                    }
+                }
+            };
+            async move {
+                match subgraph_start_future.await {
+                    Ok(()) => this.manager_metrics.subgraph_count.inc(),
+                    Err(err) => match err.downcast_ref::<StoreError>() {
+                        Some(e @ StoreError::DeploymentLeaseHeld(..)) => {
+                            this.manager_metrics.writer_lease_conflicts.inc();
+                            error!(
+                                err_logger,
+                                "Refusing to start subgraph";
+                                "error" => format!("{}", e),
+                                "code" => LogCode::SubgraphWriterLeaseHeld
+                            )
+                        }
+                        _ => error!(
+                            err_logger,
+                            "Failed to start subgraph";
+                            "error" => format!("{}", err),
+                            "code" => LogCode::SubgraphStartFailure
+                        ),
+                    },
+                }
             }
         });
     }
@@ -224,6 +421,29 @@ where
 
         self.manager_metrics.subgraph_count.dec();
     }
+
+    async fn warm_up(self: Arc<Self>, loc: DeploymentLocator, manifest: serde_yaml::Mapping) {
+        let logger = self.logger_factory.subgraph_logger(&loc);
+        let result = match BlockchainKind::from_manifest(&manifest) {
+            Ok(BlockchainKind::Ethereum) => {
+                self.warm_up_inner::<graph_chain_ethereum::Chain>(&logger, &loc, manifest)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            warn!(logger, "Failed to prepare standby deployment";
+                  "error" => format!("{:#}", e));
+        }
+    }
+
+    fn cool_down(&self, loc: &DeploymentLocator) {
+        // Dropping the module cache stops the threads running the mappings
+        if self.warm.lock().unwrap().remove(&loc.id).is_some() {
+            let logger = self.logger_factory.subgraph_logger(loc);
+            info!(logger, "Dropped standby deployment");
+        }
+    }
 }
 
 impl<S, M, L> SubgraphInstanceManager<S, M, L>
@@ -250,16 +470,160 @@ where
             metrics_registry,
             instances: SharedInstanceKeepAliveMap::default(),
             link_resolver,
+            warm: Mutex::new(HashMap::new()),
         }
     }
 
+    /// The raw manifest whose mappings `deployment` runs. That is the one
+    /// it was reloaded with if its mappings were reloaded, and `manifest`
+    /// otherwise
+    async fn mappings_source(
+        &self,
+        logger: &Logger,
+        deployment: &DeploymentLocator,
+        manifest: serde_yaml::Mapping,
+    ) -> Result<serde_yaml::Mapping, Error> {
+        match self.subgraph_store.mappings_manifest(deployment)? {
+            Some(reloaded) => {
+                info!(logger, "Using reloaded subgraph mappings"; "manifest" => reloaded.to_string());
+                let file_bytes = self
+                    .link_resolver
+                    .as_ref()
+                    .clone()
+                    .with_retries()
+                    .cat(logger, &reloaded.to_ipfs_link())
+                    .await
+                    .context("Failed to resolve reloaded subgraph manifest from IPFS")?;
+                Ok(serde_yaml::from_slice(&file_bytes)?)
+            }
+            None => Ok(manifest),
+        }
+    }
+
+    async fn resolve_manifest<C: Blockchain>(
+        &self,
+        logger: &Logger,
+        deployment: &DeploymentLocator,
+        manifest: serde_yaml::Mapping,
+    ) -> Result<SubgraphManifest<C>, Error> {
+        info!(logger, "Resolve subgraph files using IPFS");
+
+        SubgraphManifest::resolve_from_raw(
+            deployment.hash.cheap_clone(),
+            manifest,
+            // Allow for infinite retries for subgraph definition files.
+            &self.link_resolver.as_ref().clone().with_retries(),
+            logger,
+            MAX_SPEC_VERSION.clone(),
+        )
+        .await
+        .context("Failed to resolve subgraph from IPFS")
+    }
+
+    fn host_builder<C: Blockchain>(
+        &self,
+        deployment: &DeploymentLocator,
+        chain: &C,
+        features: &BTreeSet<SubgraphFeature>,
+    ) -> Result<graph_runtime_wasm::RuntimeHostBuilder<C>, Error> {
+        // Use the trigger budget the deployment was created with so that
+        // the same triggers fail no matter how this node is configured
+        let max_trigger_bytes = self
+            .subgraph_store
+            .max_trigger_bytes(deployment)?
+            .unwrap_or(*MAX_TRIGGER_BYTES);
+        Ok(graph_runtime_wasm::RuntimeHostBuilder::new(
+            chain.runtime_adapter(),
+            self.link_resolver.cheap_clone(),
+            self.subgraph_store.cheap_clone(),
+            features,
+            max_trigger_bytes,
+        ))
+    }
+
+    /// Resolve the files of `deployment` and compile its mappings so that
+    /// starting it later does not have to
+    async fn warm_up_inner<C: Blockchain>(
+        &self,
+        logger: &Logger,
+        deployment: &DeploymentLocator,
+        manifest: serde_yaml::Mapping,
+    ) -> Result<(), Error> {
+        let source = self.mappings_source(logger, deployment, manifest).await?;
+        let manifest = self
+            .resolve_manifest::<C>(logger, deployment, source.clone())
+            .await?;
+
+        let network = self
+            .subgraph_store
+            .writable(deployment)?
+            .network_name()
+            .to_string();
+        let chain = self
+            .chains
+            .get::<C>(network.clone())
+            .with_context(|| format!("no chain configured for network {}", network))?
+            .clone();
+
+        let stopwatch_metrics = StopwatchMetrics::new(
+            logger.clone(),
+            deployment.hash.clone(),
+            self.metrics_registry.clone(),
+        );
+        let host_metrics = Arc::new(HostMetrics::new(
+            self.metrics_registry.cheap_clone(),
+            deployment.hash.as_str(),
+            stopwatch_metrics,
+        ));
+        let host_builder = self.host_builder(deployment, chain.as_ref(), &manifest.features)?;
+        let modules = manifest
+            .data_sources
+            .iter()
+            .map(|data_source| data_source.runtime())
+            .chain(manifest.templates.iter().map(|template| template.runtime()));
+        for module in modules {
+            host_builder.spawn_mapping(
+                module,
+                logger.clone(),
+                deployment.hash.clone(),
+                host_metrics.cheap_clone(),
+            )?;
+        }
+
+        let warm = WarmDeployment {
+            source,
+            manifest,
+            host_metrics,
+            host_builder,
+        };
+        self.warm
+            .lock()
+            .unwrap()
+            .insert(deployment.id, Box::new(warm));
+        info!(logger, "Prepared standby deployment");
+        Ok(())
+    }
+
+    /// Take what was prepared for `deployment` if it was prepared from the
+    /// raw manifest `source`
+    fn take_warm<C: Blockchain>(
+        &self,
+        deployment: &DeploymentLocator,
+        source: &serde_yaml::Mapping,
+    ) -> Option<WarmDeployment<C>> {
+        let warm = self.warm.lock().unwrap().remove(&deployment.id)?;
+        warm.downcast::<WarmDeployment<C>>()
+            .ok()
+            .map(|warm| *warm)
+            .filter(|warm| &warm.source == source)
+    }
+
     async fn start_subgraph_inner<C: Blockchain>(
         self: Arc<Self>,
         logger: Logger,
         deployment: DeploymentLocator,
         manifest: serde_yaml::Mapping,
     ) -> Result<(), Error> {
-        let subgraph_store = self.subgraph_store.cheap_clone();
         let registry = self.metrics_registry.cheap_clone();
         let store = self.subgraph_store.writable(&deployment)?;
 
@@ -282,19 +646,46 @@ where
             .and_then(|x| x)?;
         }
 
-        let manifest: SubgraphManifest<C> = {
-            info!(logger, "Resolve subgraph files using IPFS");
+        // A deployment that reached its end block only serves queries
+        if store.is_completed()? {
+            info!(logger, "Subgraph deployment is completed, not starting it");
+            return Ok(());
+        }
 
-            let mut manifest = SubgraphManifest::resolve_from_raw(
-                deployment.hash.cheap_clone(),
-                manifest,
-                // Allow for infinite retries for subgraph definition files.
-                &self.link_resolver.as_ref().clone().with_retries(),
-                &logger,
-                MAX_SPEC_VERSION.clone(),
-            )
-            .await
-            .context("Failed to resolve subgraph from IPFS")?;
+        // A deployment whose mappings were reloaded runs the mappings from
+        // the manifest it was reloaded with
+        let manifest = self.mappings_source(&logger, &deployment, manifest).await?;
+
+        // If this node was the standby for the deployment, its files are
+        // already resolved and its mappings compiled. Dynamic data sources
+        // and where the deployment is are still read from the store since
+        // the node that indexed the deployment until now changed them
+        let (manifest, warm) = match self.take_warm::<C>(&deployment, &manifest) {
+            Some(warm) => {
+                info!(logger, "Starting deployment prepared as standby");
+                (warm.manifest, Some((warm.host_metrics, warm.host_builder)))
+            }
+            None => (
+                self.resolve_manifest::<C>(&logger, &deployment, manifest)
+                    .await?,
+                None,
+            ),
+        };
+
+        let manifest: SubgraphManifest<C> = {
+            let mut manifest = manifest;
+
+            // Data sources in the manifest must know where the deployment
+            // starts so that their `once` block handlers run even if the
+            // deployment was grafted past their start block
+            let first_block = store
+                .block_ptr()?
+                .map(|ptr| ptr.number + 1)
+                .unwrap_or(0)
+                .max(manifest.first_block());
+            for data_source in manifest.data_sources.iter_mut() {
+                data_source.set_first_block(first_block);
+            }
 
             let data_sources = load_dynamic_data_sources::<C>(
                 store.clone(),
@@ -309,6 +700,18 @@ where
             // Add dynamic data sources to the subgraph
             manifest.data_sources.extend(data_sources);
 
+            // Handlers that only run once must not run again after a restart
+            let once_handler_runs: HashMap<_, _> = store
+                .once_handler_runs()?
+                .into_iter()
+                .map(|run| (run.data_source, run.block))
+                .collect();
+            for data_source in manifest.data_sources.iter_mut() {
+                if let Some(block) = once_handler_runs.get(&DataSourceKey::of::<C>(data_source)) {
+                    data_source.set_once_handlers_ran(*block);
+                }
+            }
+
             info!(
                 logger,
                 "Data source count at start: {}",
@@ -319,7 +722,10 @@ where
         };
 
         let required_capabilities = C::NodeCapabilities::from_data_sources(&manifest.data_sources);
-        let network = manifest.network_name();
+        // Use the network under which the store knows the deployment
+        // rather than the one in the manifest so that deployments keep
+        // working when their chain gets renamed
+        let network = store.network_name().to_string();
 
         let chain = self
             .chains
@@ -330,16 +736,31 @@ where
         // Obtain filters from the manifest
         let filter = C::TriggerFilter::from_data_sources(manifest.data_sources.iter());
         let start_blocks = manifest.start_blocks();
+        let end_block = manifest.end_block();
 
         let templates = Arc::new(manifest.templates.clone());
 
         // Create a subgraph instance from the manifest; this moves
         // ownership of the manifest and host builder into the new instance
-        let stopwatch_metrics = StopwatchMetrics::new(
-            logger.clone(),
-            deployment.hash.clone(),
-            self.metrics_registry.clone(),
-        );
+        let (host_metrics, host_builder) = match warm {
+            Some(warm) => warm,
+            None => {
+                let stopwatch_metrics = StopwatchMetrics::new(
+                    logger.clone(),
+                    deployment.hash.clone(),
+                    self.metrics_registry.clone(),
+                );
+                let host_metrics = Arc::new(HostMetrics::new(
+                    registry.cheap_clone(),
+                    deployment.hash.as_str(),
+                    stopwatch_metrics,
+                ));
+                let host_builder =
+                    self.host_builder(&deployment, chain.as_ref(), &manifest.features)?;
+                (host_metrics, host_builder)
+            }
+        };
+        let stopwatch_metrics = host_metrics.stopwatch.clone();
 
         let unified_mapping_api_version = manifest.unified_mapping_api_version()?;
         let triggers_adapter = chain.triggers_adapter(&deployment, &required_capabilities, unified_mapping_api_version ,stopwatch_metrics.clone()).map_err(|e|
@@ -353,15 +774,10 @@ where
             deployment.hash.as_str(),
         ));
         let subgraph_metrics_unregister = subgraph_metrics.clone();
-        let host_metrics = Arc::new(HostMetrics::new(
-            registry.cheap_clone(),
-            deployment.hash.as_str(),
-            stopwatch_metrics.clone(),
-        ));
         let block_stream_metrics = Arc::new(BlockStreamMetrics::new(
             registry.cheap_clone(),
             &deployment.hash,
-            manifest.network_name(),
+            network.clone(),
             store.shard().to_string(),
             stopwatch_metrics,
         ));
@@ -375,13 +791,11 @@ where
             .unwrap_or(0) as f64;
         block_stream_metrics.deployment_head.set(deployment_head);
 
-        let host_builder = graph_runtime_wasm::RuntimeHostBuilder::new(
-            chain.runtime_adapter(),
-            self.link_resolver.cheap_clone(),
-            subgraph_store,
-        );
-
         let features = manifest.features.clone();
+        let heavy_block_limit = HeavyBlockLimit {
+            max_triggers: manifest.max_triggers_per_block(),
+            policy: manifest.heavy_block_policy(),
+        };
         let unified_api_version = manifest.unified_mapping_api_version()?;
         let instance =
             SubgraphInstance::from_manifest(&logger, manifest, host_builder, host_metrics.clone())?;
@@ -392,11 +806,13 @@ where
                 deployment: deployment.clone(),
                 features,
                 start_blocks,
+                end_block,
                 store,
                 triggers_adapter,
                 chain,
                 templates,
                 unified_api_version,
+                heavy_block_limit,
             },
             state: IndexingState {
                 logger: logger.cheap_clone(),
@@ -404,6 +820,7 @@ where
                 instances: self.instances.cheap_clone(),
                 filter,
                 entity_lfu_cache: LfuCache::new(),
+                skipped_blocks: HashMap::new(),
             },
             subgraph_metrics,
             host_metrics,
@@ -423,6 +840,8 @@ where
         // scheduling. It is also logical in terms of performance to run this with `unconstrained`,
         // it has a dedicated OS thread so the OS will handle the preemption. See
         // https://github.com/tokio-rs/tokio/issues/3493.
+        let store = ctx.inputs.store.cheap_clone();
+        let scan = ScanCounter::for_deployment(&deployment.hash);
         graph::spawn_thread(deployment.to_string(), move || {
             if let Err(e) = graph::block_on(task::unconstrained(run_subgraph(ctx))) {
                 error!(
@@ -431,6 +850,9 @@ where
                     format!("{:#}", e)
                 );
             }
+            // Record what was counted since the last time; the counter goes
+            // away once nothing uses it anymore
+            record_scan_totals(&logger, store.as_ref(), &scan);
             subgraph_metrics_unregister.unregister(registry);
         });
 
@@ -448,11 +870,22 @@ where
     let store_for_err = ctx.inputs.store.cheap_clone();
     let logger = ctx.state.logger.cheap_clone();
     let id_for_err = ctx.inputs.deployment.hash.clone();
+    let chain_store = ctx.inputs.chain.chain_store();
+    let mut block_latency = BlockLatency::new();
+    let scan = ScanCounter::for_deployment(&ctx.inputs.deployment.hash);
+    let mut scan_flushed_at = Instant::now();
     let mut first_run = true;
+    // The reverts of the reorg we are in the middle of
+    let mut reorg: Option<RevertReport> = None;
 
     loop {
         debug!(logger, "Starting or restarting subgraph");
 
+        // Pick up blocks that an operator told us to skip since we last
+        // started
+        ctx.state.skipped_blocks = requested_skips(ctx.inputs.store.as_ref())?;
+        let mut skipped_blocks_loaded_at = Instant::now();
+
         let block_stream_canceler = CancelGuard::new();
         let block_stream_cancel_handle = block_stream_canceler.handle();
         let mut block_stream = ctx
@@ -479,11 +912,43 @@ where
 
         debug!(logger, "Starting block stream");
 
-        // Process events from the stream as long as no restart is needed
+        // The block stream fills the queue while we process blocks from it
+        let queue = TriggerQueue::new(
+            subgraph_metrics.trigger_queue_items.clone(),
+            subgraph_metrics.trigger_queue_bytes.clone(),
+        );
+        let producer = queue.fill_from(&logger, &mut block_stream).fuse();
+        futures::pin_mut!(producer);
+
+        // Process events from the queue as long as no restart is needed
         loop {
-            let (block, cursor) = match block_stream.next().await {
-                Some(Ok(BlockStreamEvent::ProcessBlock(block, cursor))) => (block, cursor),
-                Some(Ok(BlockStreamEvent::Revert(subgraph_ptr, _))) => {
+            let event = while_running(queue.pop(), &mut producer).await;
+
+            // Once the node shuts down, finish the block we are working on
+            // but do not start another one
+            let _shutdown_guard = shutdown::guard();
+            if shutdown::is_requested() {
+                info!(logger, "Stopping subgraph for shutdown");
+                if let Some(reorg) = reorg.take() {
+                    report_reorg(&logger, &subgraph_metrics, reorg);
+                }
+                ctx.state
+                    .instances
+                    .write()
+                    .unwrap()
+                    .remove(&ctx.inputs.deployment.id);
+                return Ok(());
+            }
+
+            let (block, cursor) = match event {
+                Some(BlockStreamEvent::ProcessBlock(block, cursor)) => {
+                    // Processing a block ends the reorg
+                    if let Some(reorg) = reorg.take() {
+                        report_reorg(&logger, &subgraph_metrics, reorg);
+                    }
+                    (block, cursor)
+                }
+                Some(BlockStreamEvent::Revert(subgraph_ptr, _)) => {
                     info!(
                         logger,
                         "Reverting block to get back to main chain";
@@ -493,7 +958,7 @@ where
 
                     // We would like to revert the DB state to the parent of the current block.
                     // First, load the block in order to get the parent hash.
-                    if let Err(e) = ctx
+                    match ctx
                         .inputs
                         .triggers_adapter
                         .parent_ptr(&subgraph_ptr)
@@ -504,18 +969,79 @@ where
                                 .store
                                 .revert_block_operations(parent_ptr)
                                 .map_err(Into::into)
-                        })
-                    {
-                        error!(
-                            &logger,
-                            "Could not revert block. \
-                            The likely cause is the block not being found due to a deep reorg. \
-                            Retrying";
-                            "block_number" => format!("{}", subgraph_ptr.number),
-                            "block_hash" => format!("{}", subgraph_ptr.hash),
-                            "error" => e.to_string(),
-                        );
-                        continue;
+                        }) {
+                        Ok(report) => match reorg.as_mut() {
+                            Some(reorg) if reorg.is_continued_by(&report) => reorg.extend(report),
+                            _ => {
+                                if let Some(reorg) = reorg.replace(report) {
+                                    report_reorg(&logger, &subgraph_metrics, reorg);
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            if let Some(e @ StoreError::DeploymentLeaseHeld(..)) =
+                                e.downcast_ref::<StoreError>()
+                            {
+                                ctx.block_stream_metrics.deployment_blocked.set(1.0);
+                                error!(
+                                    &logger,
+                                    "Refusing to revert block for subgraph";
+                                    "error" => e.to_string(),
+                                    "code" => LogCode::SubgraphWriterLeaseHeld
+                                );
+                                return Err(anyhow!("{}", e));
+                            }
+
+                            // Retrying can not get past the archive; fail
+                            // the subgraph so that operators notice
+                            if let Some(e @ StoreError::RevertPastArchive(..)) =
+                                e.downcast_ref::<StoreError>()
+                            {
+                                let message = e.to_string();
+                                error!(
+                                    &logger,
+                                    "Could not revert block";
+                                    "error" => &message,
+                                    "code" => LogCode::SubgraphSyncingFailure
+                                );
+                                let error = SubgraphError {
+                                    subgraph_id: id_for_err.clone(),
+                                    message: message.clone(),
+                                    block_ptr: Some(subgraph_ptr),
+                                    handler: None,
+                                    deterministic: false,
+                                };
+                                ctx.block_stream_metrics.deployment_failed.set(1.0);
+                                store_for_err
+                                    .fail_subgraph(error)
+                                    .await
+                                    .context("Failed to set subgraph status to `failed`")?;
+                                return Err(anyhow!(
+                                    "{}, code: {}",
+                                    message,
+                                    LogCode::SubgraphSyncingFailure
+                                ));
+                            }
+
+                            error!(
+                                &logger,
+                                "Could not revert block. \
+                                The likely cause is the block not being found due to a deep reorg. \
+                                Retrying";
+                                "block_number" => format!("{}", subgraph_ptr.number),
+                                "block_hash" => format!("{}", subgraph_ptr.hash),
+                                "error" => e.to_string(),
+                            );
+
+                            // The block stream has moved past this revert;
+                            // restart it so it starts over from the store
+                            ctx.state
+                                .instances
+                                .write()
+                                .unwrap()
+                                .remove(&ctx.inputs.deployment.id);
+                            break;
+                        }
                     }
 
                     ctx.block_stream_metrics
@@ -534,37 +1060,54 @@ where
                     ctx.state.entity_lfu_cache = LfuCache::new();
                     continue;
                 }
-                // Log and drop the errors from the block_stream
-                // The block stream will continue attempting to produce blocks
-                Some(Err(e)) => {
+                // The block stream only ends when it is canceled
+                None => {
+                    if let Some(reorg) = reorg.take() {
+                        report_reorg(&logger, &subgraph_metrics, reorg);
+                    }
                     debug!(
                         &logger,
-                        "Block stream produced a non-fatal error";
-                        "error" => format!("{}", e),
+                        "Subgraph block stream shut down cleanly";
+                        "id" => id_for_err.to_string(),
                     );
-                    continue;
+                    return Ok(());
                 }
-                None => unreachable!("The block stream stopped producing blocks"),
             };
 
             let block_ptr = block.ptr();
+            let block_timestamp = block.block.timestamp();
+
+            // Pick up blocks that an operator told us to skip while we are
+            // running, so that the skip also applies to blocks that we have
+            // not reached yet
+            if skipped_blocks_loaded_at.elapsed() >= SKIPPED_BLOCKS_RELOAD_INTERVAL {
+                match requested_skips(ctx.inputs.store.as_ref()) {
+                    Ok(skipped_blocks) => ctx.state.skipped_blocks = skipped_blocks,
+                    Err(e) => warn!(logger, "Failed to reload the blocks to skip";
+                                    "error" => e.to_string()),
+                }
+                skipped_blocks_loaded_at = Instant::now();
+            }
 
             if block.trigger_count() > 0 {
                 subgraph_metrics
-                    .block_trigger_count
-                    .observe(block.trigger_count() as f64);
+                    .observe_block_trigger_count(block_ptr.number, block.trigger_count());
             }
 
             let start = Instant::now();
             let deployment_failed = ctx.block_stream_metrics.deployment_failed.clone();
+            let deployment_blocked = ctx.block_stream_metrics.deployment_blocked.clone();
 
-            let res = process_block(
-                &logger,
-                ctx.inputs.triggers_adapter.cheap_clone(),
-                ctx,
-                block_stream_cancel_handle.clone(),
-                block,
-                cursor.into(),
+            let res = while_running(
+                process_block(
+                    &logger,
+                    ctx.inputs.triggers_adapter.cheap_clone(),
+                    ctx,
+                    block_stream_cancel_handle.clone(),
+                    block,
+                    cursor.into(),
+                ),
+                &mut producer,
             )
             .await;
 
@@ -583,6 +1126,66 @@ where
                         ctx.inputs.store.unfail()?;
                     }
                     deployment_failed.set(0.0);
+                    deployment_blocked.set(0.0);
+
+                    if let Some(timestamp) = block_timestamp {
+                        let latency = BlockLatency::latency(timestamp, SystemTime::now());
+                        subgraph_metrics.block_lag.set(latency);
+                        if block_latency.is_near_head(block_ptr.number, || {
+                            chain_store
+                                .chain_head_ptr()
+                                .ok()
+                                .flatten()
+                                .map(|ptr| ptr.number)
+                        }) {
+                            subgraph_metrics.block_latency.observe(latency);
+                            block_latency.observe(latency);
+                            if let Some((p50, p95)) = block_latency.report(Instant::now()) {
+                                if let Err(e) = ctx.inputs.store.set_block_latency(p50, p95) {
+                                    warn!(logger, "Failed to record block latency";
+                                          "error" => e.to_string());
+                                }
+                            }
+                        }
+                    }
+
+                    if scan_flushed_at.elapsed() >= scan_counter::FLUSH_INTERVAL {
+                        scan_flushed_at = Instant::now();
+                        record_scan_totals(&logger, ctx.inputs.store.as_ref(), &scan);
+                    }
+
+                    if let Some(end_block) = ctx.inputs.end_block {
+                        // Reorgs can not touch the end block anymore once
+                        // it is further behind the chain head than the
+                        // chain's reorg threshold
+                        let end_block_is_final = block_ptr.number >= end_block
+                            && chain_store
+                                .chain_head_ptr()?
+                                .map(|head| {
+                                    head.number
+                                        - ctx.inputs.chain.ingestor_adapter().ancestor_count()
+                                        >= end_block
+                                })
+                                .unwrap_or(false);
+                        if end_block_is_final {
+                            ctx.inputs.store.complete_subgraph()?;
+                            info!(
+                                logger,
+                                "Subgraph reached its end block, stopping it";
+                                "end_block" => end_block,
+                                "block_number" => block_ptr.number,
+                            );
+
+                            // Stop the block stream; queries are still served
+                            // from the store
+                            ctx.state
+                                .instances
+                                .write()
+                                .unwrap()
+                                .remove(&ctx.inputs.deployment.id);
+                            return Ok(());
+                        }
+                    }
 
                     if needs_restart {
                         // Cancel the stream for real
@@ -605,6 +1208,19 @@ where
                     return Ok(());
                 }
 
+                // Another node is writing to this deployment. Stop indexing
+                // without touching the deployment's state in the store
+                Err(BlockProcessingError::LeaseHeld(e)) => {
+                    deployment_blocked.set(1.0);
+                    error!(
+                        &logger,
+                        "Refusing to write to subgraph";
+                        "error" => e.to_string(),
+                        "code" => LogCode::SubgraphWriterLeaseHeld
+                    );
+                    return Err(e.into());
+                }
+
                 // Handle unexpected stream errors by marking the subgraph as failed.
                 Err(e) => {
                     let message = format!("{:#}", e).replace("\n", "\t");
@@ -643,12 +1259,32 @@ enum BlockProcessingError {
 
     #[error("subgraph stopped while processing triggers")]
     Canceled,
+
+    // Another node holds the writer lease for the deployment
+    #[error("{0}")]
+    LeaseHeld(StoreError),
 }
 
 impl BlockProcessingError {
     fn is_deterministic(&self) -> bool {
         matches!(self, BlockProcessingError::Deterministic(_))
     }
+
+    /// Errors from matching and decoding triggers are unknown, unless the
+    /// data source marked them as a `DeterministicHostError`, for example
+    /// because a log is too large for the event it should be decoded with
+    fn from_mapping_error(e: Error, deployment: &DeploymentHash, block_ptr: &BlockPtr) -> Self {
+        if e.downcast_ref::<DeterministicHostError>().is_none() {
+            return BlockProcessingError::Unknown(e);
+        }
+        BlockProcessingError::Deterministic(SubgraphError {
+            subgraph_id: deployment.clone(),
+            message: format!("{:#}", e).replace("\n", "\t"),
+            block_ptr: Some(block_ptr.cheap_clone()),
+            handler: None,
+            deterministic: true,
+        })
+    }
 }
 
 impl From<Error> for BlockProcessingError {
@@ -657,6 +1293,107 @@ impl From<Error> for BlockProcessingError {
     }
 }
 
+impl HeavyBlockLimit {
+    /// Apply the policy to `triggers` if `block_ptr` has too many of them,
+    /// and return the triggers that should be processed
+    fn apply<T>(
+        &self,
+        logger: &Logger,
+        deployment: &DeploymentHash,
+        block_ptr: &BlockPtr,
+        proof_of_indexing: Option<&Arc<AtomicRefCell<ProofOfIndexing>>>,
+        heavy_blocks: &Counter,
+        triggers: Vec<T>,
+    ) -> Result<Vec<T>, BlockProcessingError> {
+        if triggers.len() <= self.max_triggers {
+            return Ok(triggers);
+        }
+
+        heavy_blocks.inc();
+        let max = self.max_triggers;
+        match self.policy {
+            HeavyBlockPolicy::Warn => {
+                warn!(logger, "Block has more triggers than the subgraph allows, processing it anyway";
+                      "triggers" => triggers.len(),
+                      "max_triggers_per_block" => max);
+                Ok(triggers)
+            }
+            HeavyBlockPolicy::Skip => {
+                error!(logger, "Skipping block with more triggers than the subgraph allows without running any handlers";
+                       "triggers" => triggers.len(),
+                       "max_triggers_per_block" => max);
+                if let Some(proof_of_indexing) = proof_of_indexing {
+                    proof_of_indexing.borrow_mut().write(
+                        logger,
+                        OPERATOR_INTERVENTION_REGION,
+                        &ProofOfIndexingEvent::SkipHeavyBlock {
+                            block_hash: &block_ptr.hash.to_string(),
+                            trigger_count: triggers.len() as u64,
+                        },
+                    );
+                }
+                Ok(vec![])
+            }
+            HeavyBlockPolicy::Fail => Err(BlockProcessingError::Deterministic(SubgraphError {
+                subgraph_id: deployment.clone(),
+                message: format!(
+                    "block has {} triggers, but the subgraph allows at most {} per block",
+                    triggers.len(),
+                    max
+                ),
+                block_ptr: Some(block_ptr.cheap_clone()),
+                handler: None,
+                deterministic: true,
+            })),
+        }
+    }
+}
+
+/// Record what reverting the blocks of a reorg did
+fn report_reorg(logger: &Logger, metrics: &SubgraphInstanceMetrics, reorg: RevertReport) {
+    metrics
+        .revert_duration
+        .observe(reorg.duration_ms as f64 / 1000.0);
+    metrics
+        .revert_entity_count
+        .observe((reorg.entities_restored() + reorg.entities_removed()) as f64);
+    metrics.revert_blocks.observe(reorg.blocks() as f64);
+    info!(logger, "Reverted blocks of reorg";
+          "from_block" => reorg.from_block,
+          "to_block" => reorg.to_block,
+          "blocks" => reorg.blocks(),
+          "entities_restored" => reorg.entities_restored(),
+          "entities_removed" => reorg.entities_removed(),
+          "entity_types" => reorg.entity_types(),
+          "dynamic_data_sources_removed" => reorg.dynamic_data_sources_removed,
+          "time_ms" => reorg.duration_ms);
+}
+
+/// Add what `scan` counted to the scan totals of the deployment. If that
+/// fails, the counts are kept to try again later
+fn record_scan_totals(logger: &Logger, store: &dyn WritableStore, scan: &ScanCounter) {
+    let totals = scan.take();
+    if totals.is_empty() {
+        return;
+    }
+    if let Err(e) = store.add_scan_totals(&totals) {
+        scan.restore(&totals);
+        warn!(logger, "Failed to record scan totals";
+              "error" => e.to_string());
+    }
+}
+
+/// The reasons for the blocks that an operator told the deployment to skip
+/// and that it has not skipped yet, by block hash
+fn requested_skips(store: &dyn WritableStore) -> Result<HashMap<String, String>, StoreError> {
+    Ok(store
+        .skipped_blocks()?
+        .into_iter()
+        .filter(|skipped| skipped.is_requested())
+        .map(|skipped| (skipped.block_hash, skipped.reason))
+        .collect())
+}
+
 /// Processes a block and returns the updated context and a boolean flag indicating
 /// whether new dynamic data sources have been added to the subgraph.
 async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
@@ -702,6 +1439,44 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         None
     };
 
+    // Run no handlers for blocks that an operator told us to skip, but
+    // make the skip part of the proof of indexing
+    let triggers = match ctx.state.skipped_blocks.get(&block_ptr.hash.to_string()) {
+        Some(reason) => {
+            error!(&logger, "Skipping block on operator request without running any handlers";
+                   "reason" => reason,
+                   "skipped_triggers" => triggers.len());
+            if let Some(proof_of_indexing) = &proof_of_indexing {
+                proof_of_indexing.borrow_mut().write(
+                    &logger,
+                    OPERATOR_INTERVENTION_REGION,
+                    &ProofOfIndexingEvent::SkipBlock {
+                        block_hash: &block_ptr.hash.to_string(),
+                    },
+                );
+            }
+            ctx.inputs
+                .store
+                .record_skipped_block(&block_ptr)
+                .map_err(|e| BlockProcessingError::Unknown(e.into()))?;
+            vec![]
+        }
+        None => triggers,
+    };
+
+    // The triggers have been matched against the filters but not decoded
+    // yet, so a block with too many of them costs little so far. Only the
+    // triggers for the data sources that existed at the start of the block
+    // count, since we can only skip a block before processing any of it
+    let triggers = ctx.inputs.heavy_block_limit.apply(
+        &logger,
+        &ctx.inputs.deployment.hash,
+        &block_ptr,
+        proof_of_indexing.as_ref(),
+        &metrics.heavy_blocks,
+        triggers,
+    )?;
+
     // Process events one after the other, passing in entity operations
     // collected previously to every new event being processed
     let mut block_state = match process_triggers(
@@ -709,7 +1484,8 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         BlockState::new(
             ctx.inputs.store.clone(),
             std::mem::take(&mut ctx.state.entity_lfu_cache),
-        ),
+        )
+        .with_existing_data_sources(ctx.state.instance.created_data_sources()),
         proof_of_indexing.cheap_clone(),
         ctx.subgraph_metrics.clone(),
         &ctx.state.instance,
@@ -722,7 +1498,13 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         Ok(block_state) => block_state,
 
         // Some form of unknown or non-deterministic error ocurred.
-        Err(MappingError::Unknown(e)) => return Err(BlockProcessingError::Unknown(e)),
+        Err(MappingError::Unknown(e)) => {
+            return Err(BlockProcessingError::from_mapping_error(
+                e,
+                &ctx.inputs.deployment.hash,
+                &block_ptr,
+            ))
+        }
         Err(MappingError::PossibleReorg(e)) => {
             info!(ctx.state.logger,
                     "Possible reorg detected, retrying";
@@ -796,6 +1578,7 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
 
         // Process the triggers in each host in the same order the
         // corresponding data sources have been created.
+        use graph::blockchain::TriggerData;
         for trigger in triggers {
             block_state = SubgraphInstance::<C, T>::process_trigger_in_runtime_hosts(
                 &logger,
@@ -806,15 +1589,24 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
                 proof_of_indexing.cheap_clone(),
             )
             .await
-            .map_err(|e| {
+            .map_err(|mut e| {
+                // Name the trigger, like `process_triggers` does
+                let error_context = trigger.error_context();
+                if !error_context.is_empty() {
+                    e = e.context(error_context);
+                }
+
                 // This treats a `PossibleReorg` as an ordinary error which will fail the subgraph.
                 // This can cause an unnecessary subgraph failure, to fix it we need to figure out a
                 // way to revert the effect of `create_dynamic_data_sources` so we may return a
                 // clean context as in b21fa73b-6453-4340-99fb-1a78ec62efb1.
                 match e {
-                    MappingError::PossibleReorg(e) | MappingError::Unknown(e) => {
-                        BlockProcessingError::Unknown(e)
-                    }
+                    MappingError::PossibleReorg(e) => BlockProcessingError::Unknown(e),
+                    MappingError::Unknown(e) => BlockProcessingError::from_mapping_error(
+                        e,
+                        &ctx.inputs.deployment.hash,
+                        &block_ptr,
+                    ),
                 }
             })?;
         }
@@ -853,6 +1645,19 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         .await?;
     }
 
+    // The proof of indexing only covers what mappings do, and the block
+    // info is derived from the block alone, so it stays out of the proof
+    if ctx.inputs.features.contains(&SubgraphFeature::BlockInfo) {
+        let key = EntityKey {
+            subgraph_id: ctx.inputs.deployment.hash.clone(),
+            entity_type: BLOCK_INFO_OBJECT.to_owned(),
+            entity_id: block_ptr.hash.to_string(),
+        };
+        block_state
+            .entity_cache
+            .set(key, block_info(block.as_ref()));
+    }
+
     let section = ctx.host_metrics.stopwatch.start_section("as_modifications");
     let ModificationsAndCache {
         modifications: mods,
@@ -888,6 +1693,19 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
         );
     }
 
+    // Remember which handlers that only run once ran so that they do not
+    // run again after a restart. Skipping a block on operator request
+    // skips them like all other handlers in the block. This happens before
+    // the block is transacted since a block that is processed again after
+    // a crash runs them again in the same block
+    let once_handlers = ctx.state.instance.once_handlers_in(block_ptr.number);
+    if !once_handlers.is_empty() {
+        ctx.inputs
+            .store
+            .record_once_handler_runs(block_ptr.number, once_handlers)
+            .map_err(|e| BlockProcessingError::Unknown(e.into()))?;
+    }
+
     // Transact entity operations into the store and update the
     // subgraph's block stream pointer
     let _section = ctx.host_metrics.stopwatch.start_section("transact_block");
@@ -923,6 +1741,8 @@ async fn process_block<T: RuntimeHostBuilder<C>, C: Blockchain>(
             Ok((ctx, needs_restart))
         }
 
+        Err(e @ StoreError::DeploymentLeaseHeld(..)) => Err(BlockProcessingError::LeaseHeld(e)),
+
         Err(e) => Err(anyhow!("Error while processing block stream for a subgraph: {}", e).into()),
     }
 }
@@ -1082,3 +1902,99 @@ fn persist_dynamic_data_sources<T: RuntimeHostBuilder<C>, C: Blockchain>(
     // Merge filters from data sources into the block stream builder
     ctx.state.filter.extend(data_sources.iter());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::prelude::web3::types::H256;
+
+    /// The blocks 1 to 3 of a block stream where block 2 has three
+    /// triggers and the other blocks have one. Triggers are stand-ins since
+    /// applying the limit only counts them
+    fn block_stream() -> Vec<(BlockPtr, Vec<usize>)> {
+        (1..=3)
+            .map(|number: u64| {
+                let ptr = BlockPtr::from((H256::from_low_u64_be(number), number));
+                let trigger_count = if number == 2 { 3 } else { 1 };
+                (ptr, (0..trigger_count).collect())
+            })
+            .collect()
+    }
+
+    /// Run the blocks from `block_stream` through a limit of two triggers
+    /// per block with `policy`, and return what `apply` returned for each
+    /// block, whether anything was written to the proof of indexing of
+    /// each block, and how many heavy blocks were counted
+    fn run_blocks(
+        policy: HeavyBlockPolicy,
+    ) -> (Vec<Result<usize, BlockProcessingError>>, Vec<bool>, f64) {
+        let logger = Logger::root(slog::Discard, o!());
+        let deployment = DeploymentHash::new("heavyBlocks").unwrap();
+        let limit = HeavyBlockLimit {
+            max_triggers: 2,
+            policy,
+        };
+        let heavy_blocks = Counter::new("heavy_blocks", "test counter").unwrap();
+
+        let mut results = Vec::new();
+        let mut poi_written = Vec::new();
+        for (block_ptr, triggers) in block_stream() {
+            let proof_of_indexing =
+                Arc::new(AtomicRefCell::new(ProofOfIndexing::new(block_ptr.number)));
+            let result = limit.apply(
+                &logger,
+                &deployment,
+                &block_ptr,
+                Some(&proof_of_indexing),
+                &heavy_blocks,
+                triggers,
+            );
+            results.push(result.map(|triggers| triggers.len()));
+            let proof_of_indexing = Arc::try_unwrap(proof_of_indexing).unwrap().into_inner();
+            poi_written.push(
+                proof_of_indexing
+                    .take()
+                    .contains_key(OPERATOR_INTERVENTION_REGION),
+            );
+        }
+        (results, poi_written, heavy_blocks.get())
+    }
+
+    fn trigger_counts(results: Vec<Result<usize, BlockProcessingError>>) -> Vec<usize> {
+        results.into_iter().map(|result| result.unwrap()).collect()
+    }
+
+    #[test]
+    fn heavy_blocks_are_processed_with_warn() {
+        let (results, poi_written, heavy_blocks) = run_blocks(HeavyBlockPolicy::Warn);
+        assert_eq!(vec![1, 3, 1], trigger_counts(results));
+        assert_eq!(vec![false, false, false], poi_written);
+        assert_eq!(1.0, heavy_blocks);
+    }
+
+    #[test]
+    fn heavy_blocks_are_skipped_with_skip() {
+        let (results, poi_written, heavy_blocks) = run_blocks(HeavyBlockPolicy::Skip);
+        assert_eq!(vec![1, 0, 1], trigger_counts(results));
+        assert_eq!(vec![false, true, false], poi_written);
+        assert_eq!(1.0, heavy_blocks);
+    }
+
+    #[test]
+    fn heavy_blocks_fail_the_subgraph_with_fail() {
+        let (mut results, poi_written, heavy_blocks) = run_blocks(HeavyBlockPolicy::Fail);
+        match results.remove(1) {
+            Err(BlockProcessingError::Deterministic(error)) => {
+                assert_eq!(Some(2), error.block_ptr.map(|ptr| ptr.number));
+                assert_eq!(
+                    "block has 3 triggers, but the subgraph allows at most 2 per block",
+                    error.message
+                );
+            }
+            _ => panic!("expected a deterministic error for the heavy block"),
+        }
+        assert_eq!(vec![1, 1], trigger_counts(results));
+        assert_eq!(vec![false, false, false], poi_written);
+        assert_eq!(1.0, heavy_blocks);
+    }
+}
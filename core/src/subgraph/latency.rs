@@ -0,0 +1,175 @@
+//! Track how long it takes from a block being produced on chain until the
+//! entity changes it causes can be queried
+use graph::prelude::BlockNumber;
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    /// Only blocks that are at most this many blocks behind the chain head
+    /// are included in the latency measurements so that initial sync does
+    /// not distort them
+    static ref HEAD_DISTANCE: BlockNumber = std::env::var("GRAPH_BLOCK_LATENCY_HEAD_DISTANCE")
+        .unwrap_or("10".into())
+        .parse::<BlockNumber>()
+        .expect("invalid GRAPH_BLOCK_LATENCY_HEAD_DISTANCE");
+}
+
+/// The number of recent measurements from which percentiles are computed
+const SAMPLES: usize = 500;
+
+/// How often percentiles are reported
+const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(crate) struct BlockLatency {
+    head_distance: BlockNumber,
+    /// The highest chain head we have seen. The chain head only moves
+    /// forward, so that blocks that are far behind this are certainly not
+    /// close to the head, and we do not need to look it up again for them
+    chain_head: Option<BlockNumber>,
+    samples: VecDeque<f64>,
+    last_report: Option<Instant>,
+}
+
+impl BlockLatency {
+    pub fn new() -> Self {
+        Self::with_head_distance(*HEAD_DISTANCE)
+    }
+
+    fn with_head_distance(head_distance: BlockNumber) -> Self {
+        BlockLatency {
+            head_distance,
+            chain_head: None,
+            samples: VecDeque::with_capacity(SAMPLES),
+            last_report: None,
+        }
+    }
+
+    /// Return `true` if `block` is close enough to the chain head to be
+    /// measured. `chain_head` is only called when the chain head we
+    /// remember does not already show that `block` is too far behind
+    pub fn is_near_head<F>(&mut self, block: BlockNumber, chain_head: F) -> bool
+    where
+        F: FnOnce() -> Option<BlockNumber>,
+    {
+        let distance = self.head_distance;
+        let far_behind = |head: BlockNumber| block.saturating_add(distance) < head;
+
+        if self.chain_head.map(far_behind).unwrap_or(false) {
+            return false;
+        }
+        if let Some(head) = chain_head() {
+            self.chain_head = Some(self.chain_head.map_or(head, |known| known.max(head)));
+        }
+        match self.chain_head {
+            Some(head) => !far_behind(head),
+            None => false,
+        }
+    }
+
+    /// The latency in seconds of a block with the given `timestamp`, in
+    /// seconds since the epoch, that became queryable at `now`. Clocks on
+    /// the chain and on this machine are not perfectly in sync, and the
+    /// latency is therefore never less than 0
+    pub fn latency(timestamp: u64, now: SystemTime) -> f64 {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        (now - timestamp as f64).max(0.0)
+    }
+
+    /// Remember `latency` for computing percentiles
+    pub fn observe(&mut self, latency: f64) {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    /// Return the median and 95th percentile of recent latencies if they
+    /// have not been reported in a while
+    pub fn report(&mut self, now: Instant) -> Option<(f64, f64)> {
+        if let Some(last_report) = self.last_report {
+            if now.saturating_duration_since(last_report) < REPORT_INTERVAL {
+                return None;
+            }
+        }
+        let percentiles = self.percentiles()?;
+        self.last_report = Some(now);
+        Some(percentiles)
+    }
+
+    fn percentiles(&self) -> Option<(f64, f64)> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.samples.iter().cloned().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let at = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+        Some((at(0.5), at(0.95)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn latency_is_never_negative() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(12.0, BlockLatency::latency(988, now));
+        assert_eq!(0.0, BlockLatency::latency(1005, now));
+    }
+
+    #[test]
+    fn only_blocks_near_head_are_measured() {
+        let mut latency = BlockLatency::with_head_distance(10);
+        let lookups = Cell::new(0);
+        let head = |number| {
+            lookups.set(lookups.get() + 1);
+            Some(number)
+        };
+
+        // Initial sync: the head is only looked up once
+        assert!(!latency.is_near_head(1, || head(1000)));
+        assert!(!latency.is_near_head(2, || head(1000)));
+        assert!(!latency.is_near_head(989, || head(1000)));
+        assert_eq!(1, lookups.get());
+
+        // Close to the head, we check it every time
+        assert!(latency.is_near_head(990, || head(1000)));
+        assert!(latency.is_near_head(995, || head(1001)));
+        assert_eq!(3, lookups.get());
+
+        // The head moved far ahead
+        assert!(!latency.is_near_head(996, || head(2000)));
+        assert!(!latency.is_near_head(997, || head(2000)));
+        assert_eq!(4, lookups.get());
+
+        // Without a known head, nothing is measured
+        let mut latency = BlockLatency::with_head_distance(10);
+        assert!(!latency.is_near_head(1, || None));
+    }
+
+    #[test]
+    fn reports_percentiles_periodically() {
+        let mut latency = BlockLatency::with_head_distance(10);
+        let start = Instant::now();
+        assert_eq!(None, latency.report(start));
+
+        for i in 1..=100 {
+            latency.observe(i as f64);
+        }
+        assert_eq!(Some((51.0, 95.0)), latency.report(start));
+        assert_eq!(None, latency.report(start + Duration::from_secs(1)));
+
+        latency.observe(1000.0);
+        let (p50, p95) = latency
+            .report(start + REPORT_INTERVAL)
+            .expect("report is due");
+        assert_eq!(51.0, p50);
+        assert_eq!(96.0, p95);
+    }
+}
@@ -1,8 +1,11 @@
+mod file_check;
 mod instance;
 mod instance_manager;
+mod latency;
 mod loader;
 mod provider;
 mod registrar;
+mod trigger_queue;
 
 pub use self::instance::SubgraphInstance;
 pub use self::instance_manager::SubgraphInstanceManager;
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Mutex;
 
 use async_trait::async_trait;
@@ -11,6 +11,8 @@ use graph::{
 pub struct SubgraphAssignmentProvider<L, I> {
     logger_factory: LoggerFactory,
     subgraphs_running: Arc<Mutex<HashSet<DeploymentId>>>,
+    /// The standby deployments that the instance manager keeps prepared
+    subgraphs_warm: Mutex<HashMap<DeploymentId, DeploymentLocator>>,
     link_resolver: Arc<L>,
     instance_manager: Arc<I>,
 }
@@ -28,6 +30,7 @@ where
         SubgraphAssignmentProvider {
             logger_factory,
             subgraphs_running: Arc::new(Mutex::new(HashSet::new())),
+            subgraphs_warm: Mutex::new(HashMap::new()),
             link_resolver: Arc::new(link_resolver.as_ref().cheap_clone().with_retries()),
             instance_manager: Arc::new(instance_manager),
         }
@@ -52,14 +55,11 @@ where
             ));
         }
 
-        let file_bytes = self
-            .link_resolver
-            .cat(&logger, &loc.hash.to_ipfs_link())
-            .await
-            .map_err(SubgraphAssignmentProviderError::ResolveError)?;
+        // The instance manager uses what it prepared for the deployment
+        // while this node was its standby
+        self.subgraphs_warm.lock().unwrap().remove(&loc.id);
 
-        let raw: serde_yaml::Mapping = serde_yaml::from_slice(&file_bytes)
-            .map_err(|e| SubgraphAssignmentProviderError::ResolveError(e.into()))?;
+        let raw = self.manifest(&logger, &loc).await?;
 
         self.instance_manager
             .cheap_clone()
@@ -87,4 +87,66 @@ where
             Err(SubgraphAssignmentProviderError::NotRunning(deployment))
         }
     }
+
+    async fn keep_warm(&self, deployments: Vec<DeploymentLocator>) {
+        let stale: Vec<_> = {
+            let wanted: HashSet<_> = deployments.iter().map(|loc| loc.id).collect();
+            let mut warm = self.subgraphs_warm.lock().unwrap();
+            let stale = warm
+                .keys()
+                .filter(|id| !wanted.contains(id))
+                .cloned()
+                .collect::<Vec<_>>();
+            stale
+                .into_iter()
+                .filter_map(|id| warm.remove(&id))
+                .collect()
+        };
+        for loc in stale {
+            self.instance_manager.cool_down(&loc);
+        }
+
+        for loc in deployments {
+            // Deployments that run here already need no standby
+            if self.subgraphs_warm.lock().unwrap().contains_key(&loc.id)
+                || self.subgraphs_running.lock().unwrap().contains(&loc.id)
+            {
+                continue;
+            }
+
+            let logger = self.logger_factory.subgraph_logger(&loc);
+            match self.manifest(&logger, &loc).await {
+                Ok(raw) => {
+                    self.instance_manager
+                        .cheap_clone()
+                        .warm_up(loc.clone(), raw)
+                        .await;
+                    self.subgraphs_warm.lock().unwrap().insert(loc.id, loc);
+                }
+                Err(e) => warn!(logger, "Failed to get the manifest of standby deployment";
+                                "error" => e.to_string()),
+            }
+        }
+    }
+}
+
+impl<L, I> SubgraphAssignmentProvider<L, I>
+where
+    L: LinkResolver,
+    I: SubgraphInstanceManager,
+{
+    async fn manifest(
+        &self,
+        logger: &Logger,
+        loc: &DeploymentLocator,
+    ) -> Result<serde_yaml::Mapping, SubgraphAssignmentProviderError> {
+        let file_bytes = self
+            .link_resolver
+            .cat(logger, &loc.hash.to_ipfs_link())
+            .await
+            .map_err(SubgraphAssignmentProviderError::ResolveError)?;
+
+        serde_yaml::from_slice(&file_bytes)
+            .map_err(|e| SubgraphAssignmentProviderError::ResolveError(e.into()))
+    }
 }
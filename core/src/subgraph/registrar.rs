@@ -1,28 +1,52 @@
 use std::collections::HashSet;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use graph::blockchain::Blockchain;
 use graph::blockchain::BlockchainKind;
 use graph::blockchain::BlockchainMap;
-use graph::components::store::{DeploymentId, DeploymentLocator, SubscriptionManager};
+use graph::blockchain::DataSource;
+use graph::blockchain::NodeCapabilities;
+use graph::blockchain::RuntimeAdapter;
+use graph::components::store::{
+    DeploymentId, DeploymentLocator, SubscriptionManager, VersionComparison,
+};
+use graph::data::subgraph::features::{imports_disabled_eth_call, unused_features};
+use graph::data::subgraph::labels::validate_labels;
 use graph::data::subgraph::schema::SubgraphDeploymentEntity;
-use graph::data::subgraph::MAX_SPEC_VERSION;
+use graph::data::subgraph::status::{MappingsReload, SkippedBlock};
+use graph::data::subgraph::{DeploymentProfile, Labels, MAX_SPEC_VERSION};
 use graph::prelude::{
-    CreateSubgraphResult, SubgraphAssignmentProvider as SubgraphAssignmentProviderTrait,
+    CreateSubgraphResult, DeploymentFilesReport,
+    SubgraphAssignmentProvider as SubgraphAssignmentProviderTrait,
     SubgraphRegistrar as SubgraphRegistrarTrait, *,
 };
+use graph::util::scan_counter::ScanCounter;
+use graph_runtime_wasm::module::registry::validate_imports;
+
+use super::file_check::check_deployment_files;
+
+/// How often a node checks which deployments it is the standby for
+const STANDBY_WARM_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct SubgraphRegistrar<L, P, S, SM> {
     logger: Logger,
     logger_factory: LoggerFactory,
     resolver: Arc<L>,
+    /// A resolver that gives up instead of retrying forever, for checking
+    /// whether files can still be resolved
+    check_resolver: Arc<L>,
     provider: Arc<P>,
     store: Arc<S>,
     subscription_manager: Arc<SM>,
     chains: Arc<BlockchainMap>,
     node_id: NodeId,
     version_switching_mode: SubgraphVersionSwitchingMode,
+    retry_failed: RetryFailedPolicy,
+    /// Whether operations that are only meant for developing subgraphs,
+    /// like reloading mappings, are allowed
+    dev_mode: bool,
+    unresolvable_files: Box<GaugeVec>,
     assignment_event_stream_cancel_guard: CancelGuard, // cancels on drop
 }
 
@@ -42,20 +66,35 @@ where
         chains: Arc<BlockchainMap>,
         node_id: NodeId,
         version_switching_mode: SubgraphVersionSwitchingMode,
+        retry_failed: RetryFailedPolicy,
+        dev_mode: bool,
+        registry: Arc<impl MetricsRegistry>,
     ) -> Self {
         let logger = logger_factory.component_logger("SubgraphRegistrar", None);
         let logger_factory = logger_factory.with_parent(logger.clone());
+        let unresolvable_files = registry
+            .new_gauge_vec(
+                "deployment_unresolvable_files",
+                "The number of files of a deployment that could not be resolved or differ from \
+                 what is stored, as of the last time the files were validated",
+                vec![String::from("deployment")],
+            )
+            .expect("failed to create `deployment_unresolvable_files` gauge");
 
         SubgraphRegistrar {
             logger,
             logger_factory,
             resolver: Arc::new(resolver.as_ref().clone().with_retries()),
+            check_resolver: resolver,
             provider,
             store,
             subscription_manager,
             chains,
             node_id,
             version_switching_mode,
+            retry_failed,
+            dev_mode,
+            unresolvable_files,
             assignment_event_stream_cancel_guard: CancelGuard::new(),
         }
     }
@@ -130,6 +169,20 @@ where
         })
     }
 
+    /// Keep the deployments this node is the standby for prepared so that
+    /// they start quickly when this node takes them over
+    pub async fn keep_standbys_warm(&self) {
+        let mut interval = tokio::time::interval(STANDBY_WARM_INTERVAL);
+        loop {
+            interval.tick().await;
+            match self.store.standbys(&self.node_id) {
+                Ok(deployments) => self.provider.keep_warm(deployments).await,
+                Err(e) => warn!(self.logger, "Failed to look up standby deployments";
+                                "error" => e.to_string()),
+            }
+        }
+    }
+
     pub fn assignment_events(&self) -> impl Stream<Item = AssignmentEvent, Error = Error> + Send {
         let store = self.store.clone();
         let node_id = self.node_id.clone();
@@ -212,6 +265,8 @@ where
     fn start_assigned_subgraphs(&self) -> impl Future<Item = (), Error = Error> {
         let provider = self.provider.clone();
         let logger = self.logger.clone();
+        let store = self.store.clone();
+        let retry_failed = self.retry_failed;
 
         future::result(self.store.assignments(&self.node_id))
             .map_err(|e| anyhow!("Error querying subgraph assignments: {}", e))
@@ -223,12 +278,16 @@ where
                 let deployments = HashSet::<DeploymentLocator>::from_iter(deployments);
                 let (sender, receiver) = futures01::sync::mpsc::channel::<()>(1);
                 for id in deployments {
+                    prepare_failed(store.as_ref(), retry_failed, &id, &logger);
                     let sender = sender.clone();
                     let logger = logger.clone();
+                    let provider = provider.clone();
 
-                    graph::spawn(
-                        start_subgraph(id, provider.clone(), logger).map(move |()| drop(sender)),
-                    );
+                    graph::spawn_essential("start-subgraph", move || {
+                        let sender = sender.clone();
+                        start_subgraph(id.clone(), provider.clone(), logger.clone())
+                            .map(move |()| drop(sender))
+                    });
                 }
                 drop(sender);
                 receiver.collect().then(move |_| {
@@ -237,6 +296,41 @@ where
                 })
             })
     }
+
+    /// Find the one deployment with the given `hash`
+    fn locate(&self, hash: &DeploymentHash) -> Result<DeploymentLocator, SubgraphRegistrarError> {
+        let locations = self.store.locators(hash)?;
+        match locations.len() {
+            0 => Err(SubgraphRegistrarError::DeploymentNotFound(hash.to_string())),
+            1 => Ok(locations[0].clone()),
+            _ => Err(SubgraphRegistrarError::StoreError(
+                anyhow!(
+                    "there are {} different deployments with id {}",
+                    locations.len(),
+                    hash.as_str()
+                )
+                .into(),
+            )),
+        }
+    }
+
+    /// Fetch and parse the manifest `hash` without retrying
+    async fn cat_manifest(
+        &self,
+        logger: &Logger,
+        hash: &DeploymentHash,
+    ) -> Result<serde_yaml::Mapping, SubgraphRegistrarError> {
+        let file_bytes = self
+            .check_resolver
+            .cat(logger, &hash.to_ipfs_link())
+            .await
+            .map_err(|e| {
+                SubgraphRegistrarError::ResolveError(SubgraphManifestResolveError::ResolveError(e))
+            })?;
+
+        serde_yaml::from_slice(&file_bytes)
+            .map_err(|e| SubgraphRegistrarError::ResolveError(e.into()))
+    }
 }
 
 #[async_trait]
@@ -263,7 +357,13 @@ where
         name: SubgraphName,
         hash: DeploymentHash,
         node_id: NodeId,
-    ) -> Result<(), SubgraphRegistrarError> {
+        version_switching_mode: Option<SubgraphVersionSwitchingMode>,
+        labels: Option<Labels>,
+    ) -> Result<DeploymentProfile, SubgraphRegistrarError> {
+        if let Some(labels) = &labels {
+            validate_labels(labels).map_err(SubgraphRegistrarError::InvalidLabels)?;
+        }
+
         // We don't have a location for the subgraph yet; that will be
         // assigned when we deploy for real. For logging purposes, make up a
         // fake locator
@@ -290,7 +390,7 @@ where
             SubgraphRegistrarError::ResolveError(SubgraphManifestResolveError::ResolveError(e))
         })?;
 
-        match kind {
+        let profile = match kind {
             BlockchainKind::Ethereum => {
                 create_subgraph_version::<graph_chain_ethereum::Chain, _, _>(
                     &logger,
@@ -300,7 +400,8 @@ where
                     hash.cheap_clone(),
                     raw,
                     node_id,
-                    self.version_switching_mode,
+                    version_switching_mode.unwrap_or(self.version_switching_mode),
+                    labels,
                     self.resolver.cheap_clone(),
                 )
                 .await?
@@ -314,7 +415,7 @@ where
             "subgraph_hash" => hash.to_string(),
         );
 
-        Ok(())
+        Ok(profile)
     }
 
     async fn remove_subgraph(&self, name: SubgraphName) -> Result<(), SubgraphRegistrarError> {
@@ -334,25 +435,257 @@ where
         hash: &DeploymentHash,
         node_id: &NodeId,
     ) -> Result<(), SubgraphRegistrarError> {
-        let locations = self.store.locators(hash)?;
-        let deployment = match locations.len() {
-            0 => return Err(SubgraphRegistrarError::DeploymentNotFound(hash.to_string())),
-            1 => locations[0].clone(),
-            _ => {
-                return Err(SubgraphRegistrarError::StoreError(
-                    anyhow!(
-                        "there are {} different deployments with id {}",
-                        locations.len(),
-                        hash.as_str()
-                    )
-                    .into(),
-                ))
-            }
-        };
+        let deployment = self.locate(hash)?;
         self.store.reassign_subgraph(&deployment, node_id)?;
 
         Ok(())
     }
+
+    async fn set_archive_blocks(
+        &self,
+        hash: &DeploymentHash,
+        blocks: Option<BlockNumber>,
+    ) -> Result<(), SubgraphRegistrarError> {
+        if let Some(blocks) = blocks {
+            if blocks <= 0 {
+                return Err(SubgraphRegistrarError::Unknown(anyhow!(
+                    "the number of blocks to keep unarchived must be positive, but is {}",
+                    blocks
+                )));
+            }
+        }
+        let deployment = self.locate(hash)?;
+        self.store.set_archive_blocks(&deployment, blocks)?;
+
+        Ok(())
+    }
+
+    async fn skip_block(
+        &self,
+        hash: &DeploymentHash,
+        block_hash: &str,
+        reason: String,
+    ) -> Result<(), SubgraphRegistrarError> {
+        let block_hash = parse_block_hash(block_hash)?;
+        if reason.trim().is_empty() {
+            return Err(SubgraphRegistrarError::Unknown(anyhow!(
+                "a reason for skipping a block is required"
+            )));
+        }
+        let deployment = self.locate(hash)?;
+        self.store
+            .skip_block(&deployment, &block_hash, reason.clone())?;
+
+        warn!(self.logger, "Operator told subgraph to skip a block, \
+                            its handlers will not run for the block and its \
+                            proof of indexing will reflect that";
+              "subgraph_id" => hash.to_string(),
+              "block_hash" => &block_hash,
+              "reason" => reason);
+        Ok(())
+    }
+
+    async fn unskip_block(
+        &self,
+        hash: &DeploymentHash,
+        block_hash: &str,
+    ) -> Result<SkippedBlock, SubgraphRegistrarError> {
+        let block_hash = parse_block_hash(block_hash)?;
+        let deployment = self.locate(hash)?;
+        let skipped = self.store.unskip_block(&deployment, &block_hash)?;
+
+        if let Some(number) = skipped.block_number.filter(|_| skipped.is_applied()) {
+            warn!(self.logger, "Operator withdrew the skip of a block that the subgraph \
+                                has already skipped; rewind the subgraph to before the block \
+                                to process it";
+                  "subgraph_id" => hash.to_string(),
+                  "block_hash" => &block_hash,
+                  "block_number" => number);
+        } else {
+            warn!(self.logger, "Operator withdrew the skip of a block";
+                  "subgraph_id" => hash.to_string(),
+                  "block_hash" => &block_hash);
+        }
+        Ok(skipped)
+    }
+
+    async fn reset_scan_totals(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError> {
+        let deployment = self.locate(hash)?;
+        self.store.reset_scan_totals(&deployment)?;
+        // Forget what was counted but not written yet. Nodes other than
+        // this one might still add a little from before the reset
+        ScanCounter::for_deployment(hash).take();
+
+        info!(self.logger, "Operator reset the scan totals of subgraph";
+              "subgraph_id" => hash.to_string());
+        Ok(())
+    }
+
+    async fn set_labels(
+        &self,
+        hash: &DeploymentHash,
+        labels: Labels,
+    ) -> Result<(), SubgraphRegistrarError> {
+        validate_labels(&labels).map_err(SubgraphRegistrarError::InvalidLabels)?;
+        // Only label deployments that exist so that typos do not go
+        // unnoticed; labels for new deployments are set when they are
+        // deployed
+        self.locate(hash)?;
+        self.store.set_labels(hash, labels)?;
+
+        info!(self.logger, "Operator set the labels of subgraph";
+              "subgraph_id" => hash.to_string());
+        Ok(())
+    }
+
+    async fn labels(&self, hash: &DeploymentHash) -> Result<Labels, SubgraphRegistrarError> {
+        self.locate(hash)?;
+        Ok(self.store.labels(hash)?)
+    }
+
+    async fn reload_mappings(
+        &self,
+        hash: &DeploymentHash,
+        manifest: Option<DeploymentHash>,
+        block: Option<BlockNumber>,
+    ) -> Result<MappingsReload, SubgraphRegistrarError> {
+        if !self.dev_mode {
+            return Err(SubgraphRegistrarError::ReloadError(
+                "reloading mappings is only possible when the node runs with `--dev`".to_string(),
+            ));
+        }
+        let deployment = self.locate(hash)?;
+        if self.store.assigned_node(&deployment)?.as_ref() != Some(&self.node_id) {
+            return Err(SubgraphRegistrarError::ReloadError(format!(
+                "deployment {} is not assigned to this node",
+                hash
+            )));
+        }
+        let manifest = manifest.unwrap_or_else(|| hash.clone());
+        let logger = self.logger_factory.subgraph_logger(&deployment);
+
+        let original = self.cat_manifest(&logger, hash).await?;
+        let raw = self.cat_manifest(&logger, &manifest).await?;
+        check_reloadable(&original, &raw).map_err(SubgraphRegistrarError::ReloadError)?;
+
+        let kind = BlockchainKind::from_manifest(&raw).map_err(|e| {
+            SubgraphRegistrarError::ResolveError(SubgraphManifestResolveError::ResolveError(e))
+        })?;
+        let block_ptr_to = match kind {
+            BlockchainKind::Ethereum => {
+                prepare_reload::<graph_chain_ethereum::Chain, _, _>(
+                    &logger,
+                    self.store.clone(),
+                    self.chains.cheap_clone(),
+                    manifest.cheap_clone(),
+                    raw,
+                    self.check_resolver.cheap_clone(),
+                    block,
+                )
+                .await?
+            }
+        };
+
+        // The deployment must not process blocks while it is rewound and
+        // its mappings are switched
+        match self.provider.stop(deployment.clone()).await {
+            Ok(()) | Err(SubgraphAssignmentProviderError::NotRunning(_)) => {}
+            Err(e) => return Err(SubgraphRegistrarError::Unknown(e.into())),
+        }
+        let reload = self
+            .store
+            .reload_mappings(&deployment, &manifest, block_ptr_to)?;
+        start_subgraph(deployment, self.provider.clone(), self.logger.clone()).await;
+
+        warn!(self.logger, "Reloaded the mappings of subgraph, its proof of \
+                            indexing can not be compared with that of other \
+                            indexers anymore";
+              "subgraph_id" => hash.to_string(),
+              "manifest" => manifest.to_string(),
+              "rewound_to" => format!("{:?}", reload.rewound_to));
+        Ok(reload)
+    }
+
+    async fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, SubgraphRegistrarError> {
+        let report = self.store.maintain_metadata()?;
+        info!(self.logger, "Maintained subgraph metadata";
+              "versions_removed" => report.versions_removed,
+              "audit_entries_removed" => report.audit_entries_removed,
+              "version_switches_removed" => report.version_switches_removed);
+        Ok(report)
+    }
+
+    async fn compare_versions(
+        &self,
+        name: SubgraphName,
+        block: BlockNumber,
+        sample_size: usize,
+    ) -> Result<VersionComparison, SubgraphRegistrarError> {
+        if !self.store.subgraph_exists(&name)? {
+            return Err(SubgraphRegistrarError::NameNotFound(name.to_string()));
+        }
+        let start = Instant::now();
+        let comparison = self.store.compare_versions(&name, block, sample_size)?;
+        info!(self.logger, "Compared subgraph versions";
+              "subgraph_name" => name.to_string(),
+              "current" => comparison.current.to_string(),
+              "pending" => comparison.pending.to_string(),
+              "block" => block,
+              "same" => comparison.is_same(),
+              "time_ms" => start.elapsed().as_millis());
+        Ok(comparison)
+    }
+
+    async fn validate_deployment_files(
+        &self,
+    ) -> Result<Vec<DeploymentFilesReport>, SubgraphRegistrarError> {
+        let deployments = self.store.assignments(&self.node_id)?;
+        info!(self.logger, "Validating files of assigned subgraphs";
+              "deployments" => deployments.len());
+
+        // Files that are cached would resolve even if they are gone from IPFS
+        let resolver = self.check_resolver.as_ref().clone().without_cache();
+
+        let mut reports = Vec::with_capacity(deployments.len());
+        for deployment in deployments {
+            let logger = self
+                .logger
+                .new(o!("subgraph_id" => deployment.hash.to_string()));
+            let stored_schema = match self.store.input_schema(&deployment.hash) {
+                Ok(schema) => Some(schema),
+                Err(e) => {
+                    warn!(logger, "Failed to load stored schema, not comparing schemas";
+                          "error" => e.to_string());
+                    None
+                }
+            };
+            let report = check_deployment_files(
+                &logger,
+                &resolver,
+                &deployment.hash,
+                stored_schema.as_deref(),
+            )
+            .await;
+
+            self.unresolvable_files
+                .with_label_values(&[deployment.hash.as_str()])
+                .set((report.unresolvable.len() + report.mismatched.len()) as f64);
+            for file in &report.unresolvable {
+                warn!(logger, "Subgraph file can not be resolved";
+                      "link" => &file.link,
+                      "error" => &file.error);
+            }
+            for link in &report.mismatched {
+                warn!(logger, "Subgraph file differs from the stored copy"; "link" => link);
+            }
+            reports.push(report);
+        }
+
+        info!(self.logger, "Validated files of assigned subgraphs";
+              "deployments" => reports.len(),
+              "with_problems" => reports.iter().filter(|report| !report.is_ok()).count());
+        Ok(reports)
+    }
 }
 
 async fn handle_assignment_event(
@@ -380,6 +713,68 @@ async fn handle_assignment_event(
     }
 }
 
+/// Check that `block_hash` is `0x` followed by hex digits and return it in
+/// lowercase, which is how block hashes are compared
+fn parse_block_hash(block_hash: &str) -> Result<String, SubgraphRegistrarError> {
+    let valid = block_hash
+        .strip_prefix("0x")
+        .map(|hex| {
+            !hex.is_empty() && hex.len() % 2 == 0 && hex.chars().all(|c| c.is_ascii_hexdigit())
+        })
+        .unwrap_or(false);
+    if !valid {
+        return Err(SubgraphRegistrarError::Unknown(anyhow!(
+            "invalid block hash `{}`: expected `0x` followed by hex digits",
+            block_hash
+        )));
+    }
+    Ok(block_hash.to_lowercase())
+}
+
+/// Check whether `deployment` has failed, and if so, whether `policy` says
+/// to retry it. Clear the failure of deployments that are retried. The
+/// deployment is started afterwards no matter what happens here, just
+/// like before we had a policy for failed deployments
+fn prepare_failed(
+    store: &impl SubgraphStore,
+    policy: RetryFailedPolicy,
+    deployment: &DeploymentLocator,
+    logger: &Logger,
+) {
+    let logger = logger
+        .new(o!("subgraph_id" => deployment.hash.to_string(), "sgd" => deployment.id.to_string()));
+
+    let error = match store.fatal_error(deployment) {
+        Ok(Some(error)) => error,
+        Ok(None) => return,
+        Err(e) => {
+            warn!(logger, "Failed to check whether subgraph has failed";
+                  "error" => e.to_string());
+            return;
+        }
+    };
+
+    if !policy.retries(&error) {
+        info!(logger, "Starting failed subgraph without clearing its failure";
+              "error" => &error.message,
+              "deterministic" => error.deterministic,
+              "retry_failed_on_startup" => format!("{:?}", policy));
+        return;
+    }
+
+    match store.retry_failed(deployment) {
+        Ok(()) => {
+            info!(logger, "Retrying failed subgraph";
+                  "error" => &error.message,
+                  "deterministic" => error.deterministic);
+        }
+        Err(e) => {
+            warn!(logger, "Failed to clear failure of subgraph";
+                  "error" => e.to_string());
+        }
+    }
+}
+
 async fn start_subgraph(
     deployment: DeploymentLocator,
     provider: Arc<impl SubgraphAssignmentProviderTrait>,
@@ -467,6 +862,88 @@ async fn resolve_subgraph_chain_blocks(
     Ok((start_block_ptr, base_ptr))
 }
 
+/// Check that the manifest `reloaded` differs from `original` only in the
+/// mappings of its data sources and templates, so that a deployment of
+/// `original` can run it without changing what it indexes
+fn check_reloadable(
+    original: &serde_yaml::Mapping,
+    reloaded: &serde_yaml::Mapping,
+) -> Result<(), String> {
+    let key = |name: &str| serde_yaml::Value::from(name);
+
+    if original.get(&key("schema")) != reloaded.get(&key("schema")) {
+        return Err("the manifest has a different schema".to_string());
+    }
+    for list in &["dataSources", "templates"] {
+        let without_mappings = |manifest: &serde_yaml::Mapping| -> Vec<serde_yaml::Value> {
+            manifest
+                .get(&key(list))
+                .and_then(|value| value.as_sequence())
+                .map(|sources| {
+                    sources
+                        .iter()
+                        .map(|source| {
+                            let mut source = source.clone();
+                            if let Some(source) = source.as_mapping_mut() {
+                                source.remove(&key("mapping"));
+                            }
+                            source
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        if without_mappings(original) != without_mappings(reloaded) {
+            return Err(format!(
+                "the `{}` of the manifest differ in more than their mappings",
+                list
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Resolve and validate the manifest whose mappings a deployment should
+/// run, and look up the block to rewind the deployment to
+async fn prepare_reload<C: Blockchain, S: SubgraphStore, L: LinkResolver>(
+    logger: &Logger,
+    store: Arc<S>,
+    chains: Arc<BlockchainMap>,
+    manifest: DeploymentHash,
+    raw: serde_yaml::Mapping,
+    resolver: Arc<L>,
+    block: Option<BlockNumber>,
+) -> Result<Option<BlockPtr>, SubgraphRegistrarError> {
+    let manifest = UnvalidatedSubgraphManifest::<C>::resolve(
+        manifest,
+        raw,
+        resolver,
+        logger,
+        MAX_SPEC_VERSION.clone(),
+    )
+    .map_err(SubgraphRegistrarError::ResolveError)
+    .await?
+    .validate(store, false)
+    .map_err(SubgraphRegistrarError::ManifestValidationError)?;
+
+    let block = match block {
+        Some(block) => block,
+        None => return Ok(None),
+    };
+    let chain = chains
+        .get::<C>(manifest.network_name())
+        .map_err(SubgraphRegistrarError::NetworkNotSupported)?;
+    chain
+        .block_pointer_from_number(logger, block)
+        .await
+        .map(Some)
+        .map_err(|_| {
+            SubgraphRegistrarError::ManifestValidationError(vec![
+                SubgraphManifestValidationError::BlockNotFound(block.to_string()),
+            ])
+        })
+}
+
 async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolver>(
     logger: &Logger,
     store: Arc<S>,
@@ -476,8 +953,9 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolve
     raw: serde_yaml::Mapping,
     node_id: NodeId,
     version_switching_mode: SubgraphVersionSwitchingMode,
+    labels: Option<Labels>,
     resolver: Arc<L>,
-) -> Result<(), SubgraphRegistrarError> {
+) -> Result<DeploymentProfile, SubgraphRegistrarError> {
     let unvalidated = UnvalidatedSubgraphManifest::<C>::resolve(
         deployment,
         raw,
@@ -492,6 +970,15 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolve
         .validate(store.cheap_clone(), true)
         .map_err(SubgraphRegistrarError::ManifestValidationError)?;
 
+    if let Ok(true) = imports_disabled_eth_call(&manifest) {
+        warn!(
+            logger,
+            "Subgraph declares the `noEthCalls` feature but its mappings import `ethereum.call`; \
+             any call will fail the subgraph";
+            "subgraph_id" => manifest.id.to_string(),
+        );
+    }
+
     let network_name = manifest.network_name();
 
     let chain = chains
@@ -499,6 +986,59 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolve
         .map_err(SubgraphRegistrarError::NetworkNotSupported)?
         .cheap_clone();
 
+    // Refuse deployments that no provider can index, for example ones
+    // with call handlers on a network without trace support
+    let required_capabilities = C::NodeCapabilities::from_data_sources(&manifest.data_sources);
+    chain
+        .check_capabilities(&required_capabilities)
+        .map_err(SubgraphRegistrarError::NetworkNotSupported)?;
+
+    // Refuse mappings that import host functions this node does not
+    // provide; they would only fail once the first trigger is processed
+    let runtime_adapter = chain.runtime_adapter();
+    let mut chain_fns = Vec::new();
+    for data_source in &manifest.data_sources {
+        let host_fns = runtime_adapter
+            .host_fns(data_source)
+            .map_err(SubgraphRegistrarError::NetworkNotSupported)?;
+        chain_fns.extend(host_fns.iter().map(|host_fn| host_fn.name));
+    }
+    let import_errors = validate_imports(&manifest, &chain_fns);
+    if !import_errors.is_empty() {
+        return Err(SubgraphRegistrarError::ManifestValidationError(
+            import_errors,
+        ));
+    }
+
+    let wildcards: Vec<_> = manifest
+        .wildcard_data_sources()
+        .map(|ds| ds.name().to_string())
+        .collect();
+    if !wildcards.is_empty() {
+        warn!(
+            logger,
+            "Subgraph has data sources without an address; they handle events from all \
+             contracts, which requires scanning the whole chain for them and makes the \
+             subgraph slow to sync";
+            "subgraph_id" => manifest.id.to_string(),
+            "data_sources" => wildcards.join(", "),
+        );
+    }
+
+    match unused_features(&manifest) {
+        Ok(unused) if !unused.is_empty() => warn!(
+            logger,
+            "Subgraph declares features that it does not use";
+            "subgraph_id" => manifest.id.to_string(),
+            "features" => unused
+                .iter()
+                .map(|feature| feature.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        _ => {}
+    }
+
     let logger = logger.clone();
     let store = store.clone();
     let deployment_store = store.clone();
@@ -528,8 +1068,15 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolve
         "block" => format!("{:?}", base_block.as_ref().map(|(_,ptr)| ptr.number))
     );
 
+    // Placement rules can select deployments by their labels, which
+    // therefore need to be stored before the deployment is created
+    if let Some(labels) = labels {
+        deployment_store.set_labels(&manifest.id, labels)?;
+    }
+
     // Apply the subgraph versioning and deployment operations,
     // creating a new subgraph deployment if one doesn't exist.
+    let profile = DeploymentProfile::from_manifest(&manifest);
     let deployment = SubgraphDeploymentEntity::new(&manifest, false, start_block).graft(base_block);
     deployment_store
         .create_subgraph_deployment(
@@ -541,5 +1088,41 @@ async fn create_subgraph_version<C: Blockchain, S: SubgraphStore, L: LinkResolve
             version_switching_mode,
         )
         .map_err(|e| SubgraphRegistrarError::SubgraphDeploymentError(e))
-        .map(|_| ())
+        .map(|_| profile)
+}
+
+#[test]
+fn reloadable_manifests() {
+    let manifest = |mapping: &str, address: &str| -> serde_yaml::Mapping {
+        serde_yaml::from_str(&format!(
+            "
+schema:
+  file:
+    /: /ipfs/QmSchema
+dataSources:
+  - kind: ethereum/contract
+    name: Token
+    network: mainnet
+    source:
+      address: '{}'
+    mapping:
+      file:
+        /: /ipfs/{}
+",
+            address, mapping
+        ))
+        .unwrap()
+    };
+    let original = manifest("QmMapping", "0x01");
+
+    assert_eq!(Ok(()), check_reloadable(&original, &original));
+    assert_eq!(
+        Ok(()),
+        check_reloadable(&original, &manifest("QmNewMapping", "0x01"))
+    );
+    assert!(check_reloadable(&original, &manifest("QmNewMapping", "0x02")).is_err());
+
+    let mut other_schema = original.clone();
+    other_schema.insert("schema".into(), "QmOtherSchema".into());
+    assert!(check_reloadable(&original, &other_schema).is_err());
 }
@@ -0,0 +1,257 @@
+//! A queue between the block stream and the execution of handlers. It lets
+//! the block stream fetch blocks while handlers run, but limits how much
+//! memory blocks that are waiting to be processed can use
+use futures::future::{select, Either, FusedFuture};
+use futures::{pin_mut, Future, Stream, StreamExt};
+use graph::blockchain::block_stream::BlockStreamEvent;
+use graph::blockchain::Blockchain;
+use graph::prelude::tokio::sync::Notify;
+use graph::prelude::{debug, BlockNumber, CancelableError, Error, Gauge, Logger};
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+lazy_static! {
+    /// The maximum estimated size of the blocks that are waiting for their
+    /// triggers to be processed, per deployment, in megabytes
+    static ref TRIGGER_QUEUE_SIZE: usize = 1024 * 1024
+        * std::env::var("GRAPH_TRIGGER_QUEUE_SIZE")
+            .unwrap_or("64".into())
+            .parse::<usize>()
+            .expect("invalid GRAPH_TRIGGER_QUEUE_SIZE");
+}
+
+/// The estimated size of a block without triggers, in bytes
+const BLOCK_SIZE: usize = 16 * 1024;
+
+/// The estimated size of each trigger in a block, in bytes
+const TRIGGER_SIZE: usize = 2 * 1024;
+
+fn estimated_size<C: Blockchain>(event: &BlockStreamEvent<C>) -> usize {
+    match event {
+        BlockStreamEvent::ProcessBlock(block, _) => {
+            BLOCK_SIZE + TRIGGER_SIZE * block.trigger_count()
+        }
+        BlockStreamEvent::Revert(..) => 0,
+    }
+}
+
+struct QueueState<C: Blockchain> {
+    events: VecDeque<(BlockStreamEvent<C>, usize)>,
+    bytes: usize,
+    closed: bool,
+}
+
+pub(crate) struct TriggerQueue<C: Blockchain> {
+    state: Mutex<QueueState<C>>,
+    capacity: usize,
+    pushed: Notify,
+    popped: Notify,
+    items_gauge: Box<Gauge>,
+    bytes_gauge: Box<Gauge>,
+}
+
+impl<C: Blockchain> TriggerQueue<C> {
+    pub fn new(items_gauge: Box<Gauge>, bytes_gauge: Box<Gauge>) -> Self {
+        Self::with_capacity(*TRIGGER_QUEUE_SIZE, items_gauge, bytes_gauge)
+    }
+
+    fn with_capacity(capacity: usize, items_gauge: Box<Gauge>, bytes_gauge: Box<Gauge>) -> Self {
+        items_gauge.set(0.0);
+        bytes_gauge.set(0.0);
+        TriggerQueue {
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                bytes: 0,
+                closed: false,
+            }),
+            capacity,
+            pushed: Notify::new(),
+            popped: Notify::new(),
+            items_gauge,
+            bytes_gauge,
+        }
+    }
+
+    fn update_gauges(&self, state: &QueueState<C>) {
+        self.items_gauge.set(state.events.len() as f64);
+        self.bytes_gauge.set(state.bytes as f64);
+    }
+
+    /// Add `event` to the end of the queue, waiting until there is enough
+    /// space for it. An empty queue accepts any event, no matter how large
+    /// it is.
+    ///
+    /// Reverting the block at the end of the queue removes that block
+    /// instead of adding the revert, since there is no point in processing
+    /// a block only to revert it right away
+    pub async fn push(&self, event: BlockStreamEvent<C>) {
+        if let BlockStreamEvent::Revert(ptr, _) = &event {
+            let mut state = self.state.lock().unwrap();
+            let queued = match state.events.back() {
+                Some((BlockStreamEvent::ProcessBlock(block, _), _)) => block.ptr() == *ptr,
+                _ => false,
+            };
+            if queued {
+                let (_, size) = state.events.pop_back().unwrap();
+                state.bytes -= size;
+                self.update_gauges(&state);
+                drop(state);
+                self.popped.notify_one();
+                return;
+            }
+        }
+
+        let size = estimated_size(&event);
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.events.is_empty() || state.bytes + size <= self.capacity {
+                    state.events.push_back((event, size));
+                    state.bytes += size;
+                    self.update_gauges(&state);
+                    drop(state);
+                    self.pushed.notify_one();
+                    return;
+                }
+            }
+            self.popped.notified().await;
+        }
+    }
+
+    /// Take the event at the front of the queue, waiting until there is
+    /// one. Return `None` once the queue has been closed and all events
+    /// have been taken
+    pub async fn pop(&self) -> Option<BlockStreamEvent<C>> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some((event, size)) = state.events.pop_front() {
+                    state.bytes -= size;
+                    self.update_gauges(&state);
+                    drop(state);
+                    self.popped.notify_one();
+                    return Some(event);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.pushed.notified().await;
+        }
+    }
+
+    /// Indicate that no more events will be added to the queue
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.pushed.notify_one();
+    }
+
+    /// Move the events from `stream` into the queue until the stream ends
+    /// or is canceled. Other errors from the stream are logged and dropped;
+    /// the stream will continue to produce blocks after them
+    pub async fn fill_from<S>(&self, logger: &Logger, stream: &mut S)
+    where
+        S: Stream<Item = Result<BlockStreamEvent<C>, CancelableError<Error>>> + Unpin,
+    {
+        while let Some(event) = stream.next().await {
+            match event {
+                Ok(event) => self.push(event).await,
+                Err(CancelableError::Cancel) => break,
+                Err(e) => debug!(
+                    logger,
+                    "Block stream produced a non-fatal error";
+                    "error" => format!("{}", e),
+                ),
+            }
+        }
+        self.close();
+    }
+}
+
+/// Wait for `fut` while making progress on `background` at the same time.
+/// Once `background` is done, this just waits for `fut`
+pub(crate) async fn while_running<F, B>(fut: F, background: &mut B) -> F::Output
+where
+    F: Future,
+    B: FusedFuture<Output = ()> + Unpin,
+{
+    pin_mut!(fut);
+    match select(fut, background).await {
+        Either::Left((output, _)) => output,
+        Either::Right(((), fut)) => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use graph::blockchain::block_stream::BlockWithTriggers;
+    use graph::blockchain::BlockPtr;
+    use graph::prelude::web3::types::H256;
+    use graph::prelude::LightEthereumBlock;
+    use graph_chain_ethereum::chain::BlockFinality;
+    use graph_chain_ethereum::Chain;
+    use std::sync::Arc;
+
+    fn queue(capacity: usize) -> TriggerQueue<Chain> {
+        let gauge = |name| Box::new(Gauge::new(name, "test gauge").unwrap());
+        TriggerQueue::with_capacity(capacity, gauge("items"), gauge("bytes"))
+    }
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number), number))
+    }
+
+    fn block(number: u64) -> BlockStreamEvent<Chain> {
+        let mut block = LightEthereumBlock::default();
+        block.hash = Some(H256::from_low_u64_be(number));
+        block.number = Some(number.into());
+        BlockStreamEvent::ProcessBlock(
+            BlockWithTriggers::new(BlockFinality::Final(Arc::new(block), None), vec![]),
+            None,
+        )
+    }
+
+    fn number(event: Option<BlockStreamEvent<Chain>>) -> BlockNumber {
+        match event {
+            Some(BlockStreamEvent::ProcessBlock(block, _)) => block.ptr().number,
+            _ => panic!("expected a block"),
+        }
+    }
+
+    #[tokio::test]
+    async fn reverting_queued_blocks_discards_them() {
+        let queue = queue(10 * BLOCK_SIZE);
+        queue.push(block(1)).await;
+        queue.push(block(2)).await;
+        queue.push(BlockStreamEvent::Revert(ptr(2), None)).await;
+        assert_eq!(1.0, queue.items_gauge.get());
+        assert_eq!(BLOCK_SIZE as f64, queue.bytes_gauge.get());
+
+        // Block 1 has been handed out, and reverting it must be queued
+        assert_eq!(1, number(queue.pop().await));
+        queue.push(BlockStreamEvent::Revert(ptr(1), None)).await;
+        match queue.pop().await {
+            Some(BlockStreamEvent::Revert(reverted, _)) => assert_eq!(ptr(1), reverted),
+            _ => panic!("expected a revert"),
+        }
+    }
+
+    #[tokio::test]
+    async fn full_queue_waits_for_space() {
+        let queue = queue(BLOCK_SIZE);
+
+        // An empty queue accepts anything, but then it is full
+        queue.push(block(1)).await;
+        assert!(queue.push(block(2)).now_or_never().is_none());
+
+        assert_eq!(1, number(queue.pop().await));
+        assert!(queue.push(block(2)).now_or_never().is_some());
+
+        queue.close();
+        assert_eq!(2, number(queue.pop().await));
+        assert!(queue.pop().await.is_none());
+    }
+}
@@ -0,0 +1,759 @@
+//! Notify external webhooks when deployments change state. Every
+//! `GRAPH_WEBHOOK_POLL_INTERVAL` seconds, we poll the stored status of the
+//! deployments assigned to this node, compare it with what we saw in the
+//! previous poll, and send one POST request with a JSON payload for each
+//! transition of such a deployment to every webhook that is interested in
+//! it.
+//! Deliveries run in their own tasks and are retried with jittered
+//! exponential backoff; events that can not be delivered after
+//! `GRAPH_WEBHOOK_MAX_ATTEMPTS` attempts are counted in the
+//! `webhook_undelivered_events` metric. None of this touches indexing,
+//! which therefore never waits for a webhook
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use graph::components::store::StatusStore;
+use graph::data::subgraph::schema::SubgraphHealth;
+use graph::data::subgraph::{status, Labels};
+use graph::prelude::{
+    error, info, lazy_static, o, reqwest, retry, serde_json, tokio, warn, BlockNumber, CounterVec,
+    Logger, MetricsRegistry, NodeId, StoreError,
+};
+use hmac::{Hmac, Mac, NewMac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+lazy_static! {
+    /// How often we look for changes in the state of deployments, in seconds
+    static ref POLL_INTERVAL: Duration = {
+        let secs = std::env::var("GRAPH_WEBHOOK_POLL_INTERVAL")
+            .ok()
+            .map(|s| {
+                u64::from_str(&s).unwrap_or_else(|_| {
+                    panic!("GRAPH_WEBHOOK_POLL_INTERVAL must be a number, but is `{}`", s)
+                })
+            })
+            .unwrap_or(10);
+        Duration::from_secs(secs)
+    };
+
+    /// How often we try to deliver an event to a webhook before giving up
+    static ref MAX_ATTEMPTS: u32 = std::env::var("GRAPH_WEBHOOK_MAX_ATTEMPTS")
+        .ok()
+        .map(|s| {
+            u32::from_str(&s).unwrap_or_else(|_| {
+                panic!("GRAPH_WEBHOOK_MAX_ATTEMPTS must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(5)
+        .max(1);
+}
+
+/// How long we wait before the first retry of a failed delivery; the wait
+/// doubles with every further retry up to `MAX_BACKOFF`, and is jittered so
+/// that deliveries that failed together are not retried together
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest we wait between attempts to deliver an event
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long one delivery attempt may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The header that carries the HMAC-SHA256 signature of the payload
+const SIGNATURE_HEADER: &str = "x-graph-signature";
+
+/// The header that carries the event, so that receivers can route
+/// requests without parsing them
+const EVENT_HEADER: &str = "x-graph-event";
+
+/// The changes in the state of a deployment that webhooks can be told about
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeploymentEvent {
+    /// The deployment was assigned to this node for the first time
+    Created,
+    /// The deployment became healthy again, e.g., after being restarted
+    Healthy,
+    /// The deployment caught up with the chain head
+    Synced,
+    /// The deployment encountered a non-fatal error
+    Unhealthy,
+    /// The deployment failed with a fatal error
+    Failed,
+    /// The deployment processed its end block
+    Completed,
+    /// The deployment is not assigned to any node anymore
+    Removed,
+}
+
+impl DeploymentEvent {
+    fn as_str(&self) -> &'static str {
+        use DeploymentEvent::*;
+        match self {
+            Created => "created",
+            Healthy => "healthy",
+            Synced => "synced",
+            Unhealthy => "unhealthy",
+            Failed => "failed",
+            Completed => "completed",
+            Removed => "removed",
+        }
+    }
+}
+
+/// A webhook from the `[[webhook]]` sections of the configuration file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Webhook {
+    pub url: String,
+    /// The events to send to this webhook; if empty, all events are sent
+    #[serde(default)]
+    pub events: Vec<DeploymentEvent>,
+    /// If set, payloads are signed with HMAC-SHA256 using this secret
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    fn wants(&self, event: DeploymentEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+/// What we know about a deployment from the last poll
+#[derive(Clone, Debug)]
+struct Deployment {
+    hash: String,
+    names: Vec<String>,
+    labels: Labels,
+    network: Option<String>,
+    block: Option<BlockNumber>,
+    health: SubgraphHealth,
+    synced: bool,
+    node: Option<String>,
+    error: Option<String>,
+}
+
+impl From<status::Info> for Deployment {
+    fn from(info: status::Info) -> Self {
+        let chain = info.chains.into_iter().next();
+        Deployment {
+            hash: info.subgraph,
+            names: info.names,
+            labels: info.labels,
+            network: chain.as_ref().map(|chain| chain.network.clone()),
+            block: chain
+                .and_then(|chain| chain.latest_block)
+                .map(|block| block.number()),
+            health: info.health,
+            synced: info.synced,
+            node: info.node,
+            error: info.fatal_error.map(|e| e.message).or_else(|| {
+                info.non_fatal_errors
+                    .into_iter()
+                    .next()
+                    .map(|error| error.message)
+            }),
+        }
+    }
+}
+
+impl Deployment {
+    /// The state that `self` was in when it was created
+    fn as_created(&self) -> Deployment {
+        Deployment {
+            health: SubgraphHealth::Healthy,
+            synced: false,
+            error: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// The events for a deployment that was `old` in the previous poll and is
+/// `new` now; `None` means that we did not see the deployment. We only
+/// report events for deployments assigned to `node`. A deployment that
+/// moves between nodes is neither created nor removed.
+///
+/// Events are derived from the stored state of the deployment, so that a
+/// deployment that was created and changed state between two polls, e.g.,
+/// because it failed right away, gets an event for each change, and a
+/// deployment that failed again with a different error after being
+/// restarted gets another `Failed` event
+fn transitions(
+    node: &str,
+    old: Option<&Deployment>,
+    new: Option<&Deployment>,
+) -> Vec<DeploymentEvent> {
+    use DeploymentEvent::*;
+
+    let ours = |deployment: &Deployment| deployment.node.as_deref() == Some(node);
+    match (old, new) {
+        (None, Some(new)) if ours(new) => {
+            let mut events = vec![Created];
+            events.extend(transitions(node, Some(&new.as_created()), Some(new)));
+            events
+        }
+        (Some(old), None) if ours(old) => vec![Removed],
+        (Some(old), Some(new)) if ours(old) && new.node.is_none() => vec![Removed],
+        (Some(old), Some(new)) if ours(new) => {
+            let mut events = Vec::new();
+            let failed_again = old.health == SubgraphHealth::Failed
+                && new.health == SubgraphHealth::Failed
+                && old.error != new.error;
+            if old.health != new.health || failed_again {
+                events.push(match new.health {
+                    SubgraphHealth::Healthy => Healthy,
+                    SubgraphHealth::Unhealthy => Unhealthy,
+                    SubgraphHealth::Failed => Failed,
+                    SubgraphHealth::Completed => Completed,
+                });
+            }
+            if !old.synced && new.synced {
+                events.push(Synced);
+            }
+            events
+        }
+        _ => vec![],
+    }
+}
+
+/// The events for all deployments between the poll that found `known`
+/// and the one that found `current`
+fn events(
+    node: &str,
+    known: &HashMap<String, Deployment>,
+    current: &HashMap<String, Deployment>,
+) -> Vec<(DeploymentEvent, Deployment)> {
+    let mut events = Vec::new();
+    for (hash, deployment) in current {
+        for event in transitions(node, known.get(hash), Some(deployment)) {
+            events.push((event, deployment.clone()));
+        }
+    }
+    for (hash, deployment) in known {
+        if !current.contains_key(hash) {
+            for event in transitions(node, Some(deployment), None) {
+                events.push((event, deployment.clone()));
+            }
+        }
+    }
+    events
+}
+
+/// Look up the state of the deployments assigned to `node`, and of the
+/// deployments in `known` that are not assigned to it anymore so that we
+/// can tell whether they were removed or moved to another node. `lookup`
+/// returns the stored state of the deployments that match a filter
+fn poll_deployments(
+    node: &NodeId,
+    known: &[String],
+    lookup: impl Fn(status::Filter) -> Result<Vec<Deployment>, StoreError>,
+) -> Result<HashMap<String, Deployment>, StoreError> {
+    let by_hash = |deployments: Vec<Deployment>| {
+        deployments
+            .into_iter()
+            .map(|deployment| (deployment.hash.clone(), deployment))
+    };
+
+    let mut current: HashMap<_, _> = by_hash(lookup(status::Filter::Node(node.clone()))?).collect();
+    let gone: Vec<_> = known
+        .iter()
+        .filter(|hash| !current.contains_key(*hash))
+        .cloned()
+        .collect();
+    // An empty list of deployments would look up all of them
+    if !gone.is_empty() {
+        current.extend(by_hash(lookup(status::Filter::Deployments(gone))?));
+    }
+    Ok(current)
+}
+
+/// The deployments in `deployments` that are assigned to `node`.
+/// Deployments that moved to another node or were removed are not ours
+/// to watch anymore
+fn assigned_to(
+    node: &str,
+    deployments: HashMap<String, Deployment>,
+) -> HashMap<String, Deployment> {
+    deployments
+        .into_iter()
+        .filter(|(_, deployment)| deployment.node.as_deref() == Some(node))
+        .collect()
+}
+
+/// The body of the POST request for an event
+#[derive(Serialize)]
+struct Payload<'a> {
+    event: DeploymentEvent,
+    deployment: &'a str,
+    names: &'a [String],
+    labels: &'a Labels,
+    network: Option<&'a str>,
+    block: Option<BlockNumber>,
+    error: Option<&'a str>,
+    node: &'a str,
+    /// Seconds since the epoch at which we noticed the event
+    timestamp: u64,
+}
+
+/// The value of the signature header for `body`
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Sends events about deployments assigned to this node to webhooks
+pub struct WebhookNotifier<S> {
+    logger: Logger,
+    store: Arc<S>,
+    node_id: NodeId,
+    webhooks: Arc<Vec<Webhook>>,
+    client: reqwest::Client,
+    undelivered: Box<CounterVec>,
+}
+
+impl<S: StatusStore> WebhookNotifier<S> {
+    pub fn new(
+        logger: &Logger,
+        store: Arc<S>,
+        node_id: NodeId,
+        webhooks: Vec<Webhook>,
+        registry: Arc<impl MetricsRegistry>,
+    ) -> Self {
+        let undelivered = registry
+            .new_counter_vec(
+                "webhook_undelivered_events",
+                "Counts deployment events that could not be delivered to a webhook",
+                vec![String::from("event")],
+            )
+            .expect("failed to create `webhook_undelivered_events` counter");
+        WebhookNotifier {
+            logger: logger.new(o!("component" => "WebhookNotifier")),
+            store,
+            node_id,
+            webhooks: Arc::new(webhooks),
+            client: reqwest::Client::new(),
+            undelivered,
+        }
+    }
+
+    /// Poll the state of deployments every `GRAPH_WEBHOOK_POLL_INTERVAL`
+    /// seconds. The first poll only establishes what state deployments
+    /// are in and does not send any events
+    pub fn start(self) {
+        if self.webhooks.is_empty() {
+            return;
+        }
+        info!(self.logger, "Sending deployment events to webhooks";
+              "webhooks" => self.webhooks.len());
+        graph::spawn_named("webhook-notifier", async move {
+            let mut known: Option<HashMap<String, Deployment>> = None;
+            let mut interval = tokio::time::interval(*POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let current = match self.poll(known.as_ref()).await {
+                    Ok(current) => current,
+                    Err(e) => {
+                        warn!(self.logger, "Failed to look up the status of deployments";
+                              "error" => e);
+                        continue;
+                    }
+                };
+                if let Some(known) = &known {
+                    for (event, deployment) in events(self.node_id.as_str(), known, &current) {
+                        self.send(event, &deployment);
+                    }
+                }
+                known = Some(assigned_to(self.node_id.as_str(), current));
+            }
+        });
+    }
+
+    async fn poll(
+        &self,
+        known: Option<&HashMap<String, Deployment>>,
+    ) -> Result<HashMap<String, Deployment>, String> {
+        let store = self.store.clone();
+        let node = self.node_id.clone();
+        let known: Vec<_> = known
+            .map(|known| known.keys().cloned().collect())
+            .unwrap_or_default();
+        graph::spawn_blocking_allow_panic(move || {
+            poll_deployments(&node, &known, |filter| {
+                let infos = store.status(filter)?;
+                Ok(infos.into_iter().map(Deployment::from).collect())
+            })
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+    }
+
+    /// Deliver `event` to all webhooks that want it, each in its own task
+    fn send(&self, event: DeploymentEvent, deployment: &Deployment) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let payload = Payload {
+            event,
+            deployment: &deployment.hash,
+            names: &deployment.names,
+            labels: &deployment.labels,
+            network: deployment.network.as_deref(),
+            block: deployment.block,
+            error: deployment.error.as_deref(),
+            node: self.node_id.as_str(),
+            timestamp,
+        };
+        let body = serde_json::to_vec(&payload).expect("deployment events can be serialized");
+
+        info!(self.logger, "Deployment changed state";
+              "event" => event.as_str(),
+              "deployment" => &deployment.hash);
+        for webhook in self.webhooks.iter().filter(|webhook| webhook.wants(event)) {
+            let logger = self.logger.clone();
+            let client = self.client.clone();
+            let webhook = webhook.clone();
+            let body = body.clone();
+            let undelivered = self.undelivered.clone();
+            graph::spawn_named("webhook-delivery", async move {
+                if !deliver(&logger, &client, &webhook, event, body).await {
+                    undelivered.with_label_values(&[event.as_str()]).inc();
+                }
+            });
+        }
+    }
+}
+
+/// Try to deliver `body` to `webhook` up to `GRAPH_WEBHOOK_MAX_ATTEMPTS`
+/// times and return whether that succeeded
+async fn deliver(
+    logger: &Logger,
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    event: DeploymentEvent,
+    body: Vec<u8>,
+) -> bool {
+    let logger = logger.new(o!("event" => event.as_str(), "url" => webhook.url.clone()));
+    let client = client.clone();
+    let url = webhook.url.clone();
+    let signature = webhook
+        .secret
+        .as_ref()
+        .map(|secret| signature(secret, &body));
+    let res = retry("webhook delivery", &logger)
+        .limit(*MAX_ATTEMPTS as usize)
+        .backoff(INITIAL_BACKOFF, MAX_BACKOFF)
+        .timeout(REQUEST_TIMEOUT)
+        .run(move || {
+            let mut request = client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, event.as_str());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature.as_str());
+            }
+            let request = request.body(body.clone());
+            async move {
+                request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(|_| ())
+            }
+        })
+        .await;
+    match res {
+        Ok(()) => true,
+        Err(e) => {
+            error!(logger, "Giving up on delivering event to webhook";
+                   "attempts" => *MAX_ATTEMPTS,
+                   "error" => e.to_string());
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DeploymentEvent::*;
+
+    fn deployment(node: Option<&str>, health: SubgraphHealth, synced: bool) -> Deployment {
+        Deployment {
+            hash: "QmWebhook".to_string(),
+            names: vec!["webhook/test".to_string()],
+            labels: Labels::new(),
+            network: Some("mainnet".to_string()),
+            block: Some(7),
+            health,
+            synced,
+            node: node.map(str::to_string),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn deployment_transitions() {
+        use SubgraphHealth as H;
+
+        let healthy = deployment(Some("me"), H::Healthy, false);
+        let synced = deployment(Some("me"), H::Healthy, true);
+        let failed = deployment(Some("me"), H::Failed, true);
+        let completed = deployment(Some("me"), H::Completed, true);
+        let elsewhere = deployment(Some("other"), H::Healthy, false);
+        let unassigned = deployment(None, H::Healthy, false);
+
+        assert_eq!(vec![Created], transitions("me", None, Some(&healthy)));
+        assert!(transitions("me", None, Some(&elsewhere)).is_empty());
+        assert!(transitions("me", Some(&healthy), Some(&healthy)).is_empty());
+        assert_eq!(
+            vec![Synced],
+            transitions("me", Some(&healthy), Some(&synced))
+        );
+        assert_eq!(
+            vec![Failed, Synced],
+            transitions("me", Some(&healthy), Some(&failed))
+        );
+        assert_eq!(
+            vec![Healthy],
+            transitions("me", Some(&failed), Some(&synced))
+        );
+        assert_eq!(
+            vec![Completed],
+            transitions("me", Some(&synced), Some(&completed))
+        );
+        assert_eq!(vec![Removed], transitions("me", Some(&healthy), None));
+
+        // Deployments that changed state before we first saw them
+        assert_eq!(
+            vec![Created, Failed, Synced],
+            transitions("me", None, Some(&failed))
+        );
+        let failed_again = Deployment {
+            error: Some("failed again".to_string()),
+            ..failed.clone()
+        };
+        assert_eq!(
+            vec![Failed],
+            transitions("me", Some(&failed), Some(&failed_again))
+        );
+        assert_eq!(
+            vec![Removed],
+            transitions("me", Some(&healthy), Some(&unassigned))
+        );
+
+        // Moving between nodes is neither a removal nor a creation
+        assert!(transitions("me", Some(&healthy), Some(&elsewhere)).is_empty());
+        assert!(transitions("me", Some(&elsewhere), Some(&healthy)).is_empty());
+        assert!(transitions("me", Some(&elsewhere), None).is_empty());
+    }
+
+    #[test]
+    fn poll_deployments_of_this_node() {
+        use std::cell::RefCell;
+        use SubgraphHealth as H;
+
+        let me = NodeId::new("me").unwrap();
+        let with_hash = |hash: &str, deployment: Deployment| Deployment {
+            hash: hash.to_string(),
+            ..deployment
+        };
+        let store = RefCell::new(vec![
+            with_hash("QmA", deployment(Some("me"), H::Healthy, false)),
+            with_hash("QmB", deployment(Some("other"), H::Healthy, false)),
+        ]);
+        let lookups = RefCell::new(Vec::new());
+        let lookup = |filter: status::Filter| {
+            let deployments = store.borrow();
+            let found: Vec<Deployment> = match filter {
+                status::Filter::Node(node) => {
+                    lookups.borrow_mut().push(format!("node {}", node.as_str()));
+                    deployments
+                        .iter()
+                        .filter(|deployment| deployment.node.as_deref() == Some(node.as_str()))
+                        .cloned()
+                        .collect()
+                }
+                status::Filter::Deployments(hashes) => {
+                    lookups.borrow_mut().push(hashes.join(","));
+                    deployments
+                        .iter()
+                        .filter(|deployment| hashes.contains(&deployment.hash))
+                        .cloned()
+                        .collect()
+                }
+                _ => panic!("unexpected filter"),
+            };
+            Ok::<_, StoreError>(found)
+        };
+        let sorted = |mut events: Vec<(DeploymentEvent, Deployment)>| {
+            events.sort_by(|a, b| (&a.1.hash, a.0 as u8).cmp(&(&b.1.hash, b.0 as u8)));
+            events
+                .into_iter()
+                .map(|(event, deployment)| (event, deployment.hash))
+                .collect::<Vec<_>>()
+        };
+
+        // Only the deployment assigned to this node is polled
+        let known = assigned_to("me", poll_deployments(&me, &[], &lookup).unwrap());
+        assert_eq!(vec!["QmA"], known.keys().collect::<Vec<_>>());
+        assert_eq!(vec!["node me"], *lookups.borrow());
+
+        // QmA moves to another node, and QmC is created and fails before
+        // the next poll
+        store.borrow_mut()[0].node = Some("other".to_string());
+        store
+            .borrow_mut()
+            .push(with_hash("QmC", deployment(Some("me"), H::Failed, false)));
+        lookups.borrow_mut().clear();
+        let current = poll_deployments(&me, &["QmA".to_string()], &lookup).unwrap();
+        assert_eq!(vec!["node me", "QmA"], *lookups.borrow());
+        assert_eq!(
+            vec![(Created, "QmC".to_string()), (Failed, "QmC".to_string())],
+            sorted(events("me", &known, &current))
+        );
+        let known = assigned_to("me", current);
+
+        // QmC is removed
+        store.borrow_mut().pop();
+        let current = poll_deployments(&me, &["QmC".to_string()], &lookup).unwrap();
+        assert_eq!(
+            vec![(Removed, "QmC".to_string())],
+            sorted(events("me", &known, &current))
+        );
+    }
+
+    #[test]
+    fn webhook_events() {
+        let all = Webhook {
+            url: "http://localhost/all".to_string(),
+            events: vec![],
+            secret: None,
+        };
+        let failures = Webhook {
+            url: "http://localhost/failures".to_string(),
+            events: vec![Failed, Unhealthy],
+            secret: None,
+        };
+        assert!(all.wants(Created) && all.wants(Failed));
+        assert!(failures.wants(Failed));
+        assert!(!failures.wants(Synced));
+
+        let events: Vec<DeploymentEvent> =
+            serde_json::from_str(r#"["created", "failed", "removed"]"#).unwrap();
+        assert_eq!(vec![Created, Failed, Removed], events);
+    }
+
+    #[test]
+    fn payload_signature() {
+        // Test vector from RFC 4231, test case 2
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            signature("Jefe", b"what do ya want for nothing?")
+        );
+    }
+
+    /// Read one HTTP request from `socket` and return its lowercased
+    /// headers and its body
+    async fn read_request(
+        socket: &mut tokio::net::TcpStream,
+    ) -> (HashMap<String, String>, Vec<u8>) {
+        use tokio::io::AsyncReadExt;
+
+        let mut buf = Vec::new();
+        let head_len = loop {
+            let mut chunk = [0u8; 1024];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the request was complete");
+            buf.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+        let head = String::from_utf8(buf[..head_len].to_vec()).unwrap();
+        let headers: HashMap<_, _> = head
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+            .collect();
+        let len: usize = headers["content-length"].parse().unwrap();
+        let mut body = buf[head_len..].to_vec();
+        while body.len() < len {
+            let mut chunk = [0u8; 1024];
+            let n = socket.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the body was complete");
+            body.extend_from_slice(&chunk[..n]);
+        }
+        (headers, body)
+    }
+
+    #[tokio::test]
+    async fn deliver_signed_payload() {
+        use tokio::io::AsyncWriteExt;
+
+        const SECRET: &str = "webhook secret";
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        // The first attempt fails, and the delivery is retried
+        let server = graph::spawn(async move {
+            let mut requests = Vec::new();
+            for status in &["500 Internal Server Error", "200 OK"] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                requests.push(read_request(&mut socket).await);
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    status
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        let webhook = Webhook {
+            url,
+            events: vec![],
+            secret: Some(SECRET.to_string()),
+        };
+        let logger = Logger::root(graph::prelude::slog::Discard, o!());
+        let body = br#"{"event":"failed","deployment":"QmWebhook"}"#.to_vec();
+        assert!(
+            deliver(
+                &logger,
+                &reqwest::Client::new(),
+                &webhook,
+                Failed,
+                body.clone()
+            )
+            .await
+        );
+
+        let requests = server.await.unwrap();
+        assert_eq!(2, requests.len());
+        for (headers, received) in requests {
+            assert_eq!(body, received);
+            assert_eq!("failed", headers[EVENT_HEADER]);
+            assert_eq!("application/json", headers["content-type"]);
+
+            // The receiver can verify the signature with the shared secret
+            let mac =
+                hex::decode(headers[SIGNATURE_HEADER].strip_prefix("sha256=").unwrap()).unwrap();
+            let verify = |secret: &str, body: &[u8]| {
+                let mut hmac = Hmac::<Sha256>::new_varkey(secret.as_bytes()).unwrap();
+                hmac.update(body);
+                hmac.verify(&mac).is_ok()
+            };
+            assert!(verify(SECRET, &received));
+            assert!(!verify("wrong secret", &received));
+            assert!(!verify(SECRET, b"{}"));
+        }
+    }
+}
@@ -1,8 +1,17 @@
-use std::{sync::Arc, time::Duration};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use std::{env, sync::Arc};
 
 use crate::{
     blockchain::{Blockchain, IngestorAdapter, IngestorError},
-    prelude::{info, lazy_static, tokio, trace, warn, Error, LogCode, Logger},
+    components::store::BlockNumber,
+    prelude::{
+        debug, info, lazy_static, tokio, trace, warn, Error, Gauge, LogCode, Logger,
+        MetricsRegistry,
+    },
+    prometheus::labels,
+    util::futures::{record_retry, Backoff},
+    util::shutdown,
 };
 
 lazy_static! {
@@ -12,6 +21,116 @@ lazy_static! {
         .ok()
         .map(|s| s.eq_ignore_ascii_case("true"))
         .unwrap_or(false);
+
+    /// The smallest interval, in milliseconds, at which adaptive polling
+    /// will poll for new blocks. Defaults to 100ms
+    static ref ADAPTIVE_POLLING_MIN: Duration =
+        polling_bound("GRAPH_ETHEREUM_POLLING_INTERVAL_MIN", 100);
+
+    /// The largest interval, in milliseconds, at which adaptive polling
+    /// will poll for new blocks. Defaults to 10s
+    static ref ADAPTIVE_POLLING_MAX: Duration =
+        polling_bound("GRAPH_ETHEREUM_POLLING_INTERVAL_MAX", 10_000);
+}
+
+fn polling_bound(var: &str, default: u64) -> Duration {
+    env::var(var)
+        .ok()
+        .map(|s| {
+            s.parse::<u64>().unwrap_or_else(|_| {
+                panic!("{} must be a number of milliseconds, but is `{}`", var, s)
+            })
+        })
+        .map(Duration::from_millis)
+        .unwrap_or(Duration::from_millis(default))
+}
+
+/// Tracks how quickly the chain head advances and derives the interval at
+/// which to poll for new blocks from that. When adaptive polling is turned
+/// off, the interval never changes from the one it was created with.
+struct PollingInterval {
+    /// The bounds `(min, max)` for the interval, or `None` if adaptive
+    /// polling is turned off
+    bounds: Option<(Duration, Duration)>,
+    current: Duration,
+    /// The most recent changes of the chain head that we saw, as the time
+    /// when we saw it and the new head block number
+    heads: VecDeque<(Instant, BlockNumber)>,
+    /// How many polls in a row did not produce a new chain head
+    idle_polls: u32,
+}
+
+impl PollingInterval {
+    /// How many changes of the chain head to use for estimating the block time
+    const SAMPLES: usize = 16;
+    /// Poll this fraction of the observed block time
+    const BLOCK_TIME_FRACTION: f64 = 0.25;
+    /// Double the interval for every poll that does not see a new chain
+    /// head once this many polls in a row came up empty
+    const IDLE_POLLS_BEFORE_BACKOFF: u32 = 8;
+
+    fn fixed(interval: Duration) -> Self {
+        Self {
+            bounds: None,
+            current: interval,
+            heads: VecDeque::new(),
+            idle_polls: 0,
+        }
+    }
+
+    fn adaptive(initial: Duration, min: Duration, max: Duration) -> Self {
+        Self {
+            bounds: Some((min, max)),
+            current: initial.max(min).min(max),
+            heads: VecDeque::new(),
+            idle_polls: 0,
+        }
+    }
+
+    fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Record that a poll at `now` found the chain head at block `head` and
+    /// adjust the polling interval accordingly
+    fn observe(&mut self, now: Instant, head: BlockNumber) {
+        let (min, max) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let advanced = match self.heads.back() {
+            Some((_, last)) => head > *last,
+            None => true,
+        };
+
+        if !advanced {
+            self.idle_polls += 1;
+            if self.idle_polls > Self::IDLE_POLLS_BEFORE_BACKOFF {
+                self.current = (self.current * 2).min(max);
+            }
+            return;
+        }
+
+        self.idle_polls = 0;
+        self.heads.push_back((now, head));
+        while self.heads.len() > Self::SAMPLES + 1 {
+            self.heads.pop_front();
+        }
+
+        if let (Some((first_seen, first)), Some((last_seen, last))) =
+            (self.heads.front(), self.heads.back())
+        {
+            if last > first {
+                let block_time =
+                    last_seen.saturating_duration_since(*first_seen) / (*last - *first) as u32;
+                self.current = block_time
+                    .mul_f64(Self::BLOCK_TIME_FRACTION)
+                    .max(min)
+                    .min(max);
+            }
+        }
+    }
 }
 
 pub struct BlockIngestor<C>
@@ -20,28 +139,65 @@ where
 {
     adapter: Arc<C::IngestorAdapter>,
     logger: Logger,
-    polling_interval: Duration,
+    polling_interval: PollingInterval,
+    polling_interval_gauge: Box<Gauge>,
+    /// How long to wait after polls that failed. We never poll more often
+    /// than the polling interval
+    failures: Backoff,
 }
 
 impl<C> BlockIngestor<C>
 where
     C: Blockchain,
 {
+    /// Create a block ingestor for `network` that polls for new blocks
+    /// every `polling_interval`. If `adaptive_polling` is `true`, the
+    /// ingestor only starts with that interval, and then adjusts it to the
+    /// block time it observes for the chain.
     pub fn new(
         adapter: Arc<C::IngestorAdapter>,
         polling_interval: Duration,
+        adaptive_polling: bool,
+        network: String,
+        registry: Arc<impl MetricsRegistry>,
     ) -> Result<BlockIngestor<C>, Error> {
         let logger = adapter.logger().clone();
+        let polling_interval = if adaptive_polling {
+            PollingInterval::adaptive(
+                polling_interval,
+                *ADAPTIVE_POLLING_MIN,
+                *ADAPTIVE_POLLING_MAX,
+            )
+        } else {
+            PollingInterval::fixed(polling_interval)
+        };
+        let polling_interval_gauge = registry.new_gauge(
+            "block_ingestor_polling_interval",
+            "The interval, in seconds, at which the block ingestor polls for new blocks",
+            labels! { String::from("network") => network },
+        )?;
+        polling_interval_gauge.set(polling_interval.current().as_secs_f64());
         Ok(BlockIngestor {
             adapter,
             logger,
             polling_interval,
+            polling_interval_gauge,
+            failures: Backoff::new(Duration::from_millis(500), Duration::from_secs(30)),
         })
     }
 
-    pub async fn into_polling_stream(self) {
+    pub async fn into_polling_stream(mut self) {
         loop {
-            match self.do_poll().await {
+            // Finish the poll that is under way before shutting down
+            let guard = shutdown::guard();
+            if shutdown::is_requested() {
+                info!(self.logger, "Stopping block ingestor for shutdown");
+                return;
+            }
+
+            let res = self.do_poll().await;
+            let failed = res.is_err();
+            match res {
                 // Some polls will fail due to transient issues
                 Err(err @ IngestorError::BlockUnavailable(_)) => {
                     info!(
@@ -61,14 +217,37 @@ where
                         "Trying again after block polling failed: {}", inner_err
                     );
                 }
-                Ok(()) => (),
+                Ok(head) => self.update_polling_interval(head),
             }
 
             if *CLEANUP_BLOCKS {
                 self.cleanup_cached_blocks()
             }
+            drop(guard);
+
+            // Back off while polls keep failing, e.g., because the Ethereum
+            // node is unreachable
+            let delay = if failed {
+                record_retry("block ingestor poll");
+                self.polling_interval
+                    .current()
+                    .max(self.failures.next_delay())
+            } else {
+                self.failures.reset();
+                self.polling_interval.current()
+            };
+            tokio::time::sleep(delay).await;
+        }
+    }
 
-            tokio::time::sleep(self.polling_interval).await;
+    fn update_polling_interval(&mut self, head: BlockNumber) {
+        let previous = self.polling_interval.current();
+        self.polling_interval.observe(Instant::now(), head);
+        let current = self.polling_interval.current();
+        if current != previous {
+            debug!(self.logger, "Adjusted block polling interval";
+                   "polling_interval_ms" => current.as_millis());
+            self.polling_interval_gauge.set(current.as_secs_f64());
         }
     }
 
@@ -93,7 +272,9 @@ where
         }
     }
 
-    async fn do_poll(&self) -> Result<(), IngestorError> {
+    /// Poll for new blocks and ingest them. Returns the number of the
+    /// latest block of the chain
+    async fn do_poll(&self) -> Result<BlockNumber, IngestorError> {
         trace!(self.logger, "BlockIngestor::do_poll");
 
         // Get chain head ptr from store
@@ -106,7 +287,7 @@ where
 
         // If latest block matches head block in store, nothing needs to be done
         if Some(&latest_block) == head_block_ptr_opt.as_ref() {
-            return Ok(());
+            return Ok(latest_block.number);
         }
 
         // Compare latest block with head ptr, alert user if far behind
@@ -170,6 +351,80 @@ where
         while let Some(hash) = missing_block_hash {
             missing_block_hash = self.adapter.ingest_block(&hash).await?;
         }
-        Ok(())
+        Ok(latest_block.number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PollingInterval;
+    use std::time::{Duration, Instant};
+
+    const MIN: Duration = Duration::from_millis(100);
+    const MAX: Duration = Duration::from_secs(10);
+
+    /// Simulate polling a chain that produces a block every `block_time`
+    /// and return the polling interval we settle on
+    fn settle(block_time: Duration) -> Duration {
+        let mut interval = PollingInterval::adaptive(Duration::from_millis(500), MIN, MAX);
+        let start = Instant::now();
+        let mut now = start;
+        for _ in 0..200 {
+            now += interval.current();
+            let head = (now.duration_since(start).as_millis() / block_time.as_millis()) as i32;
+            interval.observe(now, head);
+        }
+        interval.current()
+    }
+
+    #[test]
+    fn adapts_to_block_time() {
+        let mainnet = settle(Duration::from_secs(13));
+        assert!(mainnet >= Duration::from_secs(2) && mainnet <= Duration::from_secs(5));
+
+        let fast = settle(Duration::from_millis(250));
+        assert_eq!(MIN, fast);
+    }
+
+    #[test]
+    fn backs_off_when_idle() {
+        let block_time = Duration::from_secs(1);
+        let mut interval = PollingInterval::adaptive(block_time, MIN, MAX);
+        let mut now = Instant::now();
+        let mut head = 0;
+
+        // A chain with one block per second
+        let mut produce_blocks = |interval: &mut PollingInterval, now: &mut Instant| {
+            for _ in 0..=PollingInterval::SAMPLES {
+                *now += block_time;
+                head += 1;
+                interval.observe(*now, head);
+            }
+            head
+        };
+        let head = produce_blocks(&mut interval, &mut now);
+        assert_eq!(Duration::from_millis(250), interval.current());
+
+        // The chain stops producing blocks
+        for _ in 0..20 {
+            now += interval.current();
+            interval.observe(now, head);
+        }
+        assert_eq!(MAX, interval.current());
+
+        // Once the chain produces blocks again, we go back to the old interval
+        produce_blocks(&mut interval, &mut now);
+        assert_eq!(Duration::from_millis(250), interval.current());
+    }
+
+    #[test]
+    fn fixed_interval_never_changes() {
+        let mut interval = PollingInterval::fixed(Duration::from_millis(500));
+        let mut now = Instant::now();
+        for head in 0..20 {
+            now += Duration::from_secs(12);
+            interval.observe(now, head);
+        }
+        assert_eq!(Duration::from_millis(500), interval.current());
     }
 }
@@ -110,6 +110,7 @@ pub enum BlockStreamEvent<C: Blockchain> {
 pub struct BlockStreamMetrics {
     pub deployment_head: Box<Gauge>,
     pub deployment_failed: Box<Gauge>,
+    pub deployment_blocked: Box<Gauge>,
     pub reverted_blocks: Box<Gauge>,
     pub stopwatch: StopwatchMetrics,
 }
@@ -145,12 +146,21 @@ impl BlockStreamMetrics {
             .new_gauge(
                 "deployment_failed",
                 "Boolean gauge to indicate whether the deployment has failed (1 == failed)",
-                labels,
+                labels.clone(),
             )
             .expect("failed to create `deployment_failed` gauge");
+        let deployment_blocked = registry
+            .new_gauge(
+                "deployment_blocked",
+                "Boolean gauge to indicate whether writing to the deployment is blocked \
+                 because another node holds its writer lease (1 == blocked)",
+                labels,
+            )
+            .expect("failed to create `deployment_blocked` gauge");
         Self {
             deployment_head,
             deployment_failed,
+            deployment_blocked,
             reverted_blocks,
             stopwatch,
         }
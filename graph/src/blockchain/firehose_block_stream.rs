@@ -5,6 +5,7 @@ use std::time::Duration;
 
 use crate::firehose::endpoints::FirehoseEndpoint;
 use crate::prelude::*;
+use crate::util::futures::{record_retry, Backoff};
 
 use super::block_stream::{BlockStream, BlockStreamEvent, FirehoseMapper};
 use super::Blockchain;
@@ -58,7 +59,7 @@ pub struct FirehoseBlockStream<C: Blockchain, F: FirehoseMapper<C>> {
     endpoint: Arc<FirehoseEndpoint>,
     state: BlockStreamState,
     ctx: FirehoseBlockStreamContext<C, F>,
-    connection_attempts: u64,
+    backoff: Backoff,
 }
 
 impl<C, F> FirehoseBlockStream<C, F>
@@ -90,7 +91,7 @@ where
                 filter,
                 start_blocks,
             },
-            connection_attempts: 0,
+            backoff: Backoff::new(Duration::from_secs(4), Duration::from_secs(64)),
         }
     }
 }
@@ -139,7 +140,7 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Stream for FirehoseBlockStream<C, F> {
                     match stream_connection.poll_unpin(cx) {
                         Poll::Ready(Ok(streaming)) => {
                             self.state = BlockStreamState::Connected(streaming);
-                            self.connection_attempts = 0;
+                            self.backoff.reset();
                             info!(self.ctx.logger, "Blockstream connected");
 
                             // Re-loop to next state
@@ -241,10 +242,10 @@ impl<C: Blockchain, F: FirehoseMapper<C>> Stream for FirehoseBlockStream<C, F> {
 
 impl<C: Blockchain, F: FirehoseMapper<C>> FirehoseBlockStream<C, F> {
     /// Schedule a delayed function that will wake us later in time. This implementation
-    /// uses an exponential backoff strategy to retry with incremental longer delays.
+    /// uses a jittered exponential backoff to retry with incremental longer delays.
     fn schedule_error_retry<T>(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
-        self.connection_attempts += 1;
-        let wait_duration = wait_duration(self.connection_attempts);
+        record_retry("firehose block stream");
+        let wait_duration = self.backoff.next_delay();
 
         let waker = cx.waker().clone();
         tokio::spawn(async move {
@@ -255,13 +256,3 @@ impl<C: Blockchain, F: FirehoseMapper<C>> FirehoseBlockStream<C, F> {
         Poll::Pending
     }
 }
-
-fn wait_duration(attempt_number: u64) -> Duration {
-    let pow = if attempt_number > 5 {
-        5
-    } else {
-        attempt_number
-    };
-
-    Duration::from_secs(2 << pow)
-}
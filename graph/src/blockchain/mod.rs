@@ -18,6 +18,7 @@ use crate::{
     data::subgraph::UnifiedMappingApiVersion,
     prelude::{DataSourceContext, SubgraphManifestValidationError},
     runtime::{AscHeap, AscPtr, DeterministicHostError, HostExportError},
+    util::scan_counter::ScanCounter,
 };
 use crate::{
     components::{
@@ -33,7 +34,7 @@ use slog::Logger;
 use slog::{self, SendSyncRefUnwindSafeKV};
 use std::{
     any::Any,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     convert::TryFrom,
     fmt::{self, Debug},
     str::FromStr,
@@ -61,6 +62,12 @@ pub trait Block: Send + Sync {
     fn parent_hash(&self) -> Option<BlockHash> {
         self.parent_ptr().map(|ptr| ptr.hash)
     }
+
+    /// The time at which the block was produced, in seconds since the
+    /// epoch, if the chain records that
+    fn timestamp(&self) -> Option<u64> {
+        None
+    }
 }
 
 #[async_trait]
@@ -113,6 +120,10 @@ pub trait Blockchain: Debug + Sized + Send + Sync + Unpin + 'static {
 
     fn ingestor_adapter(&self) -> Arc<Self::IngestorAdapter>;
 
+    /// Check that the chain has a provider with `capabilities`. The error
+    /// should name the capabilities that are missing
+    fn check_capabilities(&self, capabilities: &Self::NodeCapabilities) -> Result<(), Error>;
+
     fn chain_store(&self) -> Arc<dyn ChainStore>;
 
     async fn block_pointer_from_number(
@@ -199,11 +210,25 @@ pub trait DataSource<C: Blockchain>:
 {
     fn address(&self) -> Option<&[u8]>;
     fn start_block(&self) -> BlockNumber;
+    fn end_block(&self) -> Option<BlockNumber>;
     fn name(&self) -> &str;
+    /// The kinds of handlers this data source has, e.g., `event` or
+    /// `block/once`
+    fn handler_kinds(&self) -> BTreeSet<&'static str>;
     fn kind(&self) -> &str;
     fn network(&self) -> Option<&str>;
     fn context(&self) -> Arc<Option<DataSourceContext>>;
     fn creation_block(&self) -> Option<BlockNumber>;
+    /// Tell the data source which block its deployment processes first;
+    /// only called for the data sources in the manifest
+    fn set_first_block(&mut self, first_block: BlockNumber);
+    /// The block in which the handlers of this data source that only run
+    /// once run, or `None` if it has no such handlers
+    fn once_handler_block(&self) -> Option<BlockNumber>;
+    /// Tell the data source that its handlers that only run once already
+    /// ran in `block` so that they only run again if `block` is processed
+    /// again
+    fn set_once_handlers_ran(&mut self, block: BlockNumber);
     fn api_version(&self) -> semver::Version;
     fn runtime(&self) -> &[u8];
 
@@ -244,6 +269,8 @@ pub trait DataSourceTemplate<C: Blockchain>: Send + Sync + Clone + Debug {
     fn api_version(&self) -> semver::Version;
     fn runtime(&self) -> &[u8];
     fn name(&self) -> &str;
+    /// The kinds of handlers data sources created from this template have
+    fn handler_kinds(&self) -> BTreeSet<&'static str>;
 }
 
 #[async_trait]
@@ -274,11 +301,20 @@ pub trait MappingTrigger: Send + Sync {
     fn logging_extras(&self) -> Box<dyn SendSyncRefUnwindSafeKV> {
         Box::new(slog::o! {})
     }
+
+    /// Whether this trigger is handled by a block handler. Block handlers
+    /// run after all other handlers for a block and can therefore see
+    /// everything that was changed in the block.
+    fn is_block_handler(&self) -> bool {
+        false
+    }
 }
 
 pub struct HostFnCtx<'a> {
     pub logger: Logger,
     pub block_ptr: BlockPtr,
+    /// Counts what the host function fetches from the chain
+    pub scan: Arc<ScanCounter>,
     pub heap: &'a mut dyn AscHeap,
 }
 
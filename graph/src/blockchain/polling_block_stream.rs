@@ -3,7 +3,7 @@ use futures03::{stream::Stream, Future, FutureExt};
 use std::cmp;
 use std::collections::VecDeque;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::Duration;
 
@@ -15,8 +15,10 @@ use super::{Block, BlockPtr, Blockchain};
 
 use crate::components::store::BlockNumber;
 use crate::components::store::WritableStore;
+use crate::components::subgraph::SYNCED_CRITERION;
 use crate::data::subgraph::UnifiedMappingApiVersion;
 use crate::prelude::*;
+use crate::util::futures::{record_retry, Backoff};
 #[cfg(debug_assertions)]
 use fail::fail_point;
 
@@ -98,6 +100,9 @@ where
     max_block_range_size: BlockNumber,
     target_triggers_per_block_range: u64,
     unified_api_version: UnifiedMappingApiVersion,
+    /// How many consecutive checks found the subgraph close enough to the
+    /// chain head to count as synced; `None` until the first check
+    synced_checks: Arc<Mutex<Option<u32>>>,
 }
 
 impl<C: Blockchain> Clone for PollingBlockStreamContext<C> {
@@ -118,6 +123,7 @@ impl<C: Blockchain> Clone for PollingBlockStreamContext<C> {
             max_block_range_size: self.max_block_range_size,
             target_triggers_per_block_range: self.target_triggers_per_block_range,
             unified_api_version: self.unified_api_version.clone(),
+            synced_checks: self.synced_checks.cheap_clone(),
         }
     }
 }
@@ -125,8 +131,16 @@ impl<C: Blockchain> Clone for PollingBlockStreamContext<C> {
 pub struct PollingBlockStream<C: Blockchain> {
     state: BlockStreamState<C>,
     consecutive_err_count: u32,
+    /// How long to pause after errors
+    backoff: Backoff,
     chain_head_update_stream: ChainHeadUpdateStream,
     ctx: PollingBlockStreamContext<C>,
+    /// The block the subgraph will be at once everything this stream has
+    /// yielded so far has been processed. Consumers may queue blocks, and
+    /// the subgraph pointer in the store can therefore lag behind what the
+    /// stream has yielded. Until the stream has yielded anything, the
+    /// subgraph pointer from the store is used
+    yielded_ptr: Option<BlockPtr>,
 }
 
 // This is the same as `ReconciliationStep` but without retries.
@@ -138,8 +152,8 @@ where
     Blocks(VecDeque<BlockWithTriggers<C>>, BlockNumber),
 
     // The payload is the current subgraph head pointer, which should be reverted, such that the
-    // parent of the current subgraph head becomes the new subgraph head.
-    Revert(BlockPtr),
+    // parent of the current subgraph head becomes the new subgraph head, and that parent.
+    Revert(BlockPtr, BlockPtr),
     Done,
 }
 
@@ -166,6 +180,7 @@ where
         Self {
             state: BlockStreamState::BeginReconciliation,
             consecutive_err_count: 0,
+            backoff: Backoff::new(Duration::from_secs(5), Duration::from_secs(120)),
             chain_head_update_stream,
             ctx: PollingBlockStreamContext {
                 subgraph_store,
@@ -185,7 +200,9 @@ where
                 max_block_range_size,
                 target_triggers_per_block_range,
                 unified_api_version,
+                synced_checks: Arc::new(Mutex::new(None)),
             },
+            yielded_ptr: None,
         }
     }
 }
@@ -195,11 +212,14 @@ where
     C: Blockchain,
 {
     /// Perform reconciliation steps until there are blocks to yield or we are up-to-date.
-    async fn next_blocks(&self) -> Result<NextBlocks<C>, Error> {
+    /// `yielded_ptr` is the block the stream has moved the subgraph to so far
+    async fn next_blocks(&self, yielded_ptr: Option<BlockPtr>) -> Result<NextBlocks<C>, Error> {
         let ctx = self.clone();
 
+        ctx.check_synced()?;
+
         loop {
-            match ctx.get_next_step().await? {
+            match ctx.get_next_step(yielded_ptr.clone()).await? {
                 ReconciliationStep::ProcessDescendantBlocks(next_blocks, range_size) => {
                     return Ok(NextBlocks::Blocks(
                         next_blocks.into_iter().collect(),
@@ -210,25 +230,31 @@ where
                     continue;
                 }
                 ReconciliationStep::Done => {
-                    // Reconciliation is complete, so try to mark subgraph as Synced
-                    ctx.update_subgraph_synced_status()?;
-
                     return Ok(NextBlocks::Done);
                 }
-                ReconciliationStep::Revert(block) => return Ok(NextBlocks::Revert(block)),
+                ReconciliationStep::Revert(block) => {
+                    let parent = ctx.adapter.parent_ptr(&block).await?;
+                    return Ok(NextBlocks::Revert(block, parent));
+                }
             }
         }
     }
 
     /// Determine the next reconciliation step. Does not modify Store or ChainStore.
-    async fn get_next_step(&self) -> Result<ReconciliationStep<C>, Error> {
+    async fn get_next_step(
+        &self,
+        yielded_ptr: Option<BlockPtr>,
+    ) -> Result<ReconciliationStep<C>, Error> {
         let ctx = self.clone();
         let start_blocks = self.start_blocks.clone();
         let max_block_range_size = self.max_block_range_size;
 
         // Get pointers from database for comparison
         let head_ptr_opt = ctx.chain_store.chain_head_ptr()?;
-        let subgraph_ptr = ctx.subgraph_store.block_ptr()?;
+        let subgraph_ptr = match yielded_ptr {
+            Some(ptr) => Some(ptr),
+            None => ctx.subgraph_store.block_ptr()?,
+        };
 
         // If chain head ptr is not set yet
         let head_ptr = match head_ptr_opt {
@@ -466,23 +492,35 @@ where
         }
     }
 
-    /// Set subgraph deployment entity synced flag if and only if the subgraph block pointer is
-    /// caught up to the head block pointer.
-    fn update_subgraph_synced_status(&self) -> Result<(), Error> {
-        let head_ptr_opt = self.chain_store.chain_head_ptr()?;
-        let subgraph_ptr = self.subgraph_store.block_ptr()?;
+    /// Check whether the subgraph is close enough to the chain head to
+    /// count as synced according to `SYNCED_CRITERION`. Once it has been
+    /// for enough consecutive checks, mark the deployment as synced, which
+    /// also promotes it if it is the pending version of a subgraph
+    fn check_synced(&self) -> Result<(), Error> {
+        let head_ptr = match self.chain_store.chain_head_ptr()? {
+            Some(head_ptr) => head_ptr,
+            // Without a chain head, we can't tell how far behind we are
+            None => return Ok(()),
+        };
+        let distance = match self.subgraph_store.block_ptr()? {
+            Some(ptr) => head_ptr.number - ptr.number,
+            None => BlockNumber::MAX,
+        };
 
-        if head_ptr_opt != subgraph_ptr || head_ptr_opt.is_none() || subgraph_ptr.is_none() {
-            // Not synced yet
-            Ok(())
-        } else {
-            // Synced
+        let mut synced_checks = self.synced_checks.lock().unwrap();
+        let checks = SYNCED_CRITERION.check(synced_checks.unwrap_or(0), distance);
+        if *synced_checks != Some(checks) {
+            self.subgraph_store.set_synced_checks(checks)?;
+            *synced_checks = Some(checks);
+        }
 
+        if SYNCED_CRITERION.is_met(checks) {
             // Stop recording time-to-sync metrics.
             self.metrics.stopwatch.disable();
 
-            self.subgraph_store.deployment_synced()
+            self.subgraph_store.deployment_synced()?;
         }
+        Ok(())
     }
 }
 
@@ -497,7 +535,8 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                 BlockStreamState::BeginReconciliation => {
                     // Start the reconciliation process by asking for blocks
                     let ctx = self.ctx.clone();
-                    let fut = async move { ctx.next_blocks().await };
+                    let yielded_ptr = self.yielded_ptr.clone();
+                    let fut = async move { ctx.next_blocks(yielded_ptr).await };
                     self.state = BlockStreamState::Reconciliation(fut.boxed());
                 }
 
@@ -514,6 +553,7 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                                     (self.ctx.max_block_range_size * 9 / 10).max(10);
                             }
                             self.consecutive_err_count = 0;
+                            self.backoff.reset();
 
                             let total_triggers =
                                 next_blocks.iter().map(|b| b.trigger_count()).sum::<usize>();
@@ -534,6 +574,7 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                         Poll::Ready(Ok(NextBlocks::Done)) => {
                             // Reset error count
                             self.consecutive_err_count = 0;
+                            self.backoff.reset();
 
                             // Switch to idle
                             self.state = BlockStreamState::Idle;
@@ -541,7 +582,8 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                             // Poll for chain head update
                             continue;
                         }
-                        Poll::Ready(Ok(NextBlocks::Revert(block))) => {
+                        Poll::Ready(Ok(NextBlocks::Revert(block, parent))) => {
+                            self.yielded_ptr = Some(parent);
                             self.state = BlockStreamState::BeginReconciliation;
                             break Poll::Ready(Some(Ok(BlockStreamEvent::Revert(
                                 block,
@@ -558,10 +600,9 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                             self.consecutive_err_count += 1;
 
                             // Pause before trying again
-                            let secs = (5 * self.consecutive_err_count).max(120) as u64;
-
+                            record_retry("polling block stream");
                             self.state = BlockStreamState::RetryAfterDelay(Box::pin(
-                                tokio::time::sleep(Duration::from_secs(secs)).map(Ok),
+                                tokio::time::sleep(self.backoff.next_delay()).map(Ok),
                             ));
 
                             break Poll::Ready(Some(Err(e)));
@@ -574,6 +615,7 @@ impl<C: Blockchain> Stream for PollingBlockStream<C> {
                     match next_blocks.pop_front() {
                         // Yield one block
                         Some(next_block) => {
+                            self.yielded_ptr = Some(next_block.ptr());
                             break Poll::Ready(Some(Ok(BlockStreamEvent::ProcessBlock(
                                 next_block,
                                 FirehoseCursor::None,
@@ -131,7 +131,10 @@ impl From<(H256, i32)> for BlockPtr {
 
 impl From<(H256, u64)> for BlockPtr {
     fn from((hash, number): (H256, u64)) -> BlockPtr {
-        let number = i32::try_from(number).unwrap();
+        // Block sources check that block numbers fit before they hand
+        // blocks on, so getting here with a bigger number is a bug
+        let number = i32::try_from(number)
+            .unwrap_or_else(|_| panic!("block number out of range: {}", number));
 
         BlockPtr::from((hash, number))
     }
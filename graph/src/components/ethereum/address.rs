@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+use tiny_keccak::keccak256;
+use web3::types::Address;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("invalid address `{0}`: {1}")]
+    Invalid(String, String),
+    #[error("invalid checksum for address `{0}` (did you mean `{1}`?)")]
+    Checksum(String, String),
+}
+
+/// The EIP-55 mixed-case checksum encoding of `address`, including the
+/// `0x` prefix
+pub fn to_checksum(address: &Address) -> String {
+    let hex = hex::encode(address.as_bytes());
+    let hash = keccak256(hex.as_bytes());
+
+    let mut checksummed = String::with_capacity(42);
+    checksummed.push_str("0x");
+    for (i, c) in hex.chars().enumerate() {
+        let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0xf;
+        if nibble >= 8 {
+            checksummed.push(c.to_ascii_uppercase());
+        } else {
+            checksummed.push(c);
+        }
+    }
+    checksummed
+}
+
+/// Parse an address with or without the `0x` prefix. Addresses that are
+/// all lowercase or all uppercase are accepted as they are; if `s` mixes
+/// upper- and lowercase letters, it must have a valid EIP-55 checksum
+pub fn parse_address(s: &str) -> Result<Address, AddressError> {
+    let address = parse_address_unchecked(s)?;
+    check_checksum(s, &address)?;
+    Ok(address)
+}
+
+/// Parse an address with or without the `0x` prefix without looking at
+/// its capitalization
+pub fn parse_address_unchecked(s: &str) -> Result<Address, AddressError> {
+    let hex = s.trim_start_matches("0x");
+    if hex.len() != 40 {
+        return Err(AddressError::Invalid(
+            s.to_string(),
+            format!("expected 40 hex digits but got {}", hex.len()),
+        ));
+    }
+    Address::from_str(hex).map_err(|e| AddressError::Invalid(s.to_string(), e.to_string()))
+}
+
+/// Check that `s`, which was parsed as `address`, has a valid EIP-55
+/// checksum if it mixes upper- and lowercase letters
+pub fn check_checksum(s: &str, address: &Address) -> Result<(), AddressError> {
+    let hex = s.trim_start_matches("0x");
+    let has_lower = hex.chars().any(|c| c.is_ascii_lowercase());
+    let has_upper = hex.chars().any(|c| c.is_ascii_uppercase());
+    if has_lower && has_upper {
+        let checksummed = to_checksum(address);
+        if &checksummed[2..] != hex {
+            return Err(AddressError::Checksum(s.to_string(), checksummed));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `s` looks like an address, i.e., is `0x` followed by exactly
+/// 40 hex digits
+pub fn is_address_like(s: &str) -> bool {
+    is_hex_of_len(s, 40)
+}
+
+/// Whether `s` is an address that mixes upper- and lowercase letters
+/// with a valid EIP-55 checksum. Such strings are almost certainly meant
+/// as addresses, whereas strings in only one case might be anything
+pub fn is_checksummed(s: &str) -> bool {
+    let hex = s.trim_start_matches("0x");
+    hex.chars().any(|c| c.is_ascii_lowercase())
+        && hex.chars().any(|c| c.is_ascii_uppercase())
+        && parse_address(s).is_ok()
+}
+
+fn is_hex_of_len(s: &str, len: usize) -> bool {
+    s.len() == len + 2 && s.starts_with("0x") && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors from EIP-55
+    const CHECKSUMMED: [&str; 4] = [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn checksum() {
+        for expected in CHECKSUMMED.iter() {
+            let address = parse_address(expected).unwrap();
+            assert_eq!(*expected, to_checksum(&address));
+        }
+    }
+
+    #[test]
+    fn parse() {
+        for checksummed in CHECKSUMMED.iter() {
+            let address = parse_address(checksummed).unwrap();
+            let lower = checksummed.to_lowercase();
+            let upper = format!("0x{}", checksummed[2..].to_uppercase());
+            assert_eq!(address, parse_address(&lower).unwrap());
+            assert_eq!(address, parse_address(&upper).unwrap());
+            assert_eq!(address, parse_address(&lower[2..]).unwrap());
+        }
+
+        // Flip the case of one letter
+        let bad = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD";
+        assert_eq!(
+            Err(AddressError::Checksum(
+                bad.to_string(),
+                CHECKSUMMED[0].to_string()
+            )),
+            parse_address(bad)
+        );
+
+        assert!(matches!(
+            parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeA"),
+            Err(AddressError::Invalid(_, _))
+        ));
+        assert!(matches!(
+            parse_address("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeg"),
+            Err(AddressError::Invalid(_, _))
+        ));
+    }
+
+    #[test]
+    fn address_like() {
+        assert!(is_address_like(CHECKSUMMED[0]));
+        assert!(!is_address_like(&CHECKSUMMED[0][2..]));
+        let hash = format!("0x{}", "aB".repeat(32));
+        assert!(!is_address_like(&hash));
+    }
+
+    #[test]
+    fn checksummed() {
+        for checksummed in CHECKSUMMED.iter() {
+            assert!(is_checksummed(checksummed));
+            assert!(!is_checksummed(&checksummed.to_lowercase()));
+            assert!(!is_checksummed(&checksummed.to_uppercase()));
+        }
+        assert!(!is_checksummed(
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD"
+        ));
+        assert!(!is_checksummed("0xNotAnAddress"));
+    }
+}
@@ -1,10 +1,15 @@
+mod address;
 mod types;
 
 use web3::types::H256;
 
+pub use self::address::{
+    check_checksum, is_address_like, is_checksummed, parse_address, parse_address_unchecked,
+    to_checksum, AddressError,
+};
 pub use self::types::{
-    evaluate_transaction_status, EthereumBlock, EthereumBlockWithCalls, EthereumCall,
-    LightEthereumBlock, LightEthereumBlockExt,
+    evaluate_transaction_status, uncles_complete, EthereumBlock, EthereumBlockWithCalls,
+    EthereumCall, EthereumTransaction, EthereumUncle, LightEthereumBlock, LightEthereumBlockExt,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
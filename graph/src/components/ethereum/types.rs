@@ -1,5 +1,5 @@
-use serde::{Deserialize, Serialize};
-use std::{convert::TryFrom, sync::Arc};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use std::{convert::TryFrom, ops::Deref, sync::Arc};
 use web3::types::{
     Action, Address, Block, Bytes, Log, Res, Trace, Transaction, TransactionReceipt, H256, U256,
     U64,
@@ -10,12 +10,115 @@ use crate::{
     prelude::{BlockNumber, DeploymentHash, EntityKey, ToEntityKey},
 };
 
-pub type LightEthereumBlock = Block<Transaction>;
+pub type LightEthereumBlock = Block<EthereumTransaction>;
+
+/// A transaction together with the fields that EIP-2718 and EIP-1559 added
+/// to transactions. The version of `web3` we use does not know about them,
+/// so we keep them next to its `Transaction`
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct EthereumTransaction {
+    #[serde(flatten)]
+    pub transaction: Transaction,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<U64>,
+    #[serde(
+        rename = "maxFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_fee_per_gas: Option<U256>,
+    #[serde(
+        rename = "maxPriorityFeePerGas",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_priority_fee_per_gas: Option<U256>,
+}
+
+impl EthereumTransaction {
+    /// The price the transaction paid per unit of gas in a block with base
+    /// fee `base_fee_per_gas`. For EIP-1559 transactions, that is
+    /// `min(maxFeePerGas, baseFeePerGas + maxPriorityFeePerGas)`, and
+    /// `None` if the block has no base fee; for all other transactions, it
+    /// is their gas price
+    pub fn effective_gas_price(&self, base_fee_per_gas: Option<U256>) -> Option<U256> {
+        match (self.max_fee_per_gas, self.max_priority_fee_per_gas) {
+            (Some(max_fee), Some(max_priority_fee)) => base_fee_per_gas
+                .map(|base_fee| max_fee.min(base_fee.saturating_add(max_priority_fee))),
+            _ => Some(self.transaction.gas_price),
+        }
+    }
+}
+
+/// Some providers omit the `gasPrice` of EIP-1559 transactions, which
+/// `Transaction` insists on. We use zero for it, since mappings get the
+/// effective gas price of such transactions as their gas price anyway
+impl<'de> Deserialize<'de> for EthereumTransaction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        fn field<T: DeserializeOwned>(
+            value: &serde_json::Value,
+            name: &str,
+        ) -> Result<Option<T>, serde_json::Error> {
+            value
+                .get(name)
+                .cloned()
+                .map_or(Ok(None), serde_json::from_value)
+        }
+
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        let transaction_type = field(&value, "type").map_err(D::Error::custom)?;
+        let max_fee_per_gas = field(&value, "maxFeePerGas").map_err(D::Error::custom)?;
+        let max_priority_fee_per_gas =
+            field(&value, "maxPriorityFeePerGas").map_err(D::Error::custom)?;
+        if max_fee_per_gas.is_some() {
+            if let Some(tx) = value.as_object_mut() {
+                tx.entry("gasPrice").or_insert_with(|| "0x0".into());
+            }
+        }
+        let transaction = serde_json::from_value(value).map_err(D::Error::custom)?;
+        Ok(EthereumTransaction {
+            transaction,
+            transaction_type,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+        })
+    }
+}
+
+impl Deref for EthereumTransaction {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        &self.transaction
+    }
+}
+
+impl From<Transaction> for EthereumTransaction {
+    fn from(transaction: Transaction) -> Self {
+        EthereumTransaction {
+            transaction,
+            transaction_type: None,
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+        }
+    }
+}
+
+/// The header of an uncle, as returned by `eth_getUncleByBlockHashAndIndex`
+pub type EthereumUncle = Block<H256>;
 
 pub trait LightEthereumBlockExt {
     fn number(&self) -> BlockNumber;
-    fn transaction_for_log(&self, log: &Log) -> Option<Transaction>;
-    fn transaction_for_call(&self, call: &EthereumCall) -> Option<Transaction>;
+    fn transaction_for_log(&self, log: &Log) -> Option<EthereumTransaction>;
+    fn transaction_for_call(&self, call: &EthereumCall) -> Option<EthereumTransaction>;
+    /// Blocks that were cached before we kept the type and the fee fields
+    /// of transactions lack them, and must be fetched again
+    fn has_transaction_types(&self) -> bool;
     fn parent_ptr(&self) -> Option<BlockPtr>;
     fn format(&self) -> String;
     fn block_ptr(&self) -> BlockPtr;
@@ -23,21 +126,33 @@ pub trait LightEthereumBlockExt {
 
 impl LightEthereumBlockExt for LightEthereumBlock {
     fn number(&self) -> BlockNumber {
-        BlockNumber::try_from(self.number.unwrap().as_u64()).unwrap()
+        let number = self.number.unwrap().as_u64();
+        BlockNumber::try_from(number)
+            .unwrap_or_else(|_| panic!("block number out of range: {}", number))
     }
 
-    fn transaction_for_log(&self, log: &Log) -> Option<Transaction> {
+    fn transaction_for_log(&self, log: &Log) -> Option<EthereumTransaction> {
         log.transaction_hash
             .and_then(|hash| self.transactions.iter().find(|tx| tx.hash == hash))
             .cloned()
     }
 
-    fn transaction_for_call(&self, call: &EthereumCall) -> Option<Transaction> {
+    fn transaction_for_call(&self, call: &EthereumCall) -> Option<EthereumTransaction> {
         call.transaction_hash
             .and_then(|hash| self.transactions.iter().find(|tx| tx.hash == hash))
             .cloned()
     }
 
+    fn has_transaction_types(&self) -> bool {
+        // Providers report the type of all transactions in blocks after
+        // London, the first blocks with a base fee
+        self.base_fee_per_gas.is_none()
+            || self
+                .transactions
+                .iter()
+                .all(|tx| tx.transaction_type.is_some())
+    }
+
     fn parent_ptr(&self) -> Option<BlockPtr> {
         match self.number() {
             0 => None,
@@ -102,6 +217,29 @@ pub fn evaluate_transaction_status(receipt_status: Option<U64>) -> bool {
 pub struct EthereumBlock {
     pub block: Arc<LightEthereumBlock>,
     pub transaction_receipts: Vec<TransactionReceipt>,
+    /// The headers of the uncles that `block` includes, in the order of
+    /// `block.uncles`. Blocks that were stored before we kept uncles, and
+    /// blocks we only have the header of, have none
+    #[serde(default)]
+    pub uncles: Vec<EthereumUncle>,
+}
+
+impl EthereumBlock {
+    /// The headers of all uncles of the block, or `None` if we do not
+    /// have all of them
+    pub fn complete_uncles(&self) -> Option<&[EthereumUncle]> {
+        uncles_complete(&self.block, &self.uncles).then(|| self.uncles.as_slice())
+    }
+}
+
+/// Whether `uncles` are the headers of all uncles of `block`
+pub fn uncles_complete<T>(block: &Block<T>, uncles: &[EthereumUncle]) -> bool {
+    block.uncles.len() == uncles.len()
+        && block
+            .uncles
+            .iter()
+            .zip(uncles)
+            .all(|(hash, uncle)| uncle.hash == Some(*hash))
 }
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -116,6 +254,11 @@ pub struct EthereumCall {
     pub block_hash: H256,
     pub transaction_hash: Option<H256>,
     pub transaction_index: u64,
+    /// The position of the call in the call tree of its transaction. The
+    /// call of the transaction itself has an empty trace address, `[0]` is
+    /// the first call that it made, and `[0, 2]` is the third call that
+    /// `[0]` made
+    pub trace_address: Vec<usize>,
 }
 
 impl EthereumCall {
@@ -152,6 +295,7 @@ impl EthereumCall {
             block_hash: trace.block_hash,
             transaction_hash: trace.transaction_hash,
             transaction_index,
+            trace_address: trace.trace_address.clone(),
         })
     }
 }
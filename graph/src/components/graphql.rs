@@ -3,7 +3,7 @@ use futures::prelude::*;
 use crate::data::query::{CacheStatus, Query, QueryTarget};
 use crate::data::subscription::{Subscription, SubscriptionError, SubscriptionResult};
 use crate::data::{graphql::effort::LoadManager, query::QueryResults};
-use crate::prelude::DeploymentHash;
+use crate::prelude::{ApiSchema, DeploymentHash, QueryExecutionError};
 
 use async_trait::async_trait;
 use std::sync::Arc;
@@ -42,6 +42,21 @@ pub trait GraphQlRunner: Send + Sync + 'static {
         target: QueryTarget,
     ) -> Result<SubscriptionResult, SubscriptionError>;
 
+    /// Checks that a GraphQL subscription is valid and within the
+    /// complexity and depth limits without running it, and returns its
+    /// complexity.
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        subscription: Subscription,
+        target: QueryTarget,
+    ) -> Result<u64, SubscriptionError>;
+
+    /// Returns the API schema of the deployment that `target` resolves to.
+    async fn api_schema(
+        self: Arc<Self>,
+        target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError>;
+
     fn load_manager(&self) -> Arc<LoadManager>;
 }
 
@@ -33,6 +33,12 @@ pub trait LinkResolver: Send + Sync + 'static {
     where
         Self: Sized;
 
+    /// Makes the resolver fetch every file instead of serving files it
+    /// has cached.
+    fn without_cache(self) -> Self
+    where
+        Self: Sized;
+
     /// Fetches the link contents as bytes.
     async fn cat(&self, logger: &Logger, link: &Link) -> Result<Vec<u8>, Error>;
 
@@ -205,6 +205,21 @@ pub trait MetricsRegistry: Send + Sync + 'static {
         Ok(counters)
     }
 
+    fn new_histogram_with_labels(
+        &self,
+        name: &str,
+        help: &str,
+        const_labels: HashMap<String, String>,
+        buckets: Vec<f64>,
+    ) -> Result<Box<Histogram>, PrometheusError> {
+        let opts = HistogramOpts::new(name, help)
+            .const_labels(const_labels)
+            .buckets(buckets);
+        let histogram = Box::new(Histogram::with_opts(opts)?);
+        self.register(name, histogram.clone());
+        Ok(histogram)
+    }
+
     fn new_deployment_histogram(
         &self,
         name: &str,
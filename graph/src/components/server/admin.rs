@@ -1,8 +1,11 @@
 use std::io;
 use std::sync::Arc;
 
-use crate::prelude::Logger;
-use crate::prelude::NodeId;
+use crate::components::server::version::BuildInfo;
+use crate::components::store::AdminAuditStore;
+use crate::data::graphql::{object, IntoValue};
+use crate::data::store::scalar::Timestamp;
+use crate::prelude::{q, serde_json, Logger, MetricsRegistry, NodeId};
 
 /// Common trait for JSON-RPC admin server implementations.
 pub trait JsonRpcServer<P> {
@@ -13,7 +16,71 @@ pub trait JsonRpcServer<P> {
         http_port: u16,
         ws_port: u16,
         provider: Arc<P>,
+        audit: Arc<dyn AdminAuditStore>,
+        registry: Arc<impl MetricsRegistry>,
         node_id: NodeId,
+        build_info: BuildInfo,
         logger: Logger,
     ) -> Result<Self::Server, io::Error>;
 }
+
+/// A call to a method of the admin server that changes something
+#[derive(Clone, Debug)]
+pub struct AdminCall {
+    pub method: String,
+    /// The parameters of the call, with secrets redacted
+    pub params: serde_json::Value,
+    /// Who made the call, if the admin server authenticates callers
+    pub caller: Option<String>,
+    /// Where the call came from, as far as we can tell
+    pub source: Option<String>,
+}
+
+/// An entry in the admin audit log
+#[derive(Clone, Debug)]
+pub struct AdminAuditEntry {
+    pub id: i64,
+    pub call: AdminCall,
+    /// Whether the call succeeded; `None` if the call has not finished
+    /// yet, or never did because the node stopped while running it
+    pub succeeded: Option<bool>,
+    pub error: Option<String>,
+    pub started_at: Timestamp,
+    pub finished_at: Option<Timestamp>,
+}
+
+impl AdminAuditEntry {
+    pub fn result(&self) -> &'static str {
+        match self.succeeded {
+            Some(true) => "success",
+            Some(false) => "failure",
+            None => "pending",
+        }
+    }
+}
+
+impl IntoValue for AdminAuditEntry {
+    fn into_value(self) -> q::Value {
+        let result = self.result();
+        let AdminAuditEntry {
+            id,
+            call,
+            succeeded: _,
+            error,
+            started_at,
+            finished_at,
+        } = self;
+        object! {
+            __typename: "AdminAuditEntry",
+            id: id.to_string(),
+            method: call.method,
+            params: call.params.to_string(),
+            caller: call.caller,
+            source: call.source,
+            result: result,
+            error: error,
+            startedAt: started_at.to_string(),
+            finishedAt: finished_at.map(|ts| ts.to_string()),
+        }
+    }
+}
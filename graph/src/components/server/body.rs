@@ -0,0 +1,145 @@
+//! Limits on the request bodies that the servers accept. Bodies are read
+//! chunk by chunk and rejected as soon as they grow past the limit for
+//! their server, whatever the client claims in `Content-Length`, so that
+//! no server ever buffers more than its limit. The JSON-RPC admin server
+//! and the WebSocket server enforce their limits with the options of the
+//! libraries they are built on
+use futures03::{pin_mut, Stream, StreamExt};
+use lazy_static::lazy_static;
+use std::env;
+use std::fmt;
+
+use super::query::GraphQLServerError;
+
+fn size_from_env(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .map(|s| {
+            s.parse::<usize>()
+                .unwrap_or_else(|_| panic!("{} must be a number of bytes, but is `{}`", name, s))
+        })
+        .unwrap_or(default)
+}
+
+lazy_static! {
+    /// The largest body of a GraphQL query sent over HTTP
+    pub static ref GRAPHQL_MAX_BODY_SIZE: usize =
+        size_from_env("GRAPH_GRAPHQL_MAX_BODY_SIZE", 1024 * 1024);
+    /// The largest message that a GraphQL client can send over a WebSocket
+    pub static ref GRAPHQL_WS_MAX_MESSAGE_SIZE: usize =
+        size_from_env("GRAPH_GRAPHQL_WS_MAX_MESSAGE_SIZE", 1024 * 1024);
+    /// The largest body of a query to the index node server
+    pub static ref INDEX_NODE_MAX_BODY_SIZE: usize =
+        size_from_env("GRAPH_INDEX_NODE_MAX_BODY_SIZE", 1024 * 1024);
+    /// The largest body of a JSON-RPC request to the admin server
+    pub static ref ADMIN_MAX_BODY_SIZE: usize =
+        size_from_env("GRAPH_ADMIN_MAX_BODY_SIZE", 64 * 1024);
+}
+
+/// Check that a request whose body should be JSON has a fitting
+/// `Content-Type`. Requests without one are accepted since many GraphQL
+/// clients do not set it
+pub fn check_json_content_type(content_type: Option<&str>) -> Result<(), GraphQLServerError> {
+    let content_type = match content_type {
+        Some(content_type) => content_type,
+        None => return Ok(()),
+    };
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    if essence.eq_ignore_ascii_case("application/json") {
+        Ok(())
+    } else {
+        Err(GraphQLServerError::UnsupportedMediaType(
+            content_type.to_string(),
+        ))
+    }
+}
+
+/// Read the chunks of `body` into memory, failing as soon as the body is
+/// longer than `limit` bytes. `content_length` is the length that the
+/// client announced, which lets us reject large bodies without reading
+/// any of them
+pub async fn read_body<S, B, E>(
+    body: S,
+    content_length: Option<u64>,
+    limit: usize,
+) -> Result<Vec<u8>, GraphQLServerError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+    E: fmt::Display,
+{
+    if content_length.map_or(false, |length| length > limit as u64) {
+        return Err(GraphQLServerError::PayloadTooLarge(limit));
+    }
+
+    pin_mut!(body);
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| {
+            GraphQLServerError::InternalError(format!("Failed to read request body: {}", e))
+        })?;
+        let chunk = chunk.as_ref();
+        if bytes.len() + chunk.len() > limit {
+            return Err(GraphQLServerError::PayloadTooLarge(limit));
+        }
+        bytes.extend_from_slice(chunk);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures03::executor::block_on;
+    use futures03::stream;
+
+    fn body(chunks: &[&'static str]) -> impl Stream<Item = Result<&'static str, String>> {
+        stream::iter(chunks.to_vec().into_iter().map(Ok))
+    }
+
+    #[test]
+    fn reads_bodies_up_to_the_limit() {
+        let read = |chunks: &[&'static str], length| block_on(read_body(body(chunks), length, 8));
+
+        assert_eq!(b"{}".to_vec(), read(&["{", "}"], Some(2)).unwrap());
+        assert_eq!(b"12345678".to_vec(), read(&["1234", "5678"], None).unwrap());
+        assert_eq!(Vec::<u8>::new(), read(&[], Some(0)).unwrap());
+
+        // Rejected because of what the client announced
+        assert!(matches!(
+            read(&["{}"], Some(9)),
+            Err(GraphQLServerError::PayloadTooLarge(8))
+        ));
+        // Rejected while reading, since clients can lie or leave out the
+        // length
+        assert!(matches!(
+            read(&["1234", "56789"], None),
+            Err(GraphQLServerError::PayloadTooLarge(8))
+        ));
+        assert!(matches!(
+            read(&["1234", "56789"], Some(2)),
+            Err(GraphQLServerError::PayloadTooLarge(8))
+        ));
+
+        let failing = stream::iter(vec![Ok("{"), Err("connection reset")]);
+        assert!(matches!(
+            block_on(read_body(failing, None, 8)),
+            Err(GraphQLServerError::InternalError(_))
+        ));
+    }
+
+    #[test]
+    fn checks_content_type() {
+        assert!(check_json_content_type(None).is_ok());
+        assert!(check_json_content_type(Some("application/json")).is_ok());
+        assert!(check_json_content_type(Some("Application/JSON; charset=utf-8")).is_ok());
+
+        let err = check_json_content_type(Some("text/plain")).unwrap_err();
+        assert_eq!(415, err.status_code());
+        assert!(matches!(
+            check_json_content_type(Some("application/x-www-form-urlencoded")),
+            Err(GraphQLServerError::UnsupportedMediaType(_))
+        ));
+        assert!(check_json_content_type(Some("")).is_err());
+    }
+}
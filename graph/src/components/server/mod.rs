@@ -1,6 +1,9 @@
 /// Component for running GraphQL queries over HTTP.
 pub mod query;
 
+/// Limits on the size and content type of request bodies.
+pub mod body;
+
 /// Component for running GraphQL subscriptions over WebSockets.
 pub mod subscription;
 
@@ -12,3 +15,6 @@ pub mod index_node;
 
 /// Components for the Prometheus metrics server.
 pub mod metrics;
+
+/// Information about the build of the running binary.
+pub mod version;
@@ -11,6 +11,10 @@ pub enum GraphQLServerError {
     ClientError(String),
     QueryError(QueryError),
     InternalError(String),
+    /// The request body is larger than the limit, in bytes, of the server
+    PayloadTooLarge(usize),
+    /// The request body has a content type other than JSON
+    UnsupportedMediaType(String),
 }
 
 impl From<QueryError> for GraphQLServerError {
@@ -40,10 +44,44 @@ impl fmt::Display for GraphQLServerError {
             GraphQLServerError::InternalError(ref s) => {
                 write!(f, "GraphQL server error (internal error): {}", s)
             }
+            GraphQLServerError::PayloadTooLarge(limit) => write!(
+                f,
+                "GraphQL server error (client error): the request body is larger than {} bytes",
+                limit
+            ),
+            GraphQLServerError::UnsupportedMediaType(ref content_type) => write!(
+                f,
+                "GraphQL server error (client error): the content type must be \
+                 `application/json`, not `{}`",
+                content_type
+            ),
         }
     }
 }
 
+impl GraphQLServerError {
+    /// The HTTP status of responses for this error
+    pub fn status_code(&self) -> u16 {
+        match self {
+            GraphQLServerError::ClientError(_) | GraphQLServerError::QueryError(_) => 400,
+            GraphQLServerError::InternalError(_) => 500,
+            GraphQLServerError::PayloadTooLarge(_) => 413,
+            GraphQLServerError::UnsupportedMediaType(_) => 415,
+        }
+    }
+
+    /// Whether the error was caused by the client rather than the server
+    pub fn is_client_error(&self) -> bool {
+        self.status_code() < 500
+    }
+
+    /// The body of the response for this error, in the same shape as the
+    /// errors of GraphQL responses
+    pub fn as_graphql_body(&self) -> String {
+        serde_json::json!({ "errors": [{ "message": self.to_string() }] }).to_string()
+    }
+}
+
 impl Error for GraphQLServerError {
     fn description(&self) -> &str {
         "Failed to process the GraphQL request"
@@ -51,9 +89,11 @@ impl Error for GraphQLServerError {
 
     fn cause(&self) -> Option<&dyn Error> {
         match *self {
-            GraphQLServerError::ClientError(_) => None,
             GraphQLServerError::QueryError(ref e) => Some(e),
-            GraphQLServerError::InternalError(_) => None,
+            GraphQLServerError::ClientError(_)
+            | GraphQLServerError::InternalError(_)
+            | GraphQLServerError::PayloadTooLarge(_)
+            | GraphQLServerError::UnsupportedMediaType(_) => None,
         }
     }
 }
@@ -0,0 +1,34 @@
+use serde::Serialize;
+
+/// What we know about how the running binary was built. The values are
+/// captured when the binary is compiled so that they always describe the
+/// code that is actually running
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// The version of the `graph-node` crate
+    pub version: String,
+    /// The git testament rendered the same way as for `--version`
+    pub testament: String,
+    /// The commit the binary was built from; `None` if it was not built
+    /// from a git checkout
+    pub commit: Option<String>,
+    /// The date of that commit
+    pub commit_date: Option<String>,
+    /// The most recent tag reachable from the commit
+    pub tag: Option<String>,
+    /// The number of commits since `tag`
+    pub distance: Option<usize>,
+    /// Whether the checkout had uncommitted changes
+    pub dirty: bool,
+    /// The output of `rustc --version` for the compiler that built the binary
+    pub rustc: String,
+    /// The cargo profile, `debug` or `release`
+    pub profile: String,
+    /// The cargo features that were enabled
+    pub features: Vec<String>,
+    /// The highest `specVersion` of subgraph manifests this node accepts
+    pub max_spec_version: String,
+    /// The highest mapping `apiVersion` this node accepts
+    pub max_api_version: String,
+}
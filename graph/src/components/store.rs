@@ -14,14 +14,15 @@ use std::fmt::Display;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use web3::types::{Address, H256};
 
 use crate::blockchain::Blockchain;
+use crate::components::server::admin::{AdminAuditEntry, AdminCall};
 use crate::components::server::index_node::VersionInfo;
 use crate::components::transaction_receipt;
-use crate::data::subgraph::status;
+use crate::data::subgraph::{status, DeploymentProfile, Labels, ScanTotals};
 use crate::data::{store::*, subgraph::Source};
 use crate::prelude::*;
 use crate::util::lfu_cache::LfuCache;
@@ -400,6 +401,10 @@ pub struct EntityQuery {
 
     pub query_id: Option<String>,
 
+    /// When the query has to be done. Database queries are cancelled
+    /// when they have not finished by then
+    pub deadline: Option<Instant>,
+
     _force_use_of_new: (),
 }
 
@@ -418,6 +423,7 @@ impl EntityQuery {
             range: EntityRange::first(100),
             logger: None,
             query_id: None,
+            deadline: None,
             _force_use_of_new: (),
         }
     }
@@ -772,6 +778,18 @@ pub enum StoreError {
          there are most likely two (or more) nodes indexing this subgraph"
     )]
     DuplicateBlockProcessing(DeploymentHash, BlockNumber),
+    /// Another node holds the writer lease for the deployment and is still
+    /// actively indexing it
+    #[error("deployment `{0}` is being indexed by node `{1}`")]
+    DeploymentLeaseHeld(DeploymentHash, String),
+    /// A reorg wants to revert the deployment to a block before the
+    /// block up to which entity versions have been archived. Reverting
+    /// will never succeed; the deployment needs to be rewound manually
+    #[error(
+        "can not revert subgraph `{0}` to block {1} as entity versions \
+         up to block {2} have been archived"
+    )]
+    RevertPastArchive(DeploymentHash, BlockNumber, BlockNumber),
     /// An internal error where we expected the application logic to enforce
     /// some constraint, e.g., that subgraph names are unique, but found that
     /// constraint to not hold
@@ -843,6 +861,14 @@ pub struct StoredDynamicDataSource {
     pub creation_block: Option<BlockNumber>,
 }
 
+/// Records that the handlers of a data source that only run once ran in
+/// `block`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnceHandlerRun {
+    pub data_source: DataSourceKey,
+    pub block: BlockNumber,
+}
+
 pub trait SubscriptionManager: Send + Sync + 'static {
     /// Subscribe to changes for specific subgraphs and entities.
     ///
@@ -948,10 +974,111 @@ pub trait SubgraphStore: Send + Sync + 'static {
         node_id: &NodeId,
     ) -> Result<(), StoreError>;
 
+    /// Archive entity versions of the deployment that were closed more
+    /// than `blocks` blocks before its current head; `None` turns archiving
+    /// off. Archived versions can still be queried with a `block`
+    /// constraint, but such queries are slower
+    fn set_archive_blocks(
+        &self,
+        deployment: &DeploymentLocator,
+        blocks: Option<BlockNumber>,
+    ) -> Result<(), StoreError>;
+
+    /// Tell the deployment to skip the block with `block_hash`, given as
+    /// `0x` followed by hex digits, without running any handlers for it.
+    /// The skip takes effect when the deployment gets to the block after
+    /// it was (re)started. If the deployment failed on that block, its
+    /// failure is cleared and kept in its failure history
+    fn skip_block(
+        &self,
+        deployment: &DeploymentLocator,
+        block_hash: &str,
+        reason: String,
+    ) -> Result<(), StoreError>;
+
+    /// Withdraw the request to skip the block with `block_hash` and return
+    /// what we know about the skip. If the deployment has already skipped
+    /// the block, it needs to be rewound to before the block to process it
+    fn unskip_block(
+        &self,
+        deployment: &DeploymentLocator,
+        block_hash: &str,
+    ) -> Result<status::SkippedBlock, StoreError>;
+
+    /// Start counting what the deployment requests from its chain from
+    /// zero, for example, after it was moved to a different provider
+    fn reset_scan_totals(&self, deployment: &DeploymentLocator) -> Result<(), StoreError>;
+
+    /// The labels that operators attached to the deployment `deployment`
+    fn labels(&self, deployment: &DeploymentHash) -> Result<Labels, StoreError>;
+
+    /// Replace the labels of the deployment `deployment` with `labels`,
+    /// which must be valid. Labels can be set before the deployment is
+    /// created so that they are taken into account when it is placed
+    fn set_labels(&self, deployment: &DeploymentHash, labels: Labels) -> Result<(), StoreError>;
+
+    /// Record that the deployment runs the mappings from the manifest
+    /// `manifest` from now on, after rewinding it to `block_ptr_to` if
+    /// that is given. The deployment must not be running
+    fn reload_mappings(
+        &self,
+        deployment: &DeploymentLocator,
+        manifest: &DeploymentHash,
+        block_ptr_to: Option<BlockPtr>,
+    ) -> Result<status::MappingsReload, StoreError>;
+
+    /// Return the manifest whose mappings the deployment runs instead of
+    /// the ones from its own manifest, if its mappings were reloaded
+    fn mappings_manifest(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<DeploymentHash>, StoreError>;
+
+    /// Return the most bytes that passing one trigger to a handler may
+    /// allocate on the WASM heap for the deployment, as recorded when it
+    /// was created. Deployments created before that was recorded return
+    /// `None`
+    fn max_trigger_bytes(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<usize>, StoreError>;
+
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError>;
 
+    /// Return the error that made the deployment fail, or `None` if it has
+    /// not failed
+    fn fatal_error(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<SubgraphError>, StoreError>;
+
+    /// Clear the fatal error of a failed deployment so that it tries to
+    /// index the failed block again when it is started. The error is kept
+    /// in the failure history of the deployment
+    fn retry_failed(&self, deployment: &DeploymentLocator) -> Result<(), StoreError>;
+
+    /// Remove superseded subgraph versions, old admin audit log entries and
+    /// old version switches, and vacuum the metadata tables. Versions that are the current or
+    /// pending version of a subgraph are never removed
+    fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, StoreError>;
+
+    /// Compare the entities of the pending version of the subgraph `name`
+    /// with those of its current version as of `block`. Both versions must
+    /// have processed `block` and be stored in the same shard. The sample
+    /// for each entity type contains at most `sample_size` entities of
+    /// each kind of difference
+    fn compare_versions(
+        &self,
+        name: &SubgraphName,
+        block: BlockNumber,
+        sample_size: usize,
+    ) -> Result<VersionComparison, StoreError>;
+
     fn assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError>;
 
+    /// The deployments for which `node` is the standby
+    fn standbys(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError>;
+
     /// Return `true` if a subgraph `name` exists, regardless of whether the
     /// subgraph has any deployments attached to it
     fn subgraph_exists(&self, name: &SubgraphName) -> Result<bool, StoreError>;
@@ -1005,7 +1132,11 @@ pub trait WritableStore: Send + Sync + 'static {
     /// subgraph block pointer to `block_ptr_to`.
     ///
     /// `block_ptr_to` must point to the parent block of the subgraph block pointer.
-    fn revert_block_operations(&self, block_ptr_to: BlockPtr) -> Result<(), StoreError>;
+    /// Returns a summary of what the revert did
+    fn revert_block_operations(
+        &self,
+        block_ptr_to: BlockPtr,
+    ) -> Result<status::RevertReport, StoreError>;
 
     /// Remove the fatal error from a subgraph and check if it is healthy or unhealthy.
     fn unfail(&self) -> Result<(), StoreError>;
@@ -1056,6 +1187,51 @@ pub trait WritableStore: Send + Sync + 'static {
     /// Report the name of the shard in which the subgraph is stored. This
     /// should only be used for reporting and monitoring
     fn shard(&self) -> &str;
+
+    /// The name of the network the deployment indexes. This is the name the
+    /// store uses for the chain, which differs from the network in the
+    /// manifest if the chain was renamed after the deployment was created
+    fn network_name(&self) -> &str;
+
+    /// Record the median and 95th percentile, in seconds, of the time it
+    /// took recent blocks to become queryable so that they can be reported
+    /// in the indexing status
+    fn set_block_latency(&self, p50: f64, p95: f64) -> Result<(), StoreError>;
+
+    /// Record how many consecutive checks found the deployment close
+    /// enough to the chain head to count as synced so that the progress
+    /// towards being synced can be reported in the indexing status
+    fn set_synced_checks(&self, checks: u32) -> Result<(), StoreError>;
+
+    /// Add `totals` to what the deployment has requested from its chain
+    fn add_scan_totals(&self, totals: &ScanTotals) -> Result<(), StoreError>;
+
+    /// Return the blocks that an operator told the deployment to skip, in
+    /// the order in which they were requested
+    fn skipped_blocks(&self) -> Result<Vec<status::SkippedBlock>, StoreError>;
+
+    /// Record that the deployment skipped `block_ptr` on operator request
+    /// instead of processing it
+    fn record_skipped_block(&self, block_ptr: &BlockPtr) -> Result<(), StoreError>;
+
+    /// Return the data sources whose handlers that only run once have run,
+    /// and the blocks in which they ran
+    fn once_handler_runs(&self) -> Result<Vec<OnceHandlerRun>, StoreError>;
+
+    /// Record that the handlers of `data_sources` that only run once ran
+    /// in `block`. Reverting `block` forgets that again
+    fn record_once_handler_runs(
+        &self,
+        block: BlockNumber,
+        data_sources: Vec<DataSourceKey>,
+    ) -> Result<(), StoreError>;
+
+    /// Mark the deployment as completed because it has processed all blocks
+    /// up to its end block
+    fn complete_subgraph(&self) -> Result<(), StoreError>;
+
+    /// Return true if the deployment has been marked as completed
+    fn is_completed(&self) -> Result<bool, StoreError>;
 }
 
 #[async_trait]
@@ -1120,14 +1296,91 @@ impl SubgraphStore for MockStore {
         unimplemented!()
     }
 
+    fn set_archive_blocks(
+        &self,
+        _: &DeploymentLocator,
+        _: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn skip_block(&self, _: &DeploymentLocator, _: &str, _: String) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn unskip_block(
+        &self,
+        _: &DeploymentLocator,
+        _: &str,
+    ) -> Result<status::SkippedBlock, StoreError> {
+        unimplemented!()
+    }
+
+    fn reset_scan_totals(&self, _: &DeploymentLocator) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn labels(&self, _: &DeploymentHash) -> Result<Labels, StoreError> {
+        unimplemented!()
+    }
+
+    fn set_labels(&self, _: &DeploymentHash, _: Labels) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn reload_mappings(
+        &self,
+        _: &DeploymentLocator,
+        _: &DeploymentHash,
+        _: Option<BlockPtr>,
+    ) -> Result<status::MappingsReload, StoreError> {
+        unimplemented!()
+    }
+
+    fn mappings_manifest(
+        &self,
+        _: &DeploymentLocator,
+    ) -> Result<Option<DeploymentHash>, StoreError> {
+        unimplemented!()
+    }
+
+    fn max_trigger_bytes(&self, _: &DeploymentLocator) -> Result<Option<usize>, StoreError> {
+        unimplemented!()
+    }
+
     fn assigned_node(&self, _: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
         unimplemented!()
     }
 
+    fn fatal_error(&self, _: &DeploymentLocator) -> Result<Option<SubgraphError>, StoreError> {
+        unimplemented!()
+    }
+
+    fn retry_failed(&self, _: &DeploymentLocator) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, StoreError> {
+        unimplemented!()
+    }
+
+    fn compare_versions(
+        &self,
+        _: &SubgraphName,
+        _: BlockNumber,
+        _: usize,
+    ) -> Result<VersionComparison, StoreError> {
+        unimplemented!()
+    }
+
     fn assignments(&self, _: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
         unimplemented!()
     }
 
+    fn standbys(&self, _: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
+        unimplemented!()
+    }
+
     fn subgraph_exists(&self, _: &SubgraphName) -> Result<bool, StoreError> {
         unimplemented!()
     }
@@ -1179,7 +1432,7 @@ impl WritableStore for MockStore {
         unimplemented!()
     }
 
-    fn revert_block_operations(&self, _: BlockPtr) -> Result<(), StoreError> {
+    fn revert_block_operations(&self, _: BlockPtr) -> Result<status::RevertReport, StoreError> {
         unimplemented!()
     }
 
@@ -1237,6 +1490,50 @@ impl WritableStore for MockStore {
     fn shard(&self) -> &str {
         unimplemented!()
     }
+
+    fn network_name(&self) -> &str {
+        unimplemented!()
+    }
+
+    fn set_block_latency(&self, _: f64, _: f64) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn set_synced_checks(&self, _: u32) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn add_scan_totals(&self, _: &ScanTotals) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn skipped_blocks(&self) -> Result<Vec<status::SkippedBlock>, StoreError> {
+        unimplemented!()
+    }
+
+    fn record_skipped_block(&self, _: &BlockPtr) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn once_handler_runs(&self) -> Result<Vec<OnceHandlerRun>, StoreError> {
+        unimplemented!()
+    }
+
+    fn record_once_handler_runs(
+        &self,
+        _: BlockNumber,
+        _: Vec<DataSourceKey>,
+    ) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn complete_subgraph(&self) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn is_completed(&self) -> Result<bool, StoreError> {
+        unimplemented!()
+    }
 }
 
 pub trait BlockStore: Send + Sync + 'static {
@@ -1288,6 +1585,21 @@ pub trait ChainStore: Send + Sync + 'static {
     /// Returns the blocks present in the store.
     fn blocks(&self, hashes: Vec<H256>) -> Result<Vec<LightEthereumBlock>, Error>;
 
+    /// Returns the headers of the uncles stored with the block with
+    /// `block_hash`, or `None` if the block is not in the store. Blocks
+    /// that were stored without their uncles have none; use
+    /// `uncles_complete` to check that all of them are there
+    fn uncles(&self, block_hash: H256) -> Result<Option<Vec<EthereumUncle>>, Error>;
+
+    /// Store `uncles` with the block with `block_hash`, replacing any
+    /// uncles stored with it before. Does nothing if the block is not in
+    /// the store
+    fn set_uncles(&self, block_hash: H256, uncles: &[EthereumUncle]) -> Result<(), Error>;
+
+    /// Remove the uncles stored with the blocks with `block_hashes`, for
+    /// example, because a reorg reverted these blocks
+    fn remove_uncles(&self, block_hashes: &[H256]) -> Result<(), Error>;
+
     /// Get the `offset`th ancestor of `block_hash`, where offset=0 means the block matching
     /// `block_hash` and offset=1 means its parent. Returns None if unable to complete due to
     /// missing blocks in the chain store.
@@ -1355,8 +1667,13 @@ pub trait QueryStore: Send + Sync {
 
     async fn is_deployment_synced(&self) -> Result<bool, Error>;
 
+    /// The block up to which the data that queries see has been written.
+    /// When queries go to a replica, that can be behind `latest_block_ptr`
     fn block_ptr(&self) -> Result<Option<BlockPtr>, Error>;
 
+    /// The block that the deployment has been indexed up to
+    fn latest_block_ptr(&self) -> Result<Option<BlockPtr>, Error>;
+
     fn block_number(&self, block_hash: H256) -> Result<Option<BlockNumber>, StoreError>;
 
     fn wait_stats(&self) -> PoolWaitStats;
@@ -1385,6 +1702,21 @@ pub trait StatusStore: Send + Sync + 'static {
 
     fn status(&self, filter: status::Filter) -> Result<Vec<status::Info>, StoreError>;
 
+    /// The status of all deployments in the store, no matter which node
+    /// they are assigned to, together with the health of those nodes
+    fn cluster_status(
+        &self,
+        filter: status::ClusterFilter,
+    ) -> Result<Vec<status::ClusterInfo>, StoreError>;
+
+    /// The status of the deployments that match `query`, in the order and
+    /// for the page that it asks for. Each status has a cursor that can be
+    /// used to get the page after it
+    fn indexing_statuses(
+        &self,
+        query: status::StatusQuery,
+    ) -> Result<Vec<status::Info>, StoreError>;
+
     /// Support for the explorer-specific API
     fn version_info(&self, version_id: &str) -> Result<VersionInfo, StoreError>;
 
@@ -1408,6 +1740,123 @@ pub trait StatusStore: Send + Sync + 'static {
         indexer: &'a Option<Address>,
         block: BlockPtr,
     ) -> DynTryFuture<'a, Option<[u8; 32]>>;
+
+    /// Up to `first` entries of the admin audit log, in the order in which
+    /// the calls were made, starting after the entry with id `after`. If
+    /// `method` is given, only include calls of that method
+    fn admin_audit_log(
+        &self,
+        first: usize,
+        after: Option<i64>,
+        method: Option<String>,
+    ) -> Result<Vec<AdminAuditEntry>, StoreError>;
+
+    /// Up to `first` of the most recent times that the pending version of
+    /// the subgraph `subgraph_name` became its current version, most
+    /// recent first
+    fn version_switches(
+        &self,
+        subgraph_name: &str,
+        first: usize,
+    ) -> Result<Vec<status::VersionSwitch>, StoreError>;
+
+    /// The profile that was derived from the manifest of `deployment` when
+    /// it was deployed, or `None` for deployments that predate profiles
+    fn deployment_profile(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<Option<DeploymentProfile>, StoreError>;
+
+    /// The indexes that the schema of `deployment` declares with `@index`,
+    /// followed by the ones that are left over from earlier versions of
+    /// the schema
+    fn composite_indexes(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<Vec<status::CompositeIndex>, StoreError>;
+}
+
+/// Record the calls to the admin server that change something
+pub trait AdminAuditStore: Send + Sync + 'static {
+    /// Record that `call` is about to be made and return the id of its
+    /// entry in the audit log
+    fn start_admin_call(&self, call: AdminCall) -> Result<i64, StoreError>;
+
+    /// Record the outcome of the call with audit log entry `id`; `error` is
+    /// `None` if the call succeeded
+    fn finish_admin_call(&self, id: i64, error: Option<String>) -> Result<(), StoreError>;
+}
+
+/// What a run of metadata maintenance removed
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MetadataMaintenanceReport {
+    /// Subgraph versions that had been superseded by newer versions
+    pub versions_removed: usize,
+    /// Old entries in the admin audit log
+    pub audit_entries_removed: usize,
+    /// Old entries in the history of version switches
+    pub version_switches_removed: usize,
+}
+
+/// How the entities of the pending version of a subgraph differ from those
+/// of its current version at a block
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VersionComparison {
+    pub current: DeploymentHash,
+    pub pending: DeploymentHash,
+    pub block: BlockNumber,
+    /// One entry for each entity type that either version has, ordered by
+    /// entity type
+    pub entity_types: Vec<EntityTypeComparison>,
+}
+
+impl VersionComparison {
+    /// Whether both versions have exactly the same entities
+    pub fn is_same(&self) -> bool {
+        self.entity_types.iter().all(|ty| ty.is_same())
+    }
+}
+
+/// The differences between the entities of one type. Entities are
+/// `added` if only the pending version has them, `removed` if only the
+/// current version has them, and `changed` if the versions disagree on
+/// the value of some attribute
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityTypeComparison {
+    pub entity_type: String,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    /// Some of the entities that differ: the first few that were added,
+    /// removed and changed, each ordered by id
+    pub sample: Vec<EntityDifference>,
+    /// Attributes that are not compared since only one of the versions
+    /// has them, or since their type changed
+    pub not_compared: Vec<String>,
+}
+
+impl EntityTypeComparison {
+    pub fn is_same(&self) -> bool {
+        self.added == 0 && self.removed == 0 && self.changed == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DifferenceKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct EntityDifference {
+    pub id: String,
+    pub kind: DifferenceKind,
+    /// The attributes whose values differ; only set for changed entities
+    pub fields: Vec<String>,
 }
 
 /// An entity operation that can be transacted into the store; as opposed to
@@ -1582,6 +2031,20 @@ impl EntityCache {
         Ok(entity)
     }
 
+    /// The ids of the entities of type `entity_type` that have been set or
+    /// removed so far, including by the currently executing handler, in
+    /// sorted order
+    pub fn changed_ids(&self, entity_type: &EntityType) -> Vec<String> {
+        let ids: BTreeSet<_> = self
+            .updates
+            .keys()
+            .chain(self.handler_updates.keys())
+            .filter(|key| &key.entity_type == entity_type)
+            .map(|key| key.entity_id.clone())
+            .collect();
+        ids.into_iter().collect()
+    }
+
     pub fn remove(&mut self, key: EntityKey) {
         self.entity_op(key, EntityOp::Remove);
     }
@@ -1759,13 +2222,14 @@ pub enum AttributeNames {
 }
 
 impl AttributeNames {
+    /// No columns besides the ones that are always selected
+    pub fn empty() -> Self {
+        AttributeNames::Select(BTreeSet::new())
+    }
+
     pub fn insert(&mut self, column_name: &str) {
         match self {
-            AttributeNames::All => {
-                let mut set = BTreeSet::new();
-                set.insert(column_name.to_string());
-                *self = AttributeNames::Select(set)
-            }
+            AttributeNames::All => {}
             AttributeNames::Select(set) => {
                 set.insert(column_name.to_string());
             }
@@ -1783,11 +2247,8 @@ impl AttributeNames {
     pub fn extend(&mut self, other: Self) {
         use AttributeNames::*;
         match (self, other) {
-            (All, All) => {}
-            (self_ @ All, other @ Select(_)) => *self_ = other,
-            (Select(_), All) => {
-                unreachable!()
-            }
+            (All, _) => {}
+            (self_ @ Select(_), All) => *self_ = All,
             (Select(a), Select(b)) => a.extend(b),
         }
     }
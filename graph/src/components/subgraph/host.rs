@@ -8,7 +8,10 @@ use futures::sync::mpsc;
 
 use crate::prelude::*;
 use crate::{blockchain::Blockchain, components::subgraph::SharedProofOfIndexing};
-use crate::{components::metrics::HistogramVec, runtime::DeterministicHostError};
+use crate::{
+    components::metrics::{Counter, HistogramVec},
+    runtime::DeterministicHostError,
+};
 
 #[derive(Debug)]
 pub enum MappingError {
@@ -61,11 +64,19 @@ pub trait RuntimeHost<C: Blockchain>: Send + Sync + 'static {
     /// Block number in which this host was created.
     /// Returns `None` for static data sources.
     fn creation_block_number(&self) -> Option<BlockNumber>;
+
+    /// The block in which the handlers of the data source of this host
+    /// that only run once run, if they have not run yet
+    fn once_handler_block(&self) -> Option<BlockNumber>;
+
+    fn data_source_key(&self) -> DataSourceKey;
 }
 
 pub struct HostMetrics {
     handler_execution_time: Box<HistogramVec>,
     host_fn_execution_time: Box<HistogramVec>,
+    duplicate_data_sources: Box<Counter>,
+    module_cache_hits: Box<Counter>,
     pub stopwatch: StopwatchMetrics,
 }
 
@@ -93,13 +104,37 @@ impl HostMetrics {
                 vec![0.025, 0.05, 0.2, 2.0, 8.0, 20.0],
             )
             .expect("failed to create `deployment_host_fn_execution_time` histogram");
+        let duplicate_data_sources = registry
+            .new_deployment_counter(
+                "deployment_duplicate_data_sources",
+                "Counts attempts to create a data source from a template that already exists",
+                subgraph,
+            )
+            .expect("failed to create `deployment_duplicate_data_sources` counter");
+        let module_cache_hits = registry
+            .new_deployment_counter(
+                "deployment_module_cache_hits",
+                "Counts data sources whose mapping module was already compiled",
+                subgraph,
+            )
+            .expect("failed to create `deployment_module_cache_hits` counter");
         Self {
             handler_execution_time,
             host_fn_execution_time,
+            duplicate_data_sources,
+            module_cache_hits,
             stopwatch,
         }
     }
 
+    pub fn add_duplicate_data_source(&self) {
+        self.duplicate_data_sources.inc();
+    }
+
+    pub fn module_cache_hit(&self) {
+        self.module_cache_hits.inc();
+    }
+
     pub fn observe_handler_execution_time(&self, duration: f64, handler: &str) {
         self.handler_execution_time
             .with_label_values(&[handler][..])
@@ -154,10 +189,12 @@ pub trait RuntimeHostBuilder<C: Blockchain>: Clone + Send + Sync + 'static {
         metrics: Arc<HostMetrics>,
     ) -> Result<Self::Host, Error>;
 
-    /// Spawn a mapping and return a channel for mapping requests. The sender should be able to be
-    /// cached and shared among mappings that use the same wasm file.
+    /// Return a channel for mapping requests to the mapping `raw_module`. Each distinct module is
+    /// only compiled and spawned the first time it is asked for; all data sources that use the
+    /// same wasm file share its sender.
     fn spawn_mapping(
-        raw_module: Vec<u8>,
+        &self,
+        raw_module: &[u8],
         logger: Logger,
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
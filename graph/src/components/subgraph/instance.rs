@@ -1,4 +1,6 @@
-use crate::blockchain::Blockchain;
+use std::collections::{HashMap, HashSet};
+
+use crate::blockchain::{Blockchain, DataSource as _};
 use crate::prelude::*;
 use crate::util::lfu_cache::LfuCache;
 use crate::{components::store::WritableStore, data::subgraph::schema::SubgraphError};
@@ -11,6 +13,35 @@ pub struct DataSourceTemplateInfo<C: Blockchain> {
     pub creation_block: BlockNumber,
 }
 
+/// Identifies a data source created from a template by the name of the
+/// template, the address the data source was created for and its context.
+/// A deployment has at most one data source for each key; creating a data
+/// source for a key that already exists does nothing
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DataSourceKey {
+    pub name: String,
+    pub address: Option<Vec<u8>>,
+    /// The context as JSON with its attributes sorted by name
+    pub context: Option<String>,
+}
+
+impl DataSourceKey {
+    pub fn of<C: Blockchain>(data_source: &C::DataSource) -> Self {
+        DataSourceKey {
+            name: data_source.name().to_string(),
+            address: data_source.address().map(|address| address.to_vec()),
+            context: data_source.context().as_ref().as_ref().map(|context| {
+                serde_json::to_string(&context.clone().sorted())
+                    .expect("data source contexts can be serialized")
+            }),
+        }
+    }
+}
+
+/// The data sources that were created from templates before the current
+/// block, with the block in which each of them was created
+pub type CreatedDataSources = Arc<HashMap<DataSourceKey, BlockNumber>>;
+
 #[derive(Debug)]
 pub struct BlockState<C: Blockchain> {
     pub entity_cache: EntityCache,
@@ -20,6 +51,14 @@ pub struct BlockState<C: Blockchain> {
     // Data sources created in the current handler.
     handler_created_data_sources: Vec<DataSourceTemplateInfo<C>>,
 
+    // Data sources that existed before this block, and the keys of the
+    // data sources created in this block and in the current handler. The
+    // keys are kept after the data sources have been drained so that
+    // creating the same data source again later in the block is detected
+    existing_data_sources: CreatedDataSources,
+    created_data_source_keys: HashSet<DataSourceKey>,
+    handler_created_data_source_keys: HashSet<DataSourceKey>,
+
     // Marks whether a handler is currently executing.
     in_handler: bool,
 }
@@ -34,10 +73,50 @@ impl<C: Blockchain> BlockState<C> {
             deterministic_errors: Vec::new(),
             created_data_sources: Vec::new(),
             handler_created_data_sources: Vec::new(),
+            existing_data_sources: Arc::new(HashMap::new()),
+            created_data_source_keys: HashSet::new(),
+            handler_created_data_source_keys: HashSet::new(),
             in_handler: false,
         }
     }
 
+    /// Treat `existing` as the data sources that have already been created
+    /// when checking for duplicate data sources
+    pub fn with_existing_data_sources(mut self, existing: CreatedDataSources) -> Self {
+        self.existing_data_sources = existing;
+        self
+    }
+
+    /// All data sources known to this block state, including the ones
+    /// created in this block so far. A block state derived from this one
+    /// for a nested mapping call uses this as its existing data sources
+    pub fn known_data_sources(&self) -> CreatedDataSources {
+        if self.created_data_source_keys.is_empty()
+            && self.handler_created_data_source_keys.is_empty()
+        {
+            return self.existing_data_sources.cheap_clone();
+        }
+        let mut known = self.existing_data_sources.as_ref().clone();
+        // The block number does not matter here since derived block
+        // states only check whether a data source exists
+        for key in self
+            .created_data_source_keys
+            .iter()
+            .chain(self.handler_created_data_source_keys.iter())
+        {
+            known.entry(key.clone()).or_insert(BlockNumber::MAX);
+        }
+        Arc::new(known)
+    }
+
+    /// Whether a data source with `key` exists, either from before this
+    /// block or because it was created earlier in this block
+    pub fn has_data_source(&self, key: &DataSourceKey) -> bool {
+        self.existing_data_sources.contains_key(key)
+            || self.created_data_source_keys.contains(key)
+            || self.handler_created_data_source_keys.contains(key)
+    }
+
     pub fn extend(&mut self, other: BlockState<C>) {
         assert!(!other.in_handler);
 
@@ -46,12 +125,21 @@ impl<C: Blockchain> BlockState<C> {
             deterministic_errors,
             created_data_sources,
             handler_created_data_sources,
+            existing_data_sources: _,
+            created_data_source_keys,
+            handler_created_data_source_keys,
             in_handler,
         } = self;
 
         match in_handler {
-            true => handler_created_data_sources.extend(other.created_data_sources),
-            false => created_data_sources.extend(other.created_data_sources),
+            true => {
+                handler_created_data_sources.extend(other.created_data_sources);
+                handler_created_data_source_keys.extend(other.created_data_source_keys);
+            }
+            false => {
+                created_data_sources.extend(other.created_data_sources);
+                created_data_source_keys.extend(other.created_data_source_keys);
+            }
         }
         deterministic_errors.extend(other.deterministic_errors);
         entity_cache.extend(other.entity_cache);
@@ -82,6 +170,8 @@ impl<C: Blockchain> BlockState<C> {
         self.in_handler = false;
         self.created_data_sources
             .extend(self.handler_created_data_sources.drain(..));
+        self.created_data_source_keys
+            .extend(self.handler_created_data_source_keys.drain());
         self.entity_cache.exit_handler()
     }
 
@@ -89,12 +179,14 @@ impl<C: Blockchain> BlockState<C> {
         assert!(self.in_handler);
         self.in_handler = false;
         self.handler_created_data_sources.clear();
+        self.handler_created_data_source_keys.clear();
         self.entity_cache.exit_handler_and_discard_changes();
         self.deterministic_errors.push(e);
     }
 
-    pub fn push_created_data_source(&mut self, ds: DataSourceTemplateInfo<C>) {
+    pub fn push_created_data_source(&mut self, key: DataSourceKey, ds: DataSourceTemplateInfo<C>) {
         assert!(self.in_handler);
+        self.handler_created_data_source_keys.insert(key);
         self.handler_created_data_sources.push(ds);
     }
 }
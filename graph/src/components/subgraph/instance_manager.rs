@@ -15,4 +15,12 @@ pub trait SubgraphInstanceManager: Send + Sync + 'static {
         manifest: serde_yaml::Mapping,
     );
     fn stop_subgraph(&self, deployment: DeploymentLocator);
+
+    /// Prepare `deployment`, for which this node is the standby, so that
+    /// it starts quickly once it is assigned to this node. Nothing is
+    /// indexed until then
+    async fn warm_up(self: Arc<Self>, deployment: DeploymentLocator, manifest: serde_yaml::Mapping);
+
+    /// Forget what `warm_up` prepared for `deployment`
+    fn cool_down(&self, deployment: &DeploymentLocator);
 }
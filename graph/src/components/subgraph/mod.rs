@@ -8,11 +8,14 @@ mod registrar;
 pub use crate::prelude::Entity;
 
 pub use self::host::{HostMetrics, MappingError, RuntimeHost, RuntimeHostBuilder};
-pub use self::instance::{BlockState, DataSourceTemplateInfo};
+pub use self::instance::{BlockState, CreatedDataSources, DataSourceKey, DataSourceTemplateInfo};
 pub use self::instance_manager::SubgraphInstanceManager;
 pub use self::proof_of_indexing::{
     BlockEventStream, ProofOfIndexing, ProofOfIndexingEvent, ProofOfIndexingFinisher,
-    SharedProofOfIndexing,
+    SharedProofOfIndexing, OPERATOR_INTERVENTION_REGION,
 };
 pub use self::provider::SubgraphAssignmentProvider;
-pub use self::registrar::{SubgraphRegistrar, SubgraphVersionSwitchingMode};
+pub use self::registrar::{
+    DeploymentFilesReport, RetryFailedPolicy, SubgraphRegistrar, SubgraphVersionSwitchingMode,
+    SyncedCriterion, UnresolvableFile, SYNCED_CRITERION,
+};
@@ -16,6 +16,17 @@ pub enum ProofOfIndexingEvent<'a> {
         id: &'a str,
         data: &'a HashMap<String, Value>,
     },
+    /// The block was skipped on operator request without running any
+    /// handlers for it
+    SkipBlock {
+        block_hash: &'a str,
+    },
+    /// The block was skipped without running any handlers because it had
+    /// more triggers than the subgraph allows
+    SkipHeavyBlock {
+        block_hash: &'a str,
+        trigger_count: u64,
+    },
 }
 
 impl StableHash for ProofOfIndexingEvent<'_> {
@@ -37,6 +48,16 @@ impl StableHash for ProofOfIndexingEvent<'_> {
                 id.stable_hash(sequence_number.next_child(), state);
                 data.stable_hash(sequence_number.next_child(), state);
             }
+            SkipBlock { block_hash } => {
+                block_hash.stable_hash(sequence_number.next_child(), state);
+            }
+            SkipHeavyBlock {
+                block_hash,
+                trigger_count,
+            } => {
+                block_hash.stable_hash(sequence_number.next_child(), state);
+                trigger_count.stable_hash(sequence_number.next_child(), state);
+            }
         }
     }
 }
@@ -62,6 +83,16 @@ impl fmt::Debug for ProofOfIndexingEvent<'_> {
                 builder.field("id", id);
                 builder.field("data", &data.iter().collect::<BTreeMap<_, _>>());
             }
+            Self::SkipBlock { block_hash } => {
+                builder.field("block_hash", block_hash);
+            }
+            Self::SkipHeavyBlock {
+                block_hash,
+                trigger_count,
+            } => {
+                builder.field("block_hash", block_hash);
+                builder.field("trigger_count", trigger_count);
+            }
         }
         builder.finish()
     }
@@ -8,6 +8,13 @@ pub use online::{BlockEventStream, ProofOfIndexing, ProofOfIndexingFinisher};
 use atomic_refcell::AtomicRefCell;
 use std::sync::Arc;
 
+/// The causality region for events that record operator interventions
+/// like skipping a block, be it on operator request or because it has too
+/// many triggers. Keeping them separate from the regions that the
+/// data sources write to makes it easy to tell whether a proof of
+/// indexing differs because of an intervention
+pub const OPERATOR_INTERVENTION_REGION: &str = "operatorIntervention";
+
 /// This concoction of types is to allow MappingContext to be static, yet still
 /// have shared mutable data for derive_with_empty_block_state. The static
 /// requirement is so that host exports can be static for wasmtime.
@@ -13,4 +13,8 @@ pub trait SubgraphAssignmentProvider: Send + Sync + 'static {
         &self,
         deployment: DeploymentLocator,
     ) -> Result<(), SubgraphAssignmentProviderError>;
+
+    /// Keep `deployments`, for which this node is the standby, prepared to
+    /// be started, and stop keeping any other deployments prepared
+    async fn keep_warm(&self, deployments: Vec<DeploymentLocator>);
 }
@@ -1,23 +1,157 @@
+use std::str::FromStr;
+
 use async_trait::async_trait;
 
+use crate::components::store::VersionComparison;
+use crate::data::subgraph::schema::SubgraphError;
+use crate::data::subgraph::status::{MappingsReload, SkippedBlock};
+use crate::data::subgraph::{DeploymentProfile, Labels};
 use crate::prelude::*;
 
-#[derive(Clone, Copy, Debug)]
+/// When a newly deployed version of a subgraph becomes its current version
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SubgraphVersionSwitchingMode {
+    /// Make the new version the current version right away
     Instant,
+    /// Keep the new version as the pending version until it is synced
+    /// according to `SYNCED_CRITERION`, as long as the current version is
+    /// synced
     Synced,
 }
 
-impl SubgraphVersionSwitchingMode {
-    pub fn parse(mode: &str) -> Self {
-        match mode.to_ascii_lowercase().as_str() {
-            "instant" => SubgraphVersionSwitchingMode::Instant,
-            "synced" => SubgraphVersionSwitchingMode::Synced,
-            _ => panic!("invalid version switching mode: {:?}", mode),
+impl FromStr for SubgraphVersionSwitchingMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "instant" => Ok(SubgraphVersionSwitchingMode::Instant),
+            "synced" => Ok(SubgraphVersionSwitchingMode::Synced),
+            _ => Err(anyhow::anyhow!("invalid version switching mode: {:?}", s)),
+        }
+    }
+}
+
+/// When a deployment counts as synced: it must be at most `head_distance`
+/// blocks behind the chain head for `checks` consecutive checks. The block
+/// stream checks every time it reconciles the deployment with the chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncedCriterion {
+    pub head_distance: BlockNumber,
+    pub checks: u32,
+}
+
+impl SyncedCriterion {
+    /// The number of consecutive successful checks after a check that
+    /// found the deployment `distance` blocks behind the chain head, when
+    /// the checks before it had `checks` successes in a row. The count
+    /// stops growing once the criterion is met
+    pub fn check(&self, checks: u32, distance: BlockNumber) -> u32 {
+        if distance <= self.head_distance {
+            checks.saturating_add(1).min(self.checks)
+        } else {
+            0
+        }
+    }
+
+    pub fn is_met(&self, checks: u32) -> bool {
+        checks >= self.checks
+    }
+}
+
+lazy_static! {
+    /// The criterion for deployments to count as synced, set with
+    /// `GRAPH_SYNCED_HEAD_DISTANCE` and `GRAPH_SYNCED_CHECKS`. By default,
+    /// a deployment is synced as soon as it reaches the chain head once
+    pub static ref SYNCED_CRITERION: SyncedCriterion = {
+        fn env<T: FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .map(|s| {
+                    T::from_str(&s).unwrap_or_else(|_| {
+                        panic!("{} must be a non-negative number, but is `{}`", name, s)
+                    })
+                })
+                .unwrap_or(default)
+        }
+
+        let head_distance = env::<u32>("GRAPH_SYNCED_HEAD_DISTANCE", 0);
+        let checks = env::<u32>("GRAPH_SYNCED_CHECKS", 1);
+        if checks == 0 {
+            panic!("GRAPH_SYNCED_CHECKS must be at least 1");
+        }
+        SyncedCriterion {
+            head_distance: head_distance as BlockNumber,
+            checks,
+        }
+    };
+}
+
+/// Which failed deployments a node retries when it starts the deployments
+/// assigned to it. Retrying clears the failure; all assigned deployments
+/// are started either way, and those that are not retried keep their
+/// failure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryFailedPolicy {
+    /// Do not clear the failure of any deployment
+    None,
+    /// Retry deployments that failed with a non-deterministic error;
+    /// deployments that failed deterministically would only fail again
+    NonDeterministic,
+    /// Retry all failed deployments
+    All,
+}
+
+impl RetryFailedPolicy {
+    /// Whether a deployment that failed with `error` should be retried
+    pub fn retries(&self, error: &SubgraphError) -> bool {
+        match self {
+            RetryFailedPolicy::None => false,
+            RetryFailedPolicy::NonDeterministic => !error.deterministic,
+            RetryFailedPolicy::All => true,
+        }
+    }
+}
+
+impl FromStr for RetryFailedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(RetryFailedPolicy::None),
+            "non-deterministic" => Ok(RetryFailedPolicy::NonDeterministic),
+            "all" => Ok(RetryFailedPolicy::All),
+            _ => Err(anyhow::anyhow!(
+                "invalid policy for retrying failed deployments: {:?}",
+                s
+            )),
         }
     }
 }
 
+/// A file of a deployment that could not be fetched
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnresolvableFile {
+    pub link: String,
+    pub error: String,
+}
+
+/// Which files of a deployment can not be resolved any more, or resolve to
+/// something other than what the deployment was created with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeploymentFilesReport {
+    pub deployment: String,
+    pub unresolvable: Vec<UnresolvableFile>,
+    /// Links whose contents differ from what the store has
+    pub mismatched: Vec<String>,
+}
+
+impl DeploymentFilesReport {
+    pub fn is_ok(&self) -> bool {
+        self.unresolvable.is_empty() && self.mismatched.is_empty()
+    }
+}
+
 /// Common trait for subgraph registrars.
 #[async_trait]
 pub trait SubgraphRegistrar: Send + Sync + 'static {
@@ -26,12 +160,19 @@ pub trait SubgraphRegistrar: Send + Sync + 'static {
         name: SubgraphName,
     ) -> Result<CreateSubgraphResult, SubgraphRegistrarError>;
 
+    /// Deploy `hash` as a new version of the subgraph `name`. If
+    /// `version_switching_mode` is `None`, the registrar's mode is used.
+    /// If `labels` are given, they replace the labels of the deployment
+    /// before it is placed. Returns the profile of the deployment's
+    /// manifest
     async fn create_subgraph_version(
         &self,
         name: SubgraphName,
         hash: DeploymentHash,
         assignment_node_id: NodeId,
-    ) -> Result<(), SubgraphRegistrarError>;
+        version_switching_mode: Option<SubgraphVersionSwitchingMode>,
+        labels: Option<Labels>,
+    ) -> Result<DeploymentProfile, SubgraphRegistrarError>;
 
     async fn remove_subgraph(&self, name: SubgraphName) -> Result<(), SubgraphRegistrarError>;
 
@@ -40,4 +181,119 @@ pub trait SubgraphRegistrar: Send + Sync + 'static {
         hash: &DeploymentHash,
         node_id: &NodeId,
     ) -> Result<(), SubgraphRegistrarError>;
+
+    /// Archive entity versions of the deployment that were closed more
+    /// than `blocks` blocks before its head, or stop archiving if `blocks`
+    /// is `None`
+    async fn set_archive_blocks(
+        &self,
+        hash: &DeploymentHash,
+        blocks: Option<BlockNumber>,
+    ) -> Result<(), SubgraphRegistrarError>;
+
+    /// Tell the deployment to skip the block with `block_hash` without
+    /// running any handlers for it. `reason` is recorded with the skip and
+    /// shown in the indexing status
+    async fn skip_block(
+        &self,
+        hash: &DeploymentHash,
+        block_hash: &str,
+        reason: String,
+    ) -> Result<(), SubgraphRegistrarError>;
+
+    /// Withdraw the request to skip the block with `block_hash`
+    async fn unskip_block(
+        &self,
+        hash: &DeploymentHash,
+        block_hash: &str,
+    ) -> Result<SkippedBlock, SubgraphRegistrarError>;
+
+    /// Start counting how much data the deployment requests from its
+    /// chain from zero
+    async fn reset_scan_totals(&self, hash: &DeploymentHash) -> Result<(), SubgraphRegistrarError>;
+
+    /// Replace the labels of the deployment with `labels`
+    async fn set_labels(
+        &self,
+        hash: &DeploymentHash,
+        labels: Labels,
+    ) -> Result<(), SubgraphRegistrarError>;
+
+    /// The labels of the deployment
+    async fn labels(&self, hash: &DeploymentHash) -> Result<Labels, SubgraphRegistrarError>;
+
+    /// Make the deployment run the mappings from the manifest `manifest`,
+    /// or re-resolve its own mappings if that is not given, keeping its
+    /// id and data. If `block` is given, the deployment is rewound to that
+    /// block first. Only for development, since the proof of indexing of
+    /// the deployment can not be compared with that of other deployments
+    /// afterwards
+    async fn reload_mappings(
+        &self,
+        hash: &DeploymentHash,
+        manifest: Option<DeploymentHash>,
+        block: Option<BlockNumber>,
+    ) -> Result<MappingsReload, SubgraphRegistrarError>;
+
+    /// Remove metadata about subgraphs that is not needed any more and
+    /// report what was removed
+    async fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, SubgraphRegistrarError>;
+
+    /// Compare the entities of the pending version of the subgraph `name`
+    /// with those of its current version at `block`, so that operators can
+    /// check the pending version before it becomes the current version
+    async fn compare_versions(
+        &self,
+        name: SubgraphName,
+        block: BlockNumber,
+        sample_size: usize,
+    ) -> Result<VersionComparison, SubgraphRegistrarError>;
+
+    /// Resolve the manifest and all linked files of every deployment
+    /// assigned to this node again and report the ones that can not be
+    /// resolved or differ from what is stored. This only reads files and
+    /// does not affect the deployments
+    async fn validate_deployment_files(
+        &self,
+    ) -> Result<Vec<DeploymentFilesReport>, SubgraphRegistrarError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synced_criterion() {
+        let criterion = SyncedCriterion {
+            head_distance: 2,
+            checks: 3,
+        };
+
+        let mut checks = 0;
+        for distance in &[5, 2, 0, 1] {
+            checks = criterion.check(checks, *distance);
+        }
+        assert_eq!(3, checks);
+        assert!(criterion.is_met(checks));
+
+        // Further checks do not count beyond what is needed
+        assert_eq!(3, criterion.check(checks, 0));
+
+        // Falling behind starts over
+        checks = criterion.check(checks, 3);
+        assert_eq!(0, checks);
+        assert!(!criterion.is_met(checks));
+        assert_eq!(1, criterion.check(checks, 2));
+    }
+
+    #[test]
+    fn parse_version_switching_mode() {
+        use SubgraphVersionSwitchingMode::*;
+
+        assert_eq!(Instant, "instant".parse().unwrap());
+        assert_eq!(Synced, "Synced".parse().unwrap());
+        assert!("eventually"
+            .parse::<SubgraphVersionSwitchingMode>()
+            .is_err());
+    }
 }
@@ -6,6 +6,7 @@ use crate::prelude::s::{
 };
 use lazy_static::lazy_static;
 use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
 
 lazy_static! {
     static ref ALLOW_NON_DETERMINISTIC_FULLTEXT_SEARCH: bool = if cfg!(debug_assertions) {
@@ -307,6 +308,21 @@ impl DirectiveFinder for Vec<Directive> {
     }
 }
 
+pub trait FieldExt {
+    /// The number of bytes that values of this field must have if it is
+    /// annotated with `@length(bytes: N)`
+    fn byte_length(&self) -> Option<usize>;
+}
+
+impl FieldExt for Field {
+    fn byte_length(&self) -> Option<usize> {
+        match self.find_directive("length")?.argument("bytes")? {
+            Value::Int(bytes) => bytes.as_i64().and_then(|bytes| usize::try_from(bytes).ok()),
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod directive_finder_tests {
     use graphql_parser::parse_schema;
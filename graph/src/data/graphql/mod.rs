@@ -2,7 +2,7 @@ mod serialization;
 
 /// Traits to navigate the GraphQL AST
 pub mod ext;
-pub use ext::{DirectiveExt, DocumentExt, ObjectTypeExt, TypeExt, ValueExt};
+pub use ext::{DirectiveExt, DocumentExt, FieldExt, ObjectTypeExt, TypeExt, ValueExt};
 
 /// Utilities for working with GraphQL values.
 mod values;
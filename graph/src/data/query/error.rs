@@ -40,6 +40,7 @@ pub enum QueryExecutionError {
     NamedTypeError(String),
     AbstractTypeError(String),
     InvalidArgumentError(Pos, String, q::Value),
+    InvalidArgumentValueError(Pos, String, q::Value, String),
     MissingArgumentError(Pos, String),
     InvalidVariableTypeError(Pos, String),
     MissingVariableError(Pos, String),
@@ -121,6 +122,9 @@ impl fmt::Display for QueryExecutionError {
             InvalidArgumentError(_, s, v) => {
                 write!(f, "Invalid value provided for argument `{}`: {:?}", s, v)
             }
+            InvalidArgumentValueError(_, s, v, reason) => {
+                write!(f, "Invalid value `{}` provided for argument `{}`: {}", v, s, reason)
+            }
             MissingArgumentError(_, s) => {
                 write!(f, "No value provided for required argument: `{}`", s)
             }
@@ -371,6 +375,7 @@ impl Serialize for QueryError {
             QueryError::ExecutionError(NonNullError(pos, _))
             | QueryError::ExecutionError(ListValueError(pos, _))
             | QueryError::ExecutionError(InvalidArgumentError(pos, _, _))
+            | QueryError::ExecutionError(InvalidArgumentValueError(pos, _, _, _))
             | QueryError::ExecutionError(MissingArgumentError(pos, _))
             | QueryError::ExecutionError(InvalidVariableTypeError(pos, _))
             | QueryError::ExecutionError(MissingVariableError(pos, _))
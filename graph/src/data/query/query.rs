@@ -1,8 +1,10 @@
-use serde::de::Deserializer;
+use serde::de::{self, Deserializer, Visitor};
 use serde::Deserialize;
 use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::{
     data::graphql::shape_hash::shape_hash,
@@ -17,6 +19,65 @@ where
     Ok(q::Number::from(i))
 }
 
+/// Accept only JSON numbers that really are floats. Integers that are
+/// too big for an `Int` are turned into strings by `deserialize_string`
+/// so that they do not lose precision by going through an `f64`
+fn deserialize_float<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct FloatVisitor;
+
+    impl<'de> Visitor<'de> for FloatVisitor {
+        type Value = f64;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a float")
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+            Ok(v)
+        }
+    }
+
+    deserializer.deserialize_f64(FloatVisitor)
+}
+
+/// Accept strings, and integers that do not fit into an `Int` as their
+/// decimal representation
+fn deserialize_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct StringVisitor;
+
+    impl<'de> Visitor<'de> for StringVisitor {
+        type Value = String;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a string or an integer")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<String, E> {
+            Ok(v.to_owned())
+        }
+
+        fn visit_string<E: de::Error>(self, v: String) -> Result<String, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<String, E> {
+            Ok(v.to_string())
+        }
+    }
+
+    deserializer.deserialize_any(StringVisitor)
+}
+
 fn deserialize_list<'de, D>(deserializer: D) -> Result<Vec<q::Value>, D::Error>
 where
     D: Deserializer<'de>,
@@ -39,7 +100,9 @@ where
 enum GraphQLValue {
     #[serde(deserialize_with = "deserialize_number")]
     Int(q::Number),
+    #[serde(deserialize_with = "deserialize_float")]
     Float(f64),
+    #[serde(deserialize_with = "deserialize_string")]
     String(String),
     Boolean(bool),
     Null,
@@ -131,6 +194,9 @@ pub struct Query {
     pub shape_hash: u64,
     pub query_text: Arc<String>,
     pub variables_text: Arc<String>,
+    /// A deadline set by the client. Execution stops when either this
+    /// deadline or the server's own query timeout is reached
+    pub deadline: Option<Instant>,
     _force_use_of_new: (),
 }
 
@@ -155,7 +221,13 @@ impl Query {
             shape_hash,
             query_text: Arc::new(query_text),
             variables_text: Arc::new(variables_text),
+            deadline: None,
             _force_use_of_new: (),
         }
     }
+
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
 }
@@ -1,7 +1,7 @@
 use super::error::{QueryError, QueryExecutionError};
 use crate::{
     data::graphql::SerializableValue,
-    prelude::{q, CacheWeight, DeploymentHash},
+    prelude::{q, BlockNumber, CacheWeight, DeploymentHash},
 };
 use http::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
@@ -12,6 +12,7 @@ use serde::Serialize;
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 fn serialize_data<S>(data: &Option<Data>, serializer: S) -> Result<S::Ok, S::Error>
 where
@@ -48,18 +49,55 @@ pub type Data = BTreeMap<String, q::Value>;
 /// A collection of query results that is serialized as a single result.
 pub struct QueryResults {
     results: Vec<Arc<QueryResult>>,
+    /// The block that the deployment had been indexed up to when the
+    /// query was run
+    latest_block: Option<BlockNumber>,
 }
 
 impl QueryResults {
     pub fn empty() -> Self {
         QueryResults {
             results: Vec::new(),
+            latest_block: None,
         }
     }
 
     pub fn first(&self) -> Option<&Arc<QueryResult>> {
         self.results.first()
     }
+
+    /// The deployment that answered the query, if execution got far
+    /// enough to know that
+    pub fn deployment(&self) -> Option<&DeploymentHash> {
+        self.results.iter().find_map(|r| r.deployment.as_ref())
+    }
+
+    /// The oldest block at which the data in the results was computed.
+    /// Results from the cache can be for a block before the latest block
+    pub fn block(&self) -> Option<BlockNumber> {
+        self.results.iter().filter_map(|r| r.block).min()
+    }
+
+    pub fn latest_block(&self) -> Option<BlockNumber> {
+        self.latest_block
+    }
+
+    pub fn set_latest_block(&mut self, block: BlockNumber) {
+        self.latest_block = Some(block);
+    }
+
+    /// How long ago the oldest of the results was computed
+    pub fn age(&self) -> Option<Duration> {
+        self.results
+            .iter()
+            .filter_map(|r| r.computed_at.map(|at| at.elapsed()))
+            .max()
+    }
+
+    /// Whether execution of any part of the query ran out of time
+    pub fn timed_out(&self) -> bool {
+        self.results.iter().any(|r| r.timed_out())
+    }
 }
 
 impl Serialize for QueryResults {
@@ -117,6 +155,7 @@ impl From<Data> for QueryResults {
     fn from(x: Data) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            latest_block: None,
         }
     }
 }
@@ -125,13 +164,17 @@ impl From<QueryResult> for QueryResults {
     fn from(x: QueryResult) -> Self {
         QueryResults {
             results: vec![Arc::new(x)],
+            latest_block: None,
         }
     }
 }
 
 impl From<Arc<QueryResult>> for QueryResults {
     fn from(x: Arc<QueryResult>) -> Self {
-        QueryResults { results: vec![x] }
+        QueryResults {
+            results: vec![x],
+            latest_block: None,
+        }
     }
 }
 
@@ -139,6 +182,7 @@ impl From<QueryExecutionError> for QueryResults {
     fn from(x: QueryExecutionError) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            latest_block: None,
         }
     }
 }
@@ -147,6 +191,7 @@ impl From<Vec<QueryExecutionError>> for QueryResults {
     fn from(x: Vec<QueryExecutionError>) -> Self {
         QueryResults {
             results: vec![Arc::new(x.into())],
+            latest_block: None,
         }
     }
 }
@@ -169,6 +214,21 @@ impl QueryResults {
             .body(T::from(json))
             .unwrap()
     }
+
+    /// The response to a batch of operations: a JSON array with the
+    /// results of each operation in the order of the batch
+    pub fn batch_as_http_response<T: From<String>>(results: &[QueryResults]) -> http::Response<T> {
+        let json = serde_json::to_string(results)
+            .expect("Failed to serialize GraphQL batch response to JSON");
+        http::Response::builder()
+            .status(http::StatusCode::OK)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(ACCESS_CONTROL_ALLOW_HEADERS, "Content-Type, User-Agent")
+            .header(ACCESS_CONTROL_ALLOW_METHODS, "GET, OPTIONS, POST")
+            .header(CONTENT_TYPE, "application/json")
+            .body(T::from(json))
+            .unwrap()
+    }
 }
 
 /// The result of running a query, if successful.
@@ -183,6 +243,13 @@ pub struct QueryResult {
     errors: Vec<QueryError>,
     #[serde(skip_serializing)]
     pub deployment: Option<DeploymentHash>,
+    /// The block at which the data was computed
+    #[serde(skip_serializing)]
+    pub block: Option<BlockNumber>,
+    /// When the data was computed; results that come from the query cache
+    /// were computed earlier than the query that they answer
+    #[serde(skip_serializing)]
+    pub computed_at: Option<Instant>,
 }
 
 impl QueryResult {
@@ -191,6 +258,8 @@ impl QueryResult {
             data: Some(data),
             errors: Vec::new(),
             deployment: None,
+            block: None,
+            computed_at: None,
         }
     }
 
@@ -203,6 +272,8 @@ impl QueryResult {
             data: self.data.clone(),
             errors: self.errors.clone(),
             deployment: self.deployment.clone(),
+            block: self.block,
+            computed_at: self.computed_at,
         }
     }
 
@@ -210,6 +281,13 @@ impl QueryResult {
         !self.errors.is_empty()
     }
 
+    /// Whether execution stopped because the query ran out of time
+    pub fn timed_out(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|e| matches!(e, QueryError::ExecutionError(QueryExecutionError::Timeout)))
+    }
+
     pub fn has_data(&self) -> bool {
         self.data.is_some()
     }
@@ -241,6 +319,8 @@ impl From<QueryExecutionError> for QueryResult {
             data: None,
             errors: vec![e.into()],
             deployment: None,
+            block: None,
+            computed_at: None,
         }
     }
 }
@@ -251,6 +331,8 @@ impl From<QueryError> for QueryResult {
             data: None,
             errors: vec![e],
             deployment: None,
+            block: None,
+            computed_at: None,
         }
     }
 }
@@ -261,6 +343,8 @@ impl From<Vec<QueryExecutionError>> for QueryResult {
             data: None,
             errors: e.into_iter().map(QueryError::from).collect(),
             deployment: None,
+            block: None,
+            computed_at: None,
         }
     }
 }
@@ -1,5 +1,7 @@
 use crate::components::store::{EntityType, SubgraphStore};
-use crate::data::graphql::ext::{DirectiveExt, DirectiveFinder, DocumentExt, TypeExt, ValueExt};
+use crate::data::graphql::ext::{
+    DirectiveExt, DirectiveFinder, DocumentExt, ObjectTypeExt, TypeExt, ValueExt,
+};
 use crate::data::store::ValueType;
 use crate::data::subgraph::{DeploymentHash, SubgraphName};
 use crate::prelude::{
@@ -103,6 +105,16 @@ pub enum SchemaValidationError {
     FulltextIncludedFieldMissingRequiredProperty,
     #[error("Fulltext entity field, {0}, not found or not a string")]
     FulltextIncludedFieldInvalid(String),
+    #[error("@index directive on type `{0}` must have a non-empty `fields` list of field names")]
+    IndexFieldsUndefined(String),
+    #[error("@index directive on type `{0}` can not include field `{1}`: {2}")]
+    IndexFieldInvalid(String, String, String), // (type, field, reason)
+    #[error(
+        "@index directive on type `{0}` has an invalid `unique` argument; it must be a boolean"
+    )]
+    IndexUniqueInvalid(String),
+    #[error("@length directive on field `{1}` of type `{0}` is invalid: {2}")]
+    LengthDirectiveInvalid(String, String, String), // (type, field, reason)
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -251,6 +263,36 @@ impl From<&s::Directive> for FulltextDefinition {
         }
     }
 }
+
+/// A composite index on an entity type, declared with
+/// `@index(fields: ["a", "b"], unique: false)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexDefinition {
+    /// The GraphQL names of the indexed fields, in index order
+    pub fields: Vec<String>,
+    pub unique: bool,
+}
+
+impl From<&s::Directive> for IndexDefinition {
+    // Assumes the input is an index directive that has already been
+    // validated because it makes liberal use of unwrap()
+    fn from(directive: &Directive) -> Self {
+        let fields = directive
+            .argument("fields")
+            .unwrap()
+            .as_list()
+            .unwrap()
+            .iter()
+            .map(|field| field.as_str().unwrap().to_owned())
+            .collect();
+        let unique = match directive.argument("unique") {
+            Some(Value::Boolean(unique)) => *unique,
+            _ => false,
+        };
+        IndexDefinition { fields, unique }
+    }
+}
+
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 pub enum SchemaImportError {
     #[error("Schema for imported subgraph `{0}` was not found")]
@@ -395,6 +437,19 @@ impl ApiSchema {
     pub fn interfaces_for_type(&self, type_name: &EntityType) -> Option<&Vec<InterfaceType>> {
         self.schema.interfaces_for_type(type_name)
     }
+
+    /// The schema in GraphQL SDL, including all the types and fields that
+    /// are generated for querying, like filters and `orderBy` enums.
+    pub fn sdl(&self) -> String {
+        self.schema.document.to_string()
+    }
+
+    /// A hash of `sdl()` that only changes when the SDL does. Since it only
+    /// depends on the text of the SDL, it is the same across restarts and
+    /// across index nodes.
+    pub fn sdl_hash(&self) -> String {
+        hex::encode(tiny_keccak::keccak256(self.sdl().as_bytes()))
+    }
 }
 
 /// A validated and preprocessed GraphQL schema for a subgraph.
@@ -660,6 +715,8 @@ impl Schema {
         errors.append(&mut self.validate_fields());
         errors.append(&mut self.validate_import_directives());
         errors.append(&mut self.validate_fulltext_directives());
+        errors.append(&mut self.validate_index_directives());
+        errors.append(&mut self.validate_length_directives());
         errors.append(&mut self.validate_imported_types(schemas));
 
         if errors.is_empty() {
@@ -969,6 +1026,106 @@ impl Schema {
         return vec![];
     }
 
+    /// Check that every `@index` directive on an entity type lists fields
+    /// that exist on the type and that can be used in a BTree index, i.e.,
+    /// that are neither lists nor derived
+    fn validate_index_directives(&self) -> Vec<SchemaValidationError> {
+        fn is_list(field_type: &Type) -> bool {
+            match field_type {
+                Type::NamedType(_) => false,
+                Type::ListType(_) => true,
+                Type::NonNullType(inner) => is_list(inner),
+            }
+        }
+
+        let mut errors = vec![];
+        for object_type in self.document.get_object_type_definitions() {
+            for index in object_type
+                .directives
+                .iter()
+                .filter(|directive| directive.name == "index")
+            {
+                match index.argument("unique") {
+                    None | Some(Value::Boolean(_)) => {}
+                    Some(_) => errors.push(SchemaValidationError::IndexUniqueInvalid(
+                        object_type.name.clone(),
+                    )),
+                }
+
+                let fields = match index.argument("fields").and_then(|fields| fields.as_list()) {
+                    Some(fields) if !fields.is_empty() => fields,
+                    _ => {
+                        errors.push(SchemaValidationError::IndexFieldsUndefined(
+                            object_type.name.clone(),
+                        ));
+                        continue;
+                    }
+                };
+
+                for field in fields {
+                    let name = match field.as_str() {
+                        Some(name) => name,
+                        None => {
+                            errors.push(SchemaValidationError::IndexFieldsUndefined(
+                                object_type.name.clone(),
+                            ));
+                            continue;
+                        }
+                    };
+                    let invalid = |reason: &str| {
+                        SchemaValidationError::IndexFieldInvalid(
+                            object_type.name.clone(),
+                            name.to_owned(),
+                            reason.to_owned(),
+                        )
+                    };
+                    match object_type.field(name) {
+                        None => errors.push(invalid("no such field")),
+                        Some(field) if field.is_derived() => {
+                            errors.push(invalid("derived fields are not stored"))
+                        }
+                        Some(field) if is_list(&field.field_type) => {
+                            errors.push(invalid("list fields can not be ordered"))
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Check that `@length` directives are only used on `Bytes` fields and
+    /// have a positive `bytes` argument
+    fn validate_length_directives(&self) -> Vec<SchemaValidationError> {
+        let mut errors = vec![];
+        for (type_name, fields) in self.document.get_object_and_interface_type_fields() {
+            for field in fields {
+                let directive = match field.find_directive("length") {
+                    Some(directive) => directive,
+                    None => continue,
+                };
+                let invalid = |reason: &str| {
+                    SchemaValidationError::LengthDirectiveInvalid(
+                        type_name.to_owned(),
+                        field.name.clone(),
+                        reason.to_owned(),
+                    )
+                };
+                if field.field_type.get_base_type() != "Bytes" {
+                    errors.push(invalid("only fields of type `Bytes` can have a length"));
+                } else if field.is_derived() {
+                    errors.push(invalid("derived fields are not stored"));
+                }
+                match directive.argument("bytes") {
+                    Some(Value::Int(bytes)) if bytes.as_i64().map_or(false, |bytes| bytes > 0) => {}
+                    _ => errors.push(invalid("the `bytes` argument must be a positive integer")),
+                }
+            }
+        }
+        errors
+    }
+
     fn validate_import_directives(&self) -> Vec<SchemaValidationError> {
         self.subgraph_schema_object_type()
             .map_or(vec![], |subgraph_schema_type| {
@@ -1105,6 +1262,8 @@ impl Schema {
             "String".into(),
             "Bytes".into(),
             "BigInt".into(),
+            "Int8".into(),
+            "Timestamp".into(),
             // Reserved Query and Subscription types
             "Query".into(),
             "Subscription".into(),
@@ -1346,6 +1505,17 @@ impl Schema {
             .map(FulltextDefinition::from)
             .collect())
     }
+
+    /// The composite indexes declared with `@index` on `object_type`. The
+    /// schema must have been validated
+    pub fn entity_index_definitions(object_type: &ObjectType) -> Vec<IndexDefinition> {
+        object_type
+            .directives
+            .iter()
+            .filter(|directive| directive.name == "index")
+            .map(IndexDefinition::from)
+            .collect()
+    }
 }
 
 #[test]
@@ -1601,6 +1771,8 @@ fn test_reserved_types_validation() {
         "String",
         "Bytes",
         "BigInt",
+        "Int8",
+        "Timestamp",
         // Reserved keywords
         "Query",
         "Subscription",
@@ -1691,3 +1863,125 @@ type Gravatar @entity {
 
     assert_eq!(schema.validate_fulltext_directives(), vec![]);
 }
+
+#[test]
+fn test_index_directive_validation() {
+    const SCHEMA: &str = r#"
+type Transfer @entity
+    @index(fields: ["owner", "timestamp"])
+    @index(fields: ["token"], unique: true) {
+  id: ID!
+  owner: Bytes!
+  timestamp: BigInt!
+  token: Token!
+  tags: [String!]!
+}
+type Token @entity {
+  id: ID!
+  transfers: [Transfer!]! @derivedFrom(field: "token")
+}"#;
+
+    let document = graphql_parser::parse_schema(SCHEMA).expect("Failed to parse schema");
+    let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document);
+    assert_eq!(schema.validate_index_directives(), vec![]);
+
+    let transfer = schema
+        .document
+        .get_object_type_definitions()
+        .into_iter()
+        .find(|object_type| object_type.name == "Transfer")
+        .unwrap();
+    assert_eq!(
+        Schema::entity_index_definitions(transfer),
+        vec![
+            IndexDefinition {
+                fields: vec!["owner".to_owned(), "timestamp".to_owned()],
+                unique: false
+            },
+            IndexDefinition {
+                fields: vec!["token".to_owned()],
+                unique: true
+            }
+        ]
+    );
+
+    fn validate(directive: &str, expected: SchemaValidationError) {
+        let schema = format!(
+            "type Token @entity {} {{ id: ID!, name: String!, tags: [String!]!, \
+             transfers: [Token!]! @derivedFrom(field: \"id\") }}",
+            directive
+        );
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document);
+        assert_eq!(schema.validate_index_directives(), vec![expected]);
+    }
+
+    let invalid = |field: &str, reason: &str| {
+        SchemaValidationError::IndexFieldInvalid(
+            "Token".to_owned(),
+            field.to_owned(),
+            reason.to_owned(),
+        )
+    };
+    validate(
+        "@index(fields: [])",
+        SchemaValidationError::IndexFieldsUndefined("Token".to_owned()),
+    );
+    validate(
+        "@index(unique: true)",
+        SchemaValidationError::IndexFieldsUndefined("Token".to_owned()),
+    );
+    validate(
+        r#"@index(fields: ["name"], unique: "yes")"#,
+        SchemaValidationError::IndexUniqueInvalid("Token".to_owned()),
+    );
+    validate(
+        r#"@index(fields: ["name", "missing"])"#,
+        invalid("missing", "no such field"),
+    );
+    validate(
+        r#"@index(fields: ["tags"])"#,
+        invalid("tags", "list fields can not be ordered"),
+    );
+    validate(
+        r#"@index(fields: ["transfers"])"#,
+        invalid("transfers", "derived fields are not stored"),
+    );
+}
+
+#[test]
+fn test_length_directive_validation() {
+    fn validate(field: &str) -> Vec<SchemaValidationError> {
+        let schema = format!("type Token @entity {{ id: ID!, name: String!, {} }}", field);
+        let document = graphql_parser::parse_schema(&schema).expect("Failed to parse schema");
+        let schema = Schema::new(DeploymentHash::new("id1").unwrap(), document);
+        schema.validate_length_directives()
+    }
+
+    let invalid = |field: &str, reason: &str| {
+        vec![SchemaValidationError::LengthDirectiveInvalid(
+            "Token".to_owned(),
+            field.to_owned(),
+            reason.to_owned(),
+        )]
+    };
+
+    assert_eq!(validate("owner: Bytes! @length(bytes: 20)"), vec![]);
+    assert_eq!(validate("owners: [Bytes!]! @length(bytes: 20)"), vec![]);
+    assert_eq!(
+        validate("label: String! @length(bytes: 20)"),
+        invalid("label", "only fields of type `Bytes` can have a length")
+    );
+    assert_eq!(
+        validate("owner: Bytes! @length(bytes: 0)"),
+        invalid("owner", "the `bytes` argument must be a positive integer")
+    );
+    assert_eq!(
+        validate(r#"owner: Bytes! @length(bytes: "20")"#),
+        invalid("owner", "the `bytes` argument must be a positive integer")
+    );
+    assert_eq!(
+        validate("owner: Bytes! @length"),
+        invalid("owner", "the `bytes` argument must be a positive integer")
+    );
+}
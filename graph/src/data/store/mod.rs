@@ -131,6 +131,8 @@ pub const ID: &str = "ID";
 pub const BYTES_SCALAR: &str = "Bytes";
 pub const BIG_INT_SCALAR: &str = "BigInt";
 pub const BIG_DECIMAL_SCALAR: &str = "BigDecimal";
+pub const INT8_SCALAR: &str = "Int8";
+pub const TIMESTAMP_SCALAR: &str = "Timestamp";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ValueType {
@@ -139,7 +141,9 @@ pub enum ValueType {
     Bytes,
     BigDecimal,
     Int,
+    Int8,
     String,
+    Timestamp,
 }
 
 impl FromStr for ValueType {
@@ -152,6 +156,8 @@ impl FromStr for ValueType {
             "Bytes" => Ok(ValueType::Bytes),
             "BigDecimal" => Ok(ValueType::BigDecimal),
             "Int" => Ok(ValueType::Int),
+            "Int8" => Ok(ValueType::Int8),
+            "Timestamp" => Ok(ValueType::Timestamp),
             "String" | "ID" => Ok(ValueType::String),
             s => Err(anyhow!("Type not available in this context: {}", s)),
         }
@@ -179,6 +185,8 @@ pub enum Value {
     Null,
     Bytes(scalar::Bytes),
     BigInt(scalar::BigInt),
+    Int8(i64),
+    Timestamp(scalar::Timestamp),
 }
 
 impl StableHash for Value {
@@ -203,6 +211,8 @@ impl StableHash for Value {
             List(inner) => inner.stable_hash(sequence_number, state),
             Bytes(inner) => inner.stable_hash(sequence_number, state),
             BigInt(inner) => inner.stable_hash(sequence_number, state),
+            Int8(inner) => inner.stable_hash(sequence_number, state),
+            Timestamp(inner) => inner.stable_hash(sequence_number, state),
         }
     }
 }
@@ -236,9 +246,24 @@ impl Value {
                     BYTES_SCALAR => Value::Bytes(scalar::Bytes::from_str(s)?),
                     BIG_INT_SCALAR => Value::BigInt(scalar::BigInt::from_str(s)?),
                     BIG_DECIMAL_SCALAR => Value::BigDecimal(scalar::BigDecimal::from_str(s)?),
+                    INT8_SCALAR => Value::Int8(i64::from_str(s).map_err(|e| {
+                        QueryExecutionError::ValueParseError(INT8_SCALAR.to_string(), e.to_string())
+                    })?),
+                    TIMESTAMP_SCALAR => {
+                        Value::Timestamp(scalar::Timestamp::from_str(s).map_err(|e| {
+                            QueryExecutionError::ValueParseError(
+                                TIMESTAMP_SCALAR.to_string(),
+                                e.to_string(),
+                            )
+                        })?)
+                    }
                     _ => Value::String(s.clone()),
                 }
             }
+            (q::Value::Int(i), NamedType(n)) if n == INT8_SCALAR => Value::Int8(
+                i.as_i64()
+                    .ok_or_else(|| QueryExecutionError::NamedTypeError(INT8_SCALAR.to_string()))?,
+            ),
             (q::Value::Int(i), _) => Value::Int(
                 i.to_owned()
                     .as_i64()
@@ -284,6 +309,22 @@ impl Value {
         }
     }
 
+    pub fn as_int8(self) -> Option<i64> {
+        if let Value::Int8(i) = self {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_timestamp(self) -> Option<scalar::Timestamp> {
+        if let Value::Timestamp(ts) = self {
+            Some(ts)
+        } else {
+            None
+        }
+    }
+
     pub fn as_big_decimal(self) -> Option<scalar::BigDecimal> {
         if let Value::BigDecimal(d) = self {
             Some(d)
@@ -332,6 +373,7 @@ impl Value {
             Value::Bool(_) => "Boolean".to_owned(),
             Value::Bytes(_) => "Bytes".to_owned(),
             Value::Int(_) => "Int".to_owned(),
+            Value::Int8(_) => "Int8".to_owned(),
             Value::List(values) => {
                 if let Some(v) = values.first() {
                     format!("[{}]", v.type_name())
@@ -341,6 +383,7 @@ impl Value {
             }
             Value::Null => "Null".to_owned(),
             Value::String(_) => "String".to_owned(),
+            Value::Timestamp(_) => "Timestamp".to_owned(),
         }
     }
 }
@@ -360,6 +403,8 @@ impl fmt::Display for Value {
                     format!("[{}]", values.iter().map(ToString::to_string).join(", ")),
                 Value::Bytes(ref bytes) => bytes.to_string(),
                 Value::BigInt(ref number) => number.to_string(),
+                Value::Int8(i) => i.to_string(),
+                Value::Timestamp(ref ts) => ts.to_string(),
             }
         )
     }
@@ -378,6 +423,8 @@ impl From<Value> for q::Value {
             }
             Value::Bytes(bytes) => q::Value::String(bytes.to_string()),
             Value::BigInt(number) => q::Value::String(number.to_string()),
+            Value::Int8(i) => q::Value::String(i.to_string()),
+            Value::Timestamp(ts) => q::Value::String(ts.to_string()),
         }
     }
 }
@@ -418,6 +465,18 @@ impl From<i32> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(value: i64) -> Value {
+        Value::Int8(value)
+    }
+}
+
+impl From<scalar::Timestamp> for Value {
+    fn from(value: scalar::Timestamp) -> Value {
+        Value::Timestamp(value)
+    }
+}
+
 impl From<scalar::BigDecimal> for Value {
     fn from(value: scalar::BigDecimal) -> Value {
         Value::BigDecimal(value)
@@ -641,3 +700,30 @@ fn value_bigint() {
     );
     assert_eq!(q::Value::from(from_query), graphql_value);
 }
+
+#[test]
+fn value_int8() {
+    let graphql_value = q::Value::String("-9223372036854775808".to_owned());
+    let ty = q::Type::NamedType(INT8_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(i64::MIN));
+    assert_eq!(q::Value::from(from_query), graphql_value);
+
+    let graphql_value = q::Value::Int(q::Number::from(17));
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(from_query, Value::Int8(17));
+}
+
+#[test]
+fn value_timestamp() {
+    let graphql_value = q::Value::String("2024-01-01T00:00:00Z".to_owned());
+    let ty = q::Type::NamedType(TIMESTAMP_SCALAR.to_owned());
+    let from_query = Value::from_query_value(&graphql_value, &ty).unwrap();
+    assert_eq!(
+        from_query,
+        Value::Timestamp(
+            scalar::Timestamp::from_microseconds_since_epoch(1_704_067_200_000_000).unwrap()
+        )
+    );
+    assert_eq!(q::Value::from(from_query), graphql_value);
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Timelike, Utc};
 use diesel::deserialize::FromSql;
 use diesel::serialize::ToSql;
 use diesel_derives::{AsExpression, FromSqlRow};
@@ -84,6 +85,163 @@ impl BigDecimal {
 
         BigDecimal(bigdecimal::BigDecimal::new(int_val.into(), scale))
     }
+
+    /// Round to at most `decimals` digits after the decimal point, rounding
+    /// halves away from zero. The result is normalized, so it does not have
+    /// trailing zeros
+    pub fn round_to_decimals(&self, decimals: u32) -> BigDecimal {
+        self.round(decimals as i64, RoundingMode::HalfUp)
+    }
+
+    /// Round to at most `decimals` digits after the decimal point with
+    /// `mode`; a negative `decimals` rounds to tens, hundreds, and so on.
+    /// If the rounded value still has more than `MAX_SIGNFICANT_DIGITS`
+    /// significant digits, it is rounded to that many with `mode`, too, so
+    /// that normalizing it does not round it again in a different way
+    pub fn round(&self, decimals: i64, mode: RoundingMode) -> BigDecimal {
+        let (mut int_val, mut scale) = self.0.as_bigint_and_exponent();
+        if scale > decimals {
+            int_val = div_pow10_rounded(&int_val, (scale - decimals) as u64, mode);
+            scale = decimals;
+        }
+
+        let digits = bigdecimal::BigDecimal::new(int_val.clone(), 0).digits();
+        let max_digits = Self::MAX_SIGNFICANT_DIGITS as u64;
+        if digits > max_digits {
+            int_val = div_pow10_rounded(&int_val, digits - max_digits, mode);
+            scale -= (digits - max_digits) as i64;
+        }
+        Self::from(bigdecimal::BigDecimal::new(int_val, scale))
+    }
+
+    /// Raise to the power `exp` by repeated squaring. Intermediate results
+    /// are rounded to `POW_PRECISION` significant digits, and the result
+    /// is normalized. Returns `None` for zero to a negative power
+    pub fn pow(&self, exp: i32) -> Option<BigDecimal> {
+        use bigdecimal::{One, Zero};
+
+        if exp < 0 && self.0.is_zero() {
+            return None;
+        }
+
+        let mut result = bigdecimal::BigDecimal::one();
+        let mut base = self.0.clone();
+        let mut n = (exp as i64).abs() as u64;
+        while n > 0 {
+            if n & 1 == 1 {
+                result = (&result * &base).with_prec(POW_PRECISION);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = (&base * &base).with_prec(POW_PRECISION);
+            }
+        }
+        if exp < 0 {
+            result = bigdecimal::BigDecimal::one() / result;
+        }
+        Some(Self::from(result))
+    }
+}
+
+/// The number of significant digits that `BigDecimal::pow` keeps for
+/// intermediate results. Keeping twice as many as we store means that
+/// rounding them does not show in the normalized result
+const POW_PRECISION: u64 = 2 * BigDecimal::MAX_SIGNFICANT_DIGITS as u64;
+
+/// How `BigDecimal::round` treats the digits it drops. Mappings pass the
+/// mode as the number of the variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Toward zero, i.e., truncate
+    Down = 0,
+    /// Away from zero
+    Up = 1,
+    /// Toward negative infinity
+    Floor = 2,
+    /// Toward positive infinity
+    Ceiling = 3,
+    /// To the nearest value, halves away from zero
+    HalfUp = 4,
+    /// To the nearest value, halves toward zero
+    HalfDown = 5,
+    /// To the nearest value, halves to the even neighbour
+    HalfEven = 6,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown rounding mode {0}")]
+pub struct UnknownRoundingMode(pub u32);
+
+impl TryFrom<u32> for RoundingMode {
+    type Error = UnknownRoundingMode;
+    fn try_from(mode: u32) -> Result<RoundingMode, UnknownRoundingMode> {
+        use RoundingMode::*;
+
+        Ok(match mode {
+            0 => Down,
+            1 => Up,
+            2 => Floor,
+            3 => Ceiling,
+            4 => HalfUp,
+            5 => HalfDown,
+            6 => HalfEven,
+            _ => return Err(UnknownRoundingMode(mode)),
+        })
+    }
+}
+
+/// Divide `int_val` by `10^digits` and round the quotient with `mode`
+fn div_pow10_rounded(
+    int_val: &num_bigint::BigInt,
+    digits: u64,
+    mode: RoundingMode,
+) -> num_bigint::BigInt {
+    use num_traits::{pow::Pow, Zero};
+    use std::cmp::Ordering;
+
+    let negative = int_val.sign() == BigIntSign::Minus;
+
+    // If we drop more digits than `int_val` has, the quotient is zero and
+    // the remainder is less than half of the divisor. Checking for that
+    // first keeps us from computing an arbitrarily large power of ten
+    let int_digits = bigdecimal::BigDecimal::new(int_val.clone(), 0).digits();
+    let (quotient, half) = if digits > int_digits {
+        if int_val.is_zero() {
+            return num_bigint::BigInt::zero();
+        }
+        (num_bigint::BigInt::zero(), Ordering::Less)
+    } else {
+        let divisor = num_bigint::BigInt::from(10u32).pow(digits as u32);
+        // Both truncate toward zero
+        let quotient = int_val / &divisor;
+        let remainder = int_val % &divisor;
+        if remainder.is_zero() {
+            return quotient;
+        }
+        let half = (remainder.magnitude() + remainder.magnitude()).cmp(divisor.magnitude());
+        (quotient, half)
+    };
+
+    let away_from_zero = match mode {
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::Floor => negative,
+        RoundingMode::Ceiling => !negative,
+        RoundingMode::HalfUp | RoundingMode::HalfDown | RoundingMode::HalfEven => match half {
+            Ordering::Less => false,
+            Ordering::Greater => true,
+            Ordering::Equal => match mode {
+                RoundingMode::HalfUp => true,
+                RoundingMode::HalfDown => false,
+                _ => !(&quotient % num_bigint::BigInt::from(2)).is_zero(),
+            },
+        },
+    };
+    match (away_from_zero, negative) {
+        (false, _) => quotient,
+        (true, false) => quotient + num_bigint::BigInt::from(1),
+        (true, true) => quotient - num_bigint::BigInt::from(1),
+    }
 }
 
 impl Display for BigDecimal {
@@ -513,7 +671,11 @@ impl FromStr for Bytes {
     type Err = hex::FromHexError;
 
     fn from_str(s: &str) -> Result<Bytes, Self::Err> {
-        hex::decode(s.trim_start_matches("0x")).map(|x| Bytes(x.into()))
+        let hex = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        hex::decode(hex).map(|x| Bytes(x.into()))
     }
 }
 
@@ -556,12 +718,91 @@ impl<'de> Deserialize<'de> for Bytes {
     }
 }
 
+/// A point in time with microsecond precision, the same precision that
+/// Postgres uses for `timestamptz`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(DateTime<Utc>);
+
+#[derive(Error, Debug)]
+pub enum TimestampParseError {
+    #[error("invalid timestamp `{0}`: {1}")]
+    Format(String, chrono::ParseError),
+    #[error("timestamp `{0}` is out of range")]
+    OutOfRange(String),
+}
+
+impl Timestamp {
+    pub fn from_microseconds_since_epoch(micros: i64) -> Option<Self> {
+        let secs = micros.div_euclid(1_000_000);
+        let nsecs = micros.rem_euclid(1_000_000) as u32 * 1000;
+        NaiveDateTime::from_timestamp_opt(secs, nsecs)
+            .map(|naive| Timestamp(DateTime::from_utc(naive, Utc)))
+    }
+
+    pub fn as_microseconds_since_epoch(&self) -> i64 {
+        self.0.timestamp() * 1_000_000 + self.0.timestamp_subsec_micros() as i64
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(datetime: DateTime<Utc>) -> Self {
+        // Drop anything finer than microseconds
+        let micros = datetime.timestamp_subsec_micros();
+        Timestamp(datetime.with_nanosecond(micros * 1000).unwrap_or(datetime))
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+}
+
+/// Timestamps can be written in RFC 3339 format, like
+/// `2024-01-01T00:00:00Z`, or as a number of microseconds since the epoch
+impl FromStr for Timestamp {
+    type Err = TimestampParseError;
+
+    fn from_str(s: &str) -> Result<Timestamp, Self::Err> {
+        if let Ok(micros) = i64::from_str(s) {
+            return Timestamp::from_microseconds_since_epoch(micros)
+                .ok_or_else(|| TimestampParseError::OutOfRange(s.to_string()));
+        }
+        DateTime::parse_from_rfc3339(s)
+            .map(|datetime| Timestamp::from(datetime.with_timezone(&Utc)))
+            .map_err(|e| TimestampParseError::Format(s.to_string(), e))
+    }
+}
+
+impl StableHash for Timestamp {
+    fn stable_hash<H: StableHasher>(&self, sequence_number: H::Seq, state: &mut H) {
+        self.as_microseconds_since_epoch()
+            .stable_hash(sequence_number, state)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let s = <String>::deserialize(deserializer)?;
+        Timestamp::from_str(&s).map_err(D::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{BigDecimal, BigInt};
+    use super::{BigDecimal, BigInt, Bytes, RoundingMode, Timestamp};
     use stable_hash::crypto::SetHasher;
     use stable_hash::prelude::*;
     use stable_hash::utils::stable_hash;
+    use std::convert::TryFrom;
     use std::str::FromStr;
     use web3::types::U64;
 
@@ -658,4 +899,253 @@ mod test {
             assert_eq!(normalized.to_string(), string);
         }
     }
+
+    #[test]
+    fn round_to_decimals() {
+        let round = |s: &str, decimals| {
+            BigDecimal::from_str(s)
+                .unwrap()
+                .round_to_decimals(decimals)
+                .to_string()
+        };
+
+        assert_eq!("1.234568", round("1.23456789", 6));
+        assert_eq!("1.5", round("1.5", 6));
+        assert_eq!("0.33", round("0.3333333333333333333333333333333333", 2));
+        assert_eq!("1", round("0.995", 2));
+        assert_eq!("-1", round("-0.995", 2));
+        assert_eq!("0.01", round("0.005", 2));
+        assert_eq!("-0.01", round("-0.005", 2));
+        assert_eq!("0", round("0.004", 2));
+        assert_eq!("2", round("1.5", 0));
+        assert_eq!("1900", round("1900", 3));
+        assert_eq!("0", round("0.0000000000000000000000000000001", 6));
+    }
+
+    #[test]
+    fn round_with_modes() {
+        use RoundingMode::*;
+
+        let round = |s: &str, decimals, mode| {
+            BigDecimal::from_str(s)
+                .unwrap()
+                .round(decimals, mode)
+                .to_string()
+        };
+
+        let modes = [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven];
+        let cases: &[(&str, [&str; 7])] = &[
+            ("1.25", ["1.2", "1.3", "1.2", "1.3", "1.3", "1.2", "1.2"]),
+            ("1.35", ["1.3", "1.4", "1.3", "1.4", "1.4", "1.3", "1.4"]),
+            (
+                "-1.25",
+                ["-1.2", "-1.3", "-1.3", "-1.2", "-1.3", "-1.2", "-1.2"],
+            ),
+            ("1.26", ["1.2", "1.3", "1.2", "1.3", "1.3", "1.3", "1.3"]),
+            (
+                "-1.21",
+                ["-1.2", "-1.3", "-1.3", "-1.2", "-1.2", "-1.2", "-1.2"],
+            ),
+            ("1.2", ["1.2", "1.2", "1.2", "1.2", "1.2", "1.2", "1.2"]),
+        ];
+        for (value, expected) in cases {
+            for (mode, expected) in modes.iter().zip(expected.iter()) {
+                assert_eq!(*expected, round(value, 1, *mode), "{} {:?}", value, mode);
+            }
+        }
+
+        // Negative decimals round to tens, hundreds, ...
+        assert_eq!("1200", round("1234.5", -2, Down));
+        assert_eq!("1300", round("1234.5", -2, Up));
+        assert_eq!("0", round("49", -2, HalfUp));
+
+        // Results with more significant digits than we store are rounded
+        // with the same mode
+        let long = "1234567890123456789012345678901234567.9";
+        assert_eq!(
+            "1234567890123456789012345678901234000",
+            round(long, 0, Down)
+        );
+        assert_eq!("1234567890123456789012345678901235000", round(long, 0, Up));
+        assert_eq!(
+            "-1234567890123456789012345678901235000",
+            round(&format!("-{}", long), 0, Floor)
+        );
+
+        // Dropping far more digits than a value has does not compute huge
+        // powers of ten
+        let min = i32::MIN as i64;
+        assert_eq!("0", round("1234.5", min, Down));
+        assert_eq!("0", round("-1234.5", min, HalfEven));
+        assert_eq!(
+            (num_bigint::BigInt::from(-1), min),
+            BigDecimal::from_str("-1234.5")
+                .unwrap()
+                .round(min, Floor)
+                .as_bigint_and_exponent()
+        );
+        assert_eq!("1.5", round("1.5", i32::MAX as i64, Down));
+        let tiny = BigDecimal::new(15.into(), -1_000_000_000);
+        assert_eq!("0", tiny.round(2, HalfUp).to_string());
+        assert_eq!("0.01", tiny.round(2, Up).to_string());
+        assert_eq!("0", tiny.round(min, Floor).to_string());
+        assert_eq!(
+            (num_bigint::BigInt::from(1), min),
+            tiny.round(min, Ceiling).as_bigint_and_exponent()
+        );
+
+        assert_eq!(Ok(HalfEven), RoundingMode::try_from(6).map_err(|_| ()));
+        assert!(RoundingMode::try_from(7).is_err());
+    }
+
+    /// A small, seeded generator so that failures can be reproduced
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    #[test]
+    fn round_matches_reference() {
+        use RoundingMode::*;
+
+        // Round `mantissa * 10^-scale` by dropping `dropped` digits, with
+        // machine integers instead of `BigInt`
+        fn reference(mantissa: i64, dropped: u32, mode: RoundingMode) -> i128 {
+            let negative = mantissa < 0;
+            let magnitude = (mantissa as i128).abs();
+            let unit = 10i128.pow(dropped);
+            let (quotient, remainder) = (magnitude / unit, magnitude % unit);
+            let up = remainder != 0
+                && match mode {
+                    Down => false,
+                    Up => true,
+                    Floor => negative,
+                    Ceiling => !negative,
+                    HalfUp => 2 * remainder >= unit,
+                    HalfDown => 2 * remainder > unit,
+                    HalfEven => {
+                        2 * remainder > unit || (2 * remainder == unit && quotient % 2 == 1)
+                    }
+                };
+            let rounded = if up { quotient + 1 } else { quotient };
+            if negative {
+                -rounded
+            } else {
+                rounded
+            }
+        }
+
+        let modes = [Down, Up, Floor, Ceiling, HalfUp, HalfDown, HalfEven];
+        let mut rng = XorShift(0x5eed_1234_abcd_ef01);
+        for _ in 0..20_000 {
+            let digits = rng.below(18) as u32 + 1;
+            let mut mantissa = (rng.next() % 10u64.pow(digits)) as i64;
+            if rng.below(2) == 0 {
+                mantissa = -mantissa;
+            }
+            let scale = rng.below(30) as i64;
+            let dropped = rng.below(20) as u32;
+            let decimals = scale - dropped as i64;
+            let mode = modes[rng.below(modes.len() as u64) as usize];
+
+            let value = BigDecimal::new(BigInt::from(mantissa), -scale);
+            let expected = BigDecimal::new(
+                BigInt::from_str(&reference(mantissa, dropped, mode).to_string()).unwrap(),
+                -decimals,
+            );
+            assert_eq!(
+                expected,
+                value.round(decimals, mode),
+                "{} to {} decimals with {:?}",
+                value,
+                decimals,
+                mode
+            );
+        }
+    }
+
+    #[test]
+    fn pow_matches_reference() {
+        let mut rng = XorShift(0x0dd_ba11_cafe_f00d);
+        for _ in 0..5_000 {
+            let mantissa = rng.below(2_000) as i64 - 1_000;
+            let scale = rng.below(5) as i64;
+            let exp = rng.below(11) as u8;
+            let value = BigDecimal::new(BigInt::from(mantissa), -scale);
+
+            // As long as the exact power has fewer digits than
+            // `POW_PRECISION`, `pow` must not round at all
+            let exact = BigDecimal::new(BigInt::from(mantissa).pow(exp), -scale * exp as i64);
+            assert_eq!(
+                Some(exact.clone()),
+                value.pow(exp as i32),
+                "{}^{}",
+                value,
+                exp
+            );
+
+            if mantissa != 0 {
+                assert_eq!(
+                    Some(BigDecimal::from(1) / exact),
+                    value.pow(-(exp as i32)),
+                    "{}^-{}",
+                    value,
+                    exp
+                );
+            }
+        }
+
+        assert_eq!(None, BigDecimal::zero().pow(-1));
+        assert_eq!(Some(BigDecimal::from(1)), BigDecimal::zero().pow(0));
+
+        // The price at the highest tick of Uniswap v3, which is close to
+        // 2^128. Results must not change between runs, or proofs of
+        // indexing would
+        let base = BigDecimal::from_str("1.0001").unwrap();
+        let price = base.pow(887272).unwrap();
+        assert_eq!(price, base.pow(887272).unwrap());
+        assert!(price > BigDecimal::from_str("3.40256e38").unwrap());
+        assert!(price < BigDecimal::from_str("3.40257e38").unwrap());
+        assert!(price.digits() <= BigDecimal::MAX_SIGNFICANT_DIGITS as u64);
+    }
+
+    #[test]
+    fn bytes_to_from_str() {
+        for s in &["0xdeadbeef", "0xDEADbeef", "0XDEADBEEF", "deadbeef"] {
+            let bytes = Bytes::from_str(s).unwrap();
+            assert_eq!("0xdeadbeef", bytes.to_string());
+            assert_eq!(bytes, Bytes::from_str(&bytes.to_string()).unwrap());
+        }
+        assert_eq!("0x", Bytes::from_str("0x").unwrap().to_string());
+        assert!(Bytes::from_str("0xdeadbee").is_err());
+        assert!(Bytes::from_str("0xdeadbeeg").is_err());
+    }
+
+    #[test]
+    fn timestamp_to_from_str() {
+        let ts = Timestamp::from_str("2024-01-01T00:00:00Z").unwrap();
+        assert_eq!(1_704_067_200_000_000, ts.as_microseconds_since_epoch());
+        assert_eq!("2024-01-01T00:00:00Z", ts.to_string());
+        assert_eq!(ts, Timestamp::from_str("1704067200000000").unwrap());
+
+        // Other time zones are converted to UTC, and precision beyond
+        // microseconds is dropped
+        let ts = Timestamp::from_str("2024-01-01T02:00:00.1234567+02:00").unwrap();
+        assert_eq!("2024-01-01T00:00:00.123456Z", ts.to_string());
+
+        let ts = Timestamp::from_microseconds_since_epoch(-1).unwrap();
+        assert_eq!("1969-12-31T23:59:59.999999Z", ts.to_string());
+
+        assert!(Timestamp::from_str("yesterday").is_err());
+    }
 }
@@ -33,6 +33,23 @@ pub enum SubgraphFeature {
     Grafting,
     FullTextSearch,
     IpfsOnEthereumContracts,
+    CompositeIndexes,
+    /// The subgraph must not make any `eth_call`s; mappings that try to will
+    /// fail the subgraph deterministically
+    NoEthCalls,
+    /// Strings from mappings with `apiVersion` 0.0.6 or later that contain
+    /// NUL characters or invalid UTF-16 fail the subgraph deterministically
+    /// instead of being sanitized
+    StrictStrings,
+    /// Blocks with more triggers than `maxTriggersPerBlock` are skipped
+    /// without running any handlers
+    SkipHeavyBlocks,
+    /// Blocks with more triggers than `maxTriggersPerBlock` fail the
+    /// subgraph deterministically
+    FailHeavyBlocks,
+    /// The node writes a `BlockInfo` entity for every block the subgraph
+    /// processes
+    BlockInfo,
 }
 
 impl fmt::Display for SubgraphFeature {
@@ -80,6 +97,28 @@ pub fn validate_subgraph_features<C: Blockchain>(
     }
 }
 
+/// Features that the manifest declares and that we could detect if the
+/// subgraph used them, but that it does not use. Declaring them does no
+/// harm, but usually means the manifest was copied from somewhere else
+pub fn unused_features<C: Blockchain>(
+    manifest: &SubgraphManifest<C>,
+) -> Result<BTreeSet<SubgraphFeature>, InvalidMapping> {
+    const DETECTABLE: [SubgraphFeature; 4] = [
+        SubgraphFeature::Grafting,
+        SubgraphFeature::FullTextSearch,
+        SubgraphFeature::IpfsOnEthereumContracts,
+        SubgraphFeature::CompositeIndexes,
+    ];
+
+    let used = detect_features(manifest)?;
+    Ok(manifest
+        .features
+        .iter()
+        .filter(|feature| DETECTABLE.contains(feature) && !used.contains(feature))
+        .cloned()
+        .collect())
+}
+
 pub fn detect_features<C: Blockchain>(
     manifest: &SubgraphManifest<C>,
 ) -> Result<BTreeSet<SubgraphFeature>, InvalidMapping> {
@@ -88,6 +127,12 @@ pub fn detect_features<C: Blockchain>(
         detect_grafting(&manifest),
         detect_full_text_search(&manifest.schema),
         detect_ipfs_on_ethereum_contracts(&manifest)?,
+        detect_composite_indexes(&manifest.schema),
+        detect_no_eth_calls(&manifest),
+        detect_strict_strings(&manifest),
+        detect_declared(&manifest, SubgraphFeature::SkipHeavyBlocks),
+        detect_declared(&manifest, SubgraphFeature::FailHeavyBlocks),
+        detect_declared(&manifest, SubgraphFeature::BlockInfo),
     ]
     .into_iter()
     .filter_map(|x| x)
@@ -105,6 +150,48 @@ fn detect_non_fatal_errors<C: Blockchain>(
     }
 }
 
+fn detect_no_eth_calls<C: Blockchain>(manifest: &SubgraphManifest<C>) -> Option<SubgraphFeature> {
+    if manifest.features.contains(&SubgraphFeature::NoEthCalls) {
+        Some(SubgraphFeature::NoEthCalls)
+    } else {
+        None
+    }
+}
+
+fn detect_strict_strings<C: Blockchain>(manifest: &SubgraphManifest<C>) -> Option<SubgraphFeature> {
+    if manifest.features.contains(&SubgraphFeature::StrictStrings) {
+        Some(SubgraphFeature::StrictStrings)
+    } else {
+        None
+    }
+}
+
+/// Features that only change how the node treats the subgraph are used
+/// exactly when they are declared
+fn detect_declared<C: Blockchain>(
+    manifest: &SubgraphManifest<C>,
+    feature: SubgraphFeature,
+) -> Option<SubgraphFeature> {
+    manifest.features.contains(&feature).then(|| feature)
+}
+
+/// Return `true` if the manifest declares `noEthCalls`, but one of its
+/// mappings imports `ethereum.call` anyway. Such a subgraph will fail as
+/// soon as the mapping actually makes a call
+pub fn imports_disabled_eth_call<C: Blockchain>(
+    manifest: &SubgraphManifest<C>,
+) -> Result<bool, InvalidMapping> {
+    if !manifest.features.contains(&SubgraphFeature::NoEthCalls) {
+        return Ok(false);
+    }
+    for runtime in manifest.runtimes() {
+        if calls_host_fn(runtime, "ethereum.call").map_err(|_| InvalidMapping)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 fn detect_grafting<C: Blockchain>(manifest: &SubgraphManifest<C>) -> Option<SubgraphFeature> {
     manifest.graft.as_ref().map(|_| SubgraphFeature::Grafting)
 }
@@ -121,6 +208,16 @@ fn detect_full_text_search(schema: &Schema) -> Option<SubgraphFeature> {
     }
 }
 
+fn detect_composite_indexes(schema: &Schema) -> Option<SubgraphFeature> {
+    schema
+        .document
+        .get_object_type_definitions()
+        .into_iter()
+        .flat_map(|object_type| object_type.directives.iter())
+        .any(|directive| directive.name == "index")
+        .then(|| SubgraphFeature::CompositeIndexes)
+}
+
 pub struct InvalidMapping;
 
 impl From<InvalidMapping> for SubgraphFeatureValidationError {
@@ -146,17 +243,29 @@ fn detect_ipfs_on_ethereum_contracts<C: Blockchain>(
 mod tests {
     use super::*;
     use SubgraphFeature::*;
-    const VARIANTS: [SubgraphFeature; 4] = [
+    const VARIANTS: [SubgraphFeature; 10] = [
         NonFatalErrors,
         Grafting,
         FullTextSearch,
         IpfsOnEthereumContracts,
+        CompositeIndexes,
+        NoEthCalls,
+        StrictStrings,
+        SkipHeavyBlocks,
+        FailHeavyBlocks,
+        BlockInfo,
     ];
-    const STRING: [&'static str; 4] = [
+    const STRING: [&'static str; 10] = [
         "nonFatalErrors",
         "grafting",
         "fullTextSearch",
         "ipfsOnEthereumContracts",
+        "compositeIndexes",
+        "noEthCalls",
+        "strictStrings",
+        "skipHeavyBlocks",
+        "failHeavyBlocks",
+        "blockInfo",
     ];
 
     #[test]
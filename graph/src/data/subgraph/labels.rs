@@ -0,0 +1,172 @@
+//! Labels that operators attach to deployments to organize them, like
+//! `team: defi` or `tier: gold`. Labels are stored in the primary by
+//! deployment hash and can be used to filter indexing statuses and in the
+//! placement rules of the configuration file. They mean nothing to the
+//! node itself. Keys and values are restricted to a small set of
+//! characters so that they can be written as `key:value` in filters
+
+use crate::data::graphql::{object, IntoValue};
+use crate::prelude::q;
+use std::collections::BTreeMap;
+
+/// The labels of a deployment, keyed by label key
+pub type Labels = BTreeMap<String, String>;
+
+pub const MAX_LABELS: usize = 32;
+pub const MAX_LABEL_KEY_LENGTH: usize = 63;
+pub const MAX_LABEL_VALUE_LENGTH: usize = 255;
+
+/// Keys start with a lowercase letter and consist of lowercase letters,
+/// digits, `-`, `_`, `.` and `/`
+fn validate_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > MAX_LABEL_KEY_LENGTH {
+        return Err(format!(
+            "label key `{}` must have between 1 and {} characters",
+            key, MAX_LABEL_KEY_LENGTH
+        ));
+    }
+    let valid = key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key.chars().all(|c| {
+            c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.' | '/')
+        });
+    if !valid {
+        return Err(format!(
+            "label key `{}` must start with a lowercase letter and only contain \
+             lowercase letters, digits, `-`, `_`, `.` and `/`",
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Values consist of letters, digits, `-`, `_`, `.`, `/` and `:`
+fn validate_value(key: &str, value: &str) -> Result<(), String> {
+    if value.is_empty() || value.len() > MAX_LABEL_VALUE_LENGTH {
+        return Err(format!(
+            "the value of label `{}` must have between 1 and {} characters",
+            key, MAX_LABEL_VALUE_LENGTH
+        ));
+    }
+    let valid = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/' | ':'));
+    if !valid {
+        return Err(format!(
+            "the value `{}` of label `{}` must only contain letters, digits, \
+             `-`, `_`, `.`, `/` and `:`",
+            value, key
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `labels` can be stored for a deployment
+pub fn validate_labels(labels: &Labels) -> Result<(), String> {
+    if labels.len() > MAX_LABELS {
+        return Err(format!(
+            "a deployment can have at most {} labels but {} were given",
+            MAX_LABELS,
+            labels.len()
+        ));
+    }
+    for (key, value) in labels {
+        validate_key(key)?;
+        validate_value(key, value)?;
+    }
+    Ok(())
+}
+
+/// A filter on labels, written as `key` to select deployments that have
+/// the label `key`, or as `key:value` to select deployments where the
+/// label `key` has the value `value`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabelSelector {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl LabelSelector {
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        let (key, value) = match selector.find(':') {
+            Some(pos) => (&selector[..pos], Some(&selector[pos + 1..])),
+            None => (selector, None),
+        };
+        validate_key(key)?;
+        if let Some(value) = value {
+            validate_value(key, value)?;
+        }
+        Ok(LabelSelector {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+        })
+    }
+
+    pub fn matches(&self, labels: &Labels) -> bool {
+        match (labels.get(&self.key), &self.value) {
+            (Some(actual), Some(value)) => actual == value,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl std::fmt::Display for LabelSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}:{}", self.key, value),
+            None => write!(f, "{}", self.key),
+        }
+    }
+}
+
+/// Labels as a list of `Label` objects for the index node API
+pub fn labels_into_value(labels: Labels) -> q::Value {
+    q::Value::List(
+        labels
+            .into_iter()
+            .map(|(key, value)| {
+                object! {
+                    __typename: "Label",
+                    key: key,
+                    value: value,
+                }
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn validates_labels() {
+    let labels = |pairs: &[(&str, &str)]| -> Labels {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+
+    assert!(validate_labels(&labels(&[("team", "defi"), ("tier", "gold")])).is_ok());
+    assert!(validate_labels(&labels(&[("example.com/owner", "ops:oncall")])).is_ok());
+    assert!(validate_labels(&labels(&[("Team", "defi")])).is_err());
+    assert!(validate_labels(&labels(&[("1team", "defi")])).is_err());
+    assert!(validate_labels(&labels(&[("team:a", "defi")])).is_err());
+    assert!(validate_labels(&labels(&[("team", "")])).is_err());
+    assert!(validate_labels(&labels(&[("team", "de fi")])).is_err());
+    let long = "x".repeat(MAX_LABEL_VALUE_LENGTH + 1);
+    assert!(validate_labels(&labels(&[("team", &long)])).is_err());
+    let many: Labels = (0..=MAX_LABELS)
+        .map(|i| (format!("k{}", i), "v".to_string()))
+        .collect();
+    assert!(validate_labels(&many).is_err());
+
+    let selector = LabelSelector::parse("tier:gold").unwrap();
+    assert!(selector.matches(&labels(&[("tier", "gold")])));
+    assert!(!selector.matches(&labels(&[("tier", "silver")])));
+    let selector = LabelSelector::parse("team").unwrap();
+    assert!(selector.matches(&labels(&[("team", "defi")])));
+    assert!(!selector.matches(&labels(&[("tier", "gold")])));
+    assert_eq!(
+        "owner:a:b",
+        LabelSelector::parse("owner:a:b").unwrap().to_string()
+    );
+    assert!(LabelSelector::parse("Team:defi").is_err());
+}
@@ -14,6 +14,8 @@ use thiserror::Error;
 use wasmparser;
 use web3::types::Address;
 
+use crate::components::ethereum::{check_checksum, parse_address_unchecked, AddressError};
+use crate::data::graphql::ext::DocumentExt;
 use crate::data::store::Entity;
 use crate::data::{
     schema::{Schema, SchemaImportError, SchemaValidationError},
@@ -34,13 +36,22 @@ use crate::prelude::{impl_slog_value, q, BlockNumber, Deserialize, Serialize};
 
 use std::fmt;
 use std::ops::Deref;
-use std::str::FromStr;
 use std::sync::Arc;
 
 /// This version adds a new subgraph validation step that rejects manifests whose mappings have
 /// different API versions if at least one of them is equal to or higher than `0.0.5`.
 pub const API_VERSION_0_0_5: Version = Version::new(0, 0, 5);
 
+/// This version normalizes `BigDecimal` values when mappings store them, so
+/// that stored values never have trailing zeros or more than
+/// `BigDecimal::MAX_SIGNFICANT_DIGITS` significant digits, no matter how
+/// they were produced. It also adds the host exports `crypto.sha256`,
+/// `crypto.ripemd160`, `bigDecimal.pow`, `bigDecimal.truncate` and
+/// `store.changedEntities`, makes bitwise `BigInt` operations other than
+/// left shifts fail for negative numbers, and passes the uncles of a block
+/// to block handlers.
+pub const API_VERSION_0_0_6: Version = Version::new(0, 0, 6);
+
 /// Before this check was introduced, there were already subgraphs in the wild with spec version
 /// 0.0.3, due to confusion with the api version. To avoid breaking those, we accept 0.0.3 though it
 /// doesn't exist. In the future we should not use 0.0.3 as version and skip to 0.0.4 to avoid
@@ -61,21 +72,58 @@ lazy_static! {
         .ok()
         .and_then(|api_version_str| Version::parse(&api_version_str).ok())
         .unwrap_or(SPEC_VERSION_0_0_3);
-    static ref MAX_API_VERSION: semver::Version = std::env::var("GRAPH_MAX_API_VERSION")
+    pub static ref MAX_API_VERSION: semver::Version = std::env::var("GRAPH_MAX_API_VERSION")
         .ok()
         .and_then(|api_version_str| semver::Version::parse(&api_version_str).ok())
         .unwrap_or(semver::Version::new(0, 0, 5));
+
+    /// The most data sources without an address that a subgraph may have.
+    /// Each of them makes us scan the whole chain for its events
+    static ref MAX_WILDCARD_DATA_SOURCES: usize =
+        std::env::var("GRAPH_MAX_WILDCARD_DATA_SOURCES")
+            .ok()
+            .map(|s| {
+                s.parse::<usize>().unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_MAX_WILDCARD_DATA_SOURCES must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(10);
+
+    /// The most triggers a block may have for a deployment before we
+    /// consider it too heavy, unless the manifest sets `maxTriggersPerBlock`
+    static ref MAX_TRIGGERS_PER_BLOCK: usize =
+        std::env::var("GRAPH_MAX_TRIGGERS_PER_BLOCK")
+            .ok()
+            .map(|s| {
+                s.parse::<usize>().unwrap_or_else(|_| {
+                    panic!(
+                        "GRAPH_MAX_TRIGGERS_PER_BLOCK must be a number, but is `{}`",
+                        s
+                    )
+                })
+            })
+            .unwrap_or(100_000);
 }
 
 /// Rust representation of the GraphQL schema for a `SubgraphManifest`.
 pub mod schema;
 
 pub mod features;
+pub mod labels;
+pub mod profile;
 pub mod status;
 
 pub use features::{SubgraphFeature, SubgraphFeatureValidationError};
+pub use labels::{LabelSelector, Labels};
+pub use profile::{DeploymentProfile, ScanTotals};
 
-/// Deserialize an Address (with or without '0x' prefix).
+/// Deserialize an Address (with or without '0x' prefix). EIP-55
+/// checksums are only checked when a subgraph is deployed, see
+/// `address_checksum_errors`, so that manifests of existing deployments
+/// keep parsing.
 fn deserialize_address<'de, D>(deserializer: D) -> Result<Option<Address>, D::Error>
 where
     D: de::Deserializer<'de>,
@@ -83,12 +131,30 @@ where
     use serde::de::Error;
 
     let s: String = de::Deserialize::deserialize(deserializer)?;
-    let address = s.trim_start_matches("0x");
-    Address::from_str(address)
+    parse_address_unchecked(&s)
         .map_err(D::Error::custom)
         .map(Some)
 }
 
+/// Check the EIP-55 checksums of the `source.address` of all data sources
+/// in the `raw` manifest
+fn address_checksum_errors(raw: &serde_yaml::Mapping) -> Vec<AddressError> {
+    use serde_yaml::Value;
+
+    let data_sources = match raw.get(&Value::from("dataSources")) {
+        Some(Value::Sequence(data_sources)) => data_sources,
+        _ => return vec![],
+    };
+    data_sources
+        .iter()
+        .filter_map(|ds| ds.get("source")?.get("address")?.as_str())
+        .filter_map(|s| {
+            let address = parse_address_unchecked(s).ok()?;
+            check_checksum(s, &address).err()
+        })
+        .collect()
+}
+
 // Note: This has a StableHash impl. Do not modify fields without a backward
 // compatible change to the StableHash impl (below)
 /// The IPFS hash used to identifiy a deployment externally, i.e., the
@@ -291,6 +357,10 @@ pub enum SubgraphRegistrarError {
     ManifestValidationError(Vec<SubgraphManifestValidationError>),
     #[error("subgraph deployment error: {0}")]
     SubgraphDeploymentError(StoreError),
+    #[error("subgraph mappings can not be reloaded: {0}")]
+    ReloadError(String),
+    #[error("invalid deployment labels: {0}")]
+    InvalidLabels(String),
     #[error("subgraph registrar error: {0}")]
     Unknown(anyhow::Error),
 }
@@ -365,6 +435,28 @@ pub enum SubgraphManifestValidationError {
     EthereumNetworkRequired,
     #[error("subgraph data source has too many similar block handlers")]
     DataSourceBlockHandlerLimitExceeded,
+    #[error("block handler `{0}` has a `once` filter and can not also be used with other filters")]
+    DataSourceBlockHandlerOnceFilterConflict(String),
+    #[error("block handler `{0}` has a `polling` filter that runs every {1} blocks, but it must run at least every block")]
+    DataSourceBlockHandlerPollingInterval(String, BlockNumber),
+    #[error("event handler `{0}` asks for the transaction receipt, which requires apiVersion 0.0.6 or later")]
+    DataSourceEventHandlerReceiptApiVersion(String),
+    #[error("event handler `{0}` filters on topic{1}, but only indexed parameters are topics and event `{2}` has {3} indexed parameters")]
+    DataSourceEventHandlerTopicNotIndexed(String, usize, String, usize),
+    #[error("event handler `{0}` filters on topic{1} without listing any values for it")]
+    DataSourceEventHandlerTopicEmpty(String, usize),
+    #[error("event handler `{0}` {}", format_anonymous_mismatch(.1, *.2))]
+    DataSourceEventHandlerAnonymousMismatch(String, String, bool),
+    #[error("event handler `{0}` handles an anonymous event, which requires the data source to have an address")]
+    DataSourceEventHandlerAnonymousWithoutAddress(String),
+    #[error("event handler `{0}` handles an anonymous event and can not filter on topics")]
+    DataSourceEventHandlerAnonymousTopicFilter(String),
+    #[error("data source `{0}` has end block {2} before its start block {1}")]
+    DataSourceEndBlockBeforeStartBlock(String, BlockNumber, BlockNumber),
+    #[error("data source `{0}` handles event `{1}`, but no event in its ABI has that signature{}", format_near_misses(.2))]
+    DataSourceEventNotFound(String, String, Vec<String>),
+    #[error("data source `{0}` handles event `{1}`, which matches several events in its ABI: {}. Mark parameters as `indexed` in the signature to select one of them", .2.join(", "))]
+    DataSourceEventAmbiguous(String, String, Vec<String>),
     #[error("the specified block must exist on the Ethereum network")]
     BlockNotFound(String),
     #[error("imported schema(s) are invalid: {0:?}")]
@@ -375,6 +467,18 @@ pub enum SubgraphManifestValidationError {
     GraftBaseInvalid(String),
     #[error("subgraph must use a single apiVersion across its data sources. Found: {}", format_versions(.0))]
     DifferentApiVersions(BTreeSet<Version>),
+    #[error("subgraph has {0} data sources without an address, but at most {1} are allowed since each of them scans the events of all contracts")]
+    TooManyWildcardDataSources(usize, usize),
+    #[error("mapping `{0}` imports host functions that this node does not provide: {}", .1.join(", "))]
+    DataSourceMissingHostExports(String, Vec<String>),
+    #[error("{0}")]
+    SourceAddressChecksum(AddressError),
+    #[error("feature `{0}` requires `maxTriggersPerBlock` in the manifest so that every indexer treats the same blocks as too heavy")]
+    HeavyBlockPolicyWithoutLimit(SubgraphFeature),
+    #[error("features `skipHeavyBlocks` and `failHeavyBlocks` can not both be declared")]
+    ConflictingHeavyBlockPolicies,
+    #[error("`maxTriggersPerBlock` must be at least 1")]
+    MaxTriggersPerBlockZero,
     #[error(transparent)]
     FeatureValidationError(#[from] SubgraphFeatureValidationError),
 }
@@ -442,6 +546,10 @@ pub struct Source {
     pub abi: String,
     #[serde(rename = "startBlock", default)]
     pub start_block: BlockNumber,
+    /// The last block for which the data source runs handlers; it runs
+    /// forever if this is not set
+    #[serde(rename = "endBlock", default)]
+    pub end_block: Option<BlockNumber>,
 }
 
 pub fn calls_host_fn(runtime: &[u8], host_fn: &str) -> anyhow::Result<bool> {
@@ -461,6 +569,24 @@ pub fn calls_host_fn(runtime: &[u8], host_fn: &str) -> anyhow::Result<bool> {
     Ok(false)
 }
 
+/// The names of everything that the WASM module `runtime` imports, which
+/// the host needs to provide when it instantiates the module
+pub fn imported_host_fns(runtime: &[u8]) -> anyhow::Result<BTreeSet<String>> {
+    use wasmparser::Payload;
+
+    let mut names = BTreeSet::new();
+    for payload in wasmparser::Parser::new(0).parse_all(runtime) {
+        if let Payload::ImportSection(s) = payload? {
+            for import in s {
+                let import = import?;
+                names.insert(import.field.unwrap_or(import.module).to_string());
+            }
+        }
+    }
+
+    Ok(names)
+}
+
 #[derive(Clone, PartialEq, Debug)]
 pub struct UnifiedMappingApiVersion(Option<Version>);
 
@@ -502,6 +628,28 @@ fn format_versions(versions: &BTreeSet<Version>) -> String {
     versions.iter().map(ToString::to_string).join(", ")
 }
 
+fn format_near_misses(signatures: &[String]) -> String {
+    if signatures.is_empty() {
+        String::new()
+    } else {
+        format!(". Events with the same name: {}", signatures.join(", "))
+    }
+}
+
+fn format_anonymous_mismatch(event: &str, anonymous_in_abi: bool) -> String {
+    if anonymous_in_abi {
+        format!(
+            "handles event `{}`, which is anonymous in the ABI, and must be marked `anonymous`",
+            event
+        )
+    } else {
+        format!(
+            "is marked `anonymous`, but event `{}` is not anonymous in the ABI",
+            event
+        )
+    }
+}
+
 impl From<BTreeSet<Version>> for DifferentMappingApiVersions {
     fn from(versions: BTreeSet<Version>) -> Self {
         Self(versions)
@@ -553,6 +701,21 @@ impl Graft {
     }
 }
 
+/// What a deployment does with blocks that have more triggers than its
+/// `max_triggers_per_block`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeavyBlockPolicy {
+    /// Process the block anyway and log a warning. Since the limit may
+    /// differ between indexers, this is the only policy that does not
+    /// need a limit in the manifest
+    Warn,
+    /// Run no handlers for the block and record that in the proof of
+    /// indexing
+    Skip,
+    /// Fail the subgraph deterministically
+    Fail,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BaseSubgraphManifest<C, S, D, T> {
@@ -567,6 +730,9 @@ pub struct BaseSubgraphManifest<C, S, D, T> {
     pub graft: Option<Graft>,
     #[serde(default)]
     pub templates: Vec<T>,
+    /// The most triggers a block may have before it is too heavy for the
+    /// subgraph, overriding `GRAPH_MAX_TRIGGERS_PER_BLOCK`
+    pub max_triggers_per_block: Option<usize>,
     #[serde(skip_serializing, default)]
     pub chain: PhantomData<C>,
 }
@@ -587,8 +753,9 @@ pub type SubgraphManifest<C> = BaseSubgraphManifest<
     <C as Blockchain>::DataSourceTemplate,
 >;
 
-/// Unvalidated SubgraphManifest
-pub struct UnvalidatedSubgraphManifest<C: Blockchain>(SubgraphManifest<C>);
+/// Unvalidated SubgraphManifest, together with the checksum errors in the
+/// addresses of its data sources
+pub struct UnvalidatedSubgraphManifest<C: Blockchain>(SubgraphManifest<C>, Vec<AddressError>);
 
 impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
     /// Entry point for resolving a subgraph definition.
@@ -601,9 +768,11 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
         logger: &Logger,
         max_spec_version: semver::Version,
     ) -> Result<Self, SubgraphManifestResolveError> {
+        let checksum_errors = address_checksum_errors(&raw);
         Ok(Self(
             SubgraphManifest::resolve_from_raw(id, raw, resolver.deref(), logger, max_spec_version)
                 .await?,
+            checksum_errors,
         ))
     }
 
@@ -628,6 +797,14 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
             errors.extend(ds.validate());
         }
 
+        // A mixed-case address with a bad checksum is most likely a typo
+        errors.extend(
+            self.1
+                .iter()
+                .cloned()
+                .map(SubgraphManifestValidationError::SourceAddressChecksum),
+        );
+
         // For API versions newer than 0.0.5, validate that all mappings uses the same api_version
         if let Err(different_api_versions) = self.0.unified_mapping_api_version() {
             errors.push(different_api_versions.into());
@@ -642,6 +819,14 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
             .collect::<Vec<String>>();
         networks.sort();
         networks.dedup();
+
+        let wildcards = self.0.wildcard_data_sources().count();
+        if wildcards > *MAX_WILDCARD_DATA_SOURCES {
+            errors.push(SubgraphManifestValidationError::TooManyWildcardDataSources(
+                wildcards,
+                *MAX_WILDCARD_DATA_SOURCES,
+            ));
+        }
         match networks.len() {
             0 => errors.push(SubgraphManifestValidationError::EthereumNetworkRequired),
             1 => (),
@@ -670,6 +855,8 @@ impl<C: Blockchain> UnvalidatedSubgraphManifest<C> {
             }
         }
 
+        errors.extend(self.0.validate_heavy_block_policy());
+
         // Validate subgraph feature usage and declaration.
         if self.0.spec_version >= SPEC_VERSION_0_0_4 {
             if let Err(feature_validation_error) = validate_subgraph_features(&self.0) {
@@ -724,6 +911,16 @@ impl<C: Blockchain> SubgraphManifest<C> {
             .expect("Validated manifest does not have a network defined on any datasource")
     }
 
+    /// The first block a deployment of this manifest processes: the block
+    /// after the graft block for grafted deployments, and the lowest start
+    /// block of its data sources otherwise
+    pub fn first_block(&self) -> BlockNumber {
+        match &self.graft {
+            Some(graft) => graft.block + 1,
+            None => self.start_blocks().into_iter().min().unwrap_or(0),
+        }
+    }
+
     pub fn start_blocks(&self) -> Vec<BlockNumber> {
         self.data_sources
             .iter()
@@ -731,6 +928,22 @@ impl<C: Blockchain> SubgraphManifest<C> {
             .collect()
     }
 
+    /// The block after which the deployment has nothing left to do, which
+    /// is the highest end block of the data sources in the manifest if all
+    /// of them have one. Data sources created from templates stop
+    /// together with the deployment
+    pub fn end_block(&self) -> Option<BlockNumber> {
+        let manifest_data_sources = self
+            .data_sources
+            .iter()
+            .filter(|data_source| data_source.creation_block().is_none());
+        let mut end_block = None;
+        for data_source in manifest_data_sources {
+            end_block = end_block.max(Some(data_source.end_block()?));
+        }
+        end_block
+    }
+
     pub fn api_versions(&self) -> impl Iterator<Item = semver::Version> + '_ {
         self.templates
             .iter()
@@ -742,6 +955,60 @@ impl<C: Blockchain> SubgraphManifest<C> {
             )
     }
 
+    /// Ethereum data sources without an address, which handle events
+    /// from all contracts
+    pub fn wildcard_data_sources(&self) -> impl Iterator<Item = &C::DataSource> + '_ {
+        self.data_sources
+            .iter()
+            .filter(|ds| ds.kind() == "ethereum/contract" && ds.address().is_none())
+    }
+
+    /// The most triggers a block may have before it is too heavy
+    pub fn max_triggers_per_block(&self) -> usize {
+        self.max_triggers_per_block
+            .unwrap_or(*MAX_TRIGGERS_PER_BLOCK)
+    }
+
+    /// What to do with blocks that have more than
+    /// `max_triggers_per_block` triggers
+    pub fn heavy_block_policy(&self) -> HeavyBlockPolicy {
+        if self.features.contains(&SubgraphFeature::FailHeavyBlocks) {
+            HeavyBlockPolicy::Fail
+        } else if self.features.contains(&SubgraphFeature::SkipHeavyBlocks) {
+            HeavyBlockPolicy::Skip
+        } else {
+            HeavyBlockPolicy::Warn
+        }
+    }
+
+    /// Skipping or failing on heavy blocks changes what the subgraph
+    /// indexes, which is only deterministic if all indexers use the same
+    /// limit, and that has to come from the manifest
+    fn validate_heavy_block_policy(&self) -> Vec<SubgraphManifestValidationError> {
+        let mut errors = vec![];
+        let skip = self.features.contains(&SubgraphFeature::SkipHeavyBlocks);
+        let fail = self.features.contains(&SubgraphFeature::FailHeavyBlocks);
+        if skip && fail {
+            errors.push(SubgraphManifestValidationError::ConflictingHeavyBlockPolicies);
+        }
+        if self.max_triggers_per_block.is_none() {
+            for (declared, feature) in &[
+                (skip, SubgraphFeature::SkipHeavyBlocks),
+                (fail, SubgraphFeature::FailHeavyBlocks),
+            ] {
+                if *declared {
+                    errors.push(
+                        SubgraphManifestValidationError::HeavyBlockPolicyWithoutLimit(*feature),
+                    );
+                }
+            }
+        }
+        if self.max_triggers_per_block == Some(0) {
+            errors.push(SubgraphManifestValidationError::MaxTriggersPerBlockZero);
+        }
+        errors
+    }
+
     pub fn runtimes(&self) -> impl Iterator<Item = &[u8]> + '_ {
         self.templates
             .iter()
@@ -773,6 +1040,7 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            max_triggers_per_block,
             chain,
         } = self;
 
@@ -801,6 +1069,12 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
         )
         .await?;
 
+        let schema = if features.contains(&SubgraphFeature::BlockInfo) {
+            with_block_info(schema)?
+        } else {
+            schema
+        };
+
         for ds in &data_sources {
             ensure!(
                 semver::VersionReq::parse(&format!("<= {}", *MAX_API_VERSION))
@@ -822,11 +1096,23 @@ impl<C: Blockchain> UnresolvedSubgraphManifest<C> {
             data_sources,
             graft,
             templates,
+            max_triggers_per_block,
             chain,
         })
     }
 }
 
+/// Add the `BlockInfo` type to `schema`. The type belongs to the node, and
+/// subgraphs can not define a type with that name themselves
+pub fn with_block_info(schema: Schema) -> Result<Schema, anyhow::Error> {
+    ensure!(
+        schema.document.get_named_type("BlockInfo").is_none(),
+        "subgraphs with the `blockInfo` feature can not define a type named `BlockInfo`"
+    );
+    let document = format!("{}\n{}", schema.document, schema::BLOCK_INFO_SCHEMA);
+    Schema::parse(&document, schema.id)
+}
+
 /// Important details about the current state of a subgraph deployment
 /// used while executing queries against a deployment
 ///
@@ -845,6 +1131,8 @@ pub struct DeploymentState {
     pub max_reorg_depth: u32,
     /// The number of the last block that the subgraph has processed
     pub latest_ethereum_block_number: BlockNumber,
+    /// Whether the subgraph has failed and stopped processing blocks
+    pub failed: bool,
 }
 
 impl DeploymentState {
@@ -945,3 +1233,35 @@ fn test_display_vector() {
         format!("{}", manifest_validation_error)
     )
 }
+
+#[test]
+fn source_address_checksum() {
+    fn source(address: &str) -> Result<Source, serde_yaml::Error> {
+        serde_yaml::from_str(&format!("address: '{}'\nabi: Token\n", address))
+    }
+
+    let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    let address = source(checksummed).unwrap().address;
+    assert_eq!(
+        address,
+        source(&checksummed.to_lowercase()).unwrap().address
+    );
+    assert_eq!(address, source(&checksummed[2..]).unwrap().address);
+
+    // Manifests of existing deployments must keep parsing; bad checksums
+    // are only rejected when a subgraph is deployed
+    let typo = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD";
+    assert_eq!(address, source(typo).unwrap().address);
+
+    let raw: serde_yaml::Mapping = serde_yaml::from_str(&format!(
+        "dataSources:\n  - source:\n      address: '{}'\n  - source:\n      address: '{}'\n",
+        checksummed, typo
+    ))
+    .unwrap();
+    let errors = address_checksum_errors(&raw);
+    assert_eq!(1, errors.len());
+    assert_eq!(
+        "invalid checksum for address `0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD` (did you mean `0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed`?)",
+        errors[0].to_string()
+    );
+}
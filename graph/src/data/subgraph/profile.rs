@@ -0,0 +1,154 @@
+//! Static facts about a deployment that can be derived from its manifest
+//! alone, for example, to plan capacity. Deployments with templates can
+//! create data sources while they index and can therefore grow without
+//! bounds, while deployments without them always watch the same contracts.
+//! The profile also reports how much data the deployment has requested
+//! from its chain so far, which tells us what syncing it again would cost.
+
+use crate::{
+    blockchain::{Blockchain, DataSource, DataSourceTemplate},
+    components::store::BlockNumber,
+    data::graphql::{object, IntoValue},
+    data::store::scalar::Timestamp,
+    data::subgraph::SubgraphManifest,
+    prelude::{q, Deserialize, Serialize},
+};
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeploymentProfile {
+    /// The number of data sources declared in the manifest
+    pub static_data_sources: usize,
+    /// The number of templates declared in the manifest
+    pub templates: usize,
+    /// The kinds of handlers used by data sources and templates, like
+    /// `event`, `call`, `block` or `block/once`
+    pub handler_kinds: BTreeSet<String>,
+    /// The features declared in the manifest
+    pub features: BTreeSet<String>,
+    /// The lowest start block of the data sources in the manifest
+    pub earliest_start_block: Option<BlockNumber>,
+    /// What the deployment has requested from its chain. This is not part
+    /// of what is stored with the manifest; the store fills it in
+    #[serde(skip)]
+    pub scanned: ScanTotals,
+}
+
+impl DeploymentProfile {
+    pub fn from_manifest<C: Blockchain>(manifest: &SubgraphManifest<C>) -> Self {
+        let static_data_sources: Vec<_> = manifest
+            .data_sources
+            .iter()
+            .filter(|data_source| data_source.creation_block().is_none())
+            .collect();
+
+        let handler_kinds = static_data_sources
+            .iter()
+            .flat_map(|data_source| data_source.handler_kinds())
+            .chain(
+                manifest
+                    .templates
+                    .iter()
+                    .flat_map(|template| template.handler_kinds()),
+            )
+            .map(|kind| kind.to_string())
+            .collect();
+
+        DeploymentProfile {
+            static_data_sources: static_data_sources.len(),
+            templates: manifest.templates.len(),
+            handler_kinds,
+            features: manifest.features.iter().map(|f| f.to_string()).collect(),
+            earliest_start_block: static_data_sources
+                .iter()
+                .map(|data_source| data_source.start_block())
+                .min(),
+            scanned: ScanTotals::default(),
+        }
+    }
+
+    /// Whether the deployment can create data sources while it indexes
+    pub fn has_templates(&self) -> bool {
+        self.templates > 0
+    }
+}
+
+impl IntoValue for DeploymentProfile {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "DeploymentProfile",
+            staticDataSources: self.static_data_sources as i32,
+            templates: self.templates as i32,
+            hasTemplates: self.has_templates(),
+            handlerKinds: self.handler_kinds.into_iter().collect::<Vec<_>>(),
+            features: self.features.into_iter().collect::<Vec<_>>(),
+            earliestStartBlock: self.earliest_start_block,
+            scanned: self.scanned,
+        }
+    }
+}
+
+/// How much data a deployment has requested from its chain since it was
+/// deployed, or since an operator last reset the totals. Syncing the
+/// deployment again from scratch requests all of it again
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanTotals {
+    /// The number of blocks that were scanned for triggers
+    pub blocks: u64,
+    /// The number of logs that were fetched
+    pub logs: u64,
+    /// The number of traces that were fetched
+    pub traces: u64,
+    /// The number of `ethereum.call`s that mappings made, including those
+    /// that were answered from the call cache, since a node that syncs
+    /// the deployment again can not count on having them cached
+    pub eth_calls: u64,
+    /// The size of the responses with logs and traces
+    pub bytes: u64,
+    /// When an operator last reset the totals
+    pub reset_at: Option<Timestamp>,
+}
+
+/// What each part of a scan adds to `ScanTotals::estimated_resync_cost`.
+/// The weights roughly follow the compute units that hosted providers
+/// charge for the requests involved; logs and traces are fetched in bulk
+/// and mostly cost through the size of the responses
+const BLOCK_COST: u64 = 16;
+const ETH_CALL_COST: u64 = 26;
+const KILOBYTE_COST: u64 = 1;
+
+impl ScanTotals {
+    pub fn is_empty(&self) -> bool {
+        self.blocks == 0
+            && self.logs == 0
+            && self.traces == 0
+            && self.eth_calls == 0
+            && self.bytes == 0
+    }
+
+    /// A rough estimate of the RPC cost of syncing the deployment again
+    /// from scratch, in compute units. It is only meant to compare
+    /// deployments with each other, not to predict a bill
+    pub fn estimated_resync_cost(&self) -> u64 {
+        self.blocks * BLOCK_COST
+            + self.eth_calls * ETH_CALL_COST
+            + self.bytes / 1024 * KILOBYTE_COST
+    }
+}
+
+impl IntoValue for ScanTotals {
+    fn into_value(self) -> q::Value {
+        let estimated_resync_cost = self.estimated_resync_cost();
+        object! {
+            __typename: "ScanTotals",
+            blocks: self.blocks,
+            logs: self.logs,
+            traces: self.traces,
+            ethCalls: self.eth_calls,
+            bytes: self.bytes,
+            estimatedResyncCost: estimated_resync_cost,
+            resetAt: self.reset_at.map(|reset_at| reset_at.to_string()),
+        }
+    }
+}
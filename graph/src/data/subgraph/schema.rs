@@ -14,11 +14,54 @@ use crate::data::graphql::TryFromValue;
 use crate::data::store::Value;
 use crate::data::subgraph::SubgraphManifest;
 use crate::prelude::*;
-use crate::{blockchain::Blockchain, components::store::EntityType};
+use crate::{
+    blockchain::{Block, Blockchain},
+    components::store::EntityType,
+    data::store::scalar,
+};
 
 pub const POI_TABLE: &str = "poi2$";
 lazy_static! {
     pub static ref POI_OBJECT: EntityType = EntityType::new("Poi$".to_string());
+    /// The entity type that the node writes for every block that a
+    /// subgraph with the `blockInfo` feature processes
+    pub static ref BLOCK_INFO_OBJECT: EntityType = EntityType::new("BlockInfo".to_string());
+}
+
+/// The definition of `BlockInfo` that is added to the schema of subgraphs
+/// with the `blockInfo` feature. The `id` is the block hash
+pub const BLOCK_INFO_SCHEMA: &str = "
+type BlockInfo @entity {
+  id: ID!
+  number: BigInt!
+  hash: Bytes!
+  parentHash: Bytes
+  timestamp: BigInt
+}
+";
+
+/// The `BlockInfo` entity for `block`
+pub fn block_info<B: Block>(block: &B) -> Entity {
+    let hash = block.hash();
+    let mut entity = Entity::new();
+    entity.set("id", hash.to_string());
+    entity.set("number", BigInt::from(block.number()));
+    entity.set("hash", scalar::Bytes::from(hash));
+    entity.set(
+        "parentHash",
+        block
+            .parent_hash()
+            .map(|hash| Value::Bytes(scalar::Bytes::from(hash)))
+            .unwrap_or(Value::Null),
+    );
+    entity.set(
+        "timestamp",
+        block
+            .timestamp()
+            .map(|timestamp| Value::BigInt(BigInt::from(timestamp)))
+            .unwrap_or(Value::Null),
+    );
+    entity
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -31,6 +74,10 @@ pub enum SubgraphHealth {
 
     /// No longer syncing due to fatal error.
     Failed,
+
+    /// No longer syncing because it has processed all blocks up to its end
+    /// block; it still serves queries.
+    Completed,
 }
 
 impl SubgraphHealth {
@@ -39,6 +86,7 @@ impl SubgraphHealth {
             SubgraphHealth::Healthy => "healthy",
             SubgraphHealth::Unhealthy => "unhealthy",
             SubgraphHealth::Failed => "failed",
+            SubgraphHealth::Completed => "completed",
         }
     }
 
@@ -47,6 +95,7 @@ impl SubgraphHealth {
             SubgraphHealth::Healthy => false,
             SubgraphHealth::Unhealthy => false,
             SubgraphHealth::Failed => true,
+            SubgraphHealth::Completed => false,
         }
     }
 }
@@ -59,6 +108,7 @@ impl FromStr for SubgraphHealth {
             "healthy" => Ok(SubgraphHealth::Healthy),
             "unhealthy" => Ok(SubgraphHealth::Unhealthy),
             "failed" => Ok(SubgraphHealth::Failed),
+            "completed" => Ok(SubgraphHealth::Completed),
             _ => Err(anyhow!("failed to parse `{}` as SubgraphHealth", s)),
         }
     }
@@ -153,6 +203,12 @@ pub struct SubgraphManifestEntity {
     pub repository: Option<String>,
     pub features: Vec<String>,
     pub schema: String,
+    /// Deployments created before profiles were recorded have none
+    pub profile: Option<super::DeploymentProfile>,
+    /// The most bytes that passing one trigger to a handler may allocate
+    /// on the WASM heap. Deployments created before the budget was
+    /// recorded have none
+    pub max_trigger_bytes: Option<usize>,
 }
 
 impl<'a, C: Blockchain> From<&'a super::SubgraphManifest<C>> for SubgraphManifestEntity {
@@ -163,6 +219,8 @@ impl<'a, C: Blockchain> From<&'a super::SubgraphManifest<C>> for SubgraphManifes
             repository: manifest.repository.clone(),
             features: manifest.features.iter().map(|f| f.to_string()).collect(),
             schema: manifest.schema.document.clone().to_string(),
+            profile: Some(super::DeploymentProfile::from_manifest(manifest)),
+            max_trigger_bytes: Some(*crate::runtime::MAX_TRIGGER_BYTES),
         }
     }
 }
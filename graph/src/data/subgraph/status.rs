@@ -1,9 +1,14 @@
 //! Support for the indexing status API
 
+use super::labels::{labels_into_value, LabelSelector, Labels};
 use super::schema::{SubgraphError, SubgraphHealth};
 use crate::components::store::DeploymentId;
 use crate::data::graphql::{object, IntoValue};
-use crate::prelude::{q, web3::types::H256, BlockPtr, Value};
+use crate::data::store::scalar::Timestamp;
+use crate::prelude::{q, web3::types::H256, BlockNumber, BlockPtr, NodeId, Value};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 pub enum Filter {
     /// Get all versions for the named subgraph
@@ -15,6 +20,132 @@ pub enum Filter {
     Deployments(Vec<String>),
     /// Get the status of all deployments with the given ids
     DeploymentIds(Vec<DeploymentId>),
+    /// Get the status of all deployments assigned to the given node
+    Node(NodeId),
+}
+
+/// Select which deployments to include in the status of the whole cluster
+pub struct ClusterFilter {
+    /// Only include deployments with this health
+    pub health: Option<SubgraphHealth>,
+    /// Only include deployments that are assigned to this node
+    pub node: Option<NodeId>,
+    /// Skip this many deployments, ordered by their id
+    pub skip: usize,
+    /// Return at most this many deployments
+    pub first: usize,
+}
+
+/// How `indexingStatuses` orders deployments
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusOrder {
+    /// By deployment id, which is the order in which deployments were
+    /// created
+    Id,
+    /// By how many blocks the deployment is behind the head of its chain
+    Lag,
+    /// By when the deployment last processed a block
+    LastProcessedAt,
+    /// By the alphabetically first name of the subgraphs that use the
+    /// deployment, or the deployment hash if there is none
+    Name,
+}
+
+impl StatusOrder {
+    /// Whether the sort key for this order is a number or text
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self, StatusOrder::Name)
+    }
+}
+
+impl FromStr for StatusOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lag" => Ok(StatusOrder::Lag),
+            "lastProcessedAt" => Ok(StatusOrder::LastProcessedAt),
+            "name" => Ok(StatusOrder::Name),
+            _ => Err(format!("unknown order `{}`", s)),
+        }
+    }
+}
+
+/// The value of the sort key of a deployment for a `StatusOrder`
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusKey {
+    Number(i64),
+    Text(String),
+}
+
+impl fmt::Display for StatusKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusKey::Number(n) => write!(f, "{}", n),
+            StatusKey::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Marks the position of a deployment in the list of statuses so that the
+/// next page can start after it. Cursors only make sense for the order for
+/// which they were returned
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusCursor {
+    pub id: DeploymentId,
+    pub key: StatusKey,
+}
+
+impl StatusCursor {
+    pub fn parse(cursor: &str, order: StatusOrder) -> Result<Self, String> {
+        let invalid = || format!("invalid cursor `{}`", cursor);
+        let mut parts = cursor.splitn(2, ':');
+        let id = parts
+            .next()
+            .and_then(|id| id.parse::<i32>().ok())
+            .ok_or_else(invalid)?;
+        let key = parts.next().ok_or_else(invalid)?;
+        let key = if order.is_numeric() {
+            StatusKey::Number(key.parse().map_err(|_| invalid())?)
+        } else {
+            StatusKey::Text(key.to_string())
+        };
+        Ok(StatusCursor {
+            id: DeploymentId(id),
+            key,
+        })
+    }
+}
+
+impl fmt::Display for StatusCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.id.0, self.key)
+    }
+}
+
+/// Select which deployments to include in `indexingStatuses`, in what
+/// order, and which page of them to return
+#[derive(Clone, Debug)]
+pub struct StatusQuery {
+    /// Only include deployments with these hashes; all deployments if this
+    /// is empty
+    pub deployments: Vec<String>,
+    pub health: Option<SubgraphHealth>,
+    pub synced: Option<bool>,
+    pub network: Option<String>,
+    /// Only include deployments used by the current or pending version of
+    /// a subgraph whose name starts with this
+    pub name_prefix: Option<String>,
+    /// Only include deployments that are assigned to this node
+    pub node: Option<NodeId>,
+    /// Only include deployments whose labels match all of these
+    pub labels: Vec<LabelSelector>,
+    pub order: StatusOrder,
+    pub descending: bool,
+    /// Return at most this many deployments
+    pub first: usize,
+    /// Start after the deployment this cursor points to
+    pub after: Option<StatusCursor>,
 }
 
 /// Light wrapper around `EthereumBlockPointer` that is compatible with GraphQL values.
@@ -86,6 +217,362 @@ impl IntoValue for ChainInfo {
     }
 }
 
+/// What reverting one entity type did
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntityRevert {
+    pub entity_type: String,
+    /// Versions that were current before the reverted blocks and were
+    /// made current again
+    pub restored: usize,
+    /// Versions that were created in the reverted blocks and deleted
+    pub removed: usize,
+}
+
+impl IntoValue for EntityRevert {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "EntityRevert",
+            entityType: self.entity_type,
+            restored: self.restored as i32,
+            removed: self.removed as i32,
+        }
+    }
+}
+
+/// A summary of the work done by one revert of a deployment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RevertReport {
+    /// The block the deployment was at before the revert
+    pub from_block: BlockNumber,
+    /// The block the deployment was reverted to
+    pub to_block: BlockNumber,
+    /// Entity types for which the revert changed something
+    pub entities: Vec<EntityRevert>,
+    pub dynamic_data_sources_removed: usize,
+    /// The time spent reverting. For a report that covers several
+    /// reverts, the sum of their times
+    pub duration_ms: u64,
+    pub reverted_at: Timestamp,
+}
+
+impl RevertReport {
+    pub fn blocks(&self) -> BlockNumber {
+        self.from_block - self.to_block
+    }
+
+    pub fn entities_restored(&self) -> usize {
+        self.entities.iter().map(|entity| entity.restored).sum()
+    }
+
+    pub fn entities_removed(&self) -> usize {
+        self.entities.iter().map(|entity| entity.removed).sum()
+    }
+
+    /// The counts for each entity type, for logging
+    pub fn entity_types(&self) -> String {
+        self.entities
+            .iter()
+            .map(|entity| {
+                format!(
+                    "{}: +{}/-{}",
+                    entity.entity_type, entity.restored, entity.removed
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Whether `later` continues this revert, i.e., starts at the block
+    /// this revert stopped at. A reorg is reverted one block at a time,
+    /// and all those reverts together are one report
+    pub fn is_continued_by(&self, later: &RevertReport) -> bool {
+        later.from_block == self.to_block
+    }
+
+    /// Add the revert `later`, which continued this one, to this report
+    pub fn extend(&mut self, later: RevertReport) {
+        self.to_block = later.to_block;
+        merge_entity_reverts(&mut self.entities, later.entities);
+        self.dynamic_data_sources_removed += later.dynamic_data_sources_removed;
+        self.duration_ms += later.duration_ms;
+        self.reverted_at = later.reverted_at;
+    }
+}
+
+/// Add the counts in `other` to those for the same entity type in `entities`
+pub fn merge_entity_reverts(entities: &mut Vec<EntityRevert>, other: Vec<EntityRevert>) {
+    for revert in other {
+        match entities
+            .iter_mut()
+            .find(|entity| entity.entity_type == revert.entity_type)
+        {
+            Some(entity) => {
+                entity.restored += revert.restored;
+                entity.removed += revert.removed;
+            }
+            None => entities.push(revert),
+        }
+    }
+}
+
+impl IntoValue for RevertReport {
+    fn into_value(self) -> q::Value {
+        let blocks = self.blocks();
+        let restored = self.entities_restored();
+        let removed = self.entities_removed();
+        let RevertReport {
+            from_block,
+            to_block,
+            entities,
+            dynamic_data_sources_removed,
+            duration_ms,
+            reverted_at,
+        } = self;
+        object! {
+            __typename: "RevertReport",
+            fromBlock: from_block,
+            toBlock: to_block,
+            blocks: blocks,
+            entitiesRestored: restored as i32,
+            entitiesRemoved: removed as i32,
+            entities: entities,
+            dynamicDataSourcesRemoved: dynamic_data_sources_removed as i32,
+            durationMs: duration_ms,
+            revertedAt: reverted_at.to_string(),
+        }
+    }
+}
+
+/// A fatal error of a deployment that was cleared so that the deployment
+/// could try again to index the block that failed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetriedFailure {
+    pub message: String,
+    pub handler: Option<String>,
+    pub block_number: Option<BlockNumber>,
+    pub block_hash: Option<String>,
+    pub deterministic: bool,
+    pub retried_at: Timestamp,
+}
+
+impl RetriedFailure {
+    pub fn new(error: SubgraphError, retried_at: Timestamp) -> Self {
+        let SubgraphError {
+            subgraph_id: _,
+            message,
+            block_ptr,
+            handler,
+            deterministic,
+        } = error;
+        RetriedFailure {
+            message,
+            handler,
+            block_number: block_ptr.as_ref().map(|ptr| ptr.number),
+            block_hash: block_ptr.map(|ptr| ptr.hash_hex()),
+            deterministic,
+            retried_at,
+        }
+    }
+}
+
+impl IntoValue for RetriedFailure {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "RetriedFailure",
+            message: self.message,
+            handler: self.handler,
+            blockNumber: self.block_number,
+            blockHash: self.block_hash,
+            deterministic: self.deterministic,
+            retriedAt: self.retried_at.to_string(),
+        }
+    }
+}
+
+/// How far copying data from another deployment, for a graft or a copy
+/// made with `graphman`, has gotten
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyStatus {
+    pub tables_done: i32,
+    pub tables_total: i32,
+    /// The number of entity versions that have been copied
+    pub rows_copied: u64,
+    pub progress_pct: f64,
+    /// Estimate of how many more seconds copying will take; `None` once
+    /// copying has finished or was cancelled, or before it has made any
+    /// progress
+    pub eta_seconds: Option<u64>,
+    pub started_at: Timestamp,
+    pub finished_at: Option<Timestamp>,
+    pub cancelled_at: Option<Timestamp>,
+}
+
+impl IntoValue for CopyStatus {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "CopyStatus",
+            tablesDone: self.tables_done,
+            tablesTotal: self.tables_total,
+            rowsCopied: self.rows_copied,
+            progressPct: self.progress_pct,
+            etaSeconds: self.eta_seconds,
+            startedAt: self.started_at.to_string(),
+            finishedAt: self.finished_at.map(|ts| ts.to_string()),
+            cancelledAt: self.cancelled_at.map(|ts| ts.to_string()),
+        }
+    }
+}
+
+/// A switch of the current version of a subgraph to the deployment that
+/// had been its pending version until that deployment was synced
+#[derive(Clone, Debug, PartialEq)]
+pub struct VersionSwitch {
+    /// The name of the subgraph
+    pub subgraph: String,
+    /// The deployment hash of the previous current version, if there was one
+    pub old_deployment: Option<String>,
+    /// The deployment hash of the new current version
+    pub new_deployment: String,
+    /// The block that the new deployment had reached when it became the
+    /// current version
+    pub block: Option<BlockNumber>,
+    pub switched_at: Timestamp,
+}
+
+impl IntoValue for VersionSwitch {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "VersionSwitch",
+            subgraphName: self.subgraph,
+            oldDeployment: self.old_deployment,
+            newDeployment: self.new_deployment,
+            blockNumber: self.block,
+            switchedAt: self.switched_at.to_string(),
+        }
+    }
+}
+
+/// An index declared with an `@index` directive, or one that was declared
+/// in an earlier version of the schema and is still in the database
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompositeIndex {
+    /// The name of the index in the database
+    pub name: String,
+    /// The entity type and the fields of the index; they are `None` and
+    /// empty for indexes that are no longer declared
+    pub entity_type: Option<String>,
+    pub fields: Vec<String>,
+    pub unique: bool,
+    /// Whether the schema of the deployment declares the index
+    pub declared: bool,
+    /// Whether the index exists in the database and can be used. Indexes
+    /// that are still being built, or whose build failed, are not valid
+    pub valid: bool,
+}
+
+impl IntoValue for CompositeIndex {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "CompositeIndex",
+            name: self.name,
+            entityType: self.entity_type,
+            fields: self.fields,
+            unique: self.unique,
+            declared: self.declared,
+            valid: self.valid,
+        }
+    }
+}
+
+/// A block that an operator told a deployment to skip without running any
+/// of its handlers, for example, because the deployment can not process it
+/// and is stuck on it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedBlock {
+    /// The hash of the block as `0x` followed by hex digits
+    pub block_hash: String,
+    pub reason: String,
+    pub requested_at: Timestamp,
+    /// When the operator withdrew the skip. Withdrawing a skip that the
+    /// deployment has already applied does not change its data; that
+    /// requires rewinding the deployment to before the block
+    pub unskipped_at: Option<Timestamp>,
+    /// The number of the block once the deployment has skipped it
+    pub block_number: Option<BlockNumber>,
+    pub skipped_at: Option<Timestamp>,
+    /// When the deployment reverted the block after it had skipped it
+    pub reverted_at: Option<Timestamp>,
+}
+
+impl SkippedBlock {
+    pub fn new(block_hash: String, reason: String, requested_at: Timestamp) -> Self {
+        SkippedBlock {
+            block_hash,
+            reason,
+            requested_at,
+            unskipped_at: None,
+            block_number: None,
+            skipped_at: None,
+            reverted_at: None,
+        }
+    }
+
+    /// Whether the deployment should skip the block when it gets to it
+    pub fn is_requested(&self) -> bool {
+        self.unskipped_at.is_none()
+    }
+
+    /// Whether the data of the deployment reflects that the block was
+    /// skipped, which makes its proof of indexing differ from that of
+    /// deployments that processed the block normally
+    pub fn is_applied(&self) -> bool {
+        self.skipped_at.is_some() && self.reverted_at.is_none()
+    }
+}
+
+impl IntoValue for SkippedBlock {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "SkippedBlock",
+            blockHash: self.block_hash,
+            reason: self.reason,
+            requestedAt: self.requested_at.to_string(),
+            unskippedAt: self.unskipped_at.map(|ts| ts.to_string()),
+            blockNumber: self.block_number,
+            skippedAt: self.skipped_at.map(|ts| ts.to_string()),
+            revertedAt: self.reverted_at.map(|ts| ts.to_string()),
+        }
+    }
+}
+
+/// A reload of the mappings of a deployment in development. From then on,
+/// the deployment runs the mappings from another manifest, and its proof of
+/// indexing can not be compared with that of other deployments
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MappingsReload {
+    /// The hash of the manifest whose mappings the deployment runs
+    pub manifest: String,
+    /// The block the deployment was rewound to before the reload
+    pub rewound_to: Option<BlockNumber>,
+    pub reloaded_at: Timestamp,
+}
+
+impl IntoValue for MappingsReload {
+    fn into_value(self) -> q::Value {
+        object! {
+            __typename: "MappingsReload",
+            manifest: self.manifest,
+            rewoundTo: self.rewound_to,
+            reloadedAt: self.reloaded_at.to_string(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Info {
     pub id: DeploymentId,
@@ -95,6 +582,9 @@ pub struct Info {
 
     /// Whether or not the subgraph has synced all the way to the current chain head.
     pub synced: bool,
+    /// How many consecutive checks found the subgraph close enough to the
+    /// chain head to count as synced
+    pub synced_checks: u32,
     pub health: SubgraphHealth,
     pub fatal_error: Option<SubgraphError>,
     pub non_fatal_errors: Vec<SubgraphError>,
@@ -106,6 +596,47 @@ pub struct Info {
 
     /// ID of the Graph Node that the subgraph is indexed by.
     pub node: Option<String>,
+
+    /// Names of the subgraphs whose current or pending version uses the
+    /// deployment, in alphabetical order
+    pub names: Vec<String>,
+
+    /// The labels that operators attached to the deployment
+    pub labels: Labels,
+
+    /// Median and 95th percentile of the time in seconds between a block
+    /// being produced and its changes becoming queryable, measured for
+    /// recent blocks close to the chain head
+    pub block_latency_p50: Option<f64>,
+    pub block_latency_p95: Option<f64>,
+
+    /// Whether the deployment declares the `noEthCalls` feature
+    pub eth_calls_disabled: bool,
+
+    /// What the most recent revert of the deployment did
+    pub last_revert: Option<RevertReport>,
+
+    /// Earlier failures of the deployment that were cleared to retry,
+    /// oldest first
+    pub failure_history: Vec<RetriedFailure>,
+
+    /// Progress of copying data into the deployment if it was created by
+    /// copying data from another deployment
+    pub copy_status: Option<CopyStatus>,
+
+    /// Blocks that an operator told the deployment to skip, in the order
+    /// in which they were requested
+    pub skipped_blocks: Vec<SkippedBlock>,
+
+    /// Reloads of the mappings of the deployment, oldest first
+    pub mappings_reloads: Vec<MappingsReload>,
+
+    /// When the deployment last processed a block
+    pub last_processed_at: Option<Timestamp>,
+
+    /// Where the next page of statuses starts when this status was
+    /// returned from a `StatusQuery`
+    pub cursor: Option<StatusCursor>,
 }
 
 impl IntoValue for Info {
@@ -118,8 +649,21 @@ impl IntoValue for Info {
             fatal_error,
             health,
             node,
+            names,
+            labels,
             non_fatal_errors,
             synced,
+            synced_checks,
+            block_latency_p50,
+            block_latency_p95,
+            eth_calls_disabled,
+            last_revert,
+            failure_history,
+            copy_status,
+            skipped_blocks,
+            mappings_reloads,
+            last_processed_at,
+            cursor,
         } = self;
 
         fn subgraph_error_to_value(subgraph_error: SubgraphError) -> q::Value {
@@ -150,17 +694,62 @@ impl IntoValue for Info {
             .map(subgraph_error_to_value)
             .collect();
         let fatal_error_val = fatal_error.map_or(q::Value::Null, subgraph_error_to_value);
+        let poi_intervention =
+            skipped_blocks.iter().any(SkippedBlock::is_applied) || !mappings_reloads.is_empty();
 
         object! {
             __typename: "SubgraphIndexingStatus",
             subgraph: subgraph,
             synced: synced,
+            syncedChecks: synced_checks as i32,
             health: q::Value::from(health),
             fatalError: fatal_error_val,
             nonFatalErrors: non_fatal_errors,
             chains: chains.into_iter().map(|chain| chain.into_value()).collect::<Vec<_>>(),
             entityCount: format!("{}", entity_count),
             node: node,
+            names: names,
+            labels: labels_into_value(labels),
+            blockLatencyP50: block_latency_p50,
+            blockLatencyP95: block_latency_p95,
+            ethCallsDisabled: eth_calls_disabled,
+            lastRevert: last_revert,
+            failureHistory: failure_history,
+            copyStatus: copy_status,
+            skippedBlocks: skipped_blocks,
+            mappingsReloads: mappings_reloads,
+            poiIntervention: poi_intervention,
+            lastProcessedAt: last_processed_at.map(|ts| ts.to_string()),
+            cursor: cursor.map(|cursor| cursor.to_string()),
+        }
+    }
+}
+
+/// The status of a deployment together with what we know about the node
+/// that the deployment is assigned to
+#[derive(Debug)]
+pub struct ClusterInfo {
+    pub info: Info,
+    /// The last time the node recorded that it is alive
+    pub last_heartbeat: Option<Timestamp>,
+    /// Whether the node has not recorded a heartbeat in a while, or never
+    /// has. Always `false` for deployments that are not assigned to a node
+    pub node_dead: bool,
+}
+
+impl IntoValue for ClusterInfo {
+    fn into_value(self) -> q::Value {
+        let ClusterInfo {
+            info,
+            last_heartbeat,
+            node_dead,
+        } = self;
+        object! {
+            __typename: "ClusterIndexingStatus",
+            node: info.node.clone(),
+            lastHeartbeat: last_heartbeat.map(|ts| ts.to_string()),
+            nodeDead: node_dead,
+            status: info.into_value(),
         }
     }
 }
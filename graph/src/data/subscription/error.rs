@@ -8,6 +8,11 @@ use thiserror::Error;
 pub enum SubscriptionError {
     #[error("GraphQL error: {0:?}")]
     GraphQLError(Vec<QueryExecutionError>),
+    /// The deployment has failed or has errors at its latest block, and
+    /// the subscription does not allow that with `subgraphError: allow`.
+    /// The message is the same as that of `QueryError::IndexingError`
+    #[error("indexing_error")]
+    IndexingError,
 }
 
 impl From<QueryExecutionError> for SubscriptionError {
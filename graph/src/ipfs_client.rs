@@ -28,7 +28,7 @@ pub struct AddResponse {
     pub size: String,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct IpfsClient {
     base: Arc<Uri>,
     client: Arc<reqwest::Client>,
@@ -51,6 +51,11 @@ impl IpfsClient {
         })
     }
 
+    /// The URL of the IPFS node this client talks to
+    pub fn base(&self) -> &Uri {
+        &self.base
+    }
+
     pub fn localhost() -> Self {
         IpfsClient {
             client: Arc::new(reqwest::Client::new()),
@@ -32,7 +32,8 @@ pub mod mock {
 /// Wrapper for spawning tasks that abort on panic, which is our default.
 mod task_spawn;
 pub use task_spawn::{
-    block_on, spawn, spawn_allow_panic, spawn_blocking, spawn_blocking_allow_panic, spawn_thread,
+    block_on, spawn, spawn_allow_panic, spawn_blocking, spawn_blocking_allow_panic,
+    spawn_essential, spawn_named, spawn_thread, EssentialTaskPolicy, TaskSupervisor,
 };
 
 pub use bytes;
@@ -98,7 +99,7 @@ pub mod prelude {
 
     pub use crate::components::ethereum::{
         EthereumBlock, EthereumBlockWithCalls, EthereumCall, EthereumNetworkIdentifier,
-        LightEthereumBlock, LightEthereumBlockExt,
+        EthereumTransaction, EthereumUncle, LightEthereumBlock, LightEthereumBlockExt,
     };
     pub use crate::components::graphql::{
         GraphQlRunner, QueryLoadManager, SubscriptionResultFuture,
@@ -114,16 +115,18 @@ pub mod prelude {
     pub use crate::components::server::metrics::MetricsServer;
     pub use crate::components::server::query::GraphQLServer;
     pub use crate::components::server::subscription::SubscriptionServer;
+    pub use crate::components::server::version::BuildInfo;
     pub use crate::components::store::{
         AttributeNames, BlockNumber, ChainStore, ChildMultiplicity, EntityCache, EntityChange,
         EntityChangeOperation, EntityCollection, EntityFilter, EntityKey, EntityLink,
         EntityModification, EntityOperation, EntityOrder, EntityQuery, EntityRange, EntityWindow,
-        EthereumCallCache, ParentLink, PoolWaitStats, QueryStore, QueryStoreManager, StoreError,
-        StoreEvent, StoreEventStream, StoreEventStreamBox, SubgraphStore, WindowAttribute,
-        BLOCK_NUMBER_MAX, SUBSCRIPTION_THROTTLE_INTERVAL,
+        EthereumCallCache, MetadataMaintenanceReport, ParentLink, PoolWaitStats, QueryStore,
+        QueryStoreManager, StoreError, StoreEvent, StoreEventStream, StoreEventStreamBox,
+        SubgraphStore, WindowAttribute, BLOCK_NUMBER_MAX, SUBSCRIPTION_THROTTLE_INTERVAL,
     };
     pub use crate::components::subgraph::{
-        BlockState, DataSourceTemplateInfo, HostMetrics, RuntimeHost, RuntimeHostBuilder,
+        BlockState, CreatedDataSources, DataSourceKey, DataSourceTemplateInfo,
+        DeploymentFilesReport, HostMetrics, RetryFailedPolicy, RuntimeHost, RuntimeHostBuilder,
         SubgraphAssignmentProvider, SubgraphInstanceManager, SubgraphRegistrar,
         SubgraphVersionSwitchingMode,
     };
@@ -138,7 +141,7 @@ pub mod prelude {
     };
     pub use crate::data::schema::{ApiSchema, Schema};
     pub use crate::data::store::ethereum::*;
-    pub use crate::data::store::scalar::{BigDecimal, BigInt, BigIntSign};
+    pub use crate::data::store::scalar::{BigDecimal, BigInt, BigIntSign, RoundingMode};
     pub use crate::data::store::{
         AssignmentEvent, Attribute, Entity, NodeId, SubscriptionFilter, ToEntityId, ToEntityKey,
         TryIntoEntity, Value, ValueType,
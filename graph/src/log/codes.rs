@@ -4,6 +4,7 @@ pub enum LogCode {
     SubgraphStartFailure,
     SubgraphSyncingFailure,
     SubgraphSyncingFailureNotRecorded,
+    SubgraphWriterLeaseHeld,
     BlockIngestionStatus,
     BlockIngestionLagging,
     GraphQlQuerySuccess,
@@ -17,6 +18,7 @@ impl Display for LogCode {
             LogCode::SubgraphStartFailure => "SubgraphStartFailure",
             LogCode::SubgraphSyncingFailure => "SubgraphSyncingFailure",
             LogCode::SubgraphSyncingFailureNotRecorded => "SubgraphSyncingFailureNotRecorded",
+            LogCode::SubgraphWriterLeaseHeld => "SubgraphWriterLeaseHeld",
             LogCode::BlockIngestionStatus => "BlockIngestionStatus",
             LogCode::BlockIngestionLagging => "BlockIngestionLagging",
             LogCode::GraphQlQuerySuccess => "GraphQLQuerySuccess",
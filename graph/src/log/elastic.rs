@@ -2,12 +2,13 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Write;
 use std::result::Result;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Once, Weak};
 use std::time::Duration;
 
 use chrono::prelude::{SecondsFormat, Utc};
 use futures03::TryFutureExt;
 use http::header::CONTENT_TYPE;
+use lazy_static::lazy_static;
 use reqwest;
 use reqwest::Client;
 use serde::ser::Serializer as SerdeSerializer;
@@ -179,6 +180,7 @@ impl ElasticDrain {
             logs: Arc::new(Mutex::new(vec![])),
         };
         drain.periodically_flush_logs();
+        drain.register();
         drain
     }
 
@@ -188,106 +190,184 @@ impl ElasticDrain {
         let config = self.config.clone();
         let mut interval = tokio::time::interval(self.config.flush_interval);
 
-        crate::task_spawn::spawn(async move {
+        crate::task_spawn::spawn_named("elastic-log-flush", async move {
             loop {
                 interval.tick().await;
 
-                let logs = logs.clone();
-                let config = config.clone();
-                let flush_logger = flush_logger.clone();
                 let logs_to_send = {
                     let mut logs = logs.lock().unwrap();
-                    let logs_to_send = (*logs).clone();
                     // Clear the logs, so the next batch can be recorded
-                    logs.clear();
-                    logs_to_send
+                    std::mem::take(&mut *logs)
                 };
 
-                // Do nothing if there are no logs to flush
-                if logs_to_send.is_empty() {
-                    continue;
+                send_logs(logs_to_send, &config, &flush_logger).await;
+            }
+        });
+    }
+
+    /// Remember the buffer of this drain so that `flush_all` can send it
+    fn register(&self) {
+        let mut drains = DRAINS.lock().unwrap();
+        drains.retain(|drain| drain.logs.strong_count() > 0);
+        drains.push(RegisteredDrain {
+            config: self.config.clone(),
+            error_logger: self.error_logger.clone(),
+            logs: Arc::downgrade(&self.logs),
+        });
+        REGISTER_FLUSH.call_once(|| crate::util::crash::on_flush(flush_all));
+    }
+}
+
+/// The buffer of a drain, and what is needed to send it
+struct RegisteredDrain {
+    config: ElasticDrainConfig,
+    error_logger: Logger,
+    logs: Weak<Mutex<Vec<ElasticLog>>>,
+}
+
+lazy_static! {
+    static ref DRAINS: Mutex<Vec<RegisteredDrain>> = Mutex::new(Vec::new());
+}
+
+static REGISTER_FLUSH: Once = Once::new();
+
+/// How long we wait for Elasticsearch when sending the logs of all drains
+/// right before the process aborts
+const FINAL_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Send the logs that all drains have buffered. Since this is called when
+/// the process is about to abort, possibly from within the runtime, the
+/// logs are sent from a separate thread with its own runtime
+fn flush_all() {
+    // Skip anything that is locked rather than risk waiting for a thread
+    // that panicked while holding the lock
+    let drains = match DRAINS.try_lock() {
+        Ok(drains) => drains,
+        Err(_) => return,
+    };
+    let batches: Vec<_> = drains
+        .iter()
+        .filter_map(|drain| {
+            let logs = drain.logs.upgrade()?;
+            let mut logs = logs.try_lock().ok()?;
+            let logs = std::mem::take(&mut *logs);
+            Some((logs, drain.config.clone(), drain.error_logger.clone()))
+        })
+        .collect();
+    drop(drains);
+    if batches.is_empty() {
+        return;
+    }
+
+    let sender = std::thread::spawn(move || {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(_) => return,
+        };
+        runtime.block_on(async move {
+            let send = async move {
+                for (logs, config, error_logger) in batches {
+                    send_logs(logs, &config, &error_logger).await;
                 }
+            };
+            tokio::time::timeout(FINAL_FLUSH_TIMEOUT, send).await.ok();
+        });
+    });
+    sender.join().ok();
+}
+
+/// Send `logs_to_send` to Elasticsearch, logging any problems to
+/// `flush_logger`
+async fn send_logs(
+    logs_to_send: Vec<ElasticLog>,
+    config: &ElasticDrainConfig,
+    flush_logger: &Logger,
+) {
+    // Do nothing if there are no logs to flush
+    if logs_to_send.is_empty() {
+        return;
+    }
 
-                debug!(
+    debug!(
+        flush_logger,
+        "Flushing {} logs to Elasticsearch",
+        logs_to_send.len()
+    );
+
+    // The Elasticsearch batch API takes requests with the following format:
+    // ```ignore
+    // action_and_meta_data\n
+    // optional_source\n
+    // action_and_meta_data\n
+    // optional_source\n
+    // ```
+    // For more details, see:
+    // https://www.elastic.co/guide/en/elasticsearch/reference/current/docs-bulk.html
+    //
+    // We're assembly the request body in the same way below:
+    let batch_body = logs_to_send.iter().fold(String::from(""), |mut out, log| {
+        // Try to serialize the log itself to a JSON string
+        match serde_json::to_string(log) {
+            Ok(log_line) => {
+                // Serialize the action line to a string
+                let action_line = json!({
+                    "index": {
+                        "_index": config.index,
+                        "_type": config.document_type,
+                        "_id": log.id,
+                    }
+                })
+                .to_string();
+
+                // Combine the two lines with newlines, make sure there is
+                // a newline at the end as well
+                out.push_str(format!("{}\n{}\n", action_line, log_line).as_str());
+            }
+            Err(e) => {
+                error!(
                     flush_logger,
-                    "Flushing {} logs to Elasticsearch",
-                    logs_to_send.len()
+                    "Failed to serialize Elasticsearch log to JSON: {}", e
                 );
-
-                // The Elasticsearch batch API takes requests with the following format:
-                // ```ignore
-                // action_and_meta_data\n
-                // optional_source\n
-                // action_and_meta_data\n
-                // optional_source\n
-                // ```
-                // For more details, see:
-                // https://www.elastic.co/guide/en/elasticsearch/reference/current/docs-bulk.html
-                //
-                // We're assembly the request body in the same way below:
-                let batch_body = logs_to_send.iter().fold(String::from(""), |mut out, log| {
-                    // Try to serialize the log itself to a JSON string
-                    match serde_json::to_string(log) {
-                        Ok(log_line) => {
-                            // Serialize the action line to a string
-                            let action_line = json!({
-                                "index": {
-                                    "_index": config.index,
-                                    "_type": config.document_type,
-                                    "_id": log.id,
-                                }
-                            })
-                            .to_string();
-
-                            // Combine the two lines with newlines, make sure there is
-                            // a newline at the end as well
-                            out.push_str(format!("{}\n{}\n", action_line, log_line).as_str());
-                        }
-                        Err(e) => {
-                            error!(
-                                flush_logger,
-                                "Failed to serialize Elasticsearch log to JSON: {}", e
-                            );
-                        }
-                    };
-
-                    out
-                });
-
-                // Build the batch API URL
-                let mut batch_url = reqwest::Url::parse(config.general.endpoint.as_str())
-                    .expect("invalid Elasticsearch URL");
-                batch_url.set_path("_bulk");
-
-                // Send batch of logs to Elasticsearch
-                let client = Client::new();
-                let logger_for_err = flush_logger.clone();
-
-                let header = match config.general.username {
-                    Some(username) => client
-                        .post(batch_url)
-                        .header(CONTENT_TYPE, "application/json")
-                        .basic_auth(username, config.general.password.clone()),
-                    None => client
-                        .post(batch_url)
-                        .header(CONTENT_TYPE, "application/json"),
-                };
-                header
-                    .body(batch_body)
-                    .send()
-                    .and_then(|response| async { response.error_for_status() })
-                    .map_ok(|_| ())
-                    .unwrap_or_else(move |e| {
-                        // Log if there was a problem sending the logs
-                        error!(
-                            logger_for_err,
-                            "Failed to send logs to Elasticsearch: {}", e
-                        );
-                    })
-                    .await;
             }
-        });
-    }
+        };
+
+        out
+    });
+
+    // Build the batch API URL
+    let mut batch_url =
+        reqwest::Url::parse(config.general.endpoint.as_str()).expect("invalid Elasticsearch URL");
+    batch_url.set_path("_bulk");
+
+    // Send batch of logs to Elasticsearch
+    let client = Client::new();
+    let logger_for_err = flush_logger.clone();
+
+    let header = match &config.general.username {
+        Some(username) => client
+            .post(batch_url)
+            .header(CONTENT_TYPE, "application/json")
+            .basic_auth(username, config.general.password.clone()),
+        None => client
+            .post(batch_url)
+            .header(CONTENT_TYPE, "application/json"),
+    };
+    header
+        .body(batch_body)
+        .send()
+        .and_then(|response| async { response.error_for_status() })
+        .map_ok(|_| ())
+        .unwrap_or_else(move |e| {
+            // Log if there was a problem sending the logs
+            error!(
+                logger_for_err,
+                "Failed to send logs to Elasticsearch: {}", e
+            );
+        })
+        .await;
 }
 
 impl Drain for ElasticDrain {
@@ -23,17 +23,74 @@ use slog::*;
 use slog_async;
 use slog_envlogger;
 use slog_term::*;
-use std::{env, fmt, io, result};
+use std::{env, fmt, io, result, str::FromStr};
+
+use crate::util::crash;
 
 pub mod codes;
 pub mod elastic;
 pub mod factory;
+pub mod json;
 pub mod split;
 
+/// How the root logger formats log records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, colored if stdout is a terminal
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow::anyhow!(
+                "invalid log format `{}`, must be one of `text` or `json`",
+                s
+            )),
+        }
+    }
+}
+
+/// Create the root logger, using the format set with `GRAPH_LOG_FORMAT`
 pub fn logger(show_debug: bool) -> Logger {
-    let use_color = isatty::stdout_isatty();
-    let decorator = slog_term::TermDecorator::new().build();
-    let drain = CustomFormat::new(decorator, use_color).fuse();
+    let format = env::var("GRAPH_LOG_FORMAT")
+        .ok()
+        .map(|s| LogFormat::from_str(&s).unwrap_or_else(|e| panic!("{}", e)))
+        .unwrap_or_default();
+    logger_with_format(show_debug, format)
+}
+
+pub fn logger_with_format(show_debug: bool, format: LogFormat) -> Logger {
+    match format {
+        LogFormat::Text => {
+            let use_color = isatty::stdout_isatty();
+            let decorator = slog_term::TermDecorator::new().build();
+            let drain = CustomFormat::new(decorator, use_color).fuse();
+            async_logger(drain, show_debug)
+        }
+        LogFormat::Json => {
+            let drain = json::JsonFormat::new(io::stdout()).fuse();
+            async_logger(drain, show_debug)
+        }
+    }
+}
+
+fn async_logger<D>(drain: D, show_debug: bool) -> Logger
+where
+    D: Drain<Ok = (), Err = Never> + Send + 'static,
+{
     let drain = slog_envlogger::LogBuilder::new(drain)
         .filter(
             None,
@@ -50,11 +107,12 @@ pub fn logger(show_debug: bool) -> Logger {
                 .unwrap(),
         )
         .build();
-    let drain = slog_async::Async::new(drain)
+    let (drain, guard) = slog_async::Async::new(drain)
         .chan_size(20000)
-        .build()
-        .fuse();
-    Logger::root(drain, o!())
+        .thread_name(crash::LOG_THREAD_NAME.to_string())
+        .build_with_guard();
+    crash::on_flush(move || drop(guard));
+    Logger::root(drain.fuse(), o!())
 }
 
 pub struct CustomFormat<D>
@@ -13,6 +13,10 @@ pub trait AscHeap {
 
     fn api_version(&self) -> Version;
 
+    /// Whether strings that contain NUL characters or invalid UTF-16
+    /// should be rejected rather than sanitized
+    fn strict_strings(&self) -> bool;
+
     fn asc_type_id(
         &mut self,
         type_id_index: IndexForAscTypeId,
@@ -10,11 +10,41 @@ pub use asc_heap::{asc_get, asc_new, try_asc_get, AscHeap, FromAscObj, ToAscObj,
 pub use asc_ptr::AscPtr;
 
 use anyhow::Error;
+use lazy_static::lazy_static;
 use semver::Version;
 use std::convert::TryInto;
 use std::fmt;
 use std::mem::size_of;
 
+lazy_static! {
+    /// The most bytes that passing one trigger to its handler may allocate
+    /// on the WASM heap. Since a different budget changes which triggers
+    /// fail, every deployment records the budget it was created with
+    pub static ref MAX_TRIGGER_BYTES: usize = std::env::var("GRAPH_RUNTIME_MAX_TRIGGER_BYTES")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_RUNTIME_MAX_TRIGGER_BYTES must be a number, but is `{}`", s)
+            })
+        })
+        // 32MiB
+        .unwrap_or(32 * 1024 * 1024);
+
+    /// The most bytes of linear memory that a mapping may use while it
+    /// handles one trigger. Only enforced for mappings with `apiVersion`
+    /// 0.0.6 and later. Since the AssemblyScript allocator grows memory in
+    /// large steps, mappings can run out of memory well before this
+    pub static ref MAX_HEAP_BYTES: usize = std::env::var("GRAPH_RUNTIME_MAX_HEAP_BYTES")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_RUNTIME_MAX_HEAP_BYTES must be a number, but is `{}`", s)
+            })
+        })
+        // 256MiB
+        .unwrap_or(256 * 1024 * 1024);
+}
+
 /// Marker trait for AssemblyScript types that the id should
 /// be in the header.
 pub trait AscIndexId {
@@ -192,6 +222,12 @@ pub enum IndexForAscTypeId {
     ArrayF32 = 49,
     ArrayF64 = 50,
     ArrayBigDecimal = 51,
+    EthereumTransactionReceipt = 52,
+    EthereumLog = 53,
+    ArrayEthereumLog = 54,
+    ArrayH256 = 55,
+    EthereumUncle = 56,
+    ArrayEthereumUncle = 57,
 }
 
 impl ToAscObj<u32> for IndexForAscTypeId {
@@ -9,22 +9,228 @@
 //!
 //! These should not be called from within executors other than tokio, particularly the blocking
 //! functions will panic in that case. We should generally avoid mixing executors whenever possible.
+//!
+//! Long-running tasks should be spawned with `spawn_named` or, if the node can not do its job
+//! without them, with `spawn_essential`. Once a `TaskSupervisor` is installed, panics in these
+//! tasks are logged at critical level with the name of the task and counted in the
+//! `task_panics_total` metric, and essential tasks are restarted or take the process down,
+//! depending on the `EssentialTaskPolicy` of the supervisor.
 
 use futures03::future::{FutureExt, TryFutureExt};
+use lazy_static::lazy_static;
+use prometheus::CounterVec;
+use slog::{crit, warn, Logger};
+use std::any::Any;
 use std::future::Future as Future03;
 use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
 
+use crate::components::metrics::{MetricsRegistry, PrometheusError};
+use crate::util::crash;
+use crate::util::futures::{record_retry, Backoff};
+
+/// How long we wait before restarting an essential task the first time it panics
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest we wait before restarting an essential task. An essential task that ran for
+/// longer than this before it panicked is restarted after `MIN_RESTART_DELAY` again
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref SUPERVISOR: RwLock<Option<Arc<TaskSupervisor>>> = RwLock::new(None);
+}
+
 fn abort_on_panic<T: Send + 'static>(
     f: impl Future03<Output = T> + Send + 'static,
 ) -> impl Future03<Output = T> {
     // We're crashing, unwind safety doesn't matter.
     AssertUnwindSafe(f).catch_unwind().unwrap_or_else(|_| {
         println!("Panic in tokio task, aborting!");
+        crash::flush_logs();
         std::process::abort()
     })
 }
 
+/// The message of a panic, if it has one
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.as_str()
+    } else {
+        "unknown panic"
+    }
+}
+
+/// What to do when an essential task panics
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EssentialTaskPolicy {
+    /// Restart the task, waiting longer between restarts if it keeps panicking
+    Restart,
+    /// Abort the process
+    Abort,
+}
+
+impl FromStr for EssentialTaskPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "restart" => Ok(EssentialTaskPolicy::Restart),
+            "abort" => Ok(EssentialTaskPolicy::Abort),
+            _ => Err(anyhow::anyhow!(
+                "invalid essential task policy `{}`, must be one of `restart` or `abort`",
+                s
+            )),
+        }
+    }
+}
+
+/// Reports panics in named tasks and decides what happens to essential tasks that panic
+pub struct TaskSupervisor {
+    logger: Logger,
+    panics: Box<CounterVec>,
+    policy: EssentialTaskPolicy,
+}
+
+impl TaskSupervisor {
+    pub fn new(
+        logger: &Logger,
+        registry: Arc<impl MetricsRegistry>,
+        policy: EssentialTaskPolicy,
+    ) -> Result<Self, PrometheusError> {
+        let panics = registry.new_counter_vec(
+            "task_panics_total",
+            "Counts panics in background tasks by the name of the task",
+            vec![String::from("task")],
+        )?;
+        Ok(TaskSupervisor {
+            logger: logger.new(slog::o!("component" => "TaskSupervisor")),
+            panics,
+            policy,
+        })
+    }
+
+    /// Make this the supervisor for tasks spawned with `spawn_named` and `spawn_essential`
+    pub fn install(self: Arc<Self>) {
+        *SUPERVISOR.write().unwrap() = Some(self);
+    }
+
+    fn report(&self, task: &str, payload: &(dyn Any + Send)) {
+        crit!(self.logger, "Task panicked";
+              "task" => task,
+              "panic" => panic_message(payload));
+        self.panics.with_label_values(&[task]).inc();
+    }
+
+    /// Spawn `f` as the task `name`. A panic in the task is reported and
+    /// results in an `Err` in the `JoinHandle`
+    pub fn spawn<T: Send + 'static>(
+        self: &Arc<Self>,
+        name: impl Into<String>,
+        f: impl Future03<Output = T> + Send + 'static,
+    ) -> JoinHandle<T> {
+        let supervisor = self.clone();
+        let name = name.into();
+        tokio::spawn(async move {
+            // We report the panic ourselves
+            match crash::catch_unwind_async(f).await {
+                Ok(res) => res,
+                Err(payload) => {
+                    supervisor.report(&name, payload.as_ref());
+                    std::panic::resume_unwind(payload)
+                }
+            }
+        })
+    }
+
+    /// Spawn the essential task `name` that runs the futures produced by
+    /// `make`. Every time the task panics, it is either restarted by
+    /// calling `make` again, or the process is aborted. The task finishes
+    /// once a future produced by `make` finishes
+    pub fn spawn_essential<F, Fut>(
+        self: &Arc<Self>,
+        name: impl Into<String>,
+        mut make: F,
+    ) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future03 + Send + 'static,
+    {
+        let supervisor = self.clone();
+        let name = name.into();
+        tokio::spawn(async move {
+            let mut backoff = Backoff::new(MIN_RESTART_DELAY, MAX_RESTART_DELAY);
+            loop {
+                let start = Instant::now();
+                // Calling `make` can panic, too. Panics that take the process
+                // down are left to the panic hook to report
+                let task = AssertUnwindSafe(async { make().await });
+                let res = match supervisor.policy {
+                    EssentialTaskPolicy::Restart => crash::catch_unwind_async(task).await,
+                    EssentialTaskPolicy::Abort => task.catch_unwind().await,
+                };
+                let payload = match res {
+                    Ok(_) => return,
+                    Err(payload) => payload,
+                };
+                supervisor.report(&name, payload.as_ref());
+
+                if supervisor.policy == EssentialTaskPolicy::Abort {
+                    crit!(supervisor.logger, "Essential task panicked, aborting"; "task" => &name);
+                    crash::flush_logs();
+                    std::process::abort();
+                }
+
+                if start.elapsed() > MAX_RESTART_DELAY {
+                    backoff.reset();
+                }
+                let delay = backoff.next_delay();
+                record_retry(&name);
+                warn!(supervisor.logger, "Restarting essential task";
+                      "task" => &name,
+                      "delay_ms" => delay.as_millis());
+                tokio::time::sleep(delay).await;
+            }
+        })
+    }
+}
+
+fn supervisor() -> Option<Arc<TaskSupervisor>> {
+    SUPERVISOR.read().unwrap().clone()
+}
+
+/// Spawn `f` as the task `name`. If a `TaskSupervisor` is installed, a panic in the task is
+/// reported and results in an `Err` in the `JoinHandle`; otherwise, it aborts the process.
+pub fn spawn_named<T: Send + 'static>(
+    name: impl Into<String>,
+    f: impl Future03<Output = T> + Send + 'static,
+) -> JoinHandle<T> {
+    match supervisor() {
+        Some(supervisor) => supervisor.spawn(name, f),
+        None => spawn(f),
+    }
+}
+
+/// Spawn the essential task `name` that runs the futures produced by `make`. If a
+/// `TaskSupervisor` is installed, the task is restarted or aborts the process when it panics,
+/// depending on the policy of the supervisor; otherwise, a panic aborts the process.
+pub fn spawn_essential<F, Fut>(name: impl Into<String>, mut make: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future03 + Send + 'static,
+{
+    match supervisor() {
+        Some(supervisor) => supervisor.spawn_essential(name, make),
+        None => spawn(async move {
+            make().await;
+        }),
+    }
+}
+
 /// Aborts on panic.
 pub fn spawn<T: Send + 'static>(f: impl Future03<Output = T> + Send + 'static) -> JoinHandle<T> {
     tokio::spawn(abort_on_panic(f))
@@ -0,0 +1,193 @@
+//! Notice when a wall clock jumps. Durations inside the node are measured
+//! with `Instant`, which only ever moves forward, and the wall clock is
+//! only used for timestamps that get stored or shown. Decisions that
+//! compare wall clock times, like whether a node is dead because its last
+//! heartbeat is too old, go wrong when the clock is set forward or back
+//! by a lot, for example, by a misconfigured NTP daemon. A `ClockWatch`
+//! compares how far a wall clock moved between observations with how much
+//! time actually passed according to `Instant` and complains loudly when
+//! the two disagree by more than `MAX_CLOCK_JUMP`
+use slog::{crit, Logger};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How much a wall clock may drift from the monotonic clock between two
+/// observations before we consider it to have jumped
+pub const MAX_CLOCK_JUMP: Duration = Duration::from_secs(60);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClockJump {
+    /// The wall clock was set back by this much
+    Backward(Duration),
+    /// The wall clock was set forward by this much
+    Forward(Duration),
+}
+
+#[derive(Default)]
+struct State {
+    /// The previous observation
+    last: Option<(Instant, SystemTime)>,
+    /// When we last noticed a jump
+    last_jump: Option<Instant>,
+}
+
+/// Watches one wall clock, either the one on this machine or the one on
+/// a database server
+pub struct ClockWatch {
+    name: &'static str,
+    state: Mutex<State>,
+}
+
+impl ClockWatch {
+    pub fn new(name: &'static str) -> Self {
+        ClockWatch {
+            name,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Record that the wall clock currently shows `wall` and log a
+    /// critical message if it jumped since the last observation
+    pub fn observe(&self, logger: &Logger, wall: SystemTime) -> Option<ClockJump> {
+        self.observe_at(logger, Instant::now(), wall)
+    }
+
+    fn observe_at(&self, logger: &Logger, now: Instant, wall: SystemTime) -> Option<ClockJump> {
+        let mut state = self.state.lock().unwrap();
+        let jump = state.last.and_then(|(last, last_wall)| {
+            detect(now.saturating_duration_since(last), last_wall, wall)
+        });
+        state.last = Some((now, wall));
+        if let Some(jump) = jump {
+            state.last_jump = Some(now);
+            let (direction, by) = match jump {
+                ClockJump::Backward(by) => ("backward", by),
+                ClockJump::Forward(by) => ("forward", by),
+            };
+            crit!(logger, "The {} clock jumped {} by {}s", self.name, direction, by.as_secs();
+                  "clock" => self.name,
+                  "direction" => direction,
+                  "jump_s" => by.as_secs());
+        }
+        jump
+    }
+
+    /// Whether the clock has not jumped in the last `period`. Decisions
+    /// that compare wall clock times should wait until the clock has been
+    /// settled for as long as the times they compare span
+    pub fn settled(&self, period: Duration) -> bool {
+        self.settled_at(Instant::now(), period)
+    }
+
+    fn settled_at(&self, now: Instant, period: Duration) -> bool {
+        match self.state.lock().unwrap().last_jump {
+            Some(last_jump) => now.saturating_duration_since(last_jump) >= period,
+            None => true,
+        }
+    }
+}
+
+/// Compare the wall clock moving from `last_wall` to `wall` with `elapsed`
+/// time passing on the monotonic clock
+fn detect(elapsed: Duration, last_wall: SystemTime, wall: SystemTime) -> Option<ClockJump> {
+    match wall.duration_since(last_wall) {
+        Ok(moved) if moved > elapsed + MAX_CLOCK_JUMP => Some(ClockJump::Forward(moved - elapsed)),
+        Ok(moved) if moved + MAX_CLOCK_JUMP < elapsed => Some(ClockJump::Backward(elapsed - moved)),
+        Ok(_) => None,
+        Err(e) => {
+            let back = e.duration() + elapsed;
+            if back > MAX_CLOCK_JUMP {
+                Some(ClockJump::Backward(back))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::o;
+
+    const TEN_MINUTES: Duration = Duration::from_secs(600);
+
+    fn logger() -> Logger {
+        Logger::root(slog::Discard, o!())
+    }
+
+    #[test]
+    fn ignores_normal_drift() {
+        let watch = ClockWatch::new("test");
+        let logger = logger();
+        let start = Instant::now();
+        let wall = SystemTime::now();
+
+        assert_eq!(None, watch.observe_at(&logger, start, wall));
+        for i in 1..20u64 {
+            let now = start + Duration::from_secs(30 * i);
+            // The wall clock runs a little fast or slow
+            let wall = wall + Duration::from_secs(30 * i) + Duration::from_millis(100 * i);
+            assert_eq!(None, watch.observe_at(&logger, now, wall));
+        }
+        assert!(watch.settled_at(start + Duration::from_secs(600), TEN_MINUTES));
+    }
+
+    #[test]
+    fn detects_backward_jump() {
+        let watch = ClockWatch::new("test");
+        let logger = logger();
+        let start = Instant::now();
+        let wall = SystemTime::now();
+
+        watch.observe_at(&logger, start, wall);
+        // 30s pass, but the wall clock is set back by 10 minutes
+        let now = start + Duration::from_secs(30);
+        let jumped = wall + Duration::from_secs(30) - TEN_MINUTES;
+        assert_eq!(
+            Some(ClockJump::Backward(TEN_MINUTES)),
+            watch.observe_at(&logger, now, jumped)
+        );
+
+        // Comparisons of wall clock times can not be trusted until the
+        // clock has been stable for the period they span
+        assert!(!watch.settled_at(now, TEN_MINUTES));
+        assert!(!watch.settled_at(now + Duration::from_secs(599), TEN_MINUTES));
+        assert!(watch.settled_at(now + TEN_MINUTES, TEN_MINUTES));
+
+        // The clock keeps running normally from where it jumped to
+        let later = now + Duration::from_secs(30);
+        assert_eq!(
+            None,
+            watch.observe_at(&logger, later, jumped + Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn detects_forward_jump() {
+        let watch = ClockWatch::new("test");
+        let logger = logger();
+        let start = Instant::now();
+        let wall = SystemTime::now();
+
+        watch.observe_at(&logger, start, wall);
+        let now = start + Duration::from_secs(30);
+        assert_eq!(
+            Some(ClockJump::Forward(TEN_MINUTES)),
+            watch.observe_at(&logger, now, wall + Duration::from_secs(30) + TEN_MINUTES)
+        );
+        assert!(!watch.settled_at(now, TEN_MINUTES));
+    }
+
+    #[test]
+    fn detects_stopped_clock() {
+        // A wall clock that does not move while time passes is as bad as
+        // one that was set back
+        let elapsed = Duration::from_secs(120);
+        let wall = SystemTime::now();
+        assert_eq!(
+            Some(ClockJump::Backward(elapsed)),
+            detect(elapsed, wall, wall)
+        );
+    }
+}
@@ -0,0 +1,399 @@
+//! Information for post-mortems when the node panics. `install` sets a
+//! panic hook that logs every panic together with what the panicking
+//! thread was working on. Code that works on something worth knowing about
+//! in a crash, like running a handler for a block of a deployment or
+//! serving a request, describes that with `enter` in synchronous code and
+//! with `scope` for futures.
+//!
+//! A panic also writes a crash report in JSON to `GRAPH_CRASH_REPORT_DIR`,
+//! or to `graph-node-crash-reports` in the system's temporary directory if
+//! that is not set, flushes the logs and aborts the process
+//!
+//! Panics that the node handles, because they happen inside `catch_unwind`
+//! or `catch_unwind_async` from this module, are neither logged nor
+//! reported
+use backtrace::Backtrace;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use slog::{crit, Logger};
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, PanicInfo, UnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::Poll;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What a thread or task is working on as `(key, value)` pairs
+pub type Context = Vec<(&'static str, String)>;
+
+lazy_static! {
+    /// Where crash reports go
+    static ref CRASH_REPORT_DIR: PathBuf = std::env::var_os("GRAPH_CRASH_REPORT_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("graph-node-crash-reports"));
+
+    /// Functions that write out logs that are still buffered
+    static ref FLUSHERS: Mutex<Vec<Box<dyn FnOnce() + Send>>> = Mutex::new(Vec::new());
+}
+
+/// Set once a panic is taking the process down so that panics in other
+/// threads, or in the hook itself, do not try to report again
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/// Numbers the reports of this process so that they get different files
+static REPORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The name of the threads that write logs. Panics in them are not logged
+/// since logging would wait for the thread that panicked
+pub const LOG_THREAD_NAME: &str = "log-writer";
+
+thread_local! {
+    static THREAD_CONTEXT: RefCell<Context> = RefCell::new(Vec::new());
+
+    /// How many calls to our `catch_unwind` the current thread is inside of
+    static CATCHING: Cell<usize> = Cell::new(0);
+}
+
+tokio::task_local! {
+    static TASK_CONTEXT: Context;
+}
+
+/// Removes the context added with `enter` when dropped
+pub struct ContextGuard {
+    len: usize,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        THREAD_CONTEXT.with(|context| context.borrow_mut().truncate(self.len));
+    }
+}
+
+/// Add `context` to what the current thread is working on until the
+/// returned guard is dropped
+pub fn enter(context: Context) -> ContextGuard {
+    THREAD_CONTEXT.with(|current| {
+        let mut current = current.borrow_mut();
+        let len = current.len();
+        current.extend(context);
+        ContextGuard { len }
+    })
+}
+
+/// Run `f` with `context` describing what it works on
+pub async fn scope<F: Future>(context: Context, f: F) -> F::Output {
+    TASK_CONTEXT.scope(context, f).await
+}
+
+/// Marks the current thread as catching panics until dropped
+struct Catching;
+
+impl Catching {
+    fn new() -> Self {
+        CATCHING.with(|catching| catching.set(catching.get() + 1));
+        Catching
+    }
+}
+
+impl Drop for Catching {
+    fn drop(&mut self) {
+        CATCHING.with(|catching| catching.set(catching.get() - 1));
+    }
+}
+
+/// Whether a panic on the current thread would be caught by the node
+fn is_catching() -> bool {
+    CATCHING
+        .try_with(|catching| catching.get() > 0)
+        .unwrap_or(false)
+}
+
+/// Like `std::panic::catch_unwind`. Use this for panics that the caller
+/// handles, so that the panic hook does not report them
+pub fn catch_unwind<F: FnOnce() -> R + UnwindSafe, R>(f: F) -> std::thread::Result<R> {
+    let _catching = Catching::new();
+    panic::catch_unwind(f)
+}
+
+/// Like `catch_unwind`, but for a future. Only polling `f` is considered
+/// to be inside `catch_unwind`, so panics in other futures that run on the
+/// same thread in between are still reported
+pub async fn catch_unwind_async<F: Future>(f: F) -> std::thread::Result<F::Output> {
+    let mut f = Box::pin(f);
+    futures03::future::poll_fn(move |cx| {
+        let _catching = Catching::new();
+        match panic::catch_unwind(AssertUnwindSafe(|| f.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    })
+    .await
+}
+
+/// Register `flush` to be called when logs need to be written out because
+/// the process is about to abort
+pub fn on_flush(flush: impl FnOnce() + Send + 'static) {
+    FLUSHERS.lock().unwrap().push(Box::new(flush));
+}
+
+/// Write out all buffered logs. Logs that are sent after this might get
+/// lost, so this should only be called right before the process aborts
+pub fn flush_logs() {
+    CRASHING.store(true, Ordering::SeqCst);
+    let flushers = match FLUSHERS.try_lock() {
+        Ok(mut flushers) => std::mem::take(&mut *flushers),
+        // Another thread is already flushing
+        Err(_) => return,
+    };
+    for flush in flushers {
+        flush();
+    }
+}
+
+/// The context of the current thread and task. Does not panic even if
+/// the context is being changed when the panic happens
+fn current_context() -> Context {
+    let mut context = THREAD_CONTEXT
+        .try_with(|context| {
+            context
+                .try_borrow()
+                .map(|context| context.clone())
+                .unwrap_or_default()
+        })
+        .unwrap_or_default();
+    if let Ok(task) = TASK_CONTEXT.try_with(|task| task.clone()) {
+        context.extend(task);
+    }
+    context
+}
+
+#[derive(Debug, Serialize)]
+struct CrashReport {
+    /// Seconds since the epoch
+    time: u64,
+    thread: String,
+    message: String,
+    location: Option<String>,
+    context: Vec<(String, String)>,
+    backtrace: String,
+}
+
+impl CrashReport {
+    fn new(message: String, location: Option<String>) -> Self {
+        CrashReport {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string(),
+            message,
+            location,
+            context: current_context()
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+            backtrace: format!("{:?}", Backtrace::new()),
+        }
+    }
+
+    fn from_panic(info: &PanicInfo) -> Self {
+        let message = if let Some(msg) = info.payload().downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = info.payload().downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "unknown panic".to_string()
+        };
+        let location = info.location().map(|location| location.to_string());
+        Self::new(message, location)
+    }
+
+    /// Write the report to a new file in `dir` and return its path
+    fn write(&self, dir: &Path) -> Result<PathBuf, anyhow::Error> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "crash-{}-{}-{}.json",
+            self.time,
+            std::process::id(),
+            REPORTS.fetch_add(1, Ordering::SeqCst)
+        ));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+/// Install the panic hook. Panics are logged to `logger`, reported to
+/// `GRAPH_CRASH_REPORT_DIR` and abort the process
+pub fn install(logger: Logger) {
+    install_hook(logger, CRASH_REPORT_DIR.clone(), true);
+}
+
+/// Install a panic hook that logs panics to `logger` and writes crash
+/// reports to `dir`. Only aborts if `abort` is set so that tests can check
+/// what the hook does
+fn install_hook(logger: Logger, dir: PathBuf, abort: bool) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        // The code that catches the panic deals with it
+        if is_catching() {
+            return;
+        }
+
+        // Write to stderr first in case the panic came from logging
+        default_hook(info);
+
+        // Once we are aborting, other panics do not matter anymore
+        if CRASHING.load(Ordering::SeqCst) {
+            return;
+        }
+        let report = CrashReport::from_panic(info);
+        let logger = if report.thread == LOG_THREAD_NAME {
+            None
+        } else {
+            Some(&logger)
+        };
+        crash(logger, &dir, report, abort);
+    }));
+}
+
+/// Write `report` to `dir`, log it to `logger` and, if `abort` is set,
+/// flush the logs and abort
+fn crash(logger: Option<&Logger>, dir: &Path, report: CrashReport, abort: bool) {
+    if abort {
+        CRASHING.store(true, Ordering::SeqCst);
+    }
+    let written = report.write(dir);
+
+    if let Some(logger) = logger {
+        let context = report
+            .context
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        crit!(logger, "Panic: {}", report.message;
+              "thread" => &report.thread,
+              "location" => report.location.as_deref().unwrap_or("unknown"),
+              "context" => &context);
+        match written {
+            Ok(path) => crit!(logger, "Wrote crash report";
+                              "path" => path.display().to_string()),
+            Err(e) => crit!(logger, "Failed to write crash report";
+                            "error" => e.to_string()),
+        }
+    }
+
+    if abort {
+        // Panics in the log threads can not wait for the logs
+        if logger.is_some() {
+            flush_logs();
+        }
+        std::process::abort();
+    }
+}
+
+#[test]
+fn catching_is_scoped() {
+    assert!(!is_catching());
+    let res = catch_unwind(|| {
+        assert!(is_catching());
+        let nested = catch_unwind(|| is_catching());
+        assert_eq!(true, nested.unwrap());
+        assert!(is_catching());
+        panic!("caught");
+    });
+    assert!(res.is_err());
+    assert!(!is_catching());
+
+    let res = futures03::executor::block_on(catch_unwind_async(async {
+        assert!(is_catching());
+        panic!("caught");
+    }));
+    assert!(res.is_err());
+    assert!(!is_catching());
+}
+
+#[test]
+fn hook_reports_context() {
+    let dir = std::env::temp_dir().join(format!("graph-crash-hook-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    install_hook(Logger::root(slog::Discard, slog::o!()), dir.clone(), false);
+
+    let res = std::thread::Builder::new()
+        .name("handler-thread".to_string())
+        .spawn(|| {
+            let _deployment = enter(vec![
+                ("deployment", "QmDeployment".to_string()),
+                ("block", "#12 (0x0c)".to_string()),
+            ]);
+            let _handler = enter(vec![("handler", "handleTransfer".to_string())]);
+            panic!("handler panicked");
+        })
+        .unwrap()
+        .join();
+    // Put the default hook back
+    let _ = panic::take_hook();
+    assert!(res.is_err());
+
+    // Other tests might panic while the hook is installed
+    let reports: Vec<serde_json::Value> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        })
+        .filter(|report: &serde_json::Value| report["message"] == "handler panicked")
+        .collect();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(1, reports.len());
+    let report = &reports[0];
+    assert_eq!("handler-thread", report["thread"]);
+    assert!(report["location"].as_str().unwrap().starts_with(file!()));
+    assert_eq!(
+        serde_json::json!([
+            ["deployment", "QmDeployment"],
+            ["block", "#12 (0x0c)"],
+            ["handler", "handleTransfer"]
+        ]),
+        report["context"]
+    );
+    assert!(!CRASHING.load(Ordering::SeqCst));
+}
+
+#[test]
+fn report_names_context() {
+    let report = {
+        let _deployment = enter(vec![
+            ("deployment", "QmDeployment".to_string()),
+            ("block", "#12 (0x0c)".to_string()),
+        ]);
+        let _handler = enter(vec![("handler", "handleTransfer".to_string())]);
+        CrashReport::new("handler panicked".to_string(), None)
+    };
+    // Dropping the guards removes the context
+    assert!(current_context().is_empty());
+
+    assert_eq!(
+        vec![
+            ("deployment".to_string(), "QmDeployment".to_string()),
+            ("block".to_string(), "#12 (0x0c)".to_string()),
+            ("handler".to_string(), "handleTransfer".to_string()),
+        ],
+        report.context
+    );
+
+    let dir = std::env::temp_dir().join(format!("graph-crash-test-{}", std::process::id()));
+    let path = report.write(&dir).unwrap();
+    let written: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!("handler panicked", written["message"]);
+    assert_eq!("QmDeployment", written["context"][0][1]);
+    assert_eq!("#12 (0x0c)", written["context"][1][1]);
+}
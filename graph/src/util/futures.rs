@@ -1,21 +1,108 @@
+use crate::components::metrics::{CounterVec, MetricsRegistry, Opts};
 use crate::ext::futures::FutureExtension;
 use futures03::{Future, FutureExt, TryFutureExt};
+use lazy_static::lazy_static;
 use slog::{debug, trace, warn, Logger};
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
-use tokio_retry::strategy::{jitter, ExponentialBackoff};
 use tokio_retry::Retry;
 
+lazy_static! {
+    static ref RETRIES: CounterVec = CounterVec::new(
+        Opts::new(
+            "retries_total",
+            "Counts how often an operation was retried, by operation name"
+        ),
+        &["operation"]
+    )
+    .expect("failed to create `retries_total` counter");
+}
+
+/// Register the metric that counts retries by operation name with
+/// `registry`. Retries are counted whether or not the metric is registered
+pub fn register_retry_metrics(registry: &impl MetricsRegistry) {
+    registry.register("retries_total", Box::new(RETRIES.clone()));
+}
+
+/// Count a retry of `operation` in the `retries_total` metric. Only needed
+/// for code that can not use `retry` and waits for a `Backoff` itself, like
+/// the block streams, the block ingestor, WebSocket reconnects and restarts
+/// of essential tasks.
+///
+/// Writes of blocks to the store and the operations of the registrar are
+/// deliberately not retried: a failed block write fails the deployment,
+/// which is then restarted from what is in the store, and registrar
+/// operations report their errors to whoever asked for them
+pub fn record_retry(operation: &str) {
+    RETRIES.with_label_values(&[operation]).inc();
+}
+
+/// An exponential backoff curve: the delay starts at `initial`, doubles
+/// with every attempt and is capped at `max`. With jitter, which is on by
+/// default, every delay is scaled by a random factor between 0.5 and 1 so
+/// that many callers that fail at the same time do not all retry at the
+/// same time, while still waiting at least half the delay
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    jitter: bool,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Backoff {
+            initial,
+            max,
+            jitter: true,
+            attempt: 0,
+        }
+    }
+
+    /// Always wait for exactly the delay given by the curve
+    pub fn without_jitter(mut self) -> Self {
+        self.jitter = false;
+        self
+    }
+
+    /// The delay to wait before the next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let factor = 2f64.powi(self.attempt.min(32) as i32);
+        self.attempt = self.attempt.saturating_add(1);
+        let delay = self.initial.mul_f64(factor).min(self.max);
+        if self.jitter {
+            delay.mul_f64(0.5 + rand::random::<f64>() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    /// Start over with the initial delay, e.g., after an attempt succeeded
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Iterator for Backoff {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        Some(self.next_delay())
+    }
+}
+
 /// Generic helper function for retrying async operations with built-in logging.
 ///
 /// To use this helper, do the following:
 ///
 /// 1. Call this function with an operation name (used for logging) and a `Logger`.
 /// 2. Optional: Chain a call to `.when(...)` to set a custom retry condition.
-/// 3. Optional: call `.log_after(...)` or `.no_logging()`.
+/// 3. Optional: call `.log_after(...)` or `.no_logging()`, and `.backoff(...)` or
+///    `.no_jitter()` to change how long to wait between attempts.
 /// 4. Call either `.limit(...)` or `.no_limit()`.
 /// 5. Call one of `.timeout_secs(...)`, `.timeout_millis(...)`, `.timeout(...)`, and
 ///    `.no_timeout()`.
@@ -23,6 +110,11 @@ use tokio_retry::Retry;
 ///
 /// All steps are required, except Step 2 and Step 3.
 ///
+/// Attempts are spaced out with a jittered exponential `Backoff` that starts
+/// at 2ms and is capped at 30s. Every retry is counted in the
+/// `retries_total` metric under the operation name, which should therefore
+/// not contain things like block hashes; put those into the logger instead.
+///
 /// Example usage:
 /// ```
 /// # extern crate graph;
@@ -55,6 +147,7 @@ pub fn retry<I, E>(operation_name: impl ToString, logger: &Logger) -> RetryConfi
         log_after: 1,
         warn_after: 10,
         limit: RetryConfigProperty::Unknown,
+        backoff: Backoff::new(Duration::from_millis(2), Duration::from_secs(30)),
         phantom_item: PhantomData,
         phantom_error: PhantomData,
     }
@@ -67,6 +160,7 @@ pub struct RetryConfig<I, E> {
     log_after: u64,
     warn_after: u64,
     limit: RetryConfigProperty<usize>,
+    backoff: Backoff,
     phantom_item: PhantomData<I>,
     phantom_error: PhantomData<E>,
 }
@@ -119,6 +213,23 @@ where
         self
     }
 
+    /// Wait `initial` before the first retry, and double that for every
+    /// further retry up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff {
+            initial,
+            max,
+            ..self.backoff
+        };
+        self
+    }
+
+    /// Wait exactly as long as the backoff curve says between attempts
+    pub fn no_jitter(mut self) -> Self {
+        self.backoff = self.backoff.without_jitter();
+        self
+    }
+
     /// Set how long (in seconds) to wait for an attempt to complete before giving up on that
     /// attempt.
     pub fn timeout_secs(self, timeout_secs: u64) -> RetryConfigWithTimeout<I, E> {
@@ -167,6 +278,7 @@ where
         let log_after = self.inner.log_after;
         let warn_after = self.inner.warn_after;
         let limit_opt = self.inner.limit.unwrap(&operation_name, "limit");
+        let backoff = self.inner.backoff;
         let timeout = self.timeout;
 
         trace!(logger, "Run with retry: {}", operation_name);
@@ -178,6 +290,7 @@ where
             log_after,
             warn_after,
             limit_opt,
+            backoff,
             move || {
                 try_it()
                     .timeout(timeout)
@@ -208,6 +321,7 @@ impl<I, E> RetryConfigNoTimeout<I, E> {
         let log_after = self.inner.log_after;
         let warn_after = self.inner.warn_after;
         let limit_opt = self.inner.limit.unwrap(&operation_name, "limit");
+        let backoff = self.inner.backoff;
 
         trace!(logger, "Run with retry: {}", operation_name);
 
@@ -218,6 +332,7 @@ impl<I, E> RetryConfigNoTimeout<I, E> {
             log_after,
             warn_after,
             limit_opt,
+            backoff,
             // No timeout, so all errors are inner errors
             move || try_it().map_err(TimeoutError::Inner),
         )
@@ -259,6 +374,7 @@ fn run_retry<O, E, F, R>(
     log_after: u64,
     warn_after: u64,
     limit_opt: Option<usize>,
+    backoff: Backoff,
     mut try_it_with_timeout: F,
 ) -> impl Future<Output = Result<O, TimeoutError<E>>> + Send
 where
@@ -271,12 +387,14 @@ where
 
     let mut attempt_count = 0;
 
-    Retry::spawn(retry_strategy(limit_opt), move || {
+    Retry::spawn(retry_strategy(limit_opt, backoff), move || {
         let operation_name = operation_name.clone();
         let logger = logger.clone();
         let condition = condition.clone();
 
         attempt_count += 1;
+        // The last attempt is not followed by a retry
+        let retries = limit_opt.map_or(true, |limit| attempt_count < limit as u64);
 
         try_it_with_timeout().then(move |result_with_timeout| {
             let is_elapsed = result_with_timeout
@@ -286,6 +404,9 @@ where
                 .unwrap_or(false);
 
             if is_elapsed {
+                if retries {
+                    record_retry(&operation_name);
+                }
                 if attempt_count >= log_after {
                     debug!(
                         logger,
@@ -305,6 +426,9 @@ where
 
                 // If needs retry
                 if condition.check(&result) {
+                    if retries {
+                        record_retry(&operation_name);
+                    }
                     if attempt_count >= warn_after {
                         // This looks like it would be nice to de-duplicate, but if we try
                         // to use log! slog complains about requiring a const for the log level
@@ -347,13 +471,10 @@ where
     })
 }
 
-fn retry_strategy(limit_opt: Option<usize>) -> Box<dyn Iterator<Item = Duration> + Send> {
-    // Exponential backoff, but with a maximum
-    let max_delay_ms = 30_000;
-    let backoff = ExponentialBackoff::from_millis(2)
-        .max_delay(Duration::from_millis(max_delay_ms))
-        .map(jitter);
-
+fn retry_strategy(
+    limit_opt: Option<usize>,
+    backoff: Backoff,
+) -> Box<dyn Iterator<Item = Duration> + Send> {
     // Apply limit (maximum retry count)
     match limit_opt {
         Some(limit) => {
@@ -545,4 +666,50 @@ mod tests {
 
         assert_eq!(result, Ok(10));
     }
+
+    #[test]
+    fn backoff_curve() {
+        let ms = Duration::from_millis;
+
+        let mut backoff = Backoff::new(ms(100), ms(1000)).without_jitter();
+        let delays: Vec<_> = backoff.by_ref().take(6).collect();
+        assert_eq!(
+            vec![ms(100), ms(200), ms(400), ms(800), ms(1000), ms(1000)],
+            delays
+        );
+        backoff.reset();
+        assert_eq!(ms(100), backoff.next_delay());
+
+        // Jitter waits at least half of the delay
+        let curve = Backoff::new(ms(100), ms(1000)).without_jitter();
+        let jittered = Backoff::new(ms(100), ms(1000));
+        for (delay, jittered) in curve.zip(jittered).take(20) {
+            assert!(jittered >= delay / 2 && jittered <= delay);
+        }
+    }
+
+    #[test]
+    fn retries_are_counted() {
+        const OPERATION: &str = "test retries are counted";
+
+        let logger = Logger::root(::slog::Discard, o!());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let result = runtime.block_on({
+            retry(OPERATION, &logger)
+                .no_logging()
+                .limit(5)
+                .no_jitter()
+                .no_timeout()
+                .run(move || future::err::<(), _>(()).compat())
+        });
+        assert_eq!(result, Err(()));
+
+        // The last attempt is not followed by a retry
+        let retries = RETRIES.with_label_values(&[OPERATION]).get();
+        assert_eq!(4.0, retries);
+    }
 }
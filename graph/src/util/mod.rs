@@ -17,3 +17,13 @@ pub mod cache_weight;
 pub mod timed_rw_lock;
 
 pub mod jobs;
+
+pub mod shutdown;
+
+pub mod profiler;
+
+pub mod crash;
+
+pub mod scan_counter;
+
+pub mod clock;
@@ -0,0 +1,194 @@
+//! Profiling the mappings of a deployment while it indexes. While a
+//! deployment is profiled, the runtime runs its mappings instrumented so
+//! that they check with the profiler at the start of every function and
+//! every loop iteration, and also whenever they call into the host. At
+//! those points, the runtime records the stack of the running mapping if
+//! at least `SAMPLE_INTERVAL` has passed since the last sample. Each
+//! sample is weighted with the microseconds that passed since the
+//! previous one. When no deployment is profiled, `profile_for` only costs
+//! an atomic load, and checking whether a sample is due never takes a lock
+use anyhow::{anyhow, Error};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::data::subgraph::DeploymentHash;
+
+/// Do not take samples more often than this since getting the stack of
+/// the mapping is expensive
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(1);
+
+lazy_static! {
+    static ref PROFILES: RwLock<HashMap<DeploymentHash, Arc<Profile>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// The number of entries in `PROFILES`
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Profile {
+    /// Times are measured in microseconds since this
+    start: Instant,
+    /// When the last sample was taken, or the current handler started
+    last: AtomicU64,
+    /// Microseconds spent in each stack. Frames are separated by `;` and
+    /// the outermost frame comes first
+    stacks: Mutex<HashMap<String, u64>>,
+}
+
+impl Profile {
+    fn new() -> Self {
+        Profile {
+            start: Instant::now(),
+            last: AtomicU64::new(0),
+            stacks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_micros() as u64
+    }
+
+    /// Start measuring time for a new handler so that time spent outside
+    /// of handlers is not attributed to any stack
+    pub fn enter(&self) {
+        self.last.store(self.now(), Ordering::Relaxed);
+    }
+
+    /// Whether enough time has passed since the last sample to take
+    /// another one
+    pub fn due(&self) -> bool {
+        let elapsed = self.now().saturating_sub(self.last.load(Ordering::Relaxed));
+        elapsed >= SAMPLE_INTERVAL.as_micros() as u64
+    }
+
+    /// Attribute the time since the last sample to `stack`, which must
+    /// list the outermost frame first
+    pub fn record(&self, stack: &[String]) {
+        let stack = stack
+            .iter()
+            .map(|frame| frame.replace(';', ":"))
+            .collect::<Vec<_>>()
+            .join(";");
+        let now = self.now();
+        let micros = now.saturating_sub(self.last.swap(now, Ordering::Relaxed));
+        *self.stacks.lock().unwrap().entry(stack).or_insert(0) += micros;
+    }
+
+    /// A report in the collapsed stack format that flamegraph tools
+    /// understand, with the most expensive stacks first
+    fn report(&self) -> String {
+        let stacks = self.stacks.lock().unwrap();
+        let mut stacks: Vec<_> = stacks.iter().collect();
+        stacks.sort_by(|(s1, m1), (s2, m2)| m2.cmp(m1).then_with(|| s1.cmp(s2)));
+        stacks
+            .into_iter()
+            .map(|(stack, micros)| format!("{} {}\n", stack, micros))
+            .collect()
+    }
+}
+
+/// Removes the profile for a deployment when dropped, so that it also
+/// gets removed if the future in `profile` is cancelled
+struct Registration(DeploymentHash);
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if PROFILES.write().unwrap().remove(&self.0).is_some() {
+            ACTIVE.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The profile that samples for `deployment` should be added to, if it is
+/// being profiled
+pub fn profile_for(deployment: &DeploymentHash) -> Option<Arc<Profile>> {
+    if ACTIVE.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    PROFILES.read().unwrap().get(deployment).cloned()
+}
+
+/// Profile the handlers of `deployment` that run in this process for
+/// `duration` and return the report. Fails if the deployment is already
+/// being profiled
+pub async fn profile(deployment: DeploymentHash, duration: Duration) -> Result<String, Error> {
+    let profile = Arc::new(Profile::new());
+    let _registration = {
+        let mut profiles = PROFILES.write().unwrap();
+        if profiles.contains_key(&deployment) {
+            return Err(anyhow!(
+                "deployment {} is already being profiled",
+                deployment
+            ));
+        }
+        profiles.insert(deployment.clone(), profile.clone());
+        ACTIVE.fetch_add(1, Ordering::SeqCst);
+        Registration(deployment)
+    };
+
+    tokio::time::sleep(duration).await;
+
+    Ok(profile.report())
+}
+
+#[test]
+fn report_ranks_stacks_by_time() {
+    let profile = Profile::new();
+    {
+        let mut stacks = profile.stacks.lock().unwrap();
+        stacks.insert("handleA;a".to_string(), 10_000_000);
+        stacks.insert("handleB;b;c".to_string(), 30_000_000);
+    }
+    profile.record(&["handleA".to_string(), "x;y".to_string()]);
+    let report = profile.report();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(3, lines.len());
+    assert_eq!("handleB;b;c 30000000", lines[0]);
+    assert_eq!("handleA;a 10000000", lines[1]);
+    assert!(lines[2].starts_with("handleA;x:y "));
+}
+
+#[test]
+fn samples_are_weighted_with_elapsed_time() {
+    let profile = Profile::new();
+    profile.enter();
+    assert!(!profile.due());
+
+    std::thread::sleep(2 * SAMPLE_INTERVAL);
+    assert!(profile.due());
+    profile.record(&["handleA".to_string(), "loop".to_string()]);
+    assert!(!profile.due());
+
+    // Time between handlers does not count
+    std::thread::sleep(2 * SAMPLE_INTERVAL);
+    profile.enter();
+    profile.record(&["handleB".to_string()]);
+
+    let stacks = profile.stacks.lock().unwrap();
+    assert!(stacks["handleA;loop"] >= 2 * SAMPLE_INTERVAL.as_micros() as u64);
+    assert!(stacks["handleB"] < 2 * SAMPLE_INTERVAL.as_micros() as u64);
+}
+
+#[test]
+fn profile_for_registered_deployments() {
+    let deployment = DeploymentHash::new("QmProfiled").unwrap();
+    assert!(profile_for(&deployment).is_none());
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let report = runtime.block_on(async {
+        let profiling = tokio::spawn(profile(deployment.clone(), Duration::from_millis(200)));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(profile(deployment.clone(), Duration::from_millis(1))
+            .await
+            .is_err());
+        let running = profile_for(&deployment).expect("deployment is profiled");
+        running.enter();
+        running.record(&["handleA".to_string()]);
+        profiling.await.unwrap().unwrap()
+    });
+    assert!(report.starts_with("handleA "));
+    assert!(profile_for(&deployment).is_none());
+}
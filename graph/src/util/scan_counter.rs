@@ -0,0 +1,175 @@
+//! Count how much data deployments request from their chains. Chains add
+//! to the counter of a deployment while they fetch data for it, and the
+//! instance manager moves what was counted into the store every
+//! `FLUSH_INTERVAL`, where it adds up to the `ScanTotals` of the
+//! deployment across restarts. Counters live as long as something in the
+//! process uses them; the instance manager keeps the counter of a
+//! deployment while the deployment runs
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use crate::data::subgraph::{DeploymentHash, ScanTotals};
+
+/// How often the instance manager writes counts to the store
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    static ref COUNTERS: Mutex<HashMap<DeploymentHash, Weak<ScanCounter>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// The number of bytes `value` takes up in JSON. The JSON is not kept
+/// around, we only count the bytes as they are written
+pub fn json_size<T: Serialize + ?Sized>(value: &T) -> u64 {
+    struct Counter(u64);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+/// What a deployment requested since its counts were last taken
+#[derive(Debug, Default)]
+pub struct ScanCounter {
+    blocks: AtomicU64,
+    logs: AtomicU64,
+    traces: AtomicU64,
+    eth_calls: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl ScanCounter {
+    /// The counter for `deployment`, shared by everything in this process
+    /// that fetches data for it
+    pub fn for_deployment(deployment: &DeploymentHash) -> Arc<ScanCounter> {
+        let mut counters = COUNTERS.lock().unwrap();
+        if let Some(counter) = counters.get(deployment).and_then(Weak::upgrade) {
+            return counter;
+        }
+
+        // Forget the counters that nothing uses anymore so that the map
+        // does not keep growing as deployments come and go
+        counters.retain(|_, counter| counter.strong_count() > 0);
+        let counter = Arc::new(ScanCounter::default());
+        counters.insert(deployment.clone(), Arc::downgrade(&counter));
+        counter
+    }
+
+    /// `count` blocks were scanned for triggers
+    pub fn add_blocks(&self, count: u64) {
+        self.blocks.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// `count` logs were fetched in responses of `bytes` bytes
+    pub fn add_logs(&self, count: u64, bytes: u64) {
+        self.logs.fetch_add(count, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// `count` traces were fetched in responses of `bytes` bytes
+    pub fn add_traces(&self, count: u64, bytes: u64) {
+        self.traces.fetch_add(count, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// A mapping made an `ethereum.call`
+    pub fn add_eth_call(&self) {
+        self.eth_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Return what was counted since the last call and start counting
+    /// from zero
+    pub fn take(&self) -> ScanTotals {
+        ScanTotals {
+            blocks: self.blocks.swap(0, Ordering::Relaxed),
+            logs: self.logs.swap(0, Ordering::Relaxed),
+            traces: self.traces.swap(0, Ordering::Relaxed),
+            eth_calls: self.eth_calls.swap(0, Ordering::Relaxed),
+            bytes: self.bytes.swap(0, Ordering::Relaxed),
+            reset_at: None,
+        }
+    }
+
+    /// Count `totals` again, for example, because they could not be
+    /// written to the store
+    pub fn restore(&self, totals: &ScanTotals) {
+        self.blocks.fetch_add(totals.blocks, Ordering::Relaxed);
+        self.logs.fetch_add(totals.logs, Ordering::Relaxed);
+        self.traces.fetch_add(totals.traces, Ordering::Relaxed);
+        self.eth_calls
+            .fetch_add(totals.eth_calls, Ordering::Relaxed);
+        self.bytes.fetch_add(totals.bytes, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn take_and_restore() {
+    let deployment = DeploymentHash::new("QmScanCounter").unwrap();
+    let counter = ScanCounter::for_deployment(&deployment);
+    assert!(Arc::ptr_eq(
+        &counter,
+        &ScanCounter::for_deployment(&deployment)
+    ));
+
+    counter.add_blocks(100);
+    counter.add_logs(3, 1000);
+    counter.add_traces(2, 500);
+    counter.add_eth_call();
+    let totals = counter.take();
+    assert_eq!(
+        ScanTotals {
+            blocks: 100,
+            logs: 3,
+            traces: 2,
+            eth_calls: 1,
+            bytes: 1500,
+            reset_at: None,
+        },
+        totals
+    );
+    assert!(counter.take().is_empty());
+
+    counter.restore(&totals);
+    counter.add_blocks(1);
+    assert_eq!(101, counter.take().blocks);
+
+    // Counters that nothing uses anymore are forgotten
+    counter.add_blocks(5);
+    drop(counter);
+    let other = DeploymentHash::new("QmScanCounterOther").unwrap();
+    let _other = ScanCounter::for_deployment(&other);
+    {
+        let counters = COUNTERS.lock().unwrap();
+        assert!(!counters.contains_key(&deployment));
+        assert!(counters.contains_key(&other));
+    }
+    assert!(ScanCounter::for_deployment(&deployment).take().is_empty());
+}
+
+#[test]
+fn json_size_counts_serialized_bytes() {
+    let value = serde_json::json!({ "logs": [1, 2, 3], "hash": "0x00ff" });
+    assert_eq!(
+        serde_json::to_vec(&value).unwrap().len() as u64,
+        json_size(&value)
+    );
+    assert_eq!(2, json_size(&Vec::<u8>::new()));
+}
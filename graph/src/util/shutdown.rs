@@ -0,0 +1,105 @@
+//! Orderly shutdown of the node. Once `request` has been called, long
+//! running components like block ingestors and subgraph runners stop at
+//! the next point where they can do so without leaving work half done.
+//! While they are in the middle of such work, they hold a `ShutdownGuard`,
+//! and `wait` finishes once all guards have been dropped
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+use tokio::sync::{watch, Notify};
+
+lazy_static! {
+    static ref REQUESTED: (watch::Sender<bool>, watch::Receiver<bool>) = watch::channel(false);
+
+    /// The number of `ShutdownGuard` that are alive
+    static ref GUARDS: Mutex<usize> = Mutex::new(0);
+
+    /// Notified when the last `ShutdownGuard` is dropped
+    static ref IDLE: Notify = Notify::new();
+}
+
+/// Ask all components to stop
+pub fn request() {
+    // We hold on to a receiver ourselves, so sending can not fail
+    REQUESTED.0.send(true).ok();
+}
+
+/// Whether a shutdown has been requested
+pub fn is_requested() -> bool {
+    *REQUESTED.1.borrow()
+}
+
+/// Finishes once a shutdown has been requested
+pub async fn requested() {
+    let mut receiver = REQUESTED.1.clone();
+    while !*receiver.borrow() {
+        if receiver.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Finishes once no `ShutdownGuard` is alive anymore
+pub async fn wait() {
+    loop {
+        if *GUARDS.lock().unwrap() == 0 {
+            return;
+        }
+        // `notify_one` stores a permit if nobody is waiting yet, so we
+        // can not miss the last guard being dropped
+        IDLE.notified().await;
+    }
+}
+
+/// Keeps `wait` from finishing while it is alive
+pub struct ShutdownGuard(());
+
+/// Get a guard that keeps a shutdown waiting. Components should get a
+/// guard before they check `is_requested`, so that a shutdown either waits
+/// for them or they see that it was requested
+pub fn guard() -> ShutdownGuard {
+    *GUARDS.lock().unwrap() += 1;
+    ShutdownGuard(())
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        let mut guards = GUARDS.lock().unwrap();
+        *guards -= 1;
+        if *guards == 0 {
+            IDLE.notify_one();
+        }
+    }
+}
+
+// The shutdown state is global, and a requested shutdown can not be taken
+// back, so everything is tested in one test
+#[tokio::test]
+async fn shutdown_waits_for_guards() {
+    use std::time::Duration;
+
+    assert!(!is_requested());
+    let waiting = tokio::spawn(requested());
+    let first_guard = guard();
+    let second_guard = guard();
+
+    request();
+    assert!(is_requested());
+    tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .expect("requested finishes once a shutdown is requested")
+        .unwrap();
+    requested().await;
+
+    // Waiting finishes only once the last guard is dropped
+    drop(first_guard);
+    assert!(tokio::time::timeout(Duration::from_millis(100), wait())
+        .await
+        .is_err());
+    let waiting = tokio::spawn(wait());
+    drop(second_guard);
+    tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .expect("wait finishes once all guards are dropped")
+        .unwrap();
+    wait().await;
+}
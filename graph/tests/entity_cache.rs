@@ -207,3 +207,26 @@ fn consecutive_modifications() {
         },])
     );
 }
+
+#[test]
+fn changed_ids() {
+    let store = MockStore::new().writable(&*DEPLOYMENT).unwrap();
+    let mut cache = EntityCache::new(store.clone());
+    let band = EntityType::from("Band");
+
+    let (mogwai_key, mogwai_data) = make_band("mogwai", vec![("id", "mogwai".into())]);
+    cache.set(mogwai_key.clone(), mogwai_data);
+    let (sigurros_key, _) = make_band("sigurros", vec![]);
+    cache.remove(sigurros_key);
+    cache.set(
+        EntityKey::data(SUBGRAPH_ID.clone(), "Album".to_string(), "agaetis".into()),
+        Entity::from(vec![("id", "agaetis".into())]),
+    );
+    assert_eq!(vec!["mogwai", "sigurros"], cache.changed_ids(&band));
+
+    // Ids that were changed several times are only returned once
+    let (low_key, low_data) = make_band("low", vec![("id", "low".into())]);
+    cache.set(low_key, low_data);
+    cache.remove(mogwai_key);
+    assert_eq!(vec!["low", "mogwai", "sigurros"], cache.changed_ids(&band));
+}
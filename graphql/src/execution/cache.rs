@@ -196,7 +196,7 @@ impl QueryBlockCache {
             let shard = self.shard;
             let network = network.to_string();
 
-            graph::spawn(async move {
+            graph::spawn_named("query-cache-stats", async move {
                 let insert_time_ms = block.total_insert_time.as_millis();
                 let mut dead_inserts = 0;
                 let mut total_hits = 0;
@@ -1,7 +1,7 @@
 use super::cache::{QueryBlockCache, QueryCache};
 use crossbeam::atomic::AtomicCell;
 use graph::{
-    data::schema::META_FIELD_NAME,
+    data::schema::{META_FIELD_NAME, META_FIELD_TYPE},
     prelude::{s, CheapClone},
     util::timed_rw_lock::TimedMutex,
 };
@@ -12,12 +12,16 @@ use stable_hash::prelude::*;
 use stable_hash::utils::stable_hash;
 use std::borrow::ToOwned;
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::iter;
+use std::str::FromStr;
 use std::time::Instant;
 
 use graph::data::graphql::*;
 use graph::data::query::CacheStatus;
+use graph::data::store::BIG_DECIMAL_SCALAR;
 use graph::prelude::*;
+use graph::util::crash;
 use graph::util::lfu_cache::LfuCache;
 
 use super::QueryHash;
@@ -26,6 +30,7 @@ use crate::introspection::{
 };
 use crate::prelude::*;
 use crate::query::ast as qast;
+use crate::schema::api::DECIMALS_ARGUMENT;
 use crate::schema::ast as sast;
 use crate::values::coercion;
 
@@ -353,7 +358,10 @@ pub async fn execute_root_selection_set<R: Resolver>(
     // and once for insert.
     let mut key: Option<QueryHash> = None;
 
-    if R::CACHEABLE && (*CACHE_ALL || CACHED_SUBGRAPH_IDS.contains(ctx.query.schema.id())) {
+    // The `latestBlock` in `_meta` changes with every block, even when the
+    // data does not, and can therefore not be cached
+    let cacheable = R::CACHEABLE && !selects_latest_block(&ctx, &selection_set, &root_type);
+    if cacheable && (*CACHE_ALL || CACHED_SUBGRAPH_IDS.contains(ctx.query.schema.id())) {
         if let (Some(block_ptr), Some(network)) = (block_ptr.as_ref(), &ctx.query.network) {
             // JSONB and metadata queries use `BLOCK_NUMBER_MAX`. Ignore this case for two reasons:
             // - Metadata queries are not cacheable.
@@ -388,6 +396,7 @@ pub async fn execute_root_selection_set<R: Resolver>(
     let execute_ctx = ctx.cheap_clone();
     let execute_selection_set = selection_set.cheap_clone();
     let execute_root_type = root_type.cheap_clone();
+    let data_block = block_ptr.as_ref().map(|ptr| ptr.number);
     let run_query = async move {
         let _permit = execute_ctx.resolver.query_permit().await;
 
@@ -395,6 +404,10 @@ pub async fn execute_root_selection_set<R: Resolver>(
         let query_text = execute_ctx.query.query_text.cheap_clone();
         let variables_text = execute_ctx.query.variables_text.cheap_clone();
         match graph::spawn_blocking_allow_panic(move || {
+            let _crash_context = crash::enter(vec![
+                ("deployment", execute_ctx.query.schema.id().to_string()),
+                ("query_id", execute_ctx.query.query_id.clone()),
+            ]);
             let mut query_res = QueryResult::from(execute_root_selection_set_uncached(
                 &execute_ctx,
                 &execute_selection_set,
@@ -404,6 +417,8 @@ pub async fn execute_root_selection_set<R: Resolver>(
             // Unwrap: In practice should never fail, but if it does we will catch the panic.
             execute_ctx.resolver.post_process(&mut query_res).unwrap();
             query_res.deployment = Some(execute_ctx.query.schema.id().clone());
+            query_res.block = data_block;
+            query_res.computed_at = Some(Instant::now());
             Arc::new(query_res)
         })
         .await
@@ -484,6 +499,34 @@ pub async fn execute_root_selection_set<R: Resolver>(
     result
 }
 
+/// Whether the top-level `selection_set` asks for `_meta { latestBlock }`.
+/// The other fields of `_meta` only depend on the block of the query and
+/// can be cached like any other data
+fn selects_latest_block(
+    ctx: &ExecutionContext<impl Resolver>,
+    selection_set: &q::SelectionSet,
+    root_type: &s::ObjectType,
+) -> bool {
+    let meta_type = match ctx
+        .query
+        .schema
+        .document()
+        .get_object_type_definition(META_FIELD_TYPE)
+    {
+        Some(meta_type) => meta_type,
+        None => return false,
+    };
+    collect_fields(ctx, root_type, iter::once(selection_set))
+        .values()
+        .filter(|fields| fields[0].name == META_FIELD_NAME)
+        .any(|fields| {
+            let selection_sets = fields.iter().map(|field| &field.selection_set);
+            collect_fields(ctx, meta_type, selection_sets)
+                .values()
+                .any(|fields| fields[0].name == "latestBlock")
+        })
+}
+
 /// Executes a selection set, requiring the result to be of the given object type.
 ///
 /// Allows passing in a parent value during recursive processing of objects and their fields.
@@ -713,10 +756,57 @@ fn execute_field(
                 &field_definition.field_type,
                 &argument_values,
             )
+            .and_then(|value| round_decimals(field, field_definition, &argument_values, value))
         })
         .and_then(|value| complete_value(ctx, field, &field_definition.field_type, &fields, value))
 }
 
+/// Round the values of a `BigDecimal` field to the number of digits after
+/// the decimal point given with its `decimals` argument. This only changes
+/// how the values are rendered in the response
+fn round_decimals(
+    field: &q::Field,
+    field_definition: &s::Field,
+    argument_values: &HashMap<&str, q::Value>,
+    value: q::Value,
+) -> Result<q::Value, Vec<QueryExecutionError>> {
+    fn round(value: q::Value, decimals: u32) -> q::Value {
+        match value {
+            q::Value::String(s) => match BigDecimal::from_str(&s) {
+                Ok(d) => q::Value::String(d.round_to_decimals(decimals).to_string()),
+                Err(_) => q::Value::String(s),
+            },
+            q::Value::List(values) => q::Value::List(
+                values
+                    .into_iter()
+                    .map(|value| round(value, decimals))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    if field_definition.field_type.get_base_type() != BIG_DECIMAL_SCALAR {
+        return Ok(value);
+    }
+    let decimals = match argument_values.get(DECIMALS_ARGUMENT) {
+        None | Some(q::Value::Null) => return Ok(value),
+        Some(decimals) => decimals,
+    };
+    let rounding = match decimals {
+        q::Value::Int(n) => n.as_i64().and_then(|n| u32::try_from(n).ok()),
+        _ => None,
+    };
+    match rounding {
+        Some(decimals) => Ok(round(value, decimals)),
+        None => Err(vec![QueryExecutionError::InvalidArgumentError(
+            field.position,
+            DECIMALS_ARGUMENT.to_owned(),
+            decimals.clone(),
+        )]),
+    }
+}
+
 /// Resolves the value of a field.
 fn resolve_field_value(
     ctx: &ExecutionContext<impl Resolver>,
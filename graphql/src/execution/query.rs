@@ -635,11 +635,6 @@ fn coerce_variable(
 
     let resolver = |name: &str| schema.document().get_named_type(name);
 
-    coerce_value(value, &variable_def.var_type, &resolver, &HashMap::new()).map_err(|value| {
-        vec![QueryExecutionError::InvalidArgumentError(
-            variable_def.position,
-            variable_def.name.to_owned(),
-            value.clone(),
-        )]
-    })
+    coerce_value(value, &variable_def.var_type, &resolver, &HashMap::new())
+        .map_err(|e| vec![e.into_argument_error(variable_def.position, &variable_def.name)])
 }
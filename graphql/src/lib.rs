@@ -29,12 +29,14 @@ pub mod prelude {
     pub use super::execution::{ExecutionContext, Query, Resolver};
     pub use super::introspection::{introspection_schema, IntrospectionResolver};
     pub use super::query::{execute_query, ext::BlockConstraint, QueryExecutionOptions};
-    pub use super::schema::{api_schema, ast::is_list, ast::validate_entity, APISchemaError};
+    pub use super::schema::{
+        api_schema, ast::is_list, ast::validate_entity, ast::ByteLengthError, APISchemaError,
+    };
     pub use super::store::{build_query, StoreResolver};
     pub use super::subscription::SubscriptionExecutionOptions;
     pub use super::values::MaybeCoercible;
 
-    pub use super::runner::GraphQlRunner;
+    pub use super::runner::{GraphQlRunner, GRAPHQL_QUERY_TIMEOUT};
     pub use graph::prelude::s::ObjectType;
 }
 
@@ -5,12 +5,14 @@ use std::time::{Duration, Instant};
 
 use crate::prelude::{QueryExecutionOptions, StoreResolver, SubscriptionExecutionOptions};
 use crate::query::execute_query;
+use crate::schema::api::ErrorPolicy;
 use crate::subscription::execute_prepared_subscription;
 use graph::{
     components::store::SubscriptionManager,
     prelude::{
-        async_trait, o, CheapClone, DeploymentState, GraphQlRunner as GraphQlRunnerTrait, Logger,
-        Query, QueryExecutionError, Subscription, SubscriptionError, SubscriptionResult,
+        async_trait, o, ApiSchema, CheapClone, DeploymentState,
+        GraphQlRunner as GraphQlRunnerTrait, Logger, Query, QueryExecutionError, Subscription,
+        SubscriptionError, SubscriptionResult,
     },
 };
 use graph::{data::graphql::effort::LoadManager, prelude::QueryStoreManager};
@@ -30,7 +32,7 @@ pub struct GraphQlRunner<S, SM> {
 }
 
 lazy_static! {
-    static ref GRAPHQL_QUERY_TIMEOUT: Option<Duration> = env::var("GRAPH_GRAPHQL_QUERY_TIMEOUT")
+    pub static ref GRAPHQL_QUERY_TIMEOUT: Option<Duration> = env::var("GRAPH_GRAPHQL_QUERY_TIMEOUT")
         .ok()
         .map(|s| Duration::from_secs(
             u64::from_str(&s)
@@ -139,6 +141,10 @@ where
         // while the query is running. `self.store` can not be used after this
         // point, and everything needs to go through the `store` we are
         // setting up here
+        let subgraph_name = match &target {
+            QueryTarget::Name(name) => Some(name.clone()),
+            QueryTarget::Deployment(_) => None,
+        };
         let store = self.store.query_store(target, false).await?;
         let state = store.deployment_state().await?;
         let network = Some(store.network_name().to_string());
@@ -153,6 +159,7 @@ where
             .unwrap_or(state);
 
         let max_depth = max_depth.unwrap_or(*GRAPHQL_MAX_DEPTH);
+        let client_deadline = query.deadline;
         let query = crate::execution::Query::new(
             &self.logger,
             schema,
@@ -171,6 +178,9 @@ where
         let by_block_constraint = query.block_constraint()?;
         let mut max_block = 0;
         let mut result: QueryResults = QueryResults::empty();
+        // The deployment state is loaded for every query anyway, and using
+        // it for the latest block avoids another trip to the primary
+        result.set_latest_block(state.latest_ethereum_block_number);
 
         // Note: This will always iterate at least once.
         for (bc, (selection_set, error_policy)) in by_block_constraint {
@@ -182,7 +192,8 @@ where
                 error_policy,
                 query.schema.id().clone(),
             )
-            .await?;
+            .await?
+            .with_subgraph_name(subgraph_name.clone());
             max_block = max_block.max(resolver.block_number());
             let query_res = execute_query(
                 query.clone(),
@@ -190,7 +201,10 @@ where
                 resolver.block_ptr.clone(),
                 QueryExecutionOptions {
                     resolver,
-                    deadline: GRAPHQL_QUERY_TIMEOUT.map(|t| Instant::now() + t),
+                    deadline: earliest(
+                        client_deadline,
+                        GRAPHQL_QUERY_TIMEOUT.map(|t| Instant::now() + t),
+                    ),
                     max_first: max_first.unwrap_or(*GRAPHQL_MAX_FIRST),
                     max_skip: max_skip.unwrap_or(*GRAPHQL_MAX_SKIP),
                     load_manager: self.load_manager.clone(),
@@ -199,13 +213,67 @@ where
             .await;
             result.append(query_res);
         }
-
         query.log_execution(max_block);
         self.deployment_changed(store.as_ref(), state, max_block as u64)
             .await
             .map_err(QueryResults::from)
             .map(|()| result)
     }
+
+    /// Resolve `target` and prepare `subscription` for running against
+    /// it. Like queries, subscriptions see an `indexing_error` if the
+    /// deployment has errors and one of their fields denies that with
+    /// `subgraphError`. Since subscriptions always follow the latest
+    /// block, a deployment that has failed counts as having errors, too
+    async fn prepare_subscription(
+        &self,
+        subscription: Subscription,
+        target: QueryTarget,
+    ) -> Result<
+        (
+            Arc<dyn QueryStore + Send + Sync>,
+            Arc<crate::execution::Query>,
+        ),
+        SubscriptionError,
+    > {
+        let store = self.store.query_store(target, true).await?;
+        let schema = store.api_schema()?;
+        let network = store.network_name().to_string();
+
+        let query = crate::execution::Query::new(
+            &self.logger,
+            schema,
+            Some(network),
+            subscription.query,
+            *GRAPHQL_MAX_COMPLEXITY,
+            *GRAPHQL_MAX_DEPTH,
+        )?;
+
+        let state = store.deployment_state().await?;
+        let has_errors = state.failed
+            || store
+                .has_non_fatal_errors(None)
+                .await
+                .map_err(QueryExecutionError::from)?;
+        if has_errors {
+            let deny = query
+                .block_constraint()?
+                .values()
+                .any(|(_, error_policy)| *error_policy == ErrorPolicy::Deny);
+            if deny {
+                return Err(SubscriptionError::IndexingError);
+            }
+        }
+        Ok((store, query))
+    }
+}
+
+/// The earlier of two deadlines, where `None` means no deadline
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
 
 #[async_trait]
@@ -252,18 +320,7 @@ where
         subscription: Subscription,
         target: QueryTarget,
     ) -> Result<SubscriptionResult, SubscriptionError> {
-        let store = self.store.query_store(target, true).await?;
-        let schema = store.api_schema()?;
-        let network = store.network_name().to_string();
-
-        let query = crate::execution::Query::new(
-            &self.logger,
-            schema,
-            Some(network.clone()),
-            subscription.query,
-            *GRAPHQL_MAX_COMPLEXITY,
-            *GRAPHQL_MAX_DEPTH,
-        )?;
+        let (store, query) = self.prepare_subscription(subscription, target).await?;
 
         if let Err(err) = self
             .load_manager
@@ -293,6 +350,22 @@ where
         .await
     }
 
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        subscription: Subscription,
+        target: QueryTarget,
+    ) -> Result<u64, SubscriptionError> {
+        let (_, query) = self.prepare_subscription(subscription, target).await?;
+        Ok(query.complexity)
+    }
+
+    async fn api_schema(
+        self: Arc<Self>,
+        target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        self.store.query_store(target, false).await?.api_schema()
+    }
+
     fn load_manager(&self) -> Arc<LoadManager> {
         self.load_manager.clone()
     }
@@ -7,8 +7,9 @@ use lazy_static::lazy_static;
 use crate::schema::ast;
 
 use graph::data::{
-    graphql::ext::{DirectiveExt, DocumentExt, ValueExt},
+    graphql::ext::{DirectiveExt, DocumentExt, TypeExt, ValueExt},
     schema::{META_FIELD_NAME, META_FIELD_TYPE, SCHEMA_TYPE_NAME},
+    store::BIG_DECIMAL_SCALAR,
     subgraph::SubgraphFeature,
 };
 use graph::prelude::s::{Value, *};
@@ -27,6 +28,9 @@ pub enum APISchemaError {
 
 const BLOCK_HEIGHT: &str = "Block_height";
 
+/// The argument of `BigDecimal` fields that rounds their values
+pub(crate) const DECIMALS_ARGUMENT: &str = "decimals";
+
 const ERROR_POLICY_TYPE: &str = "_SubgraphErrorPolicy_";
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -83,6 +87,7 @@ pub fn api_schema(
     add_types_for_object_types(&mut schema, &object_types)?;
     add_types_for_interface_types(&mut schema, &interface_types)?;
     add_field_arguments(&mut schema, input_schema)?;
+    add_decimals_arguments(&mut schema);
     add_query_type(&mut schema, &object_types, &interface_types, features)?;
     add_subscription_type(&mut schema, &object_types, &interface_types, features)?;
 
@@ -108,6 +113,8 @@ fn add_builtin_scalar_types(schema: &mut Document) -> Result<(), APISchemaError>
         "String",
         "Bytes",
         "BigInt",
+        "Int8",
+        "Timestamp",
     ]
     .iter()
     {
@@ -390,6 +397,8 @@ fn field_scalar_filter_input_values(
         "BigDecimal" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "ID" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "Int" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Int8" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
+        "Timestamp" => vec!["", "not", "gt", "lt", "gte", "lte", "in", "not_in"],
         "String" => vec![
             "",
             "not",
@@ -843,6 +852,39 @@ fn add_field_arguments(
     Ok(())
 }
 
+/// Adds a `decimals` argument to all fields of type `BigDecimal` or
+/// `[BigDecimal]` so that queries can round their values for display
+fn add_decimals_arguments(schema: &mut Document) {
+    for definition in &mut schema.definitions {
+        let fields = match definition {
+            Definition::TypeDefinition(TypeDefinition::Object(t)) => &mut t.fields,
+            Definition::TypeDefinition(TypeDefinition::Interface(t)) => &mut t.fields,
+            _ => continue,
+        };
+        for field in fields
+            .iter_mut()
+            .filter(|field| field.field_type.get_base_type() == BIG_DECIMAL_SCALAR)
+        {
+            field.arguments.push(decimals_argument());
+        }
+    }
+}
+
+fn decimals_argument() -> InputValue {
+    InputValue {
+        position: Pos::default(),
+        description: Some(
+            "Round the value to at most this many digits after the decimal point, \
+             rounding halves away from zero. Stored values are not affected."
+                .to_owned(),
+        ),
+        name: DECIMALS_ARGUMENT.to_string(),
+        value_type: Type::NamedType("Int".to_owned()),
+        default_value: None,
+        directives: vec![],
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::BTreeSet;
@@ -877,6 +919,12 @@ mod tests {
         schema
             .get_named_type("String")
             .expect("String type is missing in API schema");
+        schema
+            .get_named_type("Int8")
+            .expect("Int8 type is missing in API schema");
+        schema
+            .get_named_type("Timestamp")
+            .expect("Timestamp type is missing in API schema");
     }
 
     #[test]
@@ -1241,4 +1289,31 @@ type Gravatar @entity {
         }
         .expect("\"metadata\" field is missing on Query type");
     }
+
+    #[test]
+    fn api_schema_contains_decimals_argument_on_big_decimal_fields() {
+        let input_schema = parse_schema(
+            "type Token @entity { id: ID!, price: BigDecimal!, prices: [BigDecimal!]!, name: String }",
+        )
+        .expect("Failed to parse input schema");
+        let schema =
+            api_schema(&input_schema, &BTreeSet::new()).expect("Failed to derive API schema");
+
+        let token_type = match schema.get_named_type("Token") {
+            Some(TypeDefinition::Object(t)) => t,
+            _ => panic!("Token type is missing in derived API schema"),
+        };
+        let argument_names = |name: &str| {
+            ast::get_field(token_type, name)
+                .expect("field is missing on Token type")
+                .arguments
+                .iter()
+                .map(|argument| argument.name.to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec!["decimals"], argument_names("price"));
+        assert_eq!(vec!["decimals"], argument_names("prices"));
+        assert!(argument_names("name").is_empty());
+    }
 }
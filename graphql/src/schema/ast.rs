@@ -1,5 +1,5 @@
 use anyhow::anyhow;
-use graph::data::graphql::ext::DirectiveFinder;
+use graph::data::graphql::ext::{DirectiveFinder, FieldExt};
 use graphql_parser::Pos;
 use lazy_static::lazy_static;
 use std::ops::Deref;
@@ -391,6 +391,8 @@ fn is_assignable(value: &store::Value, scalar_type: &ValueType, is_list: bool) -
         | (store::Value::Bool(_), ValueType::Boolean)
         | (store::Value::Bytes(_), ValueType::Bytes)
         | (store::Value::Int(_), ValueType::Int)
+        | (store::Value::Int8(_), ValueType::Int8)
+        | (store::Value::Timestamp(_), ValueType::Timestamp)
         | (store::Value::Null, _) => true,
         (store::Value::List(values), _) if is_list => values
             .iter()
@@ -399,6 +401,35 @@ fn is_assignable(value: &store::Value, scalar_type: &ValueType, is_list: bool) -
     }
 }
 
+/// The error `validate_entity` returns when a value violates a `@length`
+/// directive. Such a violation only depends on the schema and the data
+/// the mappings produced, and callers can therefore always treat it as
+/// deterministic
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ByteLengthError(String);
+
+/// Check that `value`, or every element of it if it is a list, has the
+/// number of bytes that the `@length` directive on `field` asks for
+pub(crate) fn check_byte_length(field: &Field, value: &store::Value) -> Result<(), String> {
+    let expected = match field.byte_length() {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    match value {
+        store::Value::Bytes(bytes) if bytes.as_slice().len() != expected => Err(format!(
+            "`{}` has {} bytes but must have {}",
+            bytes,
+            bytes.as_slice().len(),
+            expected
+        )),
+        store::Value::List(values) => values
+            .iter()
+            .try_for_each(|value| check_byte_length(field, value)),
+        _ => Ok(()),
+    }
+}
+
 pub fn validate_entity(
     schema: &Document,
     key: &EntityKey,
@@ -453,6 +484,13 @@ pub fn validate_entity(
                         value.type_name()
                     );
                 }
+                if let Err(e) = check_byte_length(field, value) {
+                    return Err(ByteLengthError(format!(
+                        "Entity {}[{}]: invalid value for field `{}`: {}",
+                        key.entity_type, key.entity_id, field.name, e
+                    ))
+                    .into());
+                }
             }
             (None, false) => {
                 if is_non_null_type(&field.field_type) {
@@ -492,7 +530,7 @@ fn entity_validation() {
         thing
     }
 
-    fn check(thing: Entity, errmsg: &str) {
+    fn check(thing: Entity, errmsg: &str) -> Option<anyhow::Error> {
         const DOCUMENT: &str = "
       enum Color { red, yellow, blue }
       interface Stuff { id: ID!, name: String! }
@@ -506,6 +544,7 @@ fn entity_validation() {
           favorite_color: Color,
           stuff: Stuff,
           things: [Thing!]!
+          owner: Bytes @length(bytes: 20)
           # Make sure we do not validate derived fields; it's ok
           # to store a thing with a null Cruft
           cruft: Cruft! @derivedFrom(field: \"thing\")
@@ -528,9 +567,11 @@ fn entity_validation() {
                 id,
                 err.unwrap_err()
             );
+            None
         } else {
             if let Err(e) = err {
                 assert_eq!(errmsg, e.to_string(), "checking entity {}", id);
+                Some(e)
             } else {
                 panic!(
                     "Expected error `{}` but got ok when checking entity {}",
@@ -591,4 +632,33 @@ fn entity_validation() {
         thing,
         "Entity Thing[t8]: field `cruft` is derived and can not be set",
     );
+
+    let mut thing = make_thing("t9");
+    thing.set(
+        "owner",
+        store::Value::Bytes(store::scalar::Bytes::from(&[7u8; 20][..])),
+    );
+    check(thing, "");
+
+    let mut thing = make_thing("t10");
+    thing.set(
+        "owner",
+        store::Value::Bytes(store::scalar::Bytes::from(&[7u8; 19][..])),
+    );
+    let err = check(
+        thing,
+        "Entity Thing[t10]: invalid value for field `owner`: \
+         `0x07070707070707070707070707070707070707` has 19 bytes but must have 20",
+    );
+    // Only `@length` violations are marked as such
+    assert!(err.unwrap().is::<ByteLengthError>());
+
+    let mut thing = make_thing("t11");
+    thing.set("owner", "0x07");
+    let err = check(
+        thing,
+        "Entity Thing[t11]: the value `0x07` for field `owner` must \
+         have type Bytes but has type String",
+    );
+    assert!(!err.unwrap().is::<ByteLengthError>());
 }
@@ -869,6 +869,7 @@ fn execute_field(
         ctx.max_first,
         ctx.max_skip,
         ctx.query.query_id.clone(),
+        ctx.deadline,
         collected_column_names,
     )
     .map_err(|e| vec![e])
@@ -889,6 +890,7 @@ fn fetch(
     max_first: u32,
     max_skip: u32,
     query_id: String,
+    deadline: Option<Instant>,
     collected_column_names: AttributeNamesByObjectType<'_>,
 ) -> Result<Vec<Node>, QueryExecutionError> {
     let mut query = build_query(
@@ -901,6 +903,7 @@ fn fetch(
         collected_column_names,
     )?;
     query.query_id = Some(query_id);
+    query.deadline = deadline;
 
     if multiplicity == ChildMultiplicity::Single {
         // Suppress 'order by' in lookups of scalar values since
@@ -941,7 +944,7 @@ impl<'a> CollectedAttributeNames<'a> {
     fn update(&mut self, object_or_interface: ObjectOrInterface<'a>, field: &q::Field) {
         self.0
             .entry(object_or_interface)
-            .or_insert(AttributeNames::All)
+            .or_insert_with(AttributeNames::empty)
             .update(field);
     }
 
@@ -1019,7 +1022,7 @@ fn filter_derived_fields(
     match column_names_type {
         AttributeNames::All => column_names_type,
         AttributeNames::Select(sql_column_names) => {
-            let mut filtered = AttributeNames::All;
+            let mut filtered = AttributeNames::empty();
             sql_column_names
                 .into_iter()
                 .filter_map(|column_name| {
@@ -1,8 +1,12 @@
 use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::mem::discriminant;
 
+use graph::components::ethereum::{is_address_like, is_checksummed};
 use graph::prelude::*;
-use graph::{components::store::EntityType, data::graphql::ObjectOrInterface};
+use graph::{
+    components::store::EntityType,
+    data::graphql::{ObjectOrInterface, TypeExt as _},
+};
 
 use crate::schema::ast as sast;
 use crate::store::prefetch::ObjectCondition;
@@ -163,7 +167,21 @@ fn build_filter_from_object(
                 })?;
 
                 let ty = &field.field_type;
-                let store_value = Value::from_query_value(value, ty)?;
+                let value = normalize_address_value(value, ty);
+                let store_value = Value::from_query_value(&value, ty)?;
+
+                // Values for substring matches can be shorter than the
+                // field's `@length`; everything else has to match it
+                match op {
+                    Contains | NotContains | StartsWith | NotStartsWith | EndsWith
+                    | NotEndsWith => {}
+                    _ => sast::check_byte_length(field, &store_value).map_err(|e| {
+                        QueryExecutionError::ValueParseError(
+                            "Bytes".to_owned(),
+                            format!("filter `{}`: {}", key, e),
+                        )
+                    })?,
+                }
 
                 Ok(match op {
                     Not => EntityFilter::Not(field_name, store_value),
@@ -186,6 +204,33 @@ fn build_filter_from_object(
     })))
 }
 
+/// Lowercase checksummed addresses in filters on `ID` and `String` fields
+/// so that they match what mappings store with `toHex()`, which is always
+/// lowercase. Only addresses with a valid EIP-55 checksum are changed;
+/// all other strings, including addresses with a bad checksum, are user
+/// data and compared exactly as given, so that such filters keep matching
+/// nothing instead of failing. Values for `Bytes` fields need no help
+/// since their hex digits are parsed regardless of case
+fn normalize_address_value(value: &q::Value, ty: &s::Type) -> q::Value {
+    match ty.get_base_type() {
+        "ID" | "String" => {}
+        _ => return value.clone(),
+    }
+
+    match value {
+        q::Value::String(s) if is_address_like(s) && is_checksummed(s) => {
+            q::Value::String(s.to_lowercase())
+        }
+        q::Value::List(values) => q::Value::List(
+            values
+                .iter()
+                .map(|value| normalize_address_value(value, ty))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
 /// Parses a list of GraphQL values into a vector of entity field values.
 fn list_values(value: Value, filter_type: &str) -> Result<Vec<Value>, QueryExecutionError> {
     match value {
@@ -750,4 +795,152 @@ mod tests {
             )]))
         )
     }
+
+    #[test]
+    fn build_query_normalizes_addresses_in_filters() {
+        const CHECKSUMMED: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        const BAD_CHECKSUM: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD";
+
+        fn query(filter: &str, value: q::Value) -> Result<EntityQuery, QueryExecutionError> {
+            let whre = "where";
+            let mut args = default_arguments();
+            args.insert(
+                whre,
+                q::Value::Object(BTreeMap::from_iter(vec![(filter.to_string(), value)])),
+            );
+            build_query(
+                &ObjectType {
+                    fields: vec![
+                        field("id", Type::NamedType("ID".to_owned())),
+                        field("owner", Type::NamedType("Bytes".to_owned())),
+                        field("name", Type::NamedType("String".to_owned())),
+                    ],
+                    ..default_object()
+                },
+                BLOCK_NUMBER_MAX,
+                &args,
+                &BTreeMap::new(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+            )
+        }
+
+        let lower = CHECKSUMMED.to_lowercase();
+
+        let filter = query("owner", q::Value::String(CHECKSUMMED.to_string()))
+            .unwrap()
+            .filter;
+        assert_eq!(
+            Some(EntityFilter::And(vec![EntityFilter::Equal(
+                "owner".to_string(),
+                Value::Bytes(lower.parse().unwrap()),
+            )])),
+            filter
+        );
+
+        let filter = query(
+            "owner_in",
+            q::Value::List(vec![q::Value::String(CHECKSUMMED.to_string())]),
+        )
+        .unwrap()
+        .filter;
+        assert_eq!(
+            Some(EntityFilter::And(vec![EntityFilter::In(
+                "owner".to_string(),
+                vec![Value::Bytes(lower.parse().unwrap())],
+            )])),
+            filter
+        );
+
+        // Checksummed addresses in ids and strings match the lowercase
+        // strings that mappings store
+        for field in &["id", "name"] {
+            let filter = query(field, q::Value::String(CHECKSUMMED.to_string()))
+                .unwrap()
+                .filter;
+            assert_eq!(
+                Some(EntityFilter::And(vec![EntityFilter::Equal(
+                    field.to_string(),
+                    Value::String(lower.clone()),
+                )])),
+                filter
+            );
+        }
+
+        // Other strings are user data and left alone, and so are addresses
+        // with a bad checksum, which just match nothing
+        let upper = format!("0x{}", CHECKSUMMED[2..].to_uppercase());
+        for value in &["0xMixedCase", BAD_CHECKSUM, upper.as_str()] {
+            for field in &["id", "name"] {
+                let filter = query(field, q::Value::String(value.to_string()))
+                    .unwrap()
+                    .filter;
+                assert_eq!(
+                    Some(EntityFilter::And(vec![EntityFilter::Equal(
+                        field.to_string(),
+                        Value::String(value.to_string()),
+                    )])),
+                    filter
+                );
+            }
+        }
+
+        // A bad checksum in a filter on a `Bytes` field is not an error
+        let filter = query("owner", q::Value::String(BAD_CHECKSUM.to_string()))
+            .unwrap()
+            .filter;
+        assert_eq!(
+            Some(EntityFilter::And(vec![EntityFilter::Equal(
+                "owner".to_string(),
+                Value::Bytes(lower.parse().unwrap()),
+            )])),
+            filter
+        );
+    }
+
+    #[test]
+    fn build_query_checks_byte_length_in_filters() {
+        fn query(filter: &str, value: &str) -> Result<EntityQuery, QueryExecutionError> {
+            let mut args = default_arguments();
+            args.insert(
+                "where",
+                q::Value::Object(BTreeMap::from_iter(vec![(
+                    filter.to_string(),
+                    q::Value::String(value.to_string()),
+                )])),
+            );
+            let mut owner = field("owner", Type::NamedType("Bytes".to_owned()));
+            owner.directives = vec![Directive {
+                name: "length".to_string(),
+                position: Pos::default(),
+                arguments: vec![("bytes".to_string(), SchemaValue::Int(4.into()))],
+            }];
+            build_query(
+                &ObjectType {
+                    fields: vec![field("id", Type::NamedType("ID".to_owned())), owner],
+                    ..default_object()
+                },
+                BLOCK_NUMBER_MAX,
+                &args,
+                &BTreeMap::new(),
+                std::u32::MAX,
+                std::u32::MAX,
+                Default::default(),
+            )
+        }
+
+        assert!(query("owner", "0x01020304").is_ok());
+        assert!(query("owner_not", "0x01020304").is_ok());
+        // Substring matches can be shorter
+        assert!(query("owner_contains", "0x0102").is_ok());
+        assert!(matches!(
+            query("owner", "0x010203"),
+            Err(QueryExecutionError::ValueParseError(_, _))
+        ));
+        assert!(matches!(
+            query("owner_gt", "0x0102030405"),
+            Err(QueryExecutionError::ValueParseError(_, _))
+        ));
+    }
 }
@@ -15,6 +15,42 @@ use crate::{prelude::*, schema::api::ErrorPolicy};
 
 use crate::store::query::collect_entities_from_query_field;
 
+/// The `_Block_` object for `ptr` in `_meta`
+fn block_object(ptr: Option<&BlockPtr>) -> q::Value {
+    let hash = ptr
+        .and_then(|ptr| {
+            // locate_block indicates that we do not have a block hash
+            // by setting the hash to `zero`
+            // See 7a7b9708-adb7-4fc2-acec-88680cb07ec1
+            let hash_h256 = ptr.hash_as_h256();
+            if hash_h256 == web3::types::H256::zero() {
+                None
+            } else {
+                Some(q::Value::String(format!("0x{:x}", hash_h256)))
+            }
+        })
+        .unwrap_or(q::Value::Null);
+    let number = ptr
+        .map(|ptr| q::Value::Int((ptr.number as i32).into()))
+        .unwrap_or(q::Value::Null);
+    object! {
+        hash: hash,
+        number: number,
+        __typename: BLOCK_FIELD_TYPE
+    }
+}
+
+/// Whether `selection_set` for `_meta` asks for `latestBlock`. We do not
+/// have the fragment definitions here and assume that fragment spreads
+/// select it
+fn selects_latest_block(selection_set: &q::SelectionSet) -> bool {
+    selection_set.items.iter().any(|selection| match selection {
+        q::Selection::Field(field) => field.name == "latestBlock",
+        q::Selection::InlineFragment(fragment) => selects_latest_block(&fragment.selection_set),
+        q::Selection::FragmentSpread(_) => true,
+    })
+}
+
 /// A resolver that fetches entities from a `Store`.
 #[derive(Clone)]
 pub struct StoreResolver {
@@ -23,6 +59,8 @@ pub struct StoreResolver {
     subscription_manager: Arc<dyn SubscriptionManager>,
     pub(crate) block_ptr: Option<BlockPtr>,
     deployment: DeploymentHash,
+    /// The name that the query used to find `deployment`, if any
+    subgraph_name: Option<SubgraphName>,
     has_non_fatal_errors: bool,
     error_policy: ErrorPolicy,
 }
@@ -46,6 +84,7 @@ impl StoreResolver {
             subscription_manager,
             block_ptr: None,
             deployment,
+            subgraph_name: None,
 
             // Checking for non-fatal errors does not work with subscriptions.
             has_non_fatal_errors: false,
@@ -69,7 +108,7 @@ impl StoreResolver {
         let store_clone = store.cheap_clone();
         let deployment2 = deployment.clone();
         let block_ptr = graph::spawn_blocking_allow_panic(move || {
-            Self::locate_block(store_clone.as_ref(), bc, deployment2.clone())
+            Self::locate_block(store_clone.as_ref(), bc, deployment2)
         })
         .await
         .map_err(|e| QueryExecutionError::Panic(e.to_string()))
@@ -85,12 +124,19 @@ impl StoreResolver {
             subscription_manager,
             block_ptr: Some(block_ptr),
             deployment,
+            subgraph_name: None,
             has_non_fatal_errors,
             error_policy,
         };
         Ok(resolver)
     }
 
+    /// Report `name` as the subgraph name in the `_meta` field
+    pub fn with_subgraph_name(mut self, name: Option<SubgraphName>) -> Self {
+        self.subgraph_name = name;
+        self
+    }
+
     pub fn block_number(&self) -> BlockNumber {
         self.block_ptr
             .as_ref()
@@ -98,17 +144,33 @@ impl StoreResolver {
             .unwrap_or(BLOCK_NUMBER_MAX)
     }
 
+    /// The block up to which queries can see data. That is `None` for a
+    /// deployment that has not processed any blocks yet, or when queries
+    /// go to a replica that has not caught up with a new deployment
+    fn visible_block_ptr(
+        store: &dyn QueryStore,
+        subgraph: &DeploymentHash,
+    ) -> Result<BlockPtr, QueryExecutionError> {
+        store
+            .block_ptr()
+            .map_err(|e| QueryExecutionError::from(StoreError::from(e)))?
+            .ok_or_else(|| {
+                StoreError::QueryExecutionError(format!(
+                    "subgraph {} has not indexed any blocks yet",
+                    subgraph
+                ))
+                .into()
+            })
+    }
+
     fn locate_block(
         store: &dyn QueryStore,
         bc: BlockConstraint,
         subgraph: DeploymentHash,
     ) -> Result<BlockPtr, QueryExecutionError> {
         match bc {
-            BlockConstraint::Number(number) => store
-                .block_ptr()
-                .map_err(|e| StoreError::from(e).into())
-                .and_then(|ptr| {
-                    let ptr = ptr.expect("we should have already checked that the subgraph exists");
+            BlockConstraint::Number(number) => {
+                Self::visible_block_ptr(store, &subgraph).and_then(|ptr| {
                     if ptr.number < number {
                         Err(QueryExecutionError::ValueParseError(
                             "block.number".to_owned(),
@@ -127,7 +189,8 @@ impl StoreResolver {
                         // See 7a7b9708-adb7-4fc2-acec-88680cb07ec1
                         Ok(BlockPtr::from((web3::types::H256::zero(), number as u64)))
                     }
-                }),
+                })
+            }
             BlockConstraint::Hash(hash) => {
                 store
                     .block_number(hash)
@@ -143,57 +206,46 @@ impl StoreResolver {
                             .map(|number| BlockPtr::from((hash, number as u64)))
                     })
             }
-            BlockConstraint::Latest => store
-                .block_ptr()
-                .map_err(|e| StoreError::from(e).into())
-                .and_then(|ptr| {
-                    let ptr = ptr.expect("we should have already checked that the subgraph exists");
-                    Ok(ptr)
-                }),
+            BlockConstraint::Latest => Self::visible_block_ptr(store, &subgraph),
         }
     }
 
     fn handle_meta(
         &self,
         prefetched_object: Option<q::Value>,
+        field: &q::Field,
         object_type: &ObjectOrInterface<'_>,
     ) -> Result<(Option<q::Value>, Option<q::Value>), QueryExecutionError> {
         // Pretend that the whole `_meta` field was loaded by prefetch. Eager
-        // loading this is ok until we add more information to this field
-        // that would force us to query the database; when that happens, we
-        // need to switch to loading on demand
+        // loading this is ok except for `latestBlock`, which needs a trip
+        // to the primary and is therefore only loaded when it is selected
         if object_type.is_meta() {
-            let hash = self
-                .block_ptr
-                .as_ref()
-                .and_then(|ptr| {
-                    // locate_block indicates that we do not have a block hash
-                    // by setting the hash to `zero`
-                    // See 7a7b9708-adb7-4fc2-acec-88680cb07ec1
-                    let hash_h256 = ptr.hash_as_h256();
-                    if hash_h256 == web3::types::H256::zero() {
-                        None
-                    } else {
-                        Some(q::Value::String(format!("0x{:x}", hash_h256)))
-                    }
-                })
-                .unwrap_or(q::Value::Null);
-            let number = self
-                .block_ptr
-                .as_ref()
-                .map(|ptr| q::Value::Int((ptr.number as i32).into()))
-                .unwrap_or(q::Value::Null);
             let mut map = BTreeMap::new();
-            let block = object! {
-                hash: hash,
-                number: number,
-                __typename: BLOCK_FIELD_TYPE
-            };
-            map.insert("prefetch:block".to_string(), q::Value::List(vec![block]));
+            map.insert(
+                "prefetch:block".to_string(),
+                q::Value::List(vec![block_object(self.block_ptr.as_ref())]),
+            );
+            if selects_latest_block(&field.selection_set) {
+                let latest_block_ptr = self
+                    .store
+                    .latest_block_ptr()
+                    .map_err(|e| QueryExecutionError::from(StoreError::from(e)))?;
+                map.insert(
+                    "prefetch:latestBlock".to_string(),
+                    q::Value::List(vec![block_object(latest_block_ptr.as_ref())]),
+                );
+            }
             map.insert(
                 "deployment".to_string(),
                 q::Value::String(self.deployment.to_string()),
             );
+            map.insert(
+                "subgraphName".to_string(),
+                self.subgraph_name
+                    .as_ref()
+                    .map(|name| q::Value::String(name.to_string()))
+                    .unwrap_or(q::Value::Null),
+            );
             map.insert(
                 "hasIndexingErrors".to_string(),
                 q::Value::Boolean(self.has_non_fatal_errors),
@@ -252,7 +304,7 @@ impl Resolver for StoreResolver {
         object_type: ObjectOrInterface<'_>,
         _arguments: &HashMap<&str, q::Value>,
     ) -> Result<q::Value, QueryExecutionError> {
-        let (prefetched_object, meta) = self.handle_meta(prefetched_object, &object_type)?;
+        let (prefetched_object, meta) = self.handle_meta(prefetched_object, field, &object_type)?;
         if let Some(meta) = meta {
             return Ok(meta);
         }
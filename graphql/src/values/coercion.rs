@@ -1,7 +1,48 @@
 use crate::schema;
 use graph::prelude::s::{EnumType, InputValue, ScalarType, Type, TypeDefinition, Value};
-use graph::prelude::{q, QueryExecutionError};
+use graph::prelude::{q, BigDecimal, QueryExecutionError};
+use graphql_parser::Pos;
 use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// A value that could not be coerced. If the value has the right kind but
+/// is still not acceptable, like a float for a `BigInt` or a string that
+/// is not hex for `Bytes`, the error also says what is wrong with it
+#[derive(Clone, Debug, PartialEq)]
+pub struct CoercionError {
+    pub value: Value,
+    pub reason: Option<String>,
+}
+
+impl CoercionError {
+    fn invalid(value: Value, reason: impl Into<String>) -> Self {
+        CoercionError {
+            value,
+            reason: Some(reason.into()),
+        }
+    }
+
+    pub(crate) fn into_argument_error(self, pos: Pos, name: &str) -> QueryExecutionError {
+        match self.reason {
+            Some(reason) => QueryExecutionError::InvalidArgumentValueError(
+                pos,
+                name.to_owned(),
+                self.value,
+                reason,
+            ),
+            None => QueryExecutionError::InvalidArgumentError(pos, name.to_owned(), self.value),
+        }
+    }
+}
+
+impl From<Value> for CoercionError {
+    fn from(value: Value) -> Self {
+        CoercionError {
+            value,
+            reason: None,
+        }
+    }
+}
 
 /// A GraphQL value that can be coerced according to a type.
 pub trait MaybeCoercible<T> {
@@ -25,50 +66,132 @@ impl MaybeCoercible<EnumType> for Value {
 
 impl MaybeCoercible<ScalarType> for Value {
     fn coerce(self, using_type: &ScalarType) -> Result<Value, Value> {
-        match (using_type.name.as_str(), self) {
-            (_, v @ Value::Null) => Ok(v),
-            ("Boolean", v @ Value::Boolean(_)) => Ok(v),
-            ("BigDecimal", Value::Float(f)) => Ok(Value::String(f.to_string())),
-            ("BigDecimal", Value::Int(i)) => {
-                Ok(Value::String(i.as_i64().ok_or(Value::Int(i))?.to_string()))
+        coerce_scalar(self, using_type).map_err(|e| e.value)
+    }
+}
+
+/// Whether `s` is an optional minus sign followed by decimal digits
+fn is_decimal_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Coerce `value` to the scalar type `using_type`. The same rules apply to
+/// literals in the query and to variables, and coercing a value that was
+/// already coerced leaves it unchanged:
+///
+/// - `Int` accepts integers and strings of decimal digits that fit into
+///   32 bits
+/// - `BigInt` and `Int8` accept integers and strings of decimal digits, but
+///   not floats since they would silently lose precision. `Int8` values
+///   must fit into 64 bits
+/// - `BigDecimal` accepts integers, floats and strings with decimal numbers
+/// - `Bytes` accepts strings of hex digits with a `0x` or `0X` prefix and
+///   normalizes them to lowercase with a `0x` prefix. Strings with an odd
+///   number of digits are padded with a leading zero
+/// - `ID` accepts strings and integers
+fn coerce_scalar(value: Value, using_type: &ScalarType) -> Result<Value, CoercionError> {
+    let name = using_type.name.as_str();
+    match (name, value) {
+        (_, v @ Value::Null) => Ok(v),
+        ("Boolean", v @ Value::Boolean(_)) => Ok(v),
+        ("BigDecimal", Value::Float(f)) if f.is_finite() => Ok(Value::String(f.to_string())),
+        ("BigDecimal", Value::Int(i)) => {
+            Ok(Value::String(i.as_i64().ok_or(Value::Int(i))?.to_string()))
+        }
+        ("BigDecimal", Value::String(s)) => match BigDecimal::from_str(&s) {
+            Ok(_) => Ok(Value::String(s)),
+            Err(_) => Err(CoercionError::invalid(
+                Value::String(s),
+                "BigDecimal values must be decimal numbers",
+            )),
+        },
+        ("Int", Value::Int(num)) => {
+            let n = num.as_i64().ok_or_else(|| Value::Int(num.clone()))?;
+            if i32::min_value() as i64 <= n && n <= i32::max_value() as i64 {
+                Ok(Value::Int((n as i32).into()))
+            } else {
+                Err(CoercionError::invalid(
+                    Value::Int(num),
+                    "Int values must fit into 32 bits",
+                ))
             }
-            ("BigDecimal", v @ Value::String(_)) => Ok(v),
-            ("Int", Value::Int(num)) => {
-                let n = num.as_i64().ok_or_else(|| Value::Int(num.clone()))?;
-                if i32::min_value() as i64 <= n && n <= i32::max_value() as i64 {
-                    Ok(Value::Int((n as i32).into()))
-                } else {
-                    Err(Value::Int(num))
+        }
+        ("Int", Value::String(s)) if is_decimal_integer(&s) => match i32::from_str(&s) {
+            Ok(n) => Ok(Value::Int(n.into())),
+            Err(_) => Err(CoercionError::invalid(
+                Value::String(s),
+                "Int values must fit into 32 bits",
+            )),
+        },
+        ("String", v @ Value::String(_)) => Ok(v),
+        ("ID", v @ Value::String(_)) => Ok(v),
+        ("ID", Value::Int(n)) => {
+            Ok(Value::String(n.as_i64().ok_or(Value::Int(n))?.to_string()))
+        }
+        ("Bytes", Value::String(s)) => {
+            let hex = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => hex,
+                _ => {
+                    return Err(CoercionError::invalid(
+                        Value::String(s),
+                        "Bytes values must be hex strings starting with `0x`",
+                    ))
                 }
+            };
+            // An odd number of digits is padded with a leading zero
+            let pad = if hex.len() % 2 == 0 { "" } else { "0" };
+            Ok(Value::String(format!(
+                "0x{}{}",
+                pad,
+                hex.to_ascii_lowercase()
+            )))
+        }
+        ("BigInt", Value::String(s)) | ("Int8", Value::String(s)) => {
+            if !is_decimal_integer(&s) {
+                Err(CoercionError::invalid(
+                    Value::String(s),
+                    format!("{} values must be strings of decimal digits", name),
+                ))
+            } else if name == "Int8" && i64::from_str(&s).is_err() {
+                Err(CoercionError::invalid(
+                    Value::String(s),
+                    "Int8 values must fit into 64 bits",
+                ))
+            } else {
+                Ok(Value::String(s))
             }
-            ("String", v @ Value::String(_)) => Ok(v),
-            ("ID", v @ Value::String(_)) => Ok(v),
-            ("ID", Value::Int(n)) => {
-                Ok(Value::String(n.as_i64().ok_or(Value::Int(n))?.to_string()))
-            }
-            ("Bytes", v @ Value::String(_)) => Ok(v),
-            ("BigInt", v @ Value::String(_)) => Ok(v),
-            ("BigInt", Value::Int(n)) => {
-                Ok(Value::String(n.as_i64().ok_or(Value::Int(n))?.to_string()))
-            }
-            (_, v) => Err(v),
         }
+        ("BigInt", Value::Int(n)) | ("Int8", Value::Int(n)) => {
+            Ok(Value::String(n.as_i64().ok_or(Value::Int(n))?.to_string()))
+        }
+        ("BigInt", v @ Value::Float(_)) | ("Int8", v @ Value::Float(_)) => {
+            Err(CoercionError::invalid(
+                v,
+                format!(
+                    "{} values can not be floats; pass the number as an integer or as a string of decimal digits",
+                    name
+                ),
+            ))
+        }
+        ("Timestamp", v @ Value::String(_)) => Ok(v),
+        (_, v) => Err(v.into()),
     }
 }
 
-/// On error, the `value` is returned as `Err(value)`.
+/// On error, the `value` is returned as part of the `CoercionError`.
 fn coerce_to_definition<'a>(
     value: Value,
     definition: &str,
     resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
     variables: &HashMap<String, q::Value>,
-) -> Result<Value, Value> {
+) -> Result<Value, CoercionError> {
     match resolver(definition).ok_or_else(|| value.clone())? {
         // Accept enum values if they match a value in the enum type
-        TypeDefinition::Enum(t) => value.coerce(t),
+        TypeDefinition::Enum(t) => value.coerce(t).map_err(CoercionError::from),
 
         // Try to coerce Scalar values
-        TypeDefinition::Scalar(t) => value.coerce(t),
+        TypeDefinition::Scalar(t) => coerce_scalar(value, t),
 
         // Try to coerce InputObject values
         TypeDefinition::InputObject(t) => match value {
@@ -84,18 +207,29 @@ fn coerce_to_definition<'a>(
                     coerced_object.insert(
                         name.clone(),
                         match coerce_input_value(Some(value), def, resolver, variables) {
-                            Err(_) | Ok(None) => return Err(object_for_error),
+                            Err(QueryExecutionError::InvalidArgumentValueError(
+                                _,
+                                field,
+                                _,
+                                reason,
+                            )) => {
+                                return Err(CoercionError::invalid(
+                                    object_for_error,
+                                    format!("field `{}`: {}", field, reason),
+                                ))
+                            }
+                            Err(_) | Ok(None) => return Err(object_for_error.into()),
                             Ok(Some(v)) => v,
                         },
                     );
                 }
                 Ok(Value::Object(coerced_object))
             }
-            _ => Err(value),
+            _ => Err(value.into()),
         },
 
         // Everything else remains unimplemented
-        _ => Err(value),
+        _ => Err(value.into()),
     }
 }
 
@@ -131,22 +265,21 @@ pub(crate) fn coerce_input_value<'a>(
     };
 
     Ok(Some(
-        coerce_value(value, &def.value_type, resolver, variable_values).map_err(|val| {
-            QueryExecutionError::InvalidArgumentError(def.position, def.name.to_owned(), val)
-        })?,
+        coerce_value(value, &def.value_type, resolver, variable_values)
+            .map_err(|e| e.into_argument_error(def.position, &def.name))?,
     ))
 }
 
-/// On error, the `value` is returned as `Err(value)`.
+/// On error, the `value` is returned as part of the `CoercionError`.
 pub(crate) fn coerce_value<'a>(
     value: Value,
     ty: &Type,
     resolver: &impl Fn(&str) -> Option<&'a TypeDefinition>,
     variable_values: &HashMap<String, q::Value>,
-) -> Result<Value, Value> {
+) -> Result<Value, CoercionError> {
     match (ty, value) {
         // Null values cannot be coerced into non-null types.
-        (Type::NonNullType(_), Value::Null) => Err(Value::Null),
+        (Type::NonNullType(_), Value::Null) => Err(Value::Null.into()),
 
         // Non-null values may be coercible into non-null types
         (Type::NonNullType(_), val) => {
@@ -189,18 +322,21 @@ pub(crate) fn coerce_value<'a>(
         }
 
         // Otherwise the list type is not coercible.
-        (Type::ListType(_), value) => Err(value),
+        (Type::ListType(_), value) => Err(value.into()),
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use graph::data::graphql::DocumentExt as _;
+    use graph::data::store::scalar;
+    use graph::prelude::{s, QueryExecutionError};
     use graphql_parser::query::Value;
     use graphql_parser::schema::{EnumType, EnumValue, ScalarType, TypeDefinition};
     use graphql_parser::Pos;
     use std::collections::HashMap;
 
-    use super::coerce_to_definition;
+    use super::{coerce_input_value, coerce_to_definition};
 
     #[test]
     fn coercion_using_enum_type_definitions_is_correct() {
@@ -491,7 +627,7 @@ mod tests {
                 &resolver,
                 &HashMap::new()
             ),
-            Ok(Value::String("0x21f".to_string()))
+            Ok(Value::String("0x021f".to_string()))
         );
     }
 
@@ -514,4 +650,179 @@ mod tests {
             Ok(Value::Int((-13289123 as i32).into()))
         );
     }
+
+    #[test]
+    fn scalar_coercion_matrix() {
+        fn int(n: i32) -> Value {
+            Value::Int(n.into())
+        }
+
+        fn string(s: &str) -> Value {
+            Value::String(s.to_string())
+        }
+
+        // For each scalar, the value we coerce and what we expect to get
+        // back, or `None` if the value must be rejected
+        let cases = vec![
+            ("Boolean", Value::Boolean(true), Some(Value::Boolean(true))),
+            ("Boolean", int(1), None),
+            ("String", string("10"), Some(string("10"))),
+            ("String", int(10), None),
+            ("ID", string("a"), Some(string("a"))),
+            ("ID", int(10), Some(string("10"))),
+            ("ID", Value::Float(1.5), None),
+            ("Int", int(10), Some(int(10))),
+            ("Int", string("10"), Some(int(10))),
+            ("Int", string("-10"), Some(int(-10))),
+            ("Int", string("2147483648"), None),
+            ("Int", string("1.5"), None),
+            ("Int", string("ten"), None),
+            ("Int", Value::Float(10.0), None),
+            ("BigInt", int(10), Some(string("10"))),
+            (
+                "BigInt",
+                string("-123456789012345678901234567890"),
+                Some(string("-123456789012345678901234567890")),
+            ),
+            ("BigInt", string("1.5"), None),
+            ("BigInt", string("0x10"), None),
+            ("BigInt", string(""), None),
+            ("BigInt", Value::Float(10.0), None),
+            ("Int8", int(10), Some(string("10"))),
+            (
+                "Int8",
+                string("9223372036854775807"),
+                Some(string("9223372036854775807")),
+            ),
+            ("Int8", string("9223372036854775808"), None),
+            ("Int8", Value::Float(10.0), None),
+            ("BigDecimal", int(10), Some(string("10"))),
+            ("BigDecimal", Value::Float(1.5), Some(string("1.5"))),
+            ("BigDecimal", string("-1.5e10"), Some(string("-1.5e10"))),
+            ("BigDecimal", string("one"), None),
+            ("BigDecimal", Value::Float(std::f64::NAN), None),
+            ("Bytes", string("0x"), Some(string("0x"))),
+            ("Bytes", string("0xdeadBEEF"), Some(string("0xdeadbeef"))),
+            ("Bytes", string("0XDEADBEEF"), Some(string("0xdeadbeef"))),
+            ("Bytes", string("0xdeadbee"), Some(string("0x0deadbee"))),
+            ("Bytes", string("0xABC"), Some(string("0x0abc"))),
+            ("Bytes", string("deadbeef"), None),
+            ("Bytes", string("0xdeadbeeg"), None),
+            ("Bytes", int(10), None),
+        ];
+
+        for (scalar, value, expected) in cases {
+            let ty = TypeDefinition::Scalar(ScalarType::new(scalar.to_string()));
+            let resolver = |_: &str| Some(&ty);
+            let actual = coerce_to_definition(value.clone(), "", &resolver, &HashMap::new()).ok();
+            assert_eq!(expected, actual, "coercing `{}` to {}", value, scalar);
+
+            // Variables get coerced once more when they are used as
+            // arguments, which must not change them
+            if let Some(actual) = actual {
+                assert_eq!(
+                    Ok(actual.clone()),
+                    coerce_to_definition(actual.clone(), "", &resolver, &HashMap::new()),
+                    "coercing `{}` to {} again",
+                    actual,
+                    scalar
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn coerce_filter_values() {
+        const SCHEMA: &str = "
+            scalar BigInt
+            scalar Bytes
+            input Filter { value: BigInt, value_in: [BigInt!], owner: Bytes }
+            type Query { things(where: Filter): [Int] }";
+        let schema: s::Document = graphql_parser::parse_schema(SCHEMA).unwrap();
+        let resolver = |name: &str| schema.get_named_type(name);
+        let arg = schema
+            .get_object_type_definitions()
+            .into_iter()
+            .find(|t| t.name == "Query")
+            .unwrap()
+            .fields[0]
+            .arguments[0]
+            .clone();
+
+        fn filter(field: &str, value: Value) -> Value {
+            Value::Object(vec![(field.to_string(), value)].into_iter().collect())
+        }
+
+        // Literals and variables are coerced the same way
+        let literal = filter("value_in", Value::List(vec![Value::Int(10.into())]));
+        let variable = filter("value_in", Value::Variable("v".to_string()));
+        let variables: HashMap<_, _> = vec![(
+            "v".to_string(),
+            Value::List(vec![Value::String("10".to_string())]),
+        )]
+        .into_iter()
+        .collect();
+        let expected = filter(
+            "value_in",
+            Value::List(vec![Value::String("10".to_string())]),
+        );
+        assert_eq!(
+            expected,
+            coerce_input_value(Some(literal), &arg, &resolver, &HashMap::new())
+                .unwrap()
+                .unwrap()
+        );
+        assert_eq!(
+            expected,
+            coerce_input_value(Some(variable), &arg, &resolver, &variables)
+                .unwrap()
+                .unwrap()
+        );
+
+        // Hex filters with an odd number of digits are padded, and the
+        // padded value is what the store parses as `Bytes`
+        for owner in &["0xabc", "0xABC", "0x0abc"] {
+            let coerced = coerce_input_value(
+                Some(filter("owner", Value::String(owner.to_string()))),
+                &arg,
+                &resolver,
+                &HashMap::new(),
+            )
+            .unwrap()
+            .unwrap();
+            assert_eq!(
+                filter("owner", Value::String("0x0abc".to_string())),
+                coerced
+            );
+            let bytes = match coerced {
+                Value::Object(mut map) => match map.remove("owner") {
+                    Some(Value::String(s)) => s.parse::<scalar::Bytes>(),
+                    other => panic!("unexpected owner {:?}", other),
+                },
+                other => panic!("unexpected filter {:?}", other),
+            };
+            assert_eq!(vec![0x0a, 0xbc], bytes.unwrap().as_slice());
+        }
+
+        // Invalid values in filters say what is wrong with them
+        for (field, value, reason) in &[
+            ("value", Value::Float(1.5), "floats"),
+            ("value_in", Value::List(vec![Value::Float(1.5)]), "floats"),
+            ("owner", Value::String("abc".to_string()), "0x"),
+        ] {
+            match coerce_input_value(
+                Some(filter(field, value.clone())),
+                &arg,
+                &resolver,
+                &HashMap::new(),
+            ) {
+                Err(QueryExecutionError::InvalidArgumentValueError(_, name, _, message)) => {
+                    assert_eq!("where", name);
+                    assert!(message.starts_with(&format!("field `{}`", field)));
+                    assert!(message.contains(reason), "{}", message);
+                }
+                other => panic!("expected an invalid argument error but got {:?}", other),
+            }
+        }
+    }
 }
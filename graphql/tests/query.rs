@@ -16,7 +16,7 @@ use graph::{
     data::subgraph::schema::SubgraphError,
     data::{
         query::{QueryResults, QueryTarget},
-        subgraph::SubgraphFeature,
+        subgraph::{with_block_info, SubgraphFeature},
     },
     prelude::{
         futures03::stream::StreamExt, o, q, serde_json, slog, BlockPtr, DeploymentHash, Entity,
@@ -31,7 +31,7 @@ use graph_graphql::{prelude::*, subscription::execute_subscription};
 use test_store::{
     deployment_state, execute_subgraph_query_with_complexity, execute_subgraph_query_with_deadline,
     revert_block, run_test_sequentially, transact_entity_operations, transact_errors, Store,
-    BLOCK_ONE, GENESIS_PTR, LOAD_MANAGER, LOGGER, STORE, SUBSCRIPTION_MANAGER,
+    BLOCKS, BLOCK_ONE, GENESIS_PTR, LOAD_MANAGER, LOGGER, STORE, SUBSCRIPTION_MANAGER,
 };
 
 const NETWORK_NAME: &str = "fake_network";
@@ -54,6 +54,11 @@ fn setup_with_features(
     test_store::remove_subgraphs();
 
     let schema = test_schema(id.clone());
+    let schema = if features.contains(&SubgraphFeature::BlockInfo) {
+        with_block_info(schema).unwrap()
+    } else {
+        schema
+    };
     let manifest = SubgraphManifest::<graph_chain_ethereum::Chain> {
         id: id.clone(),
         spec_version: Version::new(1, 0, 0),
@@ -64,6 +69,7 @@ fn setup_with_features(
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        max_triggers_per_block: None,
         chain: PhantomData,
     };
 
@@ -1652,7 +1658,7 @@ fn query_detects_reorg() {
 fn can_query_meta() {
     run_test_sequentially(|store| async move {
         // metadata for the latest block (block 1)
-        let query = "query { _meta { deployment block { hash number __typename } __typename } }";
+        let query = "query { _meta { deployment subgraphName block { hash number __typename } latestBlock { number } __typename } }";
         let query = graphql_parser::parse_query(query)
             .expect("invalid test query")
             .into_static();
@@ -1666,14 +1672,19 @@ fn can_query_meta() {
                     number: 1,
                     __typename: "_Block_"
                 },
+                latestBlock: object! {
+                    number: 1
+                },
                 deployment: "graphqlTestsQuery",
+                // The query was sent to the deployment, not a name
+                subgraphName: q::Value::Null,
                 __typename: "_Meta_"
             },
         };
         assert_eq!(extract_data!(result), Some(exp));
 
         // metadata for block 0 by number
-        let query = "query { _meta(block: { number: 0 }) { deployment block { hash number } } }";
+        let query = "query { _meta(block: { number: 0 }) { deployment block { hash number } latestBlock { number } } }";
         let query = graphql_parser::parse_query(query)
             .expect("invalid test query")
             .into_static();
@@ -1685,11 +1696,35 @@ fn can_query_meta() {
                     hash: q::Value::Null,
                     number: 0
                 },
+                // The deployment has been indexed past the block of the data
+                latestBlock: object! {
+                    number: 1
+                },
                 deployment: "graphqlTestsQuery"
             },
         };
         assert_eq!(extract_data!(result), Some(exp));
 
+        // `latestBlock` is also loaded when it is selected through fragments
+        let query = "query { _meta { ... on _Meta_ { latestBlock { number } } ...meta } } \
+                     fragment meta on _Meta_ { block { number } }";
+        let query = graphql_parser::parse_query(query)
+            .expect("invalid test query")
+            .into_static();
+
+        let result = execute_query_document(&deployment.hash, query).await;
+        let exp = object! {
+            _meta: object! {
+                latestBlock: object! {
+                    number: 1
+                },
+                block: object! {
+                    number: 1
+                }
+            },
+        };
+        assert_eq!(extract_data!(result), Some(exp));
+
         // metadata for block 0 by hash
         let query = "query { _meta(block: { hash: \"bd34884280958002c51d3f7b5f853e6febeba33de0f40d15b0363006533c924f\" }) { \
                                         deployment block { hash number } } }";
@@ -1720,6 +1755,150 @@ fn can_query_meta() {
     })
 }
 
+/// Run `query` against the deployment `id` and return all results with
+/// the block of the data and the latest block of the deployment
+async fn execute_query_results(id: &DeploymentHash, query: &str) -> QueryResults {
+    let query = graphql_parser::parse_query(query)
+        .expect("invalid test query")
+        .into_static();
+    let runner = Arc::new(GraphQlRunner::new(
+        &*LOGGER,
+        STORE.clone(),
+        SUBSCRIPTION_MANAGER.clone(),
+        LOAD_MANAGER.clone(),
+    ));
+    runner
+        .run_query_with_complexity(
+            Query::new(query, None),
+            QueryTarget::Deployment(id.clone()),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+}
+
+#[test]
+fn cached_meta_block_lags_latest_block() {
+    run_test_sequentially(|store| async move {
+        let deployment = setup(store.as_ref());
+        let computed_at =
+            |results: &QueryResults| results.first().and_then(|result| result.computed_at);
+
+        // `_meta { block }` does not change when the deployment advances
+        // and can be served from the cache
+        let cached = "query { musicians(block: { number: 1 }) { id } \
+                      _meta(block: { number: 1 }) { block { number } } }";
+        let first = execute_query_results(&deployment.hash, cached).await;
+        assert_eq!(Some(1), first.block());
+        assert_eq!(Some(1), first.latest_block());
+
+        transact_entity_operations(
+            &STORE.subgraph_store(),
+            &deployment,
+            BLOCKS[2].clone(),
+            vec![],
+        )
+        .unwrap();
+
+        let second = execute_query_results(&deployment.hash, cached).await;
+        assert!(computed_at(&first).is_some());
+        assert_eq!(computed_at(&first), computed_at(&second));
+        assert_eq!(Some(1), second.block());
+        assert_eq!(Some(2), second.latest_block());
+        let data = extract_data!(second.first().unwrap().duplicate());
+        assert_eq!(
+            Some(object! {
+                musicians: vec![
+                    object! { id: "m1" },
+                    object! { id: "m2" },
+                    object! { id: "m3" },
+                    object! { id: "m4" },
+                ],
+                _meta: object! { block: object! { number: 1 } }
+            }),
+            data
+        );
+
+        // `_meta { latestBlock }` changes with every block and is never
+        // cached, so the data block and the latest block are the same
+        let uncached = "query { _meta { block { number } latestBlock { number } } }";
+        let first = execute_query_results(&deployment.hash, uncached).await;
+        let second = execute_query_results(&deployment.hash, uncached).await;
+        assert_ne!(computed_at(&first), computed_at(&second));
+        for results in &[first, second] {
+            assert_eq!(Some(2), results.block());
+            assert_eq!(Some(2), results.latest_block());
+            let data = extract_data!(results.first().unwrap().duplicate());
+            assert_eq!(
+                Some(object! {
+                    _meta: object! {
+                        block: object! { number: 2 },
+                        latestBlock: object! { number: 2 }
+                    }
+                }),
+                data
+            );
+        }
+    })
+}
+
+#[test]
+fn can_query_block_infos() {
+    use graph::data::subgraph::schema::{block_info, BLOCK_INFO_OBJECT};
+    use graph_chain_ethereum::chain::BlockFinality;
+    use test_store::block_store::{FakeBlock, BLOCK_ONE, BLOCK_TWO};
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_with_features(
+            store.as_ref(),
+            "graphqlTestsBlockInfo",
+            BTreeSet::from_iter(Some(SubgraphFeature::BlockInfo)),
+        );
+
+        // Write `BlockInfo` entities like the instance manager does
+        let set_block_info = |block: &FakeBlock, timestamp: u64| {
+            let mut light_block = (*block.as_ethereum_block().block).clone();
+            light_block.timestamp = timestamp.into();
+            let data = block_info(&BlockFinality::Final(Arc::new(light_block), None));
+            EntityOperation::Set {
+                key: EntityKey {
+                    subgraph_id: deployment.hash.clone(),
+                    entity_type: BLOCK_INFO_OBJECT.clone(),
+                    entity_id: data.id().unwrap(),
+                },
+                data,
+            }
+        };
+        transact_entity_operations(
+            &*STORE.subgraph_store(),
+            &deployment,
+            BLOCK_TWO.block_ptr(),
+            vec![
+                set_block_info(&*BLOCK_ONE, 1_600_000_000),
+                set_block_info(&*BLOCK_TWO, 1_600_000_015),
+            ],
+        )
+        .unwrap();
+
+        let query = "query { blockInfos(where: { timestamp_gte: \"1600000010\" }) { number hash parentHash timestamp } }";
+        let query = graphql_parser::parse_query(query)
+            .expect("invalid test query")
+            .into_static();
+        let result = execute_query_document(&deployment.hash, query).await;
+        let exp = object! {
+            blockInfos: vec![object! {
+                number: "2",
+                hash: format!("0x{}", BLOCK_TWO.hash),
+                parentHash: format!("0x{}", BLOCK_ONE.hash),
+                timestamp: "1600000015"
+            }]
+        };
+        assert_eq!(extract_data!(result), Some(exp));
+    })
+}
+
 #[test]
 fn non_fatal_errors() {
     use serde_json::json;
@@ -1815,3 +1994,60 @@ fn non_fatal_errors() {
         assert_eq!(expected, serde_json::to_value(&result).unwrap());
     })
 }
+
+#[test]
+fn subscriptions_with_non_fatal_errors() {
+    use test_store::block_store::BLOCK_TWO;
+
+    async fn subscription_complexity(
+        deployment: &DeploymentHash,
+        query: &str,
+    ) -> Result<u64, SubscriptionError> {
+        let runner = Arc::new(GraphQlRunner::new(
+            &*LOGGER,
+            STORE.clone(),
+            SUBSCRIPTION_MANAGER.clone(),
+            LOAD_MANAGER.clone(),
+        ));
+        let query = graphql_parser::parse_query(query).unwrap().into_static();
+        let subscription = Subscription {
+            query: Query::new(query, None),
+        };
+        runner
+            .subscription_complexity(subscription, QueryTarget::Deployment(deployment.clone()))
+            .await
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup_with_features(
+            store.as_ref(),
+            "testNonFatalErrorsSubscriptions",
+            BTreeSet::from_iter(Some(SubgraphFeature::NonFatalErrors)),
+        );
+
+        let query = "subscription { musician(id: \"m1\") { id } }";
+        assert!(subscription_complexity(&deployment.hash, query)
+            .await
+            .is_ok());
+
+        let err = SubgraphError {
+            subgraph_id: deployment.hash.clone(),
+            message: "cow template handler could not moo event transaction".to_string(),
+            block_ptr: Some(BLOCK_TWO.block_ptr()),
+            handler: Some("handleMoo".to_string()),
+            deterministic: true,
+        };
+        transact_errors(&*STORE, &deployment, BLOCK_TWO.block_ptr(), vec![err]).unwrap();
+
+        // `subgraphError` is implicitly `deny`
+        match subscription_complexity(&deployment.hash, query).await {
+            Err(SubscriptionError::IndexingError) => (),
+            other => panic!("expected an indexing_error, got {:?}", other),
+        }
+
+        let query = "subscription { musician(id: \"m1\", subgraphError: allow) { id } }";
+        assert!(subscription_complexity(&deployment.hash, query)
+            .await
+            .is_ok());
+    })
+}
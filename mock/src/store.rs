@@ -1,8 +1,14 @@
 use mockall::predicate::*;
 use mockall::*;
 
-use graph::{components::store::DeploymentLocator, prelude::*};
-use web3::types::H256;
+use graph::components::server::admin::AdminAuditEntry;
+use graph::components::server::index_node::VersionInfo;
+use graph::data::subgraph::{schema::SubgraphError, status, DeploymentProfile, Labels};
+use graph::{
+    components::store::{DeploymentLocator, StatusStore, VersionComparison},
+    prelude::*,
+};
+use web3::types::{Address, H256};
 
 mock! {
     pub Store {
@@ -29,6 +35,12 @@ mock! {
 
         fn blocks(&self, hashes: Vec<H256>) -> Result<Vec<LightEthereumBlock>, Error>;
 
+        fn uncles(&self, block_hash: H256) -> Result<Option<Vec<EthereumUncle>>, Error>;
+
+        fn set_uncles(&self, block_hash: H256, uncles: &[EthereumUncle]) -> Result<(), Error>;
+
+        fn remove_uncles(&self, block_hashes: &[H256]) -> Result<(), Error>;
+
         fn ancestor_block(
             &self,
             block_ptr: BlockPtr,
@@ -78,14 +90,91 @@ impl SubgraphStore for MockStore {
         unimplemented!()
     }
 
+    fn set_archive_blocks(
+        &self,
+        _: &DeploymentLocator,
+        _: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn skip_block(&self, _: &DeploymentLocator, _: &str, _: String) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn unskip_block(
+        &self,
+        _: &DeploymentLocator,
+        _: &str,
+    ) -> Result<status::SkippedBlock, StoreError> {
+        unimplemented!()
+    }
+
+    fn reset_scan_totals(&self, _: &DeploymentLocator) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn labels(&self, _: &DeploymentHash) -> Result<Labels, StoreError> {
+        unimplemented!()
+    }
+
+    fn set_labels(&self, _: &DeploymentHash, _: Labels) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn reload_mappings(
+        &self,
+        _: &DeploymentLocator,
+        _: &DeploymentHash,
+        _: Option<BlockPtr>,
+    ) -> Result<status::MappingsReload, StoreError> {
+        unimplemented!()
+    }
+
+    fn mappings_manifest(
+        &self,
+        _: &DeploymentLocator,
+    ) -> Result<Option<DeploymentHash>, StoreError> {
+        unimplemented!()
+    }
+
+    fn max_trigger_bytes(&self, _: &DeploymentLocator) -> Result<Option<usize>, StoreError> {
+        unimplemented!()
+    }
+
     fn assigned_node(&self, _: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
         unimplemented!()
     }
 
+    fn fatal_error(&self, _: &DeploymentLocator) -> Result<Option<SubgraphError>, StoreError> {
+        unimplemented!()
+    }
+
+    fn retry_failed(&self, _: &DeploymentLocator) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+
+    fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, StoreError> {
+        unimplemented!()
+    }
+
+    fn compare_versions(
+        &self,
+        _: &SubgraphName,
+        _: BlockNumber,
+        _: usize,
+    ) -> Result<VersionComparison, StoreError> {
+        unimplemented!()
+    }
+
     fn assignments(&self, _: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
         unimplemented!()
     }
 
+    fn standbys(&self, _: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
+        unimplemented!()
+    }
+
     fn subgraph_exists(&self, _: &SubgraphName) -> Result<bool, StoreError> {
         unimplemented!()
     }
@@ -124,3 +213,76 @@ impl SubgraphStore for MockStore {
         unimplemented!()
     }
 }
+
+#[async_trait]
+impl StatusStore for MockStore {
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        unimplemented!()
+    }
+
+    fn status(&self, _: status::Filter) -> Result<Vec<status::Info>, StoreError> {
+        unimplemented!()
+    }
+
+    fn cluster_status(
+        &self,
+        _: status::ClusterFilter,
+    ) -> Result<Vec<status::ClusterInfo>, StoreError> {
+        unimplemented!()
+    }
+
+    fn indexing_statuses(&self, _: status::StatusQuery) -> Result<Vec<status::Info>, StoreError> {
+        unimplemented!()
+    }
+
+    fn version_info(&self, _: &str) -> Result<VersionInfo, StoreError> {
+        unimplemented!()
+    }
+
+    fn versions_for_subgraph_id(
+        &self,
+        _: &str,
+    ) -> Result<(Option<String>, Option<String>), StoreError> {
+        unimplemented!()
+    }
+
+    fn get_proof_of_indexing<'a>(
+        self: Arc<Self>,
+        _: &'a DeploymentHash,
+        _: &'a Option<Address>,
+        _: BlockPtr,
+    ) -> DynTryFuture<'a, Option<[u8; 32]>> {
+        unimplemented!()
+    }
+
+    fn admin_audit_log(
+        &self,
+        _: usize,
+        _: Option<i64>,
+        _: Option<String>,
+    ) -> Result<Vec<AdminAuditEntry>, StoreError> {
+        unimplemented!()
+    }
+
+    fn version_switches(
+        &self,
+        _: &str,
+        _: usize,
+    ) -> Result<Vec<status::VersionSwitch>, StoreError> {
+        unimplemented!()
+    }
+
+    fn deployment_profile(
+        &self,
+        _: &DeploymentHash,
+    ) -> Result<Option<DeploymentProfile>, StoreError> {
+        unimplemented!()
+    }
+
+    fn composite_indexes(
+        &self,
+        _: &DeploymentHash,
+    ) -> Result<Vec<status::CompositeIndex>, StoreError> {
+        unimplemented!()
+    }
+}
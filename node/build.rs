@@ -0,0 +1,28 @@
+use std::env;
+use std::process::Command;
+
+/// Capture how the binary is built so that `--version --json` and the
+/// `/version` endpoint can report it
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+    let profile = env::var("PROFILE").unwrap_or_else(|_| "unknown".to_string());
+    let mut features: Vec<_> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!("cargo:rustc-env=GRAPH_NODE_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=GRAPH_NODE_BUILD_PROFILE={}", profile);
+    println!("cargo:rustc-env=GRAPH_NODE_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -123,6 +123,13 @@ pub enum Command {
         /// The shard of the deployment if `id` itself is ambiguous
         shard: Option<String>,
     },
+    /// Manage the warm standby of a deployment
+    ///
+    /// The standby node keeps the files of the deployment resolved and its
+    /// mappings compiled, and takes over indexing the deployment when it
+    /// is promoted, or when the node the deployment is assigned to stops
+    /// recording heartbeats
+    Standby(StandbyCommand),
     /// Rewind a subgraph to a specific block
     Rewind {
         /// The hash of the deployment to rewind
@@ -205,6 +212,10 @@ pub enum ConfigCommand {
         name: String,
         /// The network the subgraph indexes
         network: String,
+        /// A label of the deployment as `key=value`; can be given more
+        /// than once
+        #[structopt(long = "label")]
+        labels: Vec<String>,
     },
     /// Information about the size of database pools
     Pools {
@@ -216,6 +227,33 @@ pub enum ConfigCommand {
     },
 }
 
+#[derive(Clone, Debug, StructOpt)]
+pub enum StandbyCommand {
+    /// Make a node the standby for a deployment
+    Set {
+        /// The id of the deployment
+        id: String,
+        /// The name of the standby node
+        node: String,
+        /// The shard of the deployment if `id` itself is ambiguous
+        shard: Option<String>,
+    },
+    /// Remove the standby of a deployment
+    Clear {
+        /// The id of the deployment
+        id: String,
+        /// The shard of the deployment if `id` itself is ambiguous
+        shard: Option<String>,
+    },
+    /// Assign a deployment to its standby node right away
+    Promote {
+        /// The id of the deployment
+        id: String,
+        /// The shard of the deployment if `id` itself is ambiguous
+        shard: Option<String>,
+    },
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum ListenCommand {
     /// Listen only to assignment events
@@ -294,6 +332,25 @@ pub enum ChainCommand {
     /// There must be no deployments using that chain. If there are, the
     /// subgraphs and/or deployments using the chain must first be removed
     Remove { name: String },
+    /// Rename a chain
+    ///
+    /// All deployments using the chain will use it under its new name.
+    /// No `graph-node` may be running while the chain is renamed; the
+    /// configuration of all nodes needs to be changed to use the new name
+    /// before they are started again
+    Rename {
+        #[structopt(
+            long,
+            default_value = "120",
+            env = "GRAPH_NODE_DEAD_AFTER",
+            help = "consider nodes that have not recorded a heartbeat for that many seconds as stopped\n"
+        )]
+        dead_after: u64,
+        /// The current name of the chain
+        old: String,
+        /// The new name of the chain
+        new: String,
+    },
 }
 
 #[derive(Clone, Debug, StructOpt)]
@@ -523,9 +580,11 @@ async fn main() {
             use ConfigCommand::*;
 
             match cmd {
-                Place { name, network } => {
-                    commands::config::place(&ctx.config.deployment, &name, &network)
-                }
+                Place {
+                    name,
+                    network,
+                    labels,
+                } => commands::config::place(&ctx.config.deployment, &name, &network, labels),
                 Check { print } => commands::config::check(&ctx.config, print),
                 Pools { nodes, shard } => commands::config::pools(&ctx.config, nodes, shard),
             }
@@ -541,6 +600,20 @@ async fn main() {
             block_hash,
             block_number,
         } => commands::rewind::run(ctx.subgraph_store(), id, block_hash, block_number),
+        Standby(cmd) => {
+            use StandbyCommand::*;
+            match cmd {
+                Set { id, node, shard } => {
+                    commands::assign::set_standby(ctx.subgraph_store(), id, Some(node), shard)
+                }
+                Clear { id, shard } => {
+                    commands::assign::set_standby(ctx.subgraph_store(), id, None, shard)
+                }
+                Promote { id, shard } => {
+                    commands::assign::promote_standby(ctx.subgraph_store(), id, shard)
+                }
+            }
+        }
         Listen(cmd) => {
             use ListenCommand::*;
             match cmd {
@@ -595,6 +668,14 @@ async fn main() {
                     let (block_store, primary) = ctx.block_store_and_primary_pool();
                     commands::chain::remove(primary, block_store, name)
                 }
+                Rename {
+                    dead_after,
+                    old,
+                    new,
+                } => {
+                    let (block_store, primary) = ctx.block_store_and_primary_pool();
+                    commands::chain::rename(primary, block_store, old, new, dead_after)
+                }
             }
         }
         Stats(cmd) => {
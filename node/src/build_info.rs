@@ -0,0 +1,38 @@
+//! Information about the build of the running binary, shared by
+//! `graph-node --version --json`, the index node server's `/version`
+//! endpoint and the `version` method of the JSON-RPC admin server
+use git_testament::{git_testament, render_testament, CommitKind};
+use graph::data::subgraph::{MAX_API_VERSION, MAX_SPEC_VERSION};
+use graph::prelude::BuildInfo;
+
+git_testament!(TESTAMENT);
+
+pub fn build_info() -> BuildInfo {
+    let (commit, commit_date, tag, distance) = match TESTAMENT.commit {
+        CommitKind::NoRepository(_, _) | CommitKind::NoCommit(_, _) => (None, None, None, None),
+        CommitKind::NoTags(commit, date) => (Some(commit), Some(date), None, None),
+        CommitKind::FromTag(tag, commit, date, distance) => {
+            (Some(commit), Some(date), Some(tag), Some(distance))
+        }
+    };
+    let features = env!("GRAPH_NODE_FEATURES");
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        testament: render_testament!(TESTAMENT),
+        commit: commit.map(str::to_string),
+        commit_date: commit_date.map(str::to_string),
+        tag: tag.map(str::to_string),
+        distance,
+        dirty: !TESTAMENT.modifications.is_empty(),
+        rustc: env!("GRAPH_NODE_RUSTC_VERSION").to_string(),
+        profile: env!("GRAPH_NODE_BUILD_PROFILE").to_string(),
+        features: features
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .map(str::to_string)
+            .collect(),
+        max_spec_version: MAX_SPEC_VERSION.to_string(),
+        max_api_version: MAX_API_VERSION.to_string(),
+    }
+}
@@ -1,11 +1,13 @@
 use graph::{
     blockchain::block_ingestor::CLEANUP_BLOCKS,
+    data::subgraph::{labels::validate_labels, Labels},
     prelude::{
         anyhow::{anyhow, bail, Context, Result},
-        info, serde_json, Logger, NodeId,
+        info, serde_json, Logger, NodeId, SubgraphVersionSwitchingMode,
     },
 };
 use graph_chain_ethereum::NodeCapabilities;
+use graph_core::Webhook;
 use graph_store_postgres::{DeploymentPlacer, Shard as ShardName, PRIMARY_SHARD};
 
 use http::{HeaderMap, Uri};
@@ -14,6 +16,7 @@ use serde::{Deserialize, Serialize};
 use std::fs::read_to_string;
 use std::{
     collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
     fmt,
 };
 use url::Url;
@@ -62,6 +65,8 @@ pub struct Config {
     pub stores: BTreeMap<String, Shard>,
     pub chains: ChainSection,
     pub deployment: Deployment,
+    #[serde(default, rename = "webhook")]
+    pub webhooks: Vec<Webhook>,
 }
 
 fn validate_name(s: &str) -> Result<()> {
@@ -136,6 +141,11 @@ impl Config {
 
         self.chains.validate()?;
 
+        for webhook in &self.webhooks {
+            Url::parse(&webhook.url)
+                .map_err(|e| anyhow!("invalid webhook URL `{}`: {}", webhook.url, e))?;
+        }
+
         Ok(())
     }
 
@@ -167,6 +177,7 @@ impl Config {
             stores,
             chains,
             deployment,
+            webhooks: vec![],
         })
     }
 
@@ -383,6 +394,18 @@ impl ChainSection {
         Ok(())
     }
 
+    /// Whether `node` should ingest blocks for the chain `name`. A chain
+    /// can name its own ingestor; otherwise, the ingestor for the section
+    /// is used
+    pub fn ingests(&self, name: &str, node: &NodeId) -> bool {
+        let ingestor = self
+            .chains
+            .get(name)
+            .and_then(|chain| chain.ingestor.as_ref())
+            .unwrap_or(&self.ingestor);
+        ingestor == node.as_str()
+    }
+
     fn from_opt(opt: &Opt) -> Result<Self> {
         // If we are not the block ingestor, set the node name
         // to something that is definitely not our node_id
@@ -415,6 +438,26 @@ impl ChainSection {
             } else {
                 // Parse string (format is "NETWORK_NAME:NETWORK_CAPABILITIES:URL" OR
                 // "NETWORK_NAME::URL" which will default to NETWORK_CAPABILITIES="archive,traces")
+                // Either can end in ":limit=NNN" to allow at most NNN
+                // requests per second to the provider
+                let (arg, request_limit) = match arg.rfind(":limit=") {
+                    Some(pos) => {
+                        let limit = &arg[pos + ":limit=".len()..];
+                        let limit = limit
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|limit| *limit > 0)
+                            .ok_or_else(|| {
+                                anyhow!(
+                                    "the request limit `{}` for `{}` must be a positive number",
+                                    limit,
+                                    &arg[..pos]
+                                )
+                            })?;
+                        (&arg[..pos], Some(limit))
+                    }
+                    None => (arg.as_str(), None),
+                };
                 let colon = arg.find(':').ok_or_else(|| {
                     return anyhow!(
                         "A network name must be provided alongside the \
@@ -452,10 +495,34 @@ impl ChainSection {
                         url: url.to_string(),
                         features,
                         headers: Default::default(),
+                        request_limit,
                     }),
                 };
+                // Mixing transports for one network is possible in a
+                // configuration file, but on the command line it is much
+                // more likely that a network name was mistyped
+                if let Some(other) = chains.get(name).and_then(|chain| {
+                    chain
+                        .providers
+                        .iter()
+                        .find_map(|provider| match &provider.details {
+                            ProviderDetails::Web3(web3) if web3.transport != transport => {
+                                Some(web3.transport)
+                            }
+                            _ => None,
+                        })
+                }) {
+                    return Err(anyhow!(
+                        "Ethereum network `{}` is given to both --ethereum-{} and --ethereum-{}; \
+                         each network can only use one of them",
+                        name,
+                        other,
+                        transport
+                    ));
+                }
                 let entry = chains.entry(name.to_string()).or_insert_with(|| Chain {
                     shard: PRIMARY_SHARD.to_string(),
+                    ingestor: None,
                     providers: vec![],
                 });
                 entry.providers.push(provider);
@@ -468,6 +535,10 @@ impl ChainSection {
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Chain {
     pub shard: String,
+    /// The node that ingests blocks for this chain if it should not be
+    /// the one set for all chains
+    #[serde(default)]
+    pub ingestor: Option<String>,
     #[serde(rename = "provider")]
     pub providers: Vec<Provider>,
 }
@@ -476,6 +547,10 @@ impl Chain {
     fn validate(&mut self) -> Result<()> {
         // `Config` validates that `self.shard` references a configured shard
 
+        if let Some(ingestor) = &self.ingestor {
+            NodeId::new(ingestor)
+                .map_err(|()| anyhow!("invalid node id for ingestor {}", ingestor))?;
+        }
         for provider in self.providers.iter_mut() {
             provider.validate()?
         }
@@ -537,6 +612,10 @@ pub struct Web3Provider {
         deserialize_with = "deserialize_http_headers"
     )]
     pub headers: HeaderMap,
+
+    /// The most requests per second we send to the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_limit: Option<u32>,
 }
 
 impl Web3Provider {
@@ -624,6 +703,7 @@ impl<'de> Deserialize<'de> for Provider {
                 let mut transport = None;
                 let mut features = None;
                 let mut headers = None;
+                let mut request_limit = None;
 
                 while let Some(key) = map.next_key()? {
                     match key {
@@ -665,6 +745,12 @@ impl<'de> Deserialize<'de> for Provider {
                             let raw_headers: BTreeMap<String, String> = map.next_value()?;
                             headers = Some(btree_map_to_http_headers(raw_headers));
                         }
+                        ProviderField::RequestLimit => {
+                            if request_limit.is_some() {
+                                return Err(serde::de::Error::duplicate_field("request_limit"));
+                            }
+                            request_limit = Some(map.next_value()?);
+                        }
                     }
                 }
 
@@ -678,6 +764,9 @@ impl<'de> Deserialize<'de> for Provider {
                         {
                             return Err(serde::de::Error::custom("when `details` field is provided, deprecated `url`, `transport`, `features` and `headers` cannot be specified"));
                         }
+                        if request_limit.is_some() {
+                            return Err(serde::de::Error::custom("when `details` field is provided, `request_limit` must be part of it"));
+                        }
 
                         v
                     }
@@ -687,6 +776,7 @@ impl<'de> Deserialize<'de> for Provider {
                         features: features
                             .ok_or_else(|| serde::de::Error::missing_field("features"))?,
                         headers: headers.unwrap_or_else(|| HeaderMap::new()),
+                        request_limit,
                     }),
                 };
 
@@ -701,6 +791,7 @@ impl<'de> Deserialize<'de> for Provider {
             "url",
             "features",
             "headers",
+            "request_limit",
         ];
         deserializer.deserialize_struct("Provider", FIELDS, ProviderVisitor)
     }
@@ -717,6 +808,8 @@ enum ProviderField {
     Transport,
     Features,
     Headers,
+    #[serde(rename = "request_limit")]
+    RequestLimit,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -751,6 +844,15 @@ impl std::fmt::Display for Transport {
 pub struct Deployment {
     #[serde(rename = "rule")]
     rules: Vec<Rule>,
+    /// When a new version of a subgraph becomes its current version. If
+    /// it is not set, the `EXPERIMENTAL_SUBGRAPH_VERSION_SWITCHING_MODE`
+    /// environment variable is used
+    #[serde(default = "version_switching_from_env")]
+    version_switching: SubgraphVersionSwitchingMode,
+    /// Which deployments individual indexers are willing to take. They
+    /// narrow down the `indexers` of the rule that matched a deployment
+    #[serde(rename = "node_rule", default)]
+    node_rules: Vec<NodeRule>,
 }
 
 impl Deployment {
@@ -773,26 +875,87 @@ impl Deployment {
                 "the rules do not contain a default rule that matches everything"
             ));
         }
+        for (i, node_rule) in self.node_rules.iter().enumerate() {
+            NodeId::new(&node_rule.node)
+                .map_err(|()| anyhow!("invalid node id {} in node rule {}", node_rule.node, i))?;
+            validate_labels(&node_rule.labels)
+                .map_err(|e| anyhow!("invalid labels in node rule {}: {}", i, e))?;
+            if !self
+                .rules
+                .iter()
+                .any(|rule| rule.indexers.contains(&node_rule.node))
+            {
+                return Err(anyhow!(
+                    "node rule {} is for {}, but that node is not an indexer in any deployment rule",
+                    i,
+                    node_rule.node
+                ));
+            }
+        }
         Ok(())
     }
 
     fn from_opt(_: &Opt) -> Self {
-        Self { rules: vec![] }
+        Self {
+            rules: vec![],
+            version_switching: version_switching_from_env(),
+            node_rules: vec![],
+        }
+    }
+
+    pub fn version_switching(&self) -> SubgraphVersionSwitchingMode {
+        self.version_switching
+    }
+
+    /// Whether the node rules for `node` let it index a deployment of the
+    /// subgraph `name` on `network` with `labels`. The first rule for the
+    /// node that matches decides; if none matches, a node accepts the
+    /// deployment unless it has rules that accept some deployments, i.e., a
+    /// node with `accept` rules only takes deployments that one of them
+    /// accepts
+    fn accepts(&self, node: &str, name: &str, network: &str, labels: &Labels) -> bool {
+        let mut rules = self.node_rules.iter().filter(|rule| rule.node == node);
+        match rules
+            .clone()
+            .find(|rule| rule.matches(name, network, labels))
+        {
+            Some(rule) => rule.action == NodeAction::Accept,
+            None => !rules.any(|rule| rule.action == NodeAction::Accept),
+        }
     }
 }
 
 impl DeploymentPlacer for Deployment {
-    fn place(&self, name: &str, network: &str) -> Result<Option<(ShardName, Vec<NodeId>)>, String> {
+    fn place(
+        &self,
+        name: &str,
+        network: &str,
+        labels: &Labels,
+    ) -> Result<Option<(ShardName, Vec<NodeId>)>, String> {
         // Errors here are really programming errors. We should have validated
         // everything already so that the various conversions can't fail. We
         // still return errors so that they bubble up to the deployment request
         // rather than crashing the node and burying the crash in the logs
-        let placement = match self.rules.iter().find(|rule| rule.matches(name, network)) {
+        let placement = match self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(name, network, labels))
+        {
             Some(rule) => {
                 let shard = ShardName::new(rule.shard.clone()).map_err(|e| e.to_string())?;
-                let indexers: Vec<_> = rule
+                // Node rules can only steer a deployment towards some of the
+                // indexers; if none of them accepts it, we still need to put
+                // it somewhere and use all of them
+                let mut indexers: Vec<_> = rule
                     .indexers
                     .iter()
+                    .filter(|idx| self.accepts(idx, name, network, labels))
+                    .collect();
+                if indexers.is_empty() {
+                    indexers = rule.indexers.iter().collect();
+                }
+                let indexers: Vec<_> = indexers
+                    .into_iter()
                     .map(|idx| {
                         NodeId::new(idx.clone())
                             .map_err(|()| format!("{} is not a valid node name", idx))
@@ -820,8 +983,8 @@ impl Rule {
         self.pred.matches_anything()
     }
 
-    fn matches(&self, name: &str, network: &str) -> bool {
-        self.pred.matches(name, network)
+    fn matches(&self, name: &str, network: &str, labels: &Labels) -> bool {
+        self.pred.matches(name, network, labels)
     }
 
     fn validate(&self) -> Result<()> {
@@ -833,6 +996,7 @@ impl Rule {
         }
         ShardName::new(self.shard.clone())
             .map_err(|e| anyhow!("illegal name for store shard `{}`: {}", &self.shard, e))?;
+        validate_labels(&self.pred.labels).map_err(|e| anyhow!("invalid labels in rule: {}", e))?;
         Ok(())
     }
 }
@@ -842,19 +1006,25 @@ struct Predicate {
     #[serde(with = "serde_regex", default = "any_name")]
     name: Regex,
     network: Option<NetworkPredicate>,
+    /// The deployment must have all of these labels with these values
+    #[serde(default)]
+    labels: Labels,
 }
 
 impl Predicate {
     fn matches_anything(&self) -> bool {
-        self.name.as_str() == ANY_NAME && self.network.is_none()
+        self.name.as_str() == ANY_NAME && self.network.is_none() && self.labels.is_empty()
     }
 
-    pub fn matches(&self, name: &str, network: &str) -> bool {
+    pub fn matches(&self, name: &str, network: &str, labels: &Labels) -> bool {
         if let Some(n) = &self.network {
             if !n.matches(network) {
                 return false;
             }
         }
+        if !labels_match(&self.labels, labels) {
+            return false;
+        }
 
         match self.name.find(name) {
             None => false,
@@ -868,10 +1038,91 @@ impl Default for Predicate {
         Predicate {
             name: any_name(),
             network: None,
+            labels: Labels::new(),
         }
     }
 }
 
+/// A rule for which deployments the indexer `node` will take. The rule
+/// applies to deployments whose subgraph name matches the glob `name`,
+/// that index one of the `network`s and that have all of the `labels`
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct NodeRule {
+    node: String,
+    #[serde(default = "any_glob")]
+    name: NamePattern,
+    network: Option<NetworkPredicate>,
+    #[serde(default)]
+    labels: Labels,
+    action: NodeAction,
+}
+
+impl NodeRule {
+    fn matches(&self, name: &str, network: &str, labels: &Labels) -> bool {
+        if let Some(n) = &self.network {
+            if !n.matches(network) {
+                return false;
+            }
+        }
+        labels_match(&self.labels, labels) && self.name.matches(name)
+    }
+}
+
+/// Whether a deployment with `labels` has all the `required` labels
+fn labels_match(required: &Labels, labels: &Labels) -> bool {
+    required
+        .iter()
+        .all(|(key, value)| labels.get(key) == Some(value))
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum NodeAction {
+    Accept,
+    Reject,
+}
+
+/// A glob pattern over subgraph names: `*` matches any number of
+/// characters, including `/`, and `?` matches exactly one character
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+struct NamePattern {
+    glob: String,
+    regex: Regex,
+}
+
+impl NamePattern {
+    fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = String;
+
+    fn try_from(glob: String) -> Result<Self, Self::Error> {
+        let pattern = glob
+            .split('*')
+            .map(|part| {
+                part.split('?')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(".")
+            })
+            .collect::<Vec<_>>()
+            .join(".*");
+        let regex = Regex::new(&format!("^{}$", pattern))
+            .map_err(|e| format!("invalid name pattern `{}`: {}", glob, e))?;
+        Ok(NamePattern { glob, regex })
+    }
+}
+
+impl From<NamePattern> for String {
+    fn from(pattern: NamePattern) -> String {
+        pattern.glob
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 enum NetworkPredicate {
@@ -923,6 +1174,10 @@ fn no_name() -> Regex {
     Regex::new(NO_NAME).unwrap()
 }
 
+fn any_glob() -> NamePattern {
+    NamePattern::try_from("*".to_string()).unwrap()
+}
+
 fn primary_store() -> String {
     PRIMARY_SHARD.to_string()
 }
@@ -931,10 +1186,24 @@ fn one() -> usize {
     1
 }
 
+fn version_switching_from_env() -> SubgraphVersionSwitchingMode {
+    std::env::var("EXPERIMENTAL_SUBGRAPH_VERSION_SWITCHING_MODE")
+        .map(|mode| {
+            mode.parse()
+                .unwrap_or_else(|e| panic!("invalid version switching mode: {}", e))
+        })
+        .unwrap_or(SubgraphVersionSwitchingMode::Instant)
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::{Config, FirehoseProvider, Provider, ProviderDetails, Transport, Web3Provider};
+    use super::{
+        ChainSection, Config, Deployment, FirehoseProvider, Opt, Provider, ProviderDetails,
+        Transport, Web3Provider,
+    };
+    use graph::prelude::{NodeId, SubgraphVersionSwitchingMode};
+    use graph_store_postgres::DeploymentPlacer;
     use http::{HeaderMap, HeaderValue};
     use std::collections::BTreeSet;
     use std::fs::read_to_string;
@@ -954,6 +1223,10 @@ mod tests {
         assert_eq!(4, actual.chains.chains.len());
         assert_eq!(2, actual.stores.len());
         assert_eq!(3, actual.deployment.rules.len());
+        assert_eq!(
+            SubgraphVersionSwitchingMode::Synced,
+            actual.deployment.version_switching()
+        );
     }
 
     #[test]
@@ -976,6 +1249,7 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    request_limit: None,
                 }),
             },
             actual
@@ -1001,6 +1275,7 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    request_limit: None,
                 }),
             },
             actual
@@ -1046,7 +1321,7 @@ mod tests {
         let actual = toml::from_str(
             r#"
             label = "peering"
-            details = { type = "web3", transport = "ipc", url = "http://localhost:8545", features = ["archive"], headers = { x-test = "value" } }
+            details = { type = "web3", transport = "ipc", url = "http://localhost:8545", features = ["archive"], headers = { x-test = "value" }, request_limit = 50 }
         "#,
         )
         .unwrap();
@@ -1065,6 +1340,7 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features,
                     headers,
+                    request_limit: Some(50),
                 }),
             },
             actual
@@ -1089,6 +1365,7 @@ mod tests {
                     url: "http://localhost:8545".to_owned(),
                     features: BTreeSet::new(),
                     headers: HeaderMap::new(),
+                    request_limit: None,
                 }),
             },
             actual
@@ -1131,6 +1408,238 @@ mod tests {
         );
     }
 
+    #[test]
+    fn node_rules_narrow_placement() {
+        let deployment: Deployment = toml::from_str(
+            r#"
+            [[rule]]
+            indexers = [ "index_0", "index_1", "index_2" ]
+
+            [[node_rule]]
+            node = "index_0"
+            name = "analytics/*"
+            action = "accept"
+
+            [[node_rule]]
+            node = "index_1"
+            name = "analytics/*"
+            action = "reject"
+
+            [[node_rule]]
+            node = "index_2"
+            name = "analytics/*"
+            network = "mainnet"
+            action = "reject"
+        "#,
+        )
+        .unwrap();
+        deployment.validate().unwrap();
+
+        let nodes = |name: &str, network: &str| -> Vec<String> {
+            let (_, nodes) = deployment
+                .place(name, network, &Labels::new())
+                .unwrap()
+                .unwrap();
+            nodes.iter().map(NodeId::to_string).collect()
+        };
+
+        assert_eq!(vec!["index_0"], nodes("analytics/foo", "mainnet"));
+        assert_eq!(vec!["index_0", "index_2"], nodes("analytics/foo", "xdai"));
+        assert_eq!(vec!["index_1", "index_2"], nodes("other/foo", "mainnet"));
+        assert_eq!(vec!["index_1", "index_2"], nodes("analytics", "mainnet"));
+    }
+
+    #[test]
+    fn node_rules_never_leave_a_deployment_without_indexer() {
+        let deployment: Deployment = toml::from_str(
+            r#"
+            [[rule]]
+            indexers = [ "index_0", "index_1" ]
+
+            [[node_rule]]
+            node = "index_0"
+            name = "analytics/*"
+            action = "accept"
+
+            [[node_rule]]
+            node = "index_1"
+            name = "*"
+            action = "reject"
+        "#,
+        )
+        .unwrap();
+
+        let (_, nodes) = deployment
+            .place("other/foo", "mainnet", &Labels::new())
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, nodes.len());
+    }
+
+    #[test]
+    fn labels_steer_placement() {
+        let deployment: Deployment = toml::from_str(
+            r#"
+            [[rule]]
+            match = { labels = { team = "defi" } }
+            shard = "defi"
+            indexers = [ "index_defi_0", "index_defi_1" ]
+
+            [[rule]]
+            indexers = [ "index_0", "index_1" ]
+
+            [[node_rule]]
+            node = "index_defi_1"
+            labels = { tier = "gold" }
+            action = "accept"
+        "#,
+        )
+        .unwrap();
+        deployment.validate().unwrap();
+
+        let place = |labels: &[(&str, &str)]| -> (String, Vec<String>) {
+            let labels: Labels = labels
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let (shard, nodes) = deployment
+                .place("defi/swap", "mainnet", &labels)
+                .unwrap()
+                .unwrap();
+            (
+                shard.to_string(),
+                nodes.iter().map(NodeId::to_string).collect(),
+            )
+        };
+
+        let (shard, nodes) = place(&[]);
+        assert_eq!("primary", shard);
+        assert_eq!(vec!["index_0", "index_1"], nodes);
+
+        let (shard, nodes) = place(&[("team", "defi")]);
+        assert_eq!("defi", shard);
+        assert_eq!(vec!["index_defi_0"], nodes);
+
+        let (_, nodes) = place(&[("team", "defi"), ("tier", "gold")]);
+        assert_eq!(vec!["index_defi_0", "index_defi_1"], nodes);
+
+        let (shard, _) = place(&[("team", "nft")]);
+        assert_eq!("primary", shard);
+
+        let invalid: Deployment = toml::from_str(
+            r#"
+            [[rule]]
+            match = { labels = { Team = "defi" } }
+            indexers = [ "index_0" ]
+
+            [[rule]]
+            indexers = [ "index_0" ]
+        "#,
+        )
+        .unwrap();
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn node_rules_must_be_for_indexers() {
+        let deployment: Deployment = toml::from_str(
+            r#"
+            [[rule]]
+            indexers = [ "index_0" ]
+
+            [[node_rule]]
+            node = "index_1"
+            name = "analytics/*"
+            action = "accept"
+        "#,
+        )
+        .unwrap();
+        assert!(deployment.validate().is_err());
+    }
+
+    #[test]
+    fn chains_can_have_their_own_ingestor() {
+        let chains: ChainSection = toml::from_str(
+            r#"
+            ingestor = "index_0"
+            [mainnet]
+            shard = "primary"
+            provider = []
+            [kovan]
+            shard = "primary"
+            ingestor = "index_1"
+            provider = []
+        "#,
+        )
+        .unwrap();
+
+        let index_0 = NodeId::new("index_0").unwrap();
+        let index_1 = NodeId::new("index_1").unwrap();
+        assert!(chains.ingests("mainnet", &index_0));
+        assert!(!chains.ingests("mainnet", &index_1));
+        assert!(chains.ingests("kovan", &index_1));
+        assert!(!chains.ingests("kovan", &index_0));
+    }
+
+    #[test]
+    fn networks_can_mix_transports_but_not_share_names() {
+        let opt = Opt {
+            ethereum_rpc: vec!["mainnet:archive:http://localhost:8545".to_string()],
+            ethereum_ws: vec!["xdai:archive:ws://localhost:8546".to_string()],
+            ..Default::default()
+        };
+        let chains = ChainSection::from_opt(&opt).unwrap();
+        assert_eq!(2, chains.chains.len());
+
+        let opt = Opt {
+            ethereum_rpc: vec![
+                "mainnet:archive:http://localhost:8545".to_string(),
+                "mainnet:archive:http://localhost:8547".to_string(),
+            ],
+            ethereum_ipc: vec!["mainnet:archive:/tmp/geth.ipc".to_string()],
+            ..Default::default()
+        };
+        let err = ChainSection::from_opt(&opt).unwrap_err();
+        assert_eq!(
+            "Ethereum network `mainnet` is given to both --ethereum-rpc and --ethereum-ipc; \
+             each network can only use one of them",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn request_limit_from_opt() {
+        let opt = Opt {
+            ethereum_rpc: vec![
+                "mainnet:archive:http://localhost:8545:limit=100".to_string(),
+                "mainnet:http://localhost:8547".to_string(),
+            ],
+            ..Default::default()
+        };
+        let chains = ChainSection::from_opt(&opt).unwrap();
+        let limits: Vec<_> = chains.chains["mainnet"]
+            .providers
+            .iter()
+            .map(|provider| match &provider.details {
+                ProviderDetails::Web3(web3) => (web3.url.as_str(), web3.request_limit),
+                ProviderDetails::Firehose(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("http://localhost:8545", Some(100)),
+                ("http://localhost:8547", None)
+            ],
+            limits
+        );
+
+        let opt = Opt {
+            ethereum_rpc: vec!["mainnet:archive:http://localhost:8545:limit=0".to_string()],
+            ..Default::default()
+        };
+        assert!(ChainSection::from_opt(&opt).is_err());
+    }
+
     fn read_resource_as_string<P: AsRef<Path>>(path: P) -> String {
         let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
         d.push("resources/tests");
@@ -1,8 +1,11 @@
-use ethereum::{EthereumNetworks, NodeCapabilities, ProviderEthRpcMetrics};
+use ethereum::{
+    BlockCachePeers, EthereumNetworks, HeadLagMonitor, NodeCapabilities, ProviderEthRpcMetrics,
+    ProviderStatuses, ReceiptsProbe, TraceProbe,
+};
 use futures::future::join_all;
 use git_testament::{git_testament, render_testament};
 use graph::firehose::endpoints::{FirehoseEndpoint, FirehoseNetworkEndpoints, FirehoseNetworks};
-use graph::{ipfs_client::IpfsClient, prometheus::Registry};
+use graph::{ipfs_client::IpfsClient, prometheus::Registry, TaskSupervisor};
 use lazy_static::lazy_static;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -10,28 +13,35 @@ use std::str::FromStr;
 use std::time::Duration;
 use std::{collections::HashMap, env};
 use structopt::StructOpt;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::mpsc;
 
 use graph::blockchain::block_ingestor::BlockIngestor;
 use graph::blockchain::{Blockchain as _, BlockchainMap};
 use graph::components::store::BlockStore;
 use graph::data::graphql::effort::LoadManager;
-use graph::log::logger;
+use graph::log::logger_with_format;
 use graph::prelude::{IndexNodeServer as _, JsonRpcServer as _, *};
 use graph::util::security::SafeDisplay;
-use graph_chain_ethereum::{self as ethereum, network_indexer, EthereumAdapterTrait, Transport};
+use graph::util::{crash, shutdown};
+use graph_chain_ethereum::{
+    self as ethereum, network_indexer, EthereumAdapterTrait, RequestLimiter, Transport,
+};
 use graph_core::{
     LinkResolver, MetricsRegistry, SubgraphAssignmentProvider as IpfsSubgraphAssignmentProvider,
-    SubgraphInstanceManager, SubgraphRegistrar as IpfsSubgraphRegistrar,
+    SubgraphInstanceManager, SubgraphRegistrar as IpfsSubgraphRegistrar, WebhookNotifier,
 };
 use graph_graphql::prelude::GraphQlRunner;
 use graph_server_http::GraphQLServer as GraphQLQueryServer;
-use graph_server_index_node::IndexNodeServer;
+use graph_server_index_node::{BlockCache, IndexNodeServer};
 use graph_server_json_rpc::JsonRpcServer;
 use graph_server_metrics::PrometheusMetricsServer;
 use graph_server_websocket::SubscriptionServer as GraphQLSubscriptionServer;
-use graph_store_postgres::{register_jobs as register_store_jobs, ChainHeadUpdateListener, Store};
+use graph_store_postgres::{
+    register_heartbeat_job, register_jobs as register_store_jobs, ChainHeadUpdateListener, Store,
+};
 
+mod build_info;
 mod config;
 mod opt;
 mod store_builder;
@@ -62,6 +72,10 @@ lazy_static! {
 /// continue regardless.
 const ETH_NET_VERSION_WAIT_TIME: Duration = Duration::from_secs(30);
 
+/// How often we try to reach the providers of networks that were offline
+/// when the node started
+const OFFLINE_NETWORK_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
 git_testament!(TESTAMENT);
 
 fn read_expensive_queries() -> Result<Vec<Arc<q::Document>>, std::io::Error> {
@@ -101,10 +115,29 @@ async fn main() {
     #[cfg(debug_assertions)]
     std::mem::forget(fail::FailScenario::setup());
 
+    // `--version` is handled by clap before it looks at any other
+    // arguments, so we have to check for `--version --json` ourselves
+    let args: Vec<_> = env::args().collect();
+    if args.iter().any(|arg| arg == "--version" || arg == "-V")
+        && args.iter().any(|arg| arg == "--json")
+    {
+        match serde_json::to_string_pretty(&build_info::build_info()) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("error serializing build info: {}", e);
+                std::process::exit(1);
+            }
+        }
+        std::process::exit(0);
+    }
+
     let opt = opt::Opt::from_args();
 
     // Set up logger
-    let logger = logger(opt.debug);
+    let logger = logger_with_format(opt.debug, opt.log_format);
+
+    // Log panics with what was being worked on when they happened
+    crash::install(logger.clone());
 
     // Log version information
     info!(
@@ -149,6 +182,9 @@ async fn main() {
     // Obtain metrics server port
     let metrics_port = opt.metrics_port;
 
+    // How long indexing has to wind down when we are asked to stop
+    let shutdown_timeout = Duration::from_secs(opt.shutdown_timeout);
+
     info!(logger, "Starting up");
 
     // Optionally, identify the Elasticsearch logging configuration
@@ -167,16 +203,25 @@ async fn main() {
     // Try to create IPFS clients for each URL specified in `--ipfs`
     let ipfs_clients: Vec<_> = create_ipfs_clients(&logger, &opt.ipfs);
 
-    // Convert the clients into a link resolver. Since we want to get past
-    // possible temporary DNS failures, make the resolver retry
-    let link_resolver = Arc::new(LinkResolver::from(ipfs_clients));
-
     // Set up Prometheus registry
     let prometheus_registry = Arc::new(Registry::new());
     let metrics_registry = Arc::new(MetricsRegistry::new(
         logger.clone(),
         prometheus_registry.clone(),
     ));
+    graph::util::futures::register_retry_metrics(metrics_registry.as_ref());
+
+    // Report panics in background tasks, and restart essential tasks or
+    // take the node down when they panic
+    Arc::new(
+        TaskSupervisor::new(&logger, metrics_registry.clone(), opt.essential_task_panic)
+            .expect("failed to create task supervisor"),
+    )
+    .install();
+
+    // Convert the clients into a link resolver. Since we want to get past
+    // possible temporary DNS failures, make the resolver retry
+    let link_resolver = Arc::new(LinkResolver::new(ipfs_clients, metrics_registry.clone()));
     let mut metrics_server =
         PrometheusMetricsServer::new(&logger_factory, prometheus_registry.clone());
 
@@ -185,9 +230,26 @@ async fn main() {
     let eth_networks = if query_only {
         EthereumNetworks::new()
     } else {
-        create_ethereum_networks(logger.clone(), metrics_registry.clone(), config.clone())
-            .await
-            .expect("Failed to parse Ethereum networks")
+        let block_cache_peers = if opt.block_cache_peers.is_empty() {
+            None
+        } else {
+            let token = opt
+                .block_cache_token
+                .clone()
+                .expect("--block-cache-peers requires --block-cache-token");
+            Some(Arc::new(
+                BlockCachePeers::new(&opt.block_cache_peers, token, metrics_registry.clone())
+                    .expect("Failed to set up block cache peers"),
+            ))
+        };
+        create_ethereum_networks(
+            logger.clone(),
+            metrics_registry.clone(),
+            config.clone(),
+            block_cache_peers,
+        )
+        .await
+        .expect("Failed to parse Ethereum networks")
     };
 
     let firehose_networks = if query_only {
@@ -208,13 +270,27 @@ async fn main() {
         StoreBuilder::new(&logger, &node_id, &config, metrics_registry.cheap_clone()).await;
 
     let launch_services = |logger: Logger| async move {
-        let (eth_networks, idents) = connect_networks(&logger, eth_networks).await;
+        let (eth_networks, idents, offline) = connect_networks(&logger, eth_networks).await;
 
         let subscription_manager = store_builder.subscription_manager();
         let chain_head_update_listener = store_builder.chain_head_update_listener();
         let primary_pool = store_builder.primary_pool();
         let network_store = store_builder.network_store(idents);
 
+        // We can only use networks whose providers are all down if we know
+        // their identifier from a previous run
+        for network in &offline {
+            if network_store.block_store().chain_store(network).is_none() {
+                crit!(
+                    logger,
+                    "Could not connect to any provider for network {}, and the network \
+                     has never been used with this store before",
+                    network
+                );
+                std::process::exit(1);
+            }
+        }
+
         // To support the ethereum block ingestor, ethereum networks are referenced both by the
         // `blockchain_map` and `ethereum_chains`. Future chains should be referred to only in
         // `blockchain_map`.
@@ -232,6 +308,19 @@ async fn main() {
         );
         let blockchain_map = Arc::new(blockchain_map);
 
+        // Keep an eye on providers that serve stale heads so that we use
+        // other providers for the same network while they lag
+        HeadLagMonitor::new(&logger, metrics_registry.clone(), &eth_networks)
+            .expect("failed to create head lag monitor")
+            .start();
+
+        // Providers that did not support traces when we connected to them
+        // might later, and vice versa
+        TraceProbe::new(&logger, &eth_networks).start();
+
+        // The same goes for fetching the receipts of whole blocks
+        ReceiptsProbe::new(&logger, &eth_networks).start();
+
         let load_manager = Arc::new(LoadManager::new(
             &logger,
             expensive_queries,
@@ -243,14 +332,41 @@ async fn main() {
             subscription_manager.clone(),
             load_manager,
         ));
+        let max_batch_size = if opt.graphql_disable_batching {
+            None
+        } else {
+            Some(opt.graphql_max_batch_size)
+        };
         let mut graphql_server = GraphQLQueryServer::new(
             &logger_factory,
             graphql_metrics_registry,
             graphql_runner.clone(),
             node_id.clone(),
+            max_batch_size,
+        );
+        let subscription_server = GraphQLSubscriptionServer::new(
+            &logger,
+            metrics_registry.clone(),
+            graphql_runner.clone(),
+            network_store.clone(),
         );
-        let subscription_server =
-            GraphQLSubscriptionServer::new(&logger, graphql_runner.clone(), network_store.clone());
+
+        let chain_stores: HashMap<_, _> = eth_networks
+            .networks
+            .keys()
+            .filter_map(|network| {
+                network_store
+                    .block_store()
+                    .chain_store(network)
+                    .map(|chain_store| (network.clone(), chain_store as Arc<dyn ChainStore>))
+            })
+            .collect();
+        verify_offline_networks(&logger, offline, &eth_networks, &chain_stores);
+        let block_cache = opt
+            .block_cache_token
+            .clone()
+            .map(|token| Arc::new(BlockCache::new(token, chain_stores.clone())));
+        let provider_statuses = Arc::new(ProviderStatuses::new(&eth_networks, chain_stores));
 
         let mut index_node_server = IndexNodeServer::new(
             &logger_factory,
@@ -258,6 +374,9 @@ async fn main() {
             network_store.clone(),
             link_resolver.clone(),
             network_store.subgraph_store().clone(),
+            block_cache,
+            provider_statuses,
+            build_info::build_info(),
         );
 
         // Spawn Ethereum network indexers for all networks that are to be indexed
@@ -284,7 +403,8 @@ async fn main() {
                     None,
                     network_name,
                 );
-                graph::spawn(
+                graph::spawn_named(
+                    "network-indexer-events",
                     indexer
                         .take_event_stream()
                         .unwrap()
@@ -300,7 +420,18 @@ async fn main() {
         if !opt.disable_block_ingestor {
             let block_polling_interval = Duration::from_millis(opt.ethereum_polling_interval);
 
-            start_block_ingestor(&logger, block_polling_interval, ethereum_chains);
+            // Only ingest the chains that the configuration assigns to us
+            let ethereum_chains = ethereum_chains
+                .into_iter()
+                .filter(|(name, _)| config.chains.ingests(name, &node_id))
+                .collect();
+            start_block_ingestor(
+                &logger,
+                block_polling_interval,
+                !opt.disable_adaptive_polling,
+                metrics_registry.clone(),
+                ethereum_chains,
+            );
 
             // Start a task runner
             let mut job_runner = graph::util::jobs::Runner::new(&logger);
@@ -313,6 +444,17 @@ async fn main() {
             graph::spawn_blocking(job_runner.start());
         }
 
+        // Every node records heartbeats, whether it ingests blocks or not.
+        // They run separately from the other jobs so that long-running
+        // maintenance can not make this node look dead
+        let mut heartbeat_runner = graph::util::jobs::Runner::new(&logger);
+        register_heartbeat_job(
+            &mut heartbeat_runner,
+            network_store.clone(),
+            node_id.clone(),
+        );
+        graph::spawn_blocking(heartbeat_runner.start());
+
         let subgraph_instance_manager = SubgraphInstanceManager::new(
             &logger_factory,
             network_store.subgraph_store(),
@@ -328,14 +470,6 @@ async fn main() {
             subgraph_instance_manager,
         );
 
-        // Check version switching mode environment variable
-        let version_switching_mode = SubgraphVersionSwitchingMode::parse(
-            env::var_os("EXPERIMENTAL_SUBGRAPH_VERSION_SWITCHING_MODE")
-                .unwrap_or_else(|| "instant".into())
-                .to_str()
-                .expect("invalid version switching mode"),
-        );
-
         // Create named subgraph provider for resolving subgraph name->ID mappings
         let subgraph_registrar = Arc::new(IpfsSubgraphRegistrar::new(
             &logger_factory,
@@ -345,7 +479,10 @@ async fn main() {
             subscription_manager,
             blockchain_map,
             node_id.clone(),
-            version_switching_mode,
+            config.deployment.version_switching(),
+            opt.retry_failed_on_startup,
+            opt.dev,
+            metrics_registry.clone(),
         ));
         graph::spawn(
             subgraph_registrar
@@ -354,19 +491,51 @@ async fn main() {
                 .compat(),
         );
 
+        // Keep the deployments this node is the standby for ready to be
+        // taken over
+        let registrar = subgraph_registrar.clone();
+        graph::spawn_named("warm-standbys", async move {
+            registrar.keep_standbys_warm().await
+        });
+
+        WebhookNotifier::new(
+            &logger,
+            network_store.clone(),
+            node_id.clone(),
+            config.webhooks.clone(),
+            metrics_registry.clone(),
+        )
+        .start();
+
+        if opt.validate_deployments_on_startup {
+            let registrar = subgraph_registrar.clone();
+            let logger = logger.clone();
+            graph::spawn_named("validate-deployment-files", async move {
+                if let Err(e) = registrar.validate_deployment_files().await {
+                    error!(logger, "Failed to validate subgraph files"; "error" => e.to_string());
+                }
+            });
+        }
+
         // Start admin JSON-RPC server.
         let json_rpc_server = JsonRpcServer::serve(
             json_rpc_port,
             http_port,
             ws_port,
             subgraph_registrar.clone(),
+            network_store.subgraph_store(),
+            metrics_registry.clone(),
             node_id.clone(),
+            build_info::build_info(),
             logger.clone(),
         )
         .expect("failed to start JSON-RPC admin server");
 
-        // Let the server run forever.
-        std::mem::forget(json_rpc_server);
+        // Let the server run until the node shuts down
+        graph::spawn_named("json-rpc-shutdown", async move {
+            shutdown::requested().await;
+            json_rpc_server.close();
+        });
 
         // Add the CLI subgraph with a REST request to the admin server.
         if let Some(subgraph) = subgraph {
@@ -386,7 +555,7 @@ async fn main() {
                 async move {
                     subgraph_registrar.create_subgraph(name.clone()).await?;
                     subgraph_registrar
-                        .create_subgraph_version(name, subgraph_id, node_id)
+                        .create_subgraph_version(name, subgraph_id, node_id, None, None)
                         .await
                 }
                 .map_err(|e| panic!("Failed to deploy subgraph from `--subgraph` flag: {}", e)),
@@ -394,30 +563,32 @@ async fn main() {
         }
 
         // Serve GraphQL queries over HTTP
-        graph::spawn(
+        graph::spawn_essential("graphql-server", move || {
             graphql_server
                 .serve(http_port, ws_port)
                 .expect("Failed to start GraphQL query server")
-                .compat(),
-        );
+                .compat()
+        });
 
         // Serve GraphQL subscriptions over WebSockets
-        graph::spawn(subscription_server.serve(ws_port));
+        graph::spawn_essential("websocket-server", move || {
+            subscription_server.clone().serve(ws_port)
+        });
 
         // Run the index node server
-        graph::spawn(
+        graph::spawn_essential("index-node-server", move || {
             index_node_server
                 .serve(index_node_port)
                 .expect("Failed to start index node server")
-                .compat(),
-        );
+                .compat()
+        });
 
-        graph::spawn(
+        graph::spawn_essential("metrics-server", move || {
             metrics_server
                 .serve(metrics_port)
                 .expect("Failed to start metrics server")
-                .compat(),
-        );
+                .compat()
+        });
     };
 
     graph::spawn(launch_services(logger.clone()));
@@ -426,13 +597,13 @@ async fn main() {
     // task that simply responds to "ping" requests. Then spawn a separate
     // thread to periodically ping it and check responsiveness.
     let (ping_send, mut ping_receive) = mpsc::channel::<crossbeam_channel::Sender<()>>(1);
-    graph::spawn(async move {
+    graph::spawn_named("contention-ping", async move {
         while let Some(pong_send) = ping_receive.recv().await {
             let _ = pong_send.clone().send(());
         }
         panic!("ping sender dropped");
     });
-    std::thread::spawn(move || loop {
+    graph::spawn_thread("contention-checker", move || loop {
         std::thread::sleep(Duration::from_secs(1));
         let (pong_send, pong_receive) = crossbeam_channel::bounded(1);
         if futures::executor::block_on(ping_send.clone().send(pong_send)).is_err() {
@@ -456,7 +627,39 @@ async fn main() {
         }
     });
 
-    futures::future::pending::<()>().await;
+    // Components inside the node request a shutdown when they hit an
+    // error that keeps the node from working correctly; we then exit with
+    // an error once indexing has stopped
+    let failed = tokio::select! {
+        _ = wait_for_signal() => false,
+        _ = shutdown::requested() => true,
+    };
+
+    // Give indexing a chance to finish the blocks it is working on so
+    // that we do not get killed in the middle of writing them
+    info!(logger, "Shutting down"; "timeout_s" => shutdown_timeout.as_secs());
+    shutdown::request();
+    match tokio::time::timeout(shutdown_timeout, shutdown::wait()).await {
+        Ok(()) => {
+            info!(logger, "Shutdown complete");
+            std::process::exit(if failed { 1 } else { 0 });
+        }
+        Err(_) => {
+            crit!(logger, "Indexing did not stop in time, exiting anyway";
+                  "timeout_s" => shutdown_timeout.as_secs());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Wait until the process receives SIGTERM or SIGINT
+async fn wait_for_signal() {
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    let mut sigint = signal(SignalKind::interrupt()).expect("failed to listen for SIGINT");
+    tokio::select! {
+        _ = sigterm.recv() => {},
+        _ = sigint.recv() => {},
+    }
 }
 
 async fn create_firehose_networks(
@@ -504,6 +707,7 @@ async fn create_ethereum_networks(
     logger: Logger,
     registry: Arc<MetricsRegistry>,
     config: Config,
+    block_cache_peers: Option<Arc<BlockCachePeers>>,
 ) -> Result<EthereumNetworks, anyhow::Error> {
     let eth_rpc_metrics = Arc::new(ProviderEthRpcMetrics::new(registry));
     let mut parsed_networks = EthereumNetworks::new();
@@ -524,31 +728,59 @@ async fn create_ethereum_networks(
 
                 let (transport_event_loop, transport) = match web3.transport {
                     Rpc => Transport::new_rpc(&web3.url, web3.headers),
-                    Ipc => Transport::new_ipc(&web3.url),
+                    Ipc => {
+                        // Connecting to a missing socket panics deep inside
+                        // the transport; fail with a clear message instead
+                        if !Path::new(&web3.url).exists() {
+                            return Err(anyhow::anyhow!(
+                                "IPC path `{}` of provider `{}` does not exist",
+                                web3.url,
+                                provider.label
+                            ));
+                        }
+                        Transport::new_ipc(&web3.url)
+                    }
                     Ws => Transport::new_ws(&web3.url),
                 };
 
                 // If we drop the event loop the transport will stop working.
                 // For now it's fine to just leak it.
                 std::mem::forget(transport_event_loop);
+                let transport = transport.debug_requests(&logger, &web3.url).limit_requests(
+                    RequestLimiter::new(
+                        &provider.label,
+                        web3.request_limit,
+                        eth_rpc_metrics.clone(),
+                    ),
+                );
 
                 let supports_eip_1898 = !web3.features.contains("no_eip1898");
 
-                parsed_networks.insert(
-                    name.to_string(),
-                    capabilities,
-                    Arc::new(
-                        graph_chain_ethereum::EthereumAdapter::new(
-                            logger,
-                            provider.label,
-                            &web3.url,
-                            transport,
-                            eth_rpc_metrics.clone(),
-                            supports_eip_1898,
-                        )
-                        .await,
-                    ),
-                );
+                let adapter = graph_chain_ethereum::EthereumAdapter::new(
+                    logger.clone(),
+                    provider.label,
+                    &web3.url,
+                    transport,
+                    eth_rpc_metrics.clone(),
+                    supports_eip_1898,
+                    block_cache_peers
+                        .as_ref()
+                        .map(|peers| peers.for_network(&name)),
+                )
+                .await;
+
+                // Requests for traces only go to providers that can
+                // answer them, whatever the configuration says; the
+                // `TraceProbe` checks again later
+                if capabilities.traces && !adapter.supports_traces() {
+                    warn!(
+                        logger,
+                        "Provider is configured with `traces` but does not support `trace_filter`; \
+                         it will not be used for subgraphs with call handlers until it does"
+                    );
+                }
+
+                parsed_networks.insert(name.to_string(), capabilities, Arc::new(adapter));
             }
         }
     }
@@ -557,19 +789,22 @@ async fn create_ethereum_networks(
 }
 
 /// Try to connect to all the providers in `eth_networks` and get their net
-/// version and genesis block. Return the same `eth_networks` and the
-/// retrieved net identifiers grouped by network name. Remove all providers
-/// for which trying to connect resulted in an error from the returned
-/// `EthereumNetworks`, since it's likely pointless to try and connect to
-/// them. If the connection attempt to a provider times out after
-/// `ETH_NET_VERSION_WAIT_TIME`, keep the provider, but don't report a
-/// version for it.
+/// version and genesis block. Return the same `eth_networks`, the
+/// retrieved net identifiers grouped by network name, and the names of the
+/// networks for which we could not reach any provider. Providers that fail
+/// or time out after `ETH_NET_VERSION_WAIT_TIME` are removed from the
+/// returned `EthereumNetworks` if another provider for their network
+/// responded. If none did, the network is offline; we keep all its
+/// providers and rely on the identifier that the store remembers from
+/// previous runs, so that the node can serve queries and resume indexing
+/// once the providers come back.
 async fn connect_networks(
     logger: &Logger,
     mut eth_networks: EthereumNetworks,
 ) -> (
     EthereumNetworks,
     Vec<(String, Vec<EthereumNetworkIdentifier>)>,
+    Vec<String>,
 ) {
     // The status of a provider that we learned from connecting to it
     #[derive(PartialEq)]
@@ -580,6 +815,7 @@ async fn connect_networks(
         },
         Version {
             network: String,
+            provider: String,
             ident: EthereumNetworkIdentifier,
         },
     }
@@ -606,7 +842,7 @@ async fn connect_networks(
                     // An `Err` means a timeout, an `Ok(Err)` means some other error (maybe a typo
                     // on the URL)
                     Ok(Err(e)) | Err(e) => {
-                        error!(logger, "Connection to provider failed. Not using this provider";
+                        error!(logger, "Connection to provider failed";
                                        "error" =>  e.to_string());
                         Status::Broken {
                             network,
@@ -620,7 +856,11 @@ async fn connect_networks(
                             "network_version" => &ident.net_version,
                             "capabilities" => &capabilities
                         );
-                        Status::Version { network, ident }
+                        Status::Version {
+                            network,
+                            provider: eth_adapter.provider().to_string(),
+                            ident,
+                        }
                     }
                 }
             }),
@@ -628,22 +868,154 @@ async fn connect_networks(
     .await;
 
     // Group identifiers by network name
-    let idents: HashMap<String, Vec<EthereumNetworkIdentifier>> =
-        statuses
-            .into_iter()
-            .fold(HashMap::new(), |mut networks, status| {
-                match status {
-                    Status::Broken { network, provider } => {
-                        eth_networks.remove(&network, &provider)
-                    }
-                    Status::Version { network, ident } => {
-                        networks.entry(network.to_string()).or_default().push(ident)
+    let mut broken = Vec::new();
+    let idents: HashMap<String, Vec<(String, EthereumNetworkIdentifier)>> = statuses
+        .into_iter()
+        .fold(HashMap::new(), |mut networks, status| {
+            match status {
+                Status::Broken { network, provider } => broken.push((network, provider)),
+                Status::Version {
+                    network,
+                    provider,
+                    ident,
+                } => networks
+                    .entry(network.to_string())
+                    .or_default()
+                    .push((provider, ident)),
+            }
+            networks
+        });
+
+    // Only drop broken providers if their network has others that work
+    let mut offline = Vec::new();
+    for (network, provider) in broken {
+        if idents.contains_key(&network) {
+            warn!(logger, "Not using provider since we could not connect to it";
+                  "network" => &network, "provider" => &provider);
+            eth_networks.remove(&network, &provider);
+        } else if !offline.contains(&network) {
+            offline.push(network);
+        }
+    }
+    for network in &offline {
+        crit!(
+            logger,
+            "Could not connect to any provider for network {}. Assuming that it has not \
+             changed since the last time we ran; indexing resumes when a provider \
+             becomes available",
+            network
+        );
+    }
+
+    // Requests for a network go to any of its providers, which only works
+    // if they all follow the same chain
+    for (network, idents) in &idents {
+        if idents.iter().any(|(_, ident)| ident != &idents[0].1) {
+            let idents: Vec<_> = idents
+                .iter()
+                .map(|(provider, ident)| {
+                    format!(
+                        "{} has net version {} and genesis block {:x}",
+                        provider, ident.net_version, ident.genesis_block_hash
+                    )
+                })
+                .collect();
+            crit!(
+                logger,
+                "The providers for network {} are on different chains: {}",
+                network,
+                idents.join("; ")
+            );
+            std::process::exit(1);
+        }
+    }
+
+    // Offline networks get an empty list of identifiers so that the block
+    // store keeps their chains ingestible with the identifier it stored
+    let idents: Vec<_> = idents
+        .into_iter()
+        .map(|(network, idents)| {
+            (
+                network,
+                idents.into_iter().map(|(_, ident)| ident).collect(),
+            )
+        })
+        .chain(offline.iter().map(|network| (network.clone(), vec![])))
+        .collect();
+    (eth_networks, idents, offline)
+}
+
+/// Keep trying to reach the providers of each network in `offline` until
+/// one of them responds, and check that it follows the chain whose genesis
+/// block we stored when the network was first used. Indexing from a chain
+/// with a different genesis block would mix blocks from two chains in the
+/// chain store; we request an orderly shutdown of the node instead, and
+/// when it starts again, the check at startup marks the chain as read-only
+fn verify_offline_networks(
+    logger: &Logger,
+    offline: Vec<String>,
+    eth_networks: &EthereumNetworks,
+    chain_stores: &HashMap<String, Arc<dyn ChainStore>>,
+) {
+    for network in offline {
+        let chain_store = match chain_stores.get(&network) {
+            Some(chain_store) => chain_store.cheap_clone(),
+            None => continue,
+        };
+        let adapters: Vec<_> = eth_networks
+            .networks
+            .get(&network)
+            .map(|adapters| {
+                adapters
+                    .adapters
+                    .iter()
+                    .map(|adapter| adapter.adapter().cheap_clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let logger = logger.new(o!("network" => network));
+
+        graph::spawn_named("verify-offline-network", async move {
+            loop {
+                tokio::time::sleep(OFFLINE_NETWORK_RETRY_INTERVAL).await;
+                for adapter in &adapters {
+                    let ident = match tokio::time::timeout(
+                        ETH_NET_VERSION_WAIT_TIME,
+                        adapter.net_identifiers(),
+                    )
+                    .await
+                    {
+                        Ok(Ok(ident)) => ident,
+                        Ok(Err(_)) | Err(_) => continue,
+                    };
+                    let genesis = match chain_store.genesis_block_ptr() {
+                        Ok(genesis) => genesis.hash_as_h256(),
+                        Err(e) => {
+                            error!(logger, "Failed to read the stored genesis block";
+                                   "error" => e.to_string());
+                            continue;
+                        }
+                    };
+                    if ident.genesis_block_hash != genesis {
+                        crit!(
+                            logger,
+                            "Provider {} has genesis block {:x}, but we stored {:x} for this \
+                             network. Stopping the node",
+                            adapter.provider(),
+                            ident.genesis_block_hash,
+                            genesis
+                        );
+                        shutdown::request();
+                        return;
                     }
+                    info!(logger, "Provider is reachable again and follows the stored chain";
+                          "provider" => adapter.provider().to_string(),
+                          "network_version" => &ident.net_version);
+                    return;
                 }
-                networks
-            });
-    let idents: Vec<_> = idents.into_iter().collect();
-    (eth_networks, idents)
+            }
+        });
+    }
 }
 
 fn create_ipfs_clients(logger: &Logger, ipfs_addresses: &Vec<String>) -> Vec<IpfsClient> {
@@ -776,6 +1148,8 @@ fn networks_as_chains(
 fn start_block_ingestor(
     logger: &Logger,
     block_polling_interval: Duration,
+    adaptive_polling: bool,
+    registry: Arc<MetricsRegistry>,
     chains: HashMap<String, Arc<ethereum::Chain>>,
 ) {
     // BlockIngestor must be configured to keep at least REORG_THRESHOLD ancestors,
@@ -811,14 +1185,25 @@ fn start_block_ingestor(
                 "network_name" => &network_name
             );
 
-            let block_ingestor = BlockIngestor::<ethereum::Chain>::new(
-                chain.ingestor_adapter(),
-                block_polling_interval,
-            )
-            .expect("failed to create Ethereum block ingestor");
-
-            // Run the Ethereum block ingestor in the background
-            graph::spawn(block_ingestor.into_polling_stream());
+            // Run the Ethereum block ingestor in the background, with a
+            // fresh ingestor every time it has to be restarted
+            let chain = chain.cheap_clone();
+            let network_name = network_name.clone();
+            let registry = registry.cheap_clone();
+            graph::spawn_essential(
+                format!("block-ingestor-{}", network_name),
+                move || {
+                    BlockIngestor::<ethereum::Chain>::new(
+                        chain.ingestor_adapter(),
+                        block_polling_interval,
+                        adaptive_polling,
+                        network_name.clone(),
+                        registry.cheap_clone(),
+                    )
+                    .expect("failed to create Ethereum block ingestor")
+                    .into_polling_stream()
+                },
+            );
         });
 }
 
@@ -862,9 +1247,10 @@ mod test {
             prometheus_registry.clone(),
         ));
 
-        let ethereum_networks = create_ethereum_networks(logger, metrics_registry, config.clone())
-            .await
-            .expect("Correctly parse Ethereum network args");
+        let ethereum_networks =
+            create_ethereum_networks(logger, metrics_registry, config.clone(), None)
+                .await
+                .expect("Correctly parse Ethereum network args");
         let mut network_names = ethereum_networks.networks.keys().collect::<Vec<&String>>();
         network_names.sort();
 
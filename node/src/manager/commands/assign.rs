@@ -32,3 +32,45 @@ pub fn reassign(
 
     Ok(())
 }
+
+pub fn set_standby(
+    store: Arc<SubgraphStore>,
+    hash: String,
+    node: Option<String>,
+    shard: Option<String>,
+) -> Result<(), Error> {
+    let node = node
+        .map(|node| NodeId::new(node.clone()).map_err(|()| anyhow!("illegal node id `{}`", node)))
+        .transpose()?;
+    let deployment = locate(store.as_ref(), hash, shard)?;
+
+    match &node {
+        Some(node) => {
+            if store.assigned_node(&deployment)?.as_ref() == Some(node) {
+                return Err(anyhow!(
+                    "{} is assigned to {} and it can not also be its standby",
+                    deployment,
+                    node.as_str()
+                ));
+            }
+            println!("making {} the standby for {}", node.as_str(), deployment);
+        }
+        None => println!("removing the standby for {}", deployment),
+    }
+    store.set_standby_node(&deployment, node.as_ref())?;
+
+    Ok(())
+}
+
+pub fn promote_standby(
+    store: Arc<SubgraphStore>,
+    hash: String,
+    shard: Option<String>,
+) -> Result<(), Error> {
+    let deployment = locate(store.as_ref(), hash, shard)?;
+
+    let node = store.promote_standby(&deployment)?;
+    println!("reassigned {} to its standby {}", deployment, node.as_str());
+
+    Ok(())
+}
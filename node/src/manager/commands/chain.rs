@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use graph::blockchain::BlockPtr;
 use graph::prelude::BlockNumber;
@@ -118,3 +119,50 @@ pub fn remove(primary: ConnectionPool, store: Arc<BlockStore>, name: String) ->
 
     Ok(())
 }
+
+pub fn rename(
+    primary: ConnectionPool,
+    store: Arc<BlockStore>,
+    old: String,
+    new: String,
+    dead_after: u64,
+) -> Result<(), Error> {
+    let conn = graph_store_postgres::command_support::catalog::Connection::new(primary.get()?);
+
+    // Nodes that are running would keep using the old name; make sure
+    // none of them is alive before we change anything
+    let mut alive: Vec<_> = conn
+        .heartbeats(Duration::from_secs(dead_after))?
+        .into_iter()
+        .filter(|(_, (_, dead))| !dead)
+        .map(|(node, _)| node)
+        .collect();
+    if !alive.is_empty() {
+        alive.sort();
+        bail!(
+            "the following nodes recorded a heartbeat in the last {}s and seem to be running: {}. \
+             Stop all nodes before renaming chain {}",
+            dead_after,
+            alive.join(", "),
+            old
+        );
+    }
+
+    let sites = conn.find_sites_for_network(&old)?;
+    drop(conn);
+
+    store.rename_chain(&old, &new)?;
+
+    println!(
+        "renamed chain {} to {}; {} deployments now use chain {}",
+        old,
+        new,
+        sites.len(),
+        new
+    );
+    println!(
+        "change the name of the chain in the configuration of all nodes to {} before starting them",
+        new
+    );
+    Ok(())
+}
@@ -1,15 +1,35 @@
 use std::collections::BTreeMap;
 
-use graph::prelude::{
-    anyhow::{anyhow, Error},
-    NodeId,
+use graph::{
+    data::subgraph::{labels::validate_labels, Labels},
+    prelude::{
+        anyhow::{anyhow, Error},
+        NodeId,
+    },
 };
 use graph_store_postgres::DeploymentPlacer;
 
 use crate::config::Config;
 
-pub fn place(placer: &dyn DeploymentPlacer, name: &str, network: &str) -> Result<(), Error> {
-    match placer.place(name, network).map_err(|s| anyhow!(s))? {
+pub fn place(
+    placer: &dyn DeploymentPlacer,
+    name: &str,
+    network: &str,
+    labels: Vec<String>,
+) -> Result<(), Error> {
+    let labels = labels
+        .iter()
+        .map(|label| match label.find('=') {
+            Some(pos) => Ok((label[..pos].to_string(), label[pos + 1..].to_string())),
+            None => Err(anyhow!("label `{}` is not of the form key=value", label)),
+        })
+        .collect::<Result<Labels, _>>()?;
+    validate_labels(&labels).map_err(|e| anyhow!(e))?;
+
+    match placer
+        .place(name, network, &labels)
+        .map_err(|s| anyhow!(s))?
+    {
         None => {
             println!(
                 "no matching placement rule; default placement from JSON RPC call would be used"
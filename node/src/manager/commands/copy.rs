@@ -44,6 +44,7 @@ struct CopyTableState {
     started_at: UtcDateTime,
     finished_at: Option<UtcDateTime>,
     duration_ms: i64,
+    rows_copied: i64,
 }
 
 impl CopyState {
@@ -299,10 +300,10 @@ pub fn status(pools: HashMap<Shard, ConnectionPool>, dst: i32) -> Result<(), Err
     println!("");
 
     println!(
-        "{:^30} | {:^8} | {:^8} | {:^8} | {:^8}",
-        "entity type", "next", "target", "batch", "duration"
+        "{:^30} | {:^8} | {:^8} | {:^8} | {:^10} | {:^8}",
+        "entity type", "next", "target", "batch", "rows", "duration"
     );
-    println!("{:-<74}", "-");
+    println!("{:-<87}", "-");
     for table in tables {
         let status = if table.next_vid > 0 && table.next_vid < table.target_vid {
             ">".to_string()
@@ -313,12 +314,13 @@ pub fn status(pools: HashMap<Shard, ConnectionPool>, dst: i32) -> Result<(), Err
             done(&table.finished_at)
         };
         println!(
-            "{} {:<28} | {:>8} | {:>8} | {:>8} | {:>8}",
+            "{} {:<28} | {:>8} | {:>8} | {:>8} | {:>10} | {:>8}",
             status,
             table.entity_type,
             table.next_vid,
             table.target_vid,
             table.batch_size,
+            table.rows_copied,
             human_duration(Duration::milliseconds(table.duration_ms)),
         );
     }
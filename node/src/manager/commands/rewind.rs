@@ -16,6 +16,15 @@ pub fn run(
     let block_ptr_to = BlockPtr::try_from((block_hash.as_str(), block_number as i64))
         .map_err(|e| anyhow!("error converting to block pointer: {}", e))?;
 
-    store.rewind(id, block_ptr_to)?;
+    let report = store.rewind(id, block_ptr_to)?;
+    println!(
+        "Rewound {} blocks in {}ms: restored {} and removed {} entity versions, \
+         removed {} dynamic data sources",
+        report.blocks(),
+        report.duration_ms,
+        report.entities_restored(),
+        report.entities_removed(),
+        report.dynamic_data_sources_removed
+    );
     Ok(())
 }
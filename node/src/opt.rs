@@ -2,6 +2,10 @@ use git_testament::{git_testament, render_testament};
 use lazy_static::lazy_static;
 use structopt::StructOpt;
 
+use graph::log::LogFormat;
+use graph::prelude::RetryFailedPolicy;
+use graph::EssentialTaskPolicy;
+
 use crate::config;
 
 git_testament!(TESTAMENT);
@@ -27,6 +31,11 @@ pub struct Opt {
     pub config: Option<String>,
     #[structopt(long, help = "validate the configuration and exit")]
     pub check_config: bool,
+    #[structopt(
+        long,
+        help = "together with --version, print build information as JSON and exit"
+    )]
+    pub json: bool,
     #[structopt(
         long,
         value_name = "[NAME:]IPFS_HASH",
@@ -70,15 +79,15 @@ pub struct Opt {
         long,
         min_values=0,
         required_unless_one = &["ethereum-ws", "ethereum-ipc", "config"],
-        conflicts_with_all = &["ethereum-ws", "ethereum-ipc", "config"],
+        conflicts_with = "config",
         value_name="NETWORK_NAME:[CAPABILITIES]:URL",
         env="ETHEREUM_RPC",
-        help= "Ethereum network name (e.g. 'mainnet'), optional comma-seperated capabilities (eg 'full,archive'), and an Ethereum RPC URL, separated by a ':'",
+        help= "Ethereum network name (e.g. 'mainnet'), optional comma-seperated capabilities (eg 'full,archive'), and an Ethereum RPC URL, separated by a ':'. Append ':limit=NNN' to send at most NNN requests per second",
     )]
     pub ethereum_rpc: Vec<String>,
     #[structopt(long, min_values=0,
         required_unless_one = &["ethereum-rpc", "ethereum-ipc", "config"],
-        conflicts_with_all = &["ethereum-rpc", "ethereum-ipc", "config"],
+        conflicts_with = "config",
         value_name="NETWORK_NAME:[CAPABILITIES]:URL",
         env="ETHEREUM_WS",
         help= "Ethereum network name (e.g. 'mainnet'), optional comma-seperated capabilities (eg 'full,archive`, and an Ethereum WebSocket URL, separated by a ':'",
@@ -86,7 +95,7 @@ pub struct Opt {
     pub ethereum_ws: Vec<String>,
     #[structopt(long, min_values=0,
         required_unless_one = &["ethereum-rpc", "ethereum-ws", "config"],
-        conflicts_with_all = &["ethereum-rpc", "ethereum-ws", "config"],
+        conflicts_with = "config",
         value_name="NETWORK_NAME:[CAPABILITIES]:FILE",
         env="ETHEREUM_IPC",
         help= "Ethereum network name (e.g. 'mainnet'), optional comma-seperated capabilities (eg 'full,archive'), and an Ethereum IPC pipe, separated by a ':'",
@@ -106,6 +115,22 @@ pub struct Opt {
         help = "Port for the GraphQL HTTP server"
     )]
     pub http_port: u16,
+    #[structopt(
+        long,
+        default_value = "10",
+        value_name = "OPERATIONS",
+        env = "GRAPH_GRAPHQL_MAX_BATCH_SIZE",
+        help = "The most operations a request to the GraphQL HTTP server may \
+                contain when it sends a batch of them as a JSON array"
+    )]
+    pub graphql_max_batch_size: usize,
+    #[structopt(
+        long,
+        env = "GRAPH_GRAPHQL_DISABLE_BATCHING",
+        help = "Only accept requests to the GraphQL HTTP server that contain \
+                a single operation"
+    )]
+    pub graphql_disable_batching: bool,
     #[structopt(
         long,
         default_value = "8030",
@@ -144,6 +169,16 @@ pub struct Opt {
     pub node_id: String,
     #[structopt(long, help = "Enable debug logging")]
     pub debug: bool,
+    #[structopt(
+        long,
+        default_value = "text",
+        possible_values = &["text", "json"],
+        value_name = "FORMAT",
+        env = "GRAPH_LOG_FORMAT",
+        help = "How to format log output: `text` for human-readable lines or \
+                `json` for one JSON object per line"
+    )]
+    pub log_format: LogFormat,
 
     #[structopt(
         long,
@@ -175,6 +210,58 @@ pub struct Opt {
         help = "How often to poll the Ethereum node for new blocks"
     )]
     pub ethereum_polling_interval: u64,
+    #[structopt(
+        long,
+        value_name = "SECONDS",
+        default_value = "30",
+        env = "GRAPH_NODE_SHUTDOWN_TIMEOUT",
+        help = "How long to wait for indexing to stop after SIGTERM or SIGINT before exiting anyway"
+    )]
+    pub shutdown_timeout: u64,
+    #[structopt(
+        long,
+        env = "GRAPH_DISABLE_ADAPTIVE_POLLING",
+        help = "Always poll the Ethereum node at the `--ethereum-polling-interval` \
+                instead of adapting the interval to each chain's block time"
+    )]
+    pub disable_adaptive_polling: bool,
+    #[structopt(
+        long,
+        default_value = "non-deterministic",
+        possible_values = &["none", "non-deterministic", "all"],
+        value_name = "POLICY",
+        env = "GRAPH_RETRY_FAILED_ON_STARTUP",
+        help = "Which failed subgraphs to retry when the node starts: `none`, \
+                `non-deterministic` or `all`. Retrying clears the failure; \
+                failed subgraphs that are not retried are started as before \
+                and keep their failure"
+    )]
+    pub retry_failed_on_startup: RetryFailedPolicy,
+    #[structopt(
+        long,
+        default_value = "restart",
+        possible_values = &["restart", "abort"],
+        value_name = "POLICY",
+        env = "GRAPH_ESSENTIAL_TASK_PANIC",
+        help = "What to do when an essential task like a server or a block ingestor \
+                panics: `restart` it or `abort` the process"
+    )]
+    pub essential_task_panic: EssentialTaskPolicy,
+    #[structopt(
+        long,
+        env = "GRAPH_VALIDATE_DEPLOYMENTS_ON_STARTUP",
+        help = "Check that the manifests and files of all subgraphs assigned to this node \
+                can still be resolved when the node starts, and report the ones that can not"
+    )]
+    pub validate_deployments_on_startup: bool,
+    #[structopt(
+        long,
+        env = "GRAPH_DEV",
+        help = "Allow operations that are only meant for developing subgraphs, like \
+                reloading the mappings of a deployment with `subgraph_reload_mappings`. \
+                Do not use this for a node that serves production traffic"
+    )]
+    pub dev: bool,
     #[structopt(
         long,
         value_name = "DISABLE_BLOCK_INGESTOR",
@@ -198,6 +285,26 @@ pub struct Opt {
                 (e.g. 'ethereum/mainnet')."
     )]
     pub network_subgraphs: Vec<String>,
+    #[structopt(
+        long,
+        value_name = "TOKEN",
+        env = "GRAPH_BLOCK_CACHE_TOKEN",
+        hide_env_values = true,
+        help = "Serve cached blocks to peers that present this token from the index node \
+                server at /block-cache/<network>/<hash>, and present it to the peers \
+                given with --block-cache-peers"
+    )]
+    pub block_cache_token: Option<String>,
+    #[structopt(
+        long,
+        value_name = "URL,",
+        use_delimiter = true,
+        env = "GRAPH_BLOCK_CACHE_PEERS",
+        requires = "block-cache-token",
+        help = "Comma-separated list of index node server URLs of other graph-node \
+                instances to ask for blocks before asking the Ethereum node"
+    )]
+    pub block_cache_peers: Vec<String>,
 }
 
 impl From<Opt> for config::Opt {
@@ -131,9 +131,11 @@ impl StoreBuilder {
 
         let store = Arc::new(SubgraphStore::new(
             logger,
+            node.clone(),
             shards,
             Arc::new(config.deployment.clone()),
             notification_sender,
+            registry,
         ));
 
         (store, pools)
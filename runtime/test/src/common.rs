@@ -3,6 +3,7 @@ use graph::components::store::DeploymentLocator;
 use graph::data::subgraph::*;
 use graph::ipfs_client::IpfsClient;
 use graph::prelude::*;
+use graph::runtime::MAX_TRIGGER_BYTES;
 use graph_chain_ethereum::{
     Chain, DataSource, DataSourceTemplate, Mapping, MappingABI, TemplateSource,
 };
@@ -22,15 +23,16 @@ fn mock_host_exports(
         name: String::from("example template"),
         network: Some(String::from("mainnet")),
         source: TemplateSource {
-            abi: String::from("foo"),
+            abi: String::from("mock_abi"),
         },
         mapping: Mapping {
             kind: String::from("ethereum/events"),
             api_version,
             language: String::from("wasm/assemblyscript"),
             entities: vec![],
-            abis: vec![],
+            abis: vec![Arc::new(mock_abi())],
             event_handlers: vec![],
+            oversized_logs: None,
             call_handlers: vec![],
             block_handlers: vec![],
             link: Link {
@@ -48,6 +50,9 @@ fn mock_host_exports(
         Arc::new(templates),
         Arc::new(graph_core::LinkResolver::from(IpfsClient::localhost())),
         store,
+        false,
+        *MAX_TRIGGER_BYTES,
+        false,
     )
 }
 
@@ -107,6 +112,7 @@ pub fn mock_data_source(path: &str, api_version: Version) -> DataSource {
             address: Some(Address::from_str("0123123123012312312301231231230123123123").unwrap()),
             abi: String::from("123123"),
             start_block: 0,
+            end_block: None,
         },
         mapping: Mapping {
             kind: String::from("ethereum/events"),
@@ -115,6 +121,7 @@ pub fn mock_data_source(path: &str, api_version: Version) -> DataSource {
             entities: vec![],
             abis: vec![],
             event_handlers: vec![],
+            oversized_logs: None,
             call_handlers: vec![],
             block_handlers: vec![],
             link: Link {
@@ -124,6 +131,25 @@ pub fn mock_data_source(path: &str, api_version: Version) -> DataSource {
         },
         context: Default::default(),
         creation_block: None,
+        first_block: 0,
+        once_handlers_ran: None,
         contract_abi: Arc::new(mock_abi()),
     }
 }
+
+pub fn mock_template(path: &str, api_version: Version) -> DataSourceTemplate {
+    let data_source = mock_data_source(path, api_version);
+
+    DataSourceTemplate {
+        kind: data_source.kind,
+        network: data_source.network,
+        name: String::from("example template"),
+        source: TemplateSource {
+            abi: String::from("mock_abi"),
+        },
+        mapping: Mapping {
+            abis: vec![Arc::new(mock_abi())],
+            ..data_source.mapping
+        },
+    }
+}
@@ -1,27 +1,33 @@
+use graph::blockchain::HostFn;
+use graph::components::subgraph::MappingError;
 use graph::data::store::scalar;
 use graph::data::subgraph::*;
 use graph::prelude::web3::types::U256;
 use graph::prelude::*;
 use graph::runtime::AscPtr;
-use graph::runtime::{asc_get, asc_new, try_asc_get};
+use graph::runtime::DeterministicHostError;
+use graph::runtime::{asc_get, asc_new, try_asc_get, AscHeap, MAX_HEAP_BYTES, MAX_TRIGGER_BYTES};
 use graph::{components::store::*, ipfs_client::IpfsClient};
-use graph_chain_ethereum::{Chain, DataSource};
+use graph_chain_ethereum::{
+    Chain, DataSource, MappingBlockHandler, MappingEventHandler, MappingTrigger, RuntimeAdapter,
+};
 use graph_mock::MockMetricsRegistry;
 use graph_runtime_wasm::asc_abi::class::{Array, AscBigInt, AscEntity, AscString, Uint8Array};
 use graph_runtime_wasm::{ExperimentalFeatures, ValidModule, WasmInstance};
 use hex;
 use semver::Version;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::str::FromStr;
 use test_store::STORE;
-use web3::types::H160;
+use web3::types::{H160, H256};
 
-use crate::common::{mock_context, mock_data_source};
+use crate::common::{mock_context, mock_data_source, mock_template};
 
 mod abi;
 
 const API_VERSION_0_0_4: Version = Version::new(0, 0, 4);
 const API_VERSION_0_0_5: Version = Version::new(0, 0, 5);
+const API_VERSION_0_0_6: Version = Version::new(0, 0, 6);
 
 fn wasm_file_path(wasm_file: &str, api_version: Version) -> String {
     format!(
@@ -46,7 +52,7 @@ fn test_valid_module_and_store(
     Arc<impl SubgraphStore>,
     DeploymentLocator,
 ) {
-    test_valid_module_and_store_with_timeout(subgraph_id, data_source, api_version, None)
+    test_valid_module_and_store_with_timeout(subgraph_id, data_source, api_version, None, vec![])
 }
 
 fn test_valid_module_and_store_with_timeout(
@@ -54,6 +60,7 @@ fn test_valid_module_and_store_with_timeout(
     data_source: DataSource,
     api_version: Version,
     timeout: Option<Duration>,
+    host_fns: Vec<HostFn>,
 ) -> (
     WasmInstance<Chain>,
     Arc<impl SubgraphStore>,
@@ -93,14 +100,18 @@ fn test_valid_module_and_store_with_timeout(
         allow_non_deterministic_ipfs: true,
     };
 
+    let valid_module = Arc::new(ValidModule::new(data_source.mapping.runtime.as_ref()).unwrap());
+    let mut ctx = mock_context(
+        deployment.clone(),
+        data_source,
+        store.subgraph_store(),
+        api_version,
+    );
+    ctx.host_fns = Arc::new(host_fns);
+
     let module = WasmInstance::from_valid_module_with_ctx(
-        Arc::new(ValidModule::new(data_source.mapping.runtime.as_ref()).unwrap()),
-        mock_context(
-            deployment.clone(),
-            data_source,
-            store.subgraph_store(),
-            api_version,
-        ),
+        valid_module,
+        ctx,
         host_metrics,
         timeout,
         experimental_features,
@@ -542,6 +553,35 @@ async fn crypto_keccak256_v0_0_5() {
     test_crypto_keccak256(API_VERSION_0_0_5);
 }
 
+#[tokio::test]
+async fn crypto_sha256_and_ripemd160() {
+    // The memory layout of apiVersion 0.0.6 is the same as that of 0.0.5
+    let mut module = test_module(
+        "cryptoHashes",
+        mock_data_source(
+            &wasm_file_path("crypto_hashes.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_6,
+        ),
+        API_VERSION_0_0_6,
+    );
+    let input: &[u8] = "abc".as_ref();
+    let input: AscPtr<Uint8Array> = asc_new(&mut module, input).unwrap();
+
+    let hash: AscPtr<Uint8Array> = module.invoke_export1("sha256", input);
+    let hash: Vec<u8> = asc_get(&module, hash).unwrap();
+    assert_eq!(
+        hex::encode(hash),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+
+    let hash: AscPtr<Uint8Array> = module.invoke_export1("ripemd160", input);
+    let hash: Vec<u8> = asc_get(&module, hash).unwrap();
+    assert_eq!(
+        hex::encode(hash),
+        "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc"
+    );
+}
+
 fn test_big_int_to_hex(api_version: Version) {
     let mut module = test_module(
         "BigIntToHex",
@@ -771,6 +811,88 @@ async fn data_source_create_v0_0_5() {
     test_data_source_create(API_VERSION_0_0_5);
 }
 
+fn test_data_source_create_duplicates(api_version: Version) {
+    fn create(module: &mut WasmInstance<Chain>, address: &str) {
+        let name = asc_new(module, &String::from("example template")).unwrap();
+        let params = asc_new(module, vec![address.to_string()].as_slice()).unwrap();
+        module
+            .invoke_export2_void("dataSourceCreate", name, params)
+            .expect("unexpected error returned from dataSourceCreate");
+    }
+
+    fn handler(module: &mut WasmInstance<Chain>, addresses: &[&str]) {
+        module.instance_ctx_mut().ctx.state.enter_handler();
+        for address in addresses {
+            create(module, address);
+        }
+        module.instance_ctx_mut().ctx.state.exit_handler();
+    }
+
+    let mut module = test_module(
+        "DataSourceCreateDuplicates",
+        mock_data_source(
+            &wasm_file_path("data_source_create.wasm", api_version.clone()),
+            api_version.clone(),
+        ),
+        api_version.clone(),
+    );
+    let address = "0xc0a47dFe034B400B47bDaD5FecDa2621de6c4d95";
+    let other = "0xc000000000000000000000000000000000000000";
+
+    // Duplicates within one handler, including the same address spelled
+    // differently, only create one data source
+    handler(&mut module, &[address, address, &address.to_lowercase()]);
+
+    // Duplicates across handlers in one block are ignored, too
+    handler(&mut module, &[address, other]);
+
+    let created = module
+        .instance_ctx_mut()
+        .ctx
+        .state
+        .drain_created_data_sources();
+    assert_eq!(
+        vec![vec![address.to_string()], vec![other.to_string()]],
+        created
+            .into_iter()
+            .map(|info| info.params)
+            .collect::<Vec<_>>()
+    );
+
+    // Data sources that were drained earlier in the block still count
+    handler(&mut module, &[other]);
+    assert!(!module
+        .instance_ctx_mut()
+        .ctx
+        .state
+        .has_created_data_sources());
+
+    // Duplicates across blocks are ignored
+    {
+        let mut instance = module.instance_ctx_mut();
+        let existing = instance.ctx.state.known_data_sources();
+        let store = instance.ctx.state.entity_cache.store.clone();
+        instance.ctx.state =
+            BlockState::new(store, Default::default()).with_existing_data_sources(existing);
+    }
+    handler(&mut module, &[address, other]);
+    assert!(!module
+        .instance_ctx_mut()
+        .ctx
+        .state
+        .has_created_data_sources());
+}
+
+#[tokio::test]
+async fn data_source_create_duplicates_v0_0_4() {
+    test_data_source_create_duplicates(API_VERSION_0_0_4);
+}
+
+#[tokio::test]
+async fn data_source_create_duplicates_v0_0_5() {
+    test_data_source_create_duplicates(API_VERSION_0_0_5);
+}
+
 fn test_ens_name_by_hash(api_version: Version) {
     let mut module = test_module(
         "EnsNameByHash",
@@ -940,6 +1062,78 @@ async fn detect_contract_calls_v0_0_5() {
     test_detect_contract_calls(API_VERSION_0_0_5);
 }
 
+#[tokio::test]
+async fn no_eth_calls_fail_deterministically() {
+    let eth_call = HostFn {
+        name: "ethereum.call",
+        func: Arc::new(|_ctx, _wasm_ptr| panic!("eth_call must not reach the Ethereum node")),
+    };
+    let mut host_fns = vec![eth_call];
+    graph_runtime_wasm::disable_eth_calls(&mut host_fns);
+
+    let (module, _, _) = test_valid_module_and_store_with_timeout(
+        "noEthCalls",
+        mock_data_source(
+            &wasm_file_path("contract_calls.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_5,
+        ),
+        API_VERSION_0_0_5,
+        None,
+        host_fns,
+    );
+    let res: Result<(), _> = module.get_func("callContract").typed().unwrap().call(0u32);
+    assert!(res
+        .unwrap_err()
+        .to_string()
+        .contains("eth_call disabled for this deployment"));
+    assert!(module.instance_ctx_mut().deterministic_host_trap);
+}
+
+#[tokio::test]
+async fn no_eth_calls_deploy_warning() {
+    use graph::data::subgraph::features::imports_disabled_eth_call;
+    use std::marker::PhantomData;
+
+    fn manifest(wasm_file: &str, no_eth_calls: bool) -> SubgraphManifest<Chain> {
+        let id = DeploymentHash::new("noEthCallsWarning").unwrap();
+        let mut features = BTreeSet::new();
+        if no_eth_calls {
+            features.insert(SubgraphFeature::NoEthCalls);
+        }
+        SubgraphManifest {
+            id: id.clone(),
+            spec_version: Version::new(0, 0, 4),
+            features,
+            description: None,
+            repository: None,
+            schema: Schema::parse("type Thing @entity { id: ID! }", id).unwrap(),
+            data_sources: vec![mock_data_source(
+                &wasm_file_path(wasm_file, API_VERSION_0_0_5),
+                API_VERSION_0_0_5,
+            )],
+            graft: None,
+            templates: vec![],
+            max_triggers_per_block: None,
+            chain: PhantomData,
+        }
+    }
+
+    // Only a mapping that imports `ethereum.call` despite declaring
+    // `noEthCalls` is warned about
+    assert!(matches!(
+        imports_disabled_eth_call(&manifest("contract_calls.wasm", true)),
+        Ok(true)
+    ));
+    assert!(matches!(
+        imports_disabled_eth_call(&manifest("contract_calls.wasm", false)),
+        Ok(false)
+    ));
+    assert!(matches!(
+        imports_disabled_eth_call(&manifest("abi_store_value.wasm", true)),
+        Ok(false)
+    ));
+}
+
 fn test_allocate_global(api_version: Version) {
     let module = test_module(
         "AllocateGlobal",
@@ -1001,3 +1195,512 @@ fn test_safe_null_ptr_read(api_version: Version) {
 async fn safe_null_ptr_read_0_0_5() {
     test_safe_null_ptr_read(API_VERSION_0_0_5);
 }
+
+/// A block with what it takes to pass it to a mapping
+fn test_block() -> LightEthereumBlock {
+    LightEthereumBlock {
+        hash: Some(H256::from_low_u64_be(1)),
+        number: Some(1.into()),
+        ..Default::default()
+    }
+}
+
+fn log_trigger(data: Vec<u8>) -> MappingTrigger {
+    MappingTrigger::Log {
+        block: Arc::new(test_block()),
+        transaction: Arc::new(EthereumTransaction {
+            transaction_index: Some(0.into()),
+            ..Default::default()
+        }),
+        log: Arc::new(web3::types::Log::default()),
+        params: vec![ethabi::LogParam {
+            name: "data".to_owned(),
+            value: ethabi::Token::Bytes(data),
+        }],
+        handler: MappingEventHandler {
+            event: "Data(bytes)".to_owned(),
+            topic0: None,
+            handler: "handleData".to_owned(),
+            receipt: false,
+            topic1: None,
+            topic2: None,
+            topic3: None,
+            anonymous: false,
+        },
+        receipt: None,
+    }
+}
+
+#[tokio::test]
+async fn trigger_budget() {
+    let mut module = test_module(
+        "TriggerBudget",
+        mock_data_source(
+            &wasm_file_path("abi_token.wasm", API_VERSION_0_0_4),
+            API_VERSION_0_0_4,
+        ),
+        API_VERSION_0_0_4,
+    );
+
+    // Normal triggers are well within the budget
+    module
+        .marshal_trigger(log_trigger(vec![7; 1024]), *MAX_TRIGGER_BYTES)
+        .unwrap();
+
+    // A 50MB log is not
+    let err = module
+        .marshal_trigger(log_trigger(vec![7; 50 * 1024 * 1024]), *MAX_TRIGGER_BYTES)
+        .unwrap_err();
+    assert_eq!(
+        format!(
+            "The trigger for handler `handleData` needs more than {} bytes of memory; \
+             the limit is what GRAPH_RUNTIME_MAX_TRIGGER_BYTES was set to \
+             when the deployment was created",
+            *MAX_TRIGGER_BYTES
+        ),
+        err.to_string()
+    );
+}
+
+#[tokio::test]
+async fn heap_limit() {
+    // The memory layout of apiVersion 0.0.6 is the same as that of 0.0.5
+    let heap_module = |api_version: Version| {
+        test_module(
+            "HeapLimit",
+            mock_data_source(
+                &wasm_file_path("abi_classes.wasm", API_VERSION_0_0_5),
+                api_version.clone(),
+            ),
+            api_version,
+        )
+    };
+
+    // The limit only applies to mappings with apiVersion 0.0.6 and later
+    let module = heap_module(API_VERSION_0_0_5);
+    assert!(module.instance_ctx_mut().heap_limit.is_none());
+    let mut module = heap_module(API_VERSION_0_0_6);
+    let heap_limit = module.instance_ctx_mut().heap_limit.clone().unwrap();
+    assert_eq!(*MAX_HEAP_BYTES, heap_limit.max_bytes());
+
+    const PAGE_SIZE: usize = 64 * 1024;
+    const HEADROOM: usize = 1024 * 1024;
+    let max_heap_bytes = module.instance_ctx_mut().heap_size() + HEADROOM;
+    heap_limit.set_max_bytes(max_heap_bytes);
+
+    // Memory can be filled up to just under the limit. For the second
+    // allocation, the allocator can not double memory, and grows it by what
+    // it needs instead
+    module.raw_new(&vec![7; HEADROOM / 2]).unwrap();
+    module.raw_new(&vec![7; HEADROOM / 2 - 4096]).unwrap();
+    assert!(!module.instance_ctx_mut().heap_limit_exceeded());
+    assert!(module.instance_ctx_mut().heap_size() > max_heap_bytes - 2 * PAGE_SIZE);
+
+    // Allocating more than is left fails deterministically
+    let err = module.raw_new(&vec![7; HEADROOM / 2]).unwrap_err();
+    assert_eq!(
+        format!(
+            "The mapping needs more than {} bytes of memory; \
+             the limit is set with GRAPH_RUNTIME_MAX_HEAP_BYTES",
+            max_heap_bytes
+        ),
+        err.to_string()
+    );
+    assert!(module.instance_ctx_mut().heap_limit_exceeded());
+    assert!(module.instance_ctx_mut().heap_size() <= max_heap_bytes);
+}
+
+#[tokio::test]
+async fn heap_limit_memory_grow() {
+    let grow_module = || {
+        test_module(
+            "HeapLimitMemoryGrow",
+            mock_data_source(
+                &wasm_file_path("grow_memory.wasm", API_VERSION_0_0_5),
+                API_VERSION_0_0_6,
+            ),
+            API_VERSION_0_0_6,
+        )
+    };
+    let grow_array = |module: &WasmInstance<Chain>, length: u32| {
+        module
+            .get_func("growArray")
+            .typed::<u32, ()>()
+            .unwrap()
+            .call(length)
+    };
+
+    const PAGE_SIZE: usize = 64 * 1024;
+    const MAX_PAGES: usize = 16;
+
+    // Growing the array within the limit works
+    let module = grow_module();
+    let heap_limit = module.instance_ctx_mut().heap_limit.clone().unwrap();
+    heap_limit.set_max_bytes(MAX_PAGES * PAGE_SIZE);
+    grow_array(&module, MAX_PAGES as u32 / 2).unwrap();
+    assert!(!module.instance_ctx_mut().heap_limit_exceeded());
+
+    // The loop keeps growing the array until `memory.grow` fails, and the
+    // allocator traps; memory never grows beyond the limit
+    let module = grow_module();
+    let heap_limit = module.instance_ctx_mut().heap_limit.clone().unwrap();
+    heap_limit.set_max_bytes(MAX_PAGES * PAGE_SIZE);
+    let trap = grow_array(&module, 10 * MAX_PAGES as u32).unwrap_err();
+    assert_eq!(
+        Some(wasmtime::TrapCode::UnreachableCodeReached),
+        trap.trap_code()
+    );
+    assert!(module.instance_ctx_mut().heap_limit_exceeded());
+    assert!(module.instance_ctx_mut().heap_size() <= MAX_PAGES * PAGE_SIZE);
+}
+
+fn handler_timeout_module(host_fns: Vec<HostFn>) -> WasmInstance<Chain> {
+    test_valid_module_and_store_with_timeout(
+        "HandlerTimeout",
+        mock_data_source(
+            &wasm_file_path("handler_timeout.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_5,
+        ),
+        API_VERSION_0_0_5,
+        Some(Duration::from_secs(1)),
+        host_fns,
+    )
+    .0
+}
+
+fn block_trigger(handler: &str) -> MappingTrigger {
+    MappingTrigger::Block {
+        block: Arc::new(test_block()),
+        uncles: None,
+        handler: MappingBlockHandler {
+            handler: handler.to_string(),
+            filter: None,
+        },
+    }
+}
+
+// The timeout is enforced by a separate task, which needs a thread of its
+// own while the handler runs
+#[tokio::test(flavor = "multi_thread")]
+async fn handler_timeout_fails_deterministically() {
+    let module = handler_timeout_module(vec![]);
+    let err = match module.handle_trigger(block_trigger("handleBlockLoop")) {
+        Err(MappingError::Unknown(e)) => e,
+        Err(MappingError::PossibleReorg(e)) => panic!("unexpected possible reorg: {:#}", e),
+        Ok(_) => panic!("a handler that loops forever must time out"),
+    };
+
+    // The subgraph fails, even if it allows non-fatal errors
+    assert!(err.downcast_ref::<DeterministicHostError>().is_some());
+    assert!(format!("{:#}", err)
+        .starts_with("Handler 'handleBlockLoop' hit the timeout of '1' seconds"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn handler_timeout_ignores_host_calls() {
+    let slow_call = HostFn {
+        name: "test.slowCall",
+        func: Arc::new(move |_ctx, _wasm_ptr| {
+            std::thread::sleep(Duration::from_secs(3));
+            Ok(0)
+        }),
+    };
+    let module = handler_timeout_module(vec![slow_call]);
+    assert!(module
+        .handle_trigger(block_trigger("handleBlockSlowCall"))
+        .is_ok());
+}
+
+#[tokio::test]
+async fn block_handler_iterates_uncles() {
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let uncle = {
+        let seen = seen.clone();
+        HostFn {
+            name: "test.uncle",
+            func: Arc::new(move |ctx, wasm_ptr| {
+                let hash: Vec<u8> = asc_get::<_, Uint8Array, _>(&*ctx.heap, wasm_ptr.into())?;
+                seen.lock().unwrap().push(H256::from_slice(&hash));
+                Ok(0)
+            }),
+        }
+    };
+    let module = test_valid_module_and_store_with_timeout(
+        "BlockUncles",
+        mock_data_source(
+            &wasm_file_path("block_uncles.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_6,
+        ),
+        API_VERSION_0_0_6,
+        None,
+        vec![uncle],
+    )
+    .0;
+
+    let uncles: Vec<_> = (2..4)
+        .map(|i| EthereumUncle {
+            hash: Some(H256::from_low_u64_be(i)),
+            number: Some(0.into()),
+            ..Default::default()
+        })
+        .collect();
+    let trigger = match block_trigger("handleBlockUncles") {
+        MappingTrigger::Block { block, handler, .. } => MappingTrigger::Block {
+            block,
+            uncles: Some(Arc::new(uncles)),
+            handler,
+        },
+        _ => unreachable!(),
+    };
+    module.handle_trigger(trigger).unwrap();
+
+    assert_eq!(
+        vec![H256::from_low_u64_be(2), H256::from_low_u64_be(3)],
+        *seen.lock().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn data_source_deployment() {
+    let module = test_module(
+        "DataSourceDeployment",
+        mock_data_source(
+            &wasm_file_path("data_source_deployment.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_5,
+        ),
+        API_VERSION_0_0_5,
+    );
+
+    let deployment: AscPtr<AscString> = module.invoke_export0("deployment");
+    let deployment: String = asc_get(&module, deployment).unwrap();
+    assert_eq!(
+        subgraph_id_with_api_version("DataSourceDeployment", API_VERSION_0_0_5),
+        deployment
+    );
+}
+
+#[tokio::test]
+async fn block_handler_sees_changed_entities() {
+    let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let module = || {
+        let reported = reported.clone();
+        let report = HostFn {
+            name: "test.report",
+            func: Arc::new(move |ctx, wasm_ptr| {
+                let ids: Vec<String> =
+                    asc_get::<_, Array<AscPtr<AscString>>, _>(&*ctx.heap, wasm_ptr.into())?;
+                reported.lock().unwrap().push(ids);
+                Ok(0)
+            }),
+        };
+        let (module, _, deployment) = test_valid_module_and_store_with_timeout(
+            "ChangedEntities",
+            mock_data_source(
+                &wasm_file_path("changed_entities.wasm", API_VERSION_0_0_5),
+                API_VERSION_0_0_6,
+            ),
+            API_VERSION_0_0_6,
+            None,
+            vec![report],
+        );
+        for (entity_type, id) in &[("Thing", "two"), ("User", "one"), ("Thing", "one")] {
+            let key = EntityKey::data(
+                deployment.hash.clone(),
+                entity_type.to_string(),
+                id.to_string(),
+            );
+            let entity = Entity::from(vec![("id", Value::from(*id))]);
+            module
+                .instance_ctx_mut()
+                .ctx
+                .state
+                .entity_cache
+                .set(key, entity);
+        }
+        module
+    };
+
+    module()
+        .handle_trigger(block_trigger("handleBlock"))
+        .unwrap();
+    assert_eq!(
+        vec![vec!["one".to_string(), "two".to_string()]],
+        *reported.lock().unwrap()
+    );
+
+    // Other handlers can not ask for changed entities
+    let module = module();
+    let res: Result<u32, _> = module.get_func("changedThings").typed().unwrap().call(());
+    assert!(res
+        .unwrap_err()
+        .to_string()
+        .contains("store.changedEntities can only be called from a block handler"));
+    assert!(module.instance_ctx_mut().deterministic_host_trap);
+}
+
+/// Uncle rewards for mainnet block 2,165,403, which includes two uncles
+/// from block 2,165,401. Etherscan lists a reward of 3.75 ETH for each of
+/// them and an uncle inclusion reward of 0.3125 ETH for the block. The
+/// hashes of the blocks do not enter into the rewards and are made up
+#[tokio::test]
+async fn block_handler_computes_uncle_rewards() {
+    let rewards = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reward = |name: &'static str| {
+        let rewards = rewards.clone();
+        HostFn {
+            name,
+            func: Arc::new(move |ctx, wasm_ptr| {
+                let mut wei = [0u8; 8];
+                wei.copy_from_slice(&ctx.heap.get(wasm_ptr, 8)?);
+                rewards
+                    .lock()
+                    .unwrap()
+                    .push((name, u64::from_le_bytes(wei)));
+                Ok(0)
+            }),
+        }
+    };
+    let module = test_valid_module_and_store_with_timeout(
+        "UncleRewards",
+        mock_data_source(
+            &wasm_file_path("uncle_rewards.wasm", API_VERSION_0_0_5),
+            API_VERSION_0_0_6,
+        ),
+        API_VERSION_0_0_6,
+        None,
+        vec![reward("test.uncleReward"), reward("test.inclusionReward")],
+    )
+    .0;
+
+    let uncles = vec![
+        EthereumUncle {
+            hash: Some(H256::from_low_u64_be(2)),
+            number: Some(2_165_401.into()),
+            author: H160::from_str("bcdfc35b86bedf72f0cda046a3c16829a2ef41d1").unwrap(),
+            ..Default::default()
+        },
+        EthereumUncle {
+            hash: Some(H256::from_low_u64_be(3)),
+            number: Some(2_165_401.into()),
+            author: H160::from_str("0d0c9855c722ff0c78f21e43aa275a5b8ea60dce").unwrap(),
+            ..Default::default()
+        },
+    ];
+    let block = LightEthereumBlock {
+        hash: Some(H256::from_low_u64_be(1)),
+        number: Some(2_165_403.into()),
+        author: H160::from_str("13a06d3dfe21e0db5c016c03ea7d2509f7f8d1e3").unwrap(),
+        timestamp: U256::from(1_472_533_979u64),
+        uncles: uncles.iter().map(|uncle| uncle.hash.unwrap()).collect(),
+        ..Default::default()
+    };
+    let trigger = MappingTrigger::Block {
+        block: Arc::new(block),
+        uncles: Some(Arc::new(uncles)),
+        handler: MappingBlockHandler {
+            handler: "handleUncleRewards".to_string(),
+            filter: None,
+        },
+    };
+    module.handle_trigger(trigger).unwrap();
+
+    assert_eq!(
+        vec![
+            ("test.uncleReward", 3_750_000_000_000_000_000),
+            ("test.uncleReward", 3_750_000_000_000_000_000),
+            ("test.inclusionReward", 312_500_000_000_000_000),
+        ],
+        *rewards.lock().unwrap()
+    );
+}
+
+#[test]
+fn decode_mapping_strings() {
+    use graph_runtime_wasm::to_from::decode_utf16;
+
+    let utf16 = |s: &str| s.encode_utf16().collect::<Vec<_>>();
+
+    // NUL characters are removed, or rejected in strict mode
+    let nul = utf16("a\u{0000}b\u{0000}");
+    assert_eq!("ab", decode_utf16(&nul, false).unwrap());
+    let err = decode_utf16(&nul, true).unwrap_err();
+    assert!(err.to_string().contains("contains a NUL character"));
+
+    // Unpaired surrogates are replaced, or rejected in strict mode
+    let surrogate = vec![0x61, 0xd800, 0x62];
+    assert_eq!("a\u{fffd}b", decode_utf16(&surrogate, false).unwrap());
+    let err = decode_utf16(&surrogate, true).unwrap_err();
+    assert!(err.to_string().contains("contains invalid UTF-16"));
+
+    // Valid strings pass unchanged either way
+    let valid = utf16("gm 🌞");
+    assert_eq!("gm 🌞", decode_utf16(&valid, false).unwrap());
+    assert_eq!("gm 🌞", decode_utf16(&valid, true).unwrap());
+}
+
+#[tokio::test]
+async fn data_sources_from_one_template_share_their_module() {
+    let prometheus_registry = Arc::new(graph::prometheus::Registry::new());
+    let metrics_registry = Arc::new(graph_core::MetricsRegistry::new(
+        Logger::root(slog::Discard, o!()),
+        prometheus_registry.clone(),
+    ));
+    let deployment_id = DeploymentHash::new("moduleCache").unwrap();
+    let host_metrics = Arc::new(HostMetrics::new(
+        metrics_registry.clone(),
+        deployment_id.as_str(),
+        StopwatchMetrics::new(
+            Logger::root(slog::Discard, o!()),
+            deployment_id.clone(),
+            metrics_registry,
+        ),
+    ));
+
+    let template = mock_template(
+        &wasm_file_path("abi_classes.wasm", API_VERSION_0_0_5),
+        API_VERSION_0_0_5,
+    );
+    let create = |address: &str| {
+        DataSource::try_from(DataSourceTemplateInfo::<Chain> {
+            template: template.clone(),
+            params: vec![address.to_string()],
+            context: None,
+            creation_block: 1,
+        })
+        .unwrap()
+    };
+    let data_sources = vec![
+        create("0x0000000000000000000000000000000000000001"),
+        create("0x0000000000000000000000000000000000000002"),
+    ];
+
+    let call_cache = STORE
+        .block_store()
+        .chain_store(test_store::NETWORK_NAME)
+        .unwrap();
+    let builder = graph_runtime_wasm::RuntimeHostBuilder::<Chain>::new(
+        Arc::new(RuntimeAdapter::without_eth_adapters(call_cache)),
+        Arc::new(graph_core::LinkResolver::from(IpfsClient::localhost())),
+        STORE.subgraph_store(),
+        &BTreeSet::new(),
+        *MAX_TRIGGER_BYTES,
+    );
+    for data_source in &data_sources {
+        builder
+            .spawn_mapping(
+                data_source.mapping.runtime.as_ref(),
+                Logger::root(slog::Discard, o!()),
+                deployment_id.clone(),
+                host_metrics.clone(),
+            )
+            .unwrap();
+    }
+
+    // Only the first data source spawns the module
+    let hits = prometheus_registry
+        .gather()
+        .into_iter()
+        .find(|family| family.get_name() == "deployment_module_cache_hits")
+        .expect("deployment_module_cache_hits is registered");
+    assert_eq!(1.0, hits.get_metric()[0].get_counter().get_value());
+}
@@ -277,6 +277,72 @@ async fn abi_ethabi_token_identity_v0_0_5() {
     test_abi_ethabi_token_identity(API_VERSION_0_0_5);
 }
 
+fn test_abi_ethereum_encode_decode(api_version: Version) {
+    let mut module = test_module(
+        "abiEthereumEncodeDecode",
+        mock_data_source(
+            &wasm_file_path("abi_token.wasm", api_version.clone()),
+            api_version.clone(),
+        ),
+        api_version,
+    );
+
+    let token = Token::Tuple(vec![
+        Token::Address(H160::from_low_u64_be(42)),
+        Token::Array(vec![
+            Token::Uint(U256::from(7)),
+            Token::Uint(U256::max_value()),
+        ]),
+        Token::Tuple(vec![Token::Bool(true), Token::String("漢字Go🇧🇷".into())]),
+        Token::FixedArray(vec![
+            Token::FixedBytes(vec![1; 32]),
+            Token::FixedBytes(vec![2; 32]),
+        ]),
+    ]);
+
+    let token_ptr = asc_new(&mut module, &token).unwrap();
+    let data_ptr = module
+        .instance_ctx_mut()
+        .ethereum_encode(token_ptr)
+        .unwrap();
+    assert!(!data_ptr.is_null());
+
+    let types: AscPtr<AscString> =
+        asc_new(&mut module, "(address,uint256[],(bool,string),bytes32[2])").unwrap();
+    let decoded_ptr = module
+        .instance_ctx_mut()
+        .ethereum_decode(types, data_ptr)
+        .unwrap();
+    let decoded: Token = asc_get(&module, decoded_ptr).unwrap();
+    assert_eq!(token, decoded);
+
+    // Malformed data and types give `null` instead of trapping
+    let short: AscPtr<Uint8Array> = asc_new(&mut module, &[0u8; 10][..]).unwrap();
+    assert!(module
+        .instance_ctx_mut()
+        .ethereum_decode(types, short)
+        .unwrap()
+        .is_null());
+    let bad_types: AscPtr<AscString> = asc_new(&mut module, "(address,uint256[]").unwrap();
+    assert!(module
+        .instance_ctx_mut()
+        .ethereum_decode(bad_types, data_ptr)
+        .unwrap()
+        .is_null());
+}
+
+/// Encode a nested value with `ethereum.encode` and decode it again with
+/// `ethereum.decode`
+#[tokio::test]
+async fn abi_ethereum_encode_decode_v0_0_4() {
+    test_abi_ethereum_encode_decode(API_VERSION_0_0_4);
+}
+
+#[tokio::test]
+async fn abi_ethereum_encode_decode_v0_0_5() {
+    test_abi_ethereum_encode_decode(API_VERSION_0_0_5);
+}
+
 fn test_abi_store_value(api_version: Version) {
     let mut module = test_module(
         "abiStoreValue",
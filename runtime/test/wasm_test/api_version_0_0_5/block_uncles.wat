@@ -0,0 +1,53 @@
+;; A block handler for apiVersion 0.0.6 that passes the hash of each of
+;; the block's uncles to the host. `allocate` is the same simple allocator
+;; as in `grow_memory.wat`
+(module
+  (import "env" "test.uncle" (func $uncle (param i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 8))
+
+  (func $allocate (export "allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $end
+      (i32.add (local.tee $ptr (global.get $next)) (local.get $size)))
+    (block $fits
+      (br_if $fits
+        (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+      (if (i32.lt_s
+            (memory.grow
+              (i32.sub
+                (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                (memory.size)))
+            (i32.const 0))
+        (then unreachable)))
+    (global.set $next (local.get $end))
+    (local.get $ptr))
+
+  (func (export "id_of_type") (param $index i32) (result i32)
+    (i32.const 0))
+
+  (func (export "_start"))
+
+  (func (export "handleBlockUncles") (param $block i32)
+    (local $uncles i32)
+    (local $data i32)
+    (local $i i32)
+    ;; `block.uncles` is the 15th field of `ethereum.Block`
+    (local.set $uncles (i32.load offset=56 (local.get $block)))
+    (if (i32.eqz (local.get $uncles))
+      (then return))
+    (local.set $data (i32.load offset=4 (local.get $uncles)))
+    (block $done
+      (loop $each
+        (br_if $done
+          (i32.ge_u (local.get $i) (i32.load offset=12 (local.get $uncles))))
+        ;; `hash` is the first field of `ethereum.Uncle`
+        (drop
+          (call $uncle
+            (i32.load
+              (i32.load
+                (i32.add (local.get $data) (i32.shl (local.get $i) (i32.const 2)))))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $each)))))
@@ -0,0 +1,42 @@
+;; Asks `store.changedEntities` for the ids of changed `Thing` entities and
+;; hands them to the test through `test.report`. The string `Thing` is laid
+;; out like an AssemblyScript string, with its size in bytes just before it.
+;; `allocate` is the same simple allocator as in `grow_memory.wat`, but
+;; starts after the string
+(module
+  (import "index" "store.changedEntities" (func $changed_entities (param i32) (result i32)))
+  (import "env" "test.report" (func $report (param i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 28) "\0a\00\00\00T\00h\00i\00n\00g\00")
+  (global $next (mut i32) (i32.const 48))
+
+  (func $allocate (export "allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $end
+      (i32.add (local.tee $ptr (global.get $next)) (local.get $size)))
+    (block $fits
+      (br_if $fits
+        (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+      (if (i32.lt_s
+            (memory.grow
+              (i32.sub
+                (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                (memory.size)))
+            (i32.const 0))
+        (then unreachable)))
+    (global.set $next (local.get $end))
+    (local.get $ptr))
+
+  (func (export "id_of_type") (param $index i32) (result i32)
+    (i32.const 0))
+
+  (func (export "_start"))
+
+  (func (export "handleBlock") (param $block i32)
+    (drop (call $report (call $changed_entities (i32.const 32)))))
+
+  ;; Not a handler, just an export the test calls directly
+  (func (export "changedThings") (result i32)
+    (call $changed_entities (i32.const 32))))
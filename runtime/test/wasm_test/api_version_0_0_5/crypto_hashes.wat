@@ -0,0 +1,38 @@
+;; Hashes for apiVersion 0.0.6 that pass their input straight to the
+;; `crypto.sha256` and `crypto.ripemd160` host exports. `allocate` is the
+;; same simple allocator as in `grow_memory.wat`
+(module
+  (import "index" "crypto.sha256" (func $sha256 (param i32) (result i32)))
+  (import "index" "crypto.ripemd160" (func $ripemd160 (param i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 8))
+
+  (func $allocate (export "allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $end
+      (i32.add (local.tee $ptr (global.get $next)) (local.get $size)))
+    (block $fits
+      (br_if $fits
+        (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+      (if (i32.lt_s
+            (memory.grow
+              (i32.sub
+                (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                (memory.size)))
+            (i32.const 0))
+        (then unreachable)))
+    (global.set $next (local.get $end))
+    (local.get $ptr))
+
+  (func (export "id_of_type") (param $index i32) (result i32)
+    (i32.const 0))
+
+  (func (export "_start"))
+
+  (func (export "sha256") (param $input i32) (result i32)
+    (call $sha256 (local.get $input)))
+
+  (func (export "ripemd160") (param $input i32) (result i32)
+    (call $ripemd160 (local.get $input))))
@@ -0,0 +1,39 @@
+;; A mapping that keeps growing an array. Like the allocator of
+;; AssemblyScript, `allocate` grows memory when it runs out of space and
+;; hits `unreachable` when memory can not be grown any further
+(module
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 8))
+
+  (func $allocate (export "allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $end
+      (i32.add (local.tee $ptr (global.get $next)) (local.get $size)))
+    (block $fits
+      (br_if $fits
+        (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+      (if (i32.lt_s
+            (memory.grow
+              (i32.sub
+                (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                (memory.size)))
+            (i32.const 0))
+        (then unreachable)))
+    (global.set $next (local.get $end))
+    (local.get $ptr))
+
+  (func (export "id_of_type") (param $index i32) (result i32)
+    (i32.const 0))
+
+  ;; Add `length` chunks of 64KiB to the array
+  (func (export "growArray") (param $length i32)
+    (local $i i32)
+    (loop $grow
+      (drop (call $allocate (i32.const 65536)))
+      (br_if $grow
+        (i32.lt_u
+          (local.tee $i (i32.add (local.get $i) (i32.const 1)))
+          (local.get $length)))))
+
+  (func (export "_start")))
@@ -0,0 +1,111 @@
+;; A block handler for apiVersion 0.0.6 that computes the rewards for the
+;; uncles of a block and for including them, in wei, and passes a pointer
+;; to each reward to the host. `allocate` is the same simple allocator as
+;; in `grow_memory.wat`
+(module
+  (import "env" "test.uncleReward" (func $uncle_reward (param i32) (result i32)))
+  (import "env" "test.inclusionReward" (func $inclusion_reward (param i32) (result i32)))
+
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 8))
+
+  (func $allocate (export "allocate") (param $size i32) (result i32)
+    (local $ptr i32)
+    (local $end i32)
+    (local.set $end
+      (i32.add (local.tee $ptr (global.get $next)) (local.get $size)))
+    (block $fits
+      (br_if $fits
+        (i32.le_u (local.get $end) (i32.shl (memory.size) (i32.const 16))))
+      (if (i32.lt_s
+            (memory.grow
+              (i32.sub
+                (i32.shr_u (i32.add (local.get $end) (i32.const 65535)) (i32.const 16))
+                (memory.size)))
+            (i32.const 0))
+        (then unreachable)))
+    (global.set $next (local.get $end))
+    (local.get $ptr))
+
+  (func (export "id_of_type") (param $index i32) (result i32)
+    (i32.const 0))
+
+  (func (export "_start"))
+
+  ;; The value of a non-negative `BigInt` that fits into 64 bits. Its
+  ;; bytes are little-endian
+  (func $big_int (param $big i32) (result i64)
+    (local $data i32)
+    (local $i i32)
+    (local $value i64)
+    (local.set $data (i32.load offset=4 (local.get $big)))
+    (local.set $i (i32.load offset=8 (local.get $big)))
+    (block $done
+      (loop $each
+        (br_if $done (i32.eqz (local.get $i)))
+        (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+        (local.set $value
+          (i64.or
+            (i64.shl (local.get $value) (i64.const 8))
+            (i64.load8_u (i32.add (local.get $data) (local.get $i)))))
+        (br $each)))
+    (local.get $value))
+
+  ;; An eighth of the block reward at block `number`: 5 ETH until
+  ;; Byzantium, 3 ETH until Constantinople, and 2 ETH after that
+  (func $eighth_of_block_reward (param $number i64) (result i64)
+    (if (result i64) (i64.ge_u (local.get $number) (i64.const 7280000))
+      (then (i64.const 250000000000000000))
+      (else
+        (if (result i64) (i64.ge_u (local.get $number) (i64.const 4370000))
+          (then (i64.const 375000000000000000))
+          (else (i64.const 625000000000000000))))))
+
+  ;; Pass a pointer to `value` to `report`
+  (func $report (param $value i64) (param $host i32)
+    (local $ptr i32)
+    (i64.store (local.tee $ptr (call $allocate (i32.const 8))) (local.get $value))
+    (if (local.get $host)
+      (then (drop (call $inclusion_reward (local.get $ptr))))
+      (else (drop (call $uncle_reward (local.get $ptr))))))
+
+  (func (export "handleUncleRewards") (param $block i32)
+    (local $number i64)
+    (local $eighth i64)
+    (local $uncles i32)
+    (local $data i32)
+    (local $i i32)
+    ;; `block.number` is the 8th field of `ethereum.Block`
+    (local.set $number (call $big_int (i32.load offset=28 (local.get $block))))
+    (local.set $eighth (call $eighth_of_block_reward (local.get $number)))
+    ;; `block.uncles` is the 15th field of `ethereum.Block`
+    (local.set $uncles (i32.load offset=56 (local.get $block)))
+    (if (i32.eqz (local.get $uncles))
+      (then return))
+    (local.set $data (i32.load offset=4 (local.get $uncles)))
+    (block $done
+      (loop $each
+        (br_if $done
+          (i32.ge_u (local.get $i) (i32.load offset=12 (local.get $uncles))))
+        ;; (uncle.number + 8 - block.number) * reward / 8, where `number`
+        ;; is the 3rd field of `ethereum.Uncle`
+        (call $report
+          (i64.mul
+            (i64.sub
+              (i64.add
+                (call $big_int
+                  (i32.load offset=8
+                    (i32.load
+                      (i32.add (local.get $data) (i32.shl (local.get $i) (i32.const 2))))))
+                (i64.const 8))
+              (local.get $number))
+            (local.get $eighth))
+          (i32.const 0))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $each)))
+    ;; The miner of the block gets 1/32 of the reward for each uncle
+    (call $report
+      (i64.mul
+        (i64.extend_i32_u (local.get $i))
+        (i64.div_u (local.get $eighth) (i64.const 4)))
+      (i32.const 1))))
@@ -1,4 +1,5 @@
 use std::cmp::PartialEq;
+use std::collections::BTreeSet;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
@@ -10,11 +11,13 @@ use graph::blockchain::RuntimeAdapter;
 use graph::blockchain::{Blockchain, DataSource, MappingTrigger as _};
 use graph::components::store::SubgraphStore;
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
+use graph::data::subgraph::features::SubgraphFeature;
 use graph::prelude::{
     RuntimeHost as RuntimeHostTrait, RuntimeHostBuilder as RuntimeHostBuilderTrait, *,
 };
+use graph::runtime::HostExportError;
 
-use crate::mapping::{MappingContext, MappingRequest};
+use crate::mapping::{MappingContext, MappingRequest, ModuleCache};
 use crate::{host_exports::HostExports, module::ExperimentalFeatures};
 
 lazy_static! {
@@ -30,6 +33,12 @@ pub struct RuntimeHostBuilder<C: Blockchain> {
     runtime_adapter: Arc<C::RuntimeAdapter>,
     link_resolver: Arc<dyn LinkResolver>,
     store: Arc<dyn SubgraphStore>,
+    eth_calls_disabled: bool,
+    strict_strings: bool,
+    max_trigger_bytes: usize,
+    block_info: bool,
+    /// Clones of the builder share the cache
+    module_cache: Arc<ModuleCache<C>>,
 }
 
 impl<C: Blockchain> Clone for RuntimeHostBuilder<C> {
@@ -38,20 +47,34 @@ impl<C: Blockchain> Clone for RuntimeHostBuilder<C> {
             runtime_adapter: self.runtime_adapter.cheap_clone(),
             link_resolver: self.link_resolver.cheap_clone(),
             store: self.store.cheap_clone(),
+            eth_calls_disabled: self.eth_calls_disabled,
+            strict_strings: self.strict_strings,
+            max_trigger_bytes: self.max_trigger_bytes,
+            block_info: self.block_info,
+            module_cache: self.module_cache.cheap_clone(),
         }
     }
 }
 
 impl<C: Blockchain> RuntimeHostBuilder<C> {
+    /// Create a builder for the hosts of one deployment. Passing a trigger
+    /// to a handler may allocate at most `max_trigger_bytes` on the heap
     pub fn new(
         runtime_adapter: Arc<C::RuntimeAdapter>,
         link_resolver: Arc<dyn LinkResolver>,
         store: Arc<dyn SubgraphStore>,
+        features: &BTreeSet<SubgraphFeature>,
+        max_trigger_bytes: usize,
     ) -> Self {
         RuntimeHostBuilder {
             runtime_adapter,
             link_resolver,
             store,
+            eth_calls_disabled: features.contains(&SubgraphFeature::NoEthCalls),
+            strict_strings: features.contains(&SubgraphFeature::StrictStrings),
+            max_trigger_bytes,
+            block_info: features.contains(&SubgraphFeature::BlockInfo),
+            module_cache: Arc::new(ModuleCache::default()),
         }
     }
 }
@@ -61,7 +84,8 @@ impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
     type Req = MappingRequest<C>;
 
     fn spawn_mapping(
-        raw_module: Vec<u8>,
+        &self,
+        raw_module: &[u8],
         logger: Logger,
         subgraph_id: DeploymentHash,
         metrics: Arc<HostMetrics>,
@@ -69,15 +93,18 @@ impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
         let experimental_features = ExperimentalFeatures {
             allow_non_deterministic_ipfs: *ALLOW_NON_DETERMINISTIC_IPFS,
         };
-        crate::mapping::spawn_module(
-            raw_module,
-            logger,
-            subgraph_id,
-            metrics,
-            tokio::runtime::Handle::current(),
-            *TIMEOUT,
-            experimental_features,
-        )
+        self.module_cache
+            .get_or_spawn(raw_module, metrics.as_ref(), || {
+                crate::mapping::spawn_module(
+                    raw_module,
+                    logger,
+                    subgraph_id,
+                    metrics.cheap_clone(),
+                    tokio::runtime::Handle::current(),
+                    *TIMEOUT,
+                    experimental_features,
+                )
+            })
     }
 
     fn build(
@@ -99,10 +126,30 @@ impl<C: Blockchain> RuntimeHostBuilderTrait<C> for RuntimeHostBuilder<C> {
             templates,
             mapping_request_sender,
             metrics,
+            self.eth_calls_disabled,
+            self.strict_strings,
+            self.max_trigger_bytes,
+            self.block_info,
         )
     }
 }
 
+/// Replace the `ethereum.call` host function with one that fails
+/// deterministically without contacting the Ethereum node. The function
+/// has to stay around since mappings that import it could not be
+/// instantiated otherwise
+pub fn disable_eth_calls(host_fns: &mut Vec<HostFn>) {
+    for host_fn in host_fns.iter_mut() {
+        if host_fn.name == "ethereum.call" {
+            host_fn.func = Arc::new(|_, _| {
+                Err(HostExportError::Deterministic(anyhow!(
+                    "eth_call disabled for this deployment"
+                )))
+            });
+        }
+    }
+}
+
 pub struct RuntimeHost<C: Blockchain> {
     host_fns: Arc<Vec<HostFn>>,
     data_source: C::DataSource,
@@ -125,6 +172,10 @@ where
         templates: Arc<Vec<C::DataSourceTemplate>>,
         mapping_request_sender: Sender<MappingRequest<C>>,
         metrics: Arc<HostMetrics>,
+        eth_calls_disabled: bool,
+        strict_strings: bool,
+        max_trigger_bytes: usize,
+        block_info: bool,
     ) -> Result<Self, Error> {
         // Create new instance of externally hosted functions invoker. The `Arc` is simply to avoid
         // implementing `Clone` for `HostExports`.
@@ -135,9 +186,16 @@ where
             templates,
             link_resolver,
             store,
+            strict_strings,
+            max_trigger_bytes,
+            block_info,
         ));
 
-        let host_fns = Arc::new(runtime_adapter.host_fns(&data_source)?);
+        let mut host_fns = runtime_adapter.host_fns(&data_source)?;
+        if eth_calls_disabled {
+            disable_eth_calls(&mut host_fns);
+        }
+        let host_fns = Arc::new(host_fns);
 
         Ok(RuntimeHost {
             host_fns,
@@ -235,6 +293,14 @@ impl<C: Blockchain> RuntimeHostTrait<C> for RuntimeHost<C> {
     fn creation_block_number(&self) -> Option<BlockNumber> {
         self.data_source.creation_block()
     }
+
+    fn once_handler_block(&self) -> Option<BlockNumber> {
+        self.data_source.once_handler_block()
+    }
+
+    fn data_source_key(&self) -> DataSourceKey {
+        DataSourceKey::of::<C>(&self.data_source)
+    }
 }
 
 impl<C: Blockchain> PartialEq for RuntimeHost<C> {
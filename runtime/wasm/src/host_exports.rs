@@ -1,17 +1,21 @@
 use crate::{error::DeterminismLevel, module::IntoTrap};
 use ethabi::param_type::Reader;
-use ethabi::{decode, encode, Token};
+use ethabi::{decode, encode, ParamType, Token};
 use graph::blockchain::DataSource;
 use graph::blockchain::{Blockchain, DataSourceTemplate as _};
 use graph::components::store::EntityKey;
 use graph::components::store::EntityType;
 use graph::components::subgraph::{ProofOfIndexingEvent, SharedProofOfIndexing};
 use graph::data::store;
+use graph::data::subgraph::schema::BLOCK_INFO_OBJECT;
+use graph::data::subgraph::API_VERSION_0_0_6;
 use graph::prelude::serde_json;
 use graph::prelude::{slog::b, slog::record_static, *};
 pub use graph::runtime::{DeterministicHostError, HostExportError};
 use never::Never;
+use ripemd160::Ripemd160;
 use semver::Version;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::str::FromStr;
@@ -19,7 +23,7 @@ use std::time::{Duration, Instant};
 use web3::types::H160;
 
 use graph::ensure;
-use graph_graphql::prelude::validate_entity;
+use graph_graphql::prelude::{validate_entity, ByteLengthError};
 use wasmtime::Trap;
 
 use crate::module::{WasmInstance, WasmInstanceContext};
@@ -41,6 +45,18 @@ impl IntoTrap for HostExportError {
     }
 }
 
+lazy_static! {
+    /// The most entity ids that `store.changedEntities` will return
+    static ref MAX_CHANGED_ENTITIES: usize = std::env::var("GRAPH_MAX_CHANGED_ENTITIES")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_MAX_CHANGED_ENTITIES must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(10_000);
+}
+
 pub struct HostExports<C: Blockchain> {
     pub(crate) subgraph_id: DeploymentHash,
     pub api_version: Version,
@@ -56,6 +72,15 @@ pub struct HostExports<C: Blockchain> {
     templates: Arc<Vec<C::DataSourceTemplate>>,
     pub(crate) link_resolver: Arc<dyn LinkResolver>,
     store: Arc<dyn SubgraphStore>,
+    /// Whether strings from the mapping that need sanitizing fail the
+    /// mapping instead. Only applies from `apiVersion` 0.0.6 on
+    pub(crate) strict_strings: bool,
+    /// The most bytes that passing one trigger to a handler may allocate
+    /// on the heap
+    pub(crate) max_trigger_bytes: usize,
+    /// Whether the deployment has the `blockInfo` feature, whose
+    /// `BlockInfo` entities only the node may write
+    block_info: bool,
 }
 
 impl<C: Blockchain> HostExports<C> {
@@ -66,6 +91,9 @@ impl<C: Blockchain> HostExports<C> {
         templates: Arc<Vec<C::DataSourceTemplate>>,
         link_resolver: Arc<dyn LinkResolver>,
         store: Arc<dyn SubgraphStore>,
+        strict_strings: bool,
+        max_trigger_bytes: usize,
+        block_info: bool,
     ) -> Self {
         let causality_region = format!("ethereum/{}", data_source_network);
 
@@ -80,6 +108,9 @@ impl<C: Blockchain> HostExports<C> {
             templates,
             link_resolver,
             store,
+            strict_strings,
+            max_trigger_bytes,
+            block_info,
         }
     }
 
@@ -121,7 +152,15 @@ impl<C: Blockchain> HostExports<C> {
         entity_id: String,
         mut data: HashMap<String, Value>,
         stopwatch: &StopwatchMetrics,
-    ) -> Result<(), anyhow::Error> {
+    ) -> Result<(), HostExportError> {
+        check_writable(self.block_info, &entity_type)?;
+
+        if self.api_version >= API_VERSION_0_0_6 {
+            for value in data.values_mut() {
+                normalize_big_decimals(value);
+            }
+        }
+
         let poi_section = stopwatch.start_section("host_export_store_set__proof_of_indexing");
         if let Some(proof_of_indexing) = proof_of_indexing {
             let mut proof_of_indexing = proof_of_indexing.deref().borrow_mut();
@@ -147,7 +186,8 @@ impl<C: Blockchain> HostExports<C> {
                     entity_type,
                     v,
                     entity_id,
-                ));
+                )
+                .into());
             }
             _ => (),
         }
@@ -174,7 +214,17 @@ impl<C: Blockchain> HostExports<C> {
                 .get(&key)
                 .map_err(|e| HostExportError::Unknown(e.into()))?
                 .expect("we just stored this entity");
-            validate_entity(&schema.document, &key, &entity)?;
+            // Whether an entity is valid only depends on the schema and on
+            // the data the mappings produced. Older mappings keep failing
+            // the way they always have, except for `@length` violations
+            // which are new and deterministic for every mapping
+            validate_entity(&schema.document, &key, &entity).map_err(|e| {
+                if self.api_version >= API_VERSION_0_0_6 || e.is::<ByteLengthError>() {
+                    HostExportError::Deterministic(e)
+                } else {
+                    HostExportError::Unknown(e)
+                }
+            })?;
         }
         Ok(())
     }
@@ -187,6 +237,8 @@ impl<C: Blockchain> HostExports<C> {
         entity_type: String,
         entity_id: String,
     ) -> Result<(), HostExportError> {
+        check_writable(self.block_info, &entity_type)?;
+
         if let Some(proof_of_indexing) = proof_of_indexing {
             let mut proof_of_indexing = proof_of_indexing.deref().borrow_mut();
             proof_of_indexing.write(
@@ -223,6 +275,36 @@ impl<C: Blockchain> HostExports<C> {
         Ok(state.entity_cache.get(&store_key)?)
     }
 
+    /// The ids of the entities of type `entity_type` that were changed
+    /// earlier in the current block. Only block handlers may ask for them
+    /// since they run after all other handlers for the block
+    pub(crate) fn store_changed_entities(
+        &self,
+        state: &BlockState<C>,
+        in_block_handler: bool,
+        entity_type: String,
+    ) -> Result<Vec<String>, DeterministicHostError> {
+        if !in_block_handler {
+            return Err(DeterministicHostError(anyhow!(
+                "store.changedEntities can only be called from a block handler"
+            )));
+        }
+
+        let ids = state
+            .entity_cache
+            .changed_ids(&EntityType::new(entity_type.clone()));
+        if ids.len() > *MAX_CHANGED_ENTITIES {
+            return Err(DeterministicHostError(anyhow!(
+                "{} entities of type `{}` changed in this block, which is more than \
+                 the {} that store.changedEntities can return",
+                ids.len(),
+                entity_type,
+                *MAX_CHANGED_ENTITIES
+            )));
+        }
+        Ok(ids)
+    }
+
     /// Prints the module of `n` in hex.
     /// Integers are encoded using the least amount of digits (no leading zero digits).
     /// Their encoding may be of uneven length. The number zero encodes as "0x0".
@@ -418,6 +500,7 @@ impl<C: Blockchain> HostExports<C> {
         x: BigInt,
         y: BigInt,
     ) -> Result<BigInt, DeterministicHostError> {
+        check_bitwise_operands(&self.api_version, "bigInt.bitOr", &[&x, &y])?;
         Ok(x | y)
     }
 
@@ -426,6 +509,7 @@ impl<C: Blockchain> HostExports<C> {
         x: BigInt,
         y: BigInt,
     ) -> Result<BigInt, DeterministicHostError> {
+        check_bitwise_operands(&self.api_version, "bigInt.bitAnd", &[&x, &y])?;
         Ok(x & y)
     }
 
@@ -442,14 +526,10 @@ impl<C: Blockchain> HostExports<C> {
         x: BigInt,
         bits: u8,
     ) -> Result<BigInt, DeterministicHostError> {
+        check_bitwise_operands(&self.api_version, "bigInt.rightShift", &[&x])?;
         Ok(x >> bits)
     }
 
-    /// Useful for IPFS hashes stored as bytes
-    pub(crate) fn bytes_to_base58(&self, bytes: Vec<u8>) -> Result<String, DeterministicHostError> {
-        Ok(::bs58::encode(&bytes).into_string())
-    }
-
     pub(crate) fn big_decimal_plus(
         &self,
         x: BigDecimal,
@@ -489,6 +569,29 @@ impl<C: Blockchain> HostExports<C> {
         Ok(x / y)
     }
 
+    pub(crate) fn big_decimal_pow(
+        &self,
+        x: BigDecimal,
+        exp: i32,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        let result = x.pow(exp).ok_or_else(|| {
+            DeterministicHostError(anyhow!(
+                "attempted to raise BigDecimal `0` to the negative power `{}`",
+                exp
+            ))
+        })?;
+        check_big_decimal_exponent(result)
+    }
+
+    pub(crate) fn big_decimal_truncate(
+        &self,
+        x: BigDecimal,
+        decimals: i32,
+        mode: RoundingMode,
+    ) -> Result<BigDecimal, DeterministicHostError> {
+        check_big_decimal_exponent(x.round(decimals.into(), mode))
+    }
+
     pub(crate) fn big_decimal_equals(
         &self,
         x: BigDecimal,
@@ -521,12 +624,13 @@ impl<C: Blockchain> HostExports<C> {
         params: Vec<String>,
         context: Option<DataSourceContext>,
         creation_block: BlockNumber,
+        host_metrics: &HostMetrics,
     ) -> Result<(), HostExportError> {
         info!(
             logger,
             "Create data source";
             "name" => &name,
-            "params" => format!("{}", params.join(","))
+            "params" => params.join(",")
         );
 
         // Resolve the name into the right template
@@ -551,13 +655,34 @@ impl<C: Blockchain> HostExports<C> {
             .map_err(DeterministicHostError)?
             .clone();
 
-        // Remember that we need to create this data source
-        state.push_created_data_source(DataSourceTemplateInfo {
+        let info = DataSourceTemplateInfo {
             template,
             params,
             context,
             creation_block,
-        });
+        };
+
+        // Creating a data source that already exists, whether it was
+        // created in an earlier block or earlier in this block, does
+        // nothing. Data sources with a different context are different
+        // data sources. Whether the template can be instantiated with
+        // `params` only depends on the mapping
+        let key = C::DataSource::try_from(info.clone())
+            .map(|data_source| DataSourceKey::of::<C>(&data_source))
+            .map_err(HostExportError::Deterministic)?;
+        if state.has_data_source(&key) {
+            warn!(
+                logger,
+                "Data source already exists, not creating it again";
+                "name" => &name,
+                "params" => info.params.join(",")
+            );
+            host_metrics.add_duplicate_data_source();
+            return Ok(());
+        }
+
+        // Remember that we need to create this data source
+        state.push_created_data_source(key, info);
 
         Ok(())
     }
@@ -596,6 +721,12 @@ impl<C: Blockchain> HostExports<C> {
         self.data_source_network.clone()
     }
 
+    /// The id of the deployment the mapping runs in. That is the same for
+    /// all nodes that index the deployment and can therefore be stored
+    pub(crate) fn data_source_deployment(&self) -> String {
+        self.subgraph_id.to_string()
+    }
+
     pub(crate) fn data_source_context(&self) -> Entity {
         self.data_source_context
             .as_ref()
@@ -604,6 +735,16 @@ impl<C: Blockchain> HostExports<C> {
     }
 }
 
+/// Normalize the `BigDecimal` values in `value`, including those nested in
+/// lists
+fn normalize_big_decimals(value: &mut Value) {
+    match value {
+        Value::BigDecimal(d) => *d = d.normalized(),
+        Value::List(values) => values.iter_mut().for_each(normalize_big_decimals),
+        _ => (),
+    }
+}
+
 pub(crate) fn json_from_bytes(
     bytes: &Vec<u8>,
 ) -> Result<serde_json::Value, DeterministicHostError> {
@@ -637,13 +778,70 @@ pub(crate) fn bytes_to_string(logger: &Logger, bytes: Vec<u8>) -> String {
     s.trim_end_matches('\u{0000}').to_string()
 }
 
+/// Bitwise operations on negative numbers are ambiguous: we treat them as
+/// two's complement numbers with infinitely many bits, where the EVM uses
+/// 256 bits, and right shifts could round towards zero or towards negative
+/// infinity. Mappings with `apiVersion` 0.0.6 or later fail
+/// deterministically instead of getting an answer they might not expect.
+/// Left shifts are always fine since they multiply by a power of two
+fn check_bitwise_operands(
+    api_version: &Version,
+    op: &str,
+    operands: &[&BigInt],
+) -> Result<(), DeterministicHostError> {
+    if api_version < &API_VERSION_0_0_6 {
+        return Ok(());
+    }
+    match operands.iter().find(|x| ***x < BigInt::from(0)) {
+        Some(x) => Err(DeterministicHostError(anyhow!(
+            "{} is not defined for negative numbers, but was called with {}",
+            op,
+            x
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Fail for `BigDecimal` results that mappings could not pass back to us
+/// since their exponent is out of range
+fn check_big_decimal_exponent(x: BigDecimal) -> Result<BigDecimal, DeterministicHostError> {
+    let exp = -x.as_bigint_and_exponent().1;
+    if exp < BigDecimal::MIN_EXP.into() || exp > BigDecimal::MAX_EXP.into() {
+        return Err(DeterministicHostError(anyhow!(
+            "big decimal exponent `{}` is outside the `{}` to `{}` range",
+            exp,
+            BigDecimal::MIN_EXP,
+            BigDecimal::MAX_EXP
+        )));
+    }
+    Ok(x)
+}
+
+/// Useful for IPFS hashes stored as bytes
+pub(crate) fn bytes_to_base58(bytes: &[u8]) -> String {
+    ::bs58::encode(bytes).into_string()
+}
+
+pub(crate) fn crypto_sha256(input: &[u8]) -> [u8; 32] {
+    Sha256::digest(input).into()
+}
+
+pub(crate) fn crypto_ripemd160(input: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(input).into()
+}
+
 pub(crate) fn ethereum_encode(token: Token) -> Result<Vec<u8>, anyhow::Error> {
     Ok(encode(&[token]))
 }
 
 pub(crate) fn ethereum_decode(types: String, data: Vec<u8>) -> Result<Token, anyhow::Error> {
+    // `ethabi` panics on some malformed type strings and data, for example
+    // offsets that point past the end of the data. Mappings pass whatever
+    // they got from the chain, so we check both before decoding
+    check_abi_types(&types)?;
     let param_types =
         Reader::read(&types).or_else(|e| Err(anyhow::anyhow!("Failed to read types: {}", e)))?;
+    check_abi_tuple(std::iter::once(&param_types), &data, 0).context("Failed to decode")?;
 
     decode(&[param_types], &data)
         // The `.pop().unwrap()` here is ok because we're always only passing one
@@ -653,6 +851,183 @@ pub(crate) fn ethereum_decode(types: String, data: Vec<u8>) -> Result<Token, any
         .context("Failed to decode")
 }
 
+/// Check that `types` is well-formed enough for `Reader::read` to parse it
+/// without panicking: brackets hold a possibly empty size and follow a
+/// type, parentheses are balanced, and lists have no empty entries
+fn check_abi_types(types: &str) -> Result<(), anyhow::Error> {
+    let malformed = || anyhow::anyhow!("Failed to read types: `{}` is malformed", types);
+
+    let mut depth = 0usize;
+    let mut prev: Option<char> = None;
+    let mut chars = types.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            'a'..='z' | '0'..='9' => {}
+            '(' => {
+                if matches!(prev, Some(p) if p != '(' && p != ',') {
+                    return Err(malformed());
+                }
+                depth += 1;
+            }
+            ')' => {
+                if depth == 0 || matches!(prev, None | Some('(') | Some(',')) {
+                    return Err(malformed());
+                }
+                depth -= 1;
+            }
+            ',' => {
+                if depth == 0 || matches!(prev, None | Some('(') | Some(',')) {
+                    return Err(malformed());
+                }
+            }
+            '[' => {
+                if matches!(prev, None | Some('(') | Some(',')) {
+                    return Err(malformed());
+                }
+                while matches!(chars.peek(), Some('0'..='9')) {
+                    chars.next();
+                }
+                if chars.next() != Some(']') {
+                    return Err(malformed());
+                }
+                prev = Some(']');
+                continue;
+            }
+            _ => return Err(malformed()),
+        }
+        prev = Some(c);
+    }
+    if depth != 0 || matches!(prev, None | Some(',')) {
+        return Err(malformed());
+    }
+    Ok(())
+}
+
+const ABI_WORD: usize = 32;
+
+/// The number of bytes a value of type `kind` takes up in the head of an
+/// ABI encoding. Dynamic types only store an offset there. Fails for
+/// static types that are too big to fit into any data
+fn abi_head_size(kind: &ParamType) -> Result<usize, anyhow::Error> {
+    use ParamType::*;
+
+    if abi_is_dynamic(kind) {
+        return Ok(ABI_WORD);
+    }
+    let size = match kind {
+        FixedArray(inner, len) => abi_head_size(inner)?.checked_mul(*len),
+        Tuple(components) => components.iter().try_fold(0usize, |size, kind| {
+            abi_head_size(kind).ok().and_then(|s| size.checked_add(s))
+        }),
+        _ => Some(ABI_WORD),
+    };
+    size.ok_or_else(|| anyhow::anyhow!("the type is too big"))
+}
+
+fn abi_is_dynamic(kind: &ParamType) -> bool {
+    use ParamType::*;
+
+    match kind {
+        Bytes | String | Array(_) => true,
+        FixedArray(inner, _) => abi_is_dynamic(inner),
+        Tuple(components) => components.iter().any(|kind| abi_is_dynamic(kind)),
+        Address | Int(_) | Uint(_) | Bool | FixedBytes(_) => false,
+    }
+}
+
+/// Read the word at `pos` in `data` as an offset or a length. It must fit
+/// into the data since anything bigger can not be valid
+fn abi_read_usize(data: &[u8], pos: usize) -> Result<usize, anyhow::Error> {
+    let end = pos
+        .checked_add(ABI_WORD)
+        .filter(|end| *end <= data.len())
+        .ok_or_else(|| anyhow::anyhow!("the data is too short"))?;
+    let word = &data[pos..end];
+    let value = web3::types::U256::from_big_endian(word);
+    if value > web3::types::U256::from(data.len()) {
+        return Err(anyhow::anyhow!(
+            "the offset or length {} at byte {} is bigger than the data",
+            value,
+            pos
+        ));
+    }
+    Ok(value.as_usize())
+}
+
+/// Check that the value of type `kind` whose encoding starts at `pos`
+/// lies within `data`, following offsets and lengths the same way that
+/// decoding does
+fn check_abi_data(kind: &ParamType, data: &[u8], pos: usize) -> Result<(), anyhow::Error> {
+    use ParamType::*;
+
+    let too_short = || anyhow::anyhow!("the data is too short");
+    match kind {
+        Bytes | String => {
+            let len = abi_read_usize(data, pos)?;
+            (pos + ABI_WORD)
+                .checked_add(len)
+                .filter(|end| *end <= data.len())
+                .map(|_| ())
+                .ok_or_else(too_short)
+        }
+        Array(inner) => {
+            let len = abi_read_usize(data, pos)?;
+            let start = pos + ABI_WORD;
+            // Every element takes up at least one word
+            if len > (data.len() - start) / ABI_WORD {
+                return Err(too_short());
+            }
+            check_abi_tuple(std::iter::repeat(&**inner).take(len), data, start)
+        }
+        FixedArray(inner, len) if abi_is_dynamic(inner) => {
+            check_abi_tuple(std::iter::repeat(&**inner).take(*len), data, pos)
+        }
+        Tuple(components) if abi_is_dynamic(kind) => check_abi_tuple(components.iter(), data, pos),
+        // Static values are stored inline
+        _ => pos
+            .checked_add(abi_head_size(kind)?)
+            .filter(|end| *end <= data.len())
+            .map(|_| ())
+            .ok_or_else(too_short),
+    }
+}
+
+/// Check a sequence of values whose encoding starts at `start`. Offsets of
+/// dynamic values are relative to `start`
+fn check_abi_tuple<'a>(
+    kinds: impl Iterator<Item = &'a ParamType>,
+    data: &[u8],
+    start: usize,
+) -> Result<(), anyhow::Error> {
+    let mut head = start;
+    for kind in kinds {
+        if head > data.len() {
+            return Err(anyhow::anyhow!("the data is too short"));
+        }
+        if abi_is_dynamic(kind) {
+            let offset = abi_read_usize(data, head)?;
+            check_abi_data(kind, data, start + offset)?;
+        } else {
+            check_abi_data(kind, data, head)?;
+        }
+        head += abi_head_size(kind)?;
+    }
+    Ok(())
+}
+
+/// Fail if mappings may not change entities of type `entity_type`. The
+/// node writes the `BlockInfo` entities of deployments with the
+/// `blockInfo` feature itself
+fn check_writable(block_info: bool, entity_type: &str) -> Result<(), HostExportError> {
+    if block_info && entity_type == BLOCK_INFO_OBJECT.as_str() {
+        return Err(HostExportError::Deterministic(anyhow!(
+            "entities of type `{}` are written by Graph Node and can not be changed by mappings",
+            entity_type
+        )));
+    }
+    Ok(())
+}
+
 #[test]
 fn test_string_to_h160_with_0x() {
     assert_eq!(
@@ -686,3 +1061,252 @@ fn bytes_to_string_is_lossy() {
         )
     )
 }
+
+#[test]
+fn ethereum_encode_decode_round_trip() {
+    use web3::types::U256;
+
+    fn round_trip(types: &str, token: Token) {
+        let data = ethereum_encode(token.clone()).unwrap();
+        assert_eq!(
+            token,
+            ethereum_decode(types.to_string(), data).unwrap(),
+            "{}",
+            types
+        );
+    }
+
+    let address = Token::Address(H160::from_low_u64_be(0x1234));
+    let uint = Token::Uint(U256::from(1_000_000_000_000u64));
+    let string = Token::String("漢字Go🇧🇷".to_string());
+
+    round_trip("address", address.clone());
+    round_trip("bool", Token::Bool(true));
+    round_trip("bool", Token::Bool(false));
+    round_trip("bytes", Token::Bytes(vec![]));
+    round_trip("bytes", Token::Bytes((0..100).collect()));
+    round_trip("bytes1", Token::FixedBytes(vec![7]));
+    round_trip("bytes32", Token::FixedBytes(vec![0xab; 32]));
+    round_trip("int8", Token::Int(U256::max_value()));
+    round_trip("int256", Token::Int(U256::max_value() - U256::from(41)));
+    round_trip("int256", Token::Int(U256::from(42)));
+    round_trip("uint8", Token::Uint(U256::from(255)));
+    round_trip("uint256", Token::Uint(U256::max_value()));
+    round_trip("string", Token::String(String::new()));
+    round_trip("string", string.clone());
+    round_trip("uint256[]", Token::Array(vec![]));
+    round_trip("uint256[]", Token::Array(vec![uint.clone(), uint.clone()]));
+    round_trip(
+        "address[2]",
+        Token::FixedArray(vec![address.clone(), address.clone()]),
+    );
+    round_trip(
+        "string[]",
+        Token::Array(vec![string.clone(), Token::String("b".to_string())]),
+    );
+    round_trip(
+        "uint256[][]",
+        Token::Array(vec![Token::Array(vec![uint.clone()]), Token::Array(vec![])]),
+    );
+    round_trip(
+        "(address,uint256[])",
+        Token::Tuple(vec![address.clone(), Token::Array(vec![uint.clone()])]),
+    );
+
+    // Nested tuples with static and dynamic members
+    let inner = Token::Tuple(vec![Token::Bool(true), string.clone()]);
+    let outer = Token::Tuple(vec![
+        inner.clone(),
+        Token::FixedArray(vec![
+            Token::FixedBytes(vec![1; 32]),
+            Token::FixedBytes(vec![2; 32]),
+        ]),
+    ]);
+    round_trip("((bool,string),bytes32[2])", outer.clone());
+    round_trip(
+        "((bool,string),bytes32[2])[]",
+        Token::Array(vec![outer.clone(), outer]),
+    );
+    round_trip(
+        "(uint256,(address,(bool,string)))",
+        Token::Tuple(vec![uint, Token::Tuple(vec![address, inner])]),
+    );
+}
+
+#[test]
+fn ethereum_decode_malformed() {
+    let decode = |types: &str, data: Vec<u8>| ethereum_decode(types.to_string(), data);
+
+    assert!(decode("uint256[", vec![0; 32]).is_err());
+    assert!(decode("(address", vec![0; 32]).is_err());
+    assert!(decode("notatype", vec![0; 32]).is_err());
+    assert!(decode("uint256", vec![0; 31]).is_err());
+    assert!(decode("(address,uint256)", vec![0; 32]).is_err());
+
+    // Type strings that `Reader` can not make sense of
+    for types in &[
+        "]",
+        "[]",
+        "[1]",
+        "uint256]",
+        "uint256[[]",
+        "()",
+        "(,)",
+        "(address,)",
+        ")(",
+    ] {
+        assert!(decode(types, vec![0; 64]).is_err(), "{}", types);
+    }
+
+    // An offset that points far past the end of the data
+    let mut data = vec![0xff; 32];
+    data.extend_from_slice(&[0; 32]);
+    assert!(decode("bytes", data.clone()).is_err());
+    assert!(decode("uint256[]", data).is_err());
+
+    let word = |n: usize| {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&(n as u64).to_be_bytes());
+        word.to_vec()
+    };
+
+    // A length that is longer than the data
+    let data = [word(32), word(1000), vec![0; 32]].concat();
+    assert!(decode("bytes", data.clone()).is_err());
+    assert!(decode("string", data.clone()).is_err());
+    assert!(decode("uint256[]", data).is_err());
+
+    // An array of strings whose second element points past the end
+    let data = [word(32), word(2), word(64), word(1000), word(0)].concat();
+    assert!(decode("string[]", data).is_err());
+    let data = [word(32), word(2), word(64), word(96), word(0), word(0)].concat();
+    assert_eq!(
+        Token::Array(vec![
+            Token::String(String::new()),
+            Token::String(String::new())
+        ]),
+        decode("string[]", data).unwrap()
+    );
+
+    // A fixed array that is bigger than the data
+    assert!(decode("uint256[1000000000000000000]", vec![0; 64]).is_err());
+    assert!(decode("(bytes,uint256[18446744073709551615])", word(64)).is_err());
+}
+
+#[test]
+fn crypto_known_answers() {
+    let two_blocks = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    assert_eq!(
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        hex::encode(crypto_sha256(b""))
+    );
+    assert_eq!(
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        hex::encode(crypto_sha256(b"abc"))
+    );
+    assert_eq!(
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+        hex::encode(crypto_sha256(two_blocks))
+    );
+
+    assert_eq!(
+        "9c1185a5c5e9fc54612808977ee8f548b2258d31",
+        hex::encode(crypto_ripemd160(b""))
+    );
+    assert_eq!(
+        "8eb208f7e05d987a9b044a8e98c6b087f15a0bfc",
+        hex::encode(crypto_ripemd160(b"abc"))
+    );
+    assert_eq!(
+        "12a053384a9c0c88e405a06c27dcf49ada62eb2b",
+        hex::encode(crypto_ripemd160(two_blocks))
+    );
+}
+
+#[test]
+fn base58_known_answers() {
+    for (hex, base58) in &[
+        ("", ""),
+        ("61", "2g"),
+        ("626262", "a3gV"),
+        ("516b6fcd0f", "ABnLTmg"),
+        (
+            "73696d706c792061206c6f6e6720737472696e67",
+            "2cFupjhnEsSn59qHXstmK2ffpLv2",
+        ),
+        // Leading zero bytes become leading ones
+        ("0000287fb4cd", "11233QC4"),
+        (
+            "00eb15231dfceb60925886b67d065299925915aeb172c06647",
+            "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+        ),
+    ] {
+        assert_eq!(*base58, bytes_to_base58(&hex::decode(hex).unwrap()));
+    }
+}
+
+#[test]
+fn big_int_bitwise_vectors() {
+    let n = |s: &str| BigInt::from_str(s).unwrap();
+    let max = n("115792089237316195423570985008687907853269984665640564039457584007913129639935");
+    let mask160 = n("1461501637330902918203684832716283019655932542975");
+    let high = n("57896044618658097711785492504343953926634992332820282019728792003956564819968");
+
+    assert_eq!(mask160, max.clone() & mask160.clone());
+    assert_eq!(max, max.clone() | mask160.clone());
+    assert_eq!(
+        n("57896044618658097711785492505805455563965895251023966852445075023612497362943"),
+        high.clone() | mask160.clone()
+    );
+    assert_eq!(high, BigInt::from(1) << 255);
+    assert_eq!(BigInt::from(1), max.clone() >> 255);
+    assert_eq!(BigInt::from(0), mask160.clone() >> 160);
+    assert_eq!(
+        n("115792089237316195423570985008687907853269984665561335876943319670319585689600"),
+        mask160.clone() << 96
+    );
+    // Left shifts are not truncated to 256 bits
+    assert_eq!(
+        n("6703903964971298549787012499102923063739682910296196688861780721860882015036715592356318490985739928352511585139316390434544121123953556181212867938222080"),
+        max.clone() << 255
+    );
+
+    // Unpack a slot with a 160 bit price, a 24 bit tick, and a counter
+    // above them, the way Uniswap v3 packs `slot0`
+    let slot = n("1300080240648362699647298941637681238813292246372049421639176832035644");
+    assert_eq!(
+        n("5834007571456220530996546251580"),
+        slot.clone() & mask160.clone()
+    );
+    assert_eq!(
+        BigInt::from(16578744),
+        (slot.clone() >> 160) & BigInt::from(0xffffff)
+    );
+    assert_eq!(BigInt::from(12345), slot >> 216);
+
+    // Negative operands fail from apiVersion 0.0.6 on
+    let v5 = Version::new(0, 0, 5);
+    let minus_one = BigInt::from(-1);
+    assert!(check_bitwise_operands(&v5, "bigInt.bitAnd", &[&minus_one, &max]).is_ok());
+    assert!(check_bitwise_operands(&API_VERSION_0_0_6, "bigInt.bitAnd", &[&max, &max]).is_ok());
+    let err = check_bitwise_operands(&API_VERSION_0_0_6, "bigInt.bitAnd", &[&max, &minus_one])
+        .unwrap_err();
+    assert_eq!(
+        "bigInt.bitAnd is not defined for negative numbers, but was called with -1",
+        err.to_string()
+    );
+    assert!(
+        check_bitwise_operands(&API_VERSION_0_0_6, "bigInt.rightShift", &[&minus_one]).is_err()
+    );
+}
+
+#[test]
+fn block_info_is_not_writable() {
+    assert!(check_writable(false, "BlockInfo").is_ok());
+    assert!(check_writable(true, "Thing").is_ok());
+    assert!(matches!(
+        check_writable(true, "BlockInfo"),
+        Err(HostExportError::Deterministic(_))
+    ));
+}
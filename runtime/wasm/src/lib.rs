@@ -16,9 +16,9 @@ pub mod host_exports;
 
 pub mod error;
 
-pub use host::RuntimeHostBuilder;
+pub use host::{disable_eth_calls, RuntimeHostBuilder};
 pub use host_exports::HostExports;
-pub use mapping::{MappingContext, ValidModule};
+pub use mapping::{MappingContext, ModuleCache, ValidModule};
 pub use module::{ExperimentalFeatures, WasmInstance};
 
 #[cfg(debug_assertions)]
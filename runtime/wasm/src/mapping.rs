@@ -1,11 +1,12 @@
-use crate::module::{ExperimentalFeatures, WasmInstance};
+use crate::module::{profiling, ExperimentalFeatures, WasmInstance};
 use futures::sync::mpsc;
 use futures03::channel::oneshot::Sender;
 use graph::blockchain::{Blockchain, HostFn};
 use graph::components::subgraph::{MappingError, SharedProofOfIndexing};
 use graph::prelude::*;
-use std::collections::BTreeMap;
-use std::sync::Arc;
+use graph::util::profiler;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 const ONE_MIB: usize = 1 << 20; // 1_048_576
@@ -24,7 +25,7 @@ lazy_static! {
 
 /// Spawn a wasm module in its own thread.
 pub fn spawn_module<C: Blockchain>(
-    raw_module: Vec<u8>,
+    raw_module: &[u8],
     logger: Logger,
     subgraph_id: DeploymentHash,
     host_metrics: Arc<HostMetrics>,
@@ -32,7 +33,10 @@ pub fn spawn_module<C: Blockchain>(
     timeout: Option<Duration>,
     experimental_features: ExperimentalFeatures,
 ) -> Result<mpsc::Sender<MappingRequest<C>>, anyhow::Error> {
-    let valid_module = Arc::new(ValidModule::new(&raw_module)?);
+    let valid_module = Arc::new(ValidModule::new(raw_module)?);
+    let raw_module = raw_module.to_vec();
+    // Compiled when the deployment is profiled for the first time
+    let mut profiled_module: Option<Arc<ValidModule>> = None;
 
     // Create channel for event handling requests
     let (mapping_request_sender, mapping_request_receiver) = mpsc::channel(100);
@@ -62,8 +66,26 @@ pub fn spawn_module<C: Blockchain>(
 
                 // Start the WASM module runtime.
                 let section = host_metrics.stopwatch.start_section("module_init");
+                let valid_module = match profiler::profile_for(&ctx.host_exports.subgraph_id) {
+                    Some(_) => profiled_module
+                        .get_or_insert_with(|| {
+                            ValidModule::for_profiling(&raw_module)
+                                .map(Arc::new)
+                                .unwrap_or_else(|e| {
+                                    warn!(
+                                        logger,
+                                        "Failed to instrument mapping for profiling, \
+                                         only host calls will be sampled";
+                                        "error" => e.to_string()
+                                    );
+                                    valid_module.cheap_clone()
+                                })
+                        })
+                        .cheap_clone(),
+                    None => valid_module.cheap_clone(),
+                };
                 let module = WasmInstance::from_valid_module_with_ctx(
-                    valid_module.cheap_clone(),
+                    valid_module,
                     ctx,
                     host_metrics.cheap_clone(),
                     timeout,
@@ -95,6 +117,45 @@ pub fn spawn_module<C: Blockchain>(
     Ok(mapping_request_sender)
 }
 
+/// The mappings of a subgraph that are running, by the hash of their
+/// module. Each distinct module is only compiled once, and all data sources
+/// that use it share the thread that runs it, whether they are listed in
+/// the manifest or are created from a template later on. Handlers still run
+/// in a fresh instance with its own linear memory so that what one handler
+/// leaves in memory can not influence the next one
+pub struct ModuleCache<C: Blockchain> {
+    modules: Mutex<HashMap<[u8; 32], mpsc::Sender<MappingRequest<C>>>>,
+}
+
+impl<C: Blockchain> Default for ModuleCache<C> {
+    fn default() -> Self {
+        ModuleCache {
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<C: Blockchain> ModuleCache<C> {
+    /// Return the sender for the mapping `raw_module`, and call `spawn` to
+    /// compile and spawn it if it is not in the cache yet
+    pub fn get_or_spawn(
+        &self,
+        raw_module: &[u8],
+        host_metrics: &HostMetrics,
+        spawn: impl FnOnce() -> Result<mpsc::Sender<MappingRequest<C>>, anyhow::Error>,
+    ) -> Result<mpsc::Sender<MappingRequest<C>>, anyhow::Error> {
+        let hash = tiny_keccak::keccak256(raw_module);
+        let mut modules = self.modules.lock().unwrap();
+        if let Some(sender) = modules.get(&hash) {
+            host_metrics.module_cache_hit();
+            return Ok(sender.clone());
+        }
+        let sender = spawn()?;
+        modules.insert(hash, sender.clone());
+        Ok(sender)
+    }
+}
+
 pub struct MappingRequest<C: Blockchain> {
     pub(crate) ctx: MappingContext<C>,
     pub(crate) trigger: C::MappingTrigger,
@@ -116,7 +177,8 @@ impl<C: Blockchain> MappingContext<C> {
             logger: self.logger.cheap_clone(),
             host_exports: self.host_exports.cheap_clone(),
             block_ptr: self.block_ptr.cheap_clone(),
-            state: BlockState::new(self.state.entity_cache.store.clone(), Default::default()),
+            state: BlockState::new(self.state.entity_cache.store.clone(), Default::default())
+                .with_existing_data_sources(self.state.known_data_sources()),
             proof_of_indexing: self.proof_of_indexing.cheap_clone(),
             host_fns: self.host_fns.cheap_clone(),
         }
@@ -141,6 +203,16 @@ pub struct ValidModule {
 impl ValidModule {
     /// Pre-process and validate the module.
     pub fn new(raw_module: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::compile(raw_module)
+    }
+
+    /// A copy of `raw_module` that lets the profiler sample time spent in
+    /// loops that do not call the host, see `module::profiling`
+    pub fn for_profiling(raw_module: &[u8]) -> Result<Self, anyhow::Error> {
+        Self::compile(&profiling::instrument(raw_module)?)
+    }
+
+    fn compile(raw_module: &[u8]) -> Result<Self, anyhow::Error> {
         // We currently use Cranelift as a compilation engine. Cranelift is an optimizing compiler,
         // but that should not cause determinism issues since it adheres to the Wasm spec. Still we
         // turn off optional optimizations to be conservative.
@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use graph::runtime::DeterministicHostError;
+
+/// The size of a page of WASM linear memory
+const PAGE_SIZE: usize = 64 * 1024;
+
+/// Limits how large the linear memory of a mapping can get. The wasmtime
+/// store consults it whenever memory grows, whether the mapping grows it
+/// itself or the host allocates on its behalf, so `memory.grow` fails once
+/// the limit is reached. Clones share the same limit
+///
+/// The allocator of AssemblyScript first tries to double memory when it
+/// needs more, and only grows it by what the allocation needs if that
+/// fails. Since it can not reuse all the memory it has, the heap that a
+/// mapping can actually use can be well under the limit
+#[derive(Clone, Debug)]
+pub struct HeapLimit {
+    max_bytes: Arc<AtomicUsize>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl HeapLimit {
+    pub fn new(max_bytes: usize) -> Self {
+        HeapLimit {
+            max_bytes: Arc::new(AtomicUsize::new(max_bytes)),
+            exceeded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.max_bytes.store(max_bytes, Ordering::SeqCst)
+    }
+
+    /// Whether the last attempt to grow memory would have gone beyond the
+    /// limit. Growing memory by less afterwards clears this
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::SeqCst)
+    }
+
+    pub fn error(&self) -> DeterministicHostError {
+        DeterministicHostError(anyhow::anyhow!(
+            "The mapping needs more than {} bytes of memory; \
+             the limit is set with GRAPH_RUNTIME_MAX_HEAP_BYTES",
+            self.max_bytes()
+        ))
+    }
+}
+
+impl wasmtime::ResourceLimiter for HeapLimit {
+    fn memory_growing(&self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        let allowed = desired as usize * PAGE_SIZE <= self.max_bytes();
+        // The allocator retries with less when doubling memory fails
+        self.exceeded.store(!allowed, Ordering::SeqCst);
+        allowed
+    }
+
+    fn table_growing(&self, _current: u32, _desired: u32, _maximum: Option<u32>) -> bool {
+        true
+    }
+}
@@ -17,20 +17,29 @@ use anyhow::Error;
 use graph::data::store;
 use graph::prelude::*;
 use graph::runtime::{AscHeap, IndexForAscTypeId};
+use graph::util::crash;
+use graph::util::profiler::{self, Profile};
+use graph::util::scan_counter::ScanCounter;
 use graph::{components::subgraph::MappingError, runtime::AscPtr};
 use graph::{
-    data::subgraph::schema::SubgraphError,
-    runtime::{asc_get, asc_new, try_asc_get, DeterministicHostError},
+    data::subgraph::{schema::SubgraphError, API_VERSION_0_0_6},
+    runtime::{asc_get, asc_new, try_asc_get, DeterministicHostError, MAX_HEAP_BYTES},
 };
 
 use crate::asc_abi::class::*;
 use crate::host_exports::HostExports;
 use crate::mapping::ValidModule;
+use crate::to_from::decode_utf16;
 
+mod heap_limit;
 mod into_wasm_ret;
+pub(crate) mod profiling;
+pub mod registry;
 pub mod stopwatch;
 
+pub use heap_limit::HeapLimit;
 pub use into_wasm_ret::IntoWasmRet;
+use stopwatch::PausedStopwatch;
 pub use stopwatch::TimeoutStopwatch;
 
 pub const TRAP_TIMEOUT: &str = "trap: interrupt";
@@ -74,6 +83,10 @@ impl<C: Blockchain> AscHeap for WasmInstance<C> {
         self.instance_ctx().api_version()
     }
 
+    fn strict_strings(&self) -> bool {
+        self.instance_ctx().strict_strings()
+    }
+
     fn asc_type_id(
         &mut self,
         type_id_index: IndexForAscTypeId,
@@ -107,15 +120,35 @@ impl<C: Blockchain> WasmInstance<C> {
         Ok(self.take_ctx().ctx.state)
     }
 
-    pub(crate) fn handle_trigger(
+    pub fn handle_trigger(
         mut self,
         trigger: C::MappingTrigger,
     ) -> Result<BlockState<C>, MappingError> {
         let handler_name = trigger.handler_name().to_owned();
-        let asc_trigger = trigger.to_asc_ptr(&mut self)?;
+        self.instance_ctx_mut().in_block_handler = trigger.is_block_handler();
+        let budget = self.instance_ctx().ctx.host_exports.max_trigger_bytes;
+        let asc_trigger = self.marshal_trigger(trigger, budget)?;
         self.invoke_handler(&handler_name, asc_trigger)
     }
 
+    /// Write `trigger` to the heap so it can be passed to its handler. If
+    /// that allocates more than `budget` bytes, fail with a deterministic
+    /// error rather than risk running out of WASM memory
+    pub fn marshal_trigger(
+        &mut self,
+        trigger: C::MappingTrigger,
+        budget: usize,
+    ) -> Result<AscPtr<()>, DeterministicHostError> {
+        self.instance_ctx_mut().trigger_budget = Some(TriggerBudget {
+            handler: trigger.handler_name().to_owned(),
+            budget,
+            remaining: budget,
+        });
+        let asc_trigger = trigger.to_asc_ptr(self);
+        self.instance_ctx_mut().trigger_budget = None;
+        asc_trigger
+    }
+
     pub fn take_ctx(&mut self) -> WasmInstanceContext<C> {
         self.instance_ctx.borrow_mut().take().unwrap()
     }
@@ -143,23 +176,58 @@ impl<C: Blockchain> WasmInstance<C> {
             .get_func(handler)
             .with_context(|| format!("function {} not found", handler))?;
 
+        let profile = profiler::profile_for(&self.instance_ctx().ctx.host_exports.subgraph_id);
+        if let Some(profile) = &profile {
+            profile.enter();
+        }
+        self.instance_ctx_mut().profile = profile.map(|profile| (profile, handler.to_string()));
+
+        let _crash_context = {
+            let ctx = &self.instance_ctx().ctx;
+            crash::enter(vec![
+                ("deployment", ctx.host_exports.subgraph_id.to_string()),
+                ("block", ctx.block_ptr.to_string()),
+                ("handler", handler.to_string()),
+            ])
+        };
+
         // Caution: Make sure all exit paths from this function call `exit_handler`.
         self.instance_ctx_mut().ctx.state.enter_handler();
 
+        let result = func.typed()?.call(arg.wasm_ptr());
+
+        // Attribute the time since the last sample to the handler itself
+        if let Some((profile, handler)) = &self.instance_ctx().profile {
+            profile.record(&[handler.clone()]);
+        }
+
         // This `match` will return early if there was a non-deterministic trap.
-        let deterministic_error: Option<Error> = match func.typed()?.call(arg.wasm_ptr()) {
+        let deterministic_error: Option<Error> = match result {
             Ok(()) => None,
             Err(trap) if self.instance_ctx().possible_reorg => {
                 self.instance_ctx_mut().ctx.state.exit_handler();
                 return Err(MappingError::PossibleReorg(trap.into()));
             }
+            // A handler that runs for too long, e.g., because it loops
+            // forever, would do so again on every attempt. Skipping it
+            // would make what gets indexed depend on how fast the node
+            // is, so the subgraph fails instead
             Err(trap) if trap.to_string().contains(TRAP_TIMEOUT) => {
                 self.instance_ctx_mut().ctx.state.exit_handler();
-                return Err(MappingError::Unknown(Error::from(trap).context(format!(
+                let e = DeterministicHostError(Error::from(trap).context(format!(
                     "Handler '{}' hit the timeout of '{}' seconds",
                     handler,
                     self.instance_ctx().timeout.unwrap().as_secs()
-                ))));
+                )));
+                // Converting the error directly keeps it recognizable as
+                // deterministic
+                return Err(MappingError::Unknown(Error::from(e)));
+            }
+            // The allocator of the mapping traps when it can not grow
+            // memory any further
+            Err(trap) if self.instance_ctx().heap_limit_exceeded() => {
+                let heap_limit = self.instance_ctx().heap_limit.clone().unwrap();
+                Some(Error::from(trap).context(heap_limit.error().to_string()))
             }
             Err(trap) => {
                 use wasmtime::TrapCode::*;
@@ -217,6 +285,13 @@ pub struct ExperimentalFeatures {
     pub allow_non_deterministic_ipfs: bool,
 }
 
+/// How much more of the heap passing a trigger to `handler` may use
+struct TriggerBudget {
+    handler: String,
+    budget: usize,
+    remaining: usize,
+}
+
 pub struct WasmInstanceContext<C: Blockchain> {
     // In the future there may be multiple memories, but currently there is only one memory per
     // module. And at least AS calls it "memory". There is no uninitialized memory in Wasm, memory
@@ -235,7 +310,8 @@ pub struct WasmInstanceContext<C: Blockchain> {
     pub host_metrics: Arc<HostMetrics>,
     pub(crate) timeout: Option<Duration>,
 
-    // Used by ipfs.map.
+    // Measures how long the handler has been running, without the time
+    // spent in host exports.
     pub(crate) timeout_stopwatch: Arc<std::sync::Mutex<TimeoutStopwatch>>,
 
     // First free byte in the current arena. Set on the first call to `raw_new`.
@@ -250,6 +326,23 @@ pub struct WasmInstanceContext<C: Blockchain> {
     // A host export trap ocurred for a deterministic reason.
     pub deterministic_host_trap: bool,
 
+    // The handler that is running is a block handler.
+    pub(crate) in_block_handler: bool,
+
+    // Limits allocations while a trigger is written to the heap.
+    trigger_budget: Option<TriggerBudget>,
+
+    // Limits the linear memory of the mapping. Only set for mappings with
+    // apiVersion 0.0.6 and later.
+    pub heap_limit: Option<HeapLimit>,
+
+    // Counts the data that host functions fetch for the deployment.
+    scan: Arc<ScanCounter>,
+
+    // The profile of the deployment and the name of the running handler
+    // while the deployment is being profiled.
+    profile: Option<(Arc<Profile>, String)>,
+
     pub(crate) experimental_features: ExperimentalFeatures,
 }
 
@@ -262,10 +355,21 @@ impl<C: Blockchain> WasmInstance<C> {
         timeout: Option<Duration>,
         experimental_features: ExperimentalFeatures,
     ) -> Result<WasmInstance<C>, anyhow::Error> {
-        let mut linker = wasmtime::Linker::new(&wasmtime::Store::new(valid_module.module.engine()));
         let host_fns = ctx.host_fns.cheap_clone();
         let api_version = ctx.host_exports.api_version.clone();
 
+        // Growing memory beyond the limit fails, and the allocator of the
+        // mapping traps when that happens
+        let heap_limit =
+            Some(HeapLimit::new(*MAX_HEAP_BYTES)).filter(|_| api_version >= API_VERSION_0_0_6);
+        let store = match &heap_limit {
+            Some(heap_limit) => {
+                wasmtime::Store::new_with_limits(valid_module.module.engine(), heap_limit.clone())
+            }
+            None => wasmtime::Store::new(valid_module.module.engine()),
+        };
+        let mut linker = wasmtime::Linker::new(&store);
+
         // Used by exports to access the instance context. There are two ways this can be set:
         // - After instantiation, if no host export is called in the start function.
         // - During the start function, if it calls a host export.
@@ -290,8 +394,10 @@ impl<C: Blockchain> WasmInstance<C> {
                     match time_left {
                         None => break interrupt_handle.interrupt(), // Timed out.
 
-                        Some(time) if time < minimum_wait => break interrupt_handle.interrupt(),
-                        Some(time) => tokio::time::sleep(time).await,
+                        // The stopwatch is paused while the handler waits
+                        // for a host export, so we can only interrupt once
+                        // the time is really up
+                        Some(time) => tokio::time::sleep(time.max(minimum_wait)).await,
                     }
                 }
             });
@@ -303,6 +409,11 @@ impl<C: Blockchain> WasmInstance<C> {
             };
 
             ($wasm_name:expr, $rust_name:ident, $section:expr, $($param:ident),*) => {
+                debug_assert!(
+                    registry::is_registered($wasm_name),
+                    "host export `{}` is missing from the registry",
+                    $wasm_name
+                );
                 let modules = valid_module
                     .import_name_to_modules
                     .get($wasm_name)
@@ -315,6 +426,7 @@ impl<C: Blockchain> WasmInstance<C> {
                     let valid_module = valid_module.cheap_clone();
                     let host_metrics = host_metrics.cheap_clone();
                     let timeout_stopwatch = timeout_stopwatch.cheap_clone();
+                    let heap_limit = heap_limit.clone();
                     let ctx = ctx.cheap_clone();
                     linker.func(
                         module,
@@ -332,12 +444,15 @@ impl<C: Blockchain> WasmInstance<C> {
                                     host_metrics.cheap_clone(),
                                     timeout,
                                     timeout_stopwatch.cheap_clone(),
+                                    heap_limit.clone(),
                                     experimental_features.clone()
                                 ).unwrap())
                             }
 
                             let instance = instance.as_mut().unwrap();
+                            instance.sample_profile();
                             let _section = instance.host_metrics.stopwatch.start_section($section);
+                            let _timeout_guard = instance.pause_timeout();
 
                             let result = instance.$rust_name(
                                 $($param.into()),*
@@ -393,6 +508,9 @@ impl<C: Blockchain> WasmInstance<C> {
                         }
                     };
 
+                    instance.sample_profile();
+                    let _timeout_guard = instance.pause_timeout();
+
                     let name_for_metrics = host_fn.name.replace('.', "_");
                     let stopwatch = &instance.host_metrics.stopwatch;
                     let _section =
@@ -401,6 +519,7 @@ impl<C: Blockchain> WasmInstance<C> {
                     let ctx = HostFnCtx {
                         logger: instance.ctx.logger.cheap_clone(),
                         block_ptr: instance.ctx.block_ptr.cheap_clone(),
+                        scan: instance.scan.cheap_clone(),
                         heap: instance,
                     };
                     let ret = (host_fn.func)(ctx, call_ptr).map_err(|e| match e {
@@ -423,6 +542,21 @@ impl<C: Blockchain> WasmInstance<C> {
             }
         }
 
+        // Modules that were instrumented for profiling call into the
+        // profiler at the start of every function and loop iteration
+        if valid_module
+            .import_name_to_modules
+            .contains_key(profiling::SAMPLE)
+        {
+            let func_shared_ctx = Rc::downgrade(&shared_ctx);
+            linker.func(profiling::SAMPLE_MODULE, profiling::SAMPLE, move || {
+                let instance = func_shared_ctx.upgrade().unwrap();
+                if let Some(instance) = &*instance.borrow() {
+                    instance.sample_profile();
+                }
+            })?;
+        }
+
         link!("ethereum.encode", ethereum_encode, params_ptr);
         link!("ethereum.decode", ethereum_decode, params_ptr, data_ptr);
 
@@ -455,6 +589,14 @@ impl<C: Blockchain> WasmInstance<C> {
         );
 
         link!("store.remove", store_remove, entity_ptr, id_ptr);
+        if api_version >= API_VERSION_0_0_6 {
+            link!(
+                "store.changedEntities",
+                store_changed_entities,
+                "host_export_store_changed_entities",
+                entity_ptr
+            );
+        }
 
         link!("typeConversion.bytesToString", bytes_to_string, ptr);
         link!("typeConversion.bytesToHex", bytes_to_hex, ptr);
@@ -471,6 +613,10 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("json.toBigInt", json_to_big_int, ptr);
 
         link!("crypto.keccak256", crypto_keccak_256, ptr);
+        if api_version >= API_VERSION_0_0_6 {
+            link!("crypto.sha256", crypto_sha256, ptr);
+            link!("crypto.ripemd160", crypto_ripemd160, ptr);
+        }
 
         link!("bigInt.plus", big_int_plus, x_ptr, y_ptr);
         link!("bigInt.minus", big_int_minus, x_ptr, y_ptr);
@@ -492,6 +638,16 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("bigDecimal.times", big_decimal_times, x_ptr, y_ptr);
         link!("bigDecimal.dividedBy", big_decimal_divided_by, x, y);
         link!("bigDecimal.equals", big_decimal_equals, x_ptr, y_ptr);
+        if api_version >= API_VERSION_0_0_6 {
+            link!("bigDecimal.pow", big_decimal_pow, x_ptr, exp);
+            link!(
+                "bigDecimal.truncate",
+                big_decimal_truncate,
+                x_ptr,
+                decimals,
+                mode
+            );
+        }
 
         link!("dataSource.create", data_source_create, name, params);
         link!(
@@ -504,6 +660,7 @@ impl<C: Blockchain> WasmInstance<C> {
         link!("dataSource.address", data_source_address,);
         link!("dataSource.network", data_source_network,);
         link!("dataSource.context", data_source_context,);
+        link!("dataSource.deployment", data_source_deployment,);
 
         link!("ens.nameByHash", ens_name_by_hash, ptr);
 
@@ -526,6 +683,7 @@ impl<C: Blockchain> WasmInstance<C> {
                 host_metrics,
                 timeout,
                 timeout_stopwatch,
+                heap_limit,
                 experimental_features,
             )?);
         }
@@ -556,7 +714,25 @@ impl<C: Blockchain> AscHeap for WasmInstanceContext<C> {
 
         static MIN_ARENA_SIZE: i32 = 10_000;
 
-        let size = i32::try_from(bytes.len()).unwrap();
+        if let Some(trigger_budget) = &mut self.trigger_budget {
+            if bytes.len() > trigger_budget.remaining {
+                return Err(DeterministicHostError(anyhow!(
+                    "The trigger for handler `{}` needs more than {} bytes of memory; \
+                     the limit is what GRAPH_RUNTIME_MAX_TRIGGER_BYTES was set to \
+                     when the deployment was created",
+                    trigger_budget.handler,
+                    trigger_budget.budget
+                )));
+            }
+            trigger_budget.remaining -= bytes.len();
+        }
+
+        let size = i32::try_from(bytes.len()).map_err(|_| {
+            DeterministicHostError(anyhow!(
+                "Can not allocate {} bytes of memory at once",
+                bytes.len()
+            ))
+        })?;
         if size > self.arena_free_size {
             // Allocate a new arena. Any free space left in the previous arena is left unused. This
             // causes at most half of memory to be wasted, which is acceptable.
@@ -564,8 +740,15 @@ impl<C: Blockchain> AscHeap for WasmInstanceContext<C> {
 
             // Unwrap: This may panic if more memory needs to be requested from the OS and that
             // fails. This error is not deterministic since it depends on the operating conditions
-            // of the node.
-            self.arena_start_ptr = self.memory_allocate.call(arena_size).unwrap();
+            // of the node. The allocator traps deterministically if memory would grow beyond the
+            // heap limit.
+            self.arena_start_ptr = match self.memory_allocate.call(arena_size) {
+                Ok(ptr) => ptr,
+                Err(_) if self.heap_limit_exceeded() => {
+                    return Err(self.heap_limit.as_ref().unwrap().error())
+                }
+                Err(trap) => panic!("failed to allocate {} bytes: {}", arena_size, trap),
+            };
             self.arena_free_size = arena_size;
 
             match &self.ctx.host_exports.api_version {
@@ -614,6 +797,10 @@ impl<C: Blockchain> AscHeap for WasmInstanceContext<C> {
         self.ctx.host_exports.api_version.clone()
     }
 
+    fn strict_strings(&self) -> bool {
+        self.ctx.host_exports.strict_strings
+    }
+
     fn asc_type_id(
         &mut self,
         type_id_index: IndexForAscTypeId,
@@ -637,9 +824,12 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         host_metrics: Arc<HostMetrics>,
         timeout: Option<Duration>,
         timeout_stopwatch: Arc<std::sync::Mutex<TimeoutStopwatch>>,
+        heap_limit: Option<HeapLimit>,
         experimental_features: ExperimentalFeatures,
     ) -> Result<Self, anyhow::Error> {
         // Provide access to the WASM runtime linear memory
+        let scan = ScanCounter::for_deployment(&ctx.host_exports.subgraph_id);
+
         let memory = instance
             .get_memory("memory")
             .context("Failed to find memory export in the WASM module")?;
@@ -679,6 +869,11 @@ impl<C: Blockchain> WasmInstanceContext<C> {
             arena_start_ptr: 0,
             possible_reorg: false,
             deterministic_host_trap: false,
+            in_block_handler: false,
+            trigger_budget: None,
+            heap_limit,
+            scan,
+            profile: None,
             experimental_features,
         })
     }
@@ -690,8 +885,11 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         host_metrics: Arc<HostMetrics>,
         timeout: Option<Duration>,
         timeout_stopwatch: Arc<std::sync::Mutex<TimeoutStopwatch>>,
+        heap_limit: Option<HeapLimit>,
         experimental_features: ExperimentalFeatures,
     ) -> Result<Self, anyhow::Error> {
+        let scan = ScanCounter::for_deployment(&ctx.host_exports.subgraph_id);
+
         let memory = caller
             .get_export("memory")
             .and_then(|e| e.into_memory())
@@ -735,11 +933,59 @@ impl<C: Blockchain> WasmInstanceContext<C> {
             arena_start_ptr: 0,
             possible_reorg: false,
             deterministic_host_trap: false,
+            in_block_handler: false,
+            trigger_budget: None,
+            heap_limit,
+            scan,
+            profile: None,
             experimental_features,
         })
     }
 }
 
+impl<C: Blockchain> WasmInstanceContext<C> {
+    /// The number of bytes of linear memory the mapping currently uses
+    pub fn heap_size(&self) -> usize {
+        self.memory.data_size()
+    }
+
+    /// Whether the mapping tried to grow its memory beyond the heap limit
+    pub fn heap_limit_exceeded(&self) -> bool {
+        self.heap_limit
+            .as_ref()
+            .map(|heap_limit| heap_limit.exceeded())
+            .unwrap_or(false)
+    }
+
+    /// If the deployment is being profiled and a sample is due, record
+    /// the stack of the mapping. Frame names come from the name section
+    /// of the module when it has one
+    fn sample_profile(&self) {
+        let (profile, handler) = match &self.profile {
+            Some((profile, handler)) if profile.due() => (profile, handler),
+            _ => return,
+        };
+
+        // Creating a trap captures the stack of the running WASM code
+        let trap = Trap::new("profiler sample");
+        let mut stack = vec![handler.clone()];
+        stack.extend(trap.trace().iter().rev().map(|frame| {
+            frame
+                .func_name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("wasm-function[{}]", frame.func_index()))
+        }));
+        profile.record(&stack);
+    }
+
+    /// Pause the handler timeout until the returned guard is dropped, so
+    /// that only the time the handler spends running its own code, and not
+    /// the time it waits for host exports like `ethereum.call`, counts
+    fn pause_timeout(&self) -> PausedStopwatch {
+        PausedStopwatch::new(self.timeout_stopwatch.cheap_clone())
+    }
+}
+
 // Implementation of externals.
 impl<C: Blockchain> WasmInstanceContext<C> {
     /// function abort(message?: string | null, fileName?: string | null, lineNumber?: u32, columnNumber?: u32): void
@@ -783,9 +1029,12 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         let stopwatch = &self.host_metrics.stopwatch;
         stopwatch.start_section("host_export_store_set__wasm_instance_context_store_set");
 
-        let entity = asc_get(self, entity_ptr)?;
-        let id = asc_get(self, id_ptr)?;
-        let data = try_asc_get(self, data_ptr)?;
+        let entity: String = asc_get(self, entity_ptr)?;
+        let id: String = asc_get(self, id_ptr)
+            .map_err(|e| DeterministicHostError(e.0.context(format!("id of `{}`", entity))))?;
+        let data = try_asc_get(self, data_ptr).map_err(|e| {
+            DeterministicHostError(e.0.context(format!("entity `{}` with id `{}`", entity, id)))
+        })?;
 
         self.ctx.host_exports.store_set(
             &self.ctx.logger,
@@ -847,6 +1096,20 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         Ok(ret)
     }
 
+    /// function store.changedEntities(entity: string): Array<string>
+    pub fn store_changed_entities(
+        &mut self,
+        entity_ptr: AscPtr<AscString>,
+    ) -> Result<AscPtr<Array<AscPtr<AscString>>>, HostExportError> {
+        let entity = asc_get(self, entity_ptr)?;
+        let ids = self.ctx.host_exports.store_changed_entities(
+            &self.ctx.state,
+            self.in_block_handler,
+            entity,
+        )?;
+        Ok(asc_new(self, &*ids)?)
+    }
+
     /// function typeConversion.bytesToString(bytes: Bytes): string
     pub fn bytes_to_string(
         &mut self,
@@ -999,11 +1262,6 @@ impl<C: Blockchain> WasmInstanceContext<C> {
 
         let flags = asc_get(self, flags)?;
 
-        // Pause the timeout while running ipfs_map, ensure it will be restarted by using a guard.
-        self.timeout_stopwatch.lock().unwrap().stop();
-        let defer_stopwatch = self.timeout_stopwatch.clone();
-        let _stopwatch_guard = defer::defer(|| defer_stopwatch.lock().unwrap().start());
-
         let start_time = Instant::now();
         let output_states = HostExports::ipfs_map(
             &self.ctx.host_exports.link_resolver.clone(),
@@ -1081,6 +1339,26 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         asc_new(self, input.as_ref())
     }
 
+    /// function crypto.sha256(input: Bytes): Bytes
+    pub fn crypto_sha256(
+        &mut self,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let input: Vec<u8> = asc_get(self, input_ptr)?;
+        let hash = host_exports::crypto_sha256(&input);
+        asc_new(self, hash.as_ref())
+    }
+
+    /// function crypto.ripemd160(input: Bytes): Bytes
+    pub fn crypto_ripemd160(
+        &mut self,
+        input_ptr: AscPtr<Uint8Array>,
+    ) -> Result<AscPtr<Uint8Array>, DeterministicHostError> {
+        let input: Vec<u8> = asc_get(self, input_ptr)?;
+        let hash = host_exports::crypto_ripemd160(&input);
+        asc_new(self, hash.as_ref())
+    }
+
     /// function bigInt.plus(x: BigInt, y: BigInt): BigInt
     pub fn big_int_plus(
         &mut self,
@@ -1233,10 +1511,8 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         &mut self,
         bytes_ptr: AscPtr<Uint8Array>,
     ) -> Result<AscPtr<AscString>, DeterministicHostError> {
-        let result = self
-            .ctx
-            .host_exports
-            .bytes_to_base58(asc_get(self, bytes_ptr)?)?;
+        let bytes: Vec<u8> = asc_get(self, bytes_ptr)?;
+        let result = host_exports::bytes_to_base58(&bytes);
         asc_new(self, &result)
     }
 
@@ -1316,6 +1592,35 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         asc_new(self, &result)
     }
 
+    /// function bigDecimal.pow(x: BigDecimal, exp: i32): BigDecimal
+    pub fn big_decimal_pow(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        exp: u32,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let result = self
+            .ctx
+            .host_exports
+            .big_decimal_pow(try_asc_get(self, x_ptr)?, exp as i32)?;
+        asc_new(self, &result)
+    }
+
+    /// function bigDecimal.truncate(x: BigDecimal, decimals: i32, mode: RoundingMode): BigDecimal
+    pub fn big_decimal_truncate(
+        &mut self,
+        x_ptr: AscPtr<AscBigDecimal>,
+        decimals: u32,
+        mode: u32,
+    ) -> Result<AscPtr<AscBigDecimal>, DeterministicHostError> {
+        let mode = RoundingMode::try_from(mode).map_err(|e| DeterministicHostError(e.into()))?;
+        let result = self.ctx.host_exports.big_decimal_truncate(
+            try_asc_get(self, x_ptr)?,
+            decimals as i32,
+            mode,
+        )?;
+        asc_new(self, &result)
+    }
+
     /// function bigDecimal.equals(x: BigDecimal, y: BigDecimal): bool
     pub fn big_decimal_equals(
         &mut self,
@@ -1342,6 +1647,7 @@ impl<C: Blockchain> WasmInstanceContext<C> {
             params,
             None,
             self.ctx.block_ptr.number,
+            &self.host_metrics,
         )
     }
 
@@ -1362,6 +1668,7 @@ impl<C: Blockchain> WasmInstanceContext<C> {
             params,
             Some(context.into()),
             self.ctx.block_ptr.number,
+            &self.host_metrics,
         )
     }
 
@@ -1380,6 +1687,11 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         asc_new(self, &self.ctx.host_exports.data_source_context().sorted())
     }
 
+    /// function dataSource.deployment(): String
+    pub fn data_source_deployment(&mut self) -> Result<AscPtr<AscString>, DeterministicHostError> {
+        asc_new(self, &self.ctx.host_exports.data_source_deployment())
+    }
+
     pub fn ens_name_by_hash(
         &mut self,
         hash_ptr: AscPtr<AscString>,
@@ -1397,7 +1709,13 @@ impl<C: Blockchain> WasmInstanceContext<C> {
         msg: AscPtr<AscString>,
     ) -> Result<(), DeterministicHostError> {
         let level = LogLevel::from(level).into();
-        let msg: String = asc_get(self, msg)?;
+        // Log messages never reach the store, and a message that can't be
+        // logged as is should not fail the mapping
+        let msg: String = if self.api_version() >= API_VERSION_0_0_6 {
+            decode_utf16(msg.read_ptr(self)?.content(), false)?
+        } else {
+            asc_get(self, msg)?
+        };
         self.ctx.host_exports.log_log(&self.ctx.logger, level, msg)
     }
 
@@ -0,0 +1,115 @@
+//! Instrumenting mappings for the profiler. The runtime only gets control
+//! back from a mapping when the mapping calls into the host, so time spent
+//! in loops that do not call the host would go unnoticed. While a
+//! deployment is profiled, its mappings therefore run as a copy of the
+//! module that calls `SAMPLE` at the start of every function and every
+//! loop iteration, which gives the profiler a chance to take a sample
+use walrus::ir::{Call, Instr, InstrLocId, InstrSeqId, Loop, Visitor};
+use walrus::{FunctionId, LocalFunction, Module};
+
+/// The import that instrumented modules call to let the profiler take a
+/// sample. It is not a host export, and modules that are deployed can not
+/// import it
+pub(crate) const SAMPLE_MODULE: &str = "graph-node";
+pub(crate) const SAMPLE: &str = "profiler.sample";
+
+/// Collects the bodies of all loops in a function
+#[derive(Default)]
+struct Loops(Vec<InstrSeqId>);
+
+impl<'a> Visitor<'a> for Loops {
+    fn visit_loop(&mut self, instr: &Loop) {
+        self.0.push(instr.seq);
+    }
+}
+
+/// Add calls to `SAMPLE` to the start of every function and every loop
+/// body of `raw_module` and return the instrumented module
+pub(crate) fn instrument(raw_module: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut module = Module::from_buffer(raw_module)?;
+    let ty = module.types.add(&[], &[]);
+    let (sample, _) = module.add_import_func(SAMPLE_MODULE, SAMPLE, ty);
+
+    let funcs: Vec<_> = module.funcs.iter_local().map(|(id, _)| id).collect();
+    for id in funcs {
+        instrument_func(module.funcs.get_mut(id).kind.unwrap_local_mut(), sample);
+    }
+    Ok(module.emit_wasm())
+}
+
+fn instrument_func(func: &mut LocalFunction, sample: FunctionId) {
+    let entry = func.entry_block();
+    let mut loops = Loops::default();
+    walrus::ir::dfs_in_order(&mut loops, func, entry);
+
+    for seq in std::iter::once(entry).chain(loops.0) {
+        func.block_mut(seq).instrs.insert(
+            0,
+            (Instr::Call(Call { func: sample }), InstrLocId::default()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use walrus::ir::BinaryOp;
+    use walrus::{FunctionBuilder, ModuleConfig, ValType};
+
+    use super::*;
+
+    /// A module that exports `spin(n)`, which loops `n` times without
+    /// calling anything
+    fn spin_module() -> Vec<u8> {
+        let mut module = Module::with_config(ModuleConfig::new());
+        let n = module.locals.add(ValType::I32);
+        let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+        builder.func_body().loop_(None, |body| {
+            let start = body.id();
+            body.local_get(n)
+                .i32_const(1)
+                .binop(BinaryOp::I32Sub)
+                .local_tee(n)
+                .br_if(start);
+        });
+        let spin = builder.finish(vec![n], &mut module.funcs);
+        module.exports.add("spin", spin);
+        module.emit_wasm()
+    }
+
+    #[test]
+    fn loops_call_the_profiler() {
+        let instrumented = instrument(&spin_module()).unwrap();
+
+        let engine = wasmtime::Engine::default();
+        let module = wasmtime::Module::from_binary(&engine, &instrumented).unwrap();
+        let imports: Vec<_> = module
+            .imports()
+            .map(|import| {
+                (
+                    import.module().to_string(),
+                    import.name().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![(SAMPLE_MODULE.to_string(), SAMPLE.to_string())],
+            imports
+        );
+
+        let store = wasmtime::Store::new(&engine);
+        let calls = Rc::new(Cell::new(0));
+        let sample = {
+            let calls = calls.clone();
+            wasmtime::Func::wrap(&store, move || calls.set(calls.get() + 1))
+        };
+        let instance = wasmtime::Instance::new(&store, &module, &[sample.into()]).unwrap();
+        let spin = instance.get_func("spin").unwrap();
+        spin.typed::<i32, ()>().unwrap().call(10).unwrap();
+
+        // Once when `spin` is called, and once for every iteration
+        assert_eq!(11, calls.get());
+    }
+}
@@ -0,0 +1,262 @@
+//! The host exports that `WasmInstance` links into mapping modules. A
+//! mapping that was built with a newer `graph-ts` can import functions
+//! that this node does not have, and wasmtime only notices that when it
+//! instantiates the module for the first trigger. Comparing the imports
+//! of a mapping with this registry lets the registrar refuse such
+//! subgraphs when they are deployed. Every name passed to `link!` must be
+//! listed here
+use graph::blockchain::{Blockchain, DataSource, DataSourceTemplate};
+use graph::data::subgraph::features::SubgraphFeatureValidationError;
+use graph::data::subgraph::{
+    imported_host_fns, SubgraphManifest, SubgraphManifestValidationError, API_VERSION_0_0_6,
+    MAX_API_VERSION,
+};
+use semver::Version;
+
+const API_VERSION_0_0_4: Version = Version::new(0, 0, 4);
+
+/// The version of graph-node that provides the exports listed here
+const NODE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+struct HostExport {
+    name: &'static str,
+    /// The first `apiVersion` for which the export is linked, if it was
+    /// added after the first version
+    since: Option<Version>,
+    /// The last `apiVersion` for which the export is linked, if it was
+    /// removed for later versions
+    until: Option<Version>,
+}
+
+const fn export(name: &'static str) -> HostExport {
+    HostExport {
+        name,
+        since: None,
+        until: None,
+    }
+}
+
+const fn added_in(name: &'static str, since: Version) -> HostExport {
+    HostExport {
+        name,
+        since: Some(since),
+        until: None,
+    }
+}
+
+const fn removed_after(name: &'static str, until: Version) -> HostExport {
+    HostExport {
+        name,
+        since: None,
+        until: Some(until),
+    }
+}
+
+const HOST_EXPORTS: &[HostExport] = &[
+    export("ethereum.encode"),
+    export("ethereum.decode"),
+    export("abort"),
+    export("store.get"),
+    export("store.set"),
+    export("ipfs.cat"),
+    export("ipfs.map"),
+    export("store.remove"),
+    added_in("store.changedEntities", API_VERSION_0_0_6),
+    export("typeConversion.bytesToString"),
+    export("typeConversion.bytesToHex"),
+    export("typeConversion.bigIntToString"),
+    export("typeConversion.bigIntToHex"),
+    export("typeConversion.stringToH160"),
+    export("typeConversion.bytesToBase58"),
+    export("json.fromBytes"),
+    export("json.try_fromBytes"),
+    export("json.toI64"),
+    export("json.toU64"),
+    export("json.toF64"),
+    export("json.toBigInt"),
+    export("crypto.keccak256"),
+    added_in("crypto.sha256", API_VERSION_0_0_6),
+    added_in("crypto.ripemd160", API_VERSION_0_0_6),
+    export("bigInt.plus"),
+    export("bigInt.minus"),
+    export("bigInt.times"),
+    export("bigInt.dividedBy"),
+    export("bigInt.dividedByDecimal"),
+    export("bigInt.mod"),
+    export("bigInt.pow"),
+    export("bigInt.fromString"),
+    export("bigInt.bitOr"),
+    export("bigInt.bitAnd"),
+    export("bigInt.leftShift"),
+    export("bigInt.rightShift"),
+    export("bigDecimal.toString"),
+    export("bigDecimal.fromString"),
+    export("bigDecimal.plus"),
+    export("bigDecimal.minus"),
+    export("bigDecimal.times"),
+    export("bigDecimal.dividedBy"),
+    export("bigDecimal.equals"),
+    added_in("bigDecimal.pow", API_VERSION_0_0_6),
+    added_in("bigDecimal.truncate", API_VERSION_0_0_6),
+    export("dataSource.create"),
+    export("dataSource.createWithContext"),
+    export("dataSource.address"),
+    export("dataSource.network"),
+    export("dataSource.context"),
+    export("dataSource.deployment"),
+    export("ens.nameByHash"),
+    export("log.log"),
+    removed_after("arweave.transactionData", API_VERSION_0_0_4),
+    removed_after("box.profile", API_VERSION_0_0_4),
+];
+
+/// Whether `name` is listed in the registry
+pub(crate) fn is_registered(name: &str) -> bool {
+    HOST_EXPORTS.iter().any(|export| export.name == name)
+}
+
+/// The imports of the module `runtime` that can not be linked for a
+/// mapping with `api_version`, each with what it would take to provide
+/// it. `chain_fns` are the host functions of the chain the mapping runs on
+pub fn missing_imports(
+    runtime: &[u8],
+    api_version: &Version,
+    chain_fns: &[&str],
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut missing = Vec::new();
+    for name in imported_host_fns(runtime)? {
+        if chain_fns.contains(&name.as_str()) {
+            continue;
+        }
+        match HOST_EXPORTS.iter().find(|export| export.name == name) {
+            Some(HostExport {
+                since: Some(since), ..
+            }) if api_version < since => missing.push(format!(
+                "`{}` (only provided from apiVersion {})",
+                name, since
+            )),
+            Some(HostExport {
+                until: Some(until), ..
+            }) if api_version > until => missing.push(format!(
+                "`{}` (only provided up to apiVersion {})",
+                name, until
+            )),
+            Some(_) => {}
+            None => missing.push(format!(
+                "`{}` (not provided by graph-node {} for any apiVersion up to {})",
+                name, NODE_VERSION, *MAX_API_VERSION
+            )),
+        }
+    }
+    Ok(missing)
+}
+
+/// Check that the host provides everything that the mappings of
+/// `manifest` import. `chain_fns` are the host functions of its chain
+pub fn validate_imports<C: Blockchain>(
+    manifest: &SubgraphManifest<C>,
+    chain_fns: &[&str],
+) -> Vec<SubgraphManifestValidationError> {
+    let mappings = manifest
+        .data_sources
+        .iter()
+        .map(|ds| (ds.name(), ds.api_version(), ds.runtime()))
+        .chain(
+            manifest
+                .templates
+                .iter()
+                .map(|template| (template.name(), template.api_version(), template.runtime())),
+        );
+
+    let mut errors = Vec::new();
+    for (name, api_version, runtime) in mappings {
+        match missing_imports(runtime, &api_version, chain_fns) {
+            Ok(missing) if missing.is_empty() => {}
+            Ok(missing) => errors.push(
+                SubgraphManifestValidationError::DataSourceMissingHostExports(
+                    name.to_string(),
+                    missing,
+                ),
+            ),
+            Err(_) => errors.push(SubgraphFeatureValidationError::InvalidMapping.into()),
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module that imports functions with the given `(module, name)`
+    fn module_importing(imports: &[(&str, &str)]) -> Vec<u8> {
+        fn name(bytes: &mut Vec<u8>, name: &str) {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        }
+
+        let mut section = vec![imports.len() as u8];
+        for (module, field) in imports {
+            name(&mut section, module);
+            name(&mut section, field);
+            // A function with type 0
+            section.extend_from_slice(&[0x00, 0x00]);
+        }
+
+        let mut bytes = b"\0asm".to_vec();
+        bytes.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+        // The type section with the single type `() -> ()`
+        bytes.extend_from_slice(&[0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+        bytes.push(0x02);
+        bytes.push(section.len() as u8);
+        bytes.extend(section);
+        bytes
+    }
+
+    #[test]
+    fn finds_missing_imports() {
+        let v5 = Version::new(0, 0, 5);
+        let runtime = module_importing(&[
+            ("index", "store.set"),
+            ("index", "ethereum.call"),
+            ("index", "ethereum.getBalance"),
+            ("env", "abort"),
+        ]);
+
+        let missing = missing_imports(&runtime, &v5, &["ethereum.call"]).unwrap();
+        assert_eq!(1, missing.len());
+        assert!(missing[0].starts_with("`ethereum.getBalance` (not provided by graph-node"));
+
+        // Without the chain providing it, `ethereum.call` is missing, too
+        let missing = missing_imports(&runtime, &v5, &[]).unwrap();
+        assert_eq!(2, missing.len());
+        assert!(missing[0].starts_with("`ethereum.call`"));
+
+        // Exports that were removed are only missing for later versions
+        let runtime = module_importing(&[("index", "box.profile")]);
+        assert!(missing_imports(&runtime, &API_VERSION_0_0_4, &[])
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec!["`box.profile` (only provided up to apiVersion 0.0.4)".to_string()],
+            missing_imports(&runtime, &v5, &[]).unwrap()
+        );
+
+        // Exports that were added later are only missing for earlier versions
+        let runtime = module_importing(&[("index", "crypto.sha256")]);
+        assert_eq!(
+            vec!["`crypto.sha256` (only provided from apiVersion 0.0.6)".to_string()],
+            missing_imports(&runtime, &v5, &[]).unwrap()
+        );
+        assert!(missing_imports(&runtime, &API_VERSION_0_0_6, &[])
+            .unwrap()
+            .is_empty());
+        let runtime = module_importing(&[("index", "store.changedEntities")]);
+        assert_eq!(
+            vec!["`store.changedEntities` (only provided from apiVersion 0.0.6)".to_string()],
+            missing_imports(&runtime, &v5, &[]).unwrap()
+        );
+
+        assert!(missing_imports(b"not wasm", &v5, &[]).is_err());
+    }
+}
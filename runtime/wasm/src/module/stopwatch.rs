@@ -2,6 +2,7 @@
 // Copyright (c) 2014 Chucky Ellison <cme at freefour.com> under MIT license
 
 use std::default::Default;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 #[derive(Clone, Copy)]
@@ -56,3 +57,32 @@ impl TimeoutStopwatch {
         }
     }
 }
+
+/// Keeps a stopwatch stopped until it is dropped
+pub struct PausedStopwatch(Arc<Mutex<TimeoutStopwatch>>);
+
+impl PausedStopwatch {
+    pub fn new(stopwatch: Arc<Mutex<TimeoutStopwatch>>) -> Self {
+        stopwatch.lock().unwrap().stop();
+        PausedStopwatch(stopwatch)
+    }
+}
+
+impl Drop for PausedStopwatch {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().start();
+    }
+}
+
+#[test]
+fn paused_stopwatch() {
+    let stopwatch = Arc::new(Mutex::new(TimeoutStopwatch::start_new()));
+    {
+        let _paused = PausedStopwatch::new(stopwatch.clone());
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    assert!(stopwatch.lock().unwrap().elapsed() < Duration::from_millis(100));
+
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(stopwatch.lock().unwrap().elapsed() >= Duration::from_millis(200));
+}
@@ -248,6 +248,18 @@ impl TryFromAscObj<AscEnum<StoreValueKind>> for store::Value {
                 let array: Vec<u8> = asc_get(heap, ptr)?;
                 Value::BigInt(store::scalar::BigInt::from_signed_bytes_le(&array))
             }
+            StoreValueKind::Int8 => Value::Int8(i64::from(payload)),
+            StoreValueKind::Timestamp => {
+                let micros = i64::from(payload);
+                let ts = store::scalar::Timestamp::from_microseconds_since_epoch(micros)
+                    .ok_or_else(|| {
+                        DeterministicHostError(anyhow::anyhow!(
+                            "timestamp {} is out of range",
+                            micros
+                        ))
+                    })?;
+                Value::Timestamp(ts)
+            }
         })
     }
 }
@@ -274,6 +286,8 @@ impl ToAscObj<AscEnum<StoreValueKind>> for store::Value {
                 let bytes_obj: AscPtr<Uint8Array> = asc_new(heap, &*big_int.to_signed_bytes_le())?;
                 bytes_obj.into()
             }
+            Value::Int8(n) => EnumPayload::from(*n),
+            Value::Timestamp(ts) => EnumPayload::from(ts.as_microseconds_since_epoch()),
         };
 
         Ok(AscEnum {
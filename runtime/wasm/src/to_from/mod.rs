@@ -1,7 +1,10 @@
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::hash::Hash;
 use std::iter::FromIterator;
 
+use graph::data::subgraph::API_VERSION_0_0_6;
+use graph::prelude::anyhow;
 use graph::runtime::asc_get;
 use graph::runtime::asc_new;
 use graph::runtime::try_asc_get;
@@ -100,11 +103,52 @@ impl ToAscObj<AscString> for String {
     }
 }
 
+/// Decode a string from a mapping with `apiVersion` 0.0.6 or later.
+/// Postgres does not accept NUL characters, and unpaired surrogates can not
+/// be represented in a Rust string. Unless `strict` is set, we drop NUL
+/// characters and replace unpaired surrogates with U+FFFD; otherwise we
+/// fail with an error that shows the string
+pub fn decode_utf16(
+    content: &[u16],
+    strict: bool,
+) -> Result<String, DeterministicHostError> {
+    let mut string = String::with_capacity(content.len());
+    for c in std::char::decode_utf16(content.iter().cloned()) {
+        let problem = match c {
+            Ok('\u{0000}') if !strict => continue,
+            Ok('\u{0000}') => "a NUL character",
+            Ok(c) => {
+                string.push(c);
+                continue;
+            }
+            Err(_) if !strict => {
+                string.push(std::char::REPLACEMENT_CHARACTER);
+                continue;
+            }
+            Err(_) => "invalid UTF-16",
+        };
+        let lossy: String = String::from_utf16_lossy(content)
+            .chars()
+            .take(1024)
+            .collect();
+        return Err(DeterministicHostError(anyhow!(
+            "string {:?} contains {}",
+            lossy,
+            problem
+        )));
+    }
+    Ok(string)
+}
+
 impl FromAscObj<AscString> for String {
     fn from_asc_obj<H: AscHeap + ?Sized>(
         asc_string: AscString,
-        _: &H,
+        heap: &H,
     ) -> Result<Self, DeterministicHostError> {
+        if heap.api_version() >= API_VERSION_0_0_6 {
+            return decode_utf16(asc_string.content(), heap.strict_strings());
+        }
+
         let mut string = String::from_utf16(asc_string.content())
             .map_err(|e| DeterministicHostError(e.into()))?;
 
@@ -165,7 +209,7 @@ impl<C: AscType + AscIndexId, T: TryFromAscObj<C>> TryFromAscObj<Array<AscPtr<C>
 impl<
         K: AscType + AscIndexId,
         V: AscType + AscIndexId,
-        T: TryFromAscObj<K>,
+        T: TryFromAscObj<K> + Debug,
         U: TryFromAscObj<V>,
     > TryFromAscObj<AscTypedMapEntry<K, V>> for (T, U)
 {
@@ -173,10 +217,11 @@ impl<
         asc_entry: AscTypedMapEntry<K, V>,
         heap: &H,
     ) -> Result<Self, DeterministicHostError> {
-        Ok((
-            try_asc_get(heap, asc_entry.key)?,
-            try_asc_get(heap, asc_entry.value)?,
-        ))
+        let key: T = try_asc_get(heap, asc_entry.key)?;
+        // Name the key that a bad value belongs to
+        let value = try_asc_get(heap, asc_entry.value)
+            .map_err(|e| DeterministicHostError(e.0.context(format!("key {:?}", key))))?;
+        Ok((key, value))
     }
 }
 
@@ -197,7 +242,7 @@ impl<K: AscType + AscIndexId, V: AscType + AscIndexId, T: ToAscObj<K>, U: ToAscO
 impl<
         K: AscType + AscIndexId,
         V: AscType + AscIndexId,
-        T: TryFromAscObj<K> + Hash + Eq,
+        T: TryFromAscObj<K> + Hash + Eq + Debug,
         U: TryFromAscObj<V>,
     > TryFromAscObj<AscTypedMap<K, V>> for HashMap<T, U>
 where
@@ -17,6 +17,68 @@ impl GraphQLRequest {
     }
 }
 
+/// The operations in the body of a GraphQL request
+#[derive(Debug)]
+pub enum GraphQLOperations {
+    /// The body was a JSON object with a single operation
+    Single(Query),
+    /// The body was a JSON array of operations. Operations whose query can
+    /// not be parsed are reported individually, in the position in which
+    /// they appeared in the batch
+    Batch(Vec<Result<Query, QueryError>>),
+}
+
+impl GraphQLRequest {
+    /// Parse the body into the operations it contains. If `max_batch_size`
+    /// is `None`, batching is disabled and only a single operation is
+    /// accepted; otherwise, the body may also be an array of at most
+    /// `max_batch_size` operations
+    pub fn operations(
+        &self,
+        max_batch_size: Option<usize>,
+    ) -> Result<GraphQLOperations, GraphQLServerError> {
+        let json: serde_json::Value = serde_json::from_slice(&self.body)
+            .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
+
+        let operations = match json {
+            serde_json::Value::Array(operations) => operations,
+            json => return parse_operation(&json).map(GraphQLOperations::Single),
+        };
+
+        let max_batch_size = max_batch_size.ok_or_else(|| {
+            GraphQLServerError::ClientError(String::from(
+                "Batching of GraphQL operations is disabled",
+            ))
+        })?;
+        if operations.is_empty() {
+            return Err(GraphQLServerError::ClientError(String::from(
+                "The batch of operations is empty",
+            )));
+        }
+        if operations.len() > max_batch_size {
+            return Err(GraphQLServerError::ClientError(format!(
+                "The batch contains {} operations, but at most {} are allowed",
+                operations.len(),
+                max_batch_size
+            )));
+        }
+
+        operations
+            .iter()
+            .enumerate()
+            .map(|(i, operation)| match parse_operation(operation) {
+                Ok(query) => Ok(Ok(query)),
+                Err(GraphQLServerError::QueryError(e)) => Ok(Err(e)),
+                Err(GraphQLServerError::ClientError(e)) => Err(GraphQLServerError::ClientError(
+                    format!("Operation {} in batch: {}", i, e),
+                )),
+                Err(e) => Err(e),
+            })
+            .collect::<Result<_, _>>()
+            .map(GraphQLOperations::Batch)
+    }
+}
+
 impl Future for GraphQLRequest {
     type Item = Query;
     type Error = GraphQLServerError;
@@ -26,43 +88,46 @@ impl Future for GraphQLRequest {
         let json: serde_json::Value = serde_json::from_slice(&self.body)
             .map_err(|e| GraphQLServerError::ClientError(format!("{}", e)))?;
 
-        // Ensure the JSON data is an object
-        let obj = json.as_object().ok_or_else(|| {
-            GraphQLServerError::ClientError(String::from("Request data is not an object"))
-        })?;
+        parse_operation(&json).map(Async::Ready)
+    }
+}
 
-        // Ensure the JSON data has a "query" field
-        let query_value = obj.get("query").ok_or_else(|| {
-            GraphQLServerError::ClientError(String::from(
-                "The \"query\" field is missing in request data",
-            ))
-        })?;
+/// Parse one operation, an object with a `query` and optional `variables`
+fn parse_operation(json: &serde_json::Value) -> Result<Query, GraphQLServerError> {
+    // Ensure the JSON data is an object
+    let obj = json.as_object().ok_or_else(|| {
+        GraphQLServerError::ClientError(String::from("Request data is not an object"))
+    })?;
 
-        // Ensure the "query" field is a string
-        let query_string = query_value.as_str().ok_or_else(|| {
-            GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
-        })?;
+    // Ensure the JSON data has a "query" field
+    let query_value = obj.get("query").ok_or_else(|| {
+        GraphQLServerError::ClientError(String::from(
+            "The \"query\" field is missing in request data",
+        ))
+    })?;
 
-        // Parse the "query" field of the JSON body
-        let document = graphql_parser::parse_query(query_string)
-            .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
-            .into_static();
+    // Ensure the "query" field is a string
+    let query_string = query_value.as_str().ok_or_else(|| {
+        GraphQLServerError::ClientError(String::from("The \"query\" field is not a string"))
+    })?;
 
-        // Parse the "variables" field of the JSON body, if present
-        let variables = match obj.get("variables") {
-            None | Some(serde_json::Value::Null) => Ok(None),
-            Some(variables @ serde_json::Value::Object(_)) => {
-                serde_json::from_value(variables.clone())
-                    .map_err(|e| GraphQLServerError::ClientError(e.to_string()))
-                    .map(Some)
-            }
-            _ => Err(GraphQLServerError::ClientError(
-                "Invalid query variables provided".to_string(),
-            )),
-        }?;
+    // Parse the "query" field of the JSON body
+    let document = graphql_parser::parse_query(query_string)
+        .map_err(|e| GraphQLServerError::from(QueryError::ParseError(Arc::new(e.into()))))?
+        .into_static();
 
-        Ok(Async::Ready(Query::new(document, variables)))
-    }
+    // Parse the "variables" field of the JSON body, if present
+    let variables = match obj.get("variables") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(variables @ serde_json::Value::Object(_)) => serde_json::from_value(variables.clone())
+            .map_err(|e| GraphQLServerError::ClientError(e.to_string()))
+            .map(Some),
+        _ => Err(GraphQLServerError::ClientError(
+            "Invalid query variables provided".to_string(),
+        )),
+    }?;
+
+    Ok(Query::new(document, variables))
 }
 
 #[cfg(test)]
@@ -73,7 +138,7 @@ mod tests {
 
     use graph::{data::query::QueryTarget, prelude::*};
 
-    use super::GraphQLRequest;
+    use super::{GraphQLOperations, GraphQLRequest};
 
     lazy_static! {
         static ref TARGET: QueryTarget =
@@ -185,4 +250,84 @@ mod tests {
         assert_eq!(query.document, expected_query);
         assert_eq!(query.variables, Some(expected_variables));
     }
+
+    #[test]
+    fn keeps_big_integer_variables_exact() {
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(
+            "\
+                 {\
+                 \"query\": \"{ user { name } }\", \
+                 \"variables\": { \
+                 \"float\": 1.5, \"big\": 12345678901234567890, \"negative\": -4294967296 \
+                 } \
+                 }",
+        ));
+        let query = request.wait().expect("Should accept valid queries");
+
+        let expected_variables = QueryVariables::new(HashMap::from_iter(
+            vec![
+                (String::from("float"), q::Value::Float(1.5)),
+                (
+                    String::from("big"),
+                    q::Value::String(String::from("12345678901234567890")),
+                ),
+                (
+                    String::from("negative"),
+                    q::Value::String(String::from("-4294967296")),
+                ),
+            ]
+            .into_iter(),
+        ));
+        assert_eq!(query.variables, Some(expected_variables));
+    }
+
+    fn batch(operations: usize) -> GraphQLRequest {
+        let operations = vec!["{\"query\": \"{ user { name } }\"}"; operations];
+        GraphQLRequest::new(hyper::body::Bytes::from(format!(
+            "[{}]",
+            operations.join(",")
+        )))
+    }
+
+    #[test]
+    fn accepts_batches() {
+        match batch(5).operations(Some(10)) {
+            Ok(GraphQLOperations::Batch(queries)) => {
+                assert_eq!(5, queries.len());
+                assert!(queries.iter().all(|query| query.is_ok()));
+            }
+            other => panic!("expected a batch of 5 operations, got {:?}", other),
+        }
+        match batch(1).wait() {
+            Err(_) => (),
+            Ok(_) => panic!("a batch is not a single operation"),
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_or_disabled_batches() {
+        batch(50)
+            .operations(Some(10))
+            .expect_err("Should reject batches larger than the maximum");
+        batch(1)
+            .operations(None)
+            .expect_err("Should reject batches when batching is disabled");
+        batch(0)
+            .operations(Some(10))
+            .expect_err("Should reject empty batches");
+    }
+
+    #[test]
+    fn reports_broken_queries_in_batches_individually() {
+        let request = GraphQLRequest::new(hyper::body::Bytes::from(
+            "[{\"query\": \"{ user { name } }\"}, {\"query\": \"foo\"}]",
+        ));
+        match request.operations(Some(10)) {
+            Ok(GraphQLOperations::Batch(queries)) => {
+                assert!(queries[0].is_ok());
+                assert!(queries[1].is_err());
+            }
+            other => panic!("expected a batch of 2 operations, got {:?}", other),
+        }
+    }
 }
@@ -27,15 +27,18 @@ pub struct GraphQLServer<Q> {
     metrics: Arc<GraphQLServiceMetrics>,
     graphql_runner: Arc<Q>,
     node_id: NodeId,
+    max_batch_size: Option<usize>,
 }
 
 impl<Q> GraphQLServer<Q> {
-    /// Creates a new GraphQL server.
+    /// Creates a new GraphQL server. Requests may contain a batch of up to
+    /// `max_batch_size` operations; if it is `None`, batching is disabled.
     pub fn new(
         logger_factory: &LoggerFactory,
         metrics_registry: Arc<impl MetricsRegistry>,
         graphql_runner: Arc<Q>,
         node_id: NodeId,
+        max_batch_size: Option<usize>,
     ) -> Self {
         let logger = logger_factory.component_logger(
             "GraphQLServer",
@@ -51,6 +54,7 @@ impl<Q> GraphQLServer<Q> {
             metrics,
             graphql_runner,
             node_id,
+            max_batch_size,
         }
     }
 }
@@ -81,6 +85,7 @@ where
         let graphql_runner = self.graphql_runner.clone();
         let metrics = self.metrics.clone();
         let node_id = self.node_id.clone();
+        let max_batch_size = self.max_batch_size;
         let new_service = make_service_fn(move |_| {
             futures03::future::ok::<_, Error>(GraphQLService::new(
                 logger_for_service.clone(),
@@ -88,6 +93,7 @@ where
                 graphql_runner.clone(),
                 ws_port,
                 node_id.clone(),
+                max_batch_size,
             ))
         });
 
@@ -3,23 +3,31 @@ use std::fmt;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
-use std::time::Instant;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 use graph::prelude::*;
-use graph::{components::server::query::GraphQLServerError, data::query::QueryTarget};
+use graph::{
+    components::server::body::{check_json_content_type, read_body, GRAPHQL_MAX_BODY_SIZE},
+    components::server::query::GraphQLServerError,
+    data::query::{QueryResults, QueryTarget},
+    util::crash,
+};
+use graph_graphql::prelude::GRAPHQL_QUERY_TIMEOUT;
 use http::header;
 use http::header::{
     ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
-    CONTENT_TYPE, LOCATION,
+    CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION,
 };
+use hyper::body::HttpBody;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
 
-use crate::request::GraphQLRequest;
+use crate::request::{GraphQLOperations, GraphQLRequest};
 
 pub struct GraphQLServiceMetrics {
     query_execution_time: Box<HistogramVec>,
     failed_query_execution_time: Box<HistogramVec>,
+    query_timeouts: Box<CounterVec>,
 }
 
 impl fmt::Debug for GraphQLServiceMetrics {
@@ -48,9 +56,32 @@ impl GraphQLServiceMetrics {
             )
             .expect("failed to create `query_failed_execution_time` histogram");
 
+        let query_timeouts = registry
+            .new_counter_vec(
+                "query_timeouts",
+                "Counts GraphQL queries that were cancelled because they ran out of time, \
+                 either at the deadline the client set or at the server's query timeout",
+                vec![String::from("cause")],
+            )
+            .expect("failed to create `query_timeouts` counter");
+
         Self {
             query_execution_time,
             failed_query_execution_time,
+            query_timeouts,
+        }
+    }
+
+    /// Record the execution time of a query that started at `start`, and
+    /// whether it timed out. If it did, `client_bound` says whether the
+    /// deadline the client set was what stopped it
+    fn observe_result(&self, result: &QueryResults, start: Instant, client_bound: bool) {
+        if let Some(id) = result.first().and_then(|res| res.deployment.clone()) {
+            self.observe_query_execution_time(start.elapsed().as_secs_f64(), id.to_string());
+        }
+        if result.timed_out() {
+            let cause = if client_bound { "deadline" } else { "timeout" };
+            self.query_timeouts.with_label_values(&[cause]).inc();
         }
     }
 
@@ -67,6 +98,117 @@ impl GraphQLServiceMetrics {
     }
 }
 
+/// The header with which clients set a deadline for their query
+const X_GRAPH_DEADLINE: &str = "x-graph-deadline";
+
+/// The header that tells clients which deployment answered their query.
+/// That is mostly useful for queries that name a subgraph
+const X_GRAPH_DEPLOYMENT: &str = "x-graph-deployment";
+
+/// Add the `x-graph-deployment` header to `response` if we know which
+/// deployment answered the query
+fn with_deployment(
+    mut response: Response<Body>,
+    deployment: Option<&DeploymentHash>,
+) -> Response<Body> {
+    if let Some(value) = deployment.and_then(|id| header::HeaderValue::from_str(id.as_str()).ok()) {
+        let headers = response.headers_mut();
+        headers.insert(X_GRAPH_DEPLOYMENT, value);
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            header::HeaderValue::from_static(X_GRAPH_DEPLOYMENT),
+        );
+    }
+    response
+}
+
+/// Headers that tell clients at which block the data in a response was
+/// computed, the block the deployment has been indexed up to, and how many
+/// blocks the data is behind that. Responses from the query cache or from
+/// a replica that is catching up can be for an earlier block. The standard
+/// `Age` header says how many seconds ago the data was computed
+const X_GRAPH_BLOCK: &str = "x-graph-block";
+const X_GRAPH_LATEST_BLOCK: &str = "x-graph-latest-block";
+const X_GRAPH_BLOCKS_BEHIND: &str = "x-graph-blocks-behind";
+
+/// Add the headers that describe how current the data in `results` is
+fn with_freshness(mut response: Response<Body>, results: &[QueryResults]) -> Response<Body> {
+    let block = results.iter().filter_map(QueryResults::block).min();
+    let latest_block = results.iter().filter_map(QueryResults::latest_block).max();
+    let age = results.iter().filter_map(QueryResults::age).max();
+
+    let headers = response.headers_mut();
+    let mut exposed = Vec::new();
+    if let Some(block) = block {
+        headers.insert(X_GRAPH_BLOCK, header::HeaderValue::from(block));
+        exposed.push(X_GRAPH_BLOCK);
+    }
+    if let Some(latest_block) = latest_block {
+        headers.insert(
+            X_GRAPH_LATEST_BLOCK,
+            header::HeaderValue::from(latest_block),
+        );
+        exposed.push(X_GRAPH_LATEST_BLOCK);
+    }
+    if let (Some(block), Some(latest_block)) = (block, latest_block) {
+        let behind = (latest_block - block).max(0);
+        headers.insert(X_GRAPH_BLOCKS_BEHIND, header::HeaderValue::from(behind));
+        exposed.push(X_GRAPH_BLOCKS_BEHIND);
+    }
+    if let Some(age) = age {
+        headers.insert(header::AGE, header::HeaderValue::from(age.as_secs()));
+        exposed.push("age");
+    }
+    if !exposed.is_empty() {
+        // Unwrap: header names are valid header values
+        headers.append(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            header::HeaderValue::from_str(&exposed.join(", ")).unwrap(),
+        );
+    }
+    response
+}
+
+/// Deadlines up to this value are milliseconds from now, larger ones
+/// are milliseconds since the epoch. The cutoff is in September 2001, and
+/// no sensible relative deadline comes anywhere near it
+const RELATIVE_DEADLINE_MAX: u64 = 1_000_000_000_000;
+
+/// Parse the value of the `x-graph-deadline` header into how much time
+/// is left until the deadline, counting from `now`
+fn parse_deadline(value: &str, now: SystemTime) -> Result<Duration, GraphQLServerError> {
+    let millis = value.trim().parse::<u64>().map_err(|_| {
+        GraphQLServerError::ClientError(format!(
+            "The {} header must be a number of milliseconds, but is `{}`",
+            X_GRAPH_DEADLINE, value
+        ))
+    })?;
+    if millis <= RELATIVE_DEADLINE_MAX {
+        return Ok(Duration::from_millis(millis));
+    }
+    let deadline = UNIX_EPOCH + Duration::from_millis(millis);
+    Ok(deadline.duration_since(now).unwrap_or_default())
+}
+
+/// Wait for `run` to finish, but give up with a timeout error at the
+/// `deadline`
+async fn run_until(
+    run: impl std::future::Future<Output = QueryResults>,
+    deadline: Option<Instant>,
+) -> QueryResults {
+    let deadline = match deadline {
+        None => return run.await,
+        Some(deadline) => deadline,
+    };
+    let now = Instant::now();
+    if deadline <= now {
+        return QueryResults::from(QueryExecutionError::Timeout);
+    }
+    tokio::time::timeout(deadline - now, run)
+        .await
+        .unwrap_or_else(|_| QueryResults::from(QueryExecutionError::Timeout))
+}
+
 pub type GraphQLServiceResult = Result<Response<Body>, GraphQLServerError>;
 /// An asynchronous response to a GraphQL request.
 pub type GraphQLServiceResponse =
@@ -80,6 +222,9 @@ pub struct GraphQLService<Q> {
     graphql_runner: Arc<Q>,
     ws_port: u16,
     node_id: NodeId,
+    /// The most operations one request may contain, or `None` if batching
+    /// is disabled
+    max_batch_size: Option<usize>,
 }
 
 impl<Q> Clone for GraphQLService<Q> {
@@ -90,6 +235,7 @@ impl<Q> Clone for GraphQLService<Q> {
             graphql_runner: self.graphql_runner.clone(),
             ws_port: self.ws_port,
             node_id: self.node_id.clone(),
+            max_batch_size: self.max_batch_size,
         }
     }
 }
@@ -105,6 +251,7 @@ where
         graphql_runner: Arc<Q>,
         ws_port: u16,
         node_id: NodeId,
+        max_batch_size: Option<usize>,
     ) -> Self {
         GraphQLService {
             logger,
@@ -112,6 +259,7 @@ where
             graphql_runner,
             ws_port,
             node_id,
+            max_batch_size,
         }
     }
 
@@ -175,7 +323,7 @@ where
             GraphQLServerError::ClientError(format!("Invalid subgraph name {:?}", subgraph_name))
         })?;
 
-        self.handle_graphql_query(subgraph_name.into(), request.into_body())
+        self.handle_graphql_query(subgraph_name.into(), request)
             .await
     }
 
@@ -188,38 +336,144 @@ where
             .map_err(|id| GraphQLServerError::ClientError(format!("Invalid subgraph id `{}`", id)));
         match res {
             Err(_) => self.handle_not_found(),
-            Ok(id) => self
-                .handle_graphql_query(id.into(), request.into_body())
-                .boxed(),
+            Ok(id) => self.handle_graphql_query(id.into(), request).boxed(),
         }
     }
 
+    /// Serves the SDL of the API schema of a deployment. The response
+    /// carries an `ETag` with the hash of the SDL so that clients can
+    /// cheaply check whether the schema has changed.
+    fn handle_graphql_schema_by_id(
+        self,
+        id: String,
+        request: Request<Body>,
+    ) -> GraphQLServiceResponse {
+        match DeploymentHash::new(id) {
+            Err(_) => self.handle_not_found(),
+            Ok(id) => self.handle_graphql_schema(id.into(), request).boxed(),
+        }
+    }
+
+    async fn handle_graphql_schema(
+        self,
+        target: QueryTarget,
+        request: Request<Body>,
+    ) -> GraphQLServiceResult {
+        let schema = self
+            .graphql_runner
+            .clone()
+            .api_schema(target)
+            .await
+            .map_err(QueryError::from)?;
+        let etag = format!("\"{}\"", schema.sdl_hash());
+
+        let unchanged = request
+            .headers()
+            .get(IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(',').any(|tag| tag.trim() == etag))
+            .unwrap_or(false);
+        let response = Response::builder()
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(ETAG, etag);
+        if unchanged {
+            return Ok(response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap());
+        }
+        Ok(response
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Body::from(schema.sdl()))
+            .unwrap())
+    }
+
     async fn handle_graphql_query(
         self,
         target: QueryTarget,
-        request_body: Body,
+        request: Request<Body>,
     ) -> GraphQLServiceResult {
         let service = self.clone();
         let service_metrics = self.metrics.clone();
 
         let start = Instant::now();
-        let body = hyper::body::to_bytes(request_body)
-            .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
-            .await?;
-        let query = GraphQLRequest::new(body).compat().await;
-
-        let result = match query {
-            Ok(query) => service.graphql_runner.run_query(query, target).await,
-            Err(GraphQLServerError::QueryError(e)) => QueryResult::from(e).into(),
+        let client_deadline = request
+            .headers()
+            .get(X_GRAPH_DEADLINE)
+            .map(|value| {
+                value
+                    .to_str()
+                    .map_err(|e| GraphQLServerError::ClientError(e.to_string()))
+                    .and_then(|value| parse_deadline(value, SystemTime::now()))
+                    .map(|remaining| start + remaining)
+            })
+            .transpose()?;
+        let server_deadline = GRAPHQL_QUERY_TIMEOUT.map(|timeout| start + timeout);
+        // The deadline that will actually stop execution; clients can
+        // shorten the time a query may take, but not extend it
+        let deadline = match (client_deadline, server_deadline) {
+            (Some(client), Some(server)) => Some(client.min(server)),
+            (client, server) => client.or(server),
+        };
+        let client_bound = client_deadline.is_some() && client_deadline == deadline;
+
+        let content_type = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap_or(""));
+        check_json_content_type(content_type)?;
+        let mut body = request.into_body();
+        let content_length = body.size_hint().exact();
+        let chunks = futures03::stream::poll_fn(move |cx| Pin::new(&mut body).poll_data(cx));
+        let body = read_body(chunks, content_length, *GRAPHQL_MAX_BODY_SIZE).await?;
+        let body = hyper::body::Bytes::from(body);
+        let operations = GraphQLRequest::new(body).operations(self.max_batch_size);
+
+        let queries = match operations {
+            Ok(GraphQLOperations::Single(query)) => {
+                let query = query.with_deadline(client_deadline);
+                let run = service.graphql_runner.clone().run_query(query, target);
+                let result = run_until(run, client_deadline).await;
+                service_metrics.observe_result(&result, start, client_bound);
+                let response = with_deployment(result.as_http_response(), result.deployment());
+                return Ok(with_freshness(response, &[result]));
+            }
+            Ok(GraphQLOperations::Batch(queries)) => queries,
+            Err(GraphQLServerError::QueryError(e)) => {
+                return Ok(QueryResults::from(QueryResult::from(e)).as_http_response())
+            }
             Err(e) => return Err(e),
         };
 
-        if let Some(id) = result.first().and_then(|res| res.deployment.clone()) {
-            service_metrics
-                .observe_query_execution_time(start.elapsed().as_secs_f64(), id.to_string());
+        // The operations in a batch run one after the other, and each of
+        // them is subject to the same limits as a single query. The query
+        // timeout and the client's deadline apply to the batch as a whole,
+        // and operations that do not finish before then fail with a timeout
+        let mut results = Vec::with_capacity(queries.len());
+        for query in queries {
+            let query = match query {
+                Ok(query) => query.with_deadline(deadline),
+                Err(e) => {
+                    results.push(QueryResults::from(QueryResult::from(e)));
+                    continue;
+                }
+            };
+
+            let op_start = Instant::now();
+            let run = service
+                .graphql_runner
+                .clone()
+                .run_query(query, target.clone());
+            let result = run_until(run, deadline).await;
+            service_metrics.observe_result(&result, op_start, client_bound);
+            results.push(result);
         }
 
-        Ok(result.as_http_response())
+        // All operations in a batch go to the same target
+        let deployment = results.iter().find_map(QueryResults::deployment);
+        let response = with_deployment(QueryResults::batch_as_http_response(&results), deployment);
+        Ok(with_freshness(response, &results))
     }
 
     // Handles OPTIONS requests
@@ -295,6 +549,10 @@ where
             | (Method::GET, &["subgraphs", "network", _, _, "graphql"])
             | (Method::GET, &["subgraphs", "graphql"]) => self.handle_graphiql(),
 
+            (Method::GET, &["subgraphs", "id", subgraph_id, "graphql-schema"]) => {
+                self.handle_graphql_schema_by_id(subgraph_id.to_owned(), req)
+            }
+
             (Method::GET, path @ ["subgraphs", "id", _])
             | (Method::GET, path @ ["subgraphs", "name", _])
             | (Method::GET, path @ ["subgraphs", "name", _, _])
@@ -350,34 +608,23 @@ where
 
         // Returning Err here will prevent the client from receiving any response.
         // Instead, we generate a Response with an error code and return Ok
+        let request = format!("{} {}", req.method(), req.uri().path());
         Box::pin(async move {
-            let result = service.handle_call(req).await;
+            let result = crash::scope(vec![("request", request)], service.handle_call(req)).await;
             match result {
                 Ok(response) => Ok(response),
-                Err(err @ GraphQLServerError::ClientError(_)) => Ok(Response::builder()
-                    .status(400)
-                    .header(CONTENT_TYPE, "text/plain")
-                    .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                    .body(Body::from(err.to_string()))
-                    .unwrap()),
-                Err(err @ GraphQLServerError::QueryError(_)) => {
-                    error!(logger, "GraphQLService call failed: {}", err);
+                Err(err) => {
+                    if err.is_client_error() {
+                        debug!(logger, "GraphQLService call failed: {}", err);
+                    } else {
+                        error!(logger, "GraphQLService call failed: {}", err);
+                    }
 
                     Ok(Response::builder()
-                        .status(400)
-                        .header(CONTENT_TYPE, "text/plain")
+                        .status(err.status_code())
+                        .header(CONTENT_TYPE, "application/json")
                         .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                        .body(Body::from(format!("Query error: {}", err)))
-                        .unwrap())
-                }
-                Err(err @ GraphQLServerError::InternalError(_)) => {
-                    error!(logger, "GraphQLService call failed: {}", err);
-
-                    Ok(Response::builder()
-                        .status(500)
-                        .header(CONTENT_TYPE, "text/plain")
-                        .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                        .body(Body::from(format!("Internal server error: {}", err)))
+                        .body(Body::from(err.as_graphql_body()))
                         .unwrap())
                 }
             }
@@ -403,6 +650,7 @@ mod tests {
 
     use super::GraphQLService;
     use super::GraphQLServiceMetrics;
+    use super::{parse_deadline, UNIX_EPOCH};
 
     /// A simple stupid query runner for testing.
     pub struct TestGraphQlRunner;
@@ -443,6 +691,22 @@ mod tests {
             unreachable!();
         }
 
+        async fn subscription_complexity(
+            self: Arc<Self>,
+            _subscription: Subscription,
+            _target: QueryTarget,
+        ) -> Result<u64, SubscriptionError> {
+            unreachable!();
+        }
+
+        async fn api_schema(
+            self: Arc<Self>,
+            _target: QueryTarget,
+        ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+            let schema = Schema::parse("type Query { name: String }", USERS.clone()).unwrap();
+            Ok(Arc::new(ApiSchema::from_api_schema(schema).unwrap()))
+        }
+
         fn load_manager(&self) -> Arc<LoadManager> {
             unimplemented!()
         }
@@ -457,7 +721,8 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let mut service =
+            GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, Some(10));
 
         let request = Request::builder()
             .method(Method::POST)
@@ -489,7 +754,8 @@ mod tests {
         let graphql_runner = Arc::new(TestGraphQlRunner);
 
         let node_id = NodeId::new("test").unwrap();
-        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id);
+        let mut service =
+            GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, Some(10));
 
         let request = Request::builder()
             .method(Method::POST)
@@ -515,4 +781,159 @@ mod tests {
             .expect("Query result field \"name\" is not a string");
         assert_eq!(name, "Jordi".to_string());
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn graphql_schema_is_cached_by_etag() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let node_id = NodeId::new("test").unwrap();
+        let mut service =
+            GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, Some(10));
+        let uri = format!(
+            "http://localhost:8000/subgraphs/id/{}/graphql-schema",
+            *USERS
+        );
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri.as_str())
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response
+            .headers()
+            .get(http::header::ETAG)
+            .expect("response has an ETag")
+            .clone();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8(body.to_vec())
+            .unwrap()
+            .contains("name: String"));
+
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri.as_str())
+            .header(http::header::IF_NONE_MATCH, etag.clone())
+            .body(Body::empty())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers().get(http::header::ETAG), Some(&etag));
+    }
+
+    fn batch_request(operations: usize) -> Request<Body> {
+        let operations = vec!["{\"query\": \"{ name }\"}"; operations];
+        Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", *USERS))
+            .body(Body::from(format!("[{}]", operations.join(","))))
+            .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn posting_batches_yields_array_of_results() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let node_id = NodeId::new("test").unwrap();
+        let mut service =
+            GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, Some(10));
+
+        let response = service.call(batch_request(5)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let results = json.as_array().expect("batch response is an array");
+        assert_eq!(5, results.len());
+        for result in results {
+            assert_eq!(result["data"]["name"], "Jordi");
+        }
+
+        let response = service.call(batch_request(50)).await.unwrap();
+        let errors = test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, false);
+        assert_eq!(
+            errors[0].as_str().unwrap(),
+            "GraphQL server error (client error): The batch contains 50 operations, \
+             but at most 10 are allowed"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn posting_batches_fails_when_batching_is_disabled() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let node_id = NodeId::new("test").unwrap();
+        let mut service = GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, None);
+
+        let response = service.call(batch_request(1)).await.unwrap();
+        test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, false);
+    }
+
+    #[test]
+    fn parses_deadlines() {
+        let now = UNIX_EPOCH + Duration::from_millis(super::RELATIVE_DEADLINE_MAX + 10_000);
+
+        // Relative deadlines
+        assert_eq!(
+            Duration::from_millis(100),
+            parse_deadline("100", now).unwrap()
+        );
+        assert_eq!(Duration::from_millis(0), parse_deadline("0", now).unwrap());
+
+        // Absolute deadlines, in the future and in the past
+        let future = (super::RELATIVE_DEADLINE_MAX + 10_250).to_string();
+        assert_eq!(
+            Duration::from_millis(250),
+            parse_deadline(&future, now).unwrap()
+        );
+        let past = (super::RELATIVE_DEADLINE_MAX + 1).to_string();
+        assert_eq!(
+            Duration::from_millis(0),
+            parse_deadline(&past, now).unwrap()
+        );
+
+        assert!(parse_deadline("soon", now).is_err());
+        assert!(parse_deadline("-5", now).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expired_deadline_yields_timeout() {
+        let logger = Logger::root(slog::Discard, o!());
+        let metrics_registry = Arc::new(MockMetricsRegistry::new());
+        let metrics = Arc::new(GraphQLServiceMetrics::new(metrics_registry));
+        let graphql_runner = Arc::new(TestGraphQlRunner);
+
+        let node_id = NodeId::new("test").unwrap();
+        let mut service =
+            GraphQLService::new(logger, metrics, graphql_runner, 8001, node_id, Some(10));
+
+        // A deadline of 0ms has already passed when the query would start
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", *USERS))
+            .header("x-graph-deadline", "0")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        let errors = test_utils::assert_error_response(response, StatusCode::OK, true);
+        assert_eq!(errors[0]["message"], "Query timed out");
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost:8000/subgraphs/id/{}", *USERS))
+            .header("x-graph-deadline", "tomorrow")
+            .body(Body::from("{\"query\": \"{ name }\"}"))
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, false);
+    }
 }
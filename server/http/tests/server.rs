@@ -32,7 +32,7 @@ impl GraphQlRunner for TestGraphQlRunner {
     }
 
     async fn run_query(self: Arc<Self>, query: Query, _target: QueryTarget) -> QueryResults {
-        if query.variables.is_some()
+        let data = if query.variables.is_some()
             && query
                 .variables
                 .as_ref()
@@ -57,8 +57,14 @@ impl GraphQlRunner for TestGraphQlRunner {
                 )]
                 .into_iter(),
             )
-        }
-        .into()
+        };
+        let mut result = QueryResult::from(data);
+        result.deployment = Some(DeploymentHash::new("users").unwrap());
+        // Pretend the result came from the cache for an earlier block
+        result.block = Some(7);
+        let mut results = QueryResults::from(result);
+        results.set_latest_block(10);
+        results
     }
 
     async fn run_subscription(
@@ -69,6 +75,21 @@ impl GraphQlRunner for TestGraphQlRunner {
         unreachable!();
     }
 
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<u64, SubscriptionError> {
+        unreachable!();
+    }
+
+    async fn api_schema(
+        self: Arc<Self>,
+        _target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        unimplemented!()
+    }
+
     fn load_manager(&self) -> Arc<LoadManager> {
         unimplemented!()
     }
@@ -94,7 +115,7 @@ mod test {
                 let id = USERS.clone();
                 let query_runner = Arc::new(TestGraphQlRunner);
                 let node_id = NodeId::new("test").unwrap();
-                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+                let mut server = HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id, Some(10));
                 let http_server = server
                     .serve(8007, 8008)
                     .expect("Failed to start GraphQL server");
@@ -116,9 +137,9 @@ mod test {
                     })
                     .map_ok(|response| {
                         let errors =
-                            test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, false);
+                            test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, true);
 
-                        let message = errors[0]
+                        let message = errors[0]["message"]
                             .as_str()
                             .expect("Error message is not a string");
                         assert_eq!(message, "GraphQL server error (client error): The \"query\" field is missing in request data");
@@ -136,8 +157,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                node_id,
+                Some(10),
+            );
             let http_server = server
                 .serve(8002, 8003)
                 .expect("Failed to start GraphQL server");
@@ -218,8 +244,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                node_id,
+                Some(10),
+            );
             let http_server = server
                 .serve(8003, 8004)
                 .expect("Failed to start GraphQL server");
@@ -240,6 +271,21 @@ mod test {
                     client.request(request)
                 })
                 .map_ok(|response| {
+                    // The response says which deployment answered the query
+                    assert_eq!(
+                        response.headers().get("x-graph-deployment").unwrap(),
+                        "users"
+                    );
+                    // It also says how current the data is
+                    assert_eq!(response.headers().get("x-graph-block").unwrap(), "7");
+                    assert_eq!(
+                        response.headers().get("x-graph-latest-block").unwrap(),
+                        "10"
+                    );
+                    assert_eq!(
+                        response.headers().get("x-graph-blocks-behind").unwrap(),
+                        "3"
+                    );
                     let data = test_utils::assert_successful_response(response);
 
                     // The JSON response should match the simulated query result
@@ -265,8 +311,13 @@ mod test {
             let id = USERS.clone();
             let query_runner = Arc::new(TestGraphQlRunner);
             let node_id = NodeId::new("test").unwrap();
-            let mut server =
-                HyperGraphQLServer::new(&logger_factory, metrics_registry, query_runner, node_id);
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                node_id,
+                Some(10),
+            );
             let http_server = server
                 .serve(8005, 8006)
                 .expect("Failed to start GraphQL server");
@@ -314,4 +365,85 @@ mod test {
                 .unwrap()
         });
     }
+
+    #[test]
+    fn rejects_oversized_and_non_json_bodies() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let logger = Logger::root(slog::Discard, o!());
+            let logger_factory = LoggerFactory::new(logger, None);
+            let metrics_registry = Arc::new(MockMetricsRegistry::new());
+            let query_runner = Arc::new(TestGraphQlRunner);
+            let node_id = NodeId::new("test").unwrap();
+            let mut server = HyperGraphQLServer::new(
+                &logger_factory,
+                metrics_registry,
+                query_runner,
+                node_id,
+                Some(10),
+            );
+            let http_server = server
+                .serve(8009, 8010)
+                .expect("Failed to start GraphQL server");
+
+            tokio::spawn(http_server.fuse().compat());
+            // Give some time for the server to start.
+            sleep(Duration::from_secs(2)).await;
+
+            let client = Client::new();
+            let url = format!("http://localhost:8009/subgraphs/id/{}", *USERS);
+            let post = |content_type: &str, body: Body| {
+                Request::post(&url)
+                    .header("Content-Type", content_type)
+                    .body(body)
+                    .unwrap()
+            };
+            let message = |errors: Vec<serde_json::Value>| {
+                errors[0]["message"]
+                    .as_str()
+                    .expect("Error message is not a string")
+                    .to_string()
+            };
+
+            // Bodies over the default limit of 1MiB are rejected
+            let query = format!(
+                "{{\"query\": \"{{ name }}\", \"padding\": \"{}\"}}",
+                "x".repeat(1024 * 1024)
+            );
+            let response = client
+                .request(post("application/json", Body::from(query)))
+                .await
+                .unwrap();
+            let errors =
+                test_utils::assert_error_response(response, StatusCode::PAYLOAD_TOO_LARGE, true);
+            assert!(message(errors).contains("larger than 1048576 bytes"));
+
+            let response = client
+                .request(post("text/plain", Body::from("{\"query\": \"{ name }\"}")))
+                .await
+                .unwrap();
+            let errors = test_utils::assert_error_response(
+                response,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                true,
+            );
+            assert!(message(errors).contains("`text/plain`"));
+
+            let response = client
+                .request(post("application/json", Body::from("{\"query\": ")))
+                .await
+                .unwrap();
+            test_utils::assert_error_response(response, StatusCode::BAD_REQUEST, true);
+
+            // The content type may have parameters
+            let response = client
+                .request(post(
+                    "application/json; charset=utf-8",
+                    Body::from("{\"query\": \"{ name }\"}"),
+                ))
+                .await
+                .unwrap();
+            test_utils::assert_successful_response(response);
+        });
+    }
 }
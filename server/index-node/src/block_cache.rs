@@ -0,0 +1,147 @@
+//! Serve the blocks in our block cache to sibling graph-node instances so
+//! that several nodes indexing the same chain don't all have to get the
+//! same blocks from the Ethereum node. Only callers that present the
+//! shared token configured with `--block-cache-token` get blocks
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use http::{Response, StatusCode};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::Body;
+
+use graph::{
+    components::{server::query::GraphQLServerError, store::ChainStore},
+    prelude::{serde_json, warn, web3::types::H256, LightEthereumBlockExt, Logger},
+};
+
+pub struct BlockCache {
+    token: String,
+    chain_stores: HashMap<String, Arc<dyn ChainStore>>,
+}
+
+impl BlockCache {
+    /// Serve the blocks in `chain_stores` to callers that know `token`
+    pub fn new(token: String, chain_stores: HashMap<String, Arc<dyn ChainStore>>) -> Self {
+        BlockCache {
+            token,
+            chain_stores,
+        }
+    }
+
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        match authorization.and_then(|auth| auth.strip_prefix("Bearer ")) {
+            // Compare in constant time so the response time does not give
+            // away how much of the token a caller got right
+            Some(token) => {
+                token.len() == self.token.len()
+                    && token
+                        .bytes()
+                        .zip(self.token.bytes())
+                        .fold(0, |acc, (a, b)| acc | (a ^ b))
+                        == 0
+            }
+            None => false,
+        }
+    }
+
+    fn response(status: StatusCode, body: String, content_type: &str) -> Response<Body> {
+        Response::builder()
+            .status(status)
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    /// Handle `GET /block-cache/<network>/<hash>`. Respond with the block
+    /// as JSON, or with `404 Not Found` if the block is not in our cache.
+    /// If we have the uncles of the block, they are in `uncleHeaders`
+    pub fn handle(
+        &self,
+        logger: &Logger,
+        authorization: Option<&str>,
+        network: &str,
+        hash: &str,
+    ) -> Result<Response<Body>, GraphQLServerError> {
+        if !self.authorized(authorization) {
+            return Ok(Self::response(
+                StatusCode::UNAUTHORIZED,
+                "Unauthorized\n".to_string(),
+                "text/plain",
+            ));
+        }
+
+        let hash = match hash.trim_start_matches("0x").parse::<H256>() {
+            Ok(hash) => hash,
+            Err(_) => {
+                return Ok(Self::response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid block hash `{}`\n", hash),
+                    "text/plain",
+                ))
+            }
+        };
+
+        let chain_store = match self.chain_stores.get(network) {
+            Some(chain_store) => chain_store,
+            None => {
+                return Ok(Self::response(
+                    StatusCode::NOT_FOUND,
+                    format!("Unknown network `{}`\n", network),
+                    "text/plain",
+                ))
+            }
+        };
+
+        let block = chain_store.blocks(vec![hash]).map_err(|e| {
+            warn!(logger, "Failed to read block from block cache";
+                  "network" => network,
+                  "hash" => format!("{:x}", hash),
+                  "error" => e.to_string());
+            GraphQLServerError::InternalError(e.to_string())
+        })?;
+        // Blocks that were cached before we kept the types of transactions
+        // are of no use to peers, who would have to fetch them again
+        match block
+            .into_iter()
+            .find(|block| block.has_transaction_types())
+        {
+            Some(block) => {
+                let mut block = serde_json::to_value(&block)
+                    .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+                // Peers that do not know about uncles ignore the extra field
+                let uncles = chain_store
+                    .uncles(hash)
+                    .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+                if let (Some(uncles), Some(block)) = (uncles, block.as_object_mut()) {
+                    let uncles = serde_json::to_value(uncles)
+                        .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+                    block.insert("uncleHeaders".to_string(), uncles);
+                }
+                let body = serde_json::to_string(&block)
+                    .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+                Ok(Self::response(StatusCode::OK, body, "application/json"))
+            }
+            None => Ok(Self::response(
+                StatusCode::NOT_FOUND,
+                "Block not in cache\n".to_string(),
+                "text/plain",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn authorization() {
+        let cache = BlockCache::new("s3cret".to_string(), HashMap::new());
+
+        assert!(cache.authorized(Some("Bearer s3cret")));
+        assert!(!cache.authorized(Some("Bearer s3cre")));
+        assert!(!cache.authorized(Some("Bearer s3creT")));
+        assert!(!cache.authorized(Some("s3cret")));
+        assert!(!cache.authorized(None));
+    }
+}
@@ -1,3 +1,4 @@
+mod block_cache;
 mod explorer;
 mod request;
 mod resolver;
@@ -5,6 +6,7 @@ mod schema;
 mod server;
 mod service;
 
+pub use self::block_cache::BlockCache;
 pub use self::request::IndexNodeRequest;
 pub use self::server::IndexNodeServer;
 pub use self::service::{IndexNodeService, IndexNodeServiceResponse};
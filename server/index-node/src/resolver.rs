@@ -2,23 +2,62 @@ use either::Either;
 use graph::blockchain::BlockchainKind;
 use std::collections::{BTreeMap, HashMap};
 
+use graph::components::subgraph::{SyncedCriterion, SYNCED_CRITERION};
 use graph::data::subgraph::features::detect_features;
-use graph::data::subgraph::{status, MAX_SPEC_VERSION};
+use graph::data::subgraph::schema::SubgraphHealth;
+use graph::data::subgraph::{status, LabelSelector, MAX_SPEC_VERSION};
 use graph::prelude::*;
 use graph::{
     components::store::StatusStore,
     data::graphql::{IntoValue, ObjectOrInterface, ValueMap},
+    object,
 };
+use graph_chain_ethereum::ProviderStatuses;
 use graph_graphql::prelude::{ExecutionContext, Resolver};
 use std::convert::TryInto;
 use web3::types::{Address, H256};
 
+/// The most statuses that one page of `indexingStatuses` can contain
+const MAX_STATUS_PAGE_SIZE: u32 = 1000;
+
+/// Get the optional argument `name`. Arguments come from users, so a value
+/// that does not parse is an error in the query and not a reason to panic
+fn optional_argument<T: TryFromValue>(
+    arguments: &HashMap<&str, q::Value>,
+    name: &str,
+) -> Result<Option<T>, QueryExecutionError> {
+    arguments
+        .get_optional::<T>(name)
+        .map_err(|e| QueryExecutionError::ValueParseError(name.to_string(), e.to_string()))
+}
+
+/// The `first` argument of `indexingStatuses`, which must be between 1 and
+/// `MAX_STATUS_PAGE_SIZE`
+fn page_size(arguments: &HashMap<&str, q::Value>) -> Result<usize, QueryExecutionError> {
+    match arguments.get("first") {
+        Some(q::Value::Int(n)) => match n.as_i64() {
+            Some(n) if n > 0 && n <= MAX_STATUS_PAGE_SIZE as i64 => Ok(n as usize),
+            n => Err(QueryExecutionError::RangeArgumentsError(
+                "first",
+                MAX_STATUS_PAGE_SIZE,
+                n.unwrap_or(i64::MAX),
+            )),
+        },
+        Some(q::Value::Null) | None => Ok(100),
+        Some(value) => Err(QueryExecutionError::ValueParseError(
+            "first".to_string(),
+            value.to_string(),
+        )),
+    }
+}
+
 /// Resolver for the index node GraphQL API.
 pub struct IndexNodeResolver<S, R, St> {
     logger: Logger,
     store: Arc<S>,
     link_resolver: Arc<R>,
     subgraph_store: Arc<St>,
+    provider_statuses: Arc<ProviderStatuses>,
 }
 
 impl<S, R, St> IndexNodeResolver<S, R, St>
@@ -32,6 +71,7 @@ where
         store: Arc<S>,
         link_resolver: Arc<R>,
         subgraph_store: Arc<St>,
+        provider_statuses: Arc<ProviderStatuses>,
     ) -> Self {
         let logger = logger.new(o!("component" => "IndexNodeResolver"));
         Self {
@@ -39,6 +79,7 @@ where
             store,
             link_resolver,
             subgraph_store,
+            provider_statuses,
         }
     }
 
@@ -60,12 +101,211 @@ where
             })
             .unwrap_or_else(|| Vec::new());
 
-        let infos = self
-            .store
-            .status(status::Filter::Deployments(deployments))?;
+        // Only filter, sort and page in the store when asked to so that
+        // existing clients keep getting all statuses
+        const QUERY_ARGUMENTS: &[&str] = &[
+            "health",
+            "synced",
+            "network",
+            "subgraphNamePrefix",
+            "node",
+            "labels",
+            "orderBy",
+            "orderDirection",
+            "first",
+            "after",
+        ];
+        let has_query = QUERY_ARGUMENTS
+            .iter()
+            .any(|arg| !matches!(arguments.get(arg), None | Some(q::Value::Null)));
+        if !has_query {
+            let infos = self
+                .store
+                .status(status::Filter::Deployments(deployments))?;
+            return Ok(infos.into_value());
+        }
+
+        let query = Self::status_query(deployments, arguments)?;
+        let infos = self.store.indexing_statuses(query)?;
         Ok(infos.into_value())
     }
 
+    fn status_query(
+        deployments: Vec<String>,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<status::StatusQuery, QueryExecutionError> {
+        let node = optional_argument::<String>(arguments, "node")?
+            .map(|node| {
+                NodeId::new(node.clone())
+                    .map_err(|_| QueryExecutionError::ValueParseError("NodeId".to_string(), node))
+            })
+            .transpose()?;
+        let labels = optional_argument::<Vec<String>>(arguments, "labels")?
+            .unwrap_or_default()
+            .iter()
+            .map(|selector| {
+                LabelSelector::parse(selector)
+                    .map_err(|e| QueryExecutionError::ValueParseError("labels".to_string(), e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let order = optional_argument::<String>(arguments, "orderBy")?
+            .map(|order| {
+                order
+                    .parse::<status::StatusOrder>()
+                    .map_err(|e| QueryExecutionError::ValueParseError("orderBy".to_string(), e))
+            })
+            .transpose()?
+            .unwrap_or(status::StatusOrder::Id);
+        let descending = optional_argument::<String>(arguments, "orderDirection")?
+            .map(|dir| dir == "desc")
+            .unwrap_or(false);
+        let after = optional_argument::<String>(arguments, "after")?
+            .map(|after| {
+                status::StatusCursor::parse(&after, order)
+                    .map_err(|e| QueryExecutionError::ValueParseError("after".to_string(), e))
+            })
+            .transpose()?;
+        let first = page_size(arguments)?;
+
+        Ok(status::StatusQuery {
+            deployments,
+            health: optional_argument::<SubgraphHealth>(arguments, "health")?,
+            synced: optional_argument::<bool>(arguments, "synced")?,
+            network: optional_argument::<String>(arguments, "network")?,
+            name_prefix: optional_argument::<String>(arguments, "subgraphNamePrefix")?,
+            node,
+            labels,
+            order,
+            descending,
+            first,
+            after,
+        })
+    }
+
+    fn resolve_cluster_indexing_statuses(
+        &self,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let health = arguments
+            .get_optional::<SubgraphHealth>("health")
+            .expect("Invalid health");
+        let node = arguments
+            .get_optional::<String>("node")
+            .expect("Invalid node")
+            .map(|node| {
+                NodeId::new(node.clone())
+                    .map_err(|_| QueryExecutionError::ValueParseError("NodeId".to_string(), node))
+            })
+            .transpose()?;
+        let first = arguments
+            .get_optional::<u64>("first")
+            .expect("Invalid first")
+            .unwrap_or(100) as usize;
+        let skip = arguments
+            .get_optional::<u64>("skip")
+            .expect("Invalid skip")
+            .unwrap_or(0) as usize;
+
+        let infos = self.store.cluster_status(status::ClusterFilter {
+            health,
+            node,
+            skip,
+            first,
+        })?;
+        Ok(infos.into_value())
+    }
+
+    fn resolve_admin_audit_log(
+        &self,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let first = arguments
+            .get_optional::<u64>("first")
+            .expect("Invalid first")
+            .unwrap_or(100) as usize;
+        let after = arguments
+            .get_optional::<String>("after")
+            .expect("Invalid after")
+            .map(|after| {
+                after.parse::<i64>().map_err(|e| {
+                    QueryExecutionError::ValueParseError("after".to_string(), e.to_string())
+                })
+            })
+            .transpose()?;
+        let method = arguments
+            .get_optional::<String>("method")
+            .expect("Invalid method");
+
+        let entries = self.store.admin_audit_log(first, after, method)?;
+        Ok(entries.into_value())
+    }
+
+    fn resolve_version_switches(
+        &self,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        // We can safely unwrap because the argument is non-nullable and has been validated.
+        let subgraph_name = arguments.get_required::<String>("subgraphName").unwrap();
+        let first = arguments
+            .get_optional::<u64>("first")
+            .expect("Invalid first")
+            .unwrap_or(100) as usize;
+
+        let switches = self.store.version_switches(&subgraph_name, first)?;
+        Ok(switches.into_value())
+    }
+
+    fn resolve_deployment_profile(
+        &self,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        // We can safely unwrap because the argument is non-nullable and has been validated.
+        let deployment = arguments.get_required::<String>("deployment").unwrap();
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+
+        let profile = self.store.deployment_profile(&deployment)?;
+        Ok(profile.into_value())
+    }
+
+    fn resolve_composite_indexes(
+        &self,
+        arguments: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        // We can safely unwrap because the argument is non-nullable and has been validated.
+        let deployment = arguments.get_required::<String>("deployment").unwrap();
+        let deployment = DeploymentHash::new(deployment)
+            .map_err(QueryExecutionError::SubgraphDeploymentIdError)?;
+
+        let indexes = self.store.composite_indexes(&deployment)?;
+        Ok(indexes.into_value())
+    }
+
+    fn resolve_rpc_failures(&self, arguments: &HashMap<&str, q::Value>) -> q::Value {
+        let endpoint = arguments
+            .get_optional::<String>("endpoint")
+            .expect("Invalid endpoint");
+        graph_chain_ethereum::failed_requests(endpoint.as_deref()).into_value()
+    }
+
+    fn resolve_provider_statuses(&self, arguments: &HashMap<&str, q::Value>) -> q::Value {
+        // We can safely unwrap because the argument is non-nullable and has been validated.
+        let network = arguments.get_required::<String>("network").unwrap();
+        graph::block_on(self.provider_statuses.status(&network)).into_value()
+    }
+
+    fn resolve_synced_criterion(&self) -> q::Value {
+        let SyncedCriterion {
+            head_distance,
+            checks,
+        } = *SYNCED_CRITERION;
+        object! {
+            __typename: "SyncedCriterion",
+            headDistance: head_distance,
+            checks: checks as i32,
+        }
+    }
+
     fn resolve_indexing_statuses_for_subgraph_name(
         &self,
         arguments: &HashMap<&str, q::Value>,
@@ -138,6 +378,28 @@ where
         Ok(poi)
     }
 
+    fn resolve_subgraph_schema_hash(
+        &self,
+        argument_values: &HashMap<&str, q::Value>,
+    ) -> Result<q::Value, QueryExecutionError> {
+        let deployment_id = argument_values
+            .get_required::<DeploymentHash>("subgraphId")
+            .expect("Valid subgraphId required");
+
+        match self.subgraph_store.api_schema(&deployment_id) {
+            Ok(schema) => Ok(q::Value::String(schema.sdl_hash())),
+            Err(e) => {
+                error!(
+                    self.logger,
+                    "Failed to load API schema";
+                    "subgraph" => deployment_id,
+                    "error" => format!("{:?}", e)
+                );
+                Ok(q::Value::Null)
+            }
+        }
+    }
+
     fn resolve_indexing_status_for_version(
         &self,
         arguments: &HashMap<&str, q::Value>,
@@ -307,6 +569,7 @@ where
             store: self.store.clone(),
             link_resolver: self.link_resolver.clone(),
             subgraph_store: self.subgraph_store.clone(),
+            provider_statuses: self.provider_statuses.clone(),
         }
     }
 }
@@ -349,6 +612,13 @@ where
             return self.resolve_proof_of_indexing(argument_values);
         }
 
+        if &parent_object_type.name == "Query"
+            && &field.name == "subgraphSchemaHash"
+            && &scalar_type.name == "String"
+        {
+            return self.resolve_subgraph_schema_hash(argument_values);
+        }
+
         // Fallback to the same as is in the default trait implementation. There
         // is no way to call back into the default implementation for the trait.
         // So, note that this is duplicated.
@@ -375,6 +645,25 @@ where
                 self.resolve_indexing_statuses_for_subgraph_name(arguments)
             }
 
+            // The top-level `clusterIndexingStatuses` field
+            (None, "ClusterIndexingStatus", "clusterIndexingStatuses") => {
+                self.resolve_cluster_indexing_statuses(arguments)
+            }
+
+            // The top-level `adminAuditLog` field
+            (None, "AdminAuditEntry", "adminAuditLog") => self.resolve_admin_audit_log(arguments),
+
+            // The top-level `versionSwitches` field
+            (None, "VersionSwitch", "versionSwitches") => self.resolve_version_switches(arguments),
+
+            // The top-level `rpcFailures` field
+            (None, "RpcFailure", "rpcFailures") => Ok(self.resolve_rpc_failures(arguments)),
+
+            // The top-level `compositeIndexes` field
+            (None, "CompositeIndex", "compositeIndexes") => {
+                self.resolve_composite_indexes(arguments)
+            }
+
             // Resolve fields of `Object` values (e.g. the `chains` field of `ChainIndexingStatus`)
             (value, _, _) => Ok(value.unwrap_or(q::Value::Null)),
         }
@@ -404,8 +693,50 @@ where
                 graph::block_on(self.resolve_subgraph_features(arguments))
             }
 
+            // The top-level `syncedCriterion` field
+            (None, "syncedCriterion") => Ok(self.resolve_synced_criterion()),
+
+            // The top-level `deploymentProfile` field
+            (None, "deploymentProfile") => self.resolve_deployment_profile(arguments),
+
+            // The top-level `providerStatuses` field
+            (None, "providerStatuses") => Ok(self.resolve_provider_statuses(arguments)),
+
             // Resolve fields of `Object` values (e.g. the `latestBlock` field of `EthereumBlock`)
             (value, _) => Ok(value.unwrap_or(q::Value::Null)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_arguments_from_users_do_not_panic() {
+        let first = |n: i32| {
+            let mut arguments = HashMap::new();
+            arguments.insert("first", q::Value::Int(n.into()));
+            page_size(&arguments)
+        };
+        assert_eq!(7, first(7).unwrap());
+        assert_eq!(1000, first(1000).unwrap());
+        assert!(first(-1).is_err());
+        assert!(first(0).is_err());
+        assert!(first(1001).is_err());
+        assert_eq!(100, page_size(&HashMap::new()).unwrap());
+
+        let mut arguments = HashMap::new();
+        arguments.insert("orderBy", q::Value::Int(3.into()));
+        arguments.insert("first", q::Value::String("ten".to_string()));
+        assert!(optional_argument::<String>(&arguments, "orderBy").is_err());
+        assert!(page_size(&arguments).is_err());
+        assert_eq!(
+            None,
+            optional_argument::<String>(&arguments, "after").unwrap()
+        );
+
+        arguments.insert("labels", q::Value::List(vec![q::Value::Int(1.into())]));
+        assert!(optional_argument::<Vec<String>>(&arguments, "labels").is_err());
+    }
+}
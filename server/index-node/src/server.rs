@@ -8,7 +8,9 @@ use graph::{
     prelude::{IndexNodeServer as IndexNodeServerTrait, *},
 };
 
+use crate::block_cache::BlockCache;
 use crate::service::IndexNodeService;
+use graph_chain_ethereum::ProviderStatuses;
 use thiserror::Error;
 
 /// Errors that may occur when starting the server.
@@ -31,6 +33,9 @@ pub struct IndexNodeServer<Q, S, R, St> {
     store: Arc<S>,
     link_resolver: Arc<R>,
     subgraph_store: Arc<St>,
+    block_cache: Option<Arc<BlockCache>>,
+    provider_statuses: Arc<ProviderStatuses>,
+    build_info: Arc<BuildInfo>,
 }
 
 impl<Q, S, R, St> IndexNodeServer<Q, S, R, St> {
@@ -41,6 +46,9 @@ impl<Q, S, R, St> IndexNodeServer<Q, S, R, St> {
         store: Arc<S>,
         link_resolver: Arc<R>,
         subgraph_store: Arc<St>,
+        block_cache: Option<Arc<BlockCache>>,
+        provider_statuses: Arc<ProviderStatuses>,
+        build_info: BuildInfo,
     ) -> Self {
         let logger = logger_factory.component_logger(
             "IndexNodeServer",
@@ -57,6 +65,9 @@ impl<Q, S, R, St> IndexNodeServer<Q, S, R, St> {
             store,
             link_resolver,
             subgraph_store,
+            block_cache,
+            provider_statuses,
+            build_info: Arc::new(build_info),
         }
     }
 }
@@ -94,6 +105,9 @@ where
             store.clone(),
             self.link_resolver.clone(),
             self.subgraph_store.clone(),
+            self.block_cache.clone(),
+            self.provider_statuses.clone(),
+            self.build_info.clone(),
         );
         let new_service =
             make_service_fn(move |_| futures03::future::ok::<_, Error>(service.clone()));
@@ -2,19 +2,26 @@ use http::header::{
     self, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
     CONTENT_TYPE, LOCATION,
 };
+use hyper::body::HttpBody;
 use hyper::service::Service;
 use hyper::{Body, Method, Request, Response, StatusCode};
+use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
 
+use graph::components::server::body::{
+    check_json_content_type, read_body, INDEX_NODE_MAX_BODY_SIZE,
+};
 use graph::{components::server::query::GraphQLServerError, data::query::QueryResults};
 use graph::{components::store::StatusStore, prelude::*};
 use graph_graphql::prelude::{execute_query, Query as PreparedQuery, QueryExecutionOptions};
 
+use crate::block_cache::BlockCache;
 use crate::explorer::Explorer;
 use crate::request::IndexNodeRequest;
 use crate::resolver::IndexNodeResolver;
 use crate::schema::SCHEMA;
+use graph_chain_ethereum::ProviderStatuses;
 
 /// An asynchronous response to a GraphQL request.
 pub type IndexNodeServiceResponse = DynTryFuture<'static, Response<Body>, GraphQLServerError>;
@@ -28,6 +35,9 @@ pub struct IndexNodeService<Q, S, R, St> {
     explorer: Arc<Explorer<S>>,
     link_resolver: Arc<R>,
     subgraph_store: Arc<St>,
+    block_cache: Option<Arc<BlockCache>>,
+    provider_statuses: Arc<ProviderStatuses>,
+    build_info: Arc<BuildInfo>,
 }
 
 impl<Q, S, R, St> Clone for IndexNodeService<Q, S, R, St> {
@@ -39,6 +49,9 @@ impl<Q, S, R, St> Clone for IndexNodeService<Q, S, R, St> {
             explorer: self.explorer.clone(),
             link_resolver: self.link_resolver.clone(),
             subgraph_store: self.subgraph_store.clone(),
+            block_cache: self.block_cache.clone(),
+            provider_statuses: self.provider_statuses.clone(),
+            build_info: self.build_info.clone(),
         }
     }
 }
@@ -59,6 +72,9 @@ where
         store: Arc<S>,
         link_resolver: Arc<R>,
         subgraph_store: Arc<St>,
+        block_cache: Option<Arc<BlockCache>>,
+        provider_statuses: Arc<ProviderStatuses>,
+        build_info: Arc<BuildInfo>,
     ) -> Self {
         let explorer = Arc::new(Explorer::new(store.clone()));
 
@@ -69,6 +85,9 @@ where
             explorer,
             link_resolver,
             subgraph_store,
+            block_cache,
+            provider_statuses,
+            build_info,
         }
     }
 
@@ -95,22 +114,41 @@ where
             .unwrap()
     }
 
+    /// Serve the build information as JSON
+    fn handle_version(&self) -> Result<Response<Body>, GraphQLServerError> {
+        let body = serde_json::to_string(self.build_info.as_ref())
+            .map_err(|e| GraphQLServerError::InternalError(e.to_string()))?;
+        Ok(Response::builder()
+            .status(200)
+            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap())
+    }
+
     fn handle_graphiql() -> Response<Body> {
         Self::serve_file(Self::graphiql_html(), "text/html")
     }
 
     async fn handle_graphql_query(
         &self,
-        request_body: Body,
+        request: Request<Body>,
     ) -> Result<Response<Body>, GraphQLServerError> {
         let store = self.store.clone();
 
         // Obtain the schema for the index node GraphQL API
         let schema = SCHEMA.clone();
 
-        let body = hyper::body::to_bytes(request_body)
-            .map_err(|_| GraphQLServerError::InternalError("Failed to read request body".into()))
-            .await?;
+        let content_type = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .map(|value| value.to_str().unwrap_or(""));
+        check_json_content_type(content_type)?;
+        let mut body = request.into_body();
+        let content_length = body.size_hint().exact();
+        let chunks = futures03::stream::poll_fn(move |cx| Pin::new(&mut body).poll_data(cx));
+        let body = read_body(chunks, content_length, *INDEX_NODE_MAX_BODY_SIZE).await?;
+        let body = hyper::body::Bytes::from(body);
 
         let query = IndexNodeRequest::new(body).compat().await?;
         let query = match PreparedQuery::new(&self.logger, schema, None, query, None, 100) {
@@ -130,6 +168,7 @@ where
                     store,
                     self.link_resolver.clone(),
                     self.subgraph_store.clone(),
+                    self.provider_statuses.clone(),
                 ),
                 deadline: None,
                 max_first: std::u32::MAX,
@@ -216,11 +255,25 @@ where
             }
             (Method::GET, ["graphql", "playground"]) => Ok(Self::handle_graphiql()),
 
-            (Method::POST, ["graphql"]) => self.handle_graphql_query(req.into_body()).await,
+            (Method::POST, ["graphql"]) => self.handle_graphql_query(req).await,
             (Method::OPTIONS, ["graphql"]) => Ok(Self::handle_graphql_options(req)),
 
+            (Method::GET, ["version"]) => self.handle_version(),
+
             (Method::GET, ["explorer", rest @ ..]) => self.explorer.handle(&self.logger, rest),
 
+            (Method::GET, ["block-cache", network, hash]) => match &self.block_cache {
+                Some(block_cache) => block_cache.handle(
+                    &self.logger,
+                    req.headers()
+                        .get(header::AUTHORIZATION)
+                        .and_then(|value| value.to_str().ok()),
+                    network,
+                    hash,
+                ),
+                None => Ok(Self::handle_not_found()),
+            },
+
             _ => Ok(Self::handle_not_found()),
         }
     }
@@ -251,34 +304,18 @@ where
                 .handle_call(req)
                 .map(move |result| match result {
                     Ok(response) => Ok(response),
-                    Err(err @ GraphQLServerError::ClientError(_)) => {
-                        debug!(logger, "IndexNodeService call failed: {}", err);
-
-                        Ok(Response::builder()
-                            .status(400)
-                            .header(CONTENT_TYPE, "text/plain")
-                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                            .body(Body::from(format!("Invalid request: {}", err)))
-                            .unwrap())
-                    }
-                    Err(err @ GraphQLServerError::QueryError(_)) => {
-                        error!(logger, "IndexNodeService call failed: {}", err);
-
-                        Ok(Response::builder()
-                            .status(400)
-                            .header(CONTENT_TYPE, "text/plain")
-                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                            .body(Body::from(format!("Query error: {}", err)))
-                            .unwrap())
-                    }
-                    Err(err @ GraphQLServerError::InternalError(_)) => {
-                        error!(logger, "IndexNodeService call failed: {}", err);
+                    Err(err) => {
+                        if err.is_client_error() {
+                            debug!(logger, "IndexNodeService call failed: {}", err);
+                        } else {
+                            error!(logger, "IndexNodeService call failed: {}", err);
+                        }
 
                         Ok(Response::builder()
-                            .status(500)
-                            .header(CONTENT_TYPE, "text/plain")
+                            .status(err.status_code())
+                            .header(CONTENT_TYPE, "application/json")
                             .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                            .body(Body::from(format!("Internal server error: {}", err)))
+                            .body(Body::from(err.as_graphql_body()))
                             .unwrap())
                     }
                 }),
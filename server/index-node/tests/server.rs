@@ -0,0 +1,137 @@
+use http::StatusCode;
+use hyper::service::Service;
+use hyper::{Body, Request};
+
+use graph::components::server::body::INDEX_NODE_MAX_BODY_SIZE;
+use graph::components::server::version::BuildInfo;
+use graph::data::{
+    graphql::effort::LoadManager,
+    query::{QueryResults, QueryTarget},
+};
+use graph::ipfs_client::IpfsClient;
+use graph::prelude::*;
+use graph_chain_ethereum::{EthereumNetworks, ProviderStatuses};
+use graph_core::LinkResolver;
+use graph_mock::MockStore;
+use graph_server_index_node::IndexNodeService;
+
+/// A query runner for requests that never get as far as running a query
+pub struct TestGraphQlRunner;
+
+#[async_trait]
+impl GraphQlRunner for TestGraphQlRunner {
+    async fn run_query_with_complexity(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _complexity: Option<u64>,
+        _max_depth: Option<u8>,
+        _max_first: Option<u32>,
+        _max_skip: Option<u32>,
+    ) -> QueryResults {
+        unimplemented!()
+    }
+
+    async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+        unimplemented!()
+    }
+
+    async fn run_subscription(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<SubscriptionResult, SubscriptionError> {
+        unimplemented!()
+    }
+
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<u64, SubscriptionError> {
+        unimplemented!()
+    }
+
+    async fn api_schema(
+        self: Arc<Self>,
+        _target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        unimplemented!()
+    }
+
+    fn load_manager(&self) -> Arc<LoadManager> {
+        unimplemented!()
+    }
+}
+
+fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: "0.0.0".to_string(),
+        testament: "0.0.0".to_string(),
+        commit: None,
+        commit_date: None,
+        tag: None,
+        distance: None,
+        dirty: false,
+        rustc: "rustc".to_string(),
+        profile: "debug".to_string(),
+        features: vec![],
+        max_spec_version: "0.0.4".to_string(),
+        max_api_version: "0.0.6".to_string(),
+    }
+}
+
+/// Post `body` to the GraphQL endpoint and return the status and the
+/// message of the error in the response
+async fn post(content_type: &str, body: String) -> (StatusCode, String) {
+    let store = Arc::new(MockStore::new());
+    let mut service = IndexNodeService::new(
+        Logger::root(slog::Discard, o!()),
+        Arc::new(TestGraphQlRunner),
+        store.clone(),
+        Arc::new(LinkResolver::from(IpfsClient::localhost())),
+        store,
+        None,
+        Arc::new(ProviderStatuses::new(
+            &EthereumNetworks::new(),
+            Default::default(),
+        )),
+        Arc::new(build_info()),
+    );
+    let request = Request::post("/graphql")
+        .header("Content-Type", content_type)
+        .body(Body::from(body))
+        .unwrap();
+    let response = service.call(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let message = body["errors"][0]["message"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    (status, message)
+}
+
+#[tokio::test]
+async fn rejects_oversized_and_non_json_bodies() {
+    // Pad a request that the service rejects once it has read it to the
+    // size of the limit
+    let request = "{\"query\": 1}";
+    let padded = |size: usize| format!("{}{}", request, " ".repeat(size - request.len()));
+
+    let (status, message) = post("application/json", padded(*INDEX_NODE_MAX_BODY_SIZE)).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+    assert!(message.contains("is not a string"));
+
+    let (status, message) = post("application/json", padded(*INDEX_NODE_MAX_BODY_SIZE + 1)).await;
+    assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, status);
+    assert!(message.contains(&format!("larger than {} bytes", *INDEX_NODE_MAX_BODY_SIZE)));
+
+    let (status, message) = post("text/plain", "{\"query\": \"{ version }\"}".to_string()).await;
+    assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, status);
+    assert!(message.contains("`text/plain`"));
+
+    let (status, _) = post("application/json", "{\"query\": ".to_string()).await;
+    assert_eq!(StatusCode::BAD_REQUEST, status);
+}
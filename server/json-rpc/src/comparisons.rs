@@ -0,0 +1,163 @@
+//! Comparisons of the current and pending versions of subgraphs that run
+//! in the background. Comparing large deployments can take much longer
+//! than a client is willing to wait for a response, so
+//! `subgraph_compare_versions` only starts a comparison and returns the id
+//! of its job, and clients poll `subgraph_comparison_status` with that id
+//! until the comparison is done. Jobs only live in the memory of the node
+//! that runs them, and only the most recent ones are kept
+use graph::components::store::VersionComparison;
+use graph::data::store::scalar::Timestamp;
+use graph::prelude::chrono::Utc;
+use graph::prelude::{BlockNumber, Serialize, SubgraphName};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// The most jobs that we remember; once there are more, the oldest jobs
+/// that are not running anymore are forgotten
+pub(crate) const MAX_COMPARISON_JOBS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum JobStatus {
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ComparisonJob {
+    pub id: u64,
+    pub name: String,
+    pub block: BlockNumber,
+    pub status: JobStatus,
+    pub started_at: Timestamp,
+    pub finished_at: Option<Timestamp>,
+    pub result: Option<VersionComparison>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct ComparisonJobs {
+    /// The jobs by id; ids are handed out in increasing order so that the
+    /// first entries are the oldest jobs
+    jobs: Mutex<(u64, BTreeMap<u64, ComparisonJob>)>,
+}
+
+impl ComparisonJobs {
+    /// Record that a comparison for the subgraph `name` at `block` is
+    /// starting and return the id of its job
+    pub fn start(&self, name: &SubgraphName, block: BlockNumber) -> u64 {
+        let mut guard = self.jobs.lock().unwrap();
+        let (next_id, jobs) = &mut *guard;
+        *next_id += 1;
+        let id = *next_id;
+        jobs.insert(
+            id,
+            ComparisonJob {
+                id,
+                name: name.to_string(),
+                block,
+                status: JobStatus::Running,
+                started_at: Timestamp::from(Utc::now()),
+                finished_at: None,
+                result: None,
+                error: None,
+            },
+        );
+
+        let excess = jobs.len().saturating_sub(MAX_COMPARISON_JOBS);
+        if excess > 0 {
+            let finished: Vec<_> = jobs
+                .values()
+                .filter(|job| job.status != JobStatus::Running)
+                .map(|job| job.id)
+                .take(excess)
+                .collect();
+            for id in finished {
+                jobs.remove(&id);
+            }
+        }
+        id
+    }
+
+    /// Record the outcome of the job `id`
+    pub fn finish(&self, id: u64, result: Result<VersionComparison, String>) {
+        let mut guard = self.jobs.lock().unwrap();
+        if let Some(job) = guard.1.get_mut(&id) {
+            job.finished_at = Some(Timestamp::from(Utc::now()));
+            match result {
+                Ok(comparison) => {
+                    job.status = JobStatus::Done;
+                    job.result = Some(comparison);
+                }
+                Err(error) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(error);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<ComparisonJob> {
+        self.jobs.lock().unwrap().1.get(&id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::prelude::DeploymentHash;
+
+    fn comparison() -> VersionComparison {
+        VersionComparison {
+            current: DeploymentHash::new("QmCurrent").unwrap(),
+            pending: DeploymentHash::new("QmPending").unwrap(),
+            block: 10,
+            entity_types: vec![],
+        }
+    }
+
+    #[test]
+    fn tracks_jobs() {
+        let jobs = ComparisonJobs::default();
+        let name = SubgraphName::new("test/compare").unwrap();
+
+        let first = jobs.start(&name, 10);
+        let second = jobs.start(&name, 20);
+        assert_ne!(first, second);
+        assert_eq!(JobStatus::Running, jobs.get(first).unwrap().status);
+
+        jobs.finish(first, Ok(comparison()));
+        let job = jobs.get(first).unwrap();
+        assert_eq!(JobStatus::Done, job.status);
+        assert_eq!(Some(comparison()), job.result);
+        assert!(job.finished_at.is_some());
+
+        jobs.finish(second, Err("no pending version".to_string()));
+        let job = jobs.get(second).unwrap();
+        assert_eq!(JobStatus::Failed, job.status);
+        assert_eq!(Some("no pending version".to_string()), job.error);
+
+        assert!(jobs.get(second + 1).is_none());
+    }
+
+    #[test]
+    fn forgets_old_finished_jobs() {
+        let jobs = ComparisonJobs::default();
+        let name = SubgraphName::new("test/compare").unwrap();
+
+        // The first job keeps running and is never forgotten
+        let running = jobs.start(&name, 1);
+        let finished = jobs.start(&name, 1);
+        jobs.finish(finished, Ok(comparison()));
+        for _ in 0..MAX_COMPARISON_JOBS - 1 {
+            let id = jobs.start(&name, 1);
+            jobs.finish(id, Ok(comparison()));
+        }
+
+        assert!(jobs.get(running).is_some());
+        assert!(jobs.get(finished).is_none());
+        assert_eq!(MAX_COMPARISON_JOBS, jobs.jobs.lock().unwrap().1.len());
+    }
+}
@@ -3,15 +3,25 @@ extern crate jsonrpc_http_server;
 extern crate lazy_static;
 extern crate serde;
 
+mod comparisons;
+
+use graph::components::server::admin::AdminCall;
+use graph::components::server::body::ADMIN_MAX_BODY_SIZE;
+use graph::components::store::AdminAuditStore;
+use graph::data::subgraph::Labels;
 use graph::prelude::futures03::channel::{mpsc, oneshot};
 use graph::prelude::futures03::SinkExt;
 use graph::prelude::serde_json;
 use graph::prelude::{JsonRpcServer as JsonRpcServerTrait, *};
 use jsonrpc_http_server::{
-    jsonrpc_core::{self, Compatibility, IoHandler, Params, Value},
+    hyper,
+    jsonrpc_core::{self, Compatibility, MetaIoHandler, Metadata, Params, Value},
     RestApi, Server, ServerBuilder,
 };
 use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+
+use comparisons::ComparisonJobs;
 
 use std::collections::BTreeMap;
 use std::env;
@@ -29,6 +39,56 @@ const JSON_RPC_DEPLOY_ERROR: i64 = 0;
 const JSON_RPC_REMOVE_ERROR: i64 = 1;
 const JSON_RPC_CREATE_ERROR: i64 = 2;
 const JSON_RPC_REASSIGN_ERROR: i64 = 3;
+const JSON_RPC_ARCHIVE_ERROR: i64 = 4;
+const JSON_RPC_AUDIT_ERROR: i64 = 5;
+const JSON_RPC_MAINTAIN_ERROR: i64 = 6;
+const JSON_RPC_SKIP_BLOCK_ERROR: i64 = 7;
+const JSON_RPC_UNSKIP_BLOCK_ERROR: i64 = 8;
+const JSON_RPC_VALIDATE_FILES_ERROR: i64 = 9;
+const JSON_RPC_PROFILE_ERROR: i64 = 10;
+const JSON_RPC_RELOAD_MAPPINGS_ERROR: i64 = 11;
+const JSON_RPC_RESET_SCAN_TOTALS_ERROR: i64 = 12;
+const JSON_RPC_SET_LABELS_ERROR: i64 = 13;
+const JSON_RPC_GET_LABELS_ERROR: i64 = 14;
+const JSON_RPC_COMPARE_VERSIONS_ERROR: i64 = 15;
+const JSON_RPC_COMPARISON_STATUS_ERROR: i64 = 16;
+
+/// The longest that `subgraph_profile` can profile a deployment for
+const MAX_PROFILE_SECONDS: u64 = 600;
+
+/// How many of the entities of each type that differ between two versions
+/// a comparison lists by default, and at most
+const DEFAULT_COMPARISON_SAMPLE: usize = 10;
+const MAX_COMPARISON_SAMPLE: usize = 1000;
+
+/// Parameters whose name contains any of these are redacted in the audit
+/// log
+const SECRET_PARAMS: &[&str] = &["auth", "key", "password", "secret", "token"];
+
+/// What we know about the HTTP request that a call came in
+#[derive(Clone, Debug, Default)]
+struct RequestMeta {
+    /// The address of the client, taken from the `X-Forwarded-For` or
+    /// `X-Real-IP` header of the request
+    source: Option<String>,
+}
+
+impl Metadata for RequestMeta {}
+
+impl RequestMeta {
+    fn from_request(req: &hyper::Request<hyper::Body>) -> Self {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.split(',').next().unwrap_or("").trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        RequestMeta {
+            source: header("x-forwarded-for").or_else(|| header("x-real-ip")),
+        }
+    }
+}
 
 #[derive(Debug, Deserialize)]
 struct SubgraphCreateParams {
@@ -40,6 +100,10 @@ struct SubgraphDeployParams {
     name: SubgraphName,
     ipfs_hash: DeploymentHash,
     node_id: Option<NodeId>,
+    /// Overrides the node's version switching mode for this deployment
+    version_switching_mode: Option<SubgraphVersionSwitchingMode>,
+    /// Replace the labels of the deployment with these before it is placed
+    labels: Option<Labels>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -53,15 +117,138 @@ struct SubgraphReassignParams {
     node_id: NodeId,
 }
 
+#[derive(Debug, Deserialize)]
+struct SubgraphArchiveParams {
+    ipfs_hash: DeploymentHash,
+    /// Archive entity versions closed more than this many blocks before
+    /// the subgraph head; `null` turns archiving off
+    blocks: Option<BlockNumber>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphSkipBlockParams {
+    deployment: DeploymentHash,
+    /// The hash of the block as `0x` followed by hex digits
+    block_hash: String,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphUnskipBlockParams {
+    deployment: DeploymentHash,
+    block_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphResetScanTotalsParams {
+    deployment: DeploymentHash,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphSetLabelsParams {
+    deployment: DeploymentHash,
+    /// The new labels of the deployment; labels that are not listed are
+    /// removed
+    labels: Labels,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphGetLabelsParams {
+    deployment: DeploymentHash,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphReloadMappingsParams {
+    deployment: DeploymentHash,
+    /// The manifest with the new mappings; the deployment's own manifest
+    /// if this is not given
+    manifest: Option<DeploymentHash>,
+    /// Rewind the deployment to this block before reloading
+    block: Option<BlockNumber>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphCompareVersionsParams {
+    name: SubgraphName,
+    block: BlockNumber,
+    /// How many of the differing entities of each type to list
+    sample: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphComparisonStatusParams {
+    job: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubgraphProfileParams {
+    deployment: DeploymentHash,
+    /// How long to profile the deployment for
+    seconds: u64,
+}
+
 pub struct JsonRpcServer<R> {
     registrar: Arc<R>,
+    audit: Arc<dyn AdminAuditStore>,
+    calls: Box<CounterVec>,
+    comparisons: Arc<ComparisonJobs>,
     http_port: u16,
     ws_port: u16,
     node_id: NodeId,
+    build_info: BuildInfo,
     logger: Logger,
 }
 
 impl<R: SubgraphRegistrar> JsonRpcServer<R> {
+    /// Parse `params` and run `handler` with them, recording the call in
+    /// the audit log. The call is recorded before `handler` runs, and its
+    /// outcome once `handler` has finished, so that the audit log never
+    /// shows success for an operation that did not complete. Calls that
+    /// can not be recorded are refused
+    async fn audited<P, F, Fut>(
+        self: Arc<Self>,
+        method: &'static str,
+        params: Params,
+        meta: RequestMeta,
+        handler: F,
+    ) -> Result<Value, jsonrpc_core::Error>
+    where
+        P: DeserializeOwned,
+        F: FnOnce(Arc<Self>, P) -> Fut,
+        Fut: std::future::Future<Output = Result<Value, jsonrpc_core::Error>>,
+    {
+        let call = AdminCall {
+            method: method.to_string(),
+            params: redact(params_value(&params)),
+            caller: None,
+            source: meta.source,
+        };
+        let id = self.audit.start_admin_call(call).map_err(|e| {
+            error!(self.logger, "Failed to record {} call in the audit log", method;
+                   "error" => e.to_string());
+            jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(JSON_RPC_AUDIT_ERROR),
+                message: "internal error".to_owned(),
+                data: None,
+            }
+        })?;
+
+        let result = match params.parse::<P>() {
+            Ok(params) => handler(self.clone(), params).await,
+            Err(e) => Err(e),
+        };
+
+        let error = result.as_ref().err().map(|e| e.message.clone());
+        if let Err(e) = self.audit.finish_admin_call(id, error) {
+            error!(self.logger, "Failed to record outcome of {} call in the audit log", method;
+                   "id" => id, "error" => e.to_string());
+        }
+        let outcome = if result.is_ok() { "success" } else { "failure" };
+        self.calls.with_label_values(&[method, outcome]).inc();
+
+        result
+    }
+
     /// Handler for the `subgraph_create` endpoint.
     async fn create_handler(
         &self,
@@ -91,13 +278,27 @@ impl<R: SubgraphRegistrar> JsonRpcServer<R> {
         info!(&self.logger, "Received subgraph_deploy request"; "params" => format!("{:?}", params));
 
         let node_id = params.node_id.clone().unwrap_or(self.node_id.clone());
-        let routes = subgraph_routes(&params.name, self.http_port, self.ws_port);
+        let mut routes = subgraph_routes(&params.name, self.http_port, self.ws_port);
         match self
             .registrar
-            .create_subgraph_version(params.name.clone(), params.ipfs_hash.clone(), node_id)
+            .create_subgraph_version(
+                params.name.clone(),
+                params.ipfs_hash.clone(),
+                node_id,
+                params.version_switching_mode,
+                params.labels.clone(),
+            )
             .await
         {
-            Ok(_) => Ok(routes),
+            Ok(profile) => {
+                if let Value::Object(routes) = &mut routes {
+                    routes.insert(
+                        "profile".to_string(),
+                        jsonrpc_core::to_value(profile).expect("invalid deployment profile"),
+                    );
+                }
+                Ok(routes)
+            }
             Err(e) => Err(json_rpc_error(
                 &self.logger,
                 "subgraph_deploy",
@@ -151,6 +352,314 @@ impl<R: SubgraphRegistrar> JsonRpcServer<R> {
             )),
         }
     }
+
+    /// Handler for the `subgraph_archive` endpoint.
+    async fn archive_handler(
+        &self,
+        params: SubgraphArchiveParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_archive request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .set_archive_blocks(&params.ipfs_hash, params.blocks)
+            .await
+        {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_archive",
+                e,
+                JSON_RPC_ARCHIVE_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_skip_block` endpoint.
+    async fn skip_block_handler(
+        &self,
+        params: SubgraphSkipBlockParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_skip_block request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .skip_block(
+                &params.deployment,
+                &params.block_hash,
+                params.reason.clone(),
+            )
+            .await
+        {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_skip_block",
+                e,
+                JSON_RPC_SKIP_BLOCK_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_unskip_block` endpoint. Returns the
+    /// record of the skip so that the caller can see whether the block was
+    /// already skipped and the deployment needs to be rewound
+    async fn unskip_block_handler(
+        &self,
+        params: SubgraphUnskipBlockParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_unskip_block request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .unskip_block(&params.deployment, &params.block_hash)
+            .await
+        {
+            Ok(skipped) => Ok(serde_json::to_value(skipped).expect("invalid skipped block")),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_unskip_block",
+                e,
+                JSON_RPC_UNSKIP_BLOCK_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_reset_scan_totals` endpoint.
+    async fn reset_scan_totals_handler(
+        &self,
+        params: SubgraphResetScanTotalsParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_reset_scan_totals request"; "params" => format!("{:?}", params));
+
+        match self.registrar.reset_scan_totals(&params.deployment).await {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_reset_scan_totals",
+                e,
+                JSON_RPC_RESET_SCAN_TOTALS_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_set_labels` endpoint.
+    async fn set_labels_handler(
+        &self,
+        params: SubgraphSetLabelsParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_set_labels request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .set_labels(&params.deployment, params.labels.clone())
+            .await
+        {
+            Ok(_) => Ok(Value::Null),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_set_labels",
+                e,
+                JSON_RPC_SET_LABELS_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_get_labels` endpoint.
+    async fn get_labels_handler(
+        &self,
+        params: SubgraphGetLabelsParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        match self.registrar.labels(&params.deployment).await {
+            Ok(labels) => Ok(serde_json::to_value(labels).expect("invalid labels")),
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "subgraph_get_labels",
+                e,
+                JSON_RPC_GET_LABELS_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_reload_mappings` endpoint. Only works
+    /// when the node runs in development mode
+    async fn reload_mappings_handler(
+        &self,
+        params: SubgraphReloadMappingsParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        let logger = self.logger.clone();
+
+        info!(logger, "Received subgraph_reload_mappings request"; "params" => format!("{:?}", params));
+
+        match self
+            .registrar
+            .reload_mappings(&params.deployment, params.manifest.clone(), params.block)
+            .await
+        {
+            Ok(reload) => Ok(serde_json::to_value(reload).expect("invalid mappings reload")),
+            Err(e) => Err(json_rpc_error(
+                &logger,
+                "subgraph_reload_mappings",
+                e,
+                JSON_RPC_RELOAD_MAPPINGS_ERROR,
+                params,
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_compare_versions` endpoint. Starts
+    /// comparing the pending with the current version of a subgraph in
+    /// the background and returns the id of the comparison's job
+    async fn compare_versions_handler(
+        &self,
+        params: SubgraphCompareVersionsParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received subgraph_compare_versions request"; "params" => format!("{:?}", params));
+
+        let sample = params.sample.unwrap_or(DEFAULT_COMPARISON_SAMPLE);
+        if sample > MAX_COMPARISON_SAMPLE {
+            return Err(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(JSON_RPC_COMPARE_VERSIONS_ERROR),
+                message: format!(
+                    "a comparison can list at most {} entities of each type",
+                    MAX_COMPARISON_SAMPLE
+                ),
+                data: None,
+            });
+        }
+
+        let id = self.comparisons.start(&params.name, params.block);
+        let registrar = self.registrar.clone();
+        let comparisons = self.comparisons.clone();
+        let logger = self.logger.clone();
+        graph::spawn_blocking(async move {
+            let result = registrar
+                .compare_versions(params.name.clone(), params.block, sample)
+                .await
+                .map_err(|e| {
+                    json_rpc_error(
+                        &logger,
+                        "subgraph_compare_versions",
+                        e,
+                        JSON_RPC_COMPARE_VERSIONS_ERROR,
+                        &params,
+                    )
+                    .message
+                });
+            comparisons.finish(id, result);
+        });
+        Ok(serde_json::json!({ "job": id }))
+    }
+
+    /// Handler for the `subgraph_comparison_status` endpoint.
+    async fn comparison_status_handler(
+        &self,
+        params: SubgraphComparisonStatusParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        match self.comparisons.get(params.job) {
+            Some(job) => Ok(serde_json::to_value(job).expect("invalid comparison job")),
+            None => Err(jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(JSON_RPC_COMPARISON_STATUS_ERROR),
+                message: format!("unknown comparison job {}", params.job),
+                data: None,
+            }),
+        }
+    }
+
+    /// Handler for the `store_maintain_metadata` endpoint.
+    async fn maintain_metadata_handler(&self) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received store_maintain_metadata request");
+
+        match self.registrar.maintain_metadata().await {
+            Ok(report) => {
+                Ok(serde_json::to_value(report).expect("invalid metadata maintenance report"))
+            }
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "store_maintain_metadata",
+                e,
+                JSON_RPC_MAINTAIN_ERROR,
+                (),
+            )),
+        }
+    }
+
+    /// Handler for the `subgraph_validate_files` endpoint.
+    async fn validate_files_handler(&self) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received subgraph_validate_files request");
+
+        match self.registrar.validate_deployment_files().await {
+            Ok(reports) => {
+                Ok(serde_json::to_value(reports).expect("invalid deployment files report"))
+            }
+            Err(e) => Err(json_rpc_error(
+                &self.logger,
+                "subgraph_validate_files",
+                e,
+                JSON_RPC_VALIDATE_FILES_ERROR,
+                (),
+            )),
+        }
+    }
+}
+
+impl<R> JsonRpcServer<R> {
+    /// Handler for the `subgraph_profile` endpoint. Returns a report in
+    /// the collapsed stack format that lists how many microseconds the
+    /// handlers of the deployment spent in each stack of mapping functions
+    async fn profile_handler(
+        &self,
+        params: SubgraphProfileParams,
+    ) -> Result<Value, jsonrpc_core::Error> {
+        info!(&self.logger, "Received subgraph_profile request"; "params" => format!("{:?}", params));
+
+        let error = |message: String| {
+            error!(&self.logger, "subgraph_profile failed";
+                   "error" => &message, "params" => format!("{:?}", params));
+            jsonrpc_core::Error {
+                code: jsonrpc_core::ErrorCode::ServerError(JSON_RPC_PROFILE_ERROR),
+                message,
+                data: None,
+            }
+        };
+
+        if params.seconds == 0 || params.seconds > MAX_PROFILE_SECONDS {
+            return Err(error(format!(
+                "a deployment can be profiled for between 1 and {} seconds",
+                MAX_PROFILE_SECONDS
+            )));
+        }
+
+        let report = graph::util::profiler::profile(
+            params.deployment.clone(),
+            Duration::from_secs(params.seconds),
+        )
+        .await
+        .map_err(|e| error(e.to_string()))?;
+        if report.is_empty() {
+            return Err(error(format!(
+                "no handlers of deployment {} ran on this node while it was profiled",
+                params.deployment
+            )));
+        }
+        Ok(Value::String(report))
+    }
 }
 
 impl<R> JsonRpcServerTrait<R> for JsonRpcServer<R>
@@ -164,7 +673,10 @@ where
         http_port: u16,
         ws_port: u16,
         registrar: Arc<R>,
+        audit: Arc<dyn AdminAuditStore>,
+        registry: Arc<impl MetricsRegistry>,
         node_id: NodeId,
+        build_info: BuildInfo,
         logger: Logger,
     ) -> Result<Self::Server, io::Error> {
         let logger = logger.new(o!("component" => "JsonRpcServer"));
@@ -176,24 +688,43 @@ where
 
         let addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port);
 
-        let mut handler = IoHandler::with_compatibility(Compatibility::Both);
+        let mut handler = MetaIoHandler::<RequestMeta>::with_compatibility(Compatibility::Both);
+
+        let calls = registry
+            .new_counter_vec(
+                "admin_rpc_calls",
+                "Counts the calls to the admin JSON-RPC server that change something, \
+                 by method and result",
+                vec![String::from("method"), String::from("result")],
+            )
+            .expect("failed to create `admin_rpc_calls` counter");
 
         let arc_self = Arc::new(JsonRpcServer {
             registrar,
+            audit,
+            calls,
+            comparisons: Arc::new(ComparisonJobs::default()),
             http_port,
             ws_port,
             node_id,
+            build_info,
             logger,
         });
 
         let (task_sender, task_receiver) =
             mpsc::channel::<Box<dyn std::future::Future<Output = ()> + Send + Unpin>>(100);
-        graph::spawn(task_receiver.for_each(|f| {
-            async {
-                // Blocking due to store interactions. Won't be blocking after #905.
-                graph::spawn_blocking(f);
+        // The receiver is shared so that a restarted task keeps receiving
+        let task_receiver = Arc::new(tokio::sync::Mutex::new(task_receiver));
+        graph::spawn_essential("json-rpc-tasks", move || {
+            let task_receiver = task_receiver.clone();
+            async move {
+                let mut task_receiver = task_receiver.lock().await;
+                while let Some(f) = task_receiver.next().await {
+                    // Blocking due to store interactions. Won't be blocking after #905.
+                    graph::spawn_blocking(f);
+                }
             }
-        }));
+        });
 
         // This is a hack required because the json-rpc crate is not updated to tokio 0.2.
         // We should watch the `jsonrpsee` crate and switch to that once it's ready.
@@ -217,69 +748,300 @@ where
 
         let me = arc_self.clone();
         let sender = task_sender.clone();
-        handler.add_method("subgraph_create", move |params: Params| {
-            let me = me.clone();
-            Box::pin(tokio02_spawn(
-                sender.clone(),
-                async move {
-                    let params = params.parse()?;
-                    me.create_handler(params).await
-                }
-                .boxed(),
-            ))
-            .compat()
-        });
+        handler.add_method_with_meta(
+            "subgraph_create",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited("subgraph_create", params, meta, |me, params| async move {
+                        me.create_handler(params).await
+                    })
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
 
         let me = arc_self.clone();
         let sender = task_sender.clone();
 
-        handler.add_method("subgraph_deploy", move |params: Params| {
-            let me = me.clone();
-            Box::pin(tokio02_spawn(
-                sender.clone(),
-                async move {
-                    let params = params.parse()?;
-                    me.deploy_handler(params).await
-                }
-                .boxed(),
-            ))
-            .compat()
-        });
+        handler.add_method_with_meta(
+            "subgraph_deploy",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited("subgraph_deploy", params, meta, |me, params| async move {
+                        me.deploy_handler(params).await
+                    })
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
 
         let me = arc_self.clone();
         let sender = task_sender.clone();
-        handler.add_method("subgraph_remove", move |params: Params| {
-            let me = me.clone();
-            Box::pin(tokio02_spawn(
-                sender.clone(),
-                async move {
-                    let params = params.parse()?;
-                    me.remove_handler(params).await
-                }
-                .boxed(),
-            ))
-            .compat()
-        });
+        handler.add_method_with_meta(
+            "subgraph_remove",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited("subgraph_remove", params, meta, |me, params| async move {
+                        me.remove_handler(params).await
+                    })
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_reassign",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited("subgraph_reassign", params, meta, |me, params| async move {
+                        me.reassign_handler(params).await
+                    })
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
 
         let me = arc_self.clone();
         let sender = task_sender.clone();
-        handler.add_method("subgraph_reassign", move |params: Params| {
+        handler.add_method_with_meta(
+            "subgraph_archive",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited("subgraph_archive", params, meta, |me, params| async move {
+                        me.archive_handler(params).await
+                    })
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_skip_block",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "subgraph_skip_block",
+                        params,
+                        meta,
+                        |me, params| async move { me.skip_block_handler(params).await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_unskip_block",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "subgraph_unskip_block",
+                        params,
+                        meta,
+                        |me, params| async move { me.unskip_block_handler(params).await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_reset_scan_totals",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "subgraph_reset_scan_totals",
+                        params,
+                        meta,
+                        |me, params| async move { me.reset_scan_totals_handler(params).await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_set_labels",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "subgraph_set_labels",
+                        params,
+                        meta,
+                        |me, params| async move { me.set_labels_handler(params).await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        // Comparing versions does not change anything and therefore is not
+        // audited
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_compare_versions",
+            move |params: Params, _: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    async move { me.compare_versions_handler(params.parse()?).await }.boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_comparison_status",
+            move |params: Params, _: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    async move { me.comparison_status_handler(params.parse()?).await }.boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        // `subgraph_get_labels` does not change anything and therefore is
+        // not audited
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_get_labels",
+            move |params: Params, _: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    async move { me.get_labels_handler(params.parse()?).await }.boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_reload_mappings",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "subgraph_reload_mappings",
+                        params,
+                        meta,
+                        |me, params| async move { me.reload_mappings_handler(params).await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "store_maintain_metadata",
+            move |params: Params, meta: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    me.audited(
+                        "store_maintain_metadata",
+                        params,
+                        meta,
+                        // The method takes no parameters; ignore whatever was sent
+                        |me, _: Value| async move { me.maintain_metadata_handler().await },
+                    )
+                    .boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        // `subgraph_validate_files` only reads files and therefore is not
+        // audited
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta(
+            "subgraph_validate_files",
+            move |_: Params, _: RequestMeta| {
+                let me = me.clone();
+                Box::pin(tokio02_spawn(
+                    sender.clone(),
+                    async move { me.validate_files_handler().await }.boxed(),
+                ))
+                .compat()
+            },
+        );
+
+        // `subgraph_profile` does not change anything and therefore is not
+        // audited
+        let me = arc_self.clone();
+        let sender = task_sender.clone();
+        handler.add_method_with_meta("subgraph_profile", move |params: Params, _: RequestMeta| {
             let me = me.clone();
             Box::pin(tokio02_spawn(
                 sender.clone(),
-                async move {
-                    let params = params.parse()?;
-                    me.reassign_handler(params).await
-                }
-                .boxed(),
+                async move { me.profile_handler(params.parse()?).await }.boxed(),
             ))
             .compat()
         });
 
-        ServerBuilder::new(handler)
+        // `version` does not change anything and therefore is not audited
+        let me = arc_self.clone();
+        handler.add_method_with_meta("version", move |_: Params, _: RequestMeta| {
+            future::result(
+                serde_json::to_value(&me.build_info)
+                    .map_err(|_| jsonrpc_core::Error::internal_error()),
+            )
+        });
+
+        ServerBuilder::with_meta_extractor(handler, RequestMeta::from_request)
             // Enable REST API:
             // POST /<method>/<param1>/<param2>
             .rest_api(RestApi::Secure)
+            .max_request_body_size(*ADMIN_MAX_BODY_SIZE)
             .start_http(&addr.into())
     }
 }
@@ -308,6 +1070,36 @@ fn json_rpc_error(
     }
 }
 
+/// The parameters of a call as JSON
+fn params_value(params: &Params) -> Value {
+    match params {
+        Params::None => Value::Null,
+        Params::Array(values) => Value::Array(values.clone()),
+        Params::Map(map) => Value::Object(map.clone()),
+    }
+}
+
+/// Replace the values of all parameters that look like they contain
+/// secrets
+fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let lower = key.to_lowercase();
+                    if SECRET_PARAMS.iter().any(|secret| lower.contains(secret)) {
+                        (key, Value::String("[redacted]".to_owned()))
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        value => value,
+    }
+}
+
 pub fn parse_response(response: Value) -> Result<(), jsonrpc_core::Error> {
     // serde deserialization of the `id` field to an `Id` struct is somehow
     // incompatible with the `arbitrary-precision` feature which we use, so we
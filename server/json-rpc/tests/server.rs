@@ -0,0 +1,222 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use graph::components::server::admin::AdminCall;
+use graph::components::server::body::ADMIN_MAX_BODY_SIZE;
+use graph::components::server::version::BuildInfo;
+use graph::components::store::{AdminAuditStore, VersionComparison};
+use graph::data::subgraph::status::{MappingsReload, SkippedBlock};
+use graph::data::subgraph::{DeploymentProfile, Labels};
+use graph::prelude::{JsonRpcServer as _, *};
+use graph_mock::MockMetricsRegistry;
+use graph_server_json_rpc::JsonRpcServer;
+
+/// A registrar for requests that never get as far as calling it
+pub struct TestRegistrar;
+
+#[async_trait]
+impl SubgraphRegistrar for TestRegistrar {
+    async fn create_subgraph(
+        &self,
+        _: SubgraphName,
+    ) -> Result<CreateSubgraphResult, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn create_subgraph_version(
+        &self,
+        _: SubgraphName,
+        _: DeploymentHash,
+        _: NodeId,
+        _: Option<SubgraphVersionSwitchingMode>,
+        _: Option<Labels>,
+    ) -> Result<DeploymentProfile, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn remove_subgraph(&self, _: SubgraphName) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn reassign_subgraph(
+        &self,
+        _: &DeploymentHash,
+        _: &NodeId,
+    ) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn set_archive_blocks(
+        &self,
+        _: &DeploymentHash,
+        _: Option<BlockNumber>,
+    ) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn skip_block(
+        &self,
+        _: &DeploymentHash,
+        _: &str,
+        _: String,
+    ) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn unskip_block(
+        &self,
+        _: &DeploymentHash,
+        _: &str,
+    ) -> Result<SkippedBlock, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn reset_scan_totals(&self, _: &DeploymentHash) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn set_labels(
+        &self,
+        _: &DeploymentHash,
+        _: Labels,
+    ) -> Result<(), SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn labels(&self, _: &DeploymentHash) -> Result<Labels, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn reload_mappings(
+        &self,
+        _: &DeploymentHash,
+        _: Option<DeploymentHash>,
+        _: Option<BlockNumber>,
+    ) -> Result<MappingsReload, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn compare_versions(
+        &self,
+        _: SubgraphName,
+        _: BlockNumber,
+        _: usize,
+    ) -> Result<VersionComparison, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+
+    async fn validate_deployment_files(
+        &self,
+    ) -> Result<Vec<DeploymentFilesReport>, SubgraphRegistrarError> {
+        unimplemented!()
+    }
+}
+
+/// An audit log for requests that never change anything
+pub struct TestAuditStore;
+
+impl AdminAuditStore for TestAuditStore {
+    fn start_admin_call(&self, _: AdminCall) -> Result<i64, StoreError> {
+        unimplemented!()
+    }
+
+    fn finish_admin_call(&self, _: i64, _: Option<String>) -> Result<(), StoreError> {
+        unimplemented!()
+    }
+}
+
+fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: "0.0.0".to_string(),
+        testament: "0.0.0".to_string(),
+        commit: None,
+        commit_date: None,
+        tag: None,
+        distance: None,
+        dirty: false,
+        rustc: "rustc".to_string(),
+        profile: "debug".to_string(),
+        features: vec![],
+        max_spec_version: "0.0.4".to_string(),
+        max_api_version: "0.0.6".to_string(),
+    }
+}
+
+/// Post `body` to the server on `port` and return the status and body of
+/// the response. The request is written by hand so that the client sends
+/// exactly what we tell it to
+fn post(port: u16, content_type: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    let request = format!(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Type: {}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    );
+    // The server may stop reading a body that is too large and respond
+    // right away
+    stream.write_all(request.as_bytes()).ok();
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).ok();
+    let response = String::from_utf8_lossy(&response);
+    let status = response
+        .split(' ')
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or_else(|| panic!("invalid response `{}`", response));
+    let body = response
+        .splitn(2, "\r\n\r\n")
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    (status, body)
+}
+
+#[test]
+fn rejects_oversized_and_non_json_bodies() {
+    const PORT: u16 = 8020;
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let _guard = runtime.enter();
+    let _server = JsonRpcServer::serve(
+        PORT,
+        8000,
+        8001,
+        Arc::new(TestRegistrar),
+        Arc::new(TestAuditStore),
+        Arc::new(MockMetricsRegistry::new()),
+        NodeId::new("test").unwrap(),
+        build_info(),
+        Logger::root(slog::Discard, o!()),
+    )
+    .expect("Failed to start JSON-RPC admin server");
+
+    // Pad a request to the size of the limit
+    let request = "{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"version\"}";
+    let padded = |size: usize| format!("{}{}", request, " ".repeat(size - request.len()));
+
+    let (status, body) = post(PORT, "application/json", &padded(*ADMIN_MAX_BODY_SIZE));
+    assert_eq!(200, status);
+    assert!(body.contains("\"maxApiVersion\":\"0.0.6\""));
+
+    let (status, _) = post(PORT, "application/json", &padded(*ADMIN_MAX_BODY_SIZE + 1));
+    assert_eq!(413, status);
+
+    let (status, _) = post(PORT, "text/plain", request);
+    assert_eq!(415, status);
+
+    // Malformed requests get a JSON-RPC parse error
+    let (status, body) = post(PORT, "application/json", "{\"jsonrpc\": ");
+    assert_eq!(200, status);
+    assert!(body.contains("-32700"));
+}
@@ -14,13 +14,25 @@ use uuid::Uuid;
 
 use graph::{data::query::QueryTarget, prelude::*};
 
+use crate::metrics::{Rejection, SubscriptionMetrics};
+
 lazy_static! {
+    /// The most operations that may run on one connection at the same time
     static ref MAX_OPERATIONS_PER_CONNECTION: Option<usize> =
         env::var("GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION")
             .ok()
             .map(|s| usize::from_str(&s).unwrap_or_else(|_| panic!(
                 "failed to parse env var GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION"
             )));
+
+    /// The most complexity that all subscriptions on one connection may
+    /// have together
+    static ref MAX_COMPLEXITY_PER_CONNECTION: Option<u64> =
+        env::var("GRAPH_GRAPHQL_MAX_COMPLEXITY_PER_CONNECTION")
+            .ok()
+            .map(|s| u64::from_str(&s).unwrap_or_else(|_| panic!(
+                "failed to parse env var GRAPH_GRAPHQL_MAX_COMPLEXITY_PER_CONNECTION"
+            )));
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -57,29 +69,64 @@ impl IncomingMessage {
 #[serde(tag = "type", rename_all = "snake_case")]
 enum OutgoingMessage {
     ConnectionAck,
-    Error {
-        id: String,
-        payload: String,
-    },
-    Data {
-        id: String,
-        payload: Arc<QueryResult>,
-    },
-    Complete {
-        id: String,
-    },
+    Error { id: String, payload: ErrorPayload },
+    Data { id: String, payload: DataPayload },
+    Complete { id: String },
+}
+
+/// The payload of `error` messages; it has the same shape as responses
+/// of the HTTP server that only contain errors
+#[derive(Debug, Serialize)]
+struct ErrorPayload {
+    errors: Vec<ErrorMessage>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorMessage {
+    message: String,
+}
+
+/// The result of running a subscription, together with the deployment
+/// that the subscription ran against
+#[derive(Debug, Serialize)]
+struct DataPayload {
+    #[serde(flatten)]
+    result: Arc<QueryResult>,
+    extensions: DataExtensions,
+}
+
+#[derive(Debug, Serialize)]
+struct DataExtensions {
+    deployment: String,
 }
 
 impl OutgoingMessage {
-    pub fn from_query_result(id: String, result: Arc<QueryResult>) -> Self {
+    pub fn from_query_result(
+        id: String,
+        deployment: DeploymentHash,
+        result: Arc<QueryResult>,
+    ) -> Self {
         OutgoingMessage::Data {
             id: id,
-            payload: result,
+            payload: DataPayload {
+                result,
+                extensions: DataExtensions {
+                    deployment: deployment.to_string(),
+                },
+            },
         }
     }
 
-    pub fn from_error_string(id: String, s: String) -> Self {
-        OutgoingMessage::Error { id, payload: s }
+    pub fn from_errors(id: String, errors: Vec<String>) -> Self {
+        OutgoingMessage::Error {
+            id,
+            payload: ErrorPayload {
+                errors: errors
+                    .into_iter()
+                    .map(|message| ErrorMessage { message })
+                    .collect(),
+            },
+        }
     }
 }
 
@@ -102,31 +149,55 @@ fn send_message(
 }
 
 /// Helper function to send error messages.
+fn send_errors(
+    sink: &mpsc::UnboundedSender<WsMessage>,
+    operation_id: String,
+    errors: Vec<String>,
+) -> Result<(), WsError> {
+    send_message(sink, OutgoingMessage::from_errors(operation_id, errors))
+}
+
 fn send_error_string(
     sink: &mpsc::UnboundedSender<WsMessage>,
     operation_id: String,
     error: String,
 ) -> Result<(), WsError> {
-    sink.unbounded_send(OutgoingMessage::from_error_string(operation_id, error).into())
-        .map_err(|_| {
-            let mut response = http::Response::new(None);
-            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
-            WsError::Http(response)
-        })
+    send_errors(sink, operation_id, vec![error])
+}
+
+/// Find the deployment that `target` currently refers to, the same way
+/// the HTTP server does for queries. Errors are worded like the errors in
+/// responses of the HTTP server
+async fn resolve_deployment<M: QueryStoreManager>(
+    store: &M,
+    target: QueryTarget,
+) -> Result<DeploymentHash, String> {
+    let query_store = store
+        .query_store(target, true)
+        .await
+        .map_err(|e| e.to_string())?;
+    query_store
+        .deployment_state()
+        .await
+        .map(|state| state.id)
+        .map_err(|e| e.to_string())
 }
 
 /// Responsible for recording operation ids and stopping them.
 /// On drop, cancels all operations.
 struct Operations {
-    operations: HashMap<String, CancelGuard>,
+    /// The cancel guard and the complexity of each operation
+    operations: HashMap<String, (CancelGuard, u64)>,
     msg_sink: mpsc::UnboundedSender<WsMessage>,
+    metrics: Arc<SubscriptionMetrics>,
 }
 
 impl Operations {
-    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>) -> Self {
+    fn new(msg_sink: mpsc::UnboundedSender<WsMessage>, metrics: Arc<SubscriptionMetrics>) -> Self {
         Self {
             operations: HashMap::new(),
             msg_sink,
+            metrics,
         }
     }
 
@@ -134,14 +205,29 @@ impl Operations {
         self.operations.contains_key(id)
     }
 
-    fn insert(&mut self, id: String, guard: CancelGuard) {
-        self.operations.insert(id, guard);
+    fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// The complexity of all operations together
+    fn complexity(&self) -> u64 {
+        self.operations
+            .values()
+            .map(|(_, complexity)| complexity)
+            .sum()
+    }
+
+    fn insert(&mut self, id: String, guard: CancelGuard, complexity: u64) {
+        self.metrics.subscription_started();
+        self.operations.insert(id, (guard, complexity));
     }
 
     fn stop(&mut self, operation_id: String) -> Result<(), WsError> {
         // Remove the operation with this ID from the known operations.
         match self.operations.remove(&operation_id) {
-            Some(stopper) => {
+            Some((stopper, _)) => {
+                self.metrics.subscription_stopped();
+
                 // Cancel the subscription result stream.
                 stopper.cancel();
 
@@ -173,32 +259,43 @@ impl Drop for Operations {
 }
 
 /// A WebSocket connection implementing the GraphQL over WebSocket protocol.
-pub struct GraphQlConnection<Q, S> {
+///
+/// Problems with individual operations, like a subgraph name that does not
+/// exist, are reported to the client with `error` messages; the connection
+/// is only closed when the client violates the protocol
+pub struct GraphQlConnection<Q, M, S> {
     id: String,
     logger: Logger,
     graphql_runner: Arc<Q>,
+    store: Arc<M>,
     stream: WebSocketStream<S>,
-    deployment: DeploymentHash,
+    target: QueryTarget,
+    metrics: Arc<SubscriptionMetrics>,
 }
 
-impl<Q, S> GraphQlConnection<Q, S>
+impl<Q, M, S> GraphQlConnection<Q, M, S>
 where
     Q: GraphQlRunner,
+    M: QueryStoreManager,
     S: AsyncRead + AsyncWrite + Send + 'static + Unpin,
 {
     /// Creates a new GraphQL subscription service.
     pub(crate) fn new(
         logger: &Logger,
-        deployment: DeploymentHash,
+        target: QueryTarget,
         stream: WebSocketStream<S>,
         graphql_runner: Arc<Q>,
+        store: Arc<M>,
+        metrics: Arc<SubscriptionMetrics>,
     ) -> Self {
         GraphQlConnection {
             id: Uuid::new_v4().to_string(),
             logger: logger.new(o!("component" => "GraphQlConnection")),
             graphql_runner,
+            store,
             stream,
-            deployment,
+            target,
+            metrics,
         }
     }
 
@@ -207,10 +304,12 @@ where
         mut msg_sink: mpsc::UnboundedSender<WsMessage>,
         logger: Logger,
         connection_id: String,
-        deployment: DeploymentHash,
+        target: QueryTarget,
         graphql_runner: Arc<Q>,
+        store: Arc<M>,
+        metrics: Arc<SubscriptionMetrics>,
     ) -> Result<(), WsError> {
-        let mut operations = Operations::new(msg_sink.clone());
+        let mut operations = Operations::new(msg_sink.clone(), metrics.clone());
 
         // Process incoming messages as long as the WebSocket is open
         while let Some(ws_msg) = ws_stream.try_next().await? {
@@ -221,6 +320,8 @@ where
                    "connection" => &connection_id,
                    "msg" => format!("{}", ws_msg).as_str());
 
+            // Messages that we can not make sense of are a protocol
+            // violation and close the connection
             let msg = IncomingMessage::from_ws_message(ws_msg.clone())?;
 
             debug!(logger, "GraphQL/WebSocket message";
@@ -247,23 +348,28 @@ where
                 Start { id, payload } => {
                     // Respond with a GQL_ERROR if we already have an operation with this ID
                     if operations.contains(&id) {
-                        return send_error_string(
+                        send_error_string(
                             &msg_sink,
                             id.clone(),
                             format!("Operation with ID already started: {}", id),
-                        );
+                        )?;
+                        continue;
                     }
 
-                    if let Some(max_ops) = *MAX_OPERATIONS_PER_CONNECTION {
-                        if operations.operations.len() >= max_ops {
-                            return send_error_string(
+                    // Refuse the operation, but keep the connection and
+                    // the operations that are already running
+                    if let Some(max_operations) = *MAX_OPERATIONS_PER_CONNECTION {
+                        if operations.len() >= max_operations {
+                            metrics.rejected(Rejection::Subscriptions);
+                            send_error_string(
                                 &msg_sink,
                                 id.clone(),
                                 format!(
                                     "Reached the limit of {} operations per connection",
-                                    max_ops
+                                    max_operations
                                 ),
-                            );
+                            )?;
+                            continue;
                         }
                     }
 
@@ -272,11 +378,12 @@ where
                     let query = match parse_query(&payload.query) {
                         Ok(query) => query.into_static(),
                         Err(e) => {
-                            return send_error_string(
+                            send_error_string(
                                 &msg_sink,
                                 id.clone(),
                                 format!("Invalid query: {}: {}", payload.query, e),
-                            );
+                            )?;
+                            continue;
                         }
                     };
 
@@ -287,20 +394,34 @@ where
                             match serde_json::from_value(variables.clone()) {
                                 Ok(variables) => Some(variables),
                                 Err(e) => {
-                                    return send_error_string(
+                                    send_error_string(
                                         &msg_sink,
                                         id.clone(),
                                         format!("Invalid variables provided: {}", e),
-                                    );
+                                    )?;
+                                    continue;
                                 }
                             }
                         }
                         _ => {
-                            return send_error_string(
+                            send_error_string(
                                 &msg_sink,
                                 id.clone(),
                                 format!("Invalid variables provided (must be an object)"),
-                            );
+                            )?;
+                            continue;
+                        }
+                    };
+
+                    // Resolve the subgraph name or deployment for every
+                    // operation so that subscriptions to a name follow the
+                    // current version of the subgraph, like queries do
+                    let deployment = match resolve_deployment(store.as_ref(), target.clone()).await
+                    {
+                        Ok(deployment) => deployment,
+                        Err(e) => {
+                            send_error_string(&msg_sink, id.clone(), e)?;
+                            continue;
                         }
                     };
 
@@ -312,6 +433,52 @@ where
                         query: Query::new(query, variables),
                     };
 
+                    // Check the complexity and depth limits before starting
+                    // the subscription. Other errors are reported when the
+                    // subscription runs, as they always were
+                    let complexity = match graphql_runner
+                        .cheap_clone()
+                        .subscription_complexity(subscription.clone(), target.clone())
+                        .await
+                    {
+                        Ok(complexity) => complexity,
+                        Err(SubscriptionError::GraphQLError(errors))
+                            if errors.iter().any(|e| {
+                                matches!(
+                                    e,
+                                    QueryExecutionError::TooComplex(_, _)
+                                        | QueryExecutionError::TooDeep(_)
+                                )
+                            }) =>
+                        {
+                            metrics.rejected(Rejection::QueryLimits);
+                            let errors = errors.iter().map(ToString::to_string).collect();
+                            send_errors(&msg_sink, id.clone(), errors)?;
+                            continue;
+                        }
+                        Err(e @ SubscriptionError::IndexingError) => {
+                            send_error_string(&msg_sink, id.clone(), e.to_string())?;
+                            continue;
+                        }
+                        Err(_) => 0,
+                    };
+                    if let Some(max_complexity) = *MAX_COMPLEXITY_PER_CONNECTION {
+                        let total = operations.complexity() + complexity;
+                        if total > max_complexity {
+                            metrics.rejected(Rejection::ConnectionComplexity);
+                            send_error_string(
+                                &msg_sink,
+                                id.clone(),
+                                format!(
+                                    "The subscriptions on this connection would have a total \
+                                     complexity of {}, but at most {} is allowed",
+                                    total, max_complexity
+                                ),
+                            )?;
+                            continue;
+                        }
+                    }
+
                     debug!(logger, "Start operation";
                            "connection" => &connection_id,
                            "id" => &id);
@@ -323,6 +490,8 @@ where
                     let err_id = id.clone();
                     let err_connection_id = connection_id.clone();
                     let err_logger = logger.clone();
+                    let err_deployment = deployment.clone();
+                    let result_deployment = deployment.clone();
                     let run_subscription = graphql_runner
                         .cheap_clone()
                         .run_subscription(subscription, target)
@@ -345,6 +514,7 @@ where
                                         let result = Arc::new(QueryResult::from(e));
                                         let msg = OutgoingMessage::from_query_result(
                                             err_id.clone(),
+                                            err_deployment.clone(),
                                             result,
                                         );
 
@@ -353,13 +523,26 @@ where
                                         let _ = error_sink.unbounded_send(msg.into());
                                     }
                                 }
+                                // The deployment failed after we checked it
+                                // above
+                                err @ SubscriptionError::IndexingError => {
+                                    let msg = OutgoingMessage::from_errors(
+                                        err_id.clone(),
+                                        vec![err.to_string()],
+                                    );
+                                    let _ = error_sink.unbounded_send(msg.into());
+                                }
                             };
                         })
                         .and_then(move |result_stream| {
                             // Send results back to the client as GQL_DATA
                             result_stream
                                 .map(move |result| {
-                                    OutgoingMessage::from_query_result(result_id.clone(), result)
+                                    OutgoingMessage::from_query_result(
+                                        result_id.clone(),
+                                        result_deployment.clone(),
+                                        result,
+                                    )
                                 })
                                 .map(WsMessage::from)
                                 .map(Ok)
@@ -380,7 +563,7 @@ where
                                        "id" => &cancel_id);
                             Ok(())
                         });
-                    operations.insert(id, guard);
+                    operations.insert(id, guard, complexity);
 
                     graph::spawn_allow_panic(run_subscription);
                     Ok(())
@@ -391,9 +574,10 @@ where
     }
 }
 
-impl<Q, S> IntoFuture for GraphQlConnection<Q, S>
+impl<Q, M, S> IntoFuture for GraphQlConnection<Q, M, S>
 where
     Q: GraphQlRunner,
+    M: QueryStoreManager,
     S: AsyncRead + AsyncWrite + Send + 'static + Unpin,
 {
     type Future = Box<dyn Future<Item = Self::Item, Error = Self::Error> + Send>;
@@ -415,8 +599,10 @@ where
             msg_sink,
             self.logger.clone(),
             self.id.clone(),
-            self.deployment.clone(),
+            self.target.clone(),
             self.graphql_runner.clone(),
+            self.store.clone(),
+            self.metrics.clone(),
         );
 
         // Send outgoing messages asynchronously
@@ -1,4 +1,5 @@
 mod connection;
+mod metrics;
 mod server;
 
 pub use self::server::SubscriptionServer;
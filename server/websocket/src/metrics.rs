@@ -0,0 +1,85 @@
+use graph::prelude::{CounterVec, Gauge, MetricsRegistry};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Metrics for the connections and subscriptions of the websocket server
+pub(crate) struct SubscriptionMetrics {
+    connections: Box<Gauge>,
+    subscriptions: Box<Gauge>,
+    rejections: Box<CounterVec>,
+}
+
+impl SubscriptionMetrics {
+    pub fn new(registry: Arc<impl MetricsRegistry>) -> Self {
+        let connections = registry
+            .new_gauge(
+                "subscription_connections",
+                "The number of open GraphQL websocket connections",
+                HashMap::new(),
+            )
+            .expect("failed to create `subscription_connections` gauge");
+        let subscriptions = registry
+            .new_gauge(
+                "subscription_operations",
+                "The number of GraphQL subscriptions running over websocket connections",
+                HashMap::new(),
+            )
+            .expect("failed to create `subscription_operations` gauge");
+        let rejections = registry
+            .new_counter_vec(
+                "subscription_rejections",
+                "Counts the websocket connections and subscriptions that were refused because they exceeded a limit",
+                vec![String::from("reason")],
+            )
+            .expect("failed to create `subscription_rejections` counter");
+        SubscriptionMetrics {
+            connections,
+            subscriptions,
+            rejections,
+        }
+    }
+
+    pub fn connection_opened(&self) {
+        self.connections.inc();
+    }
+
+    pub fn connection_closed(&self) {
+        self.connections.dec();
+    }
+
+    pub fn subscription_started(&self) {
+        self.subscriptions.inc();
+    }
+
+    pub fn subscription_stopped(&self) {
+        self.subscriptions.dec();
+    }
+
+    pub fn rejected(&self, reason: Rejection) {
+        self.rejections.with_label_values(&[reason.as_str()]).inc();
+    }
+}
+
+/// The limit that a connection or subscription exceeded
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Rejection {
+    /// Too many connections from the same IP address
+    ConnectionsPerIp,
+    /// Too many subscriptions on one connection
+    Subscriptions,
+    /// The subscription by itself is too complex or too deep
+    QueryLimits,
+    /// The subscriptions on one connection are too complex in total
+    ConnectionComplexity,
+}
+
+impl Rejection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Rejection::ConnectionsPerIp => "connections_per_ip",
+            Rejection::Subscriptions => "subscriptions",
+            Rejection::QueryLimits => "query_limits",
+            Rejection::ConnectionComplexity => "connection_complexity",
+        }
+    }
+}
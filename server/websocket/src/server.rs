@@ -1,22 +1,100 @@
 use graph::{
+    components::server::body::GRAPHQL_WS_MAX_MESSAGE_SIZE,
     data::query::QueryTarget,
     prelude::{SubscriptionServer as SubscriptionServerTrait, *},
 };
 use http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_TYPE};
 use http::{HeaderValue, Response, StatusCode};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
 use std::sync::Mutex;
 use tokio::net::TcpListener;
-use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::accept_hdr_async_with_config;
 use tokio_tungstenite::tungstenite::handshake::server::Request;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 
 use crate::connection::GraphQlConnection;
+use crate::metrics::{Rejection, SubscriptionMetrics};
+
+lazy_static! {
+    /// The most websocket connections that may be open from one IP address
+    static ref MAX_CONNECTIONS_PER_IP: Option<usize> =
+        env::var("GRAPH_GRAPHQL_MAX_CONNECTIONS_PER_IP")
+            .ok()
+            .map(|s| usize::from_str(&s).unwrap_or_else(|_| panic!(
+                "failed to parse env var GRAPH_GRAPHQL_MAX_CONNECTIONS_PER_IP"
+            )));
+}
+
+/// The number of open connections for each IP address
+type ConnectionCounts = Arc<Mutex<HashMap<IpAddr, usize>>>;
+
+/// Counts a connection as open until it is dropped
+struct OpenConnection {
+    ip: IpAddr,
+    counts: ConnectionCounts,
+    metrics: Arc<SubscriptionMetrics>,
+}
+
+impl OpenConnection {
+    /// Count a new connection from `ip`, unless there are already as many
+    /// connections from it as `MAX_CONNECTIONS_PER_IP` allows
+    fn open(
+        ip: IpAddr,
+        counts: &ConnectionCounts,
+        metrics: &Arc<SubscriptionMetrics>,
+    ) -> Option<Self> {
+        let mut counts_guard = counts.lock().unwrap();
+        let count = counts_guard.entry(ip).or_insert(0);
+        if MAX_CONNECTIONS_PER_IP.map_or(false, |max| *count >= max) {
+            return None;
+        }
+        *count += 1;
+        metrics.connection_opened();
+        Some(OpenConnection {
+            ip,
+            counts: counts.clone(),
+            metrics: metrics.clone(),
+        })
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&self.ip);
+            }
+        }
+        self.metrics.connection_closed();
+    }
+}
 
 /// A GraphQL subscription server based on Hyper / Websockets.
 pub struct SubscriptionServer<Q, S> {
     logger: Logger,
     graphql_runner: Arc<Q>,
     store: Arc<S>,
+    metrics: Arc<SubscriptionMetrics>,
+    connections: ConnectionCounts,
+}
+
+// Implemented by hand so that `Q` and `S` don't have to be `Clone`
+impl<Q, S> Clone for SubscriptionServer<Q, S> {
+    fn clone(&self) -> Self {
+        SubscriptionServer {
+            logger: self.logger.clone(),
+            graphql_runner: self.graphql_runner.clone(),
+            store: self.store.clone(),
+            metrics: self.metrics.clone(),
+            connections: self.connections.clone(),
+        }
+    }
 }
 
 impl<Q, S> SubscriptionServer<Q, S>
@@ -24,18 +102,26 @@ where
     Q: GraphQlRunner,
     S: QueryStoreManager,
 {
-    pub fn new(logger: &Logger, graphql_runner: Arc<Q>, store: Arc<S>) -> Self {
+    pub fn new(
+        logger: &Logger,
+        metrics_registry: Arc<impl MetricsRegistry>,
+        graphql_runner: Arc<Q>,
+        store: Arc<S>,
+    ) -> Self {
         SubscriptionServer {
             logger: logger.new(o!("component" => "SubscriptionServer")),
             graphql_runner,
             store,
+            metrics: Arc::new(SubscriptionMetrics::new(metrics_registry)),
+            connections: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
-    async fn subgraph_id_from_url_path(
-        store: Arc<S>,
-        path: &str,
-    ) -> Result<Option<DeploymentState>, Error> {
+    /// The subgraph name or deployment that the URL `path` refers to, if
+    /// it is well-formed. Whether the name or deployment exists is only
+    /// checked when an operation starts so that we can tell clients about
+    /// it with a GraphQL error
+    fn target_from_url_path(path: &str) -> Option<QueryTarget> {
         fn target_from_name(name: String) -> Option<QueryTarget> {
             SubgraphName::new(name).ok().map(QueryTarget::Name)
         }
@@ -44,43 +130,25 @@ where
             DeploymentHash::new(id).ok().map(QueryTarget::Deployment)
         }
 
-        async fn state<S: QueryStoreManager>(
-            store: Arc<S>,
-            target: Option<QueryTarget>,
-        ) -> Option<DeploymentState> {
-            let target = match target {
-                Some(target) => target,
-                None => return None,
-            };
-            match store.query_store(target, false).await.ok() {
-                Some(query_store) => query_store.deployment_state().await.ok(),
-                None => None,
-            }
-        }
-
         let path_segments = {
             let mut segments = path.split('/');
 
             // Remove leading '/'
             let first_segment = segments.next();
             if first_segment != Some("") {
-                return Ok(None);
+                return None;
             }
 
             segments.collect::<Vec<_>>()
         };
 
         match path_segments.as_slice() {
-            &["subgraphs", "id", subgraph_id] => {
-                Ok(state(store, target_from_id(subgraph_id)).await)
-            }
+            &["subgraphs", "id", subgraph_id] => target_from_id(subgraph_id),
             &["subgraphs", "name", _] | &["subgraphs", "name", _, _] => {
-                Ok(state(store, target_from_name(path_segments[2..].join("/"))).await)
-            }
-            &["subgraphs", "network", _, _] => {
-                Ok(state(store, target_from_name(path_segments[1..].join("/"))).await)
+                target_from_name(path_segments[2..].join("/"))
             }
-            _ => Ok(None),
+            &["subgraphs", "network", _, _] => target_from_name(path_segments[1..].join("/")),
+            _ => None,
         }
     }
 }
@@ -103,8 +171,8 @@ where
             .expect("Failed to bind WebSocket port");
 
         loop {
-            let stream = match socket.accept().await {
-                Ok((stream, _)) => stream,
+            let (stream, peer) = match socket.accept().await {
+                Ok(accepted) => accepted,
                 Err(e) => {
                     trace!(self.logger, "Connection error: {}", e);
                     continue;
@@ -114,93 +182,93 @@ where
             let logger2 = self.logger.clone();
             let graphql_runner = self.graphql_runner.clone();
             let store = self.store.clone();
+            let metrics = self.metrics.clone();
+
+            // Refuse the connection during the handshake if there are too
+            // many connections from the same address already
+            let open_connection = OpenConnection::open(peer.ip(), &self.connections, &self.metrics);
+            let too_many_connections = open_connection.is_none();
+            if too_many_connections {
+                self.metrics.rejected(Rejection::ConnectionsPerIp);
+            }
+
+            // Subgraph name or deployment that the request is for
+            let target = Arc::new(Mutex::new(None));
+            let accept_target = target.clone();
 
-            // Subgraph that the request is resolved to (if any)
-            let subgraph_id = Arc::new(Mutex::new(None));
-            let accept_subgraph_id = subgraph_id.clone();
+            // Messages and frames that are larger than the limit make the
+            // connection fail rather than being buffered
+            let config = WebSocketConfig {
+                max_message_size: Some(*GRAPHQL_WS_MAX_MESSAGE_SIZE),
+                max_frame_size: Some(*GRAPHQL_WS_MAX_MESSAGE_SIZE),
+                ..WebSocketConfig::default()
+            };
+
+            let callback = move |request: &Request, mut response: Response<()>| {
+                if too_many_connections {
+                    return Err(Response::builder()
+                        .status(StatusCode::TOO_MANY_REQUESTS)
+                        .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                        .header(CONTENT_TYPE, "text/plain")
+                        .body(Some(format!("Too many connections from {}", peer.ip())))
+                        .unwrap());
+                }
 
-            accept_hdr_async(stream, move |request: &Request, mut response: Response<()>| {
                 // Try to obtain the subgraph ID or name from the URL path.
                 // Return a 404 if the URL path contains no name/ID segment.
                 let path = request.uri().path();
-
-                // `block_in_place` is not recommended but in this case we have no alternative since
-                // we're in an async context but `tokio_tungstenite` doesn't allow this callback
-                // to be a future.
-                let state = tokio::task::block_in_place(|| {
-                    graph::block_on(Self::subgraph_id_from_url_path(
-                        store.clone(),
-                        path.as_ref(),
-                    ))
-                })
-                .map_err(|e| {
-                    error!(
-                        logger,
-                        "Error resolving subgraph ID from URL path";
-                        "error" => e.to_string()
-                    );
-
+                let target = Self::target_from_url_path(path).ok_or_else(|| {
+                    debug!(logger, "Failed to establish WS connection, invalid URL path";
+                                   "path" => path);
                     Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .status(StatusCode::NOT_FOUND)
                         .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
                         .header(CONTENT_TYPE, "text/plain")
                         .body(None)
                         .unwrap()
-                })
-                .and_then(|state| {
-                    state.ok_or_else(|| {
-                        Response::builder()
-                            .status(StatusCode::NOT_FOUND)
-                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                            .header(CONTENT_TYPE, "text/plain")
-                            .body(None)
-                            .unwrap()
-                    })
                 })?;
 
-                // Check if the subgraph is deployed
-                if !state.is_deployed() {
-                        error!(logger, "Failed to establish WS connection, no data found for subgraph";
-                                        "subgraph_id" => state.id.to_string(),
-                        );
-                        return Err(Response::builder()
-                            .status(StatusCode::NOT_FOUND)
-                            .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
-                            .header(CONTENT_TYPE, "text/plain")
-                            .body(None)
-                            .unwrap());
-                    }
-
-                *accept_subgraph_id.lock().unwrap() = Some(state.id);
+                *accept_target.lock().unwrap() = Some(target);
                 response.headers_mut().insert(
                     "Sec-WebSocket-Protocol",
                     HeaderValue::from_static("graphql-ws"),
                 );
                 Ok(response)
-            })
-            .then(move |result| async move {
-                match result {
-                    Ok(ws_stream) => {
-                        // Obtain the subgraph ID or name that we resolved the request to
-                        let subgraph_id = subgraph_id.lock().unwrap().clone().unwrap();
-
-                        // Spawn a GraphQL over WebSocket connection
-                        let service = GraphQlConnection::new(
-                            &logger2,
-                            subgraph_id,
-                            ws_stream,
-                            graphql_runner.clone(),
-                        );
-
-                        graph::spawn_allow_panic(service.into_future().compat());
-                    }
-                    Err(e) => {
-                        // We gracefully skip over failed connection attempts rather
-                        // than tearing down the entire stream
-                        trace!(logger2, "Failed to establish WebSocket connection: {}", e);
+            };
+
+            accept_hdr_async_with_config(stream, callback, Some(config))
+                .then(move |result| async move {
+                    match result {
+                        Ok(ws_stream) => {
+                            // Obtain the subgraph ID or name that the request is for
+                            let target = target.lock().unwrap().clone().unwrap();
+
+                            // Spawn a GraphQL over WebSocket connection
+                            let service = GraphQlConnection::new(
+                                &logger2,
+                                target,
+                                ws_stream,
+                                graphql_runner.clone(),
+                                store,
+                                metrics,
+                            );
+
+                            // The connection counts as open until it is closed
+                            graph::spawn_allow_panic(service.into_future().compat().map(
+                                move |result| {
+                                    drop(open_connection);
+                                    result
+                                },
+                            ));
+                        }
+                        Err(e) => {
+                            // We gracefully skip over failed connection attempts rather
+                            // than tearing down the entire stream
+                            trace!(logger2, "Failed to establish WebSocket connection: {}", e);
+                        }
                     }
-                }
-            }).await
+                })
+                .await
         }
     }
 }
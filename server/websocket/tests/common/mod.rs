@@ -0,0 +1,261 @@
+//! A subscription server with a query runner and store that only know
+//! the subgraphs `test/healthy`, `test/failed` and `test/data`, and helpers
+//! to talk to it
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use graph::data::{
+    graphql::effort::LoadManager,
+    query::{QueryResults, QueryTarget},
+};
+use graph::prelude::serde_json::json;
+use graph::prelude::web3::types::H256;
+use graph::prelude::{SubscriptionServer as _, *};
+use graph::prometheus::Registry;
+use graph_core::MetricsRegistry;
+use graph_server_websocket::SubscriptionServer;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+/// The deployment of the subgraph `test/failed`, which has failed
+pub const FAILED: &str = "QmFailed";
+/// The deployment of the subgraph `test/healthy`
+pub const HEALTHY: &str = "QmHealthy";
+/// The deployment of the subgraph `test/data`, whose subscriptions send
+/// one result right away
+pub const DATA: &str = "QmData";
+
+/// A query runner that refuses subscriptions to the failed deployment
+/// like the real runner does for subscriptions with `subgraphError: deny`
+pub struct TestGraphQlRunner;
+
+#[async_trait]
+impl GraphQlRunner for TestGraphQlRunner {
+    async fn run_query_with_complexity(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _complexity: Option<u64>,
+        _max_depth: Option<u8>,
+        _max_first: Option<u32>,
+        _max_skip: Option<u32>,
+    ) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_subscription(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        target: QueryTarget,
+    ) -> Result<SubscriptionResult, SubscriptionError> {
+        match target {
+            QueryTarget::Deployment(id) if id.as_str() == FAILED => {
+                Err(SubscriptionError::IndexingError)
+            }
+            QueryTarget::Deployment(id) if id.as_str() == DATA => {
+                let mut data = BTreeMap::new();
+                data.insert("users".to_string(), q::Value::List(vec![]));
+                let result = Arc::new(QueryResult::new(data));
+                Ok(Box::new(
+                    futures03::stream::iter(vec![result]).chain(futures03::stream::pending()),
+                ))
+            }
+            _ => Ok(Box::new(futures03::stream::pending())),
+        }
+    }
+
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        target: QueryTarget,
+    ) -> Result<u64, SubscriptionError> {
+        match target {
+            QueryTarget::Deployment(id) if id.as_str() == FAILED => {
+                Err(SubscriptionError::IndexingError)
+            }
+            _ => Ok(1),
+        }
+    }
+
+    async fn api_schema(
+        self: Arc<Self>,
+        _target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        unimplemented!()
+    }
+
+    fn load_manager(&self) -> Arc<LoadManager> {
+        unimplemented!()
+    }
+}
+
+/// A query store that only knows the state of its deployment
+pub struct TestQueryStore(DeploymentHash);
+
+#[async_trait]
+impl QueryStore for TestQueryStore {
+    fn find_query_values(
+        &self,
+        _query: EntityQuery,
+    ) -> Result<Vec<BTreeMap<String, q::Value>>, QueryExecutionError> {
+        unimplemented!()
+    }
+
+    async fn is_deployment_synced(&self) -> Result<bool, Error> {
+        unimplemented!()
+    }
+
+    fn block_ptr(&self) -> Result<Option<BlockPtr>, Error> {
+        unimplemented!()
+    }
+
+    fn latest_block_ptr(&self) -> Result<Option<BlockPtr>, Error> {
+        unimplemented!()
+    }
+
+    fn block_number(&self, _block_hash: H256) -> Result<Option<BlockNumber>, StoreError> {
+        unimplemented!()
+    }
+
+    fn wait_stats(&self) -> PoolWaitStats {
+        unimplemented!()
+    }
+
+    async fn has_non_fatal_errors(&self, _block: Option<BlockNumber>) -> Result<bool, StoreError> {
+        unimplemented!()
+    }
+
+    async fn deployment_state(&self) -> Result<DeploymentState, QueryExecutionError> {
+        Ok(DeploymentState {
+            id: self.0.clone(),
+            reorg_count: 0,
+            max_reorg_depth: 0,
+            latest_ethereum_block_number: 1,
+            failed: self.0.as_str() == FAILED,
+        })
+    }
+
+    fn api_schema(&self) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        unimplemented!()
+    }
+
+    fn network_name(&self) -> &str {
+        unimplemented!()
+    }
+
+    async fn query_permit(&self) -> tokio::sync::OwnedSemaphorePermit {
+        unimplemented!()
+    }
+}
+
+/// A store with the subgraphs `test/healthy`, `test/failed` and
+/// `test/data`, which reports unknown names and deployments like the real
+/// store
+pub struct TestStore;
+
+#[async_trait]
+impl QueryStoreManager for TestStore {
+    async fn query_store(
+        &self,
+        target: QueryTarget,
+        _for_subscription: bool,
+    ) -> Result<Arc<dyn QueryStore + Send + Sync>, QueryExecutionError> {
+        let id = match target {
+            QueryTarget::Name(name) => match name.as_str() {
+                "test/healthy" => HEALTHY,
+                "test/failed" => FAILED,
+                "test/data" => DATA,
+                _ => {
+                    return Err(StoreError::QueryExecutionError(format!(
+                        "Subgraph `{}` not found",
+                        name.as_str()
+                    ))
+                    .into())
+                }
+            },
+            QueryTarget::Deployment(id) => match id.as_str() {
+                HEALTHY => HEALTHY,
+                FAILED => FAILED,
+                DATA => DATA,
+                _ => return Err(StoreError::DeploymentNotFound(id.to_string()).into()),
+            },
+        };
+        Ok(Arc::new(TestQueryStore(DeploymentHash::new(id).unwrap())))
+    }
+}
+
+pub type Client = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Start a subscription server on `port` and wait until it listens
+pub async fn start_server(port: u16) {
+    let logger = Logger::root(slog::Discard, o!());
+    let metrics_registry = Arc::new(MetricsRegistry::new(
+        logger.clone(),
+        Arc::new(Registry::new()),
+    ));
+    let server = SubscriptionServer::new(
+        &logger,
+        metrics_registry,
+        Arc::new(TestGraphQlRunner),
+        Arc::new(TestStore),
+    );
+    tokio::spawn(server.serve(port));
+
+    let start = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!("timed out waiting for the server to listen");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+pub async fn connect(port: u16, path: &str) -> Result<Client, WsError> {
+    connect_async(format!("ws://127.0.0.1:{}{}", port, path))
+        .await
+        .map(|(client, _)| client)
+}
+
+pub async fn send(client: &mut Client, msg: serde_json::Value) {
+    client.send(WsMessage::text(msg.to_string())).await.unwrap();
+}
+
+/// The next message that the server sends, or `None` if it closed the
+/// connection
+pub async fn receive(client: &mut Client) -> Option<serde_json::Value> {
+    let msg = tokio::time::timeout(Duration::from_secs(10), client.next())
+        .await
+        .expect("timed out waiting for a message");
+    match msg {
+        Some(Ok(WsMessage::Text(text))) => Some(serde_json::from_str(&text).unwrap()),
+        Some(Ok(WsMessage::Close(_))) | Some(Err(_)) | None => None,
+        Some(Ok(msg)) => panic!("unexpected message {:?}", msg),
+    }
+}
+
+pub fn start(id: &str, query: &str) -> serde_json::Value {
+    json!({ "type": "start", "id": id, "payload": { "query": query } })
+}
+
+pub fn error(id: &str, message: &str) -> serde_json::Value {
+    json!({ "type": "error", "id": id, "payload": { "errors": [{ "message": message }] } })
+}
+
+/// Check that the connection is still open and usable
+pub async fn assert_open(client: &mut Client) {
+    send(client, json!({ "type": "connection_init" })).await;
+    assert_eq!(
+        Some(json!({ "type": "connection_ack" })),
+        receive(client).await
+    );
+}
+
+pub const QUERY: &str = "subscription { users { id } }";
@@ -0,0 +1,156 @@
+use graph::components::server::body::GRAPHQL_WS_MAX_MESSAGE_SIZE;
+use graph::prelude::serde_json::json;
+use graph::prelude::*;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+
+mod common;
+
+use common::*;
+
+#[test]
+fn unknown_subgraphs_are_reported_as_errors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18102;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/heatlhy").await.unwrap();
+        assert_open(&mut client).await;
+        send(&mut client, start("1", QUERY)).await;
+        assert_eq!(
+            Some(error(
+                "1",
+                "Store error: query execution failed: Subgraph `test/heatlhy` not found"
+            )),
+            receive(&mut client).await
+        );
+        assert_open(&mut client).await;
+
+        let mut client = connect(PORT, "/subgraphs/id/QmUnknown").await.unwrap();
+        send(&mut client, start("1", QUERY)).await;
+        assert_eq!(
+            Some(error("1", "Store error: deployment not found: QmUnknown")),
+            receive(&mut client).await
+        );
+        assert_open(&mut client).await;
+    });
+}
+
+#[test]
+fn failed_deployments_are_reported_as_errors() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18103;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/failed").await.unwrap();
+        send(&mut client, start("1", QUERY)).await;
+        assert_eq!(
+            Some(error("1", "indexing_error")),
+            receive(&mut client).await
+        );
+        assert_open(&mut client).await;
+    });
+}
+
+#[test]
+fn invalid_operations_keep_the_connection() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18104;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/healthy").await.unwrap();
+        send(&mut client, start("1", "subscription {")).await;
+        let msg = receive(&mut client).await.unwrap();
+        assert_eq!("error", msg["type"]);
+        assert!(msg["payload"]["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid query: subscription {"));
+        assert_open(&mut client).await;
+
+        // Starting an operation twice is refused, but the first one keeps
+        // running
+        send(&mut client, start("2", QUERY)).await;
+        send(&mut client, start("2", QUERY)).await;
+        assert_eq!(
+            Some(error("2", "Operation with ID already started: 2")),
+            receive(&mut client).await
+        );
+        send(&mut client, json!({ "type": "stop", "id": "2" })).await;
+        assert_eq!(
+            Some(json!({ "type": "complete", "id": "2" })),
+            receive(&mut client).await
+        );
+    });
+}
+
+#[test]
+fn protocol_violations_close_the_connection() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18105;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/healthy").await.unwrap();
+        client.send(WsMessage::text("not json")).await.unwrap();
+        assert_eq!(None, receive(&mut client).await);
+
+        // Paths that can not name a subgraph are refused during the
+        // handshake
+        match connect(PORT, "/subgraphs/nonsense").await {
+            Err(WsError::Http(response)) => assert_eq!(404, response.status().as_u16()),
+            Err(e) => panic!("expected a 404, got {}", e),
+            Ok(_) => panic!("expected a 404, but the connection was accepted"),
+        }
+    });
+}
+
+#[test]
+fn oversized_messages_close_the_connection() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18106;
+        start_server(PORT).await;
+
+        // A message right at the limit is fine
+        let mut client = connect(PORT, "/subgraphs/name/test/healthy").await.unwrap();
+        let init = json!({ "type": "connection_init", "payload": {} }).to_string();
+        let padded = format!(
+            "{}{}",
+            init,
+            " ".repeat(*GRAPHQL_WS_MAX_MESSAGE_SIZE - init.len())
+        );
+        client.send(WsMessage::text(padded)).await.unwrap();
+        assert_eq!(
+            Some(json!({ "type": "connection_ack" })),
+            receive(&mut client).await
+        );
+
+        // One byte more, and the server hangs up
+        let oversized = format!("{} ", " ".repeat(*GRAPHQL_WS_MAX_MESSAGE_SIZE));
+        client.send(WsMessage::text(oversized)).await.unwrap();
+        assert_eq!(None, receive(&mut client).await);
+    });
+}
+
+#[test]
+fn operations_per_connection_are_unlimited_by_default() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18107;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/healthy").await.unwrap();
+        for id in 0..200 {
+            send(&mut client, start(&id.to_string(), QUERY)).await;
+        }
+        // All operations are running, and none of them was refused
+        send(&mut client, start("0", QUERY)).await;
+        assert_eq!(
+            Some(error("0", "Operation with ID already started: 0")),
+            receive(&mut client).await
+        );
+    });
+}
@@ -0,0 +1,34 @@
+use graph::prelude::serde_json::json;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn data_names_the_deployment() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18109;
+        start_server(PORT).await;
+
+        let expected = json!({
+            "type": "data",
+            "id": "1",
+            "payload": {
+                "data": { "users": [] },
+                "extensions": { "deployment": DATA }
+            }
+        });
+
+        // Both for subscriptions by name and by deployment id
+        let mut client = connect(PORT, "/subgraphs/name/test/data").await.unwrap();
+        send(&mut client, start("1", QUERY)).await;
+        assert_eq!(Some(expected.clone()), receive(&mut client).await);
+
+        let mut client = connect(PORT, &format!("/subgraphs/id/{}", DATA))
+            .await
+            .unwrap();
+        send(&mut client, start("1", QUERY)).await;
+        assert_eq!(Some(expected), receive(&mut client).await);
+    });
+}
@@ -0,0 +1,45 @@
+use graph::prelude::serde_json::json;
+use graph::prelude::*;
+
+mod common;
+
+use common::*;
+
+#[test]
+fn operations_over_the_limit_are_refused() {
+    // The limit is read when the first operation is started, and this is
+    // the only test in this file
+    std::env::set_var("GRAPH_GRAPHQL_MAX_OPERATIONS_PER_CONNECTION", "2");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        const PORT: u16 = 18108;
+        start_server(PORT).await;
+
+        let mut client = connect(PORT, "/subgraphs/name/test/healthy").await.unwrap();
+        send(&mut client, start("1", QUERY)).await;
+        send(&mut client, start("2", QUERY)).await;
+        send(&mut client, start("3", QUERY)).await;
+        assert_eq!(
+            Some(error(
+                "3",
+                "Reached the limit of 2 operations per connection"
+            )),
+            receive(&mut client).await
+        );
+        assert_open(&mut client).await;
+
+        // Stopping an operation makes room for another one
+        send(&mut client, json!({ "type": "stop", "id": "2" })).await;
+        assert_eq!(
+            Some(json!({ "type": "complete", "id": "2" })),
+            receive(&mut client).await
+        );
+        send(&mut client, start("3", QUERY)).await;
+        send(&mut client, start("3", QUERY)).await;
+        assert_eq!(
+            Some(error("3", "Operation with ID already started: 3")),
+            receive(&mut client).await
+        );
+    });
+}
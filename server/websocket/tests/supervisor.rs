@@ -0,0 +1,207 @@
+use std::fmt;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use graph::data::{
+    graphql::effort::LoadManager,
+    query::{QueryResults, QueryTarget},
+};
+use graph::prelude::{SubscriptionServer as _, *};
+use graph::prometheus::Registry;
+use graph::slog::{Drain, Level, OwnedKVList, Record};
+use graph::{EssentialTaskPolicy, TaskSupervisor};
+use graph_core::MetricsRegistry;
+use graph_server_websocket::SubscriptionServer;
+
+use tokio::sync::Notify;
+
+const PORT: u16 = 18101;
+
+/// A query runner that must never be asked to run anything
+pub struct TestGraphQlRunner;
+
+#[async_trait]
+impl GraphQlRunner for TestGraphQlRunner {
+    async fn run_query_with_complexity(
+        self: Arc<Self>,
+        _query: Query,
+        _target: QueryTarget,
+        _complexity: Option<u64>,
+        _max_depth: Option<u8>,
+        _max_first: Option<u32>,
+        _max_skip: Option<u32>,
+    ) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_query(self: Arc<Self>, _query: Query, _target: QueryTarget) -> QueryResults {
+        unimplemented!();
+    }
+
+    async fn run_subscription(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<SubscriptionResult, SubscriptionError> {
+        unimplemented!();
+    }
+
+    async fn subscription_complexity(
+        self: Arc<Self>,
+        _subscription: Subscription,
+        _target: QueryTarget,
+    ) -> Result<u64, SubscriptionError> {
+        unimplemented!();
+    }
+
+    async fn api_schema(
+        self: Arc<Self>,
+        _target: QueryTarget,
+    ) -> Result<Arc<ApiSchema>, QueryExecutionError> {
+        unimplemented!()
+    }
+
+    fn load_manager(&self) -> Arc<LoadManager> {
+        unimplemented!()
+    }
+}
+
+/// A store that must never be asked for anything
+pub struct TestStore;
+
+#[async_trait]
+impl QueryStoreManager for TestStore {
+    async fn query_store(
+        &self,
+        _target: QueryTarget,
+        _for_subscription: bool,
+    ) -> Result<Arc<dyn QueryStore + Send + Sync>, QueryExecutionError> {
+        unimplemented!()
+    }
+}
+
+/// The task a log record is about
+struct Task(Option<String>);
+
+impl graph::slog::Serializer for Task {
+    fn emit_arguments(
+        &mut self,
+        key: graph::slog::Key,
+        val: &fmt::Arguments,
+    ) -> graph::slog::Result {
+        if key == "task" {
+            self.0 = Some(val.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Remembers the level, message and task of every log record
+struct Recorder(Arc<Mutex<Vec<(Level, String, Option<String>)>>>);
+
+impl Drain for Recorder {
+    type Ok = ();
+    type Err = graph::slog::Never;
+
+    fn log(&self, record: &Record, _values: &OwnedKVList) -> Result<(), Self::Err> {
+        let mut task = Task(None);
+        record.kv().serialize(record, &mut task).unwrap();
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.msg().to_string(), task.0));
+        Ok(())
+    }
+}
+
+/// Wait until `cond` holds, and fail if that takes too long
+async fn wait_for(what: &str, cond: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !cond() {
+        if start.elapsed() > Duration::from_secs(10) {
+            panic!("timed out waiting for {}", what);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn listening() -> bool {
+    TcpStream::connect(("127.0.0.1", PORT)).is_ok()
+}
+
+#[test]
+fn websocket_server_is_restarted_after_panic() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let logger = Logger::root(Recorder(records.clone()).fuse(), o!());
+        let prometheus_registry = Arc::new(Registry::new());
+        let metrics_registry = Arc::new(MetricsRegistry::new(
+            logger.clone(),
+            prometheus_registry.clone(),
+        ));
+        let supervisor = Arc::new(
+            TaskSupervisor::new(
+                &logger,
+                metrics_registry.clone(),
+                EssentialTaskPolicy::Restart,
+            )
+            .unwrap(),
+        );
+
+        let server = SubscriptionServer::new(
+            &logger,
+            metrics_registry,
+            Arc::new(TestGraphQlRunner),
+            Arc::new(TestStore),
+        );
+        let kill = Arc::new(Notify::new());
+        let starts = Arc::new(AtomicUsize::new(0));
+
+        // Run the server until it gets killed
+        supervisor.spawn_essential("websocket-server", {
+            let kill = kill.clone();
+            let starts = starts.clone();
+            move || {
+                let server = server.clone();
+                let kill = kill.clone();
+                starts.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let killed = Box::pin(kill.notified());
+                    match futures03::future::select(server.serve(PORT), killed).await {
+                        futures03::future::Either::Left(_) => (),
+                        futures03::future::Either::Right(_) => panic!("websocket server killed"),
+                    }
+                }
+            }
+        });
+        wait_for("the server to listen", listening).await;
+
+        kill.notify_one();
+        wait_for("the server to restart", || {
+            starts.load(Ordering::SeqCst) == 2
+        })
+        .await;
+        wait_for("the restarted server to listen", listening).await;
+
+        let panics: Vec<_> = records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(level, msg, _)| *level == Level::Critical && msg == "Task panicked")
+            .map(|(_, _, task)| task.clone())
+            .collect();
+        assert_eq!(vec![Some("websocket-server".to_string())], panics);
+
+        let family = prometheus_registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == "task_panics_total")
+            .expect("task_panics_total is registered");
+        let metric = &family.get_metric()[0];
+        assert_eq!("websocket-server", metric.get_label()[0].get_value());
+        assert_eq!(1.0, metric.get_counter().get_value());
+    });
+}
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+    add column writer_node text default null,
+    add column writer_lease_expires timestamptz default null;
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+    drop column block_latency_p50,
+    drop column block_latency_p95;
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+    add column block_latency_p50 float8 default null,
+    add column block_latency_p95 float8 default null;
@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+    drop column archive_blocks,
+    drop column archived_block;
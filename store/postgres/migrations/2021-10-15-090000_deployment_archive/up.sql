@@ -0,0 +1,3 @@
+alter table subgraphs.subgraph_deployment
+    add column archive_blocks int4 default null,
+    add column archived_block int4 default null;
@@ -0,0 +1,4 @@
+create table node_heartbeat(
+    node_id         text primary key,
+    last_heartbeat  timestamptz not null default now()
+);
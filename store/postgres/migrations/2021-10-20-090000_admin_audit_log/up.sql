@@ -0,0 +1,18 @@
+-- Every call to the admin JSON-RPC server that changes something. A row is
+-- written before the call is made; once the call has finished, `succeeded`,
+-- `error` and `finished_at` are filled in. Rows are never changed after
+-- that, and never deleted
+create table admin_audit_log(
+    id          bigserial primary key,
+    method      text not null,
+    params      jsonb not null,
+    caller      text,
+    source      text,
+    succeeded   boolean,
+    error       text,
+    started_at  timestamptz not null default now(),
+    finished_at timestamptz
+);
+
+create index admin_audit_log_method
+    on admin_audit_log(method, id);
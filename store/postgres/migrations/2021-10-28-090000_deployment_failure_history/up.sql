@@ -0,0 +1,2 @@
+alter table subgraphs.subgraph_deployment
+    add column failure_history jsonb not null default '[]';
@@ -0,0 +1,2 @@
+alter table subgraphs.copy_table_state
+    drop column rows_copied;
@@ -0,0 +1,2 @@
+alter table subgraphs.copy_table_state
+    add column rows_copied int8 not null default 0;
@@ -0,0 +1,4 @@
+drop table subgraphs.subgraph_version_switch;
+
+alter table subgraphs.subgraph_deployment
+    drop column synced_checks;
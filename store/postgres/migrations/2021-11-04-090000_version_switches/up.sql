@@ -0,0 +1,17 @@
+alter table subgraphs.subgraph_deployment
+    add column synced_checks int4 not null default 0;
+
+-- Every time the pending version of a subgraph became its current version.
+-- `subgraph` is the name of the subgraph, and the deployments are the
+-- deployment hashes of the old and new current version
+create table subgraphs.subgraph_version_switch(
+    id             bigserial primary key,
+    subgraph       text not null,
+    old_deployment text,
+    new_deployment text not null,
+    block_number   int4,
+    switched_at    timestamptz not null default now()
+);
+
+create index subgraph_version_switch_subgraph
+    on subgraphs.subgraph_version_switch(subgraph, id);
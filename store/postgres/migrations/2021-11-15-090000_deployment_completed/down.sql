@@ -0,0 +1,34 @@
+update subgraphs.subgraph_deployment
+   set health = 'healthy'
+ where health = 'completed';
+
+alter type subgraphs.health rename to health_old;
+
+create type subgraphs.health
+    as enum ('failed', 'healthy', 'unhealthy');
+
+alter table subgraphs.subgraph_deployment
+    alter column health type subgraphs.health
+    using health::text::subgraphs.health;
+
+-- The foreign tables that map `subgraph_deployment` from other shards use
+-- the old type, too. Drop them so that the type can be dropped; they are
+-- imported again with the new type when the node maps the metadata of
+-- other shards right after running migrations
+do $$
+declare
+    tbl regclass;
+begin
+    for tbl in
+        select distinct a.attrelid::regclass
+          from pg_attribute a, pg_class c
+         where a.attrelid = c.oid
+           and c.relkind = 'f'
+           and a.atttypid = 'subgraphs.health_old'::regtype
+    loop
+        execute format('drop foreign table %s', tbl);
+    end loop;
+end;
+$$;
+
+drop type subgraphs.health_old;
@@ -0,0 +1,4 @@
+alter table subgraphs.subgraph_deployment
+    drop column revert_from,
+    drop column revert_target,
+    drop column revert_reached;
@@ -0,0 +1,9 @@
+-- A revert that spans many blocks is done in chunks; while it is in
+-- progress, the deployment's head is already at revert_target, but the
+-- entity data is only consistent with block revert_reached and still
+-- contains versions from later blocks. revert_from is the head the revert
+-- started from
+alter table subgraphs.subgraph_deployment
+    add column revert_from int,
+    add column revert_target int,
+    add column revert_reached int;
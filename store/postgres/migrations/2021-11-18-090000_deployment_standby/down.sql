@@ -0,0 +1,2 @@
+alter table subgraphs.subgraph_deployment_assignment
+    drop column standby_node;
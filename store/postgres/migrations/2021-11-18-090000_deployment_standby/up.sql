@@ -0,0 +1,4 @@
+-- The node that keeps a warm standby for the deployment and takes over
+-- indexing it when the node it is assigned to dies
+alter table subgraphs.subgraph_deployment_assignment
+    add column standby_node text;
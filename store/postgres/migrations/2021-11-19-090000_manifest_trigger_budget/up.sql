@@ -0,0 +1,5 @@
+-- The most bytes that passing one trigger to a handler could allocate on
+-- the WASM heap when the deployment was created; null for deployments that
+-- were created before the budget existed
+alter table subgraphs.subgraph_manifest
+    add column max_trigger_bytes int8;
@@ -0,0 +1,4 @@
+-- When the deployment last processed a block; null for deployments that
+-- have not processed a block since the column was added
+alter table subgraphs.subgraph_deployment
+    add column last_processed_at timestamptz;
@@ -0,0 +1,4 @@
+-- Reloads of the mappings of a deployment in development, as a list of
+-- status::MappingsReload
+alter table subgraphs.subgraph_deployment
+    add column mappings_reloads jsonb not null default '[]';
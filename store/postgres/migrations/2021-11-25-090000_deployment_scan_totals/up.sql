@@ -0,0 +1,13 @@
+-- How much data each deployment has requested from its chain; see
+-- graph::data::subgraph::ScanTotals
+create table subgraphs.subgraph_deployment_scan(
+   id          int primary key
+                   references subgraphs.subgraph_deployment(id)
+                          on delete cascade,
+   blocks      int8 not null default 0,
+   logs        int8 not null default 0,
+   traces      int8 not null default 0,
+   eth_calls   int8 not null default 0,
+   bytes       int8 not null default 0,
+   reset_at    timestamptz
+);
@@ -0,0 +1,12 @@
+-- Labels that operators attach to deployments, like `team: defi`. They
+-- are kept by deployment hash in the primary so that they can be used
+-- when a deployment is placed, before it exists in any shard
+create table subgraphs.deployment_label(
+    deployment text not null,
+    key        text not null,
+    value      text not null,
+    primary key(deployment, key)
+);
+
+create index deployment_label_key_value
+    on subgraphs.deployment_label(key, value);
@@ -0,0 +1,5 @@
+-- The head of a deployment only moves to the target of a chunked revert
+-- once all its entity data has been reverted; we need the hash of the
+-- target block to move it there when an interrupted revert is resumed
+alter table subgraphs.subgraph_deployment
+    add column revert_target_hash bytea;
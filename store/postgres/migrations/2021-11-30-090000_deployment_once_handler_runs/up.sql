@@ -0,0 +1,4 @@
+-- The data sources whose handlers that only run once have run, and the
+-- block in which they ran, as a list of OnceHandlerRun
+alter table subgraphs.subgraph_deployment
+    add column once_handler_runs jsonb not null default '[]';
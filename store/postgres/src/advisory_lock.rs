@@ -5,6 +5,11 @@
 //! documentation](https://www.postgresql.org/docs/current/explicit-locking.html#ADVISORY-LOCKS)
 //! has more details on advisory locks.
 //!
+//! All of these are session locks. Code that takes one must keep the
+//! connection checked out until it has released the lock again: the pool
+//! only closes connections that are not checked out, and closing a
+//! connection that still holds a lock would silently release it.
+//!
 //! We use the following 64 bit locks:
 //!   * 1,2: to synchronize on migratons
 //!
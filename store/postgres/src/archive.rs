@@ -0,0 +1,244 @@
+//! Archive old entity versions. Entity versions whose block range was
+//! closed a while ago are only needed for queries that use a `block`
+//! constraint. For deployments that have archiving turned on, these
+//! versions are moved from the entity tables into archive tables so that
+//! the entity tables, and their indexes, only contain recent history.
+//!
+//! For a table `thing`, the archive table is `thing$archive` and the view
+//! `thing$all` combines both tables. Queries at blocks before the
+//! deployment's `archived_block` read from the view, all other queries
+//! only read from `thing`. The archive tables only have the indexes needed
+//! to look up entities by id and block range, and store rows with a low
+//! `toast_tuple_target` so that Postgres compresses as much data as
+//! possible.
+//!
+//! Since versions are moved with a single statement that deletes them
+//! from `thing` and inserts them into `thing$archive`, each version is
+//! always visible in exactly one of the two tables, even if the mover
+//! crashes in the middle of its work.
+//!
+//! The `BlockInfo` entities that the node writes for deployments with the
+//! `blockInfo` feature are never updated, and their block range never
+//! gets closed. They are archived once the block they describe is at or
+//! before the horizon so that they age out of the entity tables together
+//! with the rest of the history. After that, only queries at blocks
+//! before the horizon see them.
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Write;
+use std::sync::Arc;
+
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::{BigInt, Integer};
+use diesel::{pg::PgConnection, sql_query, RunQueryDsl};
+use graph::data::subgraph::schema::{BLOCK_INFO_OBJECT, POI_OBJECT};
+use graph::prelude::{lazy_static, BlockNumber, CounterVec, GaugeVec, MetricsRegistry, StoreError};
+
+use crate::primary::Site;
+use crate::relational::{Layout, SqlName, Table};
+
+lazy_static! {
+    /// The number of `vid` values that one batch of the archiver looks at
+    static ref ARCHIVE_BATCH_SIZE: i64 = env::var("GRAPH_ARCHIVE_BATCH_SIZE")
+        .ok()
+        .map(|s| {
+            s.parse::<i64>().unwrap_or_else(|_| {
+                panic!("GRAPH_ARCHIVE_BATCH_SIZE must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(10_000);
+}
+
+/// Return `true` if versions from `table` get archived. We never archive
+/// the proof of indexing since it needs to be read at arbitrary blocks
+fn is_archived(table: &Table) -> bool {
+    table.object != *POI_OBJECT
+}
+
+fn archive_name(table: &Table) -> SqlName {
+    SqlName::verbatim(format!("{}$archive", table.name))
+}
+
+fn view_name(table: &Table) -> SqlName {
+    SqlName::verbatim(format!("{}$all", table.name))
+}
+
+/// The name of the view that combines current and archived versions of
+/// the entities in `table`, qualified with the deployment's namespace
+fn qualified_view_name(layout: &Layout, table: &Table) -> Option<SqlName> {
+    if is_archived(table) {
+        Some(SqlName::qualified_name(
+            &layout.site.namespace,
+            &view_name(table),
+        ))
+    } else {
+        None
+    }
+}
+
+/// Create the archive tables and views for all tables in `layout` if they
+/// do not exist yet
+pub(crate) fn create(conn: &PgConnection, layout: &Layout) -> Result<(), StoreError> {
+    let nsp = &layout.site.namespace;
+    let mut ddl = String::new();
+    for table in layout.tables.values().filter(|table| is_archived(table)) {
+        let archive = SqlName::qualified_name(nsp, &archive_name(table));
+        let view = SqlName::qualified_name(nsp, &view_name(table));
+        writeln!(
+            ddl,
+            "create table if not exists {archive} (like {hot})\n    \
+             with (toast_tuple_target = 128);",
+            archive = archive,
+            hot = table.qualified_name
+        )?;
+        writeln!(
+            ddl,
+            "create index if not exists \"{name}$archive_id\"\n    \
+             on {archive} using btree(id);",
+            name = table.name,
+            archive = archive
+        )?;
+        writeln!(
+            ddl,
+            "create index if not exists \"{name}$archive_vid\"\n    \
+             on {archive} using btree(vid);",
+            name = table.name,
+            archive = archive
+        )?;
+        writeln!(
+            ddl,
+            "create or replace view {view} as\n    \
+             select * from {hot} union all select * from {archive};",
+            view = view,
+            hot = table.qualified_name,
+            archive = archive
+        )?;
+    }
+    conn.batch_execute(&ddl)?;
+    Ok(())
+}
+
+/// Move all versions in `table` that were closed at or before `horizon`
+/// into the archive table, `ARCHIVE_BATCH_SIZE` vids at a time. If
+/// `block_info` is set, the deployment has the `blockInfo` feature and
+/// its `BlockInfo` entities are moved once they were created at or before
+/// `horizon`. `progress` is called with the number of versions moved after
+/// each batch. Return the total number of versions moved
+pub(crate) fn archive_table(
+    conn: &PgConnection,
+    layout: &Layout,
+    table: &Table,
+    horizon: BlockNumber,
+    block_info: bool,
+    progress: &dyn Fn(usize),
+) -> Result<usize, StoreError> {
+    #[derive(QueryableByName)]
+    struct MaxVid {
+        #[sql_type = "BigInt"]
+        max_vid: i64,
+    }
+
+    if !is_archived(table) {
+        return Ok(0);
+    }
+
+    let archive = SqlName::qualified_name(&layout.site.namespace, &archive_name(table));
+    let max_vid = sql_query(format!(
+        "select coalesce(max(vid), -1) as max_vid from {}",
+        table.qualified_name
+    ))
+    .get_result::<MaxVid>(conn)?
+    .max_vid;
+
+    let query = format!(
+        "with moved as (\
+            delete from {hot} \
+             where vid >= $1 and vid < $2 \
+               and {bound}(block_range) <= $3 \
+            returning *) \
+         insert into {archive} select * from moved",
+        hot = table.qualified_name,
+        bound = if block_info && table.object == *BLOCK_INFO_OBJECT {
+            "lower"
+        } else {
+            "upper"
+        },
+        archive = archive
+    );
+    let mut total = 0;
+    let mut start = 0;
+    while start <= max_vid {
+        let end = start + *ARCHIVE_BATCH_SIZE;
+        let count = sql_query(&query)
+            .bind::<BigInt, _>(start)
+            .bind::<BigInt, _>(end)
+            .bind::<Integer, _>(horizon)
+            .execute(conn)?;
+        if count > 0 {
+            progress(count);
+        }
+        total += count;
+        start = end;
+    }
+    Ok(total)
+}
+
+/// Metrics that show how far archiving has progressed for each deployment
+pub struct ArchiveMetrics {
+    versions: Box<CounterVec>,
+    block: Box<GaugeVec>,
+}
+
+impl ArchiveMetrics {
+    pub fn new(registry: Arc<impl MetricsRegistry>) -> Self {
+        let versions = registry
+            .new_counter_vec(
+                "deployment_archived_versions",
+                "Counts the entity versions that were moved to archive tables",
+                vec![String::from("deployment")],
+            )
+            .expect("failed to create `deployment_archived_versions` counter");
+        let block = registry
+            .new_gauge_vec(
+                "deployment_archived_block",
+                "Entity versions that were closed at or before this block are archived",
+                vec![String::from("deployment")],
+            )
+            .expect("failed to create `deployment_archived_block` gauge");
+        ArchiveMetrics { versions, block }
+    }
+
+    pub(crate) fn versions_moved(&self, site: &Site, count: usize) {
+        self.versions
+            .with_label_values(&[site.deployment.as_str()])
+            .inc_by(count as f64);
+    }
+
+    pub(crate) fn archived_block(&self, site: &Site, block: BlockNumber) {
+        self.block
+            .with_label_values(&[site.deployment.as_str()])
+            .set(block as f64);
+    }
+}
+
+/// Return a copy of `layout` in which all archived tables read from the
+/// views that include archived versions
+pub(crate) fn with_archive(layout: &Layout) -> Layout {
+    let mut layout = layout.clone();
+    let tables: HashMap<_, _> = layout
+        .tables
+        .iter()
+        .map(
+            |(object, table)| match qualified_view_name(&layout, table) {
+                Some(view) => {
+                    let mut table = table.as_ref().clone();
+                    table.qualified_name = view;
+                    (object.clone(), Arc::new(table))
+                }
+                None => (object.clone(), table.clone()),
+            },
+        )
+        .collect();
+    layout.tables = tables;
+    layout
+}
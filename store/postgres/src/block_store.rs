@@ -4,6 +4,7 @@ use std::{
     sync::{Arc, RwLock},
 };
 
+use diesel::{connection::SimpleConnection, Connection};
 use graph::prelude::{tokio, StoreError};
 use graph::{
     components::store::BlockStore as BlockStoreTrait,
@@ -15,8 +16,9 @@ use graph::{
 };
 
 use crate::{
-    chain_head_listener::ChainHeadUpdateSender, connection_pool::ConnectionPool, ChainStore,
-    NotificationSender,
+    chain_head_listener::ChainHeadUpdateSender,
+    connection_pool::{ConnectionPool, ForeignServer},
+    ChainStore, NotificationSender,
 };
 use crate::{subgraph_store::PRIMARY_SHARD, Shard};
 
@@ -35,8 +37,8 @@ pub mod primary {
     use std::str::FromStr;
 
     use diesel::{
-        delete, insert_into, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
-        RunQueryDsl,
+        delete, insert_into, sql_query, sql_types::Text, ExpressionMethods, OptionalExtension,
+        PgConnection, QueryDsl, RunQueryDsl,
     };
     use graph::{
         constraint_violation,
@@ -143,6 +145,32 @@ pub mod primary {
         delete(chains::table.filter(chains::name.eq(name))).execute(&conn)?;
         Ok(())
     }
+
+    /// Rename the chain `old` to `new` and make all deployments that use
+    /// it refer to it by its new name. Since `deployment_schemas.network`
+    /// references `chains.name`, we add an entry for the new name first
+    /// and delete the old one once nothing refers to it anymore
+    pub(super) fn rename_chain(
+        conn: &PgConnection,
+        old: &str,
+        new: &str,
+    ) -> Result<(), StoreError> {
+        const INSERT: &str = "\
+            insert into chains(name, net_version, genesis_block_hash, shard, namespace)
+            select $2, net_version, genesis_block_hash, shard, namespace
+              from chains
+             where name = $1";
+        const UPDATE: &str = "update deployment_schemas set network = $2 where network = $1";
+
+        for query in &[INSERT, UPDATE] {
+            sql_query(*query)
+                .bind::<Text, _>(old)
+                .bind::<Text, _>(new)
+                .execute(conn)?;
+        }
+        delete(chains::table.filter(chains::name.eq(old))).execute(conn)?;
+        Ok(())
+    }
 }
 
 /// The store that chains use to maintain their state and cache often used
@@ -433,6 +461,48 @@ impl BlockStore {
 
         Ok(())
     }
+
+    /// Rename the chain `old` to `new`, both in the primary and in the
+    /// shard that holds the chain's data, and make all deployments that
+    /// index the chain use the new name. Nothing may be using the chain
+    /// while it is renamed since `graph-node` would otherwise keep
+    /// writing under the old name; it is up to the caller to make sure
+    /// that no node is running.
+    ///
+    /// All changes happen in one transaction in the primary. If the chain
+    /// is stored in a different shard, its data there is changed through
+    /// foreign tables, which `postgres_fdw` commits together with the
+    /// transaction in the primary
+    pub fn rename_chain(&self, old: &str, new: &str) -> Result<(), StoreError> {
+        let conn = self.primary.get()?;
+        conn.transaction(|| {
+            let chain = primary::find_chain(&conn, old)?
+                .ok_or_else(|| constraint_violation!("unknown chain {}", old))?;
+            if primary::find_chain(&conn, new)?.is_some() {
+                return Err(constraint_violation!(
+                    "can not rename chain {} to {} since chain {} already exists",
+                    old,
+                    new,
+                    new
+                ));
+            }
+            primary::rename_chain(&conn, old, new)?;
+            if chain.shard == *PRIMARY_SHARD {
+                ChainStore::rename_chain(&conn, "public", old, new)
+            } else {
+                let nsp = ForeignServer::map_chain_tables(&conn, &chain.shard)?;
+                ChainStore::rename_chain(&conn, &nsp, old, new)?;
+                conn.batch_execute(&format!("drop schema {} cascade", nsp))?;
+                Ok(())
+            }
+        })?;
+
+        // The next lookup of `new` will load a chain store for it from
+        // the database
+        self.stores.write().unwrap().remove(old);
+
+        Ok(())
+    }
 }
 
 impl BlockStoreTrait for BlockStore {
@@ -1,15 +1,17 @@
 use diesel::sql_types::Integer;
 use diesel::{connection::SimpleConnection, prelude::RunQueryDsl, select};
-use diesel::{insert_into, OptionalExtension};
-use diesel::{pg::PgConnection, sql_query};
 use diesel::{
-    sql_types::{Array, Nullable, Text},
+    dsl::sql,
+    sql_types::{Array, BigInt, Bool, Nullable, Text},
     ExpressionMethods, QueryDsl,
 };
+use diesel::{insert_into, OptionalExtension};
+use diesel::{pg::PgConnection, sql_query};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::iter::FromIterator;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use graph::prelude::anyhow::anyhow;
 use graph::{data::subgraph::schema::POI_TABLE, prelude::StoreError};
@@ -128,6 +130,45 @@ pub fn supports_proof_of_indexing(
     Ok(result.len() > 0)
 }
 
+/// Return the names of the composite indexes, i.e., the indexes created
+/// for `@index` directives, in `namespace`, together with a flag that
+/// indicates whether the index is valid. An index is invalid if an earlier
+/// attempt at creating it concurrently was interrupted
+pub fn composite_indexes(
+    conn: &PgConnection,
+    namespace: &Namespace,
+) -> Result<HashMap<String, bool>, StoreError> {
+    const QUERY: &str = "
+        select c.relname::text as name, i.indisvalid as valid
+          from pg_index i
+          join pg_class c on c.oid = i.indexrelid
+          join pg_namespace n on n.oid = c.relnamespace
+         where n.nspname = $1 and c.relname ~ '^cu?idx_'";
+
+    #[derive(Debug, QueryableByName)]
+    struct Index {
+        #[sql_type = "Text"]
+        name: String,
+        #[sql_type = "Bool"]
+        valid: bool,
+    }
+
+    Ok(sql_query(QUERY)
+        .bind::<Text, _>(namespace.as_str())
+        .load::<Index>(conn)?
+        .into_iter()
+        .map(|index| (index.name, index.valid))
+        .collect())
+}
+
+/// The current time according to the database server that `conn` is
+/// connected to. Inside a transaction, this is when the transaction started
+pub fn database_time(conn: &PgConnection) -> Result<SystemTime, StoreError> {
+    let micros = select(sql::<BigInt>("(extract(epoch from now()) * 1000000)::int8"))
+        .get_result::<i64>(conn)?;
+    Ok(UNIX_EPOCH + Duration::from_micros(micros.max(0) as u64))
+}
+
 pub fn current_servers(conn: &PgConnection) -> Result<Vec<String>, StoreError> {
     #[derive(QueryableByName)]
     struct Srv {
@@ -138,16 +138,28 @@ impl ChainHeadUpdateListener {
         logger: Logger,
         metrics: Arc<BlockIngestorMetrics>,
         listener: &mut NotificationListener,
-        mut receiver: Receiver<JsonNotification>,
+        receiver: Receiver<JsonNotification>,
         watchers: Arc<RwLock<BTreeMap<String, Watcher>>>,
         counter: CounterVec,
     ) {
+        // The receiver is shared so that a restarted task picks up where
+        // the previous one left off
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+
         // Process chain head updates in a dedicated task
-        graph::spawn(async move {
-            while let Some(notification) = receiver.recv().await {
-                // Create ChainHeadUpdate from JSON
-                let update: ChainHeadUpdate =
-                    match serde_json::from_value::<ChainHeadUpdate>(notification.payload.clone()) {
+        graph::spawn_essential("chain-head-listener", move || {
+            let logger = logger.clone();
+            let metrics = metrics.clone();
+            let receiver = receiver.clone();
+            let watchers = watchers.clone();
+            let counter = counter.clone();
+            async move {
+                let mut receiver = receiver.lock().await;
+                while let Some(notification) = receiver.recv().await {
+                    // Create ChainHeadUpdate from JSON
+                    let update: ChainHeadUpdate = match serde_json::from_value::<ChainHeadUpdate>(
+                        notification.payload.clone(),
+                    ) {
                         Ok(update) => {
                             let labels = [CHANNEL_NAME.as_str(), &update.network_name];
                             counter.with_label_values(&labels).inc();
@@ -164,13 +176,16 @@ impl ChainHeadUpdateListener {
                         }
                     };
 
-                // Observe the latest chain head for each network to monitor block ingestion
-                metrics
-                    .set_chain_head_number(&update.network_name, *&update.head_block_number as i64);
+                    // Observe the latest chain head for each network to monitor block ingestion
+                    metrics.set_chain_head_number(
+                        &update.network_name,
+                        *&update.head_block_number as i64,
+                    );
 
-                // If there are subscriptions for this network, notify them.
-                if let Some(watcher) = watchers.read().get(&update.network_name) {
-                    watcher.send()
+                    // If there are subscriptions for this network, notify them.
+                    if let Some(watcher) = watchers.read().get(&update.network_name) {
+                        watcher.send()
+                    }
                 }
             }
         });
@@ -2,7 +2,7 @@ use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::sql_types::Text;
-use diesel::{insert_into, update};
+use diesel::{insert_into, sql_query, update};
 use graph::prelude::web3::types::H256;
 use graph::{
     constraint_violation,
@@ -22,7 +22,7 @@ use std::{
 
 use graph::prelude::{
     transaction_receipt::LightTransactionReceipt, BlockNumber, BlockPtr, Error, EthereumBlock,
-    EthereumNetworkIdentifier, LightEthereumBlock,
+    EthereumNetworkIdentifier, EthereumUncle, LightEthereumBlock,
 };
 
 use crate::{
@@ -60,7 +60,7 @@ mod data {
         types::{FromSql, ToSql},
     };
     use diesel::{
-        sql_types::{BigInt, Bytea, Integer, Jsonb},
+        sql_types::{Array, BigInt, Bytea, Integer, Jsonb, Nullable},
         update,
     };
     use diesel_dynamic_schema as dds;
@@ -75,7 +75,7 @@ mod data {
     use std::{convert::TryFrom, io::Write};
 
     use graph::prelude::{
-        serde_json, web3::types::H256, BlockNumber, BlockPtr, Error, EthereumBlock,
+        serde_json, web3::types::H256, BlockNumber, BlockPtr, Error, EthereumBlock, EthereumUncle,
         LightEthereumBlock,
     };
 
@@ -447,9 +447,11 @@ mod data {
             Ok(())
         }
 
-        /// Insert a light block. On conflict do nothing, since we
-        /// do not want to erase transaction receipts that might already
-        /// be there
+        /// Insert a light block. On conflict, only replace the block
+        /// itself, since we do not want to erase transaction receipts or
+        /// uncles that might already be there. That replaces blocks that
+        /// were cached before we kept the type and fee fields of their
+        /// transactions
         pub(super) fn upsert_light_block(
             &self,
             conn: &PgConnection,
@@ -462,6 +464,7 @@ mod data {
             let data = serde_json::to_value(&EthereumBlock {
                 block: Arc::new(block),
                 transaction_receipts: Vec::new(),
+                uncles: Vec::new(),
             })
             .expect("Failed to serialize block");
 
@@ -482,14 +485,18 @@ mod data {
                     insert_into(b::table)
                         .values(values.clone())
                         .on_conflict(b::hash)
-                        .do_nothing()
+                        .do_update()
+                        .set(b::data.eq(sql::<Jsonb>(
+                            "jsonb_set(ethereum_blocks.data, '{block}', excluded.data -> 'block')",
+                        )))
                         .execute(conn)
                 }
                 Storage::Private(Schema { blocks, .. }) => {
                     let query = format!(
-                        "insert into {}(hash, number, parent_hash, data) \
+                        "insert into {0} as b(hash, number, parent_hash, data) \
                          values ($1, $2, $3, $4) \
-                             on conflict(hash) do nothing",
+                             on conflict(hash) do update \
+                            set data = jsonb_set(b.data, '{{block}}', excluded.data -> 'block')",
                         blocks.qname
                     );
                     sql_query(query)
@@ -539,6 +546,105 @@ mod data {
                 .collect()
         }
 
+        /// The uncles stored with the block with `hash`. They are part of
+        /// the block's row, so that they disappear with the block when the
+        /// block is removed from the store
+        pub(super) fn uncles(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            hash: H256,
+        ) -> Result<Option<Vec<EthereumUncle>>, Error> {
+            let uncles = match self {
+                Storage::Shared => {
+                    use public::ethereum_blocks as b;
+
+                    b::table
+                        .select(sql::<Nullable<Jsonb>>("data -> 'uncles'"))
+                        .filter(b::network_name.eq(chain))
+                        .filter(b::hash.eq(format!("{:x}", hash)))
+                        .first::<Option<serde_json::Value>>(conn)
+                        .optional()?
+                }
+                Storage::Private(Schema { blocks, .. }) => blocks
+                    .table()
+                    .select(sql::<Nullable<Jsonb>>("data -> 'uncles'"))
+                    .filter(blocks.hash().eq(hash.as_bytes()))
+                    .first::<Option<serde_json::Value>>(conn)
+                    .optional()?,
+            };
+            match uncles {
+                None => Ok(None),
+                Some(None) => Ok(Some(vec![])),
+                Some(Some(uncles)) => Ok(Some(serde_json::from_value(uncles)?)),
+            }
+        }
+
+        /// Store `uncles` with the block with `hash`; do nothing if we
+        /// do not have that block
+        pub(super) fn set_uncles(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            hash: H256,
+            uncles: &[EthereumUncle],
+        ) -> Result<(), Error> {
+            let uncles = serde_json::to_value(uncles)?;
+            match self {
+                Storage::Shared => sql_query(
+                    "update ethereum_blocks set data = jsonb_set(data, '{uncles}', $1) \
+                      where network_name = $2 and hash = $3",
+                )
+                .bind::<Jsonb, _>(uncles)
+                .bind::<Text, _>(chain)
+                .bind::<Text, _>(format!("{:x}", hash))
+                .execute(conn)?,
+                Storage::Private(Schema { blocks, .. }) => sql_query(format!(
+                    "update {} set data = jsonb_set(data, '{{uncles}}', $1) where hash = $2",
+                    blocks.qname
+                ))
+                .bind::<Jsonb, _>(uncles)
+                .bind::<Bytea, _>(hash.as_bytes())
+                .execute(conn)?,
+            };
+            Ok(())
+        }
+
+        /// Forget the uncles stored with the blocks with `hashes`
+        pub(super) fn remove_uncles(
+            &self,
+            conn: &PgConnection,
+            chain: &str,
+            hashes: &[H256],
+        ) -> Result<(), Error> {
+            match self {
+                Storage::Shared => sql_query(
+                    "update ethereum_blocks set data = data - 'uncles' \
+                      where network_name = $1 and hash = any($2)",
+                )
+                .bind::<Text, _>(chain)
+                .bind::<Array<Text>, _>(
+                    hashes
+                        .iter()
+                        .map(|hash| format!("{:x}", hash))
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?,
+                Storage::Private(Schema { blocks, .. }) => sql_query(format!(
+                    "update {} set data = data - 'uncles' where hash = any($1)",
+                    blocks.qname
+                ))
+                .bind::<Array<Bytea>, _>(
+                    hashes
+                        .iter()
+                        .map(|hash| hash.as_bytes())
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)?,
+            };
+            Ok(())
+        }
+
         pub(super) fn block_hashes_by_block_number(
             &self,
             conn: &PgConnection,
@@ -1225,6 +1331,48 @@ impl ChainStore {
         })
     }
 
+    /// Rename the chain `old` to `new` in the `ethereum_networks` and
+    /// `ethereum_blocks` tables in the schema `nsp`, which is either
+    /// `public` or a schema into which these tables from the chain's shard
+    /// were imported. Chains with their own namespace do not record the
+    /// chain name anywhere else. Blocks in the shared `ethereum_blocks`
+    /// table reference `ethereum_networks`, and we therefore add an entry
+    /// for the new name before moving the blocks over to it
+    pub(crate) fn rename_chain(
+        conn: &PgConnection,
+        nsp: &str,
+        old: &str,
+        new: &str,
+    ) -> Result<(), StoreError> {
+        let queries = [
+            format!(
+                "insert into {nsp}.ethereum_networks(name, namespace, head_block_hash,
+                                                     head_block_number, net_version,
+                                                     genesis_block_hash)
+                 select $2, namespace, head_block_hash, head_block_number,
+                        net_version, genesis_block_hash
+                   from {nsp}.ethereum_networks
+                  where name = $1",
+                nsp = nsp
+            ),
+            format!(
+                "update {nsp}.ethereum_blocks set network_name = $2 where network_name = $1",
+                nsp = nsp
+            ),
+            format!(
+                "delete from {nsp}.ethereum_networks where name = $1",
+                nsp = nsp
+            ),
+        ];
+        for query in &queries {
+            sql_query(query)
+                .bind::<Text, _>(old)
+                .bind::<Text, _>(new)
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
     pub fn chain_head_pointers(&self) -> Result<HashMap<String, BlockPtr>, StoreError> {
         use public::ethereum_networks as n;
 
@@ -1380,6 +1528,22 @@ impl ChainStoreTrait for ChainStore {
         self.storage.blocks(&conn, &self.chain, hashes)
     }
 
+    fn uncles(&self, block_hash: H256) -> Result<Option<Vec<EthereumUncle>>, Error> {
+        let conn = self.get_conn()?;
+        self.storage.uncles(&conn, &self.chain, block_hash)
+    }
+
+    fn set_uncles(&self, block_hash: H256, uncles: &[EthereumUncle]) -> Result<(), Error> {
+        let conn = self.get_conn()?;
+        self.storage
+            .set_uncles(&conn, &self.chain, block_hash, uncles)
+    }
+
+    fn remove_uncles(&self, block_hashes: &[H256]) -> Result<(), Error> {
+        let conn = self.get_conn()?;
+        self.storage.remove_uncles(&conn, &self.chain, block_hashes)
+    }
+
     fn ancestor_block(
         &self,
         block_ptr: BlockPtr,
@@ -1621,6 +1785,7 @@ pub mod test_support {
             EthereumBlock {
                 block: Arc::new(block),
                 transaction_receipts: Vec::new(),
+                uncles: Vec::new(),
             }
         }
     }
@@ -0,0 +1,299 @@
+//! Compare the entities of two deployments at a block, usually the current
+//! and the pending version of a subgraph before the pending version is
+//! made current. Both deployments must live in the same shard so that the
+//! comparison can be done with set operations across their tables in the
+//! database rather than by loading entities into the node.
+//!
+//! For each entity type, we compare the entity versions that are visible
+//! at the block in the two tables. Attributes that only one of the
+//! deployments has, or whose type changed, are left out of the comparison.
+//! Enum attributes are compared if their enums have the same values.
+//! Fulltext attributes are derived from other attributes and therefore
+//! left out, too. The proof of indexing is never compared since it
+//! differs as soon as the mappings differ in any way
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use diesel::sql_types::{Array, BigInt, Integer, Text};
+use diesel::{pg::PgConnection, sql_query, RunQueryDsl};
+use graph::components::store::{DifferenceKind, EntityDifference, EntityTypeComparison};
+use graph::data::subgraph::schema::POI_OBJECT;
+use graph::prelude::{anyhow::anyhow, BlockNumber, StoreError};
+
+use crate::block_range::BLOCK_RANGE_COLUMN;
+use crate::relational::{Column, ColumnType, Layout, Table};
+
+#[derive(QueryableByName)]
+struct Counts {
+    #[sql_type = "BigInt"]
+    added: i64,
+    #[sql_type = "BigInt"]
+    removed: i64,
+    #[sql_type = "BigInt"]
+    changed: i64,
+}
+
+#[derive(QueryableByName)]
+struct Difference {
+    #[sql_type = "Text"]
+    id: String,
+    #[sql_type = "Array<Text>"]
+    fields: Vec<String>,
+}
+
+/// Whether we compare the values of `column`
+fn is_compared(column: &Column) -> bool {
+    !column.is_fulltext()
+}
+
+/// Whether the values of `column` and `other` can be compared with each
+/// other
+fn is_same_type(column: &Column, other: &Column) -> bool {
+    if column.is_list() != other.is_list() {
+        return false;
+    }
+    match (&column.column_type, &other.column_type) {
+        (ColumnType::Enum(enum_type), ColumnType::Enum(other)) => enum_type.has_same_values(other),
+        (column_type, other) => column_type == other,
+    }
+}
+
+/// The entity versions of `table` that are visible at the block bound to
+/// `$1`, with only the given `columns`. Enums are turned into text since
+/// each deployment has its own enum types, and Postgres can not compare
+/// values of different enum types
+fn visible(table: &Table, columns: &[&Column]) -> String {
+    let columns: Vec<_> = columns
+        .iter()
+        .map(|column| match (column.is_enum(), column.is_list()) {
+            (true, false) => format!("{name}::text as {name}", name = column.name.quoted()),
+            (true, true) => format!("{name}::text[] as {name}", name = column.name.quoted()),
+            (false, _) => column.name.quoted(),
+        })
+        .collect();
+    format!(
+        "select {columns} from {table} where {block_range} @> $1",
+        columns = columns.join(", "),
+        table = table.qualified_name,
+        block_range = BLOCK_RANGE_COLUMN
+    )
+}
+
+/// Compare the entities of a type that both deployments have. `current`
+/// and `pending` must read from tables that contain all versions that
+/// are visible at `block`
+fn compare_table(
+    conn: &PgConnection,
+    current: &Table,
+    pending: &Table,
+    block: BlockNumber,
+    sample_size: usize,
+) -> Result<EntityTypeComparison, StoreError> {
+    let id = pending.primary_key();
+    if id.column_type != current.primary_key().column_type {
+        return Err(StoreError::Unknown(anyhow!(
+            "the type of the id of `{}` differs between the deployments",
+            pending.object
+        )));
+    }
+
+    // The id always comes first
+    let mut columns = vec![id];
+    let mut not_compared = Vec::new();
+    let attributes = pending
+        .columns
+        .iter()
+        .filter(|column| !column.is_primary_key() && is_compared(column));
+    for column in attributes {
+        match current.column(&column.name) {
+            Some(other) if is_same_type(column, other) => columns.push(column),
+            _ => not_compared.push(column.field.clone()),
+        }
+    }
+    for column in current.columns.iter().filter(|column| is_compared(column)) {
+        if pending.column(&column.name).is_none() {
+            not_compared.push(column.field.clone());
+        }
+    }
+    not_compared.sort();
+
+    let ctes = format!(
+        "with cur as ({}), pen as ({})",
+        visible(current, &columns),
+        visible(pending, &columns)
+    );
+    let counts = sql_query(format!(
+        "{ctes} \
+         select (select count(*) from (select id from pen except select id from cur) a) as added, \
+                (select count(*) from (select id from cur except select id from pen) r) as removed, \
+                (select count(*) from (select * from pen except select * from cur) d \
+                  where d.id in (select id from cur)) as changed",
+        ctes = ctes
+    ))
+    .bind::<Integer, _>(block)
+    .get_result::<Counts>(conn)?;
+
+    let mut sample = Vec::new();
+    for (kind, count, query) in &[
+        (
+            DifferenceKind::Added,
+            counts.added,
+            "select id::text as id, array[]::text[] as fields \
+               from (select id from pen except select id from cur) a",
+        ),
+        (
+            DifferenceKind::Removed,
+            counts.removed,
+            "select id::text as id, array[]::text[] as fields \
+               from (select id from cur except select id from pen) r",
+        ),
+    ] {
+        if *count > 0 {
+            let ids = sql_query(format!("{} {} order by 1 limit $2", ctes, query))
+                .bind::<Integer, _>(block)
+                .bind::<BigInt, _>(sample_size as i64)
+                .load::<Difference>(conn)?;
+            sample.extend(ids.into_iter().map(|diff| EntityDifference {
+                id: diff.id,
+                kind: *kind,
+                fields: vec![],
+            }));
+        }
+    }
+
+    if counts.changed > 0 {
+        let mut fields = String::new();
+        let mut differs = Vec::new();
+        for column in columns.iter().skip(1) {
+            let distinct = format!(
+                "p.{name} is distinct from c.{name}",
+                name = column.name.quoted()
+            );
+            write!(
+                fields,
+                "case when {} then '{}' end, ",
+                distinct, column.field
+            )?;
+            differs.push(distinct);
+        }
+        let changed = sql_query(format!(
+            "{ctes} \
+             select p.id::text as id, \
+                    array_remove(array[{fields}null]::text[], null) as fields \
+               from pen p join cur c on p.id = c.id \
+              where {differs} \
+              order by p.id limit $2",
+            ctes = ctes,
+            fields = fields,
+            differs = differs.join(" or ")
+        ))
+        .bind::<Integer, _>(block)
+        .bind::<BigInt, _>(sample_size as i64)
+        .load::<Difference>(conn)?;
+        sample.extend(changed.into_iter().map(|diff| EntityDifference {
+            id: diff.id,
+            kind: DifferenceKind::Changed,
+            fields: diff.fields,
+        }));
+    }
+
+    Ok(EntityTypeComparison {
+        entity_type: pending.object.to_string(),
+        added: counts.added as usize,
+        removed: counts.removed as usize,
+        changed: counts.changed as usize,
+        sample,
+        not_compared,
+    })
+}
+
+/// Describe the entities of a type that only one of the deployments has;
+/// they are all `added` if `kind` is `Added`, and all `removed` otherwise
+fn one_sided(
+    conn: &PgConnection,
+    table: &Table,
+    kind: DifferenceKind,
+    block: BlockNumber,
+    sample_size: usize,
+) -> Result<EntityTypeComparison, StoreError> {
+    #[derive(QueryableByName)]
+    struct Count {
+        #[sql_type = "BigInt"]
+        count: i64,
+    }
+
+    let id = &[table.primary_key()];
+    let count = sql_query(format!(
+        "select count(*) as count from ({}) v",
+        visible(table, id)
+    ))
+    .bind::<Integer, _>(block)
+    .get_result::<Count>(conn)?
+    .count as usize;
+    let sample = sql_query(format!(
+        "select id::text as id, array[]::text[] as fields from ({}) v order by 1 limit $2",
+        visible(table, id)
+    ))
+    .bind::<Integer, _>(block)
+    .bind::<BigInt, _>(sample_size as i64)
+    .load::<Difference>(conn)?
+    .into_iter()
+    .map(|diff| EntityDifference {
+        id: diff.id,
+        kind,
+        fields: vec![],
+    })
+    .collect();
+
+    let (added, removed) = match kind {
+        DifferenceKind::Added => (count, 0),
+        _ => (0, count),
+    };
+    Ok(EntityTypeComparison {
+        entity_type: table.object.to_string(),
+        added,
+        removed,
+        changed: 0,
+        sample,
+        not_compared: vec![],
+    })
+}
+
+/// Compare the entities of the deployments with layouts `current` and
+/// `pending` at `block`. The tables of both layouts must contain all
+/// entity versions that are visible at `block`, i.e., they must read from
+/// the archive if versions at `block` might have been archived
+pub(crate) fn compare(
+    conn: &PgConnection,
+    current: &Layout,
+    pending: &Layout,
+    block: BlockNumber,
+    sample_size: usize,
+) -> Result<Vec<EntityTypeComparison>, StoreError> {
+    let mut types = BTreeMap::new();
+    for (object, table) in &current.tables {
+        types.insert(object.to_string(), (Some(table), None));
+    }
+    for (object, table) in &pending.tables {
+        types.entry(object.to_string()).or_insert((None, None)).1 = Some(table);
+    }
+    types.remove(POI_OBJECT.as_str());
+
+    let mut comparisons = Vec::with_capacity(types.len());
+    for (current, pending) in types.values() {
+        let comparison = match (current, pending) {
+            (Some(current), Some(pending)) => {
+                compare_table(conn, current, pending, block, sample_size)?
+            }
+            (None, Some(pending)) => {
+                one_sided(conn, pending, DifferenceKind::Added, block, sample_size)?
+            }
+            (Some(current), None) => {
+                one_sided(conn, current, DifferenceKind::Removed, block, sample_size)?
+            }
+            (None, None) => unreachable!("every entity type comes from one of the layouts"),
+        };
+        comparisons.push(comparison);
+    }
+    Ok(comparisons)
+}
@@ -1,7 +1,9 @@
 use diesel::r2d2::Builder;
 use diesel::{connection::SimpleConnection, pg::PgConnection};
 use diesel::{
-    r2d2::{self, event as e, ConnectionManager, HandleEvent, Pool, PooledConnection},
+    r2d2::{
+        self, event as e, ConnectionManager, HandleEvent, ManageConnection, Pool, PooledConnection,
+    },
     Connection,
 };
 use diesel::{sql_query, RunQueryDsl};
@@ -16,15 +18,15 @@ use graph::{
         anyhow::{self, anyhow, bail},
         crit, debug, error, info, o,
         tokio::sync::Semaphore,
-        CancelGuard, CancelHandle, CancelToken as _, CancelableError, Counter, Gauge, Logger,
-        MetricsRegistry, MovingStats, PoolWaitStats, StoreError,
+        CancelGuard, CancelHandle, CancelToken as _, CancelableError, Counter, Gauge, Histogram,
+        Logger, MetricsRegistry, MovingStats, PoolWaitStats, StoreError,
     },
-    util::security::SafeDisplay,
+    util::{futures::record_retry, security::SafeDisplay},
 };
 
 use std::fmt::{self, Write};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{collections::HashMap, sync::RwLock};
 
@@ -66,6 +68,15 @@ lazy_static::lazy_static! {
             panic!("GRAPH_STORE_CONNECTION_IDLE_TIMEOUT must be a positive number, but is `{}`", s)
         }))).unwrap_or(Duration::from_secs(600))
     };
+    // Closing connections after a while is the only safe way to get rid of
+    // the prepared statements and other session state that accumulate in
+    // their backends: Diesel caches prepared statements on the connection
+    // and would fail if we deallocated them behind its back
+    static ref MAX_LIFETIME: Duration = {
+        std::env::var("GRAPH_STORE_CONNECTION_MAX_LIFETIME").ok().map(|s| Duration::from_secs(u64::from_str(&s).unwrap_or_else(|_| {
+            panic!("GRAPH_STORE_CONNECTION_MAX_LIFETIME must be a positive number, but is `{}`", s)
+        }))).unwrap_or(Duration::from_secs(1800))
+    };
 }
 
 pub struct ForeignServer {
@@ -220,6 +231,31 @@ impl ForeignServer {
         Ok(())
     }
 
+    /// Import the tables in which `shard` stores chain data into a new
+    /// schema in the database accessible through `conn`, and make them
+    /// writable. This makes it possible to change chain data in `shard`
+    /// in the same transaction as data in the database behind `conn`.
+    /// Returns the name of the new schema; the caller should drop it
+    /// before committing
+    pub(crate) fn map_chain_tables(
+        conn: &PgConnection,
+        shard: &Shard,
+    ) -> Result<String, StoreError> {
+        let nsp = format!("{}_public", Self::name(shard));
+        let query = format!(
+            "create schema {nsp};
+             import foreign schema public
+                    limit to (ethereum_networks, ethereum_blocks)
+                    from server {srvname} into {nsp};
+             alter foreign table {nsp}.ethereum_networks options (add updatable 'true');
+             alter foreign table {nsp}.ethereum_blocks options (add updatable 'true');",
+            nsp = nsp,
+            srvname = Self::name(shard)
+        );
+        conn.batch_execute(&query)?;
+        Ok(nsp)
+    }
+
     /// Map the `subgraphs` schema from the foreign server `self` into the
     /// database accessible through `conn`
     fn map_metadata(&self, conn: &PgConnection) -> Result<(), StoreError> {
@@ -230,6 +266,7 @@ impl ForeignServer {
             "subgraph_error",
             "dynamic_ethereum_contract_data_source",
             "table_stats",
+            "subgraph_deployment",
         ] {
             let create_stmt =
                 catalog::create_foreign_table(conn, "subgraphs", table_name, &nsp, &self.name)?;
@@ -495,12 +532,21 @@ impl r2d2::HandleError<r2d2::Error> for ErrorHandler {
     }
 }
 
+/// Buckets for the histograms of connection ages, in seconds
+const AGE_BUCKETS: [f64; 8] = [10.0, 60.0, 300.0, 600.0, 1800.0, 3600.0, 21600.0, 86400.0];
+
 #[derive(Clone)]
 struct EventHandler {
     logger: Logger,
     count_gauge: Gauge,
     wait_gauge: Gauge,
     wait_stats: PoolWaitStats,
+    release_counter: Box<Counter>,
+    age_histogram: Box<Histogram>,
+    checkout_age_histogram: Box<Histogram>,
+    // When the pool opened each of its connections, by connection id, so
+    // that we can tell how old a connection is when it is checked out
+    births: Arc<Mutex<HashMap<u64, Instant>>>,
 }
 
 impl EventHandler {
@@ -524,11 +570,38 @@ impl EventHandler {
                 const_labels.clone(),
             )
             .expect("failed to create `store_connection_wait_time_ms` counter");
+        let release_counter = registry
+            .new_counter_with_labels(
+                "store_connection_release_count",
+                "The number of Postgres connections that the pool closed",
+                const_labels.clone(),
+            )
+            .expect("failed to create `store_connection_release_count` counter");
+        let age_histogram = registry
+            .new_histogram_with_labels(
+                "store_connection_age_secs",
+                "The age of Postgres connections when the pool closed them",
+                const_labels.clone(),
+                AGE_BUCKETS.to_vec(),
+            )
+            .expect("failed to create `store_connection_age_secs` histogram");
+        let checkout_age_histogram = registry
+            .new_histogram_with_labels(
+                "store_connection_checkout_age_secs",
+                "The age of Postgres connections when they are checked out",
+                const_labels,
+                AGE_BUCKETS.to_vec(),
+            )
+            .expect("failed to create `store_connection_checkout_age_secs` histogram");
         EventHandler {
             logger,
             count_gauge,
             wait_gauge,
             wait_stats,
+            release_counter,
+            age_histogram,
+            checkout_age_histogram,
+            births: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -547,11 +620,24 @@ impl std::fmt::Debug for EventHandler {
 }
 
 impl HandleEvent for EventHandler {
-    fn handle_acquire(&self, _: e::AcquireEvent) {}
-    fn handle_release(&self, _: e::ReleaseEvent) {}
+    fn handle_acquire(&self, event: e::AcquireEvent) {
+        self.births
+            .lock()
+            .unwrap()
+            .insert(event.connection_id(), Instant::now());
+    }
+    fn handle_release(&self, event: e::ReleaseEvent) {
+        self.births.lock().unwrap().remove(&event.connection_id());
+        self.release_counter.inc();
+        self.age_histogram.observe(event.age().as_secs_f64());
+    }
     fn handle_checkout(&self, event: e::CheckoutEvent) {
         self.count_gauge.inc();
         self.add_conn_wait_time(event.duration());
+        if let Some(birth) = self.births.lock().unwrap().get(&event.connection_id()) {
+            self.checkout_age_histogram
+                .observe(birth.elapsed().as_secs_f64());
+        }
     }
     fn handle_timeout(&self, event: e::TimeoutEvent) {
         self.add_conn_wait_time(event.timeout());
@@ -565,6 +651,23 @@ impl HandleEvent for EventHandler {
     }
 }
 
+/// The settings that the main and the fdw pool share
+fn pool_builder<M>(
+    error_handler: Box<ErrorHandler>,
+    event_handler: Box<EventHandler>,
+    max_size: u32,
+) -> Builder<M>
+where
+    M: ManageConnection<Error = r2d2::Error>,
+{
+    Pool::builder()
+        .error_handler(error_handler)
+        .event_handler(event_handler)
+        .connection_timeout(*CONNECTION_TIMEOUT)
+        .max_size(max_size)
+        .max_lifetime(Some(*MAX_LIFETIME))
+}
+
 #[derive(Clone)]
 pub struct PoolInner {
     logger: Logger,
@@ -630,23 +733,17 @@ impl PoolInner {
 
         // Connect to Postgres
         let conn_manager = ConnectionManager::new(postgres_url.clone());
-        let builder: Builder<ConnectionManager<PgConnection>> = Pool::builder()
-            .error_handler(error_handler.clone())
-            .event_handler(event_handler.clone())
-            .connection_timeout(*CONNECTION_TIMEOUT)
-            .max_size(pool_size)
-            .min_idle(*MIN_IDLE)
-            .idle_timeout(Some(*IDLE_TIMEOUT));
+        let builder: Builder<ConnectionManager<PgConnection>> =
+            pool_builder(error_handler.clone(), event_handler.clone(), pool_size)
+                .min_idle(*MIN_IDLE)
+                .idle_timeout(Some(*IDLE_TIMEOUT));
         let pool = builder.build_unchecked(conn_manager);
         let fdw_pool = fdw_pool_size.map(|pool_size| {
             let conn_manager = ConnectionManager::new(postgres_url.clone());
-            let builder: Builder<ConnectionManager<PgConnection>> = Pool::builder()
-                .error_handler(error_handler)
-                .event_handler(event_handler)
-                .connection_timeout(*CONNECTION_TIMEOUT)
-                .max_size(pool_size)
-                .min_idle(Some(1))
-                .idle_timeout(Some(FDW_IDLE_TIMEOUT));
+            let builder: Builder<ConnectionManager<PgConnection>> =
+                pool_builder(error_handler, event_handler, pool_size)
+                    .min_idle(Some(1))
+                    .idle_timeout(Some(FDW_IDLE_TIMEOUT));
             builder.build_unchecked(conn_manager)
         });
 
@@ -776,9 +873,13 @@ impl PoolInner {
         loop {
             match self.pool.get_timeout(*CONNECTION_TIMEOUT) {
                 Ok(conn) => return Ok(conn),
-                Err(e) => error!(logger, "Error checking out connection, retrying";
-                   "error" => e.to_string(),
-                ),
+                Err(e) => {
+                    // Waiting for the timeout is all the backoff we need
+                    record_retry("store connection checkout");
+                    error!(logger, "Error checking out connection, retrying";
+                       "error" => e.to_string(),
+                    )
+                }
             }
         }
     }
@@ -956,3 +1057,70 @@ fn migrate_schema(logger: &Logger, conn: &PgConnection) -> Result<(), StoreError
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph_mock::MockMetricsRegistry;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A connection manager whose connections are free to make and that
+    /// can be told to report all connections as broken
+    struct FakeManager(Arc<AtomicBool>);
+
+    impl ManageConnection for FakeManager {
+        type Connection = ();
+        type Error = r2d2::Error;
+
+        fn connect(&self) -> Result<(), r2d2::Error> {
+            Ok(())
+        }
+
+        fn is_valid(&self, _: &mut ()) -> Result<(), r2d2::Error> {
+            Ok(())
+        }
+
+        fn has_broken(&self, _: &mut ()) -> bool {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn pool_settings_and_metrics() {
+        let logger = graph::log::logger(false);
+        let error_counter = Counter::new("errors", "errors").unwrap();
+        let error_handler = Box::new(ErrorHandler(logger.clone(), error_counter));
+        let event_handler = Box::new(EventHandler::new(
+            logger,
+            Arc::new(MockMetricsRegistry::new()),
+            Arc::new(RwLock::new(MovingStats::default())),
+            HashMap::new(),
+        ));
+        let broken = Arc::new(AtomicBool::new(false));
+        let pool = pool_builder(error_handler, event_handler.clone(), 1)
+            .min_idle(Some(0))
+            .build_unchecked(FakeManager(broken.clone()));
+
+        assert_eq!(Some(*MAX_LIFETIME), pool.max_lifetime());
+
+        // Checking out a connection counts it and records its age
+        let conn = pool.get().unwrap();
+        assert_eq!(1.0, event_handler.count_gauge.get());
+        assert_eq!(1, event_handler.checkout_age_histogram.get_sample_count());
+        drop(conn);
+        assert_eq!(0.0, event_handler.count_gauge.get());
+
+        // Checking the same connection out again records its age again
+        let conn = pool.get().unwrap();
+        assert_eq!(2, event_handler.checkout_age_histogram.get_sample_count());
+        assert_eq!(1, event_handler.births.lock().unwrap().len());
+
+        // The pool closes the connection when it is checked in broken
+        broken.store(true, Ordering::SeqCst);
+        drop(conn);
+        assert_eq!(1.0, event_handler.release_counter.get());
+        assert_eq!(1, event_handler.age_histogram.get_sample_count());
+        assert!(event_handler.births.lock().unwrap().is_empty());
+        assert_eq!(0.0, event_handler.count_gauge.get());
+    }
+}
@@ -7,17 +7,28 @@
 //! should take at most, and the code adapts the number of entities that are
 //! copied in each batch to be close to that time span.
 //!
+//! Since copies can move a lot of data, they can be throttled: copying
+//! pauses for `GRAPH_COPY_BATCH_SLEEP_MS` between batches, and waits while
+//! a replica of the shard lags more than `GRAPH_COPY_MAX_REPLICA_LAG`
+//! seconds behind. The copy runs with `synchronous_commit` turned off
+//! since an interrupted copy resumes from the last recorded batch anyway.
+//!
 //! The progress of copy operations is recorded in the tables
 //! `subgraphs.copy_state` and `subgraphs.copy_table_state` so that a copy
 //! operation can resume after an interruption, for example, because
 //! `graph-node` was restarted while the copy was running.
 use std::{
+    collections::HashMap,
     convert::TryFrom,
+    env,
+    str::FromStr,
     sync::Arc,
+    thread,
     time::{Duration, Instant},
 };
 
 use diesel::{
+    connection::SimpleConnection,
     dsl::sql,
     insert_into,
     r2d2::{ConnectionManager, PooledConnection},
@@ -29,7 +40,11 @@ use diesel::{
 use graph::{
     components::store::EntityType,
     constraint_violation,
-    prelude::{info, o, warn, BlockNumber, BlockPtr, Logger, StoreError},
+    data::{store::scalar::Timestamp, subgraph::status},
+    prelude::{
+        info, lazy_static, o, warn, BlockNumber, BlockPtr, GaugeVec, Logger, MetricsRegistry,
+        StoreError,
+    },
 };
 
 use crate::{
@@ -39,9 +54,63 @@ use crate::{
 use crate::{connection_pool::ConnectionPool, relational::Layout};
 use crate::{relational::Table, relational_queries as rq};
 
-const INITIAL_BATCH_SIZE: i64 = 10_000;
-const TARGET_DURATION: Duration = Duration::from_secs(5 * 60);
 const LOG_INTERVAL: Duration = Duration::from_secs(3 * 60);
+/// How long we wait at most before checking replica lag again
+const REPLICA_LAG_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+lazy_static! {
+    /// `GRAPH_COPY_BATCH_SIZE` is how many entity versions we copy in the
+    /// first batch for each table. Defaults to 10,000
+    static ref INITIAL_BATCH_SIZE: i64 = env::var("GRAPH_COPY_BATCH_SIZE")
+        .ok()
+        .map(|s| {
+            i64::from_str(&s)
+                .ok()
+                .filter(|size| *size > 0)
+                .unwrap_or_else(|| {
+                    panic!("GRAPH_COPY_BATCH_SIZE must be a positive number, but is `{}`", s)
+                })
+        })
+        .unwrap_or(10_000);
+
+    /// `GRAPH_COPY_BATCH_TARGET_DURATION` is how long, in seconds, copying
+    /// one batch should take; batch sizes are adjusted to get close to
+    /// that. Defaults to 300s (5 minutes)
+    static ref TARGET_DURATION: Duration = env::var("GRAPH_COPY_BATCH_TARGET_DURATION")
+        .ok()
+        .map(|s| {
+            let secs = u64::from_str(&s).unwrap_or_else(|_| {
+                panic!("GRAPH_COPY_BATCH_TARGET_DURATION must be a number, but is `{}`", s)
+            });
+            Duration::from_secs(secs)
+        })
+        .unwrap_or(Duration::from_secs(5 * 60));
+
+    /// `GRAPH_COPY_BATCH_SLEEP_MS` is how long, in milliseconds, we pause
+    /// between batches to leave room for other work. Defaults to 0
+    static ref BATCH_SLEEP: Duration = env::var("GRAPH_COPY_BATCH_SLEEP_MS")
+        .ok()
+        .map(|s| {
+            let millis = u64::from_str(&s).unwrap_or_else(|_| {
+                panic!("GRAPH_COPY_BATCH_SLEEP_MS must be a number, but is `{}`", s)
+            });
+            Duration::from_millis(millis)
+        })
+        .unwrap_or(Duration::from_millis(0));
+
+    /// `GRAPH_COPY_MAX_REPLICA_LAG` is how many seconds a replica of the
+    /// shard we copy into can lag behind before we stop copying until it
+    /// catches up. Defaults to 60s
+    static ref MAX_REPLICA_LAG: Duration = env::var("GRAPH_COPY_MAX_REPLICA_LAG")
+        .ok()
+        .map(|s| {
+            let secs = u64::from_str(&s).unwrap_or_else(|_| {
+                panic!("GRAPH_COPY_MAX_REPLICA_LAG must be a number, but is `{}`", s)
+            });
+            Duration::from_secs(secs)
+        })
+        .unwrap_or(Duration::from_secs(60));
+}
 
 table! {
     subgraphs.copy_state(dst) {
@@ -71,6 +140,7 @@ table! {
         // Measures just the time we spent working, not any wait time for
         // connections or the like
         duration_ms -> BigInt,
+        rows_copied -> BigInt,
     }
 }
 
@@ -270,6 +340,7 @@ struct TableState {
     target_vid: i64,
     batch_size: i64,
     duration_ms: i64,
+    rows_copied: i64,
 }
 
 impl TableState {
@@ -302,8 +373,9 @@ impl TableState {
             dst,
             next_vid: 0,
             target_vid,
-            batch_size: INITIAL_BATCH_SIZE,
+            batch_size: *INITIAL_BATCH_SIZE,
             duration_ms: 0,
+            rows_copied: 0,
         })
     }
 
@@ -349,11 +421,20 @@ impl TableState {
                 cts::target_vid,
                 cts::batch_size,
                 cts::duration_ms,
+                cts::rows_copied,
             ))
-            .load::<(i32, String, i64, i64, i64, i64)>(conn)?
+            .load::<(i32, String, i64, i64, i64, i64, i64)>(conn)?
             .into_iter()
             .map(
-                |(id, entity_type, current_vid, target_vid, batch_size, duration_ms)| {
+                |(
+                    id,
+                    entity_type,
+                    current_vid,
+                    target_vid,
+                    batch_size,
+                    duration_ms,
+                    rows_copied,
+                )| {
                     let entity_type = EntityType::new(entity_type);
                     let src =
                         resolve_entity(src_layout, "source", &entity_type, dst_layout.site.id, id);
@@ -373,6 +454,7 @@ impl TableState {
                             target_vid,
                             batch_size,
                             duration_ms,
+                            rows_copied,
                         }),
                         (Err(e), _) => Err(e),
                         (_, Err(e)) => Err(e),
@@ -409,6 +491,7 @@ impl TableState {
             cts::next_vid.eq(self.next_vid),
             cts::batch_size.eq(self.batch_size),
             cts::duration_ms.eq(self.duration_ms),
+            cts::rows_copied.eq(self.rows_copied),
         );
         update(
             cts::table
@@ -458,13 +541,15 @@ impl TableState {
         // but do not go over target_vid
         let first_batch = self.next_vid == 0;
         let last_vid = (self.next_vid + self.batch_size - 1).min(self.target_vid);
-        rq::CopyEntityBatchQuery::new(self.dst.as_ref(), &self.src, self.next_vid, last_vid)?
-            .execute(conn)?;
+        let rows =
+            rq::CopyEntityBatchQuery::new(self.dst.as_ref(), &self.src, self.next_vid, last_vid)?
+                .execute(conn)?;
 
         let duration = start.elapsed();
 
         // remember how far we got
         self.next_vid = last_vid + 1;
+        self.rows_copied += rows as i64;
 
         // adjust batch size by trying to extrapolate in such a way that we
         // get close to TARGET_DURATION for the time it takes to copy one
@@ -483,30 +568,144 @@ impl TableState {
     }
 }
 
+/// How far a copy has gotten, summed over all its tables
+#[derive(Default)]
+struct Summary {
+    tables_done: i32,
+    tables_total: i32,
+    rows_copied: i64,
+    /// How much of the `vid` ranges of all tables we have gone through
+    vids_done: i64,
+    vids_total: i64,
+    duration_ms: i64,
+}
+
+impl Summary {
+    fn of(tables: &[TableState]) -> Self {
+        let mut summary = Summary::default();
+        for table in tables {
+            summary.add(
+                table.next_vid,
+                table.target_vid,
+                table.rows_copied,
+                table.duration_ms,
+            );
+        }
+        summary
+    }
+
+    fn add(&mut self, next_vid: i64, target_vid: i64, rows_copied: i64, duration_ms: i64) {
+        self.tables_total += 1;
+        if next_vid > target_vid {
+            self.tables_done += 1;
+        }
+        self.rows_copied += rows_copied;
+        self.vids_done += next_vid.min(target_vid + 1);
+        self.vids_total += target_vid + 1;
+        self.duration_ms += duration_ms;
+    }
+
+    fn progress_pct(&self) -> f64 {
+        CopyProgress::progress_pct(self.vids_done, self.vids_total)
+    }
+
+    /// Extrapolate how much longer the copy will take from how long it
+    /// took to get this far. Pauses for throttling are not included
+    fn eta(&self) -> Option<Duration> {
+        if self.vids_done <= 0 {
+            return None;
+        }
+        let remaining = (self.vids_total - self.vids_done).max(0);
+        let millis = self.duration_ms as f64 * remaining as f64 / self.vids_done as f64;
+        Some(Duration::from_millis(millis as u64))
+    }
+}
+
+/// Metrics that show how far copying data into each deployment has gotten
+pub struct CopyMetrics {
+    tables_done: Box<GaugeVec>,
+    tables_total: Box<GaugeVec>,
+    rows_copied: Box<GaugeVec>,
+    eta: Box<GaugeVec>,
+}
+
+impl CopyMetrics {
+    pub fn new(registry: Arc<impl MetricsRegistry>) -> Self {
+        let deployment = vec![String::from("deployment")];
+        let tables_done = registry
+            .new_gauge_vec(
+                "deployment_copy_tables_done",
+                "The number of tables that have been copied completely",
+                deployment.clone(),
+            )
+            .expect("failed to create `deployment_copy_tables_done` gauge");
+        let tables_total = registry
+            .new_gauge_vec(
+                "deployment_copy_tables_total",
+                "The number of tables that need to be copied",
+                deployment.clone(),
+            )
+            .expect("failed to create `deployment_copy_tables_total` gauge");
+        let rows_copied = registry
+            .new_gauge_vec(
+                "deployment_copy_rows_copied",
+                "The number of entity versions that have been copied",
+                deployment.clone(),
+            )
+            .expect("failed to create `deployment_copy_rows_copied` gauge");
+        let eta = registry
+            .new_gauge_vec(
+                "deployment_copy_eta_seconds",
+                "Estimate of how many more seconds copying will take",
+                deployment,
+            )
+            .expect("failed to create `deployment_copy_eta_seconds` gauge");
+        CopyMetrics {
+            tables_done,
+            tables_total,
+            rows_copied,
+            eta,
+        }
+    }
+
+    fn update(&self, site: &Site, summary: &Summary) {
+        let labels = [site.deployment.as_str()];
+        self.tables_done
+            .with_label_values(&labels)
+            .set(summary.tables_done as f64);
+        self.tables_total
+            .with_label_values(&labels)
+            .set(summary.tables_total as f64);
+        self.rows_copied
+            .with_label_values(&labels)
+            .set(summary.rows_copied as f64);
+        self.eta
+            .with_label_values(&labels)
+            .set(summary.eta().map(|eta| eta.as_secs_f64()).unwrap_or(0.0));
+    }
+}
+
 // A helper for logging progress while data is being copied
 struct CopyProgress<'a> {
     logger: &'a Logger,
+    metrics: &'a CopyMetrics,
     last_log: Instant,
     src: Arc<Site>,
     dst: Arc<Site>,
-    current_vid: i64,
-    target_vid: i64,
 }
 
 impl<'a> CopyProgress<'a> {
-    fn new(logger: &'a Logger, state: &CopyState) -> Self {
-        let target_vid: i64 = state.tables.iter().map(|table| table.target_vid).sum();
+    fn new(logger: &'a Logger, metrics: &'a CopyMetrics, state: &CopyState) -> Self {
         Self {
             logger,
+            metrics,
             last_log: Instant::now(),
             src: state.src.site.clone(),
             dst: state.dst.site.clone(),
-            current_vid: 0,
-            target_vid,
         }
     }
 
-    fn start(&self) {
+    fn start(&self, tables: &[TableState]) {
         info!(
             self.logger,
             "Initialize data copy from {}[{}] to {}[{}]",
@@ -515,6 +714,7 @@ impl<'a> CopyProgress<'a> {
             self.dst.deployment,
             self.dst.namespace
         );
+        self.metrics.update(&self.dst, &Summary::of(tables));
     }
 
     fn progress_pct(current_vid: i64, target_vid: i64) -> f64 {
@@ -527,8 +727,12 @@ impl<'a> CopyProgress<'a> {
         }
     }
 
-    fn update(&mut self, table: &TableState) {
+    fn update(&mut self, tables: &[TableState], current: usize) {
+        let summary = Summary::of(tables);
+        self.metrics.update(&self.dst, &summary);
+
         if self.last_log.elapsed() > LOG_INTERVAL {
+            let table = &tables[current];
             info!(
                 self.logger,
                 "Copied {:.2}% of `{}` entities ({}/{} entity versions), {:.2}% of overall data",
@@ -536,17 +740,18 @@ impl<'a> CopyProgress<'a> {
                 table.dst.object,
                 table.next_vid,
                 table.target_vid,
-                Self::progress_pct(self.current_vid + table.next_vid, self.target_vid)
+                summary.progress_pct();
+                "tables_done" => summary.tables_done,
+                "tables" => summary.tables_total,
+                "rows_copied" => summary.rows_copied,
+                "eta_s" => summary.eta().map(|eta| eta.as_secs())
             );
             self.last_log = Instant::now();
         }
     }
 
-    fn table_finished(&mut self, table: &TableState) {
-        self.current_vid += table.next_vid;
-    }
-
-    fn finished(&self) {
+    fn finished(&self, tables: &[TableState]) {
+        self.metrics.update(&self.dst, &Summary::of(tables));
         info!(
             self.logger,
             "Finished copying data into {}[{}]", self.dst.deployment, self.dst.namespace
@@ -554,6 +759,75 @@ impl<'a> CopyProgress<'a> {
     }
 }
 
+/// Return the progress of copying data into the deployments `ids` for
+/// those of them that were created by copying
+pub(crate) fn copy_statuses(
+    conn: &PgConnection,
+    ids: &[i32],
+) -> Result<HashMap<i32, status::CopyStatus>, StoreError> {
+    use copy_state as cs;
+    use copy_table_state as cts;
+    use diesel::sql_types::{BigInt, Nullable};
+
+    fn micros(column: &str) -> String {
+        format!("(extract(epoch from {}) * 1000000)::int8", column)
+    }
+
+    fn timestamp(dst: i32, micros: i64) -> Result<Timestamp, StoreError> {
+        Timestamp::from_microseconds_since_epoch(micros).ok_or_else(|| {
+            constraint_violation!("invalid timestamp {} in CopyState {}", micros, dst)
+        })
+    }
+
+    let mut summaries: HashMap<i32, Summary> = HashMap::new();
+    for (dst, next_vid, target_vid, rows_copied, duration_ms) in cts::table
+        .filter(cts::dst.eq_any(ids))
+        .select((
+            cts::dst,
+            cts::next_vid,
+            cts::target_vid,
+            cts::rows_copied,
+            cts::duration_ms,
+        ))
+        .load::<(i32, i64, i64, i64, i64)>(conn)?
+    {
+        summaries
+            .entry(dst)
+            .or_default()
+            .add(next_vid, target_vid, rows_copied, duration_ms);
+    }
+
+    cs::table
+        .filter(cs::dst.eq_any(ids))
+        .select((
+            cs::dst,
+            sql::<BigInt>(&micros("started_at")),
+            sql::<Nullable<BigInt>>(&micros("finished_at")),
+            sql::<Nullable<BigInt>>(&micros("cancelled_at")),
+        ))
+        .load::<(i32, i64, Option<i64>, Option<i64>)>(conn)?
+        .into_iter()
+        .map(|(dst, started_at, finished_at, cancelled_at)| {
+            let summary = summaries.remove(&dst).unwrap_or_default();
+            let eta = match (finished_at, cancelled_at) {
+                (None, None) => summary.eta(),
+                _ => None,
+            };
+            let status = status::CopyStatus {
+                tables_done: summary.tables_done,
+                tables_total: summary.tables_total,
+                rows_copied: summary.rows_copied.max(0) as u64,
+                progress_pct: summary.progress_pct(),
+                eta_seconds: eta.map(|eta| eta.as_secs()),
+                started_at: timestamp(dst, started_at)?,
+                finished_at: finished_at.map(|ts| timestamp(dst, ts)).transpose()?,
+                cancelled_at: cancelled_at.map(|ts| timestamp(dst, ts)).transpose()?,
+            };
+            Ok((dst, status))
+        })
+        .collect()
+}
+
 /// A helper for copying subgraphs
 pub struct Connection {
     /// The connection pool for the shard that will contain the destination
@@ -563,6 +837,7 @@ pub struct Connection {
     src: Arc<Layout>,
     dst: Arc<Layout>,
     target_block: BlockPtr,
+    metrics: Arc<CopyMetrics>,
 }
 
 impl Connection {
@@ -578,6 +853,7 @@ impl Connection {
         src: Arc<Layout>,
         dst: Arc<Layout>,
         target_block: BlockPtr,
+        metrics: Arc<CopyMetrics>,
     ) -> Result<Self, StoreError> {
         let logger = logger.new(o!("dst" => dst.site.namespace.to_string()));
         let mut last_log = Instant::now();
@@ -594,6 +870,7 @@ impl Connection {
             src,
             dst,
             target_block,
+            metrics,
         })
     }
 
@@ -614,32 +891,60 @@ impl Connection {
             )
         })?;
 
-        let mut progress = CopyProgress::new(&self.logger, &state);
-        progress.start();
+        let mut progress = CopyProgress::new(&self.logger, &self.metrics, &state);
+        progress.start(&state.tables);
 
-        for table in state.tables.iter_mut().filter(|table| !table.finished()) {
-            while !table.finished() {
+        for current in 0..state.tables.len() {
+            while !state.tables[current].finished() {
                 // It is important that this check happens outside the write
                 // transaction so that we do not hold on to locks acquired
                 // by the check
-                if table.is_cancelled(&self.conn)? {
+                if state.tables[current].is_cancelled(&self.conn)? {
                     return Ok(Status::Cancelled);
                 }
+                let table = &mut state.tables[current];
                 let status = self.transaction(|conn| table.copy_batch(conn))?;
                 if status == Status::Cancelled {
                     return Ok(status);
                 }
-                progress.update(table);
+                progress.update(&state.tables, current);
+                self.throttle()?;
             }
-            progress.table_finished(table);
         }
 
         self.transaction(|conn| state.finished(conn))?;
-        progress.finished();
+        progress.finished(&state.tables);
 
         Ok(Status::Finished)
     }
 
+    /// Pause between batches so that copying does not crowd out other
+    /// work, and wait for replicas of the shard that have fallen too far
+    /// behind to catch up
+    fn throttle(&self) -> Result<(), StoreError> {
+        if BATCH_SLEEP.as_millis() > 0 {
+            thread::sleep(*BATCH_SLEEP);
+        }
+
+        let mut last_log: Option<Instant> = None;
+        loop {
+            let lag = replica_lag(&self.conn)?;
+            if lag <= *MAX_REPLICA_LAG {
+                return Ok(());
+            }
+            if last_log.map_or(true, |last_log| last_log.elapsed() > LOG_INTERVAL) {
+                info!(
+                    self.logger,
+                    "Pausing copy until replicas catch up";
+                    "replica_lag_s" => lag.as_secs(),
+                    "max_replica_lag_s" => MAX_REPLICA_LAG.as_secs()
+                );
+                last_log = Some(Instant::now());
+            }
+            thread::sleep((lag - *MAX_REPLICA_LAG).min(REPLICA_LAG_CHECK_INTERVAL));
+        }
+    }
+
     /// Copy the data for the subgraph `src` to the subgraph `dst`. The
     /// schema for both subgraphs must have already been set up. The
     /// `target_block` must be far enough behind the chain head so that the
@@ -668,7 +973,12 @@ impl Connection {
             "Obtaining copy lock (this might take a long time if another process is still copying)"
         );
         advisory_lock::lock_copying(&self.conn, self.dst.site.as_ref())?;
+        // Losing the last few batches in a crash is harmless since we
+        // resume from what was recorded. The connection goes back to the
+        // pool afterwards, and needs to be reset
+        self.conn.batch_execute("set synchronous_commit to off")?;
         let res = self.copy_data_internal();
+        self.conn.batch_execute("reset synchronous_commit")?;
         advisory_lock::unlock_copying(&self.conn, self.dst.site.as_ref())?;
         if matches!(res, Ok(Status::Cancelled)) {
             warn!(&self.logger, "Copying was cancelled and is incomplete");
@@ -676,3 +986,20 @@ impl Connection {
         res
     }
 }
+
+/// How far the replica that lags the most is behind the database that
+/// `conn` is connected to; zero if there are no replicas
+fn replica_lag(conn: &PgConnection) -> Result<Duration, StoreError> {
+    #[derive(QueryableByName)]
+    struct Lag {
+        #[sql_type = "diesel::sql_types::BigInt"]
+        lag_ms: i64,
+    }
+
+    let lag = sql_query(
+        "select coalesce((extract(epoch from max(replay_lag)) * 1000)::int8, 0) as lag_ms \
+           from pg_stat_replication",
+    )
+    .get_result::<Lag>(conn)?;
+    Ok(Duration::from_millis(lag.lag_ms.max(0) as u64))
+}
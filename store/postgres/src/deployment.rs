@@ -5,7 +5,7 @@ use crate::detail::GraphNodeVersion;
 use diesel::{
     connection::SimpleConnection,
     dsl::{count, delete, insert_into, select, sql, update},
-    sql_types::Integer,
+    sql_types::{BigInt, Bool, Double, Integer},
 };
 use diesel::{expression::SqlLiteral, pg::PgConnection, sql_types::Numeric};
 use diesel::{
@@ -13,25 +13,36 @@ use diesel::{
     sql_query,
     sql_types::{Nullable, Text},
 };
-use graph::data::subgraph::{schema::SubgraphManifestEntity, SubgraphFeature};
+use graph::data::subgraph::{
+    schema::SubgraphManifestEntity,
+    status::{MappingsReload, RetriedFailure, RevertReport, SkippedBlock},
+    DeploymentProfile, ScanTotals, SubgraphFeature,
+};
 use graph::prelude::{
-    anyhow, bigdecimal::ToPrimitive, hex, web3::types::H256, BigDecimal, BlockNumber, BlockPtr,
-    DeploymentHash, DeploymentState, Schema, StoreError,
+    anyhow, bigdecimal::ToPrimitive, hex, serde_json, web3::types::H256, BigDecimal, BlockNumber,
+    BlockPtr, DataSourceKey, DeploymentHash, DeploymentState, NodeId, Schema, StoreError,
 };
 use graph::{data::subgraph::schema::SubgraphError, prelude::SubgraphDeploymentEntity};
 use stable_hash::crypto::SetHasher;
 use std::str::FromStr;
+use std::time::Duration;
 use std::{collections::BTreeSet, convert::TryFrom, ops::Bound};
 
 use crate::connection_pool::ForeignServer;
-use crate::{block_range::BLOCK_RANGE_COLUMN, primary::Site};
+use crate::{
+    block_range::BLOCK_RANGE_COLUMN,
+    primary::{DeploymentId, Site},
+};
+use graph::components::store::OnceHandlerRun;
 use graph::constraint_violation;
+use graph::data::store::scalar::Timestamp;
 
 #[derive(DbEnum, Debug, Clone, Copy)]
 pub enum SubgraphHealth {
     Failed,
     Healthy,
     Unhealthy,
+    Completed,
 }
 
 impl From<SubgraphHealth> for graph::data::subgraph::schema::SubgraphHealth {
@@ -43,6 +54,7 @@ impl From<SubgraphHealth> for graph::data::subgraph::schema::SubgraphHealth {
             Db::Failed => H::Failed,
             Db::Healthy => H::Healthy,
             Db::Unhealthy => H::Unhealthy,
+            Db::Completed => H::Completed,
         }
     }
 }
@@ -70,6 +82,23 @@ table! {
         current_reorg_depth -> Integer,
         max_reorg_depth -> Integer,
         firehose_cursor -> Nullable<Text>,
+        writer_node -> Nullable<Text>,
+        writer_lease_expires -> Nullable<Timestamptz>,
+        block_latency_p50 -> Nullable<Double>,
+        block_latency_p95 -> Nullable<Double>,
+        archive_blocks -> Nullable<Integer>,
+        archived_block -> Nullable<Integer>,
+        last_revert -> Nullable<Jsonb>,
+        failure_history -> Jsonb,
+        synced_checks -> Integer,
+        skipped_blocks -> Jsonb,
+        revert_from -> Nullable<Integer>,
+        revert_target -> Nullable<Integer>,
+        revert_reached -> Nullable<Integer>,
+        last_processed_at -> Nullable<Timestamptz>,
+        mappings_reloads -> Jsonb,
+        revert_target_hash -> Nullable<Binary>,
+        once_handler_runs -> Jsonb,
     }
 }
 
@@ -95,6 +124,20 @@ table! {
         features -> Array<Text>,
         schema -> Text,
         graph_node_version_id -> Nullable<Integer>,
+        profile -> Nullable<Jsonb>,
+        max_trigger_bytes -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    subgraphs.subgraph_deployment_scan {
+        id -> Integer,
+        blocks -> BigInt,
+        logs -> BigInt,
+        traces -> BigInt,
+        eth_calls -> BigInt,
+        bytes -> BigInt,
+        reset_at -> Nullable<Timestamptz>,
     }
 }
 
@@ -216,6 +259,59 @@ pub fn features(conn: &PgConnection, site: &Site) -> Result<BTreeSet<SubgraphFea
         .collect()
 }
 
+/// The profile of the deployment, if one was recorded when it was created
+pub fn profile(conn: &PgConnection, site: &Site) -> Result<Option<DeploymentProfile>, StoreError> {
+    use subgraph_manifest as sm;
+
+    let profile = sm::table
+        .select(sm::profile)
+        .filter(sm::id.eq(site.id))
+        .first::<Option<serde_json::Value>>(conn)?;
+    let profile: Option<DeploymentProfile> = profile.map(serde_json::from_value).transpose()?;
+    profile
+        .map(|mut profile| {
+            profile.scanned = scan_totals(conn, site)?;
+            Ok(profile)
+        })
+        .transpose()
+}
+
+/// What the deployment has requested from its chain
+pub fn scan_totals(conn: &PgConnection, site: &Site) -> Result<ScanTotals, StoreError> {
+    use subgraph_deployment_scan as s;
+
+    let totals = s::table
+        .filter(s::id.eq(site.id))
+        .select((
+            s::blocks,
+            s::logs,
+            s::traces,
+            s::eth_calls,
+            s::bytes,
+            sql::<Nullable<BigInt>>("(extract(epoch from reset_at) * 1000000)::int8"),
+        ))
+        .get_result::<(i64, i64, i64, i64, i64, Option<i64>)>(conn)
+        .optional()?;
+    let (blocks, logs, traces, eth_calls, bytes, reset_at) = match totals {
+        Some(totals) => totals,
+        None => return Ok(ScanTotals::default()),
+    };
+    let reset_at = reset_at
+        .map(|micros| {
+            Timestamp::from_microseconds_since_epoch(micros)
+                .ok_or_else(|| constraint_violation!("invalid scan reset timestamp {}", micros))
+        })
+        .transpose()?;
+    Ok(ScanTotals {
+        blocks: blocks as u64,
+        logs: logs as u64,
+        traces: traces as u64,
+        eth_calls: eth_calls as u64,
+        bytes: bytes as u64,
+        reset_at,
+    })
+}
+
 pub fn forward_block_ptr(
     conn: &PgConnection,
     id: &DeploymentHash,
@@ -239,6 +335,15 @@ pub fn forward_block_ptr(
         d::latest_ethereum_block_number.eq(sql(&number)),
         d::latest_ethereum_block_hash.eq(ptr.hash_slice()),
         d::current_reorg_depth.eq(0),
+        // Status queries only need to know roughly when the deployment
+        // last processed a block; we do not want to change the timestamp
+        // for every block. A timestamp in the future means the database
+        // clock was set back, and we replace it so that it does not stay
+        // stuck until the clock catches up
+        d::last_processed_at.eq(sql(
+            "case when last_processed_at between now() - interval '10 seconds' and now() \
+                  then last_processed_at else now() end",
+        )),
     ))
     .execute(conn)
     .map_err(StoreError::from)?;
@@ -293,6 +398,84 @@ pub fn update_firehose_cursor(
         .map_err(|e| e.into())
 }
 
+/// Take or renew the writer lease for the deployment on behalf of `node`
+/// so that it expires `duration` from now. The lease can only be taken if
+/// nobody holds it, if `node` already holds it, or if the lease of the
+/// current holder has expired and `take_expired` is set. If a different
+/// node holds the lease, return `StoreError::DeploymentLeaseHeld`.
+///
+/// Leases are never shortened; if the database clock was set back, the
+/// lease keeps the expiry it had rather than one computed from the
+/// earlier time
+pub fn renew_writer_lease(
+    conn: &PgConnection,
+    site: &Site,
+    node: &NodeId,
+    duration: Duration,
+    take_expired: bool,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    const QUERY: &str = "\
+        update subgraphs.subgraph_deployment
+           set writer_node = $2,
+               writer_lease_expires = greatest(writer_lease_expires,
+                                               now() + make_interval(secs => $3))
+         where id = $1
+           and (writer_node is null
+                or writer_node = $2
+                or ($4 and writer_lease_expires < now()))";
+
+    let count = sql_query(QUERY)
+        .bind::<Integer, _>(site.id)
+        .bind::<Text, _>(node.as_str())
+        .bind::<Double, _>(duration.as_secs_f64())
+        .bind::<Bool, _>(take_expired)
+        .execute(conn)?;
+    if count > 0 {
+        return Ok(());
+    }
+
+    // Nothing was updated; either the deployment does not exist or some
+    // other node holds the lease
+    let holder = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::writer_node)
+        .first::<Option<String>>(conn)
+        .optional()?;
+    match holder {
+        None => Err(StoreError::DeploymentNotFound(site.deployment.to_string())),
+        Some(holder) => Err(StoreError::DeploymentLeaseHeld(
+            site.deployment.clone(),
+            holder.unwrap_or_default(),
+        )),
+    }
+}
+
+/// Unconditionally hand the writer lease for the deployment to `node`, no
+/// matter which node currently holds it. If `node` is `None`, release the
+/// lease so that the next node that writes to the deployment can take it
+pub fn transfer_writer_lease(
+    conn: &PgConnection,
+    site: &Site,
+    node: Option<&NodeId>,
+    duration: Duration,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let expires = match node {
+        Some(_) => format!("now() + interval '{} seconds'", duration.as_secs()),
+        None => "null".to_string(),
+    };
+    update(d::table.filter(d::id.eq(site.id)))
+        .set((
+            d::writer_node.eq(node.map(|node| node.as_str())),
+            d::writer_lease_expires.eq(sql(&expires)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
 pub fn revert_block_ptr(
     conn: &PgConnection,
     id: &DeploymentHash,
@@ -311,9 +494,18 @@ pub fn revert_block_ptr(
             d::current_reorg_depth.eq(d::current_reorg_depth + 1),
             d::max_reorg_depth.eq(sql("greatest(current_reorg_depth + 1, max_reorg_depth)")),
         ))
-        .execute(conn)
-        .map(|_| ())
-        .map_err(|e| e.into())
+        .execute(conn)?;
+
+    // A completed deployment that gets rewound has blocks to process again
+    update(
+        d::table
+            .filter(d::deployment.eq(id.as_str()))
+            .filter(d::health.eq(SubgraphHealth::Completed)),
+    )
+    .set(d::health.eq(SubgraphHealth::Healthy))
+    .execute(conn)
+    .map(|_| ())
+    .map_err(|e| e.into())
 }
 
 pub fn block_ptr(conn: &PgConnection, id: &DeploymentHash) -> Result<Option<BlockPtr>, StoreError> {
@@ -385,26 +577,40 @@ pub fn state(conn: &PgConnection, id: DeploymentHash) -> Result<DeploymentState,
             d::reorg_count,
             d::max_reorg_depth,
             d::latest_ethereum_block_number,
+            d::failed,
+            d::revert_target,
         ))
-        .first::<(String, i32, i32, Option<BigDecimal>)>(conn)
+        .first::<(String, i32, i32, Option<BigDecimal>, bool, Option<i32>)>(conn)
         .optional()?
     {
         None => Err(StoreError::QueryExecutionError(format!(
             "No data found for subgraph {}",
             id
         ))),
-        Some((_, reorg_count, max_reorg_depth, latest_ethereum_block_number)) => {
+        Some((
+            _,
+            reorg_count,
+            max_reorg_depth,
+            latest_ethereum_block_number,
+            failed,
+            revert_target,
+        )) => {
             let reorg_count = convert_to_u32(Some(reorg_count), "reorg_count", id.as_str())?;
             let max_reorg_depth =
                 convert_to_u32(Some(max_reorg_depth), "max_reorg_depth", id.as_str())?;
-            let latest_ethereum_block_number =
-                latest_as_block_number(latest_ethereum_block_number, id.as_str())?;
+            // While a chunked revert is in progress, the data above its
+            // target is only partially reverted and must not be queried
+            let latest_ethereum_block_number = match revert_target {
+                Some(target) => target,
+                None => latest_as_block_number(latest_ethereum_block_number, id.as_str())?,
+            };
 
             Ok(DeploymentState {
                 id,
                 reorg_count,
                 max_reorg_depth,
                 latest_ethereum_block_number,
+                failed,
             })
         }
     }
@@ -424,6 +630,32 @@ pub fn set_synced(conn: &PgConnection, id: &DeploymentHash) -> Result<(), StoreE
     Ok(())
 }
 
+/// Mark the deployment `id` as completed, i.e., as having processed all
+/// blocks up to its end block. Has no effect on failed deployments
+pub fn complete(conn: &PgConnection, id: &DeploymentHash) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(
+        d::table
+            .filter(d::deployment.eq(id.as_str()))
+            .filter(d::health.ne(SubgraphHealth::Failed)),
+    )
+    .set(d::health.eq(SubgraphHealth::Completed))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Returns `true` if the deployment `id` has been marked as completed
+pub fn is_completed(conn: &PgConnection, id: &DeploymentHash) -> Result<bool, StoreError> {
+    use subgraph_deployment as d;
+
+    let health = d::table
+        .filter(d::deployment.eq(id.as_str()))
+        .select(d::health)
+        .first::<SubgraphHealth>(conn)?;
+    Ok(matches!(health, SubgraphHealth::Completed))
+}
+
 /// Returns `true` if the deployment (as identified by `site.id`)
 pub fn exists(conn: &PgConnection, site: &Site) -> Result<bool, StoreError> {
     use subgraph_deployment as d;
@@ -764,6 +996,8 @@ pub fn create_deployment(
                 repository,
                 features,
                 schema,
+                profile,
+                max_trigger_bytes,
             },
         failed,
         health: _,
@@ -798,6 +1032,7 @@ pub fn create_deployment(
     );
 
     let graph_node_version_id = GraphNodeVersion::create_or_get(&conn)?;
+    let profile = profile.map(serde_json::to_value).transpose()?;
 
     let manifest_values = (
         m::id.eq(site.id),
@@ -807,6 +1042,8 @@ pub fn create_deployment(
         m::features.eq(features),
         m::schema.eq(schema),
         m::graph_node_version_id.eq(graph_node_version_id),
+        m::profile.eq(profile),
+        m::max_trigger_bytes.eq(max_trigger_bytes.map(|bytes| bytes as i64)),
     );
 
     if exists && replace {
@@ -867,6 +1104,502 @@ pub fn update_entity_count(
     Ok(())
 }
 
+/// Turn archiving of entity versions that were closed more than `blocks`
+/// blocks before the subgraph head on, or turn it off if `blocks` is `None`
+pub fn set_archive_blocks(
+    conn: &PgConnection,
+    site: &Site,
+    blocks: Option<BlockNumber>,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::archive_blocks.eq(blocks))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the block before which queries need to include archived entity
+/// versions, or `None` if nothing has ever been archived
+pub fn archived_block(conn: &PgConnection, site: &Site) -> Result<Option<BlockNumber>, StoreError> {
+    use subgraph_deployment as d;
+
+    d::table
+        .filter(d::id.eq(site.id))
+        .select(d::archived_block)
+        .first::<Option<BlockNumber>>(conn)
+        .map_err(|e| e.into())
+}
+
+pub fn set_archived_block(
+    conn: &PgConnection,
+    site: &Site,
+    block: BlockNumber,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::archived_block.eq(block))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the deployments in this shard that have archiving turned on and
+/// whose subgraph head has moved far enough that more versions can be
+/// archived, together with the block up to which versions should now be
+/// archived
+pub fn archive_horizons(
+    conn: &PgConnection,
+) -> Result<Vec<(DeploymentId, BlockNumber)>, StoreError> {
+    #[derive(QueryableByName)]
+    struct Horizon {
+        #[sql_type = "Integer"]
+        id: DeploymentId,
+        #[sql_type = "Integer"]
+        horizon: BlockNumber,
+    }
+
+    const QUERY: &str = "\
+        select id, latest_ethereum_block_number::int4 - archive_blocks as horizon
+          from subgraphs.subgraph_deployment
+         where archive_blocks is not null
+           and latest_ethereum_block_number - archive_blocks
+               > coalesce(archived_block, 0)";
+
+    Ok(sql_query(QUERY)
+        .load::<Horizon>(conn)?
+        .into_iter()
+        .map(|h| (h.id, h.horizon))
+        .collect())
+}
+
+/// Record the median and 95th percentile of the time, in seconds, it took
+/// for recent blocks to become queryable after they were produced
+pub fn set_block_latency(
+    conn: &PgConnection,
+    site: &Site,
+    p50: f64,
+    p95: f64,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::id.eq(site.id)))
+        .set((d::block_latency_p50.eq(p50), d::block_latency_p95.eq(p95)))
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn set_synced_checks(conn: &PgConnection, site: &Site, checks: u32) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::synced_checks.eq(checks as i32))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Add `totals` to what the deployment has requested from its chain
+pub fn add_scan_totals(
+    conn: &PgConnection,
+    site: &Site,
+    totals: &ScanTotals,
+) -> Result<(), StoreError> {
+    const QUERY: &str = "\
+        insert into subgraphs.subgraph_deployment_scan as s
+               (id, blocks, logs, traces, eth_calls, bytes)
+        values ($1, $2, $3, $4, $5, $6)
+        on conflict(id) do update
+           set blocks = s.blocks + excluded.blocks,
+               logs = s.logs + excluded.logs,
+               traces = s.traces + excluded.traces,
+               eth_calls = s.eth_calls + excluded.eth_calls,
+               bytes = s.bytes + excluded.bytes";
+
+    sql_query(QUERY)
+        .bind::<Integer, _>(site.id)
+        .bind::<BigInt, _>(totals.blocks as i64)
+        .bind::<BigInt, _>(totals.logs as i64)
+        .bind::<BigInt, _>(totals.traces as i64)
+        .bind::<BigInt, _>(totals.eth_calls as i64)
+        .bind::<BigInt, _>(totals.bytes as i64)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Start counting what the deployment requests from its chain from zero
+pub fn reset_scan_totals(conn: &PgConnection, site: &Site) -> Result<(), StoreError> {
+    const QUERY: &str = "\
+        insert into subgraphs.subgraph_deployment_scan(id, reset_at)
+        values ($1, now())
+        on conflict(id) do update
+           set blocks = 0,
+               logs = 0,
+               traces = 0,
+               eth_calls = 0,
+               bytes = 0,
+               reset_at = excluded.reset_at";
+
+    sql_query(QUERY).bind::<Integer, _>(site.id).execute(conn)?;
+    Ok(())
+}
+
+/// Remember what the most recent revert of the deployment did. If no
+/// block was processed since the last revert, `report` continues it, and
+/// we remember what both did together
+pub fn set_last_revert(
+    conn: &PgConnection,
+    site: &Site,
+    report: RevertReport,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let last = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::last_revert)
+        .first::<Option<serde_json::Value>>(conn)?
+        .map(serde_json::from_value::<RevertReport>)
+        .transpose()?;
+    let report = match last {
+        Some(mut last) if last.is_continued_by(&report) => {
+            last.extend(report);
+            last
+        }
+        _ => report,
+    };
+    let report = serde_json::to_value(&report)?;
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::last_revert.eq(report))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// A revert that is done in chunks and has not finished yet. The entity
+/// data has only been reverted to block `reached`; the head of the
+/// deployment stays at `from` until the data has been reverted all the way
+/// to `target`, and no blocks can be written in the meantime
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingRevert {
+    pub from: BlockNumber,
+    pub target: BlockPtr,
+    pub reached: BlockNumber,
+}
+
+pub fn pending_revert(
+    conn: &PgConnection,
+    site: &Site,
+) -> Result<Option<PendingRevert>, StoreError> {
+    use subgraph_deployment as d;
+
+    let (from, target, target_hash, reached) = d::table
+        .filter(d::id.eq(site.id))
+        .select((
+            d::revert_from,
+            d::revert_target,
+            d::revert_target_hash,
+            d::revert_reached,
+        ))
+        .first::<(Option<i32>, Option<i32>, Option<Vec<u8>>, Option<i32>)>(conn)?;
+    match (from, target, target_hash, reached) {
+        (Some(from), Some(target), Some(target_hash), Some(reached)) => Ok(Some(PendingRevert {
+            from,
+            target: BlockPtr::from((H256::from_slice(&target_hash), target)),
+            reached,
+        })),
+        (None, None, None, None) => Ok(None),
+        (from, target, target_hash, reached) => Err(constraint_violation!(
+            "incomplete revert state for {}: from={:?}, target={:?}, target_hash={:?}, reached={:?}",
+            site.deployment,
+            from,
+            target,
+            target_hash.map(hex::encode),
+            reached
+        )),
+    }
+}
+
+/// Record the progress of a chunked revert, or clear it if `revert` is
+/// `None`
+pub fn set_pending_revert(
+    conn: &PgConnection,
+    site: &Site,
+    revert: Option<&PendingRevert>,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    update(d::table.filter(d::id.eq(site.id)))
+        .set((
+            d::revert_from.eq(revert.map(|r| r.from)),
+            d::revert_target.eq(revert.map(|r| r.target.number)),
+            d::revert_target_hash.eq(revert.map(|r| r.target.hash_slice())),
+            d::revert_reached.eq(revert.map(|r| r.reached)),
+        ))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// How many earlier failures we remember for each deployment
+const MAX_FAILURE_HISTORY: usize = 100;
+
+/// Add `failure` to the failure history of the deployment, forgetting the
+/// oldest failures if there are too many
+pub fn add_retried_failure(
+    conn: &PgConnection,
+    site: &Site,
+    failure: RetriedFailure,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let history = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::failure_history)
+        .get_result::<serde_json::Value>(conn)?;
+    let mut history: Vec<RetriedFailure> = serde_json::from_value(history)?;
+    history.push(failure);
+    if history.len() > MAX_FAILURE_HISTORY {
+        history.drain(..history.len() - MAX_FAILURE_HISTORY);
+    }
+
+    let history = serde_json::to_value(history)?;
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::failure_history.eq(history))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Return the blocks that an operator told the deployment to skip, in
+/// the order in which they were requested
+pub fn skipped_blocks(conn: &PgConnection, site: &Site) -> Result<Vec<SkippedBlock>, StoreError> {
+    use subgraph_deployment as d;
+
+    let skipped = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::skipped_blocks)
+        .get_result::<serde_json::Value>(conn)?;
+    Ok(serde_json::from_value(skipped)?)
+}
+
+fn set_skipped_blocks(
+    conn: &PgConnection,
+    site: &Site,
+    skipped: Vec<SkippedBlock>,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let skipped = serde_json::to_value(skipped)?;
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::skipped_blocks.eq(skipped))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Tell the deployment to skip the block with `block_hash` when it gets
+/// to it. It is an error to request a skip for a block that the
+/// deployment has already been told to skip
+pub fn request_skip_block(
+    conn: &PgConnection,
+    site: &Site,
+    block_hash: &str,
+    reason: String,
+    requested_at: Timestamp,
+) -> Result<(), StoreError> {
+    let mut skipped = skipped_blocks(conn, site)?;
+    if skipped
+        .iter()
+        .any(|block| block.block_hash == block_hash && block.is_requested())
+    {
+        return Err(StoreError::Unknown(anyhow!(
+            "deployment {} has already been told to skip block {}",
+            site.deployment,
+            block_hash
+        )));
+    }
+    skipped.push(SkippedBlock::new(
+        block_hash.to_string(),
+        reason,
+        requested_at,
+    ));
+    set_skipped_blocks(conn, site, skipped)
+}
+
+/// Withdraw the request to skip the block with `block_hash` and return
+/// what we know about the skip
+pub fn unskip_block(
+    conn: &PgConnection,
+    site: &Site,
+    block_hash: &str,
+    unskipped_at: Timestamp,
+) -> Result<SkippedBlock, StoreError> {
+    let mut skipped = skipped_blocks(conn, site)?;
+    let block = skipped
+        .iter_mut()
+        .find(|block| block.block_hash == block_hash && block.is_requested())
+        .ok_or_else(|| {
+            StoreError::Unknown(anyhow!(
+                "deployment {} has not been told to skip block {}",
+                site.deployment,
+                block_hash
+            ))
+        })?;
+    block.unskipped_at = Some(unskipped_at);
+    let block = block.clone();
+    set_skipped_blocks(conn, site, skipped)?;
+    Ok(block)
+}
+
+/// The trigger budget that the deployment was created with, or `None`
+/// for deployments created before budgets were recorded
+pub fn max_trigger_bytes(conn: &PgConnection, site: &Site) -> Result<Option<usize>, StoreError> {
+    use subgraph_manifest as m;
+
+    Ok(m::table
+        .filter(m::id.eq(site.id))
+        .select(m::max_trigger_bytes)
+        .get_result::<Option<i64>>(conn)?
+        .map(|bytes| bytes as usize))
+}
+
+/// Return the reloads of the mappings of the deployment, oldest first
+pub fn mappings_reloads(
+    conn: &PgConnection,
+    site: &Site,
+) -> Result<Vec<MappingsReload>, StoreError> {
+    use subgraph_deployment as d;
+
+    let reloads = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::mappings_reloads)
+        .get_result::<serde_json::Value>(conn)?;
+    Ok(serde_json::from_value(reloads)?)
+}
+
+/// Record that the deployment runs the mappings from `reload.manifest`
+/// from now on
+pub fn add_mappings_reload(
+    conn: &PgConnection,
+    site: &Site,
+    reload: MappingsReload,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let mut reloads = mappings_reloads(conn, site)?;
+    reloads.push(reload);
+    let reloads = serde_json::to_value(reloads)?;
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::mappings_reloads.eq(reloads))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Record that the deployment skipped the block `block_ptr`
+pub fn record_skipped_block(
+    conn: &PgConnection,
+    site: &Site,
+    block_ptr: &BlockPtr,
+    skipped_at: Timestamp,
+) -> Result<(), StoreError> {
+    let block_hash = block_ptr.hash.to_string();
+    let mut skipped = skipped_blocks(conn, site)?;
+    let block = skipped
+        .iter_mut()
+        .find(|block| block.block_hash == block_hash && block.is_requested())
+        .ok_or_else(|| {
+            constraint_violation!(
+                "deployment {} skipped block {} without being told to",
+                site.deployment,
+                block_ptr
+            )
+        })?;
+    block.block_number = Some(block_ptr.number);
+    block.skipped_at = Some(skipped_at);
+    block.reverted_at = None;
+    set_skipped_blocks(conn, site, skipped)
+}
+
+/// Record that the deployment reverted all blocks starting at `block`,
+/// including the ones it had skipped
+pub fn revert_skipped_blocks(
+    conn: &PgConnection,
+    site: &Site,
+    block: BlockNumber,
+    reverted_at: Timestamp,
+) -> Result<(), StoreError> {
+    let mut skipped = skipped_blocks(conn, site)?;
+    let mut changed = false;
+    for skip in skipped.iter_mut().filter(|skip| skip.is_applied()) {
+        if skip.block_number.map_or(false, |number| number >= block) {
+            skip.reverted_at = Some(reverted_at);
+            changed = true;
+        }
+    }
+    if changed {
+        set_skipped_blocks(conn, site, skipped)?;
+    }
+    Ok(())
+}
+
+/// Return the data sources whose handlers that only run once have run,
+/// and the blocks in which they ran
+pub fn once_handler_runs(
+    conn: &PgConnection,
+    site: &Site,
+) -> Result<Vec<OnceHandlerRun>, StoreError> {
+    use subgraph_deployment as d;
+
+    let runs = d::table
+        .filter(d::id.eq(site.id))
+        .select(d::once_handler_runs)
+        .get_result::<serde_json::Value>(conn)?;
+    Ok(serde_json::from_value(runs)?)
+}
+
+fn set_once_handler_runs(
+    conn: &PgConnection,
+    site: &Site,
+    runs: Vec<OnceHandlerRun>,
+) -> Result<(), StoreError> {
+    use subgraph_deployment as d;
+
+    let runs = serde_json::to_value(runs)?;
+    update(d::table.filter(d::id.eq(site.id)))
+        .set(d::once_handler_runs.eq(runs))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Record that the handlers of `data_sources` that only run once ran in
+/// `block`, replacing what we knew about these data sources before
+pub fn record_once_handler_runs(
+    conn: &PgConnection,
+    site: &Site,
+    block: BlockNumber,
+    data_sources: Vec<DataSourceKey>,
+) -> Result<(), StoreError> {
+    let mut runs = once_handler_runs(conn, site)?;
+    runs.retain(|run| !data_sources.contains(&run.data_source));
+    runs.extend(
+        data_sources
+            .into_iter()
+            .map(|data_source| OnceHandlerRun { data_source, block }),
+    );
+    set_once_handler_runs(conn, site, runs)
+}
+
+/// Forget the runs of handlers that only run once in `block` and later
+/// blocks since these blocks are being reverted
+pub fn revert_once_handler_runs(
+    conn: &PgConnection,
+    site: &Site,
+    block: BlockNumber,
+) -> Result<(), StoreError> {
+    let mut runs = once_handler_runs(conn, site)?;
+    let count = runs.len();
+    runs.retain(|run| run.block < block);
+    if runs.len() != count {
+        set_once_handler_runs(conn, site, runs)?;
+    }
+    Ok(())
+}
+
 /// Set the deployment's entity count to whatever `full_count_query` produces
 pub fn set_entity_count(
     conn: &PgConnection,
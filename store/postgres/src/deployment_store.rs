@@ -4,11 +4,16 @@ use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use futures03::FutureExt as _;
-use graph::components::store::{EntityType, StoredDynamicDataSource};
-use graph::data::subgraph::status;
+use graph::components::store::{
+    EntityType, EntityTypeComparison, OnceHandlerRun, StoredDynamicDataSource,
+};
+use graph::data::store::scalar::Timestamp;
+use graph::data::subgraph::{status, DeploymentProfile, ScanTotals, SubgraphFeature};
+use graph::prelude::chrono::Utc;
 use graph::prelude::{
     tokio, CancelHandle, CancelToken, CancelableError, PoolWaitStats, SubgraphDeploymentEntity,
 };
+use graph::util::clock::ClockWatch;
 use lru_time_cache::LruCache;
 use rand::{seq::SliceRandom, thread_rng};
 use std::collections::{BTreeMap, HashMap};
@@ -28,20 +33,27 @@ use graph::constraint_violation;
 use graph::data::subgraph::schema::{SubgraphError, POI_OBJECT};
 use graph::prelude::{
     anyhow, debug, futures03, info, lazy_static, o, web3, ApiSchema, AttributeNames, BlockNumber,
-    BlockPtr, CheapClone, DeploymentHash, DeploymentState, DynTryFuture, Entity, EntityKey,
-    EntityModification, EntityQuery, Error, Logger, QueryExecutionError, Schema, StopwatchMetrics,
-    StoreError, StoreEvent, Value, BLOCK_NUMBER_MAX,
+    BlockPtr, CheapClone, DataSourceKey, DeploymentHash, DeploymentState, DynTryFuture, Entity,
+    EntityKey, EntityModification, EntityQuery, Error, Logger, NodeId, QueryExecutionError, Schema,
+    StopwatchMetrics, StoreError, StoreEvent, Value, BLOCK_NUMBER_MAX,
 };
 use graph_graphql::prelude::api_schema;
 use web3::types::Address;
 
+use crate::archive::{self, ArchiveMetrics};
 use crate::block_range::block_number;
 use crate::catalog;
-use crate::deployment;
+use crate::compare;
+use crate::copy::CopyMetrics;
+use crate::deployment::{self, PendingRevert};
 use crate::relational::{Layout, LayoutCache};
 use crate::relational_queries::FromEntityData;
+use crate::revert::{self, RevertChunks, RevertMetrics};
 use crate::{connection_pool::ConnectionPool, detail};
-use crate::{dynds, primary::Site};
+use crate::{
+    dynds,
+    primary::{DeploymentId, Site},
+};
 
 lazy_static! {
     /// `GRAPH_QUERY_STATS_REFRESH_INTERVAL` is how long statistics that
@@ -57,6 +69,22 @@ lazy_static! {
             Duration::from_secs(secs)
         }).unwrap_or(Duration::from_secs(300))
     };
+
+    /// `GRAPH_DEPLOYMENT_WRITER_LEASE` is how long (in seconds) a node
+    /// keeps the exclusive right to write to a deployment after its last
+    /// write. Another node can only take over writing to the deployment
+    /// once that lease has expired or when the deployment is explicitly
+    /// reassigned. Defaults to 600s (10 minutes).
+    static ref WRITER_LEASE_DURATION: Duration = {
+        env::var("GRAPH_DEPLOYMENT_WRITER_LEASE")
+        .ok()
+        .map(|s| {
+            let secs = u64::from_str(&s).unwrap_or_else(|_| {
+                panic!("GRAPH_DEPLOYMENT_WRITER_LEASE must be a number, but is `{}`", s)
+            });
+            Duration::from_secs(secs)
+        }).unwrap_or(Duration::from_secs(600))
+    };
 }
 
 /// When connected to read replicas, this allows choosing which DB server to use for an operation.
@@ -106,6 +134,10 @@ pub struct StoreInner {
     /// hosts this because it lives long enough, but it is managed from
     /// the entities module
     pub(crate) layout_cache: LayoutCache,
+
+    /// Watches the clock of the database server, which writer leases are
+    /// based on
+    clock: ClockWatch,
 }
 
 /// Storage of the data for individual deployments. Each `DeploymentStore`
@@ -162,6 +194,7 @@ impl DeploymentStore {
             conn_round_robin_counter: AtomicUsize::new(0),
             subgraph_cache: Mutex::new(LruCache::with_capacity(100)),
             layout_cache: LayoutCache::new(*STATS_REFRESH_INTERVAL),
+            clock: ClockWatch::new("database"),
         };
         let store = DeploymentStore(Arc::new(store));
 
@@ -197,7 +230,14 @@ impl DeploymentStore {
                 let query = format!("create schema {}", &site.namespace);
                 conn.batch_execute(&query)?;
 
-                let layout = Layout::create_relational_schema(&conn, site.clone(), schema)?;
+                // For grafts, composite indexes are created concurrently
+                // once the data has been copied, in `start_subgraph`
+                let layout = Layout::create_relational_schema(
+                    &conn,
+                    site.clone(),
+                    schema,
+                    graft_base.is_some(),
+                )?;
                 // See if we are grafting and check that the graft is permissible
                 if let Some(base) = graft_base {
                     let errors = layout.can_copy_from(&base);
@@ -241,7 +281,14 @@ impl DeploymentStore {
         site: Arc<Site>,
         query: EntityQuery,
     ) -> Result<Vec<T>, QueryExecutionError> {
-        let layout = self.layout(conn, site)?;
+        let mut layout = self.layout(conn, site.cheap_clone())?;
+        // Entity versions that are visible at blocks before `archived`
+        // might have been moved to the archive
+        if let Some(archived) = deployment::archived_block(conn, site.as_ref())? {
+            if query.block < archived {
+                layout = Arc::new(archive::with_archive(layout.as_ref()));
+            }
+        }
 
         let logger = query.logger.unwrap_or(self.logger.clone());
         layout.query(
@@ -253,6 +300,7 @@ impl DeploymentStore {
             query.range,
             query.block,
             query.query_id,
+            query.deadline,
         )
     }
 
@@ -629,6 +677,71 @@ impl DeploymentStore {
         conn.transaction(|| deployment::set_synced(&conn, id))
     }
 
+    pub(crate) fn deployment_profile(
+        &self,
+        site: &Site,
+    ) -> Result<Option<DeploymentProfile>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::profile(&conn, site)
+    }
+
+    pub(crate) fn composite_indexes(
+        &self,
+        site: Arc<Site>,
+    ) -> Result<Vec<status::CompositeIndex>, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site.cheap_clone())?;
+        let mut existing = catalog::composite_indexes(&conn, &site.namespace)?;
+
+        let mut tables = layout.tables.values().collect::<Vec<_>>();
+        tables.sort_by(|a, b| a.object.as_str().cmp(b.object.as_str()));
+        let mut indexes = Vec::new();
+        for table in tables {
+            for index in &table.composite_indexes {
+                let fields = index
+                    .columns
+                    .iter()
+                    .filter_map(|name| table.column(name))
+                    .map(|column| column.field.clone())
+                    .collect();
+                indexes.push(status::CompositeIndex {
+                    name: index.name.clone(),
+                    entity_type: Some(table.object.to_string()),
+                    fields,
+                    unique: index.unique,
+                    declared: true,
+                    valid: existing.remove(&index.name).unwrap_or(false),
+                });
+            }
+        }
+
+        let mut leftover = existing.into_iter().collect::<Vec<_>>();
+        leftover.sort();
+        indexes.extend(
+            leftover
+                .into_iter()
+                .map(|(name, valid)| status::CompositeIndex {
+                    unique: name.starts_with("cuidx_"),
+                    name,
+                    entity_type: None,
+                    fields: vec![],
+                    declared: false,
+                    valid,
+                }),
+        );
+        Ok(indexes)
+    }
+
+    pub(crate) fn complete_subgraph(&self, id: &DeploymentHash) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| deployment::complete(&conn, id))
+    }
+
+    pub(crate) fn is_completed(&self, id: &DeploymentHash) -> Result<bool, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::is_completed(&conn, id)
+    }
+
     // Only used for tests
     #[cfg(debug_assertions)]
     pub(crate) fn drop_deployment_schema(
@@ -651,6 +764,8 @@ impl DeploymentStore {
         delete from subgraphs.subgraph_deployment;
         delete from subgraphs.subgraph_deployment_assignment;
         delete from subgraphs.subgraph_version;
+        delete from subgraphs.subgraph_version_switch;
+        delete from subgraphs.deployment_label;
         delete from subgraphs.subgraph_manifest;
         delete from subgraphs.copy_table_state;
         delete from subgraphs.copy_state;
@@ -680,6 +795,17 @@ impl DeploymentStore {
         Self::block_ptr_with_conn(&site.deployment, &conn)
     }
 
+    /// The block pointer of the deployment as `replica` sees it, which
+    /// lags behind the primary while the replica catches up
+    pub(crate) fn replica_block_ptr(
+        &self,
+        site: &Site,
+        replica: ReplicaId,
+    ) -> Result<Option<BlockPtr>, Error> {
+        let conn = self.get_replica_conn(replica)?;
+        Self::block_ptr_with_conn(&site.deployment, &conn)
+    }
+
     pub(crate) fn block_cursor(&self, site: &Site) -> Result<Option<String>, StoreError> {
         let conn = self.get_conn()?;
 
@@ -852,6 +978,7 @@ impl DeploymentStore {
     pub(crate) fn transact_block_operations(
         &self,
         site: Arc<Site>,
+        node: &NodeId,
         block_ptr_to: BlockPtr,
         firehose_cursor: Option<String>,
         mods: Vec<EntityModification>,
@@ -874,6 +1001,22 @@ impl DeploymentStore {
         let conn = self.get_conn()?;
 
         let event = conn.transaction(|| -> Result<_, StoreError> {
+            // Make sure no other node is writing to this deployment
+            self.renew_writer_lease(&conn, site.as_ref(), node)?;
+
+            // The data of a deployment with an unfinished revert is not
+            // consistent with its head
+            if let Some(revert) = deployment::pending_revert(&conn, site.as_ref())? {
+                return Err(constraint_violation!(
+                    "can not write block {} to {} while its revert from block {} \
+                     to block {} is unfinished",
+                    block_ptr_to.number,
+                    site.deployment,
+                    revert.from,
+                    revert.target.number
+                ));
+            }
+
             // Emit a store event for the changes we are about to make. We
             // wait with sending it until we have done all our other work
             // so that we do not hold a lock on the notification queue
@@ -927,65 +1070,242 @@ impl DeploymentStore {
         &self,
         conn: &PgConnection,
         site: Arc<Site>,
+        block_ptr_from: BlockPtr,
         block_ptr_to: BlockPtr,
-    ) -> Result<StoreEvent, StoreError> {
-        let event = conn.transaction(|| -> Result<_, StoreError> {
-            // Don't revert past a graft point
-            let info = self.subgraph_info_with_conn(&conn, site.as_ref())?;
-            if let Some(graft_block) = info.graft_block {
-                if graft_block > block_ptr_to.number {
-                    return Err(anyhow!(
-                        "Can not revert subgraph `{}` to block {} as it was \
-                        grafted at block {} and reverting past a graft point \
-                        is not possible",
-                        site.deployment.clone(),
-                        block_ptr_to.number,
-                        graft_block
-                    )
-                    .into());
+        chunks: RevertChunks,
+        metrics: &RevertMetrics,
+    ) -> Result<(StoreEvent, status::RevertReport), StoreError> {
+        let start = Instant::now();
+        let layout = self.layout(&conn, site.clone())?;
+
+        // At 1 block per 15 seconds, the maximum i32
+        // value affords just over 1020 years of blocks.
+        let target: BlockNumber = block_ptr_to
+            .number
+            .try_into()
+            .expect("block numbers fit into an i32");
+        // The revert functions want the number of the first block that we need to get rid of
+        let block = target + 1;
+
+        // Revert the metadata right away. The entity data is reverted in
+        // the same transaction if that only covers a few blocks, and in
+        // chunks otherwise. The head only moves once all the data has been
+        // reverted, and no blocks can be written while chunks are
+        // outstanding. Queries are limited to the target of the revert
+        // in the meantime since the data above it is only partially
+        // reverted
+        let (event, mut entities, dynamic_data_sources_removed, revert) =
+            conn.transaction(|| -> Result<_, StoreError> {
+                // Don't revert past a graft point
+                let info = self.subgraph_info_with_conn(&conn, site.as_ref())?;
+                if let Some(graft_block) = info.graft_block {
+                    if graft_block > block_ptr_to.number {
+                        return Err(anyhow!(
+                            "Can not revert subgraph `{}` to block {} as it was \
+                            grafted at block {} and reverting past a graft point \
+                            is not possible",
+                            site.deployment.clone(),
+                            block_ptr_to.number,
+                            graft_block
+                        )
+                        .into());
+                    }
                 }
-            }
 
-            deployment::revert_block_ptr(&conn, &site.deployment, block_ptr_to.clone())?;
+                // Archived versions can not be reverted
+                if let Some(archived) = deployment::archived_block(&conn, site.as_ref())? {
+                    if archived > block_ptr_to.number {
+                        return Err(StoreError::RevertPastArchive(
+                            site.deployment.clone(),
+                            block_ptr_to.number,
+                            archived,
+                        ));
+                    }
+                }
 
-            // Revert the data
-            let layout = self.layout(&conn, site.clone())?;
+                // An interrupted chunked revert has left the data at some
+                // block above the target
+                let revert = match deployment::pending_revert(&conn, site.as_ref())? {
+                    Some(pending) => PendingRevert {
+                        target: block_ptr_to.clone(),
+                        ..pending
+                    },
+                    None => PendingRevert {
+                        from: block_ptr_from.number,
+                        target: block_ptr_to.clone(),
+                        reached: block_ptr_from.number,
+                    },
+                };
 
-            // At 1 block per 15 seconds, the maximum i32
-            // value affords just over 1020 years of blocks.
-            let block: BlockNumber = block_ptr_to
-                .number
-                .try_into()
-                .expect("block numbers fit into an i32");
-            // The revert functions want the number of the first block that we need to get rid of
-            let block = block + 1;
+                // Revert the meta data changes that correspond to this subgraph.
+                // Only certain meta data changes need to be reverted, most
+                // importantly creation of dynamic data sources. We ensure in the
+                // rest of the code that we only record history for those meta data
+                // changes that might need to be reverted
+                let dynamic_data_sources_removed =
+                    Layout::revert_metadata(&conn, &site.deployment, block)?;
 
-            let (event, count) = layout.revert_block(&conn, &site.deployment, block)?;
+                // Skipped blocks that are reverted no longer affect the data
+                deployment::revert_skipped_blocks(
+                    &conn,
+                    site.as_ref(),
+                    block,
+                    Timestamp::from(Utc::now()),
+                )?;
 
-            // Revert the meta data changes that correspond to this subgraph.
-            // Only certain meta data changes need to be reverted, most
-            // importantly creation of dynamic data sources. We ensure in the
-            // rest of the code that we only record history for those meta data
-            // changes that might need to be reverted
-            Layout::revert_metadata(&conn, &site.deployment, block)?;
+                // Handlers that only run once run again when their block
+                // is processed again
+                deployment::revert_once_handler_runs(&conn, site.as_ref(), block)?;
+
+                if revert.reached - target > chunks.size {
+                    deployment::set_pending_revert(&conn, site.as_ref(), Some(&revert))?;
+                    return Ok((
+                        StoreEvent::new(vec![]),
+                        vec![],
+                        dynamic_data_sources_removed,
+                        Some(revert),
+                    ));
+                }
 
-            deployment::update_entity_count(
-                &conn,
-                site.as_ref(),
-                layout.count_query.as_str(),
-                count,
-            )?;
-            Ok(event)
-        })?;
+                let (event, count, entities) =
+                    layout.revert_block(&conn, &site.deployment, block)?;
+                deployment::update_entity_count(
+                    &conn,
+                    site.as_ref(),
+                    layout.count_query.as_str(),
+                    count,
+                )?;
+                deployment::set_pending_revert(&conn, site.as_ref(), None)?;
+                deployment::revert_block_ptr(&conn, &site.deployment, block_ptr_to.clone())?;
+                metrics.chunk_done(site.as_ref(), &entities);
+                Ok((event, entities, dynamic_data_sources_removed, None))
+            })?;
 
-        Ok(event)
+        let (event, from_block) = match revert {
+            Some(revert) => {
+                let from = revert.from;
+                let (chunk_event, chunk_entities) = self.revert_in_chunks(
+                    conn,
+                    site.as_ref(),
+                    layout.as_ref(),
+                    revert,
+                    chunks,
+                    metrics,
+                )?;
+                status::merge_entity_reverts(&mut entities, chunk_entities);
+                (event.extend(chunk_event), from)
+            }
+            None => (event, block_ptr_from.number),
+        };
+
+        let report = status::RevertReport {
+            from_block,
+            to_block: block_ptr_to.number,
+            entities,
+            dynamic_data_sources_removed,
+            duration_ms: start.elapsed().as_millis() as u64,
+            reverted_at: Timestamp::from(Utc::now()),
+        };
+        deployment::set_last_revert(&conn, site.as_ref(), report.clone())?;
+
+        debug!(self.logger, "Reverted deployment";
+               "sgd" => site.id.to_string(),
+               "subgraph_id" => site.deployment.to_string(),
+               "from_block" => report.from_block,
+               "to_block" => report.to_block,
+               "blocks" => report.blocks(),
+               "entities_restored" => report.entities_restored(),
+               "entities_removed" => report.entities_removed(),
+               "entity_types" => report.entity_types(),
+               "dynamic_data_sources_removed" => report.dynamic_data_sources_removed,
+               "time_ms" => report.duration_ms);
+
+        Ok((event, report))
+    }
+
+    /// Revert the entity data for `revert` from the block it has reached
+    /// down to its target, one chunk of blocks per transaction. The last
+    /// chunk moves the head to the target and clears the pending revert
+    /// from the deployment
+    fn revert_in_chunks(
+        &self,
+        conn: &PgConnection,
+        site: &Site,
+        layout: &Layout,
+        revert: PendingRevert,
+        chunks: RevertChunks,
+        metrics: &RevertMetrics,
+    ) -> Result<(StoreEvent, Vec<status::EntityRevert>), StoreError> {
+        let target = revert.target.number;
+        let mut event = StoreEvent::new(vec![]);
+        let mut entities = vec![];
+        let ends = revert::chunk_ends(revert.reached, target, chunks.size);
+        for reached in ends.into_iter().take(chunks.limit.unwrap_or(usize::MAX)) {
+            let start = Instant::now();
+            let (chunk_event, chunk_entities) = conn.transaction(|| -> Result<_, StoreError> {
+                let (event, count, entities) =
+                    layout.revert_block(&conn, &site.deployment, reached + 1)?;
+                deployment::update_entity_count(&conn, site, layout.count_query.as_str(), count)?;
+                if reached == target {
+                    deployment::set_pending_revert(&conn, site, None)?;
+                    deployment::revert_block_ptr(&conn, &site.deployment, revert.target.clone())?;
+                } else {
+                    let pending = PendingRevert {
+                        reached,
+                        ..revert.clone()
+                    };
+                    deployment::set_pending_revert(&conn, site, Some(&pending))?;
+                }
+                Ok((event, entities))
+            })?;
+            metrics.chunk_done(site, &chunk_entities);
+            debug!(self.logger, "Reverted chunk of blocks";
+                   "sgd" => site.id.to_string(),
+                   "reached" => reached,
+                   "target" => target,
+                   "time_ms" => start.elapsed().as_millis());
+            event = event.extend(chunk_event);
+            status::merge_entity_reverts(&mut entities, chunk_entities);
+        }
+        Ok((event, entities))
+    }
+
+    /// Finish a chunked revert that was interrupted, e.g., because the
+    /// node was restarted in the middle of it
+    pub(crate) fn finish_pending_revert(
+        &self,
+        logger: &Logger,
+        site: Arc<Site>,
+        metrics: &RevertMetrics,
+    ) -> Result<Option<StoreEvent>, StoreError> {
+        let conn = self.get_conn()?;
+        let revert = match deployment::pending_revert(&conn, site.as_ref())? {
+            Some(revert) => revert,
+            None => return Ok(None),
+        };
+        info!(logger, "Resuming interrupted revert";
+              "from_block" => revert.from,
+              "to_block" => revert.target.number,
+              "reached" => revert.reached);
+        let layout = self.layout(&conn, site.clone())?;
+        let (event, _) = self.revert_in_chunks(
+            &conn,
+            site.as_ref(),
+            layout.as_ref(),
+            revert,
+            RevertChunks::default(),
+            metrics,
+        )?;
+        Ok(Some(event))
     }
 
     pub(crate) fn rewind(
         &self,
         site: Arc<Site>,
         block_ptr_to: BlockPtr,
-    ) -> Result<StoreEvent, StoreError> {
+        chunks: RevertChunks,
+        metrics: &RevertMetrics,
+    ) -> Result<(StoreEvent, status::RevertReport), StoreError> {
         let conn = self.get_conn()?;
 
         // Unwrap: If we are reverting then the block ptr is not `None`.
@@ -999,15 +1319,19 @@ impl DeploymentStore {
                 block_ptr_to.number
             );
         }
-        self.rewind_with_conn(&conn, site, block_ptr_to)
+        self.rewind_with_conn(&conn, site, block_ptr_from, block_ptr_to, chunks, metrics)
     }
 
     pub(crate) fn revert_block_operations(
         &self,
         site: Arc<Site>,
+        node: &NodeId,
         block_ptr_to: BlockPtr,
-    ) -> Result<StoreEvent, StoreError> {
+        metrics: &RevertMetrics,
+    ) -> Result<(StoreEvent, status::RevertReport), StoreError> {
         let conn = self.get_conn()?;
+        self.renew_writer_lease(&conn, site.as_ref(), node)?;
+
         // Unwrap: If we are reverting then the block ptr is not `None`.
         let block_ptr_from = Self::block_ptr_with_conn(&site.deployment, &conn)?.unwrap();
 
@@ -1016,7 +1340,14 @@ impl DeploymentStore {
             panic!("revert_block_operations must revert a single block only");
         }
 
-        self.rewind_with_conn(&conn, site, block_ptr_to)
+        self.rewind_with_conn(
+            &conn,
+            site,
+            block_ptr_from,
+            block_ptr_to,
+            RevertChunks::default(),
+            metrics,
+        )
     }
 
     pub(crate) async fn deployment_state_from_id(
@@ -1104,6 +1435,7 @@ impl DeploymentStore {
         logger: &Logger,
         site: Arc<Site>,
         graft_src: Option<(Arc<Layout>, BlockPtr)>,
+        copy_metrics: Arc<CopyMetrics>,
     ) -> Result<(), StoreError> {
         let dst = self.find_layout(site)?;
 
@@ -1127,6 +1459,7 @@ impl DeploymentStore {
                 src.clone(),
                 dst.clone(),
                 block.clone(),
+                copy_metrics,
             )?;
             let status = copy_conn.copy_data()?;
             if status == crate::copy::Status::Cancelled {
@@ -1175,14 +1508,345 @@ impl DeploymentStore {
                 Ok(())
             })?;
         }
+
+        // Create any composite indexes that are missing, either because
+        // they were deferred while grafting, or because an earlier attempt
+        // to create them was interrupted
+        let conn = self.get_conn()?;
+        dst.create_composite_indexes(&conn, logger)?;
         Ok(())
     }
 
+    /// Take the writer lease for `site` on behalf of `node`, failing with
+    /// `StoreError::DeploymentLeaseHeld` if another node holds it
+    pub(crate) fn acquire_writer_lease(
+        &self,
+        site: &Site,
+        node: &NodeId,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        self.renew_writer_lease(&conn, site, node)
+    }
+
+    /// Take or renew the writer lease for `site` on behalf of `node`. A
+    /// jump of the database clock can make the lease of another node look
+    /// expired when it is not; we therefore only take over expired leases
+    /// once the clock has not jumped for as long as a lease lasts
+    fn renew_writer_lease(
+        &self,
+        conn: &PgConnection,
+        site: &Site,
+        node: &NodeId,
+    ) -> Result<(), StoreError> {
+        self.clock
+            .observe(&self.logger, catalog::database_time(conn)?);
+        let take_expired = self.clock.settled(*WRITER_LEASE_DURATION);
+        deployment::renew_writer_lease(conn, site, node, *WRITER_LEASE_DURATION, take_expired)
+    }
+
+    /// Give the writer lease for `site` to `node`, or release it if `node`
+    /// is `None`
+    pub(crate) fn transfer_writer_lease(
+        &self,
+        site: &Site,
+        node: Option<&NodeId>,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        self.transfer_writer_lease_with_conn(&conn, site, node)
+    }
+
+    pub(crate) fn transfer_writer_lease_with_conn(
+        &self,
+        conn: &PgConnection,
+        site: &Site,
+        node: Option<&NodeId>,
+    ) -> Result<(), StoreError> {
+        deployment::transfer_writer_lease(conn, site, node, *WRITER_LEASE_DURATION)
+    }
+
+    pub(crate) fn set_block_latency(
+        &self,
+        site: &Site,
+        p50: f64,
+        p95: f64,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        deployment::set_block_latency(&conn, site, p50, p95)
+    }
+
+    pub(crate) fn set_synced_checks(&self, site: &Site, checks: u32) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        deployment::set_synced_checks(&conn, site, checks)
+    }
+
+    pub(crate) fn add_scan_totals(
+        &self,
+        site: &Site,
+        totals: &ScanTotals,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        deployment::add_scan_totals(&conn, site, totals)
+    }
+
+    pub(crate) fn reset_scan_totals(&self, site: &Site) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        deployment::reset_scan_totals(&conn, site)
+    }
+
+    /// Turn archiving for `site` on or off. Turning it on creates the
+    /// archive tables if they do not exist yet. Turning it off only stops
+    /// further archiving; versions that have already been archived stay in
+    /// the archive
+    pub(crate) fn set_archive_blocks(
+        &self,
+        site: Arc<Site>,
+        blocks: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| {
+            if blocks.is_some() {
+                let layout = self.layout(&conn, site.cheap_clone())?;
+                archive::create(&conn, layout.as_ref())?;
+            }
+            deployment::set_archive_blocks(&conn, site.as_ref(), blocks)
+        })
+    }
+
+    /// The deployments in this shard that have entity versions that should
+    /// be archived, and the block up to which they should be archived
+    pub(crate) async fn archive_horizons(
+        &self,
+    ) -> Result<Vec<(DeploymentId, BlockNumber)>, StoreError> {
+        self.with_conn(|conn, _| deployment::archive_horizons(conn).map_err(|e| e.into()))
+            .await
+    }
+
+    /// Archive all entity versions for `site` that were closed at or before
+    /// `horizon`, and the `BlockInfo` entities of blocks at or before
+    /// `horizon` if `site` has the `blockInfo` feature. We record the new horizon before moving anything so
+    /// that queries include the archive for all blocks whose versions
+    /// might get moved
+    pub(crate) async fn archive(
+        &self,
+        logger: &Logger,
+        site: Arc<Site>,
+        horizon: BlockNumber,
+        metrics: Arc<ArchiveMetrics>,
+    ) -> Result<(), StoreError> {
+        let store = self.clone();
+        let logger = logger.clone();
+        self.with_conn(move |conn, cancel| {
+            let layout = store.layout(conn, site.cheap_clone())?;
+            let block_info =
+                deployment::features(conn, site.as_ref())?.contains(&SubgraphFeature::BlockInfo);
+            deployment::set_archived_block(conn, site.as_ref(), horizon)?;
+            metrics.archived_block(site.as_ref(), horizon);
+
+            let start = Instant::now();
+            let mut total = 0;
+            for table in layout.tables.values() {
+                cancel.check_cancel()?;
+                total +=
+                    archive::archive_table(conn, &layout, table, horizon, block_info, &|count| {
+                        metrics.versions_moved(site.as_ref(), count)
+                    })?;
+            }
+            info!(logger, "Archived entity versions";
+                  "sgd" => site.id.to_string(),
+                  "subgraph_id" => site.deployment.to_string(),
+                  "horizon" => horizon,
+                  "versions" => total,
+                  "time_ms" => start.elapsed().as_millis());
+            Ok(())
+        })
+        .await
+    }
+
+    /// Return the layout for `site` for copying its data to another
+    /// deployment. If versions of `site` have been archived, the layout
+    /// reads from both the entity tables and the archive
+    pub(crate) fn find_layout_for_copy(&self, site: Arc<Site>) -> Result<Arc<Layout>, StoreError> {
+        let conn = self.get_conn()?;
+        let layout = self.layout(&conn, site.cheap_clone())?;
+        match deployment::archived_block(&conn, site.as_ref())? {
+            Some(_) => Ok(Arc::new(archive::with_archive(layout.as_ref()))),
+            None => Ok(layout),
+        }
+    }
+
     pub(crate) fn unfail(&self, site: Arc<Site>) -> Result<(), StoreError> {
         let conn = self.get_conn()?;
         conn.transaction(|| deployment::unfail(&conn, &site.deployment))
     }
 
+    pub(crate) fn fatal_error(&self, site: Arc<Site>) -> Result<Option<SubgraphError>, StoreError> {
+        let conn = self.get_conn()?;
+        detail::fatal_error(&conn, &site.deployment)
+    }
+
+    /// Clear the fatal error of the deployment like `unfail` does, but add
+    /// the error to the deployment's failure history
+    pub(crate) fn retry_failed(&self, site: Arc<Site>) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| {
+            if let Some(error) = detail::fatal_error(&conn, &site.deployment)? {
+                let failure = status::RetriedFailure::new(error, Timestamp::from(Utc::now()));
+                deployment::add_retried_failure(&conn, site.as_ref(), failure)?;
+                deployment::unfail(&conn, &site.deployment)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Tell the deployment to skip the block with `block_hash`. If the
+    /// deployment failed on that block, clear the failure like
+    /// `retry_failed` does so that the deployment can move past the block
+    /// when it is started again
+    pub(crate) fn skip_block(
+        &self,
+        site: Arc<Site>,
+        block_hash: &str,
+        reason: String,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        let now = Timestamp::from(Utc::now());
+        conn.transaction(|| {
+            deployment::request_skip_block(&conn, site.as_ref(), block_hash, reason, now)?;
+            if let Some(error) = detail::fatal_error(&conn, &site.deployment)? {
+                let failed_block = error.block_ptr.as_ref().map(|ptr| ptr.hash.to_string());
+                if failed_block.as_deref() == Some(block_hash) {
+                    let failure = status::RetriedFailure::new(error, now);
+                    deployment::add_retried_failure(&conn, site.as_ref(), failure)?;
+                    deployment::unfail(&conn, &site.deployment)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    pub(crate) fn unskip_block(
+        &self,
+        site: Arc<Site>,
+        block_hash: &str,
+    ) -> Result<status::SkippedBlock, StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| {
+            deployment::unskip_block(
+                &conn,
+                site.as_ref(),
+                block_hash,
+                Timestamp::from(Utc::now()),
+            )
+        })
+    }
+
+    pub(crate) fn add_mappings_reload(
+        &self,
+        site: &Site,
+        manifest: &DeploymentHash,
+        rewound_to: Option<BlockNumber>,
+    ) -> Result<status::MappingsReload, StoreError> {
+        let conn = self.get_conn()?;
+        let reload = status::MappingsReload {
+            manifest: manifest.to_string(),
+            rewound_to,
+            reloaded_at: Timestamp::from(Utc::now()),
+        };
+        conn.transaction(|| deployment::add_mappings_reload(&conn, site, reload.clone()))?;
+        Ok(reload)
+    }
+
+    /// Compare the entities of `pending` with those of `current` at
+    /// `block`. Both deployments must be stored in this shard
+    pub(crate) fn compare_versions(
+        &self,
+        current: Arc<Site>,
+        pending: Arc<Site>,
+        block: BlockNumber,
+        sample_size: usize,
+    ) -> Result<Vec<EntityTypeComparison>, StoreError> {
+        let conn = self.get_conn()?;
+        let mut layouts = Vec::with_capacity(2);
+        for site in &[current, pending] {
+            let head = deployment::block_ptr(&conn, &site.deployment)?;
+            if head.map_or(true, |head| head.number < block) {
+                return Err(StoreError::Unknown(anyhow!(
+                    "deployment {} has not processed block {} yet",
+                    site.deployment,
+                    block
+                )));
+            }
+            let mut layout = self.layout(&conn, site.cheap_clone())?;
+            // Entity versions that are visible at blocks before `archived`
+            // might have been moved to the archive
+            if let Some(archived) = deployment::archived_block(&conn, site.as_ref())? {
+                if block < archived {
+                    layout = Arc::new(archive::with_archive(layout.as_ref()));
+                }
+            }
+            layouts.push(layout);
+        }
+        compare::compare(&conn, &layouts[0], &layouts[1], block, sample_size)
+    }
+
+    pub(crate) fn max_trigger_bytes(&self, site: &Site) -> Result<Option<usize>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::max_trigger_bytes(&conn, site)
+    }
+
+    pub(crate) fn mappings_manifest(
+        &self,
+        site: &Site,
+    ) -> Result<Option<DeploymentHash>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::mappings_reloads(&conn, site)?
+            .pop()
+            .map(|reload| {
+                DeploymentHash::new(reload.manifest).map_err(|manifest| {
+                    constraint_violation!(
+                        "invalid manifest `{}` in mappings reloads of {}",
+                        manifest,
+                        site.deployment
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    pub(crate) fn skipped_blocks(
+        &self,
+        site: &Site,
+    ) -> Result<Vec<status::SkippedBlock>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::skipped_blocks(&conn, site)
+    }
+
+    pub(crate) fn record_skipped_block(
+        &self,
+        site: &Site,
+        block_ptr: &BlockPtr,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| {
+            deployment::record_skipped_block(&conn, site, block_ptr, Timestamp::from(Utc::now()))
+        })
+    }
+
+    pub(crate) fn once_handler_runs(&self, site: &Site) -> Result<Vec<OnceHandlerRun>, StoreError> {
+        let conn = self.get_conn()?;
+        deployment::once_handler_runs(&conn, site)
+    }
+
+    pub(crate) fn record_once_handler_runs(
+        &self,
+        site: &Site,
+        block: BlockNumber,
+        data_sources: Vec<DataSourceKey>,
+    ) -> Result<(), StoreError> {
+        let conn = self.get_conn()?;
+        conn.transaction(|| deployment::record_once_handler_runs(&conn, site, block, data_sources))
+    }
+
     #[cfg(debug_assertions)]
     pub fn error_count(&self, id: &DeploymentHash) -> Result<usize, StoreError> {
         let conn = self.get_conn()?;
@@ -7,21 +7,30 @@ use crate::{
     },
     primary::DeploymentId,
 };
-use diesel::pg::PgConnection;
+use diesel::pg::{data_types::PgTimestamp, PgConnection};
 use diesel::prelude::{
-    ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl,
+    ExpressionMethods, JoinOnDsl, NullableExpressionMethods, OptionalExtension, QueryDsl,
+    RunQueryDsl,
 };
 use diesel_derives::Associations;
 use git_testament::{git_testament, git_testament_macros};
 use graph::{
     constraint_violation,
-    data::subgraph::schema::{SubgraphError, SubgraphManifestEntity},
+    data::subgraph::{
+        features::SubgraphFeature,
+        schema::{SubgraphError, SubgraphManifestEntity},
+        Labels,
+    },
     prelude::{
-        bigdecimal::ToPrimitive, BigDecimal, BlockPtr, DeploymentHash, StoreError,
+        bigdecimal::ToPrimitive, serde_json, BigDecimal, BlockPtr, DeploymentHash, StoreError,
         SubgraphDeploymentEntity,
     },
 };
-use graph::{data::subgraph::status, prelude::web3::types::H256};
+use graph::{
+    data::{store::scalar::Timestamp, subgraph::status},
+    prelude::web3::types::H256,
+};
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::{ops::Bound, sync::Arc};
 
@@ -35,6 +44,9 @@ const CARGO_PKG_VERSION_PATCH: &str = env!("CARGO_PKG_VERSION_PATCH");
 
 type Bytes = Vec<u8>;
 
+/// Microseconds from the Unix epoch to the Postgres epoch, 2000-01-01
+const PG_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
 #[derive(Queryable, QueryableByName)]
 #[table_name = "subgraph_deployment"]
 // We map all fields to make loading `Detail` with diesel easier, but we
@@ -62,6 +74,23 @@ pub struct DeploymentDetail {
     current_reorg_depth: i32,
     max_reorg_depth: i32,
     firehose_cursor: Option<String>,
+    writer_node: Option<String>,
+    writer_lease_expires: Option<PgTimestamp>,
+    block_latency_p50: Option<f64>,
+    block_latency_p95: Option<f64>,
+    archive_blocks: Option<i32>,
+    archived_block: Option<i32>,
+    last_revert: Option<serde_json::Value>,
+    failure_history: serde_json::Value,
+    synced_checks: i32,
+    skipped_blocks: serde_json::Value,
+    revert_from: Option<i32>,
+    revert_target: Option<i32>,
+    revert_reached: Option<i32>,
+    last_processed_at: Option<PgTimestamp>,
+    mappings_reloads: serde_json::Value,
+    revert_target_hash: Option<Bytes>,
+    once_handler_runs: serde_json::Value,
 }
 
 #[derive(Queryable, QueryableByName)]
@@ -163,6 +192,7 @@ impl<'a> TryFrom<DetailAndError<'a>> for status::Info {
             failed: _,
             health,
             synced,
+            synced_checks,
             fatal_error: _,
             non_fatal_errors: _,
             earliest_ethereum_block_hash,
@@ -173,6 +203,13 @@ impl<'a> TryFrom<DetailAndError<'a>> for status::Info {
             graft_base: _,
             graft_block_hash: _,
             graft_block_number: _,
+            block_latency_p50,
+            block_latency_p95,
+            last_revert,
+            failure_history,
+            skipped_blocks,
+            last_processed_at,
+            mappings_reloads,
             ..
         } = detail;
 
@@ -210,17 +247,44 @@ impl<'a> TryFrom<DetailAndError<'a>> for status::Info {
             )
         })?;
         let fatal_error = error.map(|e| SubgraphError::try_from(e)).transpose()?;
+        let last_revert = last_revert.map(serde_json::from_value).transpose()?;
+        let failure_history = serde_json::from_value(failure_history)?;
+        let skipped_blocks = serde_json::from_value(skipped_blocks)?;
+        let mappings_reloads = serde_json::from_value(mappings_reloads)?;
+        let last_processed_at = last_processed_at
+            .map(|PgTimestamp(micros)| {
+                Timestamp::from_microseconds_since_epoch(micros + PG_EPOCH_MICROS).ok_or_else(
+                    || constraint_violation!("invalid last_processed_at for {}", deployment),
+                )
+            })
+            .transpose()?;
         // 'node' needs to be filled in later from a different shard
         Ok(status::Info {
             id: id.into(),
             subgraph: deployment,
             synced,
+            synced_checks: synced_checks as u32,
             health,
             fatal_error,
             non_fatal_errors: vec![],
             chains: vec![chain],
             entity_count,
             node: None,
+            names: vec![],
+            labels: Labels::new(),
+            block_latency_p50,
+            block_latency_p95,
+            // Filled in by `deployment_statuses` from the manifest
+            eth_calls_disabled: false,
+            last_revert,
+            failure_history,
+            // Filled in by `deployment_statuses` from the copy state
+            copy_status: None,
+            skipped_blocks,
+            mappings_reloads,
+            last_processed_at,
+            // Filled in for pages of statuses
+            cursor: None,
         })
     }
 }
@@ -243,21 +307,41 @@ pub(crate) fn deployment_details(
     Ok(details)
 }
 
+/// Return the error that made the deployment `id` fail, or `None` if it
+/// has not failed
+pub(crate) fn fatal_error(
+    conn: &PgConnection,
+    id: &DeploymentHash,
+) -> Result<Option<SubgraphError>, StoreError> {
+    use subgraph_deployment as d;
+    use subgraph_error as e;
+
+    d::table
+        .inner_join(e::table.on(d::fatal_error.eq(e::id.nullable())))
+        .filter(d::deployment.eq(id.as_str()))
+        .select(e::all_columns)
+        .first::<ErrorDetail>(conn)
+        .optional()?
+        .map(SubgraphError::try_from)
+        .transpose()
+}
+
 pub(crate) fn deployment_statuses(
     conn: &PgConnection,
     sites: &Vec<Arc<Site>>,
 ) -> Result<Vec<status::Info>, StoreError> {
     use subgraph_deployment as d;
     use subgraph_error as e;
+    use subgraph_manifest as m;
 
     // Empty deployments means 'all of them'
-    if sites.is_empty() {
+    let mut infos = if sites.is_empty() {
         d::table
             .left_outer_join(e::table.on(d::fatal_error.eq(e::id.nullable())))
             .load::<(DeploymentDetail, Option<ErrorDetail>)>(conn)?
             .into_iter()
             .map(|(detail, error)| status::Info::try_from(DetailAndError(detail, error, sites)))
-            .collect()
+            .collect::<Result<Vec<_>, _>>()?
     } else {
         let ids: Vec<_> = sites.into_iter().map(|site| site.id).collect();
 
@@ -267,8 +351,25 @@ pub(crate) fn deployment_statuses(
             .load::<(DeploymentDetail, Option<ErrorDetail>)>(conn)?
             .into_iter()
             .map(|(detail, error)| status::Info::try_from(DetailAndError(detail, error, sites)))
-            .collect()
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    let ids: Vec<_> = infos.iter().map(|info| info.id.0).collect();
+    let no_eth_calls = SubgraphFeature::NoEthCalls.to_string();
+    let disabled: HashSet<i32> = m::table
+        .filter(m::id.eq_any(&ids))
+        .select((m::id, m::features))
+        .load::<(i32, Vec<String>)>(conn)?
+        .into_iter()
+        .filter(|(_, features)| features.contains(&no_eth_calls))
+        .map(|(id, _)| id)
+        .collect();
+    let mut copies = crate::copy::copy_statuses(conn, &ids)?;
+    for info in &mut infos {
+        info.eth_calls_disabled = disabled.contains(&info.id.0);
+        info.copy_status = copies.remove(&info.id.0);
     }
+    Ok(infos)
 }
 
 #[derive(Queryable, QueryableByName, Identifiable, Associations)]
@@ -285,6 +386,8 @@ struct StoredSubgraphManifest {
     features: Vec<String>,
     schema: String,
     graph_node_version_id: Option<i32>,
+    profile: Option<serde_json::Value>,
+    max_trigger_bytes: Option<i64>,
 }
 
 impl From<StoredSubgraphManifest> for SubgraphManifestEntity {
@@ -295,6 +398,11 @@ impl From<StoredSubgraphManifest> for SubgraphManifestEntity {
             repository: value.repository,
             features: value.features,
             schema: value.schema,
+            // A profile we can not read is as good as no profile
+            profile: value
+                .profile
+                .and_then(|profile| serde_json::from_value(profile).ok()),
+            max_trigger_bytes: value.max_trigger_bytes.map(|bytes| bytes as usize),
         }
     }
 }
@@ -57,6 +57,7 @@ fn to_source(
         address,
         abi,
         start_block,
+        end_block: None,
     })
 }
 
@@ -133,6 +134,7 @@ pub(crate) fn insert(
                         address,
                         abi,
                         start_block,
+                        end_block: _,
                     },
                 context,
                 creation_block: _,
@@ -216,12 +218,14 @@ pub(crate) fn revert(
     conn: &PgConnection,
     id: &DeploymentHash,
     block: BlockNumber,
-) -> Result<(), StoreError> {
+) -> Result<usize, StoreError> {
     use dynamic_ethereum_contract_data_source as decds;
 
     let dds = decds::table.filter(decds::deployment.eq(id.as_str()));
-    delete(dds.filter(decds::ethereum_block_number.ge(sql(&block.to_string())))).execute(conn)?;
-    Ok(())
+    Ok(
+        delete(dds.filter(decds::ethereum_block_number.ge(sql(&block.to_string()))))
+            .execute(conn)?,
+    )
 }
 
 pub(crate) fn drop(conn: &PgConnection, id: &DeploymentHash) -> Result<usize, StoreError> {
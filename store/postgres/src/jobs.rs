@@ -1,15 +1,19 @@
 //! Jobs for database maintenance
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use diesel::{prelude::RunQueryDsl, sql_query, sql_types::Double};
 
-use graph::prelude::{error, Logger, MetricsRegistry, StoreError};
+use graph::prelude::{
+    error, info, Logger, MetricsRegistry, NodeId, StoreError, SubgraphStore as _,
+};
 use graph::prometheus::Gauge;
+use graph::util::clock::ClockWatch;
 use graph::util::jobs::{Job, Runner};
 
+use crate::archive::ArchiveMetrics;
 use crate::connection_pool::ConnectionPool;
 use crate::{Store, SubgraphStore};
 
@@ -25,9 +29,45 @@ pub fn register(
     );
 
     runner.register(
-        Arc::new(NotificationQueueUsage::new(primary_pool, registry)),
+        Arc::new(NotificationQueueUsage::new(primary_pool, registry.clone())),
         Duration::from_secs(60),
     );
+
+    runner.register(
+        Arc::new(ArchiveJob::new(store.subgraph_store(), registry)),
+        Duration::from_secs(600),
+    );
+
+    runner.register(
+        Arc::new(MetadataMaintenanceJob {
+            store: store.subgraph_store(),
+        }),
+        Duration::from_secs(3600),
+    );
+}
+
+/// Register the job that records that `node` is alive. Other nodes use
+/// these heartbeats to tell whether the deployments assigned to `node` are
+/// still being indexed. Also register the job that makes `node` take over
+/// the deployments it is the standby for once their node is dead
+pub fn register_heartbeat(runner: &mut Runner, store: Arc<Store>, node: NodeId) {
+    runner.register(
+        Arc::new(HeartbeatJob {
+            store: store.subgraph_store(),
+            node: node.clone(),
+            clock: ClockWatch::new("system"),
+        }),
+        Duration::from_secs(30),
+    );
+
+    runner.register(
+        Arc::new(StandbyJob {
+            store: store.subgraph_store(),
+            node,
+            clock: ClockWatch::new("database"),
+        }),
+        Duration::from_secs(30),
+    );
 }
 
 /// A job that vacuums `subgraphs.subgraph_deployment`. With a large number
@@ -62,6 +102,58 @@ impl Job for VacuumDeploymentsJob {
     }
 }
 
+/// A job that moves old entity versions of deployments that have
+/// archiving turned on into their archive tables
+struct ArchiveJob {
+    store: Arc<SubgraphStore>,
+    metrics: Arc<ArchiveMetrics>,
+}
+
+impl ArchiveJob {
+    fn new(store: Arc<SubgraphStore>, registry: Arc<impl MetricsRegistry>) -> ArchiveJob {
+        ArchiveJob {
+            store,
+            metrics: Arc::new(ArchiveMetrics::new(registry)),
+        }
+    }
+}
+
+#[async_trait]
+impl Job for ArchiveJob {
+    fn name(&self) -> &str {
+        "Archive old entity versions"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        self.store.archive(logger, self.metrics.clone()).await
+    }
+}
+
+/// A job that removes metadata about subgraphs that is not needed any
+/// more, so that it does not slow down the queries the registrar runs on
+/// startup. The same maintenance can be run on demand with the
+/// `store_maintain_metadata` admin RPC method
+struct MetadataMaintenanceJob {
+    store: Arc<SubgraphStore>,
+}
+
+#[async_trait]
+impl Job for MetadataMaintenanceJob {
+    fn name(&self) -> &str {
+        "Maintain subgraph metadata"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        match self.store.maintain_metadata() {
+            Ok(report) => info!(logger, "Maintained subgraph metadata";
+                                "versions_removed" => report.versions_removed,
+                                "audit_entries_removed" => report.audit_entries_removed,
+                                "version_switches_removed" => report.version_switches_removed),
+            Err(e) => error!(logger, "Metadata maintenance failed: {}", e),
+        }
+    }
+}
+
 struct NotificationQueueUsage {
     primary: ConnectionPool,
     usage_gauge: Box<Gauge>,
@@ -115,3 +207,51 @@ impl Job for NotificationQueueUsage {
         }
     }
 }
+
+struct HeartbeatJob {
+    store: Arc<SubgraphStore>,
+    node: NodeId,
+    /// Heartbeats use the database clock, and a jump of the clock on this
+    /// machine does not affect them; we only complain about it since it
+    /// throws off anything that reports timestamps from this machine
+    clock: ClockWatch,
+}
+
+#[async_trait]
+impl Job for HeartbeatJob {
+    fn name(&self) -> &str {
+        "Record node heartbeat"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        self.clock.observe(logger, SystemTime::now());
+        if let Err(e) = self.store.record_heartbeat(&self.node) {
+            error!(
+                logger,
+                "Recording heartbeat for node {} failed: {}", self.node, e
+            );
+        }
+    }
+}
+
+struct StandbyJob {
+    store: Arc<SubgraphStore>,
+    node: NodeId,
+    clock: ClockWatch,
+}
+
+#[async_trait]
+impl Job for StandbyJob {
+    fn name(&self) -> &str {
+        "Take over deployments from dead nodes"
+    }
+
+    async fn run(&self, logger: &Logger) {
+        if let Err(e) = self.store.promote_standbys(logger, &self.node, &self.clock) {
+            error!(
+                logger,
+                "Taking over deployments for standby node {} failed: {}", self.node, e
+            );
+        }
+    }
+}
@@ -24,11 +24,13 @@ extern crate serde;
 extern crate uuid;
 
 mod advisory_lock;
+mod archive;
 mod block_range;
 mod block_store;
 mod catalog;
 mod chain_head_listener;
 mod chain_store;
+mod compare;
 pub mod connection_pool;
 mod copy;
 mod deployment;
@@ -43,6 +45,7 @@ mod primary;
 pub mod query_store;
 mod relational;
 mod relational_queries;
+mod revert;
 mod sql_value;
 mod store;
 mod store_events;
@@ -61,13 +64,16 @@ pub mod layout_for_tests {
     pub use crate::relational::*;
 }
 
+pub use self::archive::ArchiveMetrics;
 pub use self::block_store::BlockStore;
 pub use self::chain_head_listener::ChainHeadUpdateListener;
 pub use self::chain_store::ChainStore;
 pub use self::detail::DeploymentDetail;
 pub use self::jobs::register as register_jobs;
+pub use self::jobs::register_heartbeat as register_heartbeat_job;
 pub use self::notification_listener::NotificationSender;
 pub use self::primary::UnusedDeployment;
+pub use self::revert::RevertMetrics;
 pub use self::store::Store;
 pub use self::store_events::SubscriptionManager;
 pub use self::subgraph_store::{unused, DeploymentPlacer, Shard, SubgraphStore, PRIMARY_SHARD};
@@ -1,19 +1,20 @@
 //! Utilities for dealing with subgraph metadata that resides in the primary
 //! shard. Anything in this module can only be used with a database connection
 //! for the primary shard.
+use diesel::connection::SimpleConnection;
 use diesel::{
     data_types::PgTimestamp,
     dsl::{any, exists, not, select},
     pg::Pg,
     serialize::Output,
-    sql_types::{Array, Integer, Text},
+    sql_types::{Array, BigInt, Bool, Integer, Nullable, Text},
     types::{FromSql, ToSql},
 };
 use diesel::{
     dsl::{delete, insert_into, sql, update},
     r2d2::PooledConnection,
 };
-use diesel::{pg::PgConnection, r2d2::ConnectionManager};
+use diesel::{pg::PgConnection, r2d2::ConnectionManager, sql_query};
 use diesel::{
     prelude::{
         BoolExpressionMethods, ExpressionMethods, GroupByDsl, JoinOnDsl, NullableExpressionMethods,
@@ -23,11 +24,15 @@ use diesel::{
 };
 use graph::components::store::DeploymentId as GraphDeploymentId;
 use graph::{
+    components::server::admin::{AdminAuditEntry, AdminCall},
     components::store::DeploymentLocator,
     constraint_violation,
-    data::subgraph::status,
+    data::{
+        store::scalar::Timestamp,
+        subgraph::{status, Labels},
+    },
     prelude::{
-        anyhow, bigdecimal::ToPrimitive, serde_json, DeploymentHash, EntityChange,
+        anyhow, bigdecimal::ToPrimitive, serde_json, BlockNumber, DeploymentHash, EntityChange,
         EntityChangeOperation, NodeId, StoreError, SubgraphName, SubgraphVersionSwitchingMode,
     },
 };
@@ -39,14 +44,16 @@ use std::{
     convert::TryInto,
     fmt,
     io::Write,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     block_range::UNVERSIONED_RANGE,
+    catalog,
+    connection_pool::ForeignServer,
     detail::DeploymentDetail,
     subgraph_store::{unused, Shard},
-    NotificationSender,
+    NotificationSender, PRIMARY_SHARD,
 };
 
 #[cfg(debug_assertions)]
@@ -86,6 +93,7 @@ table! {
     subgraphs.subgraph_deployment_assignment {
         id -> Integer,
         node_id -> Text,
+        standby_node -> Nullable<Text>,
     }
 }
 
@@ -108,6 +116,51 @@ table! {
     }
 }
 
+table! {
+    /// The last time each node recorded that it is alive
+    node_heartbeat(node_id) {
+        node_id -> Text,
+        last_heartbeat -> Timestamptz,
+    }
+}
+
+table! {
+    /// Every call to the admin server that changes something
+    admin_audit_log(id) {
+        id -> BigInt,
+        method -> Text,
+        params -> Jsonb,
+        caller -> Nullable<Text>,
+        source -> Nullable<Text>,
+        succeeded -> Nullable<Bool>,
+        error -> Nullable<Text>,
+        started_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    /// Every time the pending version of a subgraph became its current
+    /// version
+    subgraphs.subgraph_version_switch(id) {
+        id -> BigInt,
+        subgraph -> Text,
+        old_deployment -> Nullable<Text>,
+        new_deployment -> Text,
+        block_number -> Nullable<Integer>,
+        switched_at -> Timestamptz,
+    }
+}
+
+table! {
+    /// Labels that operators attached to deployments, by deployment hash
+    subgraphs.deployment_label(deployment, key) {
+        deployment -> Text,
+        key -> Text,
+        value -> Text,
+    }
+}
+
 /// We used to support different layout schemes. The old 'Split' scheme
 /// which used JSONB layout has been removed, and we will only deal
 /// with relational layout. Trying to do anything with a 'Split' subgraph
@@ -358,8 +411,6 @@ impl From<&Site> for DeploymentLocator {
 /// not originate in the database
 #[cfg(debug_assertions)]
 pub fn make_dummy_site(deployment: DeploymentHash, namespace: Namespace, network: String) -> Site {
-    use crate::PRIMARY_SHARD;
-
     Site {
         id: DeploymentId(-7),
         deployment,
@@ -384,6 +435,13 @@ impl<'a> Connection<'a> {
         Self { conn: conn.into() }
     }
 
+    /// The underlying connection. Deployments in the primary shard keep
+    /// their data in the same database, and changing it through this
+    /// connection makes those changes part of transactions in the primary
+    pub(crate) fn as_pg(&self) -> &PgConnection {
+        self.conn.as_ref()
+    }
+
     pub(crate) fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
     where
         F: FnOnce() -> Result<T, E>,
@@ -484,40 +542,77 @@ impl<'a> Connection<'a> {
 
     /// Promote the deployment `id` to the current version everywhere where it was
     /// the pending version so far, and remove any assignments that are not needed
-    /// any longer as a result. Return the changes that were made to assignments
-    /// in the process
-    pub fn promote_deployment(&self, id: &DeploymentHash) -> Result<Vec<EntityChange>, StoreError> {
+    /// any longer as a result. Each switch is recorded together with `block`,
+    /// the block that the deployment has reached. Return the changes that were
+    /// made to assignments in the process and the switches that were made
+    pub fn promote_deployment(
+        &self,
+        id: &DeploymentHash,
+        block: Option<BlockNumber>,
+    ) -> Result<(Vec<EntityChange>, Vec<status::VersionSwitch>), StoreError> {
         use subgraph as s;
         use subgraph_version as v;
+        use subgraph_version_switch as sw;
 
         let conn = self.conn.as_ref();
 
         // Subgraphs where we need to promote the version
-        let pending_subgraph_versions: Vec<(String, String)> = s::table
+        let pending_subgraph_versions: Vec<(String, String, String)> = s::table
             .inner_join(v::table.on(s::pending_version.eq(v::id.nullable())))
             .filter(v::deployment.eq(id.as_str()))
-            .select((s::id, v::id))
+            .select((s::id, s::name, v::id))
             .for_update()
             .load(conn)?;
 
+        if pending_subgraph_versions.is_empty() {
+            return Ok((vec![], vec![]));
+        }
+
+        // Since we are in a transaction, `now()` is the same for every
+        // switch we record
+        let now = select(sql::<BigInt>("(extract(epoch from now()) * 1000000)::int8"))
+            .get_result::<i64>(conn)?;
+        let switched_at = Timestamp::from_microseconds_since_epoch(now)
+            .ok_or_else(|| constraint_violation!("invalid timestamp {}", now))?;
+
         // Switch the pending version to the current version
-        for (subgraph, version) in &pending_subgraph_versions {
+        let mut switches = Vec::new();
+        for (subgraph, name, version) in &pending_subgraph_versions {
+            let old_deployment = s::table
+                .inner_join(v::table.on(s::current_version.eq(v::id.nullable())))
+                .filter(s::id.eq(subgraph))
+                .select(v::deployment)
+                .first::<String>(conn)
+                .optional()?;
+
             update(s::table.filter(s::id.eq(subgraph)))
                 .set((
                     s::current_version.eq(version),
                     s::pending_version.eq::<Option<&str>>(None),
                 ))
                 .execute(conn)?;
+
+            insert_into(sw::table)
+                .values((
+                    sw::subgraph.eq(name),
+                    sw::old_deployment.eq(&old_deployment),
+                    sw::new_deployment.eq(id.as_str()),
+                    sw::block_number.eq(block),
+                ))
+                .execute(conn)?;
+
+            switches.push(status::VersionSwitch {
+                subgraph: name.clone(),
+                old_deployment,
+                new_deployment: id.to_string(),
+                block,
+                switched_at,
+            });
         }
 
-        // Clean up assignments if we could possibly have changed any
-        // subgraph versions
-        let changes = if pending_subgraph_versions.is_empty() {
-            vec![]
-        } else {
-            self.remove_unused_assignments()?
-        };
-        Ok(changes)
+        // Clean up assignments since we changed subgraph versions
+        let changes = self.remove_unused_assignments()?;
+        Ok((changes, switches))
     }
 
     /// Create a new subgraph with the given name. If one already exists, use
@@ -674,6 +769,33 @@ impl<'a> Connection<'a> {
         Ok(changes)
     }
 
+    /// Lock the assignments of all deployments that versions of `subgraph`
+    /// use. Several subgraphs can use the same deployment, and the
+    /// deployment stays assigned as long as any of them still uses it as
+    /// its current or pending version. When two of these subgraphs are
+    /// removed concurrently, the lock makes the second removal wait for the
+    /// first one, so that it sees that the other subgraph is gone and
+    /// removes the assignment. Without it, both removals could conclude
+    /// that the other subgraph still uses the deployment and leave it
+    /// assigned
+    fn lock_assignments_for_subgraph(&self, subgraph: &str) -> Result<(), StoreError> {
+        use deployment_schemas as ds;
+        use subgraph_deployment_assignment as a;
+        use subgraph_version as v;
+
+        let deployments = v::table
+            .inner_join(ds::table.on(v::deployment.eq(ds::subgraph)))
+            .filter(v::subgraph.eq(subgraph))
+            .select(ds::id);
+        a::table
+            .filter(a::id.eq_any(deployments))
+            .select(a::id)
+            .order_by(a::id)
+            .for_update()
+            .load::<i32>(self.conn.as_ref())?;
+        Ok(())
+    }
+
     pub fn remove_subgraph(&self, name: SubgraphName) -> Result<Vec<EntityChange>, StoreError> {
         use subgraph as s;
         use subgraph_version as v;
@@ -688,6 +810,7 @@ impl<'a> Connection<'a> {
             .first(conn)
             .optional()?;
         if let Some(subgraph) = subgraph {
+            self.lock_assignments_for_subgraph(&subgraph)?;
             delete(v::table.filter(v::subgraph.eq(&subgraph))).execute(conn)?;
             delete(s::table.filter(s::id.eq(subgraph))).execute(conn)?;
             self.remove_unused_assignments()
@@ -854,9 +977,10 @@ impl<'a> Connection<'a> {
             .map(|_| ())
     }
 
-    /// Remove all subgraph versions and the entry in `deployment_schemas` for
-    /// subgraph `id` in a transaction
+    /// Remove all subgraph versions, labels and the entry in
+    /// `deployment_schemas` for subgraph `id` in a transaction
     pub fn drop_site(&self, site: &Site) -> Result<(), StoreError> {
+        use deployment_label as l;
         use deployment_schemas as ds;
         use subgraph_version as v;
         use unused_deployments as u;
@@ -875,6 +999,8 @@ impl<'a> Connection<'a> {
             if !exists {
                 delete(v::table.filter(v::deployment.eq(site.deployment.as_str())))
                     .execute(conn)?;
+                delete(l::table.filter(l::deployment.eq(site.deployment.as_str())))
+                    .execute(conn)?;
             }
 
             update(u::table.filter(u::id.eq(site.id)))
@@ -1061,6 +1187,57 @@ impl<'a> Connection<'a> {
             .transpose()
     }
 
+    /// Make `node` the standby for `site`, or remove the standby if `node`
+    /// is `None`. The deployment must be assigned to some node
+    pub fn set_standby_node(&self, site: &Site, node: Option<&NodeId>) -> Result<(), StoreError> {
+        use subgraph_deployment_assignment as a;
+
+        let updates = update(a::table.filter(a::id.eq(site.id)))
+            .set(a::standby_node.eq(node.map(|node| node.as_str())))
+            .execute(self.conn.as_ref())?;
+        match updates {
+            0 => Err(StoreError::DeploymentNotFound(site.deployment.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn standby_node(&self, site: &Site) -> Result<Option<NodeId>, StoreError> {
+        use subgraph_deployment_assignment as a;
+
+        a::table
+            .filter(a::id.eq(site.id))
+            .select(a::standby_node)
+            .first::<Option<String>>(self.conn.as_ref())
+            .optional()?
+            .flatten()
+            .map(|node| {
+                NodeId::new(&node).map_err(|()| {
+                    constraint_violation!(
+                        "invalid standby node id `{}` for `{}`",
+                        node,
+                        site.deployment
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    /// The deployments for which `node` is the standby, together with the
+    /// node each of them is assigned to
+    pub fn standbys(&self, node: &NodeId) -> Result<Vec<(Site, String)>, StoreError> {
+        use deployment_schemas as ds;
+        use subgraph_deployment_assignment as a;
+
+        ds::table
+            .inner_join(a::table.on(a::id.eq(ds::id)))
+            .filter(a::standby_node.eq(node.as_str()))
+            .select((ds::all_columns, a::node_id))
+            .load::<(Schema, String)>(self.conn.as_ref())?
+            .into_iter()
+            .map(|(schema, assigned)| Site::try_from(schema).map(|site| (site, assigned)))
+            .collect::<Result<Vec<_>, _>>()
+    }
+
     pub fn assignments(&self, node: &NodeId) -> Result<Vec<Site>, StoreError> {
         use deployment_schemas as ds;
         use subgraph_deployment_assignment as a;
@@ -1075,23 +1252,518 @@ impl<'a> Connection<'a> {
             .collect::<Result<Vec<Site>, _>>()
     }
 
+    /// Record that `node` is alive right now
+    pub fn record_heartbeat(&self, node: &NodeId) -> Result<(), StoreError> {
+        use node_heartbeat as h;
+
+        insert_into(h::table)
+            .values((
+                h::node_id.eq(node.as_str()),
+                h::last_heartbeat.eq(sql("now()")),
+            ))
+            .on_conflict(h::node_id)
+            .do_update()
+            .set(h::last_heartbeat.eq(sql("now()")))
+            .execute(self.conn.as_ref())?;
+        Ok(())
+    }
+
+    /// The current time according to the database server. Heartbeats and
+    /// writer leases are all based on that clock
+    pub fn database_time(&self) -> Result<SystemTime, StoreError> {
+        catalog::database_time(self.conn.as_ref())
+    }
+
+    /// Return the last heartbeat of every node that has ever recorded one,
+    /// and whether the node is dead because that heartbeat is more than
+    /// `dead_after` old
+    pub fn heartbeats(
+        &self,
+        dead_after: Duration,
+    ) -> Result<HashMap<String, (Timestamp, bool)>, StoreError> {
+        use node_heartbeat as h;
+
+        let micros = "(extract(epoch from last_heartbeat) * 1000000)::int8";
+        let dead = format!(
+            "last_heartbeat < now() - make_interval(secs => {})",
+            dead_after.as_secs()
+        );
+        h::table
+            .select((h::node_id, sql::<BigInt>(micros), sql::<Bool>(&dead)))
+            .load::<(String, i64, bool)>(self.conn.as_ref())?
+            .into_iter()
+            .map(|(node, micros, dead)| {
+                Timestamp::from_microseconds_since_epoch(micros)
+                    .map(|ts| (node.clone(), (ts, dead)))
+                    .ok_or_else(|| {
+                        constraint_violation!("invalid heartbeat {} for node {}", micros, node)
+                    })
+            })
+            .collect()
+    }
+
+    pub fn start_admin_call(&self, call: AdminCall) -> Result<i64, StoreError> {
+        use admin_audit_log as l;
+
+        let id = insert_into(l::table)
+            .values((
+                l::method.eq(&call.method),
+                l::params.eq(&call.params),
+                l::caller.eq(&call.caller),
+                l::source.eq(&call.source),
+            ))
+            .returning(l::id)
+            .get_result::<i64>(self.conn.as_ref())?;
+        Ok(id)
+    }
+
+    pub fn finish_admin_call(&self, id: i64, error: Option<String>) -> Result<(), StoreError> {
+        use admin_audit_log as l;
+
+        let rows = update(
+            l::table
+                .filter(l::id.eq(id))
+                .filter(l::finished_at.is_null()),
+        )
+        .set((
+            l::succeeded.eq(error.is_none()),
+            l::error.eq(&error),
+            l::finished_at.eq(sql("now()")),
+        ))
+        .execute(self.conn.as_ref())?;
+        if rows != 1 {
+            return Err(constraint_violation!(
+                "admin audit log entry {} does not exist or has already been finished",
+                id
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn admin_audit_log(
+        &self,
+        first: usize,
+        after: Option<i64>,
+        method: Option<String>,
+    ) -> Result<Vec<AdminAuditEntry>, StoreError> {
+        use admin_audit_log as l;
+
+        fn timestamp(micros: i64) -> Result<Timestamp, StoreError> {
+            Timestamp::from_microseconds_since_epoch(micros)
+                .ok_or_else(|| constraint_violation!("invalid audit log timestamp {}", micros))
+        }
+
+        let mut query = l::table
+            .select((
+                l::id,
+                l::method,
+                l::params,
+                l::caller,
+                l::source,
+                l::succeeded,
+                l::error,
+                sql::<BigInt>("(extract(epoch from started_at) * 1000000)::int8"),
+                sql::<Nullable<BigInt>>("(extract(epoch from finished_at) * 1000000)::int8"),
+            ))
+            .filter(l::id.gt(after.unwrap_or(0)))
+            .order_by(l::id)
+            .limit(first as i64)
+            .into_boxed();
+        if let Some(method) = method {
+            query = query.filter(l::method.eq(method));
+        }
+        query
+            .load::<(
+                i64,
+                String,
+                serde_json::Value,
+                Option<String>,
+                Option<String>,
+                Option<bool>,
+                Option<String>,
+                i64,
+                Option<i64>,
+            )>(self.conn.as_ref())?
+            .into_iter()
+            .map(
+                |(id, method, params, caller, source, succeeded, error, started, finished)| {
+                    Ok(AdminAuditEntry {
+                        id,
+                        call: AdminCall {
+                            method,
+                            params,
+                            caller,
+                            source,
+                        },
+                        succeeded,
+                        error,
+                        started_at: timestamp(started)?,
+                        finished_at: finished.map(timestamp).transpose()?,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    pub fn version_switches(
+        &self,
+        subgraph_name: &str,
+        first: usize,
+    ) -> Result<Vec<status::VersionSwitch>, StoreError> {
+        use subgraph_version_switch as sw;
+
+        sw::table
+            .select((
+                sw::subgraph,
+                sw::old_deployment,
+                sw::new_deployment,
+                sw::block_number,
+                sql::<BigInt>("(extract(epoch from switched_at) * 1000000)::int8"),
+            ))
+            .filter(sw::subgraph.eq(subgraph_name))
+            .order_by(sw::id.desc())
+            .limit(first as i64)
+            .load::<(String, Option<String>, String, Option<i32>, i64)>(self.conn.as_ref())?
+            .into_iter()
+            .map(
+                |(subgraph, old_deployment, new_deployment, block, switched_at)| {
+                    let switched_at = Timestamp::from_microseconds_since_epoch(switched_at)
+                        .ok_or_else(|| {
+                            constraint_violation!(
+                                "invalid version switch timestamp {}",
+                                switched_at
+                            )
+                        })?;
+                    Ok(status::VersionSwitch {
+                        subgraph,
+                        old_deployment,
+                        new_deployment,
+                        block,
+                        switched_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Find the deployments that match `query` and return their ids and
+    /// sort keys in the order and for the page that `query` asks for. The
+    /// `subgraph_deployment` tables of all `shards` are queried through
+    /// their mappings into the primary, and `heads` are the block numbers
+    /// of the chain heads that the lag of deployments is measured against
+    pub fn indexing_status_page(
+        &self,
+        query: &status::StatusQuery,
+        shards: &[Shard],
+        heads: &HashMap<String, BlockNumber>,
+    ) -> Result<Vec<(GraphDeploymentId, status::StatusKey)>, StoreError> {
+        use status::{StatusKey, StatusOrder};
+
+        #[derive(QueryableByName)]
+        struct Row {
+            #[sql_type = "Integer"]
+            id: i32,
+            #[sql_type = "Text"]
+            key: String,
+        }
+
+        const COLUMNS: &str = "id, deployment, health, synced, \
+                               latest_ethereum_block_number, last_processed_at";
+        let deployments = shards
+            .iter()
+            .map(|shard| {
+                let nsp = if shard == &*PRIMARY_SHARD {
+                    "subgraphs".to_string()
+                } else {
+                    ForeignServer::metadata_schema(shard)
+                };
+                format!("select {} from {}.subgraph_deployment", COLUMNS, nsp)
+            })
+            .collect::<Vec<_>>()
+            .join(" union all ");
+
+        let (key, key_type) = match query.order {
+            StatusOrder::Id => ("d.id::int8", "int8"),
+            StatusOrder::Lag => (
+                "coalesce(h.head, 0)::int8 - coalesce(d.latest_ethereum_block_number, -1)::int8",
+                "int8",
+            ),
+            StatusOrder::LastProcessedAt => (
+                "coalesce((extract(epoch from d.last_processed_at) * 1000000)::int8, 0)",
+                "int8",
+            ),
+            StatusOrder::Name => ("coalesce(n.name, d.deployment)", "text"),
+        };
+        let (dir, cmp) = if query.descending {
+            ("desc", "<")
+        } else {
+            ("asc", ">")
+        };
+
+        let sql = format!(
+            "with heads as (
+                select * from unnest($1::text[], $2::int4[]) as h(network, head)),
+             deployments as ({deployments}),
+             names as (
+                select v.deployment, min(s.name) as name,
+                       bool_or(left(s.name, length($8)) = $8) as has_prefix
+                  from subgraphs.subgraph s
+                  join subgraphs.subgraph_version v
+                    on v.id in (s.current_version, s.pending_version)
+                 group by v.deployment),
+             statuses as (
+                select d.id, {key} as key
+                  from deployments d
+                  join deployment_schemas ds on ds.id = d.id and ds.active
+                  left join subgraphs.subgraph_deployment_assignment a on a.id = d.id
+                  left join heads h on h.network = ds.network
+                  left join names n on n.deployment = d.deployment
+                 where ($3::text[] is null or d.deployment = any($3))
+                   and ($4::text is null or d.health::text = $4)
+                   and ($5::bool is null or d.synced = $5)
+                   and ($6::text is null or ds.network = $6)
+                   and ($7::text is null or a.node_id = $7)
+                   and ($8::text is null or n.has_prefix)
+                   and ($12::text[] is null or not exists (
+                         select 1
+                           from unnest($12::text[]) as sel(selector)
+                          where not exists (
+                                select 1
+                                  from subgraphs.deployment_label l
+                                 where l.deployment = d.deployment
+                                   and sel.selector in (l.key, l.key || ':' || l.value)))))
+             select id, key::text as key
+               from statuses
+              where $9::text is null or (key, id) {cmp} ($9::{key_type}, $10)
+              order by key {dir}, id {dir}
+              limit $11",
+            deployments = deployments,
+            key = key,
+            key_type = key_type,
+            cmp = cmp,
+            dir = dir,
+        );
+
+        let (networks, heads): (Vec<_>, Vec<_>) = heads
+            .iter()
+            .map(|(network, head)| (network.as_str(), *head))
+            .unzip();
+        let deployments = if query.deployments.is_empty() {
+            None
+        } else {
+            Some(&query.deployments)
+        };
+        let after = query.after.as_ref();
+        let labels = if query.labels.is_empty() {
+            None
+        } else {
+            Some(
+                query
+                    .labels
+                    .iter()
+                    .map(|selector| selector.to_string())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        sql_query(sql)
+            .bind::<Array<Text>, _>(networks)
+            .bind::<Array<Integer>, _>(heads)
+            .bind::<Nullable<Array<Text>>, _>(deployments)
+            .bind::<Nullable<Text>, _>(query.health.map(|health| health.as_str()))
+            .bind::<Nullable<Bool>, _>(query.synced)
+            .bind::<Nullable<Text>, _>(query.network.as_deref())
+            .bind::<Nullable<Text>, _>(query.node.as_ref().map(|node| node.as_str()))
+            .bind::<Nullable<Text>, _>(query.name_prefix.as_deref())
+            .bind::<Nullable<Text>, _>(after.map(|after| after.key.to_string()))
+            .bind::<Integer, _>(after.map(|after| after.id.0).unwrap_or(0))
+            .bind::<BigInt, _>(query.first as i64)
+            .bind::<Nullable<Array<Text>>, _>(labels)
+            .load::<Row>(self.conn.as_ref())?
+            .into_iter()
+            .map(|row| {
+                let key = if query.order.is_numeric() {
+                    StatusKey::Number(row.key.parse().map_err(|_| {
+                        constraint_violation!("invalid sort key `{}` for status", row.key)
+                    })?)
+                } else {
+                    StatusKey::Text(row.key)
+                };
+                Ok((GraphDeploymentId::new(row.id), key))
+            })
+            .collect()
+    }
+
+    /// Remove versions that are neither the current nor the pending
+    /// version of their subgraph, except for the `keep` most recent such
+    /// versions of each subgraph. Versions of subgraphs that do not exist
+    /// anymore are always removed. Return how many versions were removed
+    pub fn remove_superseded_versions(&self, keep: usize) -> Result<usize, StoreError> {
+        let query = "\
+            delete from subgraphs.subgraph_version
+             where vid in (
+                select vid
+                  from (select v.vid,
+                               row_number() over (partition by v.subgraph
+                                                  order by v.created_at desc, v.vid desc) as rank,
+                               exists (select 1 from subgraphs.subgraph s
+                                        where s.id = v.subgraph) as has_subgraph
+                          from subgraphs.subgraph_version v
+                         where not exists (select 1 from subgraphs.subgraph s
+                                            where s.current_version = v.id
+                                               or s.pending_version = v.id)) superseded
+                 where superseded.rank > $1
+                    or not superseded.has_subgraph)";
+        Ok(sql_query(query)
+            .bind::<BigInt, _>(keep as i64)
+            .execute(self.conn.as_ref())?)
+    }
+
+    /// Remove all but the `keep` most recent entries from the admin audit
+    /// log. Entries for calls that have not finished are never removed.
+    /// Return how many entries were removed
+    pub fn trim_admin_audit_log(&self, keep: usize) -> Result<usize, StoreError> {
+        use admin_audit_log as l;
+
+        let conn = self.conn.as_ref();
+        let newest_removed = l::table
+            .select(l::id)
+            .order_by(l::id.desc())
+            .offset(keep as i64)
+            .first::<i64>(conn)
+            .optional()?;
+        match newest_removed {
+            Some(id) => Ok(delete(
+                l::table
+                    .filter(l::id.le(id))
+                    .filter(l::finished_at.is_not_null()),
+            )
+            .execute(conn)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Remove all but the `keep` most recent version switches of each
+    /// subgraph. Return how many switches were removed
+    pub fn trim_version_switches(&self, keep: usize) -> Result<usize, StoreError> {
+        let query = "\
+            delete from subgraphs.subgraph_version_switch
+             where id in (
+                select id
+                  from (select id,
+                               row_number() over (partition by subgraph
+                                                  order by id desc) as rank
+                          from subgraphs.subgraph_version_switch) switches
+                 where switches.rank > $1)";
+        Ok(sql_query(query)
+            .bind::<BigInt, _>(keep as i64)
+            .execute(self.conn.as_ref())?)
+    }
+
+    /// Vacuum and analyze the tables with metadata about subgraphs, their
+    /// versions and assignments. This can not be run inside a transaction
+    pub fn vacuum_metadata(&self) -> Result<(), StoreError> {
+        self.conn.batch_execute(
+            "vacuum (analyze) subgraphs.subgraph, subgraphs.subgraph_version, \
+                              subgraphs.subgraph_version_switch, \
+                              subgraphs.subgraph_deployment_assignment, \
+                              public.admin_audit_log",
+        )?;
+        Ok(())
+    }
+
+    /// The labels of the deployment with hash `deployment`
+    pub fn labels(&self, deployment: &DeploymentHash) -> Result<Labels, StoreError> {
+        use deployment_label as l;
+
+        Ok(l::table
+            .filter(l::deployment.eq(deployment.as_str()))
+            .select((l::key, l::value))
+            .load::<(String, String)>(self.conn.as_ref())?
+            .into_iter()
+            .collect())
+    }
+
+    /// Replace the labels of the deployment with hash `deployment` with
+    /// `labels`. The deployment does not need to exist yet
+    pub fn set_labels(
+        &self,
+        deployment: &DeploymentHash,
+        labels: &Labels,
+    ) -> Result<(), StoreError> {
+        use deployment_label as l;
+
+        let conn = self.conn.as_ref();
+        conn.transaction(|| {
+            delete(l::table.filter(l::deployment.eq(deployment.as_str()))).execute(conn)?;
+            let rows: Vec<_> = labels
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        l::deployment.eq(deployment.as_str()),
+                        l::key.eq(key),
+                        l::value.eq(value),
+                    )
+                })
+                .collect();
+            if !rows.is_empty() {
+                insert_into(l::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Fill in the node each deployment is assigned to, the names of the
+    /// subgraphs that use it and its labels
     pub fn fill_assignments(
         &self,
         mut infos: Vec<status::Info>,
     ) -> Result<Vec<status::Info>, StoreError> {
         use deployment_schemas as ds;
+        use subgraph as s;
         use subgraph_deployment_assignment as a;
+        use subgraph_version as v;
 
         let ids: Vec<_> = infos.iter().map(|info| &info.subgraph).collect();
         let nodes: HashMap<_, _> = a::table
             .inner_join(ds::table.on(ds::id.eq(a::id)))
-            .filter(ds::subgraph.eq(any(ids)))
+            .filter(ds::subgraph.eq(any(&ids)))
             .select((ds::subgraph, a::node_id))
             .load::<(String, String)>(self.conn.as_ref())?
             .into_iter()
             .collect();
+        let mut names: HashMap<String, Vec<String>> = HashMap::new();
+        for (deployment, name) in v::table
+            .inner_join(
+                s::table.on(v::id
+                    .nullable()
+                    .eq(s::current_version)
+                    .or(v::id.nullable().eq(s::pending_version))),
+            )
+            .filter(v::deployment.eq(any(&ids)))
+            .select((v::deployment, s::name))
+            .order_by(s::name)
+            .distinct()
+            .load::<(String, String)>(self.conn.as_ref())?
+        {
+            names.entry(deployment).or_default().push(name);
+        }
+        let mut labels: HashMap<String, Labels> = HashMap::new();
+        for (deployment, key, value) in deployment_label::table
+            .filter(deployment_label::deployment.eq(any(&ids)))
+            .select((
+                deployment_label::deployment,
+                deployment_label::key,
+                deployment_label::value,
+            ))
+            .load::<(String, String, String)>(self.conn.as_ref())?
+        {
+            labels.entry(deployment).or_default().insert(key, value);
+        }
         for mut info in &mut infos {
             info.node = nodes.get(&info.subgraph).map(|s| s.clone());
+            info.names = names.remove(&info.subgraph).unwrap_or_default();
+            info.labels = labels.remove(&info.subgraph).unwrap_or_default();
         }
         Ok(infos)
     }
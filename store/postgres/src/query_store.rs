@@ -55,6 +55,10 @@ impl QueryStoreTrait for QueryStore {
     }
 
     fn block_ptr(&self) -> Result<Option<BlockPtr>, Error> {
+        self.store.replica_block_ptr(&self.site, self.replica_id)
+    }
+
+    fn latest_block_ptr(&self) -> Result<Option<BlockPtr>, Error> {
         self.store.block_ptr(&self.site)
     }
 
@@ -30,9 +30,12 @@ use crate::{
 };
 use graph::components::store::EntityType;
 use graph::data::graphql::ext::{DirectiveFinder, DocumentExt, ObjectTypeExt};
-use graph::data::schema::{FulltextConfig, FulltextDefinition, Schema, SCHEMA_TYPE_NAME};
+use graph::data::schema::{
+    FulltextConfig, FulltextDefinition, IndexDefinition, Schema, SCHEMA_TYPE_NAME,
+};
 use graph::data::store::BYTES_SCALAR;
 use graph::data::subgraph::schema::{POI_OBJECT, POI_TABLE};
+use graph::data::subgraph::status::EntityRevert;
 use graph::prelude::{
     anyhow, info, BlockNumber, DeploymentHash, Entity, EntityChange, EntityCollection,
     EntityFilter, EntityKey, EntityOrder, EntityRange, Logger, QueryExecutionError, StoreError,
@@ -45,6 +48,8 @@ use crate::connection_pool::ForeignServer;
 use crate::{catalog, deployment};
 
 const POSTGRES_MAX_PARAMETERS: usize = u16::MAX as usize; // 65535
+/// Postgres truncates identifiers that are longer than this
+const POSTGRES_MAX_IDENTIFIER: usize = 63;
 const DELETE_OPERATION_CHUNK_SIZE: usize = 1_000;
 
 /// The size of string prefixes that we index. This is chosen so that we
@@ -79,14 +84,14 @@ lazy_static! {
     /// If it is not set, no statement timeout will be enforced. The statement
     /// timeout is local, i.e., can only be used within a transaction and
     /// will be cleared at the end of the transaction
-    static ref STATEMENT_TIMEOUT: Option<String> = {
+    static ref STATEMENT_TIMEOUT: Option<Duration> = {
         env::var("GRAPH_SQL_STATEMENT_TIMEOUT")
         .ok()
         .map(|s| {
             u64::from_str(&s).unwrap_or_else(|_| {
                 panic!("GRAPH_SQL_STATEMENT_TIMEOUT must be a number, but is `{}`", s)
             })
-        }).map(|timeout| format!("set local statement_timeout={}", timeout * 1000))
+        }).map(Duration::from_secs)
     };
 }
 
@@ -312,6 +317,7 @@ impl Layout {
                     &catalog,
                     Schema::entity_fulltext_definitions(&obj_type.name, &schema.document)
                         .map_err(|_| StoreError::FulltextSearchNonDeterministic)?,
+                    Schema::entity_index_definitions(obj_type),
                     &enums,
                     &id_types,
                     i as u32,
@@ -385,6 +391,7 @@ impl Layout {
             /// predictable
             position: position as u32,
             is_account_like: false,
+            composite_indexes: vec![],
         }
     }
 
@@ -392,20 +399,83 @@ impl Layout {
         self.tables.contains_key(&*POI_OBJECT)
     }
 
+    /// Create the tables and indexes for `schema`. If `defer_composite_indexes`
+    /// is `true`, the indexes declared with `@index` are not created here
+    /// and must be created later with `create_composite_indexes`; that is
+    /// used for grafted deployments so that copying data does not have to
+    /// maintain them
     pub fn create_relational_schema(
         conn: &PgConnection,
         site: Arc<Site>,
         schema: &Schema,
+        defer_composite_indexes: bool,
     ) -> Result<Layout, StoreError> {
         let catalog = Catalog::new(conn, site.clone())?;
         let layout = Self::new(site, schema, catalog, true)?;
         let sql = layout
-            .as_ddl()
+            .write_ddl(!defer_composite_indexes)
             .map_err(|_| StoreError::Unknown(anyhow!("failed to generate DDL for layout")))?;
         conn.batch_execute(&sql)?;
         Ok(layout)
     }
 
+    /// Create the composite indexes declared with `@index` that do not
+    /// exist in the database yet. Indexes are built concurrently so that
+    /// the tables stay usable while that happens, which means that `conn`
+    /// must not be in a transaction. Composite indexes that exist in the
+    /// database but are not declared in the schema are left alone; a
+    /// warning is logged for each of them
+    pub fn create_composite_indexes(
+        &self,
+        conn: &PgConnection,
+        logger: &Logger,
+    ) -> Result<(), StoreError> {
+        let mut existing = catalog::composite_indexes(conn, &self.site.namespace)?;
+
+        let mut tables = self.tables.values().collect::<Vec<_>>();
+        tables.sort_by_key(|table| table.position);
+        for table in tables {
+            for index in &table.composite_indexes {
+                match existing.remove(&index.name) {
+                    Some(true) => continue,
+                    Some(false) => {
+                        // A previous attempt at building the index
+                        // concurrently failed and left an invalid index
+                        // behind
+                        let query = format!(
+                            "drop index if exists {}.{}",
+                            self.site.namespace, index.name
+                        );
+                        conn.batch_execute(&query)?;
+                    }
+                    None => { /* nothing to clean up */ }
+                }
+                let start = Instant::now();
+                let mut query = String::new();
+                table
+                    .write_composite_index_ddl(&mut query, index, true)
+                    .map_err(|_| {
+                        StoreError::Unknown(anyhow!(
+                            "failed to generate DDL for index {}",
+                            index.name
+                        ))
+                    })?;
+                conn.batch_execute(&query)?;
+                info!(logger, "Created composite index";
+                      "index" => &index.name,
+                      "entity" => table.object.as_str(),
+                      "time_ms" => start.elapsed().as_millis());
+            }
+        }
+
+        for (name, _) in existing {
+            warn!(logger, "Composite index is no longer declared in the schema; leaving it in place";
+                  "index" => name,
+                  "namespace" => self.site.namespace.as_str());
+        }
+        Ok(())
+    }
+
     /// Determine if it is possible to copy the data of `source` into `self`
     /// by checking that our schema is compatible with `source`.
     /// Returns a list of errors if copying is not possible. An empty
@@ -448,6 +518,10 @@ impl Layout {
     /// See the unit tests at the end of this file for the actual DDL that
     /// gets generated
     pub fn as_ddl(&self) -> Result<String, fmt::Error> {
+        self.write_ddl(true)
+    }
+
+    fn write_ddl(&self, composite_indexes: bool) -> Result<String, fmt::Error> {
         let mut out = String::new();
 
         // Output enums first so table definitions can reference them
@@ -459,7 +533,7 @@ impl Layout {
         tables.sort_by_key(|table| table.position);
         // Output 'create table' statements for all tables
         for table in tables {
-            table.as_ddl(&mut out, self)?;
+            table.as_ddl(&mut out, self, composite_indexes)?;
         }
 
         Ok(out)
@@ -607,6 +681,7 @@ impl Layout {
         range: EntityRange,
         block: BlockNumber,
         query_id: Option<String>,
+        deadline: Option<Instant>,
     ) -> Result<Vec<T>, QueryExecutionError> {
         fn log_query_timing(
             logger: &Logger,
@@ -638,7 +713,7 @@ impl Layout {
             );
         }
 
-        let filter_collection = FilterCollection::new(&self, collection, filter.as_ref())?;
+        let filter_collection = FilterCollection::new(&self, collection, filter.as_ref(), &order)?;
         let query = FilterQuery::new(
             &filter_collection,
             filter.as_ref(),
@@ -649,15 +724,31 @@ impl Layout {
         )?;
         let query_clone = query.clone();
 
+        // The statement may not run past the deadline of the query; when
+        // that is earlier than the configured statement timeout, Postgres
+        // cancels the statement at the deadline
         let start = Instant::now();
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(start));
+        if remaining == Some(Duration::from_millis(0)) {
+            return Err(QueryExecutionError::Timeout);
+        }
+        let timeout = match (*STATEMENT_TIMEOUT, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        };
         let values = conn
             .transaction(|| {
-                if let Some(ref timeout_sql) = *STATEMENT_TIMEOUT {
-                    conn.batch_execute(timeout_sql)?;
+                if let Some(timeout) = timeout {
+                    // Postgres treats a timeout of 0 as no timeout
+                    let timeout = timeout.as_millis().max(1);
+                    conn.batch_execute(&format!("set local statement_timeout={}", timeout))?;
                 }
                 query.load::<EntityData>(conn)
             })
             .map_err(|e| {
+                if deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+                    return QueryExecutionError::Timeout;
+                }
                 QueryExecutionError::ResolveEntitiesError(format!(
                     "{}, query = {:?}",
                     e,
@@ -724,14 +815,18 @@ impl Layout {
         Ok(count)
     }
 
+    /// Revert all changes made in `block` and later. Return the event for
+    /// the changes, how much the entity count changed, and how many
+    /// versions were restored and removed for each entity type
     pub fn revert_block(
         &self,
         conn: &PgConnection,
         subgraph_id: &DeploymentHash,
         block: BlockNumber,
-    ) -> Result<(StoreEvent, i32), StoreError> {
+    ) -> Result<(StoreEvent, i32, Vec<EntityRevert>), StoreError> {
         let mut changes: Vec<EntityChange> = Vec::new();
         let mut count: i32 = 0;
+        let mut reverts: Vec<EntityRevert> = Vec::new();
 
         for table in self.tables.values() {
             // Remove all versions whose entire block range lies beyond
@@ -757,6 +852,13 @@ impl Layout {
             let deleted = removed.difference(&unclamped).count() as i32;
             let inserted = unclamped.difference(&removed).count() as i32;
             count += inserted - deleted;
+            if !removed.is_empty() || !unclamped.is_empty() {
+                reverts.push(EntityRevert {
+                    entity_type: table.object.to_string(),
+                    restored: unclamped.len(),
+                    removed: removed.len(),
+                });
+            }
             // EntityChange for versions we just deleted
             let deleted = removed
                 .into_iter()
@@ -773,23 +875,24 @@ impl Layout {
             });
             changes.extend(set);
         }
-        Ok((StoreEvent::new(changes), count))
+        Ok((StoreEvent::new(changes), count, reverts))
     }
 
     /// Revert the metadata (dynamic data sources and related entities) for
     /// the given `subgraph`.
     ///
     /// For metadata, reversion always means deletion since the metadata that
-    /// is subject to reversion is only ever created but never updated
+    /// is subject to reversion is only ever created but never updated.
+    /// Return the number of dynamic data sources that were removed
     pub fn revert_metadata(
         conn: &PgConnection,
         subgraph: &DeploymentHash,
         block: BlockNumber,
-    ) -> Result<(), StoreError> {
-        crate::dynds::revert(conn, &subgraph, block)?;
+    ) -> Result<usize, StoreError> {
+        let count = crate::dynds::revert(conn, &subgraph, block)?;
         crate::deployment::revert_subgraph_errors(conn, &subgraph, block)?;
 
-        Ok(())
+        Ok(count)
     }
 
     pub fn is_cacheable(&self) -> bool {
@@ -849,6 +952,13 @@ impl EnumType {
             ))
         }
     }
+
+    /// Whether `self` and `other` can take the same values. Enums of
+    /// different deployments have different names even if they are the
+    /// same enum since their names are qualified with the schema
+    pub(crate) fn has_same_values(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
 }
 
 /// This is almost the same as graph::data::store::ValueType, but without
@@ -861,7 +971,9 @@ pub enum ColumnType {
     BigInt,
     Bytes,
     Int,
+    Int8,
     String,
+    Timestamp,
     TSVector(FulltextConfig),
     Enum(EnumType),
     /// A `bytea` in SQL, represented as a ValueType::String; this is
@@ -921,7 +1033,9 @@ impl ColumnType {
             ValueType::BigInt => Ok(ColumnType::BigInt),
             ValueType::Bytes => Ok(ColumnType::Bytes),
             ValueType::Int => Ok(ColumnType::Int),
+            ValueType::Int8 => Ok(ColumnType::Int8),
             ValueType::String => Ok(ColumnType::String),
+            ValueType::Timestamp => Ok(ColumnType::Timestamp),
         }
     }
 
@@ -932,7 +1046,9 @@ impl ColumnType {
             ColumnType::BigInt => "numeric",
             ColumnType::Bytes => "bytea",
             ColumnType::Int => "integer",
+            ColumnType::Int8 => "int8",
             ColumnType::String => "text",
+            ColumnType::Timestamp => "timestamptz",
             ColumnType::TSVector(_) => "tsvector",
             ColumnType::Enum(enum_type) => enum_type.name.as_str(),
             ColumnType::BytesId => "bytea",
@@ -1109,6 +1225,52 @@ pub(crate) const PRIMARY_KEY_COLUMN: &str = "id";
 /// synthetic primary key. This is the name of the column we use.
 pub(crate) const VID_COLUMN: &str = "vid";
 
+/// An index over several columns that was declared with an `@index`
+/// directive on the entity type
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeIndex {
+    /// The name of the index in the database. It is derived from the table
+    /// and column names so that it stays the same across deployments.
+    /// Names that would be too long for Postgres are cut short and end in
+    /// a hash of the full name
+    pub name: String,
+    /// The indexed columns, in index order
+    pub columns: Vec<SqlName>,
+    /// Whether no two entity versions that are current at the same block
+    /// may have the same values for `columns`
+    pub unique: bool,
+}
+
+impl CompositeIndex {
+    fn new(table: &SqlName, columns: Vec<SqlName>, unique: bool) -> Self {
+        let prefix = if unique { "cuidx" } else { "cidx" };
+        let mut name = format!(
+            "{}_{}_{}",
+            prefix,
+            table,
+            columns
+                .iter()
+                .map(SqlName::as_str)
+                .collect::<Vec<_>>()
+                .join("_")
+        );
+        if name.len() > POSTGRES_MAX_IDENTIFIER {
+            // Indexes whose names only differ after the cutoff would get
+            // the same name, and only one of them would be created. Names
+            // are ASCII, so truncating at any byte is safe
+            let hash = blake3::hash(name.as_bytes()).to_hex();
+            name.truncate(POSTGRES_MAX_IDENTIFIER - 9);
+            name.push('_');
+            name.push_str(&hash[..8]);
+        }
+        CompositeIndex {
+            name,
+            columns,
+            unique,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Table {
     /// The name of the GraphQL object type ('Thing')
@@ -1128,6 +1290,9 @@ pub struct Table {
     /// entities are updated frequently on average
     pub is_account_like: bool,
 
+    /// The indexes declared with `@index` on the entity type
+    pub composite_indexes: Vec<CompositeIndex>,
+
     /// The position of this table in all the tables for this layout; this
     /// is really only needed for the tests to make the names of indexes
     /// predictable
@@ -1139,6 +1304,7 @@ impl Table {
         defn: &s::ObjectType,
         catalog: &Catalog,
         fulltexts: Vec<FulltextDefinition>,
+        indexes: Vec<IndexDefinition>,
         enums: &EnumMap,
         id_types: &IdTypeMap,
         position: u32,
@@ -1155,12 +1321,30 @@ impl Table {
             .collect::<Result<Vec<Column>, StoreError>>()?;
         let qualified_name = SqlName::qualified_name(&catalog.site.namespace, &table_name);
         let is_account_like = ACCOUNT_TABLES.contains(qualified_name.as_str());
+        let composite_indexes = indexes
+            .into_iter()
+            .map(|index| {
+                let columns = index
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        columns
+                            .iter()
+                            .find(|column| &column.field == field)
+                            .map(|column| column.name.clone())
+                            .ok_or_else(|| StoreError::UnknownField(field.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(CompositeIndex::new(&table_name, columns, index.unique))
+            })
+            .collect::<Result<Vec<_>, StoreError>>()?;
         let table = Table {
             object: EntityType::from(defn),
             name: table_name.clone(),
             qualified_name,
             is_account_like,
             columns,
+            composite_indexes,
             position,
         };
         Ok(table)
@@ -1214,12 +1398,67 @@ impl Table {
             .expect("every table has a primary key")
     }
 
+    /// Generate the DDL for one composite index. Unique indexes become an
+    /// exclusion constraint over the block range, like the one for `id`,
+    /// since several versions of an entity can have the same values. Postgres
+    /// can not add constraints concurrently, and `concurrently` therefore
+    /// only affects non-unique indexes
+    fn write_composite_index_ddl(
+        &self,
+        out: &mut String,
+        index: &CompositeIndex,
+        concurrently: bool,
+    ) -> fmt::Result {
+        if index.unique {
+            let columns = index
+                .columns
+                .iter()
+                .map(|column| format!("{} with =", column.quoted()))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                out,
+                "alter table {qname}\n    add constraint {name}\n    \
+                 exclude using gist ({columns}, {block_range} with &&);",
+                qname = self.qualified_name,
+                name = index.name,
+                columns = columns,
+                block_range = BLOCK_RANGE_COLUMN
+            )
+        } else {
+            // Like for single attribute indexes, only index a prefix of
+            // text columns so that values fit into a BTree
+            let columns = index
+                .columns
+                .iter()
+                .map(|name| match self.column(name) {
+                    Some(column) if column.is_text() => {
+                        format!("left({}, {})", name.quoted(), STRING_PREFIX_SIZE)
+                    }
+                    _ => name.quoted(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(
+                out,
+                "create index {concurrently}if not exists {name}\n    \
+                 on {qname} using btree({columns});",
+                concurrently = if concurrently { "concurrently " } else { "" },
+                name = index.name,
+                qname = self.qualified_name,
+                columns = columns
+            )
+        }
+    }
+
     /// Generate the DDL for one table, i.e. one `create table` statement
-    /// and all `create index` statements for the table's columns
+    /// and all `create index` statements for the table's columns. The
+    /// statements for composite indexes are only included if
+    /// `composite_indexes` is `true`
     ///
     /// See the unit tests at the end of this file for the actual DDL that
     /// gets generated
-    fn as_ddl(&self, out: &mut String, layout: &Layout) -> fmt::Result {
+    fn as_ddl(&self, out: &mut String, layout: &Layout, composite_indexes: bool) -> fmt::Result {
         writeln!(
             out,
             "create table {}.{} (",
@@ -1328,6 +1567,11 @@ impl Table {
                 index_expr = index_expr,
             )?;
         }
+        if composite_indexes {
+            for index in &self.composite_indexes {
+                self.write_composite_index_ddl(out, index, false)?;
+            }
+        }
         writeln!(out)
     }
 }
@@ -1476,6 +1720,10 @@ impl LayoutCache {
 mod tests {
     use super::*;
 
+    use graph::components::store::{
+        AttributeNames, ChildMultiplicity, EntityLink, EntityWindow, WindowAttribute,
+    };
+
     use crate::layout_for_tests::make_dummy_site;
 
     const ID_TYPE: ColumnType = ColumnType::String;
@@ -1538,6 +1786,81 @@ mod tests {
         assert_eq!(FORWARD_ENUM_SQL, sql);
     }
 
+    #[test]
+    fn composite_indexes() {
+        const GQL: &str = r#"
+        type Transfer @entity
+            @index(fields: ["owner", "timestamp"])
+            @index(fields: ["token", "logIndex"], unique: true) {
+            id: ID!
+            owner: String!
+            timestamp: BigInt!
+            token: Bytes!
+            logIndex: Int!
+        }"#;
+
+        let layout = test_layout(GQL);
+        let table = layout
+            .table(&SqlName::from("transfer"))
+            .expect("transfer table exists");
+        let names = table
+            .composite_indexes
+            .iter()
+            .map(|index| index.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "cidx_transfer_owner_timestamp",
+                "cuidx_transfer_token_log_index"
+            ],
+            names
+        );
+
+        let sql = layout.as_ddl().expect("Failed to generate DDL");
+        assert!(sql.contains(
+            "create index if not exists cidx_transfer_owner_timestamp\n    \
+             on \"sgd0815\".\"transfer\" using btree(left(\"owner\", 256), \"timestamp\");"
+        ));
+        assert!(sql.contains(
+            "alter table \"sgd0815\".\"transfer\"\n    \
+             add constraint cuidx_transfer_token_log_index\n    \
+             exclude using gist (\"token\" with =, \"log_index\" with =, block_range with &&);"
+        ));
+
+        let sql = layout.write_ddl(false).expect("Failed to generate DDL");
+        assert!(!sql.contains("cidx_"));
+        assert!(!sql.contains("cuidx_"));
+    }
+
+    #[test]
+    fn long_composite_index_names() {
+        const GQL: &str = r#"
+        type Transfer @entity
+            @index(fields: ["veryLongAttributeNameNumberOne", "veryLongAttributeNameNumberTwo"])
+            @index(fields: ["veryLongAttributeNameNumberOne", "veryLongAttributeNameNumberThree"]) {
+            id: ID!
+            veryLongAttributeNameNumberOne: String!
+            veryLongAttributeNameNumberTwo: String!
+            veryLongAttributeNameNumberThree: String!
+        }"#;
+
+        let layout = test_layout(GQL);
+        let table = layout
+            .table(&SqlName::from("transfer"))
+            .expect("transfer table exists");
+        let names = table
+            .composite_indexes
+            .iter()
+            .map(|index| index.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(2, names.len());
+        assert_ne!(names[0], names[1]);
+        for name in names {
+            assert_eq!(POSTGRES_MAX_IDENTIFIER, name.len());
+            assert!(name.starts_with("cidx_transfer_very_long_attribute_name_number_one_"));
+        }
+    }
+
     #[test]
     fn forward_enum() {
         let layout = test_layout(FORWARD_ENUM_GQL);
@@ -1613,6 +1936,84 @@ mod tests {
         );
     }
 
+    fn query_sql(layout: &Layout, collection: EntityCollection, order: EntityOrder) -> String {
+        let filter_collection = FilterCollection::new(layout, collection, None, &order)
+            .expect("failed to build filter collection");
+        let query = FilterQuery::new(
+            &filter_collection,
+            None,
+            order,
+            EntityRange::first(100),
+            BLOCK_NUMBER_MAX,
+            None,
+        )
+        .expect("failed to build query");
+        debug_query(&query).to_string()
+    }
+
+    fn attribute_names(names: &[&str]) -> AttributeNames {
+        let mut column_names = AttributeNames::empty();
+        for name in names {
+            column_names.insert(name);
+        }
+        column_names
+    }
+
+    #[test]
+    fn select_only_needed_columns() {
+        let layout = test_layout(MUSIC_GQL);
+        let musician = EntityType::from("Musician");
+
+        let sql = query_sql(
+            &layout,
+            EntityCollection::All(vec![(musician.clone(), attribute_names(&["name"]))]),
+            EntityOrder::Default,
+        );
+        assert!(sql.contains(r#"select c."id", c."name", c.vid, c.block_range"#));
+        assert!(!sql.contains("main_band"));
+        assert!(!sql.contains("bands"));
+
+        // The attribute we order by is selected even if it wasn't asked for
+        let sql = query_sql(
+            &layout,
+            EntityCollection::All(vec![(musician.clone(), attribute_names(&[]))]),
+            EntityOrder::Descending("name".to_string(), ValueType::String),
+        );
+        assert!(sql.contains(r#"select c."id", c."name", c.vid, c.block_range"#));
+
+        let sql = query_sql(
+            &layout,
+            EntityCollection::All(vec![(musician, AttributeNames::All)]),
+            EntityOrder::Default,
+        );
+        assert!(sql.contains("select  c.* "));
+    }
+
+    #[test]
+    fn select_only_needed_child_columns() {
+        let layout = test_layout(MUSIC_GQL);
+
+        let window = EntityWindow {
+            child_type: EntityType::from("Song"),
+            ids: vec!["m1".to_string(), "m2".to_string()],
+            link: EntityLink::Direct(
+                WindowAttribute::Scalar("writtenBy".to_string()),
+                ChildMultiplicity::Many,
+            ),
+            column_names: attribute_names(&["title"]),
+        };
+        let sql = query_sql(
+            &layout,
+            EntityCollection::Window(vec![window]),
+            EntityOrder::Default,
+        );
+        assert!(sql.contains(
+            r#"select c."id", c."title", c.vid, c.block_range, p.id::text as g$parent_id"#
+        ));
+        assert!(sql.contains(r#"(select c."id", c."title", c.vid, c.block_range from"#));
+        assert!(!sql.contains(r#"c."written_by","#));
+    }
+
     const THING_GQL: &str = "
         type Thing @entity {
             id: ID!
@@ -1632,6 +2033,8 @@ mod tests {
             bytes: Bytes,
             bigInt: BigInt,
             color: Color,
+            count: Int8,
+            timestamp: Timestamp,
         }";
 
     const THING_DDL: &str = "create type sgd0815.\"color\"
@@ -1666,6 +2069,8 @@ create table sgd0815.\"scalar\" (
         \"bytes\"              bytea,
         \"big_int\"            numeric,
         \"color\"              \"sgd0815\".\"color\",
+        \"count\"              int8,
+        \"timestamp\"          timestamptz,
 
         vid                  bigserial primary key,
         block_range          int4range not null,
@@ -1693,6 +2098,10 @@ create index attr_1_6_scalar_big_int
     on sgd0815.\"scalar\" using btree(\"big_int\");
 create index attr_1_7_scalar_color
     on sgd0815.\"scalar\" using btree(\"color\");
+create index attr_1_8_scalar_count
+    on sgd0815.\"scalar\" using btree(\"count\");
+create index attr_1_9_scalar_timestamp
+    on sgd0815.\"scalar\" using btree(\"timestamp\");
 
 ";
 
@@ -290,6 +290,10 @@ pub trait FromColumnValue: Sized {
 
     fn from_i32(i: i32) -> Self;
 
+    fn from_i64(i: i64) -> Self;
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self;
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self;
 
     fn from_big_int(i: serde_json::Number) -> Result<Self, StoreError>;
@@ -332,6 +336,13 @@ pub trait FromColumnValue: Sized {
                     })
             }
             (j::Number(number), ColumnType::BigInt) => Self::from_big_int(number),
+            (j::Number(number), ColumnType::Int8) => match number.as_i64() {
+                Some(i) => Ok(Self::from_i64(i)),
+                None => Err(StoreError::Unknown(anyhow!(
+                    "failed to convert {} to Int8",
+                    number
+                ))),
+            },
             (j::Number(number), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert number {} to {:?}",
                 number,
@@ -341,7 +352,15 @@ pub trait FromColumnValue: Sized {
                 Ok(Self::from_string(s))
             }
             (j::String(s), ColumnType::Bytes) => Self::from_bytes(s.trim_start_matches("\\x")),
+            // Unlike attributes of type `Bytes`, ids of type `Bytes` have
+            // never had a `0x` prefix, and clients rely on that; see
+            // `docs/implementation/schema-generation.md`
             (j::String(s), ColumnType::BytesId) => Ok(Self::from_string(bytes_as_str(&s))),
+            (j::String(s), ColumnType::Timestamp) => scalar::Timestamp::from_str(&s)
+                .map(Self::from_timestamp)
+                .map_err(|e| {
+                    StoreError::Unknown(anyhow!("failed to convert {} to Timestamp: {}", s, e))
+                }),
             (j::String(s), column_type) => Err(StoreError::Unknown(anyhow!(
                 "can not convert string {} to {:?}",
                 s,
@@ -381,6 +400,14 @@ impl FromColumnValue for q::Value {
         q::Value::Int(i.into())
     }
 
+    fn from_i64(i: i64) -> Self {
+        q::Value::String(i.to_string())
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        q::Value::String(ts.to_string())
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         q::Value::String(d.to_string())
     }
@@ -419,6 +446,14 @@ impl FromColumnValue for graph::prelude::Value {
         graph::prelude::Value::Int(i)
     }
 
+    fn from_i64(i: i64) -> Self {
+        graph::prelude::Value::Int8(i)
+    }
+
+    fn from_timestamp(ts: scalar::Timestamp) -> Self {
+        graph::prelude::Value::Timestamp(ts)
+    }
+
     fn from_big_decimal(d: scalar::BigDecimal) -> Self {
         graph::prelude::Value::BigDecimal(d)
     }
@@ -531,6 +566,12 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                 ),
             },
             Value::Int(i) => out.push_bind_param::<Integer, _>(i),
+            Value::Int8(i) => out.push_bind_param::<BigInt, _>(i),
+            Value::Timestamp(ts) => {
+                out.push_bind_param::<Text, _>(&ts.to_string())?;
+                out.push_sql("::timestamptz");
+                Ok(())
+            }
             Value::BigDecimal(d) => {
                 out.push_bind_param::<Text, _>(&d.to_string())?;
                 out.push_sql("::numeric");
@@ -549,6 +590,13 @@ impl<'a> QueryFragment<Pg> for QueryValue<'a> {
                     ColumnType::Boolean => out.push_bind_param::<Array<Bool>, _>(&sql_values),
                     ColumnType::Bytes => out.push_bind_param::<Array<Binary>, _>(&sql_values),
                     ColumnType::Int => out.push_bind_param::<Array<Integer>, _>(&sql_values),
+                    ColumnType::Int8 => out.push_bind_param::<Array<BigInt>, _>(&sql_values),
+                    ColumnType::Timestamp => {
+                        let text_values: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+                        out.push_bind_param::<Array<Text>, _>(&text_values)?;
+                        out.push_sql("::timestamptz[]");
+                        Ok(())
+                    }
                     ColumnType::String => out.push_bind_param::<Array<Text>, _>(&sql_values),
                     ColumnType::Enum(enum_type) => {
                         out.push_bind_param::<Array<Text>, _>(&sql_values)?;
@@ -887,6 +935,8 @@ impl<'a> QueryFilter<'a> {
             Value::Null
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::Bool(_)
             | Value::BigInt(_) => {
                 let filter = match negated {
@@ -928,6 +978,8 @@ impl<'a> QueryFilter<'a> {
                 | Value::Bytes(_)
                 | Value::BigDecimal(_)
                 | Value::Int(_)
+                | Value::Int8(_)
+                | Value::Timestamp(_)
                 | Value::List(_) => {
                     out.push_sql(op.as_str());
                     QueryValue(value, &column.column_type).walk_ast(out)?;
@@ -960,9 +1012,12 @@ impl<'a> QueryFilter<'a> {
             out.push_identifier(column.name.as_str())?;
             out.push_sql(op.as_str());
             match value {
-                Value::BigInt(_) | Value::BigDecimal(_) | Value::Int(_) | Value::String(_) => {
-                    QueryValue(value, &column.column_type).walk_ast(out)?
-                }
+                Value::BigInt(_)
+                | Value::BigDecimal(_)
+                | Value::Int(_)
+                | Value::Int8(_)
+                | Value::Timestamp(_)
+                | Value::String(_) => QueryValue(value, &column.column_type).walk_ast(out)?,
                 Value::Bool(_) | Value::Bytes(_) | Value::List(_) | Value::Null => {
                     return Err(UnsupportedFilter {
                         filter: op.as_str().to_owned(),
@@ -1089,6 +1144,8 @@ impl<'a> QueryFilter<'a> {
             | Value::Bytes(_)
             | Value::BigDecimal(_)
             | Value::Int(_)
+            | Value::Int8(_)
+            | Value::Timestamp(_)
             | Value::List(_)
             | Value::Null => {
                 return Err(UnsupportedFilter {
@@ -1903,7 +1960,19 @@ impl<'a> FilterCollection<'a> {
         layout: &'a Layout,
         collection: EntityCollection,
         filter: Option<&'a EntityFilter>,
+        order: &EntityOrder,
     ) -> Result<Self, QueryExecutionError> {
+        // The attribute we order by has to be selected since the queries
+        // that wrap the selection of columns order by it
+        let with_order = |mut column_names: AttributeNames| {
+            match order {
+                EntityOrder::Ascending(attr, _) | EntityOrder::Descending(attr, _) => {
+                    column_names.insert(attr)
+                }
+                EntityOrder::Default | EntityOrder::Unordered => {}
+            }
+            column_names
+        };
         match collection {
             EntityCollection::All(entities) => {
                 // This is a little ugly since we need to propagate errors
@@ -1920,7 +1989,7 @@ impl<'a> FilterCollection<'a> {
                                 filter
                                     .map(|filter| QueryFilter::new(filter, table))
                                     .transpose()
-                                    .map(|filter| (table, filter, column_names.clone()))
+                                    .map(|filter| (table, filter, with_order(column_names.clone())))
                             })
                     })
                     .collect::<Result<Vec<_>, _>>()?;
@@ -1929,7 +1998,10 @@ impl<'a> FilterCollection<'a> {
             EntityCollection::Window(windows) => {
                 let windows = windows
                     .into_iter()
-                    .map(|window| FilterWindow::new(layout, window, filter))
+                    .map(|mut window| {
+                        window.column_names = with_order(window.column_names);
+                        FilterWindow::new(layout, window, filter)
+                    })
                     .collect::<Result<Vec<_>, _>>()?;
                 let collection = if windows.len() == 1 {
                     let mut windows = windows;
@@ -2299,7 +2371,8 @@ impl<'a> FilterQuery<'a> {
     ///
     /// Generate a query
     ///   select '..' as entity, to_jsonb(e.*) as data
-    ///     from (select c.*, p.id as g$parent_id from {window.children(...)}) c
+    ///     from (select {column names}, p.id as g$parent_id
+    ///             from {window.children(...)}) c
     ///     order by c.g$parent_id, {sort_key}
     ///     limit {first} offset {skip}
     fn query_window_one_entity(
@@ -2308,8 +2381,9 @@ impl<'a> FilterQuery<'a> {
         mut out: AstPass<Pg>,
     ) -> QueryResult<()> {
         Self::select_entity_and_data(&window.table, &mut out);
-        out.push_sql(" from (\n");
-        out.push_sql("select c.*, p.id::text as g$parent_id");
+        out.push_sql(" from (\nselect ");
+        write_column_names(&window.column_names, &window.table, &mut out)?;
+        out.push_sql(", p.id::text as g$parent_id");
         window.children(
             ParentLimit::Ranked(&self.sort_key, &self.range),
             self.block,
@@ -2840,30 +2914,35 @@ pub struct CopyVid {
     pub vid: i64,
 }
 
+/// Write the columns of `table`, which must be aliased to `c`, that are
+/// needed for `column_names`. Besides the selected attributes, that always
+/// includes the `id` and the `vid` and `block_range` of the entity version
+/// since the queries that wrap this select window and order by them
 fn write_column_names(
     column_names: &AttributeNames,
     table: &Table,
     out: &mut AstPass<Pg>,
 ) -> QueryResult<()> {
     match column_names {
-        AttributeNames::All => out.push_sql(" * "),
+        AttributeNames::All => out.push_sql(" c.* "),
         AttributeNames::Select(column_names) => {
-            let mut iterator = column_names
-                .union(&BASE_SQL_COLUMNS)
-                .into_iter()
-                .map(|column_name| {
-                    &table
-                        .column_for_field(&column_name)
-                        .expect("failed to find column for field")
-                        .name
-                })
-                .peekable();
-            while let Some(column_name) = iterator.next() {
+            let column_names =
+                column_names
+                    .union(&BASE_SQL_COLUMNS)
+                    .into_iter()
+                    .map(|column_name| {
+                        &table
+                            .column_for_field(&column_name)
+                            .expect("failed to find column for field")
+                            .name
+                    });
+            for column_name in column_names {
+                out.push_sql("c.");
                 out.push_identifier(&column_name.as_str())?;
-                if iterator.peek().is_some() {
-                    out.push_sql(", ");
-                }
+                out.push_sql(", ");
             }
+            out.push_sql("c.vid, c.");
+            out.push_sql(BLOCK_RANGE_COLUMN);
         }
     }
     Ok(())
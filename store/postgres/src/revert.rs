@@ -0,0 +1,114 @@
+//! Support for reverting many blocks at once. Reverting tens of thousands
+//! of blocks in one transaction runs into statement timeouts and produces
+//! huge amounts of WAL. Such reverts are therefore done in chunks of
+//! `GRAPH_STORE_REVERT_CHUNK_SIZE` blocks, going backwards from the head,
+//! with each chunk in its own transaction. After each chunk, the entity
+//! data is exactly what it was at the end of the block where the chunk
+//! stopped, and that block is recorded in the deployment so that an
+//! interrupted revert can pick up where it left off
+use std::{env, sync::Arc};
+
+use graph::data::subgraph::status::EntityRevert;
+use graph::prelude::{lazy_static, BlockNumber, CounterVec, MetricsRegistry};
+
+use crate::primary::Site;
+
+lazy_static! {
+    /// The most blocks that one transaction of a revert covers
+    pub(crate) static ref REVERT_CHUNK_SIZE: BlockNumber = env::var("GRAPH_STORE_REVERT_CHUNK_SIZE")
+        .ok()
+        .map(|s| {
+            s.parse::<BlockNumber>().unwrap_or_else(|_| {
+                panic!("GRAPH_STORE_REVERT_CHUNK_SIZE must be a number, but is `{}`", s)
+            })
+        })
+        .map(|size| size.max(1))
+        .unwrap_or(1_000);
+}
+
+/// How a revert is split into chunks
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RevertChunks {
+    /// The most blocks that one chunk covers
+    pub size: BlockNumber,
+    /// Stop after this many chunks and leave the revert pending. Only
+    /// used by tests to simulate an interrupted revert
+    pub limit: Option<usize>,
+}
+
+impl Default for RevertChunks {
+    fn default() -> Self {
+        RevertChunks {
+            size: *REVERT_CHUNK_SIZE,
+            limit: None,
+        }
+    }
+}
+
+/// Metrics that show how much work reverts did for each deployment
+pub struct RevertMetrics {
+    chunks: Box<CounterVec>,
+    rows: Box<CounterVec>,
+}
+
+impl RevertMetrics {
+    pub fn new(registry: Arc<impl MetricsRegistry>) -> Self {
+        let chunks = registry
+            .new_counter_vec(
+                "deployment_revert_chunks",
+                "Counts the transactions in which reverts changed entity data",
+                vec![String::from("deployment")],
+            )
+            .expect("failed to create `deployment_revert_chunks` counter");
+        let rows = registry
+            .new_counter_vec(
+                "deployment_revert_rows",
+                "Counts the entity versions that reverts removed or restored",
+                vec![String::from("deployment")],
+            )
+            .expect("failed to create `deployment_revert_rows` counter");
+        RevertMetrics { chunks, rows }
+    }
+
+    pub(crate) fn chunk_done(&self, site: &Site, entities: &[EntityRevert]) {
+        let rows: usize = entities
+            .iter()
+            .map(|entity| entity.restored + entity.removed)
+            .sum();
+        self.chunks
+            .with_label_values(&[site.deployment.as_str()])
+            .inc();
+        self.rows
+            .with_label_values(&[site.deployment.as_str()])
+            .inc_by(rows as f64);
+    }
+}
+
+/// The blocks at which the chunks of a revert from `reached` to `target`
+/// stop, in the order in which they need to be processed
+pub(crate) fn chunk_ends(
+    reached: BlockNumber,
+    target: BlockNumber,
+    size: BlockNumber,
+) -> Vec<BlockNumber> {
+    let mut ends = Vec::new();
+    let mut reached = reached;
+    while reached > target {
+        reached = (reached - size).max(target);
+        ends.push(reached);
+    }
+    ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::chunk_ends;
+
+    #[test]
+    fn chunks() {
+        assert_eq!(vec![90, 80, 70], chunk_ends(100, 70, 10));
+        assert_eq!(vec![90, 80, 75], chunk_ends(100, 75, 10));
+        assert_eq!(vec![75], chunk_ends(80, 75, 10));
+        assert!(chunk_ends(75, 75, 10).is_empty());
+    }
+}
@@ -3,11 +3,12 @@ use std::sync::Arc;
 
 use graph::{
     components::{
+        server::admin::AdminAuditEntry,
         server::index_node::VersionInfo,
         store::{BlockStore as BlockStoreTrait, QueryStoreManager, StatusStore},
     },
     constraint_violation,
-    data::subgraph::status,
+    data::subgraph::{status, DeploymentProfile},
     prelude::{
         tokio, web3::types::Address, BlockPtr, CheapClone, DeploymentHash, QueryExecutionError,
         StoreError,
@@ -45,6 +46,21 @@ impl Store {
     pub fn block_store(&self) -> Arc<BlockStore> {
         self.block_store.cheap_clone()
     }
+
+    /// Fill in the current chain head for every chain in `infos`
+    fn fill_chain_heads<'a>(
+        &self,
+        infos: impl Iterator<Item = &'a mut status::Info>,
+    ) -> Result<(), StoreError> {
+        let ptrs = self.block_store.chain_head_pointers()?;
+
+        for info in infos {
+            for chain in &mut info.chains {
+                chain.chain_head_block = ptrs.get(&chain.network).map(|ptr| ptr.to_owned().into());
+            }
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -83,16 +99,65 @@ impl QueryStoreManager for Store {
 impl StatusStore for Store {
     fn status(&self, filter: status::Filter) -> Result<Vec<status::Info>, StoreError> {
         let mut infos = self.subgraph_store.status(filter)?;
-        let ptrs = self.block_store.chain_head_pointers()?;
+        self.fill_chain_heads(infos.iter_mut())?;
+        Ok(infos)
+    }
 
-        for info in &mut infos {
-            for chain in &mut info.chains {
-                chain.chain_head_block = ptrs.get(&chain.network).map(|ptr| ptr.to_owned().into());
-            }
-        }
+    fn cluster_status(
+        &self,
+        filter: status::ClusterFilter,
+    ) -> Result<Vec<status::ClusterInfo>, StoreError> {
+        let mut infos = self.subgraph_store.cluster_status(filter)?;
+        self.fill_chain_heads(infos.iter_mut().map(|cluster| &mut cluster.info))?;
         Ok(infos)
     }
 
+    fn indexing_statuses(
+        &self,
+        query: status::StatusQuery,
+    ) -> Result<Vec<status::Info>, StoreError> {
+        let heads = self
+            .block_store
+            .chain_head_pointers()?
+            .into_iter()
+            .map(|(network, ptr)| (network, ptr.number))
+            .collect();
+        let mut infos = self.subgraph_store.indexing_statuses(query, &heads)?;
+        self.fill_chain_heads(infos.iter_mut())?;
+        Ok(infos)
+    }
+
+    fn admin_audit_log(
+        &self,
+        first: usize,
+        after: Option<i64>,
+        method: Option<String>,
+    ) -> Result<Vec<AdminAuditEntry>, StoreError> {
+        self.subgraph_store.admin_audit_log(first, after, method)
+    }
+
+    fn version_switches(
+        &self,
+        subgraph_name: &str,
+        first: usize,
+    ) -> Result<Vec<status::VersionSwitch>, StoreError> {
+        self.subgraph_store.version_switches(subgraph_name, first)
+    }
+
+    fn deployment_profile(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<Option<DeploymentProfile>, StoreError> {
+        self.subgraph_store.deployment_profile(deployment)
+    }
+
+    fn composite_indexes(
+        &self,
+        deployment: &DeploymentHash,
+    ) -> Result<Vec<status::CompositeIndex>, StoreError> {
+        self.subgraph_store.composite_indexes(deployment)
+    }
+
     fn version_info(&self, version_id: &str) -> Result<VersionInfo, StoreError> {
         let mut info = self.subgraph_store.version_info(version_id)?;
 
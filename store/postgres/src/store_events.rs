@@ -152,24 +152,27 @@ impl SubscriptionManager {
         let subscriptions = self.subscriptions.clone();
 
         // Clean up stale subscriptions every 5s
-        graph::spawn(async move {
-            let mut interval = tokio::time::interval(Duration::from_secs(5));
-            loop {
-                interval.tick().await;
-                let mut subscriptions = subscriptions.write().unwrap();
-
-                // Obtain IDs of subscriptions whose receiving end has gone
-                let stale_ids = subscriptions
-                    .iter_mut()
-                    .filter_map(|(id, sender)| match sender.poll_ready() {
-                        Err(_) => Some(id.clone()),
-                        _ => None,
-                    })
-                    .collect::<Vec<_>>();
+        graph::spawn_essential("clean-up-subscriptions", move || {
+            let subscriptions = subscriptions.clone();
+            async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(5));
+                loop {
+                    interval.tick().await;
+                    let mut subscriptions = subscriptions.write().unwrap();
+
+                    // Obtain IDs of subscriptions whose receiving end has gone
+                    let stale_ids = subscriptions
+                        .iter_mut()
+                        .filter_map(|(id, sender)| match sender.poll_ready() {
+                            Err(_) => Some(id.clone()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
 
-                // Remove all stale subscriptions
-                for id in stale_ids {
-                    subscriptions.remove(&id);
+                    // Remove all stale subscriptions
+                    for id in stale_ids {
+                        subscriptions.remove(&id);
+                    }
                 }
             }
         });
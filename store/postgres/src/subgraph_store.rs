@@ -11,30 +11,40 @@ use std::{iter::FromIterator, time::Duration};
 use graph::{
     cheap_clone::CheapClone,
     components::{
+        server::admin::{AdminAuditEntry, AdminCall},
         server::index_node::VersionInfo,
-        store::{self, DeploymentLocator, EntityType, WritableStore as WritableStoreTrait},
+        store::{
+            self, AdminAuditStore, DeploymentLocator, EntityType, VersionComparison,
+            WritableStore as WritableStoreTrait,
+        },
     },
     constraint_violation,
     data::query::QueryTarget,
     data::subgraph::schema::SubgraphError,
-    data::subgraph::status,
+    data::subgraph::{status, DeploymentProfile, Labels, ScanTotals},
     prelude::StoreEvent,
     prelude::SubgraphDeploymentEntity,
     prelude::{
-        anyhow, futures03::future::join_all, lazy_static, o, web3::types::Address, ApiSchema,
-        BlockPtr, DeploymentHash, DynTryFuture, Entity, EntityKey, EntityModification, Error,
-        Logger, NodeId, QueryExecutionError, Schema, StopwatchMetrics, StoreError, SubgraphName,
-        SubgraphStore as SubgraphStoreTrait, SubgraphVersionSwitchingMode,
+        anyhow, error, futures03::future::join_all, info, lazy_static, o, warn,
+        web3::types::Address, ApiSchema, BlockNumber, BlockPtr, DataSourceKey, DeploymentHash,
+        DynTryFuture, Entity, EntityKey, EntityModification, Error, Logger,
+        MetadataMaintenanceReport, MetricsRegistry, NodeId, QueryExecutionError, Schema,
+        StopwatchMetrics, StoreError, SubgraphName, SubgraphStore as SubgraphStoreTrait,
+        SubgraphVersionSwitchingMode,
     },
+    util::clock::ClockWatch,
     util::timed_cache::TimedCache,
 };
-use store::StoredDynamicDataSource;
+use store::{OnceHandlerRun, StoredDynamicDataSource};
 
 use crate::{
+    archive::ArchiveMetrics,
     connection_pool::ConnectionPool,
+    copy::CopyMetrics,
     primary,
     primary::{DeploymentId, Site},
     relational::Layout,
+    revert::{RevertChunks, RevertMetrics},
     NotificationSender,
 };
 use crate::{
@@ -56,6 +66,48 @@ lazy_static! {
     pub static ref SEND_SUBSCRIPTION_NOTIFICATIONS: bool = {
       std::env::var("GRAPH_DISABLE_SUBSCRIPTION_NOTIFICATIONS").ok().is_none()
     };
+    /// Nodes that have not recorded a heartbeat for this many seconds are
+    /// reported as dead in the cluster status
+    static ref NODE_DEAD_AFTER: Duration = Duration::from_secs(
+        std::env::var("GRAPH_NODE_DEAD_AFTER")
+            .ok()
+            .map(|s| {
+                s.parse::<u64>().unwrap_or_else(|_| {
+                    panic!("GRAPH_NODE_DEAD_AFTER must be a number of seconds, but is `{}`", s)
+                })
+            })
+            .unwrap_or(120),
+    );
+    /// How many superseded versions of each subgraph metadata maintenance
+    /// keeps around
+    static ref METADATA_KEEP_VERSIONS: usize = std::env::var("GRAPH_METADATA_KEEP_VERSIONS")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_METADATA_KEEP_VERSIONS must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(10);
+    /// How many entries of the admin audit log metadata maintenance keeps
+    /// around
+    static ref ADMIN_AUDIT_LOG_KEEP: usize = std::env::var("GRAPH_ADMIN_AUDIT_LOG_KEEP")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_ADMIN_AUDIT_LOG_KEEP must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(10_000);
+    /// How many version switches of each subgraph metadata maintenance
+    /// keeps around
+    static ref VERSION_SWITCH_KEEP: usize = std::env::var("GRAPH_VERSION_SWITCH_KEEP")
+        .ok()
+        .map(|s| {
+            s.parse::<usize>().unwrap_or_else(|_| {
+                panic!("GRAPH_VERSION_SWITCH_KEEP must be a number, but is `{}`", s)
+            })
+        })
+        .unwrap_or(100);
 }
 
 /// How long to cache information about a deployment site
@@ -110,13 +162,18 @@ impl ToSql<Text, Pg> for Shard {
     }
 }
 
-/// Decide where a new deployment should be placed based on the subgraph name
-/// and the network it is indexing. If the deployment can be placed, returns
-/// the name of the database shard for the deployment and the names of the
-/// indexers that should index it. The deployment should then be assigned to
-/// one of the returned indexers.
+/// Decide where a new deployment should be placed based on the subgraph name,
+/// the network it is indexing and its labels. If the deployment can be
+/// placed, returns the name of the database shard for the deployment and the
+/// names of the indexers that should index it. The deployment should then be
+/// assigned to one of the returned indexers.
 pub trait DeploymentPlacer {
-    fn place(&self, name: &str, network: &str) -> Result<Option<(Shard, Vec<NodeId>)>, String>;
+    fn place(
+        &self,
+        name: &str,
+        network: &str,
+        labels: &Labels,
+    ) -> Result<Option<(Shard, Vec<NodeId>)>, String>;
 }
 
 /// Tools for managing unused deployments
@@ -200,14 +257,22 @@ impl SubgraphStore {
     /// pool. One of the shards must be named `primary`
     ///
     /// The `placer` determines where `create_subgraph_deployment` puts a new deployment
+    ///
+    /// `node` is the id of the node using this store; writes to a
+    /// deployment are made on behalf of that node, and are refused if
+    /// another node currently holds the writer lease for the deployment
     pub fn new(
         logger: &Logger,
+        node: NodeId,
         stores: Vec<(Shard, ConnectionPool, Vec<ConnectionPool>, Vec<usize>)>,
         placer: Arc<dyn DeploymentPlacer + Send + Sync + 'static>,
         sender: Arc<NotificationSender>,
+        registry: Arc<impl MetricsRegistry>,
     ) -> Self {
         Self {
-            inner: Arc::new(SubgraphStoreInner::new(logger, stores, placer, sender)),
+            inner: Arc::new(SubgraphStoreInner::new(
+                logger, node, stores, placer, sender, registry,
+            )),
         }
     }
 
@@ -223,6 +288,17 @@ impl SubgraphStore {
     pub fn notification_sender(&self) -> Arc<NotificationSender> {
         self.sender.clone()
     }
+
+    // Only needed for tests
+    #[cfg(debug_assertions)]
+    pub fn writable_for_node(
+        &self,
+        deployment: &DeploymentLocator,
+        node: NodeId,
+    ) -> Result<Arc<dyn store::WritableStore>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        Ok(Arc::new(WritableStore::new(self.clone(), site, node)?))
+    }
 }
 
 impl std::ops::Deref for SubgraphStore {
@@ -235,6 +311,8 @@ impl std::ops::Deref for SubgraphStore {
 
 pub struct SubgraphStoreInner {
     logger: Logger,
+    /// The node on whose behalf we write to deployments
+    node: NodeId,
     primary: ConnectionPool,
     stores: HashMap<Shard, Arc<DeploymentStore>>,
     /// Cache for the mapping from deployment id to shard/namespace/id. Only
@@ -246,6 +324,8 @@ pub struct SubgraphStoreInner {
     sites: TimedCache<DeploymentHash, Site>,
     placer: Arc<dyn DeploymentPlacer + Send + Sync + 'static>,
     sender: Arc<NotificationSender>,
+    copy_metrics: Arc<CopyMetrics>,
+    revert_metrics: Arc<RevertMetrics>,
 }
 
 impl SubgraphStoreInner {
@@ -265,9 +345,11 @@ impl SubgraphStoreInner {
     /// The `placer` determines where `create_subgraph_deployment` puts a new deployment
     pub fn new(
         logger: &Logger,
+        node: NodeId,
         stores: Vec<(Shard, ConnectionPool, Vec<ConnectionPool>, Vec<usize>)>,
         placer: Arc<dyn DeploymentPlacer + Send + Sync + 'static>,
         sender: Arc<NotificationSender>,
+        registry: Arc<impl MetricsRegistry>,
     ) -> Self {
         let primary = stores
             .iter()
@@ -293,11 +375,14 @@ impl SubgraphStoreInner {
         let logger = logger.new(o!("shard" => PRIMARY_SHARD.to_string()));
         SubgraphStoreInner {
             logger,
+            node,
             primary,
             stores,
             sites,
             placer,
             sender,
+            copy_metrics: Arc::new(CopyMetrics::new(registry.clone())),
+            revert_metrics: Arc::new(RevertMetrics::new(registry)),
         }
     }
 
@@ -380,6 +465,7 @@ impl SubgraphStoreInner {
         &self,
         name: &SubgraphName,
         network_name: &str,
+        labels: &Labels,
         default_node: NodeId,
     ) -> Result<(Shard, NodeId), StoreError> {
         // We try to place the deployment according to the configured rules.
@@ -389,7 +475,7 @@ impl SubgraphStoreInner {
         // uses the legacy command-line options as configuration
         let placement = self
             .placer
-            .place(name.as_str(), network_name)
+            .place(name.as_str(), network_name, labels)
             .map_err(|msg| {
                 constraint_violation!("illegal indexer name in deployment rule: {}", msg)
             })?;
@@ -447,7 +533,8 @@ impl SubgraphStoreInner {
             //       In that case, we need to use the shard and node
             //       assignment that we used last time to avoid creating
             //       the same deployment in another shard
-            let (shard, node_id) = self.place(&name, &network_name, node_id)?;
+            let labels = self.primary_conn()?.labels(&schema.id)?;
+            let (shard, node_id) = self.place(&name, &network_name, &labels, node_id)?;
             let conn = self.primary_conn()?;
             let site = conn.allocate_site(shard.clone(), &schema.id, network_name)?;
             let node_id = conn.assigned_node(&site)?.unwrap_or(node_id);
@@ -789,8 +876,15 @@ impl SubgraphStoreInner {
                 let ids = ids.into_iter().map(|id| id.into()).collect();
                 self.primary_conn()?.find_sites_by_id(ids)?
             }
+            status::Filter::Node(node) => self.primary_conn()?.assignments(&node)?,
         };
 
+        self.site_statuses(sites)
+    }
+
+    /// Look up the status of all `sites` and fill in the nodes they are
+    /// assigned to
+    fn site_statuses(&self, sites: Vec<Site>) -> Result<Vec<status::Info>, StoreError> {
         let by_shard: HashMap<Shard, Vec<Arc<Site>>> = self.deployments_by_shard(sites)?;
 
         // Go shard-by-shard to look up deployment statuses
@@ -806,6 +900,242 @@ impl SubgraphStoreInner {
         Ok(infos)
     }
 
+    /// The status of the active deployments in the whole cluster, ordered
+    /// by deployment id, together with when the node that each deployment
+    /// is assigned to last recorded a heartbeat. A deployment's node is
+    /// considered dead if it has not recorded a heartbeat in
+    /// `GRAPH_NODE_DEAD_AFTER` seconds
+    pub(crate) fn cluster_status(
+        &self,
+        filter: status::ClusterFilter,
+    ) -> Result<Vec<status::ClusterInfo>, StoreError> {
+        let sites = match &filter.node {
+            Some(node) => self
+                .primary_conn()?
+                .assignments(node)?
+                .into_iter()
+                .filter(|site| site.active)
+                .collect(),
+            None => self.primary_conn()?.find_sites(vec![], true)?,
+        };
+
+        let mut infos = self.site_statuses(sites)?;
+        if let Some(health) = filter.health {
+            infos.retain(|info| info.health == health);
+        }
+        infos.sort_by_key(|info| info.id.0);
+
+        let heartbeats = self.primary_conn()?.heartbeats(*NODE_DEAD_AFTER)?;
+        let infos = infos
+            .into_iter()
+            .skip(filter.skip)
+            .take(filter.first)
+            .map(|info| {
+                let heartbeat = info.node.as_ref().and_then(|node| heartbeats.get(node));
+                status::ClusterInfo {
+                    last_heartbeat: heartbeat.map(|(ts, _)| *ts),
+                    node_dead: info.node.is_some()
+                        && heartbeat.map(|(_, dead)| *dead).unwrap_or(true),
+                    info,
+                }
+            })
+            .collect();
+        Ok(infos)
+    }
+
+    /// The status of the deployments that match `query`. Filtering,
+    /// sorting and paging all happen in one query in the primary; only the
+    /// details of the deployments on the page are then looked up in their
+    /// shards. The lag of deployments is measured against `heads`
+    pub(crate) fn indexing_statuses(
+        &self,
+        query: status::StatusQuery,
+        heads: &HashMap<String, BlockNumber>,
+    ) -> Result<Vec<status::Info>, StoreError> {
+        let shards: Vec<_> = self.stores.keys().cloned().collect();
+        let page = self
+            .primary_conn()?
+            .indexing_status_page(&query, &shards, heads)?;
+        if page.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids = page.iter().map(|(id, _)| (*id).into()).collect();
+        let sites = self.primary_conn()?.find_sites_by_id(ids)?;
+        let mut infos: HashMap<_, _> = self
+            .site_statuses(sites)?
+            .into_iter()
+            .map(|info| (info.id, info))
+            .collect();
+
+        // Deployments that were removed since we got the page are skipped
+        Ok(page
+            .into_iter()
+            .filter_map(|(id, key)| {
+                infos.remove(&id).map(|mut info| {
+                    info.cursor = Some(status::StatusCursor { id, key });
+                    info
+                })
+            })
+            .collect())
+    }
+
+    /// Record that `node` is alive
+    pub fn record_heartbeat(&self, node: &NodeId) -> Result<(), StoreError> {
+        self.primary_conn()?.record_heartbeat(node)
+    }
+
+    /// Assign `site` to `node`, and clear its standby if `clear_standby`
+    /// is set. The writer lease moves to `node` together with the
+    /// assignment so that the old node can not write another block once
+    /// `node` has been told about the assignment and picks up indexing
+    /// from the deployment's head; that way, no block is skipped or
+    /// processed twice.
+    ///
+    /// For deployments in the primary shard, all of that happens in one
+    /// transaction. For other shards, the lease is moved in the shard
+    /// while the transaction in the primary is open, and the assignment
+    /// only becomes visible once the lease has moved. If the primary
+    /// transaction fails after that, neither node can write to the
+    /// deployment until the handover is tried again
+    fn hand_over(&self, site: &Site, node: &NodeId, clear_standby: bool) -> Result<(), StoreError> {
+        let store = self.for_site(site)?;
+        let pconn = self.primary_conn()?;
+        pconn.transaction(|| -> Result<_, StoreError> {
+            if site.shard == *PRIMARY_SHARD {
+                store.transfer_writer_lease_with_conn(pconn.as_pg(), site, Some(node))?;
+            } else {
+                // The shard has its own pool, and taking a connection
+                // from it while we hold one from the primary can not
+                // deadlock
+                store.transfer_writer_lease(site, Some(node))?;
+            }
+            let changes = pconn.reassign_subgraph(site, node)?;
+            if clear_standby {
+                pconn.set_standby_node(site, None)?;
+            }
+            pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
+        })
+    }
+
+    /// Make `node` the warm standby for `deployment`, or remove the standby
+    /// if `node` is `None`. The standby takes over indexing the deployment
+    /// when it is promoted, either explicitly or because the node the
+    /// deployment is assigned to stopped recording heartbeats
+    pub fn set_standby_node(
+        &self,
+        deployment: &DeploymentLocator,
+        node: Option<&NodeId>,
+    ) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.primary_conn()?.set_standby_node(site.as_ref(), node)
+    }
+
+    pub fn standby_node(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<NodeId>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.primary_conn()?.standby_node(site.as_ref())
+    }
+
+    /// Assign `deployment` to its standby node and return that node
+    pub fn promote_standby(&self, deployment: &DeploymentLocator) -> Result<NodeId, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let node = self
+            .primary_conn()?
+            .standby_node(site.as_ref())?
+            .ok_or_else(|| {
+                StoreError::Unknown(anyhow!(
+                    "deployment {} does not have a standby node",
+                    site.deployment
+                ))
+            })?;
+        self.hand_over(site.as_ref(), &node, true)?;
+        Ok(node)
+    }
+
+    /// Promote `node` for all deployments for which it is the standby and
+    /// whose assigned node is dead because it has not recorded a heartbeat
+    /// in a while. Deployments assigned to nodes that never recorded a
+    /// heartbeat are left alone.
+    ///
+    /// Whether a node is dead is decided with the database clock, which
+    /// `clock` watches. When that clock jumps, heartbeats can look much
+    /// older than they are; we then leave all deployments where they are
+    /// until heartbeats have been recorded with the new time for long
+    /// enough, in effect extending the leases of the assigned nodes rather
+    /// than taking their deployments away
+    pub fn promote_standbys(
+        &self,
+        logger: &Logger,
+        node: &NodeId,
+        clock: &ClockWatch,
+    ) -> Result<(), StoreError> {
+        let (standbys, heartbeats) = {
+            let pconn = self.primary_conn()?;
+            clock.observe(logger, pconn.database_time()?);
+            if !clock.settled(*NODE_DEAD_AFTER) {
+                warn!(logger, "Not taking over deployments from dead nodes since the database clock jumped recently");
+                return Ok(());
+            }
+            (pconn.standbys(node)?, pconn.heartbeats(*NODE_DEAD_AFTER)?)
+        };
+        for (site, assigned) in standbys {
+            let dead = heartbeats
+                .get(&assigned)
+                .map(|(_, dead)| *dead)
+                .unwrap_or(false);
+            if !dead || &assigned == node.as_str() {
+                continue;
+            }
+            info!(logger, "Taking over deployment from dead node";
+                  "sgd" => site.id.to_string(),
+                  "subgraph_id" => site.deployment.as_str(),
+                  "dead_node" => &assigned);
+            if let Err(e) = self.hand_over(&site, node, true) {
+                error!(logger, "Failed to take over deployment from dead node";
+                       "sgd" => site.id.to_string(),
+                       "subgraph_id" => site.deployment.as_str(),
+                       "error" => e.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn admin_audit_log(
+        &self,
+        first: usize,
+        after: Option<i64>,
+        method: Option<String>,
+    ) -> Result<Vec<AdminAuditEntry>, StoreError> {
+        self.primary_conn()?.admin_audit_log(first, after, method)
+    }
+
+    pub(crate) fn version_switches(
+        &self,
+        subgraph_name: &str,
+        first: usize,
+    ) -> Result<Vec<status::VersionSwitch>, StoreError> {
+        self.primary_conn()?.version_switches(subgraph_name, first)
+    }
+
+    pub(crate) fn deployment_profile(
+        &self,
+        id: &DeploymentHash,
+    ) -> Result<Option<DeploymentProfile>, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.deployment_profile(site.as_ref())
+    }
+
+    pub(crate) fn composite_indexes(
+        &self,
+        id: &DeploymentHash,
+    ) -> Result<Vec<status::CompositeIndex>, StoreError> {
+        let (store, site) = self.store(id)?;
+        store.composite_indexes(site)
+    }
+
     pub(crate) fn version_info(&self, version: &str) -> Result<VersionInfo, StoreError> {
         if let Some((deployment_id, created_at)) = self.primary_conn()?.version_info(version)? {
             let id = DeploymentHash::new(deployment_id.clone())
@@ -862,10 +1192,71 @@ impl SubgraphStoreInner {
         join_all(self.stores.values().map(|store| store.vacuum())).await
     }
 
-    pub fn rewind(&self, id: DeploymentHash, block_ptr_to: BlockPtr) -> Result<(), StoreError> {
+    /// Archive old entity versions for all deployments that have archiving
+    /// turned on. Failures are logged and do not stop archiving for other
+    /// deployments
+    pub async fn archive(&self, logger: &Logger, metrics: Arc<ArchiveMetrics>) {
+        for store in self.stores.values() {
+            let horizons = match store.archive_horizons().await {
+                Ok(horizons) => horizons,
+                Err(e) => {
+                    error!(logger, "Failed to find deployments to archive"; "error" => e.to_string());
+                    continue;
+                }
+            };
+            for (id, horizon) in horizons {
+                let res = match self.find_site(id) {
+                    Ok(site) => {
+                        store
+                            .archive(logger, site, horizon, metrics.cheap_clone())
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = res {
+                    error!(logger, "Archiving entity versions failed";
+                           "sgd" => id.to_string(),
+                           "error" => e.to_string());
+                }
+            }
+        }
+    }
+
+    pub fn rewind(
+        &self,
+        id: DeploymentHash,
+        block_ptr_to: BlockPtr,
+    ) -> Result<status::RevertReport, StoreError> {
         let (store, site) = self.store(&id)?;
-        let event = store.rewind(site, block_ptr_to)?;
-        self.send_store_event(&event)
+        let (event, report) = store.rewind(
+            site,
+            block_ptr_to,
+            RevertChunks::default(),
+            &self.revert_metrics,
+        )?;
+        self.send_store_event(&event)?;
+        Ok(report)
+    }
+
+    /// Rewind `id` to `block_ptr_to` in chunks of `chunk_size` blocks, but
+    /// stop after `chunks` chunks as if the node had been interrupted. Only
+    /// used by tests
+    #[cfg(debug_assertions)]
+    pub fn rewind_interrupted(
+        &self,
+        id: DeploymentHash,
+        block_ptr_to: BlockPtr,
+        chunk_size: BlockNumber,
+        chunks: usize,
+    ) -> Result<(), StoreError> {
+        let (store, site) = self.store(&id)?;
+        let chunks = RevertChunks {
+            size: chunk_size,
+            limit: Some(chunks),
+        };
+        store
+            .rewind(site, block_ptr_to, chunks, &self.revert_metrics)
+            .map(|_| ())
     }
 
     pub(crate) fn get_proof_of_indexing<'a>(
@@ -901,6 +1292,16 @@ impl SubgraphStoreInner {
     }
 }
 
+impl AdminAuditStore for SubgraphStore {
+    fn start_admin_call(&self, call: AdminCall) -> Result<i64, StoreError> {
+        self.primary_conn()?.start_admin_call(call)
+    }
+
+    fn finish_admin_call(&self, id: i64, error: Option<String>) -> Result<(), StoreError> {
+        self.primary_conn()?.finish_admin_call(id, error)
+    }
+}
+
 #[async_trait::async_trait]
 impl SubgraphStoreTrait for SubgraphStore {
     fn find_ens_name(&self, hash: &str) -> Result<Option<String>, QueryExecutionError> {
@@ -947,11 +1348,95 @@ impl SubgraphStoreTrait for SubgraphStore {
         node_id: &NodeId,
     ) -> Result<(), StoreError> {
         let site = self.find_site(deployment.id.into())?;
-        let pconn = self.primary_conn()?;
-        pconn.transaction(|| -> Result<_, StoreError> {
-            let changes = pconn.reassign_subgraph(site.as_ref(), node_id)?;
-            pconn.send_store_event(&self.sender, &StoreEvent::new(changes))
-        })
+        self.hand_over(site.as_ref(), node_id, false)
+    }
+
+    fn set_archive_blocks(
+        &self,
+        deployment: &DeploymentLocator,
+        blocks: Option<BlockNumber>,
+    ) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .set_archive_blocks(site.cheap_clone(), blocks)
+    }
+
+    fn skip_block(
+        &self,
+        deployment: &DeploymentLocator,
+        block_hash: &str,
+        reason: String,
+    ) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .skip_block(site.cheap_clone(), block_hash, reason)
+    }
+
+    fn unskip_block(
+        &self,
+        deployment: &DeploymentLocator,
+        block_hash: &str,
+    ) -> Result<status::SkippedBlock, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .unskip_block(site.cheap_clone(), block_hash)
+    }
+
+    fn reset_scan_totals(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .reset_scan_totals(site.as_ref())
+    }
+
+    fn labels(&self, deployment: &DeploymentHash) -> Result<Labels, StoreError> {
+        self.primary_conn()?.labels(deployment)
+    }
+
+    fn set_labels(&self, deployment: &DeploymentHash, labels: Labels) -> Result<(), StoreError> {
+        self.primary_conn()?.set_labels(deployment, &labels)
+    }
+
+    fn reload_mappings(
+        &self,
+        deployment: &DeploymentLocator,
+        manifest: &DeploymentHash,
+        block_ptr_to: Option<BlockPtr>,
+    ) -> Result<status::MappingsReload, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        let store = self.for_site(site.as_ref())?;
+        let rewound_to = match block_ptr_to {
+            Some(block_ptr_to) => {
+                let number = block_ptr_to.number;
+                let (event, _) = store.rewind(
+                    site.cheap_clone(),
+                    block_ptr_to,
+                    RevertChunks::default(),
+                    &self.revert_metrics,
+                )?;
+                self.send_store_event(&event)?;
+                Some(number)
+            }
+            None => None,
+        };
+        store.add_mappings_reload(site.as_ref(), manifest, rewound_to)
+    }
+
+    fn mappings_manifest(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<DeploymentHash>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .mappings_manifest(site.as_ref())
+    }
+
+    fn max_trigger_bytes(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<usize>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .max_trigger_bytes(site.as_ref())
     }
 
     fn assigned_node(&self, deployment: &DeploymentLocator) -> Result<Option<NodeId>, StoreError> {
@@ -960,6 +1445,87 @@ impl SubgraphStoreTrait for SubgraphStore {
         primary.assigned_node(site.as_ref())
     }
 
+    fn fatal_error(
+        &self,
+        deployment: &DeploymentLocator,
+    ) -> Result<Option<SubgraphError>, StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .fatal_error(site.cheap_clone())
+    }
+
+    fn retry_failed(&self, deployment: &DeploymentLocator) -> Result<(), StoreError> {
+        let site = self.find_site(deployment.id.into())?;
+        self.for_site(site.as_ref())?
+            .retry_failed(site.cheap_clone())
+    }
+
+    fn maintain_metadata(&self) -> Result<MetadataMaintenanceReport, StoreError> {
+        let primary = self.primary_conn()?;
+        let report = primary.transaction(|| -> Result<_, StoreError> {
+            let versions_removed = primary.remove_superseded_versions(*METADATA_KEEP_VERSIONS)?;
+            let audit_entries_removed = primary.trim_admin_audit_log(*ADMIN_AUDIT_LOG_KEEP)?;
+            let version_switches_removed = primary.trim_version_switches(*VERSION_SWITCH_KEEP)?;
+            Ok(MetadataMaintenanceReport {
+                versions_removed,
+                audit_entries_removed,
+                version_switches_removed,
+            })
+        })?;
+        primary.vacuum_metadata()?;
+        Ok(report)
+    }
+
+    fn compare_versions(
+        &self,
+        name: &SubgraphName,
+        block: BlockNumber,
+        sample_size: usize,
+    ) -> Result<VersionComparison, StoreError> {
+        let primary = self.primary_conn()?;
+        let current = primary.subgraph_version(name.to_string(), true)?;
+        let pending = primary.subgraph_version(name.to_string(), false)?;
+        let (current, pending) = match (current, pending) {
+            (Some(current), Some(pending)) => (Arc::new(current), Arc::new(pending)),
+            (_, None) => {
+                return Err(StoreError::Unknown(anyhow!(
+                    "subgraph `{}` has no pending version",
+                    name
+                )))
+            }
+            (None, Some(_)) => {
+                return Err(StoreError::Unknown(anyhow!(
+                    "subgraph `{}` has no current version",
+                    name
+                )))
+            }
+        };
+        if current.shard != pending.shard {
+            return Err(StoreError::Unknown(anyhow!(
+                "the current version {} of subgraph `{}` is in shard {} but the pending \
+                 version {} is in shard {}; only versions in the same shard can be compared",
+                current.deployment,
+                name,
+                current.shard,
+                pending.deployment,
+                pending.shard
+            )));
+        }
+
+        let entity_types = self.for_site(current.as_ref())?.compare_versions(
+            current.cheap_clone(),
+            pending.cheap_clone(),
+            block,
+            sample_size,
+        )?;
+        Ok(VersionComparison {
+            current: current.deployment.clone(),
+            pending: pending.deployment.clone(),
+            block,
+            entity_types,
+        })
+    }
+
     fn assignments(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
         let primary = self.primary_conn()?;
         primary
@@ -967,6 +1533,13 @@ impl SubgraphStoreTrait for SubgraphStore {
             .map(|sites| sites.iter().map(|site| site.into()).collect())
     }
 
+    fn standbys(&self, node: &NodeId) -> Result<Vec<DeploymentLocator>, StoreError> {
+        let primary = self.primary_conn()?;
+        primary
+            .standbys(node)
+            .map(|standbys| standbys.iter().map(|(site, _)| site.into()).collect())
+    }
+
     fn subgraph_exists(&self, name: &SubgraphName) -> Result<bool, StoreError> {
         let primary = self.primary_conn()?;
         primary.subgraph_exists(name)
@@ -989,7 +1562,11 @@ impl SubgraphStoreTrait for SubgraphStore {
         deployment: &DeploymentLocator,
     ) -> Result<Arc<dyn store::WritableStore>, StoreError> {
         let site = self.find_site(deployment.id.into())?;
-        Ok(Arc::new(WritableStore::new(self.clone(), site)?))
+        Ok(Arc::new(WritableStore::new(
+            self.clone(),
+            site,
+            self.node.clone(),
+        )?))
     }
 
     fn writable_for_network_indexer(
@@ -997,7 +1574,11 @@ impl SubgraphStoreTrait for SubgraphStore {
         id: &DeploymentHash,
     ) -> Result<Arc<dyn WritableStoreTrait>, StoreError> {
         let site = self.site(id)?;
-        Ok(Arc::new(WritableStore::new(self.clone(), site)?))
+        Ok(Arc::new(WritableStore::new(
+            self.clone(),
+            site,
+            self.node.clone(),
+        )?))
     }
 
     fn is_deployed(&self, id: &DeploymentHash) -> Result<bool, Error> {
@@ -1036,12 +1617,18 @@ impl WritableSubgraphStore {
         self.0.primary_conn()
     }
 
+    fn logger(&self) -> &Logger {
+        &self.0.logger
+    }
+
     pub(crate) fn send_store_event(&self, event: &StoreEvent) -> Result<(), StoreError> {
         self.0.send_store_event(event)
     }
 
-    fn layout(&self, id: &DeploymentHash) -> Result<Arc<Layout>, StoreError> {
-        self.0.layout(id)
+    /// The layout of `id` for copying its data into a graft
+    fn graft_source(&self, id: &DeploymentHash) -> Result<Arc<Layout>, StoreError> {
+        let (store, site) = self.0.store(id)?;
+        store.find_layout_for_copy(site)
     }
 }
 
@@ -1049,16 +1636,23 @@ struct WritableStore {
     store: WritableSubgraphStore,
     writable: Arc<DeploymentStore>,
     site: Arc<Site>,
+    /// The node on whose behalf we write
+    node: NodeId,
 }
 
 impl WritableStore {
-    fn new(subgraph_store: SubgraphStore, site: Arc<Site>) -> Result<Self, StoreError> {
+    fn new(
+        subgraph_store: SubgraphStore,
+        site: Arc<Site>,
+        node: NodeId,
+    ) -> Result<Self, StoreError> {
         let store = WritableSubgraphStore(subgraph_store.clone());
         let writable = subgraph_store.for_site(site.as_ref())?.clone();
         Ok(Self {
             store,
             writable,
             site,
+            node,
         })
     }
 }
@@ -1078,24 +1672,43 @@ impl WritableStoreTrait for WritableStore {
 
         let graft_base = match store.graft_pending(&self.site.deployment)? {
             Some((base_id, base_ptr)) => {
-                let src = self.store.layout(&base_id)?;
+                let src = self.store.graft_source(&base_id)?;
                 Some((src, base_ptr))
             }
             None => None,
         };
-        store.start_subgraph(logger, self.site.clone(), graft_base)?;
+        // Refuse to start if another node is still writing to the
+        // deployment; starting might copy data for a graft
+        store.acquire_writer_lease(self.site.as_ref(), &self.node)?;
+        // Indexing can only continue once the data matches the head again
+        if let Some(event) =
+            store.finish_pending_revert(logger, self.site.clone(), &self.store.revert_metrics)?
+        {
+            self.store.send_store_event(&event)?;
+        }
+        store.start_subgraph(
+            logger,
+            self.site.clone(),
+            graft_base,
+            self.store.copy_metrics.clone(),
+        )?;
         self.store.primary_conn()?.copy_finished(self.site.as_ref())
     }
 
-    fn revert_block_operations(&self, block_ptr_to: BlockPtr) -> Result<(), StoreError> {
-        let event = self
-            .writable
-            .revert_block_operations(self.site.clone(), block_ptr_to)?;
+    fn revert_block_operations(
+        &self,
+        block_ptr_to: BlockPtr,
+    ) -> Result<status::RevertReport, StoreError> {
+        let (event, report) = self.writable.revert_block_operations(
+            self.site.clone(),
+            &self.node,
+            block_ptr_to,
+            &self.store.revert_metrics,
+        )?;
         if *SEND_SUBSCRIPTION_NOTIFICATIONS {
-            self.store.send_store_event(&event)
-        } else {
-            Ok(())
+            self.store.send_store_event(&event)?;
         }
+        Ok(report)
     }
 
     fn unfail(&self) -> Result<(), StoreError> {
@@ -1134,6 +1747,7 @@ impl WritableStoreTrait for WritableStore {
 
         let event = self.writable.transact_block_operations(
             self.site.clone(),
+            &self.node,
             block_ptr_to,
             firehose_cursor,
             mods,
@@ -1166,11 +1780,15 @@ impl WritableStoreTrait for WritableStore {
     }
 
     fn unassign_subgraph(&self) -> Result<(), StoreError> {
-        let pconn = self.store.primary_conn()?;
-        pconn.transaction(|| -> Result<_, StoreError> {
-            let changes = pconn.unassign_subgraph(self.site.as_ref())?;
-            pconn.send_store_event(&self.store.0.sender, &StoreEvent::new(changes))
-        })
+        {
+            let pconn = self.store.primary_conn()?;
+            pconn.transaction(|| -> Result<_, StoreError> {
+                let changes = pconn.unassign_subgraph(self.site.as_ref())?;
+                pconn.send_store_event(&self.store.0.sender, &StoreEvent::new(changes))
+            })?;
+        }
+        self.writable
+            .transfer_writer_lease(self.site.as_ref(), None)
     }
 
     async fn load_dynamic_data_sources(&self) -> Result<Vec<StoredDynamicDataSource>, StoreError> {
@@ -1180,16 +1798,24 @@ impl WritableStoreTrait for WritableStore {
     }
 
     fn deployment_synced(&self) -> Result<(), Error> {
-        let event = {
+        let block = self.block_ptr()?.map(|ptr| ptr.number);
+        let (event, switches) = {
             // Make sure we drop `pconn` before we call into the deployment
             // store so that we do not hold two database connections which
             // might come from the same pool and could therefore deadlock
             let pconn = self.store.primary_conn()?;
             pconn.transaction(|| -> Result<_, Error> {
-                let changes = pconn.promote_deployment(&self.site.deployment)?;
-                Ok(StoreEvent::new(changes))
+                let (changes, switches) = pconn.promote_deployment(&self.site.deployment, block)?;
+                Ok((StoreEvent::new(changes), switches))
             })?
         };
+        for switch in switches {
+            info!(self.store.logger(), "Switched subgraph to new version";
+                  "subgraph_name" => &switch.subgraph,
+                  "old_deployment" => switch.old_deployment.as_deref().unwrap_or("none"),
+                  "new_deployment" => &switch.new_deployment,
+                  "block" => format!("{:?}", switch.block));
+        }
 
         self.writable.deployment_synced(&self.site.deployment)?;
 
@@ -1199,6 +1825,53 @@ impl WritableStoreTrait for WritableStore {
     fn shard(&self) -> &str {
         self.site.shard.as_str()
     }
+
+    fn network_name(&self) -> &str {
+        self.site.network.as_str()
+    }
+
+    fn set_block_latency(&self, p50: f64, p95: f64) -> Result<(), StoreError> {
+        self.writable
+            .set_block_latency(self.site.as_ref(), p50, p95)
+    }
+
+    fn set_synced_checks(&self, checks: u32) -> Result<(), StoreError> {
+        self.writable.set_synced_checks(self.site.as_ref(), checks)
+    }
+
+    fn add_scan_totals(&self, totals: &ScanTotals) -> Result<(), StoreError> {
+        self.writable.add_scan_totals(self.site.as_ref(), totals)
+    }
+
+    fn skipped_blocks(&self) -> Result<Vec<status::SkippedBlock>, StoreError> {
+        self.writable.skipped_blocks(self.site.as_ref())
+    }
+
+    fn record_skipped_block(&self, block_ptr: &BlockPtr) -> Result<(), StoreError> {
+        self.writable
+            .record_skipped_block(self.site.as_ref(), block_ptr)
+    }
+
+    fn once_handler_runs(&self) -> Result<Vec<OnceHandlerRun>, StoreError> {
+        self.writable.once_handler_runs(self.site.as_ref())
+    }
+
+    fn record_once_handler_runs(
+        &self,
+        block: BlockNumber,
+        data_sources: Vec<DataSourceKey>,
+    ) -> Result<(), StoreError> {
+        self.writable
+            .record_once_handler_runs(self.site.as_ref(), block, data_sources)
+    }
+
+    fn complete_subgraph(&self) -> Result<(), StoreError> {
+        self.writable.complete_subgraph(&self.site.deployment)
+    }
+
+    fn is_completed(&self) -> Result<bool, StoreError> {
+        self.writable.is_completed(&self.site.deployment)
+    }
 }
 
 fn same_subgraph(mods: &Vec<EntityModification>, id: &DeploymentHash) -> bool {
@@ -6,7 +6,7 @@ use std::sync::Arc;
 
 use graph::prelude::web3::types::H256;
 use graph::prelude::{anyhow::anyhow, anyhow::Error};
-use graph::prelude::{BlockNumber, QueryStoreManager};
+use graph::prelude::{BlockNumber, EthereumUncle, QueryStoreManager};
 use graph::{cheap_clone::CheapClone, prelude::web3::types::H160};
 use graph::{components::store::BlockStore as _, prelude::DeploymentHash};
 use graph::{components::store::ChainStore as _, prelude::EthereumCallCache as _};
@@ -240,6 +240,49 @@ fn block_hashes_by_number() {
     })
 }
 
+#[test]
+fn uncles() {
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE, &*BLOCK_TWO];
+    run_test(chain, move |store, _| {
+        let uncle_hashes = |hash| -> Option<Vec<H256>> {
+            store.uncles(hash).unwrap().map(|uncles| {
+                uncles
+                    .into_iter()
+                    .map(|uncle| uncle.hash.unwrap())
+                    .collect()
+            })
+        };
+        let uncle = EthereumUncle {
+            hash: Some(H256::from_low_u64_be(0xabc)),
+            number: Some(0.into()),
+            ..Default::default()
+        };
+
+        // Blocks are stored without uncles
+        assert_eq!(Some(vec![]), uncle_hashes(BLOCK_ONE.block_hash()));
+
+        store.set_uncles(BLOCK_ONE.block_hash(), &[uncle.clone()])?;
+        store.set_uncles(BLOCK_TWO.block_hash(), &[uncle.clone()])?;
+        assert_eq!(
+            Some(vec![H256::from_low_u64_be(0xabc)]),
+            uncle_hashes(BLOCK_ONE.block_hash())
+        );
+
+        // Storing the uncles of a block we do not have does nothing
+        store.set_uncles(BLOCK_THREE.block_hash(), &[uncle])?;
+        assert_eq!(None, uncle_hashes(BLOCK_THREE.block_hash()));
+
+        // Reverting a block removes only its uncles
+        store.remove_uncles(&[BLOCK_TWO.block_hash()])?;
+        assert_eq!(Some(vec![]), uncle_hashes(BLOCK_TWO.block_hash()));
+        assert_eq!(
+            Some(vec![H256::from_low_u64_be(0xabc)]),
+            uncle_hashes(BLOCK_ONE.block_hash())
+        );
+        Ok(())
+    })
+}
+
 #[track_caller]
 fn check_ancestor(
     store: &Arc<DieselChainStore>,
@@ -342,6 +385,13 @@ fn eth_call_cache() {
             .unwrap();
         assert!(ret.is_none());
 
+        // A block that replaces block one in a reorg does not see the
+        // result for block one
+        let ret = store
+            .get_call(address, &call, BLOCK_ONE_SIBLING.block_ptr())
+            .unwrap();
+        assert!(ret.is_none());
+
         let new_return_value: [u8; 3] = [10, 11, 12];
         store
             .set_call(address, &call, BLOCK_TWO.block_ptr(), &new_return_value)
@@ -368,3 +418,90 @@ fn test_transaction_receipts_in_block_function() {
         assert!(receipts.is_empty())
     })
 }
+
+#[test]
+/// A network whose providers can not be reached at startup is passed to
+/// the block store without identifiers; its chain must stay ingestible so
+/// that indexing resumes once the providers come back
+fn offline_network_stays_ingestible() {
+    run_test_sequentially(|store| async move {
+        let chain_store = store
+            .block_store()
+            .chain_store(NETWORK_NAME)
+            .expect("chain store");
+        assert!(chain_store.is_ingestible());
+
+        let store = network_store(vec![
+            (NETWORK_NAME.to_string(), vec![]),
+            (FAKE_NETWORK_SHARED.to_string(), vec![]),
+        ]);
+        for name in vec![NETWORK_NAME, FAKE_NETWORK_SHARED] {
+            let chain_store = store.block_store().chain_store(name).expect("chain store");
+            assert!(chain_store.is_ingestible(), "{} is ingestible", name);
+        }
+    })
+}
+
+#[test]
+/// Renaming a chain moves its blocks and the deployments that index it over
+/// to the new name, and a rename that fails leaves everything unchanged
+fn rename_chain() {
+    const RENAMED: &str = "renamed_network";
+
+    let chain = vec![&*GENESIS_BLOCK, &*BLOCK_ONE];
+    let subgraph = DeploymentHash::new("renameChain").unwrap();
+
+    run_test(chain, move |chain_store, store| {
+        let deployment = create_test_subgraph(&subgraph, "type Dummy @entity { id: ID! }");
+        let block_store = store.block_store();
+        let name = chain_store.chain.as_str();
+        let other = if name == NETWORK_NAME {
+            FAKE_NETWORK_SHARED
+        } else {
+            NETWORK_NAME
+        };
+        let networks = |network: &str| -> Vec<DeploymentHash> {
+            let mut deployments: Vec<_> = primary_connection()
+                .find_sites_for_network(network)
+                .unwrap()
+                .into_iter()
+                .map(|site| site.deployment)
+                .collect();
+            deployments.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+            deployments
+        };
+        let deployments = networks(name);
+
+        block_store.rename_chain(name, RENAMED)?;
+
+        assert!(block_store.chain_store(name).is_none());
+        let renamed = block_store
+            .chain_store(RENAMED)
+            .expect("renamed chain store");
+        assert_eq!(
+            vec![BLOCK_ONE.block_hash()],
+            renamed.block_hashes_by_block_number(1)?
+        );
+        assert!(networks(name).is_empty());
+        assert_eq!(deployments, networks(RENAMED));
+        if name == NETWORK_NAME {
+            assert!(deployments.contains(&deployment.hash));
+        }
+
+        // Renaming to a chain that exists fails and changes nothing
+        assert!(block_store.rename_chain(RENAMED, other).is_err());
+        let renamed = block_store
+            .chain_store(RENAMED)
+            .expect("renamed chain store");
+        assert_eq!(
+            vec![BLOCK_ONE.block_hash()],
+            renamed.block_hashes_by_block_number(1)?
+        );
+        assert_eq!(deployments, networks(RENAMED));
+
+        block_store.rename_chain(RENAMED, name)?;
+        assert!(block_store.chain_store(RENAMED).is_none());
+        assert_eq!(deployments, networks(name));
+        Ok(())
+    })
+}
@@ -4,7 +4,7 @@ use std::{marker::PhantomData, str::FromStr};
 use test_store::*;
 
 use graph::components::store::{
-    DeploymentLocator, EntityKey, EntityOrder, EntityQuery, EntityType,
+    DeploymentLocator, EntityKey, EntityOrder, EntityQuery, EntityType, StatusStore,
 };
 use graph::data::store::scalar;
 use graph::data::subgraph::schema::*;
@@ -118,6 +118,7 @@ fn insert_test_data(store: Arc<DieselSubgraphStore>) -> DeploymentLocator {
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        max_triggers_per_block: None,
         chain: PhantomData,
     };
 
@@ -325,6 +326,19 @@ fn graft() {
         )
         .expect("can create grafted subgraph");
 
+        // The data for the graft was copied completely
+        let infos = STORE.status(status::Filter::Deployments(vec![subgraph_id.to_string()]))?;
+        let copy_status = infos[0]
+            .copy_status
+            .clone()
+            .expect("grafted subgraph has a copy status");
+        assert_eq!(copy_status.tables_total, copy_status.tables_done);
+        assert!(copy_status.rows_copied > 0);
+        assert_eq!(100.0, copy_status.progress_pct);
+        assert_eq!(None, copy_status.eta_seconds);
+        assert!(copy_status.finished_at.is_some());
+        assert_eq!(None, copy_status.cancelled_at);
+
         check_graft(store, deployment)
     })
 }
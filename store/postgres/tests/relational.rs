@@ -19,7 +19,7 @@ use std::time::Duration;
 
 use graph::{
     components::store::{AttributeNames, EntityType},
-    data::store::scalar::{BigDecimal, BigInt, Bytes},
+    data::store::scalar::{BigDecimal, BigInt, Bytes, Timestamp},
 };
 use graph_store_postgres::{
     layout_for_tests::make_dummy_site,
@@ -78,6 +78,8 @@ const THINGS_GQL: &str = r#"
         bigInt: BigInt,
         bigIntArray: [BigInt!]!
         color: Color,
+        int8: Int8,
+        timestamp: Timestamp,
     }
 
     interface Pet {
@@ -107,6 +109,8 @@ const THINGS_GQL: &str = r#"
         email: String!,
         age: Int!,
         seconds_age: BigInt!,
+        visits: Int8!,
+        birthday: Timestamp!,
         weight: BigDecimal!,
         coffee: Boolean!,
         favorite_color: Color,
@@ -170,6 +174,11 @@ lazy_static! {
             vec![big_int.clone(), (big_int + 1.into()).clone()],
         );
         entity.set("color", "yellow");
+        entity.set("int8", std::i64::MAX);
+        entity.set(
+            "timestamp",
+            Timestamp::from_str("2024-01-01T12:34:56.789012Z").unwrap(),
+        );
         entity.set("__typename", "Scalar");
         entity
     };
@@ -266,6 +275,12 @@ fn update_entity(
     assert_eq!(updated, entities_with_keys.len());
 }
 
+/// Everybody was born on new year's day, and is as old as they would be
+/// in 2020
+fn birthday(age: i32) -> Timestamp {
+    Timestamp::from_str(&format!("{}-01-01T00:00:00Z", 2020 - age)).unwrap()
+}
+
 fn insert_user_entity(
     conn: &PgConnection,
     layout: &Layout,
@@ -291,6 +306,8 @@ fn insert_user_entity(
         "seconds_age".to_owned(),
         Value::BigInt(BigInt::from(age) * 31557600.into()),
     );
+    user.insert("visits".to_owned(), Value::Int8(i64::from(age) << 32));
+    user.insert("birthday".to_owned(), Value::Timestamp(birthday(age)));
     user.insert("weight".to_owned(), Value::BigDecimal(weight.into()));
     user.insert("coffee".to_owned(), Value::Bool(coffee));
     user.insert(
@@ -373,6 +390,8 @@ fn update_user_entity(
         "seconds_age".to_owned(),
         Value::BigInt(BigInt::from(age) * 31557600.into()),
     );
+    user.insert("visits".to_owned(), Value::Int8(i64::from(age) << 32));
+    user.insert("birthday".to_owned(), Value::Timestamp(birthday(age)));
     user.insert("weight".to_owned(), Value::BigDecimal(weight.into()));
     user.insert("coffee".to_owned(), Value::Bool(coffee));
     user.insert(
@@ -410,7 +429,7 @@ fn insert_test_data(conn: &PgConnection) -> Layout {
     let query = format!("create schema {}", NAMESPACE.as_str());
     conn.batch_execute(&*query).unwrap();
 
-    Layout::create_relational_schema(&conn, Arc::new(site), &schema)
+    Layout::create_relational_schema(&conn, Arc::new(site), &schema, false)
         .expect("Failed to create relational schema")
 }
 
@@ -702,6 +721,7 @@ fn count_scalar_entities(conn: &PgConnection, layout: &Layout) -> usize {
             },
             BLOCK_NUMBER_MAX,
             None,
+            None,
         )
         .expect("Count query failed")
         .len()
@@ -890,6 +910,7 @@ impl<'a> QueryChecker<'a> {
                 query.range,
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("layout.query failed to execute query");
 
@@ -1249,6 +1270,35 @@ fn check_find() {
                     ))
                     .asc("name"),
             )
+            .check(
+                vec!["2", "3"],
+                user_query()
+                    .filter(EntityFilter::LessThan(
+                        "visits".to_owned(),
+                        Value::Int8(50i64 << 32),
+                    ))
+                    .desc("visits"),
+            )
+            .check(
+                vec!["2", "3"],
+                user_query()
+                    .filter(EntityFilter::GreaterOrEqual(
+                        "birthday".to_owned(),
+                        Value::Timestamp(Timestamp::from_str("1970-01-01T00:00:00Z").unwrap()),
+                    ))
+                    .asc("name"),
+            )
+            .check(vec!["1", "2", "3"], user_query().asc("birthday"))
+            .check(
+                vec!["1"],
+                user_query().filter(EntityFilter::In(
+                    "birthday".to_owned(),
+                    vec![
+                        Value::Timestamp(birthday(67)),
+                        Value::Timestamp(birthday(50)),
+                    ],
+                )),
+            )
             .check(
                 vec!["3", "2"],
                 user_query()
@@ -1485,6 +1535,7 @@ fn text_find(expected_entity_ids: Vec<&str>, filter: EntityFilter) {
                 query.range,
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("layout.query failed to execute query");
 
@@ -7,7 +7,7 @@ use lazy_static::lazy_static;
 use std::{collections::BTreeMap, sync::Arc};
 
 use graph::prelude::{
-    o, slog, web3::types::H256, AttributeNames, ChildMultiplicity, DeploymentHash, Entity,
+    o, q, slog, web3::types::H256, AttributeNames, ChildMultiplicity, DeploymentHash, Entity,
     EntityCollection, EntityKey, EntityLink, EntityOrder, EntityRange, EntityWindow, Logger,
     ParentLink, Schema, StopwatchMetrics, Value, WindowAttribute, BLOCK_NUMBER_MAX,
 };
@@ -120,7 +120,7 @@ fn create_schema(conn: &PgConnection) -> Layout {
         NAMESPACE.clone(),
         NETWORK_NAME.to_string(),
     );
-    Layout::create_relational_schema(&conn, Arc::new(site), &schema)
+    Layout::create_relational_schema(&conn, Arc::new(site), &schema, false)
         .expect("Failed to create relational schema")
 }
 
@@ -237,6 +237,46 @@ fn find() {
     });
 }
 
+#[test]
+fn ids_round_trip() {
+    run_test(|conn, layout| {
+        const NAME: &str = "Beef";
+        insert_thing(&conn, &layout, "deadbeef", NAME);
+
+        // GraphQL responses render ids of type `Bytes` without a `0x`
+        // prefix, and the rendered id finds the entity again, with or
+        // without the prefix
+        let coll = EntityCollection::All(vec![(THING.clone(), AttributeNames::All)]);
+        let things = layout
+            .query::<BTreeMap<String, q::Value>>(
+                &*LOGGER,
+                conn,
+                coll,
+                None,
+                EntityOrder::Default,
+                EntityRange::first(10),
+                BLOCK_NUMBER_MAX,
+                None,
+                None,
+            )
+            .expect("the query succeeds");
+        assert_eq!(1, things.len());
+        let id = match &things[0]["id"] {
+            q::Value::String(id) => id.clone(),
+            other => panic!("ids are strings but got {:?}", other),
+        };
+        assert_eq!("deadbeef", id);
+
+        for id in &[id.clone(), format!("0x{}", id)] {
+            let entity = layout
+                .find(conn, &*THING, id, BLOCK_NUMBER_MAX)
+                .expect("Failed to read Thing")
+                .unwrap();
+            assert_eq!(Some(&Value::from(NAME)), entity.get("name"));
+        }
+    });
+}
+
 #[test]
 fn find_many() {
     run_test(|conn, layout| {
@@ -402,6 +442,7 @@ fn query() {
                 EntityRange::first(10),
                 BLOCK_NUMBER_MAX,
                 None,
+                None,
             )
             .expect("the query succeeds")
             .into_iter()
@@ -159,6 +159,7 @@ fn insert_test_data(store: Arc<DieselSubgraphStore>) -> DeploymentLocator {
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        max_triggers_per_block: None,
         chain: PhantomData,
     };
 
@@ -1139,6 +1140,7 @@ fn mock_data_source() -> graph_chain_ethereum::DataSource {
             address: Some(Address::from_str("0123123123012312312301231231230123123123").unwrap()),
             abi: String::from("123123"),
             start_block: 0,
+            end_block: None,
         },
         mapping: Mapping {
             kind: String::from("ethereum/events"),
@@ -1147,6 +1149,7 @@ fn mock_data_source() -> graph_chain_ethereum::DataSource {
             entities: vec![],
             abis: vec![],
             event_handlers: vec![],
+            oversized_logs: None,
             call_handlers: vec![],
             block_handlers: vec![],
             link: Link {
@@ -1272,6 +1275,7 @@ fn entity_changes_are_fired_and_forwarded_to_subscriptions() {
             data_sources: vec![],
             graft: None,
             templates: vec![],
+            max_triggers_per_block: None,
             chain: PhantomData,
         };
 
@@ -1,7 +1,12 @@
 use graph::{
-    components::store::{DeploymentLocator, StatusStore},
+    components::store::{
+        AdminAuditStore, AttributeNames, DeploymentLocator, EntityCollection, EntityKey,
+        EntityQuery, EntityType, OnceHandlerRun, StatusStore, WritableStore,
+    },
     data::subgraph::schema::SubgraphError,
     data::subgraph::schema::SubgraphHealth,
+    data::subgraph::status,
+    data::subgraph::{LabelSelector, Labels},
     prelude::EntityChange,
     prelude::EntityChangeOperation,
     prelude::QueryStoreManager,
@@ -11,13 +16,21 @@ use graph::{
     prelude::SubgraphManifest,
     prelude::SubgraphName,
     prelude::SubgraphVersionSwitchingMode,
-    prelude::{CheapClone, DeploymentHash, NodeId, SubgraphStore as _},
+    prelude::{
+        o, slog, web3::types::H256, BlockNumber, BlockPtr, CheapClone, DataSourceKey,
+        DeploymentHash, Entity, EntityOperation, Logger, NodeId, StopwatchMetrics, StoreError,
+        SubgraphStore as _, Value,
+    },
+    runtime::MAX_TRIGGER_BYTES,
     semver::Version,
+    util::clock::ClockWatch,
 };
+use graph_mock::MockMetricsRegistry;
 use graph_store_postgres::layout_for_tests::Connection as Primary;
-use graph_store_postgres::SubgraphStore;
+use graph_store_postgres::{ArchiveMetrics, SubgraphStore};
 
-use std::{collections::HashSet, marker::PhantomData, sync::Arc};
+use std::time::{Duration, SystemTime};
+use std::{collections::HashSet, iter::FromIterator, marker::PhantomData, sync::Arc};
 use test_store::*;
 
 const SUBGRAPH_GQL: &str = "
@@ -124,6 +137,7 @@ fn create_subgraph() {
             data_sources: vec![],
             graft: None,
             templates: vec![],
+            max_triggers_per_block: None,
             chain: PhantomData,
         };
         let deployment = SubgraphDeploymentEntity::new(&manifest, false, None);
@@ -293,6 +307,196 @@ fn create_subgraph() {
         let (current, pending) = subgraph_deployments(&primary);
         assert_eq!(Some(ID2), current.as_deref());
         assert_eq!(Some(ID2), pending.as_deref());
+    });
+
+    // Test that promoting a pending version records the switch once
+    run_test_sequentially(|store| async move {
+        remove_subgraphs();
+        let subgraph_store = store.subgraph_store();
+
+        const MODE: SubgraphVersionSwitchingMode = SubgraphVersionSwitchingMode::Synced;
+        const ID1: &str = "switch";
+        const ID2: &str = "switch2";
+        const ID3: &str = "switch3";
+
+        let primary = primary_connection();
+
+        let name = SubgraphName::new(SUBGRAPH_NAME.to_string()).unwrap();
+        subgraph_store.create_subgraph(name).unwrap();
+
+        let (deployment1, _) = deploy(subgraph_store.as_ref(), ID1, MODE);
+        deployment_synced(&subgraph_store, &deployment1);
+        let (deployment2, _) = deploy(subgraph_store.as_ref(), ID2, MODE);
+
+        let (current, pending) = subgraph_deployments(&primary);
+        assert_eq!(Some(ID1), current.as_deref());
+        assert_eq!(Some(ID2), pending.as_deref());
+        assert!(store
+            .version_switches(SUBGRAPH_NAME, 10)
+            .unwrap()
+            .is_empty());
+
+        // Only the first call switches versions
+        deployment_synced(&subgraph_store, &deployment2);
+        deployment_synced(&subgraph_store, &deployment2);
+
+        let (current, pending) = subgraph_deployments(&primary);
+        assert_eq!(Some(ID2), current.as_deref());
+        assert!(pending.is_none());
+
+        let switches = store.version_switches(SUBGRAPH_NAME, 10).unwrap();
+        assert_eq!(1, switches.len());
+        let switch = &switches[0];
+        assert_eq!(SUBGRAPH_NAME, switch.subgraph);
+        assert_eq!(Some(ID1), switch.old_deployment.as_deref());
+        assert_eq!(ID2, switch.new_deployment);
+
+        // Metadata maintenance only keeps the most recent switches
+        let (deployment3, _) = deploy(subgraph_store.as_ref(), ID3, MODE);
+        deployment_synced(&subgraph_store, &deployment3);
+        assert_eq!(2, store.version_switches(SUBGRAPH_NAME, 10).unwrap().len());
+        assert!(primary.trim_version_switches(1).unwrap() >= 1);
+        let switches = store.version_switches(SUBGRAPH_NAME, 10).unwrap();
+        assert_eq!(1, switches.len());
+        assert_eq!(ID3, switches[0].new_deployment);
+        assert_eq!(0, primary.trim_version_switches(1).unwrap());
+    })
+}
+
+#[test]
+fn aliased_deployment() {
+    const PROD: &str = "alias/prod";
+    const STAGING: &str = "alias/staging";
+    const ALIAS: &str = "aliasedDeployment";
+    const OTHER: &str = "otherAliasedDeployment";
+
+    fn deploy(
+        store: &SubgraphStore,
+        name: &str,
+        id: &str,
+        mode: SubgraphVersionSwitchingMode,
+    ) -> (DeploymentLocator, HashSet<EntityChange>) {
+        let name = SubgraphName::new(name.to_string()).unwrap();
+        let id = DeploymentHash::new(id.to_string()).unwrap();
+        let schema = Schema::parse(SUBGRAPH_GQL, id.clone()).unwrap();
+
+        let manifest = SubgraphManifest::<graph_chain_ethereum::Chain> {
+            id: id.clone(),
+            spec_version: Version::new(1, 0, 0),
+            features: Default::default(),
+            description: None,
+            repository: None,
+            schema: schema.clone(),
+            data_sources: vec![],
+            graft: None,
+            templates: vec![],
+            max_triggers_per_block: None,
+            chain: PhantomData,
+        };
+        let deployment = SubgraphDeploymentEntity::new(&manifest, false, None);
+        let node_id = NodeId::new("left").unwrap();
+
+        tap_changes(|| {
+            store
+                .create_subgraph_deployment(
+                    name,
+                    &schema,
+                    deployment,
+                    node_id,
+                    NETWORK_NAME.to_string(),
+                    mode,
+                )
+                .unwrap()
+        })
+    }
+
+    fn remove(store: &SubgraphStore, name: &str) -> HashSet<EntityChange> {
+        let name = SubgraphName::new(name.to_string()).unwrap();
+        tap_changes(|| store.remove_subgraph(name).unwrap()).1
+    }
+
+    fn tap_changes<R>(f: impl FnOnce() -> R) -> (R, HashSet<EntityChange>) {
+        let (res, events) = tap_store_events(f);
+        let changes = events
+            .into_iter()
+            .map(|event| event.changes.into_iter())
+            .flatten()
+            .collect();
+        (res, changes)
+    }
+
+    fn names(store: &Arc<Store>, deployment: &DeploymentLocator) -> Vec<String> {
+        store
+            .status(status::Filter::Deployments(vec![deployment
+                .hash
+                .to_string()]))
+            .unwrap()
+            .pop()
+            .map(|info| info.names)
+            .unwrap_or_default()
+    }
+
+    fn deployment_synced(store: &SubgraphStore, deployment: &DeploymentLocator) {
+        store
+            .writable(deployment)
+            .expect("can get writable")
+            .deployment_synced()
+            .unwrap();
+    }
+
+    // Removing one of the names leaves the deployment assigned to the
+    // other one
+    run_test_sequentially(|store| async move {
+        use SubgraphVersionSwitchingMode::Instant;
+
+        remove_subgraphs();
+        let subgraph_store = store.subgraph_store();
+
+        let (deployment, changes) = deploy(&subgraph_store, PROD, ALIAS, Instant);
+        assert_eq!(HashSet::from_iter(vec![assigned(&deployment)]), changes);
+        let (deployment2, changes) = deploy(&subgraph_store, STAGING, ALIAS, Instant);
+        assert_eq!(deployment, deployment2);
+        assert!(changes.is_empty());
+        assert_eq!(vec![PROD, STAGING], names(&store, &deployment));
+
+        assert!(remove(&subgraph_store, STAGING).is_empty());
+        assert!(subgraph_store.assigned_node(&deployment).unwrap().is_some());
+        assert_eq!(vec![PROD], names(&store, &deployment));
+
+        let changes = remove(&subgraph_store, PROD);
+        assert_eq!(HashSet::from_iter(vec![unassigned(&deployment)]), changes);
+        assert!(subgraph_store.assigned_node(&deployment).unwrap().is_none());
+        assert!(names(&store, &deployment).is_empty());
+    });
+
+    // A pending version keeps the deployment assigned, too, and removing
+    // both names cleans up exactly once
+    run_test_sequentially(|store| async move {
+        use SubgraphVersionSwitchingMode::{Instant, Synced};
+
+        remove_subgraphs();
+        let subgraph_store = store.subgraph_store();
+
+        let (deployment, _) = deploy(&subgraph_store, STAGING, ALIAS, Instant);
+        deployment_synced(&subgraph_store, &deployment);
+        let (other, _) = deploy(&subgraph_store, PROD, OTHER, Instant);
+        deployment_synced(&subgraph_store, &other);
+        let (deployment2, changes) = deploy(&subgraph_store, PROD, ALIAS, Synced);
+        assert_eq!(deployment, deployment2);
+        assert!(changes.is_empty());
+        assert_eq!(vec![PROD, STAGING], names(&store, &deployment));
+
+        assert!(remove(&subgraph_store, STAGING).is_empty());
+        assert!(subgraph_store.assigned_node(&deployment).unwrap().is_some());
+        assert_eq!(vec![PROD], names(&store, &deployment));
+
+        let changes = remove(&subgraph_store, PROD);
+        let expected = HashSet::from_iter(vec![unassigned(&deployment), unassigned(&other)]);
+        assert_eq!(expected, changes);
+        assert!(subgraph_store.assigned_node(&deployment).unwrap().is_none());
+        assert!(subgraph_store.assigned_node(&other).unwrap().is_none());
+
+        assert!(remove(&subgraph_store, PROD).is_empty());
     })
 }
 
@@ -310,8 +514,6 @@ fn status() {
     }
 
     run_test_sequentially(|store| async move {
-        use graph::data::subgraph::status;
-
         let deployment = setup();
         let infos = store
             .status(status::Filter::Deployments(vec![
@@ -324,6 +526,20 @@ fn status() {
         let info = infos.first().unwrap();
         assert_eq!(NAME, info.subgraph);
         assert!(!info.synced);
+        assert_eq!(0, info.synced_checks);
+
+        store
+            .subgraph_store()
+            .writable(&deployment)
+            .expect("can get writable")
+            .set_synced_checks(2)
+            .unwrap();
+        let infos = store
+            .status(status::Filter::Deployments(vec![deployment
+                .hash
+                .to_string()]))
+            .unwrap();
+        assert_eq!(2, infos[0].synced_checks);
 
         let infos = store.status(status::Filter::Deployments(vec![])).unwrap();
         assert_eq!(2, infos.len());
@@ -405,6 +621,277 @@ fn status() {
     })
 }
 
+#[test]
+fn status_eth_calls_disabled() {
+    use graph::data::subgraph::SubgraphFeature;
+
+    const NAME: &str = "noEthCallsSubgraph";
+    const OTHER: &str = "ethCallsSubgraph";
+
+    run_test_sequentially(|store| async move {
+        remove_subgraphs();
+        create_subgraph_with_features(
+            &DeploymentHash::new(NAME).unwrap(),
+            SUBGRAPH_GQL,
+            None,
+            vec![SubgraphFeature::NoEthCalls].into_iter().collect(),
+        )
+        .unwrap();
+        create_test_subgraph(&DeploymentHash::new(OTHER).unwrap(), SUBGRAPH_GQL);
+
+        let infos = store.status(status::Filter::Deployments(vec![])).unwrap();
+        assert_eq!(2, infos.len());
+        for info in infos {
+            assert_eq!(info.subgraph == NAME, info.eth_calls_disabled);
+        }
+    })
+}
+
+#[test]
+fn cluster_status() {
+    const NAME: &str = "clusterInfoSubgraph";
+    const OTHER: &str = "otherClusterInfoSubgraph";
+
+    fn setup() {
+        remove_subgraphs();
+        create_test_subgraph(&DeploymentHash::new(NAME).unwrap(), SUBGRAPH_GQL);
+        create_test_subgraph(&DeploymentHash::new(OTHER).unwrap(), SUBGRAPH_GQL);
+    }
+
+    fn filter(health: Option<SubgraphHealth>, node: Option<&str>) -> status::ClusterFilter {
+        status::ClusterFilter {
+            health,
+            node: node.map(|node| NodeId::new(node).unwrap()),
+            skip: 0,
+            first: 100,
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        setup();
+        store.subgraph_store().record_heartbeat(&*NODE_ID).unwrap();
+
+        let infos = store.cluster_status(filter(None, None)).unwrap();
+        assert_eq!(2, infos.len());
+        for info in &infos {
+            assert_eq!(Some(NODE_ID.to_string()), info.info.node);
+            assert!(info.last_heartbeat.is_some());
+            assert!(!info.node_dead);
+        }
+        let subgraphs: HashSet<_> = infos
+            .iter()
+            .map(|info| info.info.subgraph.as_str())
+            .collect();
+        assert_eq!(HashSet::from_iter(vec![NAME, OTHER]), subgraphs);
+
+        let infos = store
+            .cluster_status(filter(None, Some(NODE_ID.as_str())))
+            .unwrap();
+        assert_eq!(2, infos.len());
+
+        let infos = store
+            .cluster_status(filter(None, Some("someOtherNode")))
+            .unwrap();
+        assert!(infos.is_empty());
+
+        let infos = store
+            .cluster_status(filter(Some(SubgraphHealth::Failed), None))
+            .unwrap();
+        assert!(infos.is_empty());
+
+        let mut paged = filter(Some(SubgraphHealth::Healthy), None);
+        paged.skip = 1;
+        let infos = store.cluster_status(paged).unwrap();
+        assert_eq!(1, infos.len());
+    })
+}
+
+#[test]
+fn indexing_statuses() {
+    const NAME: &str = "statusPageSubgraph";
+    const OTHER: &str = "otherStatusPageSubgraph";
+
+    fn setup() {
+        remove_subgraphs();
+        create_test_subgraph(&DeploymentHash::new(NAME).unwrap(), SUBGRAPH_GQL);
+        create_test_subgraph(&DeploymentHash::new(OTHER).unwrap(), SUBGRAPH_GQL);
+    }
+
+    fn query(order: status::StatusOrder) -> status::StatusQuery {
+        status::StatusQuery {
+            deployments: vec![],
+            health: None,
+            synced: None,
+            network: None,
+            name_prefix: None,
+            node: None,
+            labels: vec![],
+            order,
+            descending: false,
+            first: 100,
+            after: None,
+        }
+    }
+
+    fn subgraphs(infos: &[status::Info]) -> Vec<&str> {
+        infos.iter().map(|info| info.subgraph.as_str()).collect()
+    }
+
+    run_test_sequentially(|store| async move {
+        setup();
+
+        let infos = store
+            .indexing_statuses(query(status::StatusOrder::Name))
+            .unwrap();
+        assert_eq!(vec![OTHER, NAME], subgraphs(&infos));
+        let network = infos[0].chains[0].network.clone();
+
+        // Page through the statuses one at a time
+        let mut page = query(status::StatusOrder::Name);
+        page.first = 1;
+        let infos = store.indexing_statuses(page.clone()).unwrap();
+        assert_eq!(vec![OTHER], subgraphs(&infos));
+        page.after = infos[0].cursor.clone();
+        let infos = store.indexing_statuses(page.clone()).unwrap();
+        assert_eq!(vec![NAME], subgraphs(&infos));
+        page.after = infos[0].cursor.clone();
+        assert!(store.indexing_statuses(page).unwrap().is_empty());
+
+        // Neither deployment has processed a block, so they are equally
+        // far behind and ordered by their id
+        let mut lag = query(status::StatusOrder::Lag);
+        lag.descending = true;
+        let infos = store.indexing_statuses(lag).unwrap();
+        assert_eq!(vec![OTHER, NAME], subgraphs(&infos));
+
+        let mut filter = query(status::StatusOrder::Id);
+        filter.name_prefix = Some("other".to_string());
+        let infos = store.indexing_statuses(filter).unwrap();
+        assert_eq!(vec![OTHER], subgraphs(&infos));
+
+        let mut filter = query(status::StatusOrder::Id);
+        filter.network = Some(network);
+        filter.health = Some(SubgraphHealth::Healthy);
+        filter.node = Some(NODE_ID.clone());
+        let infos = store.indexing_statuses(filter).unwrap();
+        assert_eq!(vec![NAME, OTHER], subgraphs(&infos));
+
+        let mut filter = query(status::StatusOrder::Id);
+        filter.network = Some("noSuchNetwork".to_string());
+        assert!(store.indexing_statuses(filter).unwrap().is_empty());
+
+        let mut filter = query(status::StatusOrder::Id);
+        filter.synced = Some(true);
+        assert!(store.indexing_statuses(filter).unwrap().is_empty());
+
+        let mut filter = query(status::StatusOrder::Id);
+        filter.health = Some(SubgraphHealth::Failed);
+        assert!(store.indexing_statuses(filter).unwrap().is_empty());
+
+        // Filter by labels
+        let labels = |pairs: &[(&str, &str)]| -> Labels {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let subgraph_store = store.subgraph_store();
+        let name = DeploymentHash::new(NAME).unwrap();
+        let other = DeploymentHash::new(OTHER).unwrap();
+        subgraph_store
+            .set_labels(&name, labels(&[("team", "defi"), ("tier", "gold")]))
+            .unwrap();
+        subgraph_store
+            .set_labels(&other, labels(&[("team", "nft")]))
+            .unwrap();
+        assert_eq!(
+            labels(&[("team", "defi"), ("tier", "gold")]),
+            subgraph_store.labels(&name).unwrap()
+        );
+
+        let by_labels = |selectors: &[&str]| {
+            let mut filter = query(status::StatusOrder::Id);
+            filter.labels = selectors
+                .iter()
+                .map(|selector| LabelSelector::parse(selector).unwrap())
+                .collect();
+            let infos = store.indexing_statuses(filter).unwrap();
+            subgraphs(&infos)
+                .into_iter()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![NAME, OTHER], by_labels(&["team"]));
+        assert_eq!(vec![NAME], by_labels(&["team:defi"]));
+        assert_eq!(vec![NAME], by_labels(&["team", "tier:gold"]));
+        assert!(by_labels(&["team:nft", "tier"]).is_empty());
+
+        let infos = store
+            .indexing_statuses(query(status::StatusOrder::Id))
+            .unwrap();
+        assert_eq!(labels(&[("team", "nft")]), infos[1].labels);
+
+        // Setting labels replaces all of them
+        subgraph_store.set_labels(&name, Labels::new()).unwrap();
+        assert!(subgraph_store.labels(&name).unwrap().is_empty());
+        assert_eq!(vec![OTHER], by_labels(&["team"]));
+    })
+}
+
+#[test]
+fn admin_audit_log() {
+    use graph::components::server::admin::AdminCall;
+    use graph::prelude::serde_json::json;
+
+    fn call(method: &str) -> AdminCall {
+        AdminCall {
+            method: method.to_string(),
+            params: json!({ "name": "audited" }),
+            caller: None,
+            source: Some("10.0.0.1".to_string()),
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        let subgraph_store = store.subgraph_store();
+
+        let deploy = subgraph_store
+            .start_admin_call(call("test_audit_deploy"))
+            .unwrap();
+        let remove = subgraph_store
+            .start_admin_call(call("test_audit_remove"))
+            .unwrap();
+        subgraph_store.finish_admin_call(deploy, None).unwrap();
+
+        let entries = store.admin_audit_log(10, Some(deploy - 1), None).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!(deploy, entries[0].id);
+        assert_eq!("test_audit_deploy", entries[0].call.method);
+        assert_eq!(json!({ "name": "audited" }), entries[0].call.params);
+        assert_eq!(Some("10.0.0.1"), entries[0].call.source.as_deref());
+        assert_eq!("success", entries[0].result());
+        assert!(entries[0].finished_at.is_some());
+        assert_eq!("pending", entries[1].result());
+        assert!(entries[1].finished_at.is_none());
+
+        subgraph_store
+            .finish_admin_call(remove, Some("no such subgraph".to_string()))
+            .unwrap();
+        // An entry can only be finished once
+        assert!(subgraph_store.finish_admin_call(remove, None).is_err());
+
+        let entries = store
+            .admin_audit_log(10, Some(deploy), Some("test_audit_remove".to_string()))
+            .unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!("failure", entries[0].result());
+        assert_eq!(Some("no such subgraph"), entries[0].error.as_deref());
+
+        let entries = store.admin_audit_log(1, Some(deploy - 1), None).unwrap();
+        assert_eq!(1, entries.len());
+    })
+}
+
 #[test]
 fn version_info() {
     const NAME: &str = "versionInfoSubgraph";
@@ -589,3 +1076,1070 @@ fn fail_unfail() {
         test_store::remove_subgraphs();
     })
 }
+
+#[test]
+fn writer_lease() {
+    const NAME: &str = "writerLease";
+
+    fn setup() -> DeploymentLocator {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, SUBGRAPH_GQL)
+    }
+
+    fn transact(
+        writable: &Arc<dyn WritableStore>,
+        deployment: &DeploymentLocator,
+        ptr: &BlockPtr,
+    ) -> Result<(), StoreError> {
+        let stopwatch = StopwatchMetrics::new(
+            Logger::root(slog::Discard, o!()),
+            deployment.hash.clone(),
+            Arc::new(MockMetricsRegistry::new()),
+        );
+        writable.transact_block_operations(ptr.clone(), None, vec![], stopwatch, vec![], vec![])
+    }
+
+    fn assert_held_by(res: Result<(), StoreError>, node: &str) {
+        match res {
+            Err(StoreError::DeploymentLeaseHeld(id, holder)) => {
+                assert_eq!(NAME, id.as_str());
+                assert_eq!(node, holder);
+            }
+            Err(e) => panic!("expected lease to be held by {}, got error {}", node, e),
+            Ok(()) => panic!("expected lease to be held by {}, but write succeeded", node),
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup();
+        let store = store.subgraph_store();
+
+        let left = NodeId::new("left").unwrap();
+        let right = NodeId::new("right").unwrap();
+        let left_writer = store.writable_for_node(&deployment, left.clone()).unwrap();
+        let right_writer = store.writable_for_node(&deployment, right.clone()).unwrap();
+
+        // The node that created the deployment holds the lease
+        assert_held_by(transact(&left_writer, &deployment, &BLOCK_ONE), "test");
+
+        // Reassigning hands the lease to `left` which can now write, while
+        // `right` is locked out, both when starting and when writing
+        store.reassign_subgraph(&deployment, &left).unwrap();
+        transact(&left_writer, &deployment, &BLOCK_ONE).unwrap();
+        assert_held_by(transact(&right_writer, &deployment, &BLOCKS[2]), "left");
+        assert_held_by(right_writer.start_subgraph_deployment(&*LOGGER), "left");
+        assert_eq!(
+            Some(BLOCK_ONE.clone()),
+            left_writer.block_ptr().unwrap(),
+            "the blocked writer did not change the block pointer"
+        );
+
+        // After reassigning to `right`, the roles are reversed
+        store.reassign_subgraph(&deployment, &right).unwrap();
+        transact(&right_writer, &deployment, &BLOCKS[2]).unwrap();
+        assert_held_by(transact(&left_writer, &deployment, &BLOCKS[3]), "right");
+        assert_eq!(Some(BLOCKS[2].clone()), right_writer.block_ptr().unwrap());
+
+        // Unassigning releases the lease so that any node can take it
+        right_writer.unassign_subgraph().unwrap();
+        transact(&left_writer, &deployment, &BLOCKS[3]).unwrap();
+        assert_held_by(
+            right_writer
+                .revert_block_operations(BLOCKS[2].clone())
+                .map(|_| ()),
+            "left",
+        );
+    })
+}
+
+#[test]
+fn clock_jumps() {
+    const NAME: &str = "clockJumps";
+    const TEN_MINUTES: Duration = Duration::from_secs(600);
+
+    fn setup() -> DeploymentLocator {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, SUBGRAPH_GQL)
+    }
+
+    fn transact(writable: &Arc<dyn WritableStore>, ptr: &BlockPtr) -> Result<(), StoreError> {
+        let stopwatch = StopwatchMetrics::new(
+            Logger::root(slog::Discard, o!()),
+            DeploymentHash::new(NAME).unwrap(),
+            Arc::new(MockMetricsRegistry::new()),
+        );
+        writable.transact_block_operations(ptr.clone(), None, vec![], stopwatch, vec![], vec![])
+    }
+
+    fn assigned(store: &SubgraphStore, deployment: &DeploymentLocator) -> String {
+        store
+            .assigned_node(deployment)
+            .unwrap()
+            .expect("deployment is assigned")
+            .to_string()
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup();
+        let store = store.subgraph_store();
+        let logger = Logger::root(slog::Discard, o!());
+
+        let standby = NodeId::new("standby").unwrap();
+        let other = NodeId::new("other").unwrap();
+        store.reassign_subgraph(&deployment, &*NODE_ID).unwrap();
+        store.set_standby_node(&deployment, Some(&standby)).unwrap();
+        store.record_heartbeat(&*NODE_ID).unwrap();
+        let writer = store
+            .writable_for_node(&deployment, NODE_ID.clone())
+            .unwrap();
+        let other_writer = store.writable_for_node(&deployment, other).unwrap();
+
+        // The database clock is set back by 10 minutes. Heartbeats and
+        // leases now seem to be from the future, which must neither take
+        // the deployment away from its node nor stop that node from writing
+        shift_database_times(TEN_MINUTES.as_secs() as i64);
+        let clock = ClockWatch::new("database");
+        store.promote_standbys(&logger, &standby, &clock).unwrap();
+        assert_eq!(NODE_ID.as_str(), assigned(&store, &deployment));
+        transact(&writer, &BLOCK_ONE).unwrap();
+        store.record_heartbeat(&*NODE_ID).unwrap();
+        store.promote_standbys(&logger, &standby, &clock).unwrap();
+        assert_eq!(NODE_ID.as_str(), assigned(&store, &deployment));
+        match transact(&other_writer, &BLOCKS[2]) {
+            Err(StoreError::DeploymentLeaseHeld(_, holder)) => assert_eq!(NODE_ID.as_str(), holder),
+            res => panic!(
+                "expected the lease to be held, got {:?}",
+                res.map_err(|e| e.to_string())
+            ),
+        }
+        transact(&writer, &BLOCKS[2]).unwrap();
+
+        // The clock is set forward by 20 minutes, and the heartbeat looks
+        // old enough for the node to be dead. As long as the clock has not
+        // settled after the jump, the standby does not take over
+        shift_database_times(-2 * TEN_MINUTES.as_secs() as i64);
+        let clock = ClockWatch::new("database");
+        clock.observe(&logger, SystemTime::now() - 2 * TEN_MINUTES);
+        store.promote_standbys(&logger, &standby, &clock).unwrap();
+        assert_eq!(NODE_ID.as_str(), assigned(&store, &deployment));
+
+        // A node that did not see the clock jump goes by the heartbeat
+        let clock = ClockWatch::new("database");
+        store.promote_standbys(&logger, &standby, &clock).unwrap();
+        assert_eq!(standby.as_str(), assigned(&store, &deployment));
+    })
+}
+
+#[test]
+fn archive() {
+    const NAME: &str = "archive";
+
+    fn set_name(id: &DeploymentHash, name: &str) -> EntityOperation {
+        let mut data = Entity::new();
+        data.set("id", "1");
+        data.set("name", name);
+        EntityOperation::Set {
+            key: EntityKey::data(id.clone(), "User".to_owned(), "1".to_owned()),
+            data,
+        }
+    }
+
+    fn name_at(store: &SubgraphStore, id: &DeploymentHash, block: BlockNumber) -> Option<Value> {
+        let query = EntityQuery::new(
+            id.clone(),
+            block,
+            EntityCollection::All(vec![(EntityType::from("User"), AttributeNames::All)]),
+        );
+        let entities = store.find(query).unwrap();
+        assert_eq!(1, entities.len());
+        entities[0].get("name").cloned()
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let store = store.subgraph_store();
+
+        for (ptr, name) in BLOCKS[1..].iter().zip(vec!["one", "two", "three"]) {
+            transact_entity_operations(&store, &deployment, ptr.clone(), vec![set_name(&id, name)])
+                .unwrap();
+        }
+
+        // Keep one block of history in the entity tables; the version
+        // written at block 1 was closed at block 2 and gets archived
+        store.set_archive_blocks(&deployment, Some(1)).unwrap();
+        let metrics = Arc::new(ArchiveMetrics::new(Arc::new(MockMetricsRegistry::new())));
+        store.archive(&*LOGGER, metrics).await;
+
+        assert_eq!(Some(Value::from("one")), name_at(&store, &id, 1));
+        assert_eq!(Some(Value::from("two")), name_at(&store, &id, 2));
+        assert_eq!(Some(Value::from("three")), name_at(&store, &id, 3));
+
+        // Reverting to a block whose history has been archived is refused
+        assert!(matches!(
+            store.rewind(id.clone(), BLOCKS[1].clone()),
+            Err(StoreError::RevertPastArchive(..))
+        ));
+        store.rewind(id.clone(), BLOCKS[2].clone()).unwrap();
+        assert_eq!(Some(Value::from("two")), name_at(&store, &id, 3));
+    })
+}
+
+#[test]
+fn archive_block_info() {
+    use graph::data::store::scalar;
+    use graph::data::subgraph::schema::{BLOCK_INFO_OBJECT, BLOCK_INFO_SCHEMA};
+    use graph::data::subgraph::SubgraphFeature;
+    use graph::prelude::BigInt;
+
+    const NAME: &str = "archiveBlockInfo";
+
+    fn set_block_info(id: &DeploymentHash, ptr: &BlockPtr) -> EntityOperation {
+        let mut data = Entity::new();
+        data.set("id", ptr.hash_hex());
+        data.set("number", BigInt::from(ptr.number));
+        data.set("hash", scalar::Bytes::from(ptr.hash.as_slice()));
+        EntityOperation::Set {
+            key: EntityKey {
+                subgraph_id: id.clone(),
+                entity_type: BLOCK_INFO_OBJECT.clone(),
+                entity_id: ptr.hash_hex(),
+            },
+            data,
+        }
+    }
+
+    fn numbers_at(store: &SubgraphStore, id: &DeploymentHash, block: BlockNumber) -> Vec<Value> {
+        let query = EntityQuery::new(
+            id.clone(),
+            block,
+            EntityCollection::All(vec![(BLOCK_INFO_OBJECT.clone(), AttributeNames::All)]),
+        );
+        let mut numbers: Vec<_> = store
+            .find(query)
+            .unwrap()
+            .into_iter()
+            .map(|entity| entity.get("number").cloned().unwrap())
+            .collect();
+        numbers.sort_by_key(|number| number.to_string());
+        numbers
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_subgraph_with_features(
+            &id,
+            &format!("{}{}", SUBGRAPH_GQL, BLOCK_INFO_SCHEMA),
+            None,
+            vec![SubgraphFeature::BlockInfo].into_iter().collect(),
+        )
+        .unwrap();
+        let store = store.subgraph_store();
+
+        for ptr in &BLOCKS[1..] {
+            transact_entity_operations(
+                &store,
+                &deployment,
+                ptr.clone(),
+                vec![set_block_info(&id, ptr)],
+            )
+            .unwrap();
+        }
+        let number = |number: i32| Value::BigInt(BigInt::from(number));
+        assert_eq!(
+            vec![number(1), number(2), number(3)],
+            numbers_at(&store, &id, 3)
+        );
+
+        // The horizon is block 2. `BlockInfo` entities are never closed,
+        // but those of blocks up to the horizon are archived anyway
+        store.set_archive_blocks(&deployment, Some(1)).unwrap();
+        let metrics = Arc::new(ArchiveMetrics::new(Arc::new(MockMetricsRegistry::new())));
+        store.archive(&*LOGGER, metrics).await;
+
+        assert_eq!(vec![number(1)], numbers_at(&store, &id, 1));
+        assert_eq!(vec![number(3)], numbers_at(&store, &id, 3));
+    })
+}
+
+#[test]
+fn composite_indexes() {
+    const NAME: &str = "compositeIndexes";
+    const GQL: &str = r#"
+        type Transfer @entity @index(fields: ["owner", "amount"]) {
+            id: ID!,
+            owner: String!,
+            amount: BigInt!
+        }
+    "#;
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, GQL);
+
+        assert_eq!(
+            vec![status::CompositeIndex {
+                name: "cidx_transfer_owner_amount".to_string(),
+                entity_type: Some("Transfer".to_string()),
+                fields: vec!["owner".to_string(), "amount".to_string()],
+                unique: false,
+                declared: true,
+                valid: true,
+            }],
+            store.composite_indexes(&id).unwrap()
+        );
+    })
+}
+
+#[test]
+fn revert_report() {
+    const NAME: &str = "revertReport";
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number + 1), number))
+    }
+
+    fn set_user(id: &DeploymentHash, user: u64, name: u64) -> EntityOperation {
+        let mut data = Entity::new();
+        data.set("id", user.to_string());
+        data.set("name", name.to_string());
+        EntityOperation::Set {
+            key: EntityKey::data(id.clone(), "User".to_owned(), user.to_string()),
+            data,
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let store = store.subgraph_store();
+
+        // Block 1 creates user 0; every later block updates user 0 and
+        // creates a new user
+        transact_entity_operations(&store, &deployment, ptr(1), vec![set_user(&id, 0, 1)]).unwrap();
+        for number in 2..=11 {
+            let ops = vec![set_user(&id, 0, number), set_user(&id, number, number)];
+            transact_entity_operations(&store, &deployment, ptr(number), ops).unwrap();
+        }
+
+        // Revert a 10-block reorg one block at a time, the way a subgraph
+        // does
+        let writable = store.writable(&deployment).unwrap();
+        let report = writable.revert_block_operations(ptr(10)).unwrap();
+        assert_eq!(
+            (11, 10, 1),
+            (report.from_block, report.to_block, report.blocks())
+        );
+        assert_eq!(
+            vec![status::EntityRevert {
+                entity_type: "User".to_string(),
+                restored: 1,
+                removed: 2,
+            }],
+            report.entities
+        );
+        assert_eq!(0, report.dynamic_data_sources_removed);
+        for number in (1..10).rev() {
+            writable.revert_block_operations(ptr(number)).unwrap();
+        }
+
+        let last_revert = || {
+            let infos = store
+                .status(status::Filter::Deployments(vec![NAME.to_string()]))
+                .unwrap();
+            assert_eq!(1, infos.len());
+            infos[0].last_revert.clone().unwrap()
+        };
+
+        // The status shows one report for the whole reorg
+        let report = last_revert();
+        assert_eq!(
+            (11, 1, 10),
+            (report.from_block, report.to_block, report.blocks())
+        );
+        assert_eq!(10, report.entities_restored());
+        assert_eq!(20, report.entities_removed());
+
+        // Reverting after processing blocks again is a new reorg
+        for number in 2..=3 {
+            let ops = vec![set_user(&id, 0, number), set_user(&id, number, number)];
+            transact_entity_operations(&store, &deployment, ptr(number), ops).unwrap();
+        }
+        let report = store.rewind(id.clone(), ptr(1)).unwrap();
+        assert_eq!(
+            (3, 1, 2),
+            (report.from_block, report.to_block, report.blocks())
+        );
+        assert_eq!(1, report.entities_restored());
+        assert_eq!(4, report.entities_removed());
+        assert_eq!(report, last_revert());
+    })
+}
+
+#[test]
+fn interrupted_revert() {
+    const NAME: &str = "interruptedRevert";
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number + 1), number))
+    }
+
+    fn set_user(id: &DeploymentHash, user: u64, name: u64) -> EntityOperation {
+        let mut data = Entity::new();
+        data.set("id", user.to_string());
+        data.set("name", name.to_string());
+        EntityOperation::Set {
+            key: EntityKey::data(id.clone(), "User".to_owned(), user.to_string()),
+            data,
+        }
+    }
+
+    fn user(id: &DeploymentHash, user: u64) -> EntityKey {
+        EntityKey::data(id.clone(), "User".to_owned(), user.to_string())
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let subgraph_store = store.subgraph_store();
+
+        // Block 1 creates user 0; every later block updates user 0 and
+        // creates a new user
+        transact_entity_operations(
+            &subgraph_store,
+            &deployment,
+            ptr(1),
+            vec![set_user(&id, 0, 1)],
+        )
+        .unwrap();
+        for number in 2..=11 {
+            let ops = vec![set_user(&id, 0, number), set_user(&id, number, number)];
+            transact_entity_operations(&subgraph_store, &deployment, ptr(number), ops).unwrap();
+        }
+
+        // Revert to block 1 in chunks of 2 blocks, but stop after two
+        // chunks, i.e., once the data is at block 7
+        subgraph_store
+            .rewind_interrupted(id.clone(), ptr(1), 2, 2)
+            .unwrap();
+
+        // The head has not moved, but queries can not see past the target
+        let writable = subgraph_store.writable(&deployment).unwrap();
+        assert_eq!(Some(ptr(11)), writable.block_ptr().unwrap());
+        let state = store
+            .query_store(deployment.hash.clone().into(), false)
+            .await
+            .unwrap()
+            .deployment_state()
+            .await
+            .unwrap();
+        assert_eq!(1, state.latest_ethereum_block_number);
+
+        // No blocks can be written until the revert is done
+        let ops = vec![set_user(&id, 12, 12)];
+        assert!(transact_entity_operations(&subgraph_store, &deployment, ptr(12), ops).is_err());
+
+        // Starting the deployment finishes the revert
+        writable.start_subgraph_deployment(&*LOGGER).unwrap();
+        assert_eq!(Some(ptr(1)), writable.block_ptr().unwrap());
+        let user0 = writable.get(&user(&id, 0)).unwrap().unwrap();
+        assert_eq!(Some(&Value::from("1")), user0.get("name"));
+        for number in 2..=11 {
+            assert!(writable.get(&user(&id, number)).unwrap().is_none());
+        }
+
+        // Indexing can continue
+        transact_entity_operations(
+            &subgraph_store,
+            &deployment,
+            ptr(2),
+            vec![set_user(&id, 2, 2)],
+        )
+        .unwrap();
+    })
+}
+
+#[test]
+fn retry_failed() {
+    const NAME: &str = "retryFailed";
+
+    fn setup() -> DeploymentLocator {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        create_test_subgraph(&id, SUBGRAPH_GQL)
+    }
+
+    run_test_sequentially(|store| async move {
+        let deployment = setup();
+        let subgraph_store = store.subgraph_store();
+
+        // A deployment that has not failed has nothing to retry
+        assert!(subgraph_store.fatal_error(&deployment).unwrap().is_none());
+        subgraph_store.retry_failed(&deployment).unwrap();
+
+        let error = || SubgraphError {
+            subgraph_id: deployment.hash.clone(),
+            message: "provider went away".to_string(),
+            block_ptr: Some(BLOCKS[1].clone()),
+            handler: Some("handleTransfer".to_string()),
+            deterministic: false,
+        };
+        subgraph_store
+            .writable(&deployment)
+            .expect("can get writable")
+            .fail_subgraph(error())
+            .await
+            .unwrap();
+        let fatal_error = subgraph_store.fatal_error(&deployment).unwrap();
+        assert_eq!(
+            Some(error().message),
+            fatal_error.map(|error| error.message)
+        );
+
+        // Retrying clears the failure and remembers it
+        subgraph_store.retry_failed(&deployment).unwrap();
+        assert!(subgraph_store.fatal_error(&deployment).unwrap().is_none());
+
+        let infos = subgraph_store
+            .status(status::Filter::Deployments(vec![NAME.to_string()]))
+            .unwrap();
+        assert_eq!(1, infos.len());
+        let info = &infos[0];
+        assert_eq!(SubgraphHealth::Healthy, info.health);
+        assert!(info.fatal_error.is_none());
+        assert_eq!(1, info.failure_history.len());
+        let failure = &info.failure_history[0];
+        assert_eq!(error().message, failure.message);
+        assert_eq!(error().handler, failure.handler);
+        assert_eq!(Some(BLOCKS[1].number), failure.block_number);
+        assert_eq!(Some(BLOCKS[1].hash_hex()), failure.block_hash);
+        assert!(!failure.deterministic);
+
+        test_store::remove_subgraphs();
+    })
+}
+
+#[test]
+fn skip_block() {
+    const NAME: &str = "skipBlock";
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number + 1), number))
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let subgraph_store = store.subgraph_store();
+        let writable = subgraph_store.writable(&deployment).unwrap();
+        let poison = ptr(2).hash.to_string();
+
+        transact_entity_operations(&subgraph_store, &deployment, ptr(1), vec![]).unwrap();
+        let error = SubgraphError {
+            subgraph_id: id.clone(),
+            message: "can not decode block".to_string(),
+            block_ptr: Some(ptr(2)),
+            handler: None,
+            deterministic: true,
+        };
+        writable.fail_subgraph(error).await.unwrap();
+
+        // Skipping the block the deployment failed on clears the failure
+        subgraph_store
+            .skip_block(&deployment, &poison, "poison block".to_string())
+            .unwrap();
+        assert!(subgraph_store.fatal_error(&deployment).unwrap().is_none());
+        assert!(subgraph_store
+            .skip_block(&deployment, &poison, "again".to_string())
+            .is_err());
+
+        let skipped = writable.skipped_blocks().unwrap();
+        assert_eq!(1, skipped.len());
+        assert_eq!(poison, skipped[0].block_hash);
+        assert_eq!("poison block", skipped[0].reason);
+        assert!(skipped[0].is_requested());
+        assert!(!skipped[0].is_applied());
+
+        // The deployment skips the block
+        writable.record_skipped_block(&ptr(2)).unwrap();
+        transact_entity_operations(&subgraph_store, &deployment, ptr(2), vec![]).unwrap();
+
+        let infos = subgraph_store
+            .status(status::Filter::Deployments(vec![NAME.to_string()]))
+            .unwrap();
+        let info = &infos[0];
+        assert_eq!(1, info.failure_history.len());
+        assert_eq!(1, info.skipped_blocks.len());
+        assert_eq!(Some(2), info.skipped_blocks[0].block_number);
+        assert!(info.skipped_blocks[0].is_applied());
+
+        // Withdrawing the skip leaves the data alone until the deployment
+        // is rewound to before the block
+        let skipped = subgraph_store.unskip_block(&deployment, &poison).unwrap();
+        assert!(!skipped.is_requested());
+        assert!(skipped.is_applied());
+        assert!(subgraph_store.unskip_block(&deployment, &poison).is_err());
+
+        subgraph_store.rewind(id.clone(), ptr(1)).unwrap();
+        let skipped = writable.skipped_blocks().unwrap();
+        assert!(skipped[0].reverted_at.is_some());
+        assert!(!skipped[0].is_applied());
+
+        test_store::remove_subgraphs();
+    })
+}
+
+#[test]
+fn once_handler_runs() {
+    const NAME: &str = "onceHandlerRuns";
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number + 1), number))
+    }
+
+    fn key(name: &str) -> DataSourceKey {
+        DataSourceKey {
+            name: name.to_string(),
+            address: Some(vec![1; 20]),
+            context: None,
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let subgraph_store = store.subgraph_store();
+        let writable = subgraph_store.writable(&deployment).unwrap();
+
+        assert!(writable.once_handler_runs().unwrap().is_empty());
+
+        writable
+            .record_once_handler_runs(1, vec![key("Init")])
+            .unwrap();
+        transact_entity_operations(&subgraph_store, &deployment, ptr(1), vec![]).unwrap();
+        writable
+            .record_once_handler_runs(3, vec![key("Created")])
+            .unwrap();
+        transact_entity_operations(&subgraph_store, &deployment, ptr(3), vec![]).unwrap();
+
+        // The runs survive a restart, which gets a new writable store
+        let writable = subgraph_store.writable(&deployment).unwrap();
+        let runs = writable.once_handler_runs().unwrap();
+        assert_eq!(
+            vec![
+                OnceHandlerRun {
+                    data_source: key("Init"),
+                    block: 1
+                },
+                OnceHandlerRun {
+                    data_source: key("Created"),
+                    block: 3
+                }
+            ],
+            runs
+        );
+
+        // Reverting the block in which a handler ran forgets that it ran
+        writable.revert_block_operations(ptr(2)).unwrap();
+        let runs = writable.once_handler_runs().unwrap();
+        assert_eq!(
+            vec![OnceHandlerRun {
+                data_source: key("Init"),
+                block: 1
+            }],
+            runs
+        );
+
+        // Running again replaces the earlier run
+        writable
+            .record_once_handler_runs(2, vec![key("Init")])
+            .unwrap();
+        let runs = writable.once_handler_runs().unwrap();
+        assert_eq!(
+            vec![OnceHandlerRun {
+                data_source: key("Init"),
+                block: 2
+            }],
+            runs
+        );
+
+        test_store::remove_subgraphs();
+    })
+}
+
+#[test]
+fn complete() {
+    const NAME: &str = "completeDeployment";
+
+    fn ptr(number: u64) -> BlockPtr {
+        BlockPtr::from((H256::from_low_u64_be(number + 1), number))
+    }
+
+    fn health(store: &SubgraphStore) -> SubgraphHealth {
+        let infos = store
+            .status(status::Filter::Deployments(vec![NAME.to_string()]))
+            .unwrap();
+        infos[0].health
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let subgraph_store = store.subgraph_store();
+        let writable = subgraph_store.writable(&deployment).unwrap();
+
+        transact_entity_operations(&subgraph_store, &deployment, ptr(1), vec![]).unwrap();
+        transact_entity_operations(&subgraph_store, &deployment, ptr(2), vec![]).unwrap();
+        assert!(!writable.is_completed().unwrap());
+
+        writable.complete_subgraph().unwrap();
+        assert!(writable.is_completed().unwrap());
+        assert_eq!(SubgraphHealth::Completed, health(&subgraph_store));
+
+        // Rewinding gives the deployment blocks to process again
+        subgraph_store.rewind(id.clone(), ptr(1)).unwrap();
+        assert!(!writable.is_completed().unwrap());
+        assert_eq!(SubgraphHealth::Healthy, health(&subgraph_store));
+
+        test_store::remove_subgraphs();
+    })
+}
+
+#[test]
+fn max_trigger_bytes() {
+    const NAME: &str = "maxTriggerBytes";
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let subgraph_store = store.subgraph_store();
+
+        // The budget the node runs with is recorded when the deployment
+        // is created
+        assert_eq!(
+            Some(*MAX_TRIGGER_BYTES),
+            subgraph_store.max_trigger_bytes(&deployment).unwrap()
+        );
+
+        test_store::remove_subgraphs();
+    })
+}
+
+#[test]
+fn maintain_metadata() {
+    use graph::components::server::admin::AdminCall;
+    use graph::prelude::serde_json::json;
+
+    // All deployments share the first 32 characters, and therefore become
+    // versions of the same subgraph
+    const PREFIX: &str = "supersededVersionsOfOneSubgraph0";
+
+    fn call() -> AdminCall {
+        AdminCall {
+            method: "test_maintain_metadata".to_string(),
+            params: json!({}),
+            caller: None,
+            source: None,
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        remove_subgraphs();
+        let subgraph_store = store.subgraph_store();
+        let primary = primary_connection();
+
+        let ids: Vec<_> = (1..=5)
+            .map(|i| DeploymentHash::new(format!("{}{}", PREFIX, i)).unwrap())
+            .collect();
+        for id in &ids {
+            create_test_subgraph(id, SUBGRAPH_GQL);
+        }
+        let used = |id: &DeploymentHash| {
+            let site = primary.find_active_site(id).unwrap().unwrap();
+            !primary
+                .subgraphs_using_deployment(&site)
+                .unwrap()
+                .is_empty()
+        };
+        assert!(ids.iter().all(|id| used(id)));
+
+        // Keep the two most recent superseded versions and the current one
+        assert_eq!(2, primary.remove_superseded_versions(2).unwrap());
+        assert!(!used(&ids[0]));
+        assert!(!used(&ids[1]));
+        assert!(ids[2..].iter().all(|id| used(id)));
+        let (current, pending) = primary.versions_for_subgraph(PREFIX).unwrap();
+        assert_eq!(
+            Some(ids[4].as_str()),
+            primary
+                .deployment_for_version(&current.unwrap())
+                .unwrap()
+                .as_deref()
+        );
+        assert!(pending.is_none());
+
+        // The current version is never removed
+        assert_eq!(2, primary.remove_superseded_versions(0).unwrap());
+        assert!(used(&ids[4]));
+        assert_eq!(0, primary.remove_superseded_versions(0).unwrap());
+
+        // Calls that have not finished stay in the audit log
+        let first = subgraph_store.start_admin_call(call()).unwrap();
+        let pending = subgraph_store.start_admin_call(call()).unwrap();
+        let last = subgraph_store.start_admin_call(call()).unwrap();
+        subgraph_store.finish_admin_call(first, None).unwrap();
+        subgraph_store.finish_admin_call(last, None).unwrap();
+
+        assert!(primary.trim_admin_audit_log(1).unwrap() >= 1);
+        let entries = store.admin_audit_log(10, Some(first - 1), None).unwrap();
+        assert_eq!(
+            vec![pending, last],
+            entries.iter().map(|entry| entry.id).collect::<Vec<_>>()
+        );
+        subgraph_store.finish_admin_call(pending, None).unwrap();
+
+        subgraph_store.maintain_metadata().unwrap();
+        assert!(used(&ids[4]));
+
+        remove_subgraphs();
+    })
+}
+
+#[test]
+fn standby_promotion() {
+    const NAME: &str = "standbyPromotion";
+
+    fn transact(
+        writable: &Arc<dyn WritableStore>,
+        deployment: &DeploymentLocator,
+        ptr: &BlockPtr,
+    ) -> Result<(), StoreError> {
+        let stopwatch = StopwatchMetrics::new(
+            Logger::root(slog::Discard, o!()),
+            deployment.hash.clone(),
+            Arc::new(MockMetricsRegistry::new()),
+        );
+        writable.transact_block_operations(ptr.clone(), None, vec![], stopwatch, vec![], vec![])
+    }
+
+    run_test_sequentially(|store| async move {
+        let id = DeploymentHash::new(NAME).unwrap();
+        remove_subgraphs();
+        let deployment = create_test_subgraph(&id, SUBGRAPH_GQL);
+        let store = store.subgraph_store();
+
+        let primary = NodeId::new("primary").unwrap();
+        let standby = NodeId::new("standby").unwrap();
+        let primary_writer = store
+            .writable_for_node(&deployment, primary.clone())
+            .unwrap();
+        let standby_writer = store
+            .writable_for_node(&deployment, standby.clone())
+            .unwrap();
+
+        store.reassign_subgraph(&deployment, &primary).unwrap();
+        store.set_standby_node(&deployment, Some(&standby)).unwrap();
+        assert_eq!(
+            Some(standby.clone()),
+            store.standby_node(&deployment).unwrap()
+        );
+        assert_eq!(vec![deployment.clone()], store.standbys(&standby).unwrap());
+        transact(&primary_writer, &deployment, &BLOCK_ONE).unwrap();
+
+        // Promoting assigns the deployment to the standby, which continues
+        // from the primary's head while the primary can not write anymore
+        assert_eq!(standby, store.promote_standby(&deployment).unwrap());
+        assert_eq!(
+            Some(standby.clone()),
+            store.assigned_node(&deployment).unwrap()
+        );
+        assert_eq!(None, store.standby_node(&deployment).unwrap());
+        assert!(store.standbys(&standby).unwrap().is_empty());
+        assert_eq!(Some(BLOCK_ONE.clone()), standby_writer.block_ptr().unwrap());
+        assert!(transact(&primary_writer, &deployment, &BLOCKS[2]).is_err());
+        transact(&standby_writer, &deployment, &BLOCKS[2]).unwrap();
+
+        // Without a standby, there is nothing to promote
+        assert!(store.promote_standby(&deployment).is_err());
+
+        // A handover that fails leaves the writer lease where it was.
+        // Without an assignment, reassigning the deployment fails
+        let pconn = primary_connection();
+        let site = pconn.find_active_site(&id).unwrap().unwrap();
+        pconn.unassign_subgraph(&site).unwrap();
+        assert!(store.reassign_subgraph(&deployment, &primary).is_err());
+        assert!(transact(&primary_writer, &deployment, &BLOCKS[3]).is_err());
+        transact(&standby_writer, &deployment, &BLOCKS[3]).unwrap();
+    })
+}
+
+#[test]
+fn compare_versions() {
+    use graph::components::store::{DifferenceKind, EntityDifference};
+
+    const NAME: &str = "compare/versions";
+    const CURRENT_GQL: &str = "
+        enum Color { red, green }
+        type User @entity {
+            id: ID!,
+            name: String,
+            age: Int,
+            color: Color
+        }
+    ";
+    // The new version adds an attribute; each version has its own `Color`
+    // enum in the database
+    const PENDING_GQL: &str = "
+        enum Color { red, green }
+        type User @entity {
+            id: ID!,
+            name: String,
+            age: Int,
+            color: Color,
+            email: String
+        }
+    ";
+
+    fn deploy(store: &SubgraphStore, id: &str, schema: &str) -> DeploymentLocator {
+        let name = SubgraphName::new(NAME).unwrap();
+        let id = DeploymentHash::new(id).unwrap();
+        let schema = Schema::parse(schema, id.clone()).unwrap();
+        let manifest = SubgraphManifest::<graph_chain_ethereum::Chain> {
+            id: id.clone(),
+            spec_version: Version::new(1, 0, 0),
+            features: Default::default(),
+            description: None,
+            repository: None,
+            schema: schema.clone(),
+            data_sources: vec![],
+            graft: None,
+            templates: vec![],
+            max_triggers_per_block: None,
+            chain: PhantomData,
+        };
+        let deployment = store
+            .create_subgraph_deployment(
+                name,
+                &schema,
+                SubgraphDeploymentEntity::new(&manifest, false, None),
+                NODE_ID.clone(),
+                NETWORK_NAME.to_string(),
+                SubgraphVersionSwitchingMode::Synced,
+            )
+            .unwrap();
+        store
+            .writable(&deployment)
+            .unwrap()
+            .start_subgraph_deployment(&*LOGGER)
+            .unwrap();
+        deployment
+    }
+
+    fn user(
+        deployment: &DeploymentLocator,
+        id: &str,
+        name: &str,
+        age: i32,
+        color: &str,
+    ) -> EntityOperation {
+        let mut data = Entity::new();
+        data.set("id", id);
+        data.set("name", name);
+        data.set("age", age);
+        data.set("color", color);
+        EntityOperation::Set {
+            key: EntityKey::data(deployment.hash.clone(), "User".to_owned(), id.to_owned()),
+            data,
+        }
+    }
+
+    run_test_sequentially(|store| async move {
+        remove_subgraphs();
+        let store = store.subgraph_store();
+        let name = SubgraphName::new(NAME).unwrap();
+        store.create_subgraph(name.clone()).unwrap();
+
+        // Without a pending version, there is nothing to compare
+        let current = deploy(&store, "compareVersionsCurrent", CURRENT_GQL);
+        store
+            .writable(&current)
+            .unwrap()
+            .deployment_synced()
+            .unwrap();
+        assert!(store.compare_versions(&name, 1, 10).is_err());
+        let pending = deploy(&store, "compareVersionsPending", PENDING_GQL);
+
+        // The mappings of the pending version compute the age and color
+        // of `2` differently, do not create `3`, and create `4`
+        let current_ops = vec![
+            vec![
+                user(&current, "1", "alice", 30, "red"),
+                user(&current, "2", "bob", 40, "red"),
+                user(&current, "3", "carol", 50, "red"),
+            ],
+            vec![user(&current, "1", "alicia", 30, "red")],
+        ];
+        let pending_ops = vec![
+            vec![
+                user(&pending, "1", "alice", 30, "red"),
+                user(&pending, "2", "bob", 41, "green"),
+                user(&pending, "4", "dave", 60, "red"),
+            ],
+            vec![user(&pending, "1", "alicia", 30, "red")],
+            vec![user(&pending, "1", "ally", 30, "red")],
+        ];
+        for (ptr, ops) in BLOCKS[1..].iter().zip(current_ops) {
+            transact_entity_operations(&store, &current, ptr.clone(), ops).unwrap();
+        }
+        for (ptr, ops) in BLOCKS[1..].iter().zip(pending_ops) {
+            transact_entity_operations(&store, &pending, ptr.clone(), ops).unwrap();
+        }
+
+        let diff = |id: &str, kind, fields: &[&str]| EntityDifference {
+            id: id.to_string(),
+            kind,
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        };
+        for block in 1..=2 {
+            let comparison = store.compare_versions(&name, block, 10).unwrap();
+            assert_eq!(current.hash, comparison.current);
+            assert_eq!(pending.hash, comparison.pending);
+            assert!(!comparison.is_same());
+            assert_eq!(1, comparison.entity_types.len());
+
+            let users = &comparison.entity_types[0];
+            assert_eq!("User", users.entity_type);
+            assert_eq!((1, 1, 1), (users.added, users.removed, users.changed));
+            assert_eq!(
+                vec![
+                    diff("4", DifferenceKind::Added, &[]),
+                    diff("3", DifferenceKind::Removed, &[]),
+                    diff("2", DifferenceKind::Changed, &["age", "color"]),
+                ],
+                users.sample
+            );
+            assert_eq!(vec!["email".to_string()], users.not_compared);
+        }
+
+        // The sample can be left out
+        let comparison = store.compare_versions(&name, 2, 0).unwrap();
+        assert!(comparison.entity_types[0].sample.is_empty());
+        assert_eq!(1, comparison.entity_types[0].changed);
+
+        // The current version has not processed block 3 yet
+        assert!(store.compare_versions(&name, 3, 10).is_err());
+
+        remove_subgraphs();
+    })
+}
@@ -3,6 +3,7 @@ use graph::data::graphql::effort::LoadManager;
 use graph::data::query::QueryResults;
 use graph::data::query::QueryTarget;
 use graph::data::subgraph::schema::SubgraphError;
+use graph::data::subgraph::SubgraphFeature;
 use graph::log;
 use graph::prelude::{QueryStoreManager as _, SubgraphStore as _, *};
 use graph::semver::Version;
@@ -138,26 +139,38 @@ pub fn remove_subgraphs() {
 }
 
 pub fn place(name: &str) -> Result<Option<(Shard, Vec<NodeId>)>, String> {
-    CONFIG.deployment.place(name, NETWORK_NAME)
+    CONFIG
+        .deployment
+        .place(name, NETWORK_NAME, &graph::data::subgraph::Labels::new())
 }
 
 pub fn create_subgraph(
     subgraph_id: &DeploymentHash,
     schema: &str,
     base: Option<(DeploymentHash, BlockPtr)>,
+) -> Result<DeploymentLocator, StoreError> {
+    create_subgraph_with_features(subgraph_id, schema, base, BTreeSet::new())
+}
+
+pub fn create_subgraph_with_features(
+    subgraph_id: &DeploymentHash,
+    schema: &str,
+    base: Option<(DeploymentHash, BlockPtr)>,
+    features: BTreeSet<SubgraphFeature>,
 ) -> Result<DeploymentLocator, StoreError> {
     let schema = Schema::parse(schema, subgraph_id.clone()).unwrap();
 
     let manifest = SubgraphManifest::<graph_chain_ethereum::Chain> {
         id: subgraph_id.clone(),
         spec_version: Version::new(1, 0, 0),
-        features: BTreeSet::new(),
+        features,
         description: Some(format!("manifest for {}", subgraph_id)),
         repository: Some(format!("repo for {}", subgraph_id)),
         schema: schema.clone(),
         data_sources: vec![],
         graft: None,
         templates: vec![],
+        max_triggers_per_block: None,
         chain: PhantomData,
     };
 
@@ -262,13 +275,17 @@ pub fn transact_entities_and_dynamic_data_sources(
     )
 }
 
-pub fn revert_block(store: &Arc<Store>, deployment: &DeploymentLocator, ptr: &BlockPtr) {
+pub fn revert_block(
+    store: &Arc<Store>,
+    deployment: &DeploymentLocator,
+    ptr: &BlockPtr,
+) -> status::RevertReport {
     store
         .subgraph_store()
         .writable(deployment)
         .expect("can get writable")
         .revert_block_operations(ptr.clone())
-        .unwrap();
+        .unwrap()
 }
 
 pub fn insert_ens_name(hash: &str, name: &str) {
@@ -285,6 +302,23 @@ pub fn insert_ens_name(hash: &str, name: &str) {
         .unwrap();
 }
 
+/// Move all node heartbeats and writer leases `secs` seconds into the
+/// future. To the node, that looks the same as setting the database clock
+/// back by `secs` seconds; negative `secs` look like setting it forward
+pub fn shift_database_times(secs: i64) {
+    use diesel::connection::SimpleConnection;
+
+    let conn = PRIMARY_POOL.get().unwrap();
+    conn.batch_execute(&format!(
+        "update node_heartbeat
+            set last_heartbeat = last_heartbeat + interval '{secs} seconds';
+         update subgraphs.subgraph_deployment
+            set writer_lease_expires = writer_lease_expires + interval '{secs} seconds'",
+        secs = secs
+    ))
+    .unwrap();
+}
+
 pub fn insert_entities(
     deployment: &DeploymentLocator,
     entities: Vec<(EntityType, Entity)>,
@@ -493,6 +527,24 @@ fn build_store() -> (Arc<Store>, ConnectionPool, Config, Arc<SubscriptionManager
     .unwrap()
 }
 
+/// Build a new `Store` from the test configuration whose block store was
+/// set up with the given `networks`, the same way `graph-node` does it at
+/// startup. The chains from the global `STORE` exist in the database
+/// already, so that this is like a restart of the node
+pub fn network_store(networks: Vec<(String, Vec<EthereumNetworkIdentifier>)>) -> Arc<Store> {
+    let config = CONFIG.clone();
+    let registry = Arc::new(MockMetricsRegistry::new());
+    std::thread::spawn(move || {
+        STORE_RUNTIME.handle().block_on(async {
+            StoreBuilder::new(&*LOGGER, &*NODE_ID, &config, registry)
+                .await
+                .network_store(networks)
+        })
+    })
+    .join()
+    .unwrap()
+}
+
 pub fn primary_connection() -> graph_store_postgres::layout_for_tests::Connection<'static> {
     let conn = PRIMARY_POOL.get().unwrap();
     graph_store_postgres::layout_for_tests::Connection::new(conn)
@@ -263,6 +263,48 @@ impl DockerTestClient {
             panic!("failed to run 'createdb' command using docker exec");
         }
     }
+
+    /// Calls `docker exec` on the container to run `query` against a test
+    /// database with `psql` and returns the unaligned output
+    pub async fn run_postgres_query(
+        docker: &DockerTestClient,
+        unique_id: &u16,
+        query: &str,
+    ) -> Result<String, DockerError> {
+        use bollard::exec;
+
+        let database_name = postgres_test_database_name(unique_id);
+
+        // 1. Create Exec
+        let config = exec::CreateExecOptions {
+            cmd: Some(vec!["psql", "-tA", "-d", &database_name, "-c", query]),
+            user: Some("postgres"),
+            attach_stdout: Some(true),
+            ..Default::default()
+        };
+
+        let message = docker
+            .client
+            .create_exec(&docker.service.name(), config)
+            .await?;
+
+        // 2. Start Exec and collect its output
+        let mut output = String::new();
+        let mut stream = docker.client.start_exec(&message.id, None);
+        while let Some(result) = stream.next().await {
+            if let exec::StartExecResults::Attached { log } = result? {
+                output.push_str(&log.to_string());
+            }
+        }
+
+        // 3. Inspect exec
+        let inspect = docker.client.inspect_exec(&message.id).await?;
+        if let Some(0) = inspect.exit_code {
+            Ok(output)
+        } else {
+            panic!("failed to run 'psql' command using docker exec");
+        }
+    }
 }
 
 impl From<Vec<bollard::models::Port>> for MappedPorts {
@@ -296,7 +296,29 @@ async fn run_integration_test(
     let mut graph_node_child_command = run_graph_node(&test_setup).await?;
 
     println!("Test started: {}", basename(&test_setup.test_directory));
-    let test_command_results = run_test_command(&test_setup).await?;
+    let mut test_command_results = run_test_command(&test_setup).await?;
+
+    // every deployment the test made must be in the admin audit log
+    // together with its outcome
+    let audit = DockerTestClient::run_postgres_query(
+        &postgres_docker,
+        &postgres_unique_id,
+        "select count(*), count(succeeded) from admin_audit_log \
+          where method = 'subgraph_deploy'",
+    )
+    .await
+    .context("failed to query the admin audit log")?;
+    match audit.trim().split_once('|') {
+        Some((total, finished)) if total != "0" && total == finished => { /* ok */ }
+        _ => {
+            test_command_results.success = false;
+            test_command_results.stderr.push_str(&format!(
+                "\nexpected finished deployments in the admin audit log, but found \
+                 (total|finished) `{}`",
+                audit.trim()
+            ));
+        }
+    }
 
     // stop graph-node
 